@@ -0,0 +1,130 @@
+//! Hot-path timings for parsers, formatting and store lookups, followed by a
+//! short end-to-end pipeline run. Run with `cargo bench`.
+
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use matrix_bridge_discord::bench::{
+    PipelineBenchOptions, run_pipeline_bench, synthetic_config, synthetic_message,
+};
+use matrix_bridge_discord::db::{DatabaseManager, MessageMapping, RoomMapping};
+use matrix_bridge_discord::discord::DiscordClient;
+use matrix_bridge_discord::matrix::MatrixAppservice;
+use matrix_bridge_discord::parsers::{DiscordToMatrixConverter, MatrixToDiscordConverter};
+use matrix_bridge_discord::utils::formatting::apply_pattern_string;
+
+const ITERATIONS: u32 = 10_000;
+const LOOKUP_ITERATIONS: u32 = 1_000;
+
+fn report(name: &str, iterations: u32, elapsed: Duration) {
+    println!(
+        "{name:<40} {:>10.2?}/iter ({iterations} iterations)",
+        elapsed / iterations
+    );
+}
+
+fn measure(name: &str, iterations: u32, mut f: impl FnMut()) {
+    // Warm up caches and lazily compiled regexes before timing.
+    for _ in 0..iterations / 10 {
+        f();
+    }
+    let started = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    report(name, iterations, started.elapsed());
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let data_dir = tempfile::Builder::new().prefix("bridge-bench-").tempdir()?;
+    let database_url = format!("sqlite://{}", data_dir.path().join("bench.db").display());
+    let config = Arc::new(synthetic_config("http://127.0.0.1:9", &database_url)?);
+
+    let discord = Arc::new(DiscordClient::new(config.clone()).await?);
+    let matrix = Arc::new(MatrixAppservice::new(config.clone()).await?);
+    let discord_converter = DiscordToMatrixConverter::new(discord);
+    let matrix_converter = MatrixToDiscordConverter::new(matrix);
+
+    let discord_messages: Vec<String> = (0..5).map(|i| synthetic_message(i).content).collect();
+    measure("discord -> matrix plain", ITERATIONS, || {
+        for message in &discord_messages {
+            black_box(discord_converter.format_for_matrix(black_box(message)));
+        }
+    });
+    measure("discord -> matrix html", ITERATIONS, || {
+        for message in &discord_messages {
+            black_box(discord_converter.format_as_html(black_box(message)));
+        }
+    });
+
+    let matrix_message =
+        "**hello** [link](https://example.org) see #room:example.org at 2024-05-01T18:00:00+02:00";
+    measure("matrix -> discord", ITERATIONS, || {
+        black_box(matrix_converter.format_for_discord(black_box(matrix_message)));
+    });
+
+    let vars = [("id", "1234"), ("tag", "0"), ("username", "alice")];
+    measure("ghost name pattern", ITERATIONS, || {
+        black_box(apply_pattern_string(
+            black_box(&config.ghosts.username_pattern),
+            &vars,
+        ));
+    });
+
+    let db = DatabaseManager::new(&config.database).await?;
+    db.migrate().await?;
+    let now = chrono::Utc::now();
+    db.room_store()
+        .create_room_mapping(&RoomMapping {
+            id: 0,
+            matrix_room_id: "!bench:localhost".to_string(),
+            discord_channel_id: "100".to_string(),
+            discord_channel_name: "bench".to_string(),
+            discord_guild_id: "1".to_string(),
+            created_by: None,
+            origin: None,
+            last_active_at: None,
+            created_at: now,
+            updated_at: now,
+        })
+        .await?;
+    db.message_store()
+        .upsert_message_mapping(&MessageMapping {
+            id: 0,
+            discord_message_id: "200".to_string(),
+            matrix_room_id: "!bench:localhost".to_string(),
+            matrix_event_id: "$bench".to_string(),
+            discord_channel_id: None,
+            created_at: now,
+            updated_at: now,
+        })
+        .await?;
+
+    let room_store = db.room_store();
+    let started = Instant::now();
+    for _ in 0..LOOKUP_ITERATIONS {
+        black_box(room_store.get_room_by_discord_channel("100").await?);
+    }
+    report(
+        "room lookup by channel",
+        LOOKUP_ITERATIONS,
+        started.elapsed(),
+    );
+
+    let message_store = db.message_store();
+    let started = Instant::now();
+    for _ in 0..LOOKUP_ITERATIONS {
+        black_box(message_store.get_by_discord_message_id("200").await?);
+    }
+    report(
+        "message lookup by discord id",
+        LOOKUP_ITERATIONS,
+        started.elapsed(),
+    );
+
+    let pipeline = run_pipeline_bench(PipelineBenchOptions::default()).await?;
+    println!("pipeline: {pipeline}");
+    Ok(())
+}
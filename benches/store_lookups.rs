@@ -0,0 +1,123 @@
+//! Room and message lookups on the r2d2 stores against the `async-postgres`
+//! ones, sequentially and under concurrent load. Needs a scratch database:
+//! `BENCH_POSTGRES_URL=postgres://... cargo bench --features async-postgres --bench store_lookups`.
+
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use matrix_bridge_discord::bench::synthetic_config;
+use matrix_bridge_discord::db::postgres::{PostgresMessageStore, PostgresRoomStore};
+use matrix_bridge_discord::db::{
+    DatabaseManager, MessageMapping, MessageStore, RoomMapping, RoomStore,
+};
+
+const LOOKUP_ITERATIONS: u32 = 2_000;
+const CONCURRENT_TASKS: u32 = 32;
+
+fn report(name: &str, iterations: u32, elapsed: Duration) {
+    println!(
+        "{name:<40} {:>10.2?}/iter ({iterations} iterations)",
+        elapsed / iterations
+    );
+}
+
+async fn sequential(
+    name: &str,
+    rooms: &Arc<dyn RoomStore>,
+    messages: &Arc<dyn MessageStore>,
+) -> anyhow::Result<()> {
+    let started = Instant::now();
+    for _ in 0..LOOKUP_ITERATIONS {
+        black_box(rooms.get_room_by_discord_channel("100").await?);
+        black_box(messages.get_by_discord_message_id("200").await?);
+    }
+    report(name, LOOKUP_ITERATIONS, started.elapsed());
+    Ok(())
+}
+
+async fn concurrent(
+    name: &str,
+    rooms: &Arc<dyn RoomStore>,
+    messages: &Arc<dyn MessageStore>,
+) -> anyhow::Result<()> {
+    let per_task = LOOKUP_ITERATIONS / CONCURRENT_TASKS;
+    let started = Instant::now();
+    let tasks: Vec<_> = (0..CONCURRENT_TASKS)
+        .map(|_| {
+            let rooms = rooms.clone();
+            let messages = messages.clone();
+            tokio::spawn(async move {
+                for _ in 0..per_task {
+                    black_box(rooms.get_room_by_discord_channel("100").await?);
+                    black_box(messages.get_by_discord_message_id("200").await?);
+                }
+                anyhow::Ok(())
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await??;
+    }
+    report(name, per_task * CONCURRENT_TASKS, started.elapsed());
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let Ok(database_url) = std::env::var("BENCH_POSTGRES_URL") else {
+        println!("BENCH_POSTGRES_URL is not set, skipping the store lookup bench");
+        return Ok(());
+    };
+    let config = synthetic_config("http://127.0.0.1:9", &database_url)?;
+    let db = DatabaseManager::new(&config.database).await?;
+    db.migrate().await?;
+
+    let now = chrono::Utc::now();
+    let async_rooms = db.room_store();
+    let async_messages = db.message_store();
+    if async_rooms
+        .get_room_by_discord_channel("100")
+        .await?
+        .is_none()
+    {
+        async_rooms
+            .create_room_mapping(&RoomMapping {
+                id: 0,
+                matrix_room_id: "!bench:localhost".to_string(),
+                discord_channel_id: "100".to_string(),
+                discord_channel_name: "bench".to_string(),
+                discord_guild_id: "1".to_string(),
+                created_by: None,
+                origin: None,
+                last_active_at: None,
+                created_at: now,
+                updated_at: now,
+            })
+            .await?;
+    }
+    async_messages
+        .upsert_message_mapping(&MessageMapping {
+            id: 0,
+            discord_message_id: "200".to_string(),
+            matrix_room_id: "!bench:localhost".to_string(),
+            matrix_event_id: "$bench".to_string(),
+            discord_channel_id: None,
+            created_at: now,
+            updated_at: now,
+        })
+        .await?;
+
+    let pool = db
+        .pool()
+        .ok_or_else(|| anyhow::anyhow!("BENCH_POSTGRES_URL must point at postgres"))?
+        .clone();
+    let sync_rooms: Arc<dyn RoomStore> = Arc::new(PostgresRoomStore::new(pool.clone()));
+    let sync_messages: Arc<dyn MessageStore> = Arc::new(PostgresMessageStore::new(pool));
+
+    sequential("sync lookups", &sync_rooms, &sync_messages).await?;
+    sequential("async lookups", &async_rooms, &async_messages).await?;
+    concurrent("sync lookups, concurrent", &sync_rooms, &sync_messages).await?;
+    concurrent("async lookups, concurrent", &async_rooms, &async_messages).await?;
+    Ok(())
+}
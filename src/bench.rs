@@ -0,0 +1,329 @@
+//! Load generator behind `--bench-pipeline`. Synthetic Discord messages go
+//! through `BridgeCore` with SQLite stores in a temporary directory and a
+//! loopback homeserver, so the numbers cover parsing, formatting, store
+//! lookups and the Matrix send path without any real services.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, anyhow};
+use salvo::conn::Acceptor;
+use salvo::prelude::*;
+use serde_json::json;
+use tracing::{debug, info};
+
+use crate::bridge::{BridgeCore, DiscordMessageContext};
+use crate::cache::{ChannelSnapshot, UserSnapshot};
+use crate::config::Config;
+use crate::db::{DatabaseManager, RoomMapping};
+use crate::discord::DiscordClient;
+use crate::matrix::MatrixAppservice;
+
+const SAMPLE_CONFIG: &str = include_str!("../config/config.sample.yaml");
+const BENCH_CHANNEL_ID: &str = "100000000000000001";
+const BENCH_GUILD_ID: &str = "100000000000000000";
+const BENCH_ROOM_ID: &str = "!bench:localhost";
+const BENCH_SENDERS: u64 = 50;
+
+#[derive(Debug, Clone)]
+pub struct PipelineBenchOptions {
+    pub messages: usize,
+    pub concurrency: usize,
+}
+
+impl Default for PipelineBenchOptions {
+    fn default() -> Self {
+        Self {
+            messages: 1000,
+            concurrency: 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PipelineBenchReport {
+    pub messages: usize,
+    pub failures: usize,
+    pub elapsed: Duration,
+    /// Per-message latencies, sorted ascending.
+    pub latencies: Vec<Duration>,
+}
+
+impl PipelineBenchReport {
+    pub fn messages_per_sec(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds == 0.0 {
+            return 0.0;
+        }
+        self.messages as f64 / seconds
+    }
+
+    /// Nearest-rank percentile, `percentile` in `0.0..=100.0`.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = (percentile / 100.0 * self.latencies.len() as f64).ceil() as usize;
+        self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+    }
+}
+
+impl fmt::Display for PipelineBenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "messages={} failures={} elapsed={:.2?} throughput={:.1} msgs/sec p50={:.2?} p99={:.2?} max={:.2?}",
+            self.messages,
+            self.failures,
+            self.elapsed,
+            self.messages_per_sec(),
+            self.percentile(50.0),
+            self.percentile(99.0),
+            self.percentile(100.0),
+        )
+    }
+}
+
+/// Bridge configuration for benchmarks: the bundled sample config pointed at
+/// `homeserver_url` and `database_url`, with dummy credentials.
+pub fn synthetic_config(homeserver_url: &str, database_url: &str) -> Result<Config> {
+    let mut config: Config =
+        serde_yaml::from_str(SAMPLE_CONFIG).context("failed to parse sample config")?;
+    config.bridge.homeserver_url = homeserver_url.to_string();
+    config.database.url = Some(database_url.to_string());
+    config.registration.bridge_id = "bench".to_string();
+    config.registration.appservice_token = "bench_as_token".into();
+    config.registration.homeserver_token = "bench_hs_token".into();
+    config.auth.bot_token = "bench_bot_token".into();
+    config.limits.discord_send_delay = 0;
+    Ok(config)
+}
+
+pub async fn run_pipeline_bench(options: PipelineBenchOptions) -> Result<PipelineBenchReport> {
+    if options.messages == 0 {
+        return Err(anyhow!("--bench-messages must be greater than zero"));
+    }
+    let concurrency = options.concurrency.max(1);
+
+    let homeserver = LoopbackHomeserver::start().await?;
+    let data_dir = tempfile::Builder::new()
+        .prefix("matrix-bridge-discord-bench-")
+        .tempdir()
+        .context("failed to create the bench data directory")?;
+    let database_url = format!("sqlite://{}", data_dir.path().join("bench.db").display());
+    let config = Arc::new(synthetic_config(&homeserver.url, &database_url)?);
+
+    let db = Arc::new(DatabaseManager::new(&config.database).await?);
+    db.migrate().await?;
+    db.room_store()
+        .create_room_mapping(&RoomMapping {
+            id: 0,
+            matrix_room_id: BENCH_ROOM_ID.to_string(),
+            discord_channel_id: BENCH_CHANNEL_ID.to_string(),
+            discord_channel_name: "bench".to_string(),
+            discord_guild_id: BENCH_GUILD_ID.to_string(),
+            created_by: None,
+            origin: None,
+            last_active_at: None,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        })
+        .await?;
+
+    let matrix = Arc::new(MatrixAppservice::new(config.clone()).await?);
+    let discord = Arc::new(DiscordClient::new(config).await?);
+    // Senders and the channel are served from the metadata cache so no
+    // Discord API is needed.
+    discord
+        .metadata()
+        .upsert_channel(ChannelSnapshot {
+            id: BENCH_CHANNEL_ID.to_string(),
+            name: "bench".to_string(),
+            guild_id: BENCH_GUILD_ID.to_string(),
+            topic: None,
+            slowmode_seconds: 0,
+            thread_parent_id: None,
+            everyone_can_view: true,
+        })
+        .await;
+    for sender in 0..BENCH_SENDERS {
+        discord
+            .metadata()
+            .upsert_user(UserSnapshot {
+                id: sender_id(sender),
+                username: format!("bench-user-{sender}"),
+                discriminator: "0".to_string(),
+                avatar_url: None,
+            })
+            .await;
+    }
+    let bridge = Arc::new(BridgeCore::new(matrix, discord, db));
+
+    info!(
+        "benchmarking pipeline with {} messages, concurrency {}",
+        options.messages, concurrency
+    );
+
+    let started = Instant::now();
+    let mut workers = Vec::with_capacity(concurrency);
+    for worker in 0..concurrency {
+        let bridge = bridge.clone();
+        let messages = options.messages;
+        workers.push(tokio::spawn(async move {
+            let mut latencies = Vec::new();
+            let mut failures = 0;
+            for index in (worker..messages).step_by(concurrency) {
+                let message_started = Instant::now();
+                if let Err(err) = bridge
+                    .handle_discord_message_with_context(synthetic_message(index))
+                    .await
+                {
+                    debug!("bench message {index} failed: {err:#}");
+                    failures += 1;
+                }
+                latencies.push(message_started.elapsed());
+            }
+            (latencies, failures)
+        }));
+    }
+
+    let mut latencies = Vec::with_capacity(options.messages);
+    let mut failures = 0;
+    for worker in workers {
+        let (worker_latencies, worker_failures) = worker.await?;
+        latencies.extend(worker_latencies);
+        failures += worker_failures;
+    }
+    let elapsed = started.elapsed();
+    latencies.sort_unstable();
+
+    Ok(PipelineBenchReport {
+        messages: options.messages,
+        failures,
+        elapsed,
+        latencies,
+    })
+}
+
+fn sender_id(sender: u64) -> String {
+    (200_000_000_000_000_000 + sender).to_string()
+}
+
+fn message_id(index: usize) -> String {
+    (300_000_000_000_000_000 + index as u64).to_string()
+}
+
+/// Mix of plain text, markdown, mentions, replies and edits so every stage of
+/// the pipeline is exercised.
+pub fn synthetic_message(index: usize) -> DiscordMessageContext {
+    let sender = sender_id(index as u64 % BENCH_SENDERS);
+    let content = match index % 5 {
+        0 => format!("plain message number {index}"),
+        1 => format!("**bold** and *italic* with `code` in message {index}"),
+        2 => format!(
+            "hey <@{}> check <#{BENCH_CHANNEL_ID}> at <t:1700000000:R>",
+            sender_id((index as u64 + 1) % BENCH_SENDERS)
+        ),
+        3 => format!("```rust\nfn main() {{ println!(\"{index}\"); }}\n```"),
+        _ => format!("> quoted\nreply body {index} with a link https://example.org/{index}"),
+    };
+    let reply_to = (index % 7 == 6).then(|| message_id(index - 1));
+    let edit_of = (index % 11 == 10).then(|| message_id(index - 2));
+
+    DiscordMessageContext {
+        channel_id: BENCH_CHANNEL_ID.to_string(),
+        source_message_id: Some(edit_of.clone().unwrap_or_else(|| message_id(index))),
+        sender_id: sender,
+        content,
+        attachments: Vec::new(),
+        reply_to,
+        edit_of,
+        permissions: HashSet::new(),
+        sent_at: None,
+        relayed_name: None,
+        attributed_to: None,
+        stickers: Vec::new(),
+        reference: None,
+        thread_parent_id: None,
+    }
+}
+
+static LOOPBACK_EVENT_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Answers sends with a fresh event id and everything else with `{}`.
+#[handler]
+async fn loopback_homeserver(req: &mut Request, res: &mut Response) {
+    if req.method() == salvo::http::Method::PUT && req.uri().path().contains("/send/") {
+        let counter = LOOPBACK_EVENT_COUNTER.fetch_add(1, Ordering::Relaxed);
+        res.render(Json(json!({ "event_id": format!("$bench{counter}") })));
+        return;
+    }
+    res.render(Json(json!({})));
+}
+
+struct LoopbackHomeserver {
+    url: String,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl LoopbackHomeserver {
+    async fn start() -> Result<Self> {
+        let acceptor = TcpListener::new("127.0.0.1:0").bind().await;
+        let addr = acceptor
+            .holdings()
+            .first()
+            .and_then(|holding| holding.local_addr.clone().into_std())
+            .ok_or_else(|| anyhow!("loopback homeserver has no local address"))?;
+        let router = Router::with_path("{**rest}").goal(loopback_homeserver);
+        let task = tokio::spawn(async move {
+            Server::new(acceptor).serve(router).await;
+        });
+        Ok(Self {
+            url: format!("http://{addr}"),
+            task,
+        })
+    }
+}
+
+impl Drop for LoopbackHomeserver {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{PipelineBenchOptions, PipelineBenchReport, run_pipeline_bench};
+
+    #[test]
+    fn percentile_uses_nearest_rank() {
+        let report = PipelineBenchReport {
+            messages: 100,
+            failures: 0,
+            elapsed: Duration::from_secs(2),
+            latencies: (1..=100).map(Duration::from_millis).collect(),
+        };
+        assert_eq!(report.messages_per_sec(), 50.0);
+        assert_eq!(report.percentile(50.0), Duration::from_millis(50));
+        assert_eq!(report.percentile(99.0), Duration::from_millis(99));
+        assert_eq!(report.percentile(100.0), Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn pipeline_bench_pushes_every_message_through_the_bridge() {
+        let report = run_pipeline_bench(PipelineBenchOptions {
+            messages: 40,
+            concurrency: 4,
+        })
+        .await
+        .expect("bench run");
+        assert_eq!(report.latencies.len(), 40);
+        assert_eq!(report.failures, 0);
+    }
+}
@@ -0,0 +1,191 @@
+//! First-run setup behind `--bootstrap`. Writes the commented sample config
+//! with the homeserver details filled in, plus a registration file with fresh
+//! tokens, so a container can be set up with a single command.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+use crate::cli::{generate_token, registration_yaml};
+
+const SAMPLE_CONFIG: &str = include_str!("../config/config.sample.yaml");
+const CONFIG_FILE: &str = "config.yaml";
+/// Loaded automatically when it sits next to the config file.
+const REGISTRATION_FILE: &str = "discord-registration.yaml";
+const REGISTRATION_ID: &str = "discord";
+
+#[derive(Debug, Clone)]
+pub struct BootstrapOptions {
+    pub dir: PathBuf,
+    pub domain: String,
+    pub homeserver_url: String,
+    /// Where the homeserver reaches the bridge; goes into the registration.
+    pub appservice_url: String,
+    pub force: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct BootstrapReport {
+    pub config_path: PathBuf,
+    pub registration_path: PathBuf,
+}
+
+impl fmt::Display for BootstrapReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "wrote {}", self.config_path.display())?;
+        writeln!(f, "wrote {}", self.registration_path.display())?;
+        writeln!(f, "next steps:")?;
+        writeln!(
+            f,
+            "  1. set auth.bot_token and auth.client_id in {} (or APPSERVICE_DISCORD_AUTH_BOT_TOKEN)",
+            self.config_path.display()
+        )?;
+        write!(
+            f,
+            "  2. register {} with your homeserver and restart it",
+            self.registration_path.display()
+        )
+    }
+}
+
+pub fn run_bootstrap(options: &BootstrapOptions) -> Result<BootstrapReport> {
+    let config_path = options.dir.join(CONFIG_FILE);
+    let registration_path = options.dir.join(REGISTRATION_FILE);
+    if !options.force {
+        for path in [&config_path, &registration_path] {
+            if path.exists() {
+                bail!(
+                    "{} already exists; pass --bootstrap-force to overwrite it",
+                    path.display()
+                );
+            }
+        }
+    }
+
+    std::fs::create_dir_all(&options.dir)
+        .with_context(|| format!("failed to create {}", options.dir.display()))?;
+
+    let registration = registration_yaml(
+        REGISTRATION_ID,
+        &options.appservice_url,
+        &options.domain,
+        &generate_token(),
+        &generate_token(),
+    );
+    write_private(&registration_path, &registration)?;
+    write_private(
+        &config_path,
+        &render_config(&options.domain, &options.homeserver_url),
+    )?;
+
+    Ok(BootstrapReport {
+        config_path,
+        registration_path,
+    })
+}
+
+/// The sample config with the homeserver details substituted. The
+/// registration id and tokens are left out so they are read from the
+/// registration file instead of being kept in two places.
+fn render_config(domain: &str, homeserver_url: &str) -> String {
+    let mut rendered = String::with_capacity(SAMPLE_CONFIG.len());
+    for line in SAMPLE_CONFIG.lines() {
+        // Only top-level keys of a section are indented by exactly two spaces.
+        let key = line
+            .strip_prefix("  ")
+            .filter(|rest| !rest.starts_with(' '))
+            .and_then(|rest| rest.split_once(':'))
+            .map(|(key, _)| key);
+        match key {
+            Some("domain") => {
+                rendered.push_str(&format!("  domain: {}\n", yaml_string(domain)));
+            }
+            Some("homeserver_url") => {
+                rendered.push_str(&format!(
+                    "  homeserver_url: {}\n",
+                    yaml_string(homeserver_url)
+                ));
+            }
+            Some("bridge_id" | "appservice_token" | "homeserver_token") => {}
+            _ => {
+                rendered.push_str(line);
+                rendered.push('\n');
+            }
+        }
+    }
+    rendered
+}
+
+fn yaml_string(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+/// Both files hold secrets, so they are only readable by the owner.
+fn write_private(path: &Path, content: &str) -> Result<()> {
+    std::fs::write(path, content).with_context(|| format!("failed to write {}", path.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+            .with_context(|| format!("failed to set permissions on {}", path.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BootstrapOptions, run_bootstrap};
+    use crate::config::Config;
+
+    fn options(dir: &std::path::Path) -> BootstrapOptions {
+        BootstrapOptions {
+            dir: dir.join("data"),
+            domain: "example.org".to_string(),
+            homeserver_url: "https://matrix.example.org".to_string(),
+            appservice_url: "http://bridge:9005".to_string(),
+            force: false,
+        }
+    }
+
+    #[test]
+    fn bootstrap_writes_a_config_that_loads_with_its_registration() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = options(dir.path());
+        let report = run_bootstrap(&options).unwrap();
+
+        // The only thing left for the operator is the Discord bot token.
+        let config_yaml = std::fs::read_to_string(&report.config_path)
+            .unwrap()
+            .replace(
+                "CHANGE_ME_DISCORD_BOT_TOKEN",
+                "MTIzNDU2Nzg5MDEyMzQ1Njc4.GhIjKl.real-looking-token",
+            );
+        std::fs::write(&report.config_path, config_yaml).unwrap();
+
+        let config = Config::load_from_file(&report.config_path).unwrap();
+        assert_eq!(config.bridge.domain, "example.org");
+        assert_eq!(config.bridge.homeserver_url, "https://matrix.example.org");
+
+        let registration: serde_yaml::Value =
+            serde_yaml::from_str(&std::fs::read_to_string(&report.registration_path).unwrap())
+                .unwrap();
+        assert_eq!(registration["url"].as_str(), Some("http://bridge:9005"));
+        assert_eq!(
+            registration["as_token"].as_str().map(str::to_owned),
+            Some(
+                secrecy::ExposeSecret::expose_secret(&config.registration.appservice_token)
+                    .to_string()
+            )
+        );
+        assert_ne!(registration["as_token"], registration["hs_token"]);
+
+        let err = run_bootstrap(&options).unwrap_err();
+        assert!(err.to_string().contains("already exists"));
+        run_bootstrap(&BootstrapOptions {
+            force: true,
+            ..options
+        })
+        .unwrap();
+    }
+}
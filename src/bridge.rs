@@ -1,44 +1,107 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use async_trait::async_trait;
-use chrono::Utc;
-use serde_json::json;
-use tracing::{debug, info, warn};
+use chrono::{DateTime, Utc};
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tracing::{debug, error, info, warn};
 
 use crate::cache::AsyncTimedCache;
-use crate::db::{DatabaseManager, MessageMapping, RoomMapping};
+use crate::config::{AttachmentPolicy, DeliveryMode, InactiveRoomsConfig, MatrixPresenceState};
+use crate::db::{
+    AttachmentPolicyOverrides, AuditLogEntry, AuditSource, DatabaseManager, DeliveryDirection,
+    EmojiUsageKind, MemberSyncProgress, MessageMapping, PendingDelivery, RoomMapping, RoomOrigin,
+    RoomSettings, ThreadMapping, UserRoomSettings,
+};
 use crate::discord::{
-    DiscordClient, DiscordCommandHandler, DiscordCommandOutcome, ModerationAction,
+    AuditedAction, DiscordClient, DiscordCommandHandler, DiscordCommandOutcome, DiscordNotReady,
+    ModerationAction, PreflightError, PrivilegedIntent,
+};
+use crate::emoji::{EMOTE_PACK_EVENT_TYPE, EmojiHandler, GuildEmoji, emote_pack_content};
+use crate::matrix::{
+    HomeserverAdminClient, MatrixAppservice, MatrixCommandHandler, MatrixCommandOutcome,
+    MatrixCommandSender, MatrixEvent, RelatesTo,
 };
-use crate::emoji::EmojiHandler;
-use crate::matrix::{MatrixAppservice, MatrixCommandHandler, MatrixCommandOutcome, MatrixEvent};
 use crate::media::MediaHandler;
+use crate::parsers::location::find_geo_uris;
+use crate::utils::{AdminNotifier, AlertCondition, CircuitBreaker, WebhookAlerter};
+use crate::utils::{dry_run, snowflake};
+use crate::web::Metrics;
 
+pub mod ban_sync;
 pub mod blocker;
+pub mod bridge_info;
+pub mod content_redaction;
+pub mod cross_channel;
+pub mod delivery;
+pub mod delivery_confirmation;
+pub mod ghost_directory;
+pub mod inactivity;
 pub mod logic;
+pub mod loop_guard;
+pub mod maintenance;
+pub mod member_notices;
+pub mod member_sync;
 pub mod message_flow;
+pub mod modlog;
 pub mod presence_handler;
 pub mod provisioning;
+pub mod purge;
 pub mod queue;
+pub mod receipt_handler;
+pub mod relay_unwrap;
+pub mod replay;
+pub mod room_status;
+pub mod self_test;
+pub mod slowmode;
+pub mod supervisor;
+pub mod threads;
 pub mod user_sync;
 
+use self::ban_sync::{missing_bans, sync_bans_reply};
+use self::blocker::BridgeState;
+use self::bridge_info::{BRIDGE_INFO_EVENT_TYPES, bridge_info_content, bridge_info_state_key};
+use self::cross_channel::context_notice;
+use self::delivery::{
+    DeliverySequencer, DiscordEvent, RETRY_BATCH_SIZE, RETRY_POLL_INTERVAL, delivery_room,
+    retry_delay,
+};
+use self::delivery_confirmation::{CONFIRMATION_KEY, DeliveryConfirmations};
+use self::inactivity::{
+    InactivityAction, inactivity_action, inactivity_unbridge_notice, inactivity_warning,
+};
 use self::logic::{
     action_keyword, apply_message_relation_mappings, build_discord_typing_request,
-    discord_delete_redaction_request, preview_text, should_forward_discord_typing,
+    diff_pinned_events, discord_delete_redaction_request, parse_matrix_reaction,
+    parse_matrix_redaction, preview_text, should_forward_discord_typing,
 };
-use self::message_flow::{
-    DiscordInboundMessage, MessageFlow, OutboundDiscordMessage, OutboundMatrixMessage,
+use self::maintenance::{
+    BridgectlCommand, MaintenanceMode, bridged_rooms_reply, maintenance_status_reply,
+    maintenance_toggle_reply, parse_bridgectl,
 };
-use self::presence_handler::{
-    DiscordPresence, MatrixPresenceState, MatrixPresenceTarget, PresenceHandler,
+use self::member_notices::{MemberNoticeTracker, member_change_notice};
+use self::message_flow::{
+    DiscordInboundMessage, MessageFlow, MessageRelation, OutboundDiscordMessage,
+    OutboundMatrixMessage,
 };
+use self::modlog::{ModlogAction, ModlogEntry};
+use self::presence_handler::{DiscordPresence, MatrixPresenceTarget, PresenceHandler};
 use self::provisioning::{ApprovalResponseStatus, ProvisioningCoordinator, ProvisioningError};
 use self::queue::ChannelQueue;
-
-#[derive(Debug, Clone)]
+use self::receipt_handler::{ReadMarker, ReceiptHandler, parse_receipts};
+use self::relay_unwrap::{RelayExtractors, RelayedMessage};
+use self::replay::{REPLAY_MESSAGE_LIMIT, ReplayReport};
+use self::room_status::{RoomStatus, room_status_reply};
+use self::slowmode::{SlowmodeTracker, slowmode_label, topic_with_slowmode};
+use self::supervisor::{TaskStatus, TaskSupervisor};
+use self::threads::thread_name;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DiscordMessageContext {
     pub channel_id: String,
     pub source_message_id: Option<String>,
@@ -48,9 +111,70 @@ pub struct DiscordMessageContext {
     pub reply_to: Option<String>,
     pub edit_of: Option<String>,
     pub permissions: HashSet<String>,
+    /// When the message was sent on Discord, used to backdate it if it is
+    /// delivered late.
+    #[serde(default)]
+    pub sent_at: Option<DateTime<Utc>>,
+    /// Author name unwrapped from a relay bot's message; `sender_id` is then
+    /// the ghost id for that name.
+    #[serde(default)]
+    pub relayed_name: Option<String>,
+    /// Shown before the text of another bot's message, e.g.
+    /// `Dice (command: /roll by alice)` for the output of a slash command.
+    #[serde(default)]
+    pub attributed_to: Option<String>,
+    #[serde(default)]
+    pub stickers: Vec<DiscordSticker>,
+    /// The message this one replies to or forwards, which may be in another
+    /// channel.
+    #[serde(default)]
+    pub reference: Option<DiscordReference>,
+    /// The channel the message's thread belongs to, when it was posted in
+    /// a thread.
+    #[serde(default)]
+    pub thread_parent_id: Option<String>,
+}
+
+/// Where a reply or forwarded message points.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiscordReference {
+    pub channel_id: String,
+    pub message_id: Option<String>,
+    pub forwarded: bool,
+    /// Text of the referenced message; for a forward, of the copy that was
+    /// forwarded.
+    #[serde(default)]
+    pub content: String,
+}
+
+/// A sticker posted with a Discord message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiscordSticker {
+    pub id: String,
+    pub name: String,
 }
 
 const ROOM_CACHE_TTL_SECS: u64 = 900;
+/// Presence failures in a row before updates pause and the admin is told.
+const PRESENCE_FAILURE_THRESHOLD: u32 = 10;
+const PRESENCE_BREAKER_COOLDOWN: Duration = Duration::from_secs(60);
+/// How stale a room's `last_active_at` may get before it is written again.
+const ROOM_ACTIVITY_RESOLUTION: chrono::TimeDelta = chrono::TimeDelta::minutes(5);
+
+/// How often rooms are checked against `bridge.inactive_rooms`.
+const INACTIVITY_SWEEP_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// How often counted emoji and sticker uses are written to the database.
+const EMOJI_USAGE_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often rooms are checked for pending member syncs.
+const MEMBER_SYNC_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How often the rate-limit headroom of Discord delivery routes is sampled.
+const RATE_LIMIT_SAMPLE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// When a message last made it across in each direction, since startup.
+type LastBridged = BTreeMap<&'static str, DateTime<Utc>>;
 
 #[derive(Clone)]
 pub struct BridgeCore {
@@ -66,6 +190,28 @@ pub struct BridgeCore {
     emoji_handler: Arc<EmojiHandler>,
     message_queue: Arc<ChannelQueue>,
     room_cache: Arc<AsyncTimedCache<String, RoomMapping>>,
+    /// Set once the Discord client has started; Matrix events that arrive
+    /// before wait in the delivery queue.
+    discord_ready: Arc<AtomicBool>,
+    slowmode: Arc<SlowmodeTracker>,
+    member_notices: Arc<MemberNoticeTracker>,
+    receipts: Arc<ReceiptHandler>,
+    confirmations: Arc<DeliveryConfirmations>,
+    supervisor: Arc<TaskSupervisor>,
+    delivery_order: Arc<DeliverySequencer>,
+    relay_extractors: Arc<RelayExtractors>,
+    alerter: Arc<WebhookAlerter>,
+    maintenance: Arc<MaintenanceMode>,
+    last_bridged: Arc<parking_lot::Mutex<LastBridged>>,
+    /// When `last_active_at` was last written, keyed by room mapping id.
+    room_activity: Arc<parking_lot::Mutex<BTreeMap<i64, DateTime<Utc>>>>,
+    /// [`Self::last_bridged`] per room, keyed by room mapping id.
+    room_last_bridged: Arc<parking_lot::Mutex<BTreeMap<i64, LastBridged>>>,
+    /// When each room was warned that it is about to be unbridged for
+    /// inactivity, keyed by room mapping id.
+    inactivity_warnings: Arc<parking_lot::Mutex<BTreeMap<i64, DateTime<Utc>>>>,
+    /// Discord avatars already uploaded to Matrix, by their Discord url.
+    ghost_avatars: Arc<parking_lot::Mutex<BTreeMap<String, String>>>,
 }
 
 impl BridgeCore {
@@ -78,31 +224,69 @@ impl BridgeCore {
         let homeserver_url = matrix_client.config().bridge.homeserver_url.clone();
 
         let media_handler = Arc::new(MediaHandler::new(&homeserver_url));
-        let emoji_handler = Arc::new(EmojiHandler::new(
-            db_manager.clone(),
-            media_handler.clone(),
-            homeserver_url.clone(),
-        ));
+        let emoji_handler = Arc::new(
+            EmojiHandler::new(
+                db_manager.clone(),
+                media_handler.clone(),
+                homeserver_url.clone(),
+            )
+            .with_access_token(
+                matrix_client
+                    .config()
+                    .registration
+                    .appservice_token
+                    .expose_secret()
+                    .to_string(),
+            ),
+        );
 
         Self {
-            message_flow: Arc::new(MessageFlow::with_emoji_handler(
+            message_flow: Arc::new(MessageFlow::with_room_store(
                 matrix_client.clone(),
                 discord_client.clone(),
                 Some(emoji_handler.clone()),
+                Some(db_manager.room_store()),
             )),
             matrix_command_handler: Arc::new(MatrixCommandHandler::new(
                 bridge_config.enable_self_service_bridging,
                 None,
             )),
             discord_command_handler: Arc::new(DiscordCommandHandler::new()),
-            presence_handler: Arc::new(PresenceHandler::new(None)),
-            provisioning: Arc::new(ProvisioningCoordinator::default()),
+            presence_handler: Arc::new(PresenceHandler::with_mapping(
+                None,
+                bridge_config.presence_mapping.clone(),
+            )),
+            provisioning: Arc::new(match bridge_config.approval_dm_after_secs {
+                0 => ProvisioningCoordinator::default(),
+                secs => ProvisioningCoordinator::default()
+                    .with_escalation_after(Duration::from_secs(secs)),
+            }),
             media_handler,
             emoji_handler,
             message_queue: Arc::new(ChannelQueue::new()),
             room_cache: Arc::new(AsyncTimedCache::new(Duration::from_secs(
                 ROOM_CACHE_TTL_SECS,
             ))),
+            discord_ready: Arc::default(),
+            slowmode: Arc::new(SlowmodeTracker::new()),
+            member_notices: Arc::new(MemberNoticeTracker::new()),
+            receipts: Arc::new(ReceiptHandler::new()),
+            confirmations: Arc::new(DeliveryConfirmations::new()),
+            supervisor: Arc::new(TaskSupervisor::new()),
+            delivery_order: Arc::new(DeliverySequencer::new()),
+            relay_extractors: Arc::new(
+                RelayExtractors::new(&bridge_config.relay_extractors).unwrap_or_else(|err| {
+                    warn!("ignoring bridge.relay_extractors: {}", err);
+                    RelayExtractors::default()
+                }),
+            ),
+            alerter: Arc::new(WebhookAlerter::new(&matrix_client.config())),
+            maintenance: Arc::new(MaintenanceMode::new()),
+            last_bridged: Arc::default(),
+            room_activity: Arc::default(),
+            room_last_bridged: Arc::default(),
+            inactivity_warnings: Arc::default(),
+            ghost_avatars: Arc::default(),
             matrix_client,
             discord_client,
             db_manager,
@@ -112,22 +296,294 @@ impl BridgeCore {
     pub async fn start(&self) -> Result<()> {
         self.matrix_client.start().await?;
         self.discord_client.start().await?;
+        self.mark_discord_ready().await;
 
         info!("bridge core started");
 
+        let warmup = self.clone();
+        tokio::spawn(async move {
+            if let Err(err) = warmup.warm_up_caches().await {
+                warn!("cache warmup stopped: {}", err);
+            }
+        });
+
+        let retries = self.clone();
+        self.supervisor.spawn("delivery_retries", move || {
+            let bridge = retries.clone();
+            async move { bridge.run_delivery_retries().await }
+        });
+        if self.alerter.is_enabled() {
+            let alerts = self.clone();
+            self.supervisor.spawn("alerts", move || {
+                let bridge = alerts.clone();
+                async move { bridge.run_alert_checks().await }
+            });
+        }
+        if self
+            .matrix_client
+            .config()
+            .bridge
+            .inactive_rooms
+            .unbridge_after_days
+            > 0
+        {
+            let inactivity = self.clone();
+            self.supervisor.spawn("inactive_rooms", move || {
+                let bridge = inactivity.clone();
+                async move { bridge.run_inactivity_sweeps().await }
+            });
+        }
+        let missing_intents = self.discord_client.missing_intents();
+        if self.matrix_client.config().auth.use_privileged_intents
+            && let Some(notice) = missing_intents_notice(&missing_intents)
+        {
+            self.notify_admin(&notice).await;
+        }
+        // Listing guild members needs GUILD_MEMBERS; queued syncs wait for it.
+        if !missing_intents.contains(&PrivilegedIntent::GuildMembers) {
+            let member_syncs = self.clone();
+            self.supervisor.spawn("member_sync", move || {
+                let bridge = member_syncs.clone();
+                async move { bridge.run_member_syncs().await }
+            });
+        }
+        let rate_limits = self.clone();
+        self.supervisor.spawn("rate_limits", move || {
+            let bridge = rate_limits.clone();
+            async move { bridge.run_rate_limit_sampling().await }
+        });
+        let emoji_usage = self.clone();
+        self.supervisor.spawn("emoji_usage", move || {
+            let bridge = emoji_usage.clone();
+            async move { bridge.run_emoji_usage_flush().await }
+        });
+        let presence = self.clone();
+        let presence_task = self.supervisor.spawn("presence", move || {
+            let bridge = presence.clone();
+            async move { bridge.run_presence_loop().await }
+        });
+
+        presence_task.await?;
+        Ok(())
+    }
+
+    /// Looks up the channel, guild and webhook of every bridged room, so the
+    /// first message after a restart does not wait on cold lookups. Rooms
+    /// whose channel or guild the bot can no longer see are only logged.
+    pub async fn warm_up_caches(&self) -> Result<()> {
+        let started = Instant::now();
+        let mappings = self
+            .db_manager
+            .room_store()
+            .list_room_mappings(i64::MAX, 0)
+            .await?;
+        let mut guilds = HashSet::new();
+        let mut warmed = 0;
+        for mapping in &mappings {
+            match self
+                .discord_client
+                .get_channel(&mapping.discord_channel_id)
+                .await
+            {
+                Ok(Some(_)) => {}
+                Ok(None) => {
+                    warn!(
+                        "cache warmup: discord channel {} of {} is gone",
+                        mapping.discord_channel_id, mapping.matrix_room_id
+                    );
+                    continue;
+                }
+                Err(err) => {
+                    warn!(
+                        "cache warmup: failed to look up discord channel {}: {}",
+                        mapping.discord_channel_id, err
+                    );
+                    continue;
+                }
+            }
+            if guilds.insert(mapping.discord_guild_id.clone()) {
+                match self
+                    .discord_client
+                    .get_guild(&mapping.discord_guild_id)
+                    .await
+                {
+                    Ok(Some(_)) => {}
+                    Ok(None) => warn!(
+                        "cache warmup: the bot is not a member of discord guild {}",
+                        mapping.discord_guild_id
+                    ),
+                    Err(err) => warn!(
+                        "cache warmup: failed to look up discord guild {}: {}",
+                        mapping.discord_guild_id, err
+                    ),
+                }
+            }
+            if let Err(err) = self
+                .discord_client
+                .warm_webhook(&mapping.discord_channel_id)
+                .await
+            {
+                warn!(
+                    "cache warmup: no webhook for discord channel {}: {}",
+                    mapping.discord_channel_id, err
+                );
+                continue;
+            }
+            warmed += 1;
+        }
+        info!(
+            "cache warmup finished: {}/{} channels in {} guilds ready in {:?}",
+            warmed,
+            mappings.len(),
+            guilds.len(),
+            started.elapsed()
+        );
+        Ok(())
+    }
+
+    /// State of the supervised background tasks, for `/health` and `/status`.
+    pub fn task_statuses(&self) -> Vec<TaskStatus> {
+        self.supervisor.statuses()
+    }
+
+    pub fn tasks_healthy(&self) -> bool {
+        self.supervisor.is_healthy()
+    }
+
+    /// Raises and clears the gateway and queue depth alerts every
+    /// `alerts.check_interval_secs`.
+    async fn run_alert_checks(&self) {
+        let alerts = self.matrix_client.config().alerts.clone();
+        let gateway_limit = Duration::from_secs(alerts.gateway_down_minutes * 60);
+        let mut ticker =
+            tokio::time::interval(Duration::from_secs(alerts.check_interval_secs.max(1)));
+        loop {
+            ticker.tick().await;
+            match self.discord_client.gateway_down_for() {
+                Some(down_for) if down_for >= gateway_limit => {
+                    self.alerter
+                        .raise(
+                            AlertCondition::GatewayDown,
+                            &format!(
+                                "The Discord gateway has been disconnected for {} minutes.",
+                                down_for.as_secs() / 60
+                            ),
+                        )
+                        .await;
+                }
+                Some(_) => {}
+                None => {
+                    self.alerter
+                        .resolve(
+                            AlertCondition::GatewayDown,
+                            "The Discord gateway is connected again.",
+                        )
+                        .await;
+                }
+            }
+
+            if alerts.queue_depth_threshold == 0 {
+                continue;
+            }
+            let queued = match self.db_manager.delivery_store().count_deliveries().await {
+                Ok(count) => count.max(0) as u64,
+                Err(err) => {
+                    warn!("failed to count queued deliveries: {}", err);
+                    continue;
+                }
+            };
+            if queued >= alerts.queue_depth_threshold {
+                self.alerter
+                    .raise(
+                        AlertCondition::QueueDepth,
+                        &format!(
+                            "{} messages are waiting in the delivery queue, above the threshold of {}.",
+                            queued, alerts.queue_depth_threshold
+                        ),
+                    )
+                    .await;
+            } else {
+                self.alerter
+                    .resolve(
+                        AlertCondition::QueueDepth,
+                        &format!("{} messages are waiting.", queued),
+                    )
+                    .await;
+            }
+        }
+    }
+
+    /// Sends one queued presence update per tick. Failures are logged and the
+    /// update retried; after `PRESENCE_FAILURE_THRESHOLD` failures in a row
+    /// the admin is notified and updates pause for `PRESENCE_BREAKER_COOLDOWN`.
+    async fn run_presence_loop(&self) {
         let bridge_config = self.matrix_client.config().bridge.clone();
+        let presence_enabled = !bridge_config.disable_presence
+            && self
+                .discord_client
+                .has_intent(PrivilegedIntent::GuildPresences);
         let presence_interval_ms = bridge_config.presence_interval.max(250);
         let mut ticker = tokio::time::interval(Duration::from_millis(presence_interval_ms));
+        let breaker = CircuitBreaker::new(PRESENCE_FAILURE_THRESHOLD, PRESENCE_BREAKER_COOLDOWN);
+        let target = GhostPresenceTarget {
+            matrix: self.matrix_client.as_ref(),
+            db: self.db_manager.as_ref(),
+        };
         loop {
             ticker.tick().await;
-            if !bridge_config.disable_presence {
-                self.presence_handler
-                    .process_next(self.matrix_client.as_ref())
-                    .await?;
+            if !presence_enabled || !breaker.allow(Instant::now()) {
+                continue;
+            }
+            match self.presence_handler.process_next(&target).await {
+                Ok(true) => {
+                    if breaker.record_success() {
+                        info!("matrix presence updates are working again");
+                        self.alerter
+                            .resolve(
+                                AlertCondition::HomeserverCircuitOpen,
+                                "Matrix presence updates are working again.",
+                            )
+                            .await;
+                    }
+                }
+                Ok(false) => {}
+                Err(err) => {
+                    warn!("{:#}", err);
+                    if breaker.record_failure(Instant::now()) {
+                        error!(
+                            "pausing matrix presence updates for {}s after {} failures in a row",
+                            PRESENCE_BREAKER_COOLDOWN.as_secs(),
+                            PRESENCE_FAILURE_THRESHOLD
+                        );
+                        let message = format!(
+                            "Matrix presence updates failed {} times in a row and are paused for {} seconds. Last error: {:#}",
+                            PRESENCE_FAILURE_THRESHOLD,
+                            PRESENCE_BREAKER_COOLDOWN.as_secs(),
+                            err
+                        );
+                        self.notify_admin(&message).await;
+                        self.alerter
+                            .raise(AlertCondition::HomeserverCircuitOpen, &message)
+                            .await;
+                    }
+                }
             }
         }
     }
 
+    /// Sends `message` to `bridge.admin_mxid` when one is configured.
+    async fn notify_admin(&self, message: &str) {
+        let Some(admin_mxid) = self.matrix_client.config().bridge.admin_mxid.clone() else {
+            return;
+        };
+        if let Err(err) = AdminNotifier::new(self.matrix_client.clone(), admin_mxid)
+            .notify(message)
+            .await
+        {
+            warn!("failed to notify the bridge admin: {}", err);
+        }
+    }
+
     pub async fn send_to_discord(
         &self,
         discord_channel_id: String,
@@ -141,8 +597,10 @@ impl BridgeCore {
                 reply_to: None,
                 edit_of: None,
                 attachments: Vec::new(),
+                attachment_names: BTreeMap::new(),
                 embed: None,
                 use_embed: false,
+                attachment_policy: AttachmentPolicy::Reupload,
             },
         )
         .await
@@ -161,7 +619,12 @@ impl BridgeCore {
                 body: content,
                 reply_to: None,
                 edit_of: None,
+                thread_root: None,
                 attachments: Vec::new(),
+                origin_server_ts: None,
+                formatted_body: None,
+                attribution_badge: false,
+                attachment_policy: AttachmentPolicy::Reupload,
             },
         )
         .await
@@ -190,6 +653,14 @@ impl BridgeCore {
         Ok(mapping)
     }
 
+    /// Whether `matrix_room_id` is bridged to a Discord channel.
+    pub async fn is_bridged_matrix_room(&self, matrix_room_id: &str) -> Result<bool> {
+        Ok(self
+            .get_room_mapping_cached(matrix_room_id)
+            .await?
+            .is_some())
+    }
+
     fn discord_user_id_from_mxid(&self, mxid: &str) -> Option<String> {
         let localpart = mxid.strip_prefix("@_discord_")?;
         let suffix = format!(":{}", self.matrix_client.config().bridge.domain);
@@ -200,139 +671,1569 @@ impl BridgeCore {
         Some(discord_user_id.to_string())
     }
 
+    /// Queues `event` in the delivery queue while the Discord client is not
+    /// ready yet; returns whether it was queued. The retry loop delivers it
+    /// once Discord is up, also after a restart.
+    async fn defer_until_discord_ready(&self, event: &MatrixEvent) -> bool {
+        if self.discord_ready.load(Ordering::Acquire) {
+            return false;
+        }
+        let Some(payload) = self.serialize_payload(DeliveryDirection::MatrixToDiscord, event)
+        else {
+            return false;
+        };
+        match self
+            .queue_now(DeliveryDirection::MatrixToDiscord, payload)
+            .await
+        {
+            Ok(id) => {
+                debug!(
+                    "matrix inbound deferred until discord is ready room_id={} event_id={:?} type={} id={}",
+                    event.room_id, event.event_id, event.event_type, id
+                );
+                true
+            }
+            Err(err) => {
+                warn!(
+                    "failed to defer matrix event {:?} until discord is ready, delivering now: {}",
+                    event.event_id, err
+                );
+                false
+            }
+        }
+    }
+
+    /// Queues `event` while maintenance mode is on or Discord is not ready;
+    /// returns whether it was queued.
+    async fn hold_matrix_event(&self, event: &MatrixEvent) -> bool {
+        self.hold_for_maintenance(DeliveryDirection::MatrixToDiscord, event)
+            .await
+            || self.defer_until_discord_ready(event).await
+    }
+
+    /// Lets Matrix events through to Discord and delivers the ones queued
+    /// while Discord was logging in.
+    pub async fn mark_discord_ready(&self) {
+        self.discord_ready.store(true, Ordering::Release);
+        match self.retry_due_deliveries().await {
+            Ok(0) => {}
+            Ok(replayed) => info!(
+                "replayed {} deliveries queued during discord login",
+                replayed
+            ),
+            Err(err) => warn!(
+                "failed to replay deliveries queued during discord login: {}",
+                err
+            ),
+        }
+    }
+
     pub async fn handle_matrix_message(&self, event: &MatrixEvent) -> Result<()> {
-        if self.matrix_client.is_namespaced_user(&event.sender) {
-            debug!(
-                "matrix inbound dropped room_id={} sender={} reason=echo_from_ghost",
-                event.room_id, event.sender
-            );
+        if self.handle_bridgectl(event).await? {
+            return Ok(());
+        }
+        if self.defer_until_discord_ready(event).await {
             return Ok(());
         }
+        self.deliver_matrix_message(event).await
+    }
 
+    /// Runs a `!bridgectl` command from the bridge admin, usually sent in
+    /// the admin room; returns whether `event` was one.
+    async fn handle_bridgectl(&self, event: &MatrixEvent) -> Result<bool> {
+        if self.matrix_client.config().bridge.admin_mxid.as_deref() != Some(event.sender.as_str()) {
+            return Ok(false);
+        }
         let body = event
             .content
             .as_ref()
             .map(crate::parsers::MessageUtils::extract_plain_text)
             .unwrap_or_default();
+        let Some(command) = parse_bridgectl(&body) else {
+            return Ok(false);
+        };
 
-        debug!(
-            "matrix inbound message event_id={:?} room_id={} sender={} type={} body_len={} body_preview={}",
-            event.event_id,
-            event.room_id,
-            event.sender,
-            event.event_type,
-            body.len(),
-            preview_text(&body)
-        );
-
-        let room_mapping = self
-            .db_manager
-            .room_store()
-            .get_room_by_matrix_room(&event.room_id)
-            .await?;
-
-        debug!(
-            "matrix inbound mapping lookup room_id={} mapped={}",
-            event.room_id,
-            room_mapping.is_some()
-        );
-
-        if self.matrix_command_handler.is_command(&body) {
-            debug!(
-                "matrix inbound command detected room_id={} sender={} command_preview={}",
-                event.room_id,
-                event.sender,
-                preview_text(&body)
-            );
-            let has_permissions = self
-                .matrix_client
-                .check_permission(
+        let reply = match command {
+            BridgectlCommand::Reply(reply) => reply,
+            BridgectlCommand::MaintenanceStatus => {
+                let queued = self.db_manager.delivery_store().count_deliveries().await?;
+                let mut reply = maintenance_status_reply(self.maintenance.since(), queued);
+                if let Some(notice) = missing_intents_notice(&self.missing_intents()) {
+                    reply.push('\n');
+                    reply.push_str(&notice);
+                }
+                reply
+            }
+            BridgectlCommand::Maintenance { enabled } => {
+                let changed = self
+                    .set_maintenance(enabled, &event.sender, AuditSource::AdminRoom)
+                    .await?;
+                maintenance_toggle_reply(enabled, changed)
+            }
+            BridgectlCommand::List => {
+                let rooms = self
+                    .db_manager
+                    .room_store()
+                    .list_room_mappings(i64::MAX, 0)
+                    .await?;
+                bridged_rooms_reply(&rooms)
+            }
+            BridgectlCommand::Exempt {
+                matrix_room_id,
+                exempt,
+            } => {
+                self.set_inactivity_exempt(&matrix_room_id, exempt, &event.sender)
+                    .await?
+            }
+            BridgectlCommand::Replay {
+                matrix_room_id,
+                since,
+            } => match self
+                .replay_discord_messages(
+                    &matrix_room_id,
+                    since,
                     &event.sender,
-                    &event.room_id,
-                    50,
-                    "events",
-                    "m.room.power_levels",
+                    AuditSource::AdminRoom,
                 )
-                .await
-                .unwrap_or(false);
-            debug!(
-                "matrix command permission result room_id={} sender={} granted={}",
-                event.room_id, event.sender, has_permissions
-            );
-            let outcome = self
-                .matrix_command_handler
-                .handle(&body, room_mapping.is_some(), |_| Ok(has_permissions));
-            self.handle_matrix_command_outcome(outcome, event).await?;
-            return Ok(());
-        }
-
-        let Some(mapping) = room_mapping else {
-            debug!(
-                "matrix inbound dropped room_id={} reason=no_discord_mapping",
-                event.room_id
-            );
-            return Ok(());
-        };
-        let Some(message) = MessageFlow::parse_matrix_event(event) else {
-            debug!(
-                "matrix inbound dropped room_id={} event_id={:?} reason=unsupported_or_unparseable",
-                event.room_id, event.event_id
-            );
-            return Ok(());
+                .await?
+            {
+                Some(report) => format!("Replay of {}: {}.", matrix_room_id, report),
+                None => format!("{} is not bridged.", matrix_room_id),
+            },
         };
+        self.matrix_client
+            .send_notice(&event.room_id, &reply)
+            .await?;
+        Ok(true)
+    }
 
-        let outbound = self.message_flow.matrix_to_discord(&message);
-        debug!(
-            "matrix->discord outbound prepared room_id={} discord_channel={} reply_to={:?} edit_of={:?} attachments={} content_len={} content_preview={}",
-            mapping.matrix_room_id,
-            mapping.discord_channel_id,
-            outbound.reply_to,
-            outbound.edit_of,
-            outbound.attachments.len(),
-            outbound.content.len(),
-            preview_text(&outbound.content)
-        );
+    /// Bridges the messages sent in the room's Discord channel since `since`
+    /// that are not mapped to a Matrix event, oldest first. `None` when the
+    /// room is not bridged.
+    pub async fn replay_discord_messages(
+        &self,
+        matrix_room_id: &str,
+        since: DateTime<Utc>,
+        actor: &str,
+        source: AuditSource,
+    ) -> Result<Option<ReplayReport>> {
+        let Some(mapping) = self.get_room_mapping_cached(matrix_room_id).await? else {
+            return Ok(None);
+        };
+        let messages = self
+            .discord_client
+            .channel_messages_since(&mapping.discord_channel_id, since, REPLAY_MESSAGE_LIMIT)
+            .await?;
 
-        let downloaded_attachments = self
-            .download_matrix_attachments(&outbound.attachments)
-            .await;
+        let mut report = ReplayReport {
+            fetched: messages.len(),
+            ..ReplayReport::default()
+        };
+        for message in messages {
+            let Some(message_id) = message.source_message_id.clone() else {
+                continue;
+            };
+            if self
+                .db_manager
+                .message_store()
+                .get_by_discord_message_id(&message_id)
+                .await?
+                .is_some()
+            {
+                report.already_bridged += 1;
+                continue;
+            }
+            match self.handle_discord_message_with_context(message).await {
+                Ok(()) => report.replayed += 1,
+                Err(err) => {
+                    warn!("failed to replay discord message {}: {}", message_id, err);
+                    report.failed += 1;
+                }
+            }
+        }
 
-        self.send_to_discord_with_attachments(
-            &mapping.discord_channel_id,
-            outbound,
-            &event.sender,
-            downloaded_attachments,
+        info!("replayed {} since {}: {}", matrix_room_id, since, report);
+        self.record_audit(
+            actor,
+            source,
+            "replay",
+            Some(matrix_room_id),
+            json!({ "since": since, "report": report }),
         )
-        .await?;
-        Ok(())
+        .await;
+        Ok(Some(report))
     }
 
-    async fn download_matrix_attachments(
+    pub fn maintenance_since(&self) -> Option<DateTime<Utc>> {
+        self.maintenance.since()
+    }
+
+    /// Switches maintenance mode on or off and announces it in every bridged
+    /// room and channel. Returns whether the mode changed.
+    pub async fn set_maintenance(
+        &self,
+        enabled: bool,
+        actor: &str,
+        source: AuditSource,
+    ) -> Result<bool> {
+        if self.maintenance.is_active() == enabled {
+            return Ok(false);
+        }
+        // Only switch once the announcement went out, so a failure leaves
+        // the mode as it was.
+        let notices = self.matrix_client.config().bridge.maintenance.clone();
+        let notice = if enabled {
+            notices.enter_notice
+        } else {
+            notices.exit_notice
+        };
+        if !notice.trim().is_empty() {
+            self.announce_to_bridged_rooms(&notice).await?;
+        }
+        if !self.maintenance.set(enabled) {
+            return Ok(false);
+        }
+        info!(
+            "maintenance mode switched {} by {}",
+            if enabled { "on" } else { "off" },
+            actor
+        );
+        self.record_audit(
+            actor,
+            source,
+            if enabled {
+                "maintenance_on"
+            } else {
+                "maintenance_off"
+            },
+            None,
+            json!({}),
+        )
+        .await;
+        Ok(true)
+    }
+
+    async fn announce_to_bridged_rooms(&self, notice: &str) -> Result<()> {
+        let rooms = self
+            .db_manager
+            .room_store()
+            .list_room_mappings(i64::MAX, 0)
+            .await?;
+        for room in rooms {
+            self.notify_both_sides(&room, notice).await;
+        }
+        Ok(())
+    }
+
+    /// Posts `notice` to the Matrix room and the Discord channel of `room`;
+    /// failures are only logged.
+    async fn notify_both_sides(&self, room: &RoomMapping, notice: &str) {
+        if let Err(err) = self
+            .matrix_client
+            .send_notice(&room.matrix_room_id, notice)
+            .await
+        {
+            warn!(
+                "failed to post notice to room {}: {}",
+                room.matrix_room_id, err
+            );
+        }
+        if let Err(err) = self
+            .discord_client
+            .send_message(&room.discord_channel_id, notice)
+            .await
+        {
+            warn!(
+                "failed to post notice to channel {}: {}",
+                room.discord_channel_id, err
+            );
+        }
+    }
+
+    async fn run_inactivity_sweeps(&self) {
+        let mut ticker = tokio::time::interval(INACTIVITY_SWEEP_INTERVAL);
+        loop {
+            ticker.tick().await;
+            if self.maintenance.is_active() {
+                continue;
+            }
+            if let Err(err) = self.sweep_inactive_rooms().await {
+                warn!("failed to check rooms for inactivity: {}", err);
+            }
+        }
+    }
+
+    async fn run_member_syncs(&self) {
+        let mut ticker = tokio::time::interval(MEMBER_SYNC_POLL_INTERVAL);
+        loop {
+            ticker.tick().await;
+            if self.maintenance.is_active() {
+                continue;
+            }
+            if let Err(err) = self.resume_member_syncs().await {
+                warn!("failed to look up pending member syncs: {}", err);
+            }
+        }
+    }
+
+    /// Runs the pending member syncs to the end, one room at a time.
+    async fn resume_member_syncs(&self) -> Result<()> {
+        let room_store = self.db_manager.room_store();
+        for room in room_store.list_room_mappings(i64::MAX, 0).await? {
+            let pending = room_store
+                .get_room_settings(&room.matrix_room_id)
+                .await?
+                .is_some_and(|settings| settings.member_sync.is_some());
+            if !pending {
+                continue;
+            }
+            if let Err(err) = self.sync_room_members(&room).await {
+                warn!(
+                    "member sync of {} interrupted: {}",
+                    room.matrix_room_id, err
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Joins the ghosts of the guild's members into `room`, batch by batch,
+    /// saving progress after each. Turning auto-invite off cancels the sync.
+    /// Returns how many ghosts this call joined.
+    pub async fn sync_room_members(&self, room: &RoomMapping) -> Result<u32> {
+        let limits = self.matrix_client.config().limits.clone();
+        let batch_size = member_sync::batch_size(&limits);
+        let cap = self.blocker().user_limit();
+        let room_store = self.db_manager.room_store();
+        let mut joined_now = 0;
+        loop {
+            let mut settings = self.room_settings(&room.matrix_room_id).await?;
+            let Some(mut progress) = settings.member_sync.take() else {
+                return Ok(joined_now);
+            };
+
+            let members = if progress.joined < cap {
+                self.discord_client
+                    .guild_members_after(
+                        &room.discord_guild_id,
+                        progress.after.as_deref(),
+                        batch_size as u64,
+                    )
+                    .await?
+            } else {
+                Vec::new()
+            };
+            for member in &members {
+                if progress.joined >= cap {
+                    break;
+                }
+                progress.after = Some(member.user_id.clone());
+                if member.bot {
+                    continue;
+                }
+                let joined = async {
+                    self.matrix_client
+                        .ensure_ghost_user_registered(&member.user_id, Some(&member.display_name))
+                        .await?;
+                    self.matrix_client
+                        .join_ghost_to_room(&member.user_id, &room.matrix_room_id)
+                        .await
+                }
+                .await;
+                match joined {
+                    Ok(()) => {
+                        progress.joined += 1;
+                        joined_now += 1;
+                    }
+                    Err(err) => warn!(
+                        "failed to join ghost of {} to {}: {}",
+                        member.user_id, room.matrix_room_id, err
+                    ),
+                }
+            }
+
+            let done = members.len() < batch_size || progress.joined >= cap;
+            // Re-read so a cancellation during the batch is kept.
+            settings = self.room_settings(&room.matrix_room_id).await?;
+            if settings.member_sync.is_none() {
+                return Ok(joined_now);
+            }
+            if done {
+                if progress.joined >= cap {
+                    warn!(
+                        "member sync of {} stopped at the user limit of {}",
+                        room.matrix_room_id, cap
+                    );
+                } else {
+                    info!(
+                        "member sync of {} finished with {} ghosts joined",
+                        room.matrix_room_id, progress.joined
+                    );
+                }
+            }
+            settings.member_sync = (!done).then_some(progress);
+            settings.updated_at = Utc::now();
+            room_store.set_room_settings(&settings).await?;
+            if done {
+                return Ok(joined_now);
+            }
+            tokio::time::sleep(member_sync::batch_pause(
+                &limits,
+                member_sync::jitter_seed(),
+            ))
+            .await;
+        }
+    }
+
+    /// Queues a sync of the guild's current members into the room, unless
+    /// one is already running.
+    async fn queue_member_sync(&self, matrix_room_id: &str) -> Result<()> {
+        let mut settings = self.room_settings(matrix_room_id).await?;
+        if settings.member_sync.is_some() {
+            return Ok(());
+        }
+        settings.member_sync = Some(MemberSyncProgress::default());
+        settings.updated_at = Utc::now();
+        self.db_manager
+            .room_store()
+            .set_room_settings(&settings)
+            .await?;
+        Ok(())
+    }
+
+    /// Warns rooms that are about to be unbridged for inactivity and
+    /// unbridges the ones whose notice ran out. Returns how many were
+    /// unbridged.
+    pub async fn sweep_inactive_rooms(&self) -> Result<usize> {
+        let policy = self.matrix_client.config().bridge.inactive_rooms.clone();
+        let room_store = self.db_manager.room_store();
+        let now = Utc::now();
+        let mut unbridged = 0;
+        for room in room_store.list_room_mappings(i64::MAX, 0).await? {
+            let exempt = room_store
+                .get_room_settings(&room.matrix_room_id)
+                .await?
+                .is_some_and(|settings| settings.inactivity_exempt);
+            if exempt {
+                continue;
+            }
+            // Rooms bridged before activity was recorded start counting now.
+            let Some(last_active) = room.last_active_at else {
+                room_store.touch_room_mapping(room.id, now).await?;
+                continue;
+            };
+
+            let warned_at = self.inactivity_warnings.lock().get(&room.id).copied();
+            match inactivity_action(&policy, last_active, warned_at, now) {
+                InactivityAction::Active => {
+                    self.inactivity_warnings.lock().remove(&room.id);
+                }
+                InactivityAction::Warn => {
+                    info!(
+                        "warning inactive room {} before unbridging",
+                        room.matrix_room_id
+                    );
+                    self.notify_both_sides(&room, &inactivity_warning(&policy))
+                        .await;
+                    self.inactivity_warnings.lock().insert(room.id, now);
+                }
+                InactivityAction::Wait => {}
+                InactivityAction::Unbridge => {
+                    self.unbridge_inactive_room(&room, &policy).await?;
+                    unbridged += 1;
+                }
+            }
+        }
+        Ok(unbridged)
+    }
+
+    async fn unbridge_inactive_room(
+        &self,
+        room: &RoomMapping,
+        policy: &InactiveRoomsConfig,
+    ) -> Result<()> {
+        info!(
+            "unbridging room {} from channel {} after {} days without activity",
+            room.matrix_room_id, room.discord_channel_id, policy.unbridge_after_days
+        );
+        self.notify_both_sides(room, &inactivity_unbridge_notice(policy))
+            .await;
+        self.unbridge_matrix_room(&room.matrix_room_id).await?;
+        self.inactivity_warnings.lock().remove(&room.id);
+        if let Err(err) = self
+            .discord_client
+            .remove_bridge_webhook(&room.discord_channel_id)
+            .await
+        {
+            warn!(
+                "failed to remove webhook from channel {}: {}",
+                room.discord_channel_id, err
+            );
+        }
+        self.record_audit(
+            "bridge",
+            AuditSource::Scheduled,
+            "unbridge_inactive",
+            Some(&room.matrix_room_id),
+            json!({
+                "discord_channel_id": room.discord_channel_id,
+                "last_active_at": room.last_active_at,
+            }),
+        )
+        .await;
+        Ok(())
+    }
+
+    /// Queues an inbound message or event while maintenance mode is on,
+    /// whatever the delivery mode of its direction. Returns whether it was
+    /// queued.
+    async fn hold_for_maintenance<T: Serialize>(
+        &self,
+        direction: DeliveryDirection,
+        inbound: &T,
+    ) -> bool {
+        if !self.maintenance.is_active() {
+            return false;
+        }
+        let Some(payload) = self.serialize_payload(direction, inbound) else {
+            return false;
+        };
+        match self.queue_now(direction, payload).await {
+            Ok(id) => {
+                debug!(
+                    "{} message held for maintenance id={}",
+                    direction.as_str(),
+                    id
+                );
+                true
+            }
+            Err(err) => {
+                warn!(
+                    "failed to hold {} message for maintenance, delivering now: {}",
+                    direction.as_str(),
+                    err
+                );
+                false
+            }
+        }
+    }
+
+    /// Like [`Self::hold_for_maintenance`] for a Discord event other than a
+    /// message, built only when it is held.
+    async fn hold_discord_event(&self, event: impl FnOnce() -> DiscordEvent) -> bool {
+        self.maintenance.is_active()
+            && self
+                .hold_for_maintenance(DeliveryDirection::DiscordToMatrix, &event())
+                .await
+    }
+
+    async fn deliver_matrix_message(&self, event: &MatrixEvent) -> Result<()> {
+        if self
+            .hold_for_maintenance(DeliveryDirection::MatrixToDiscord, event)
+            .await
+        {
+            return Ok(());
+        }
+        let payload = self.retry_payload(DeliveryDirection::MatrixToDiscord, event);
+        if self
+            .queue_behind_backlog(DeliveryDirection::MatrixToDiscord, &payload)
+            .await
+        {
+            return Ok(());
+        }
+        let result = self.process_matrix_message(event).await;
+        // Retrying cannot make an oversized message fit; tell the sender.
+        if let Err(err) = &result
+            && let Some(rejection) = PreflightError::find_in(err)
+        {
+            Metrics::delivery(DeliveryDirection::MatrixToDiscord.as_str(), "rejected");
+            warn!(
+                "matrix message {:?} in {} not sent to discord: {}",
+                event.event_id, event.room_id, rejection
+            );
+            self.matrix_client
+                .send_notice(&event.room_id, &rejection.sender_notice())
+                .await?;
+            return Ok(());
+        }
+        // Best-effort messages are queued too when Discord is only reconnecting.
+        let payload = match &result {
+            Err(err) if payload.is_none() && DiscordNotReady::is_cause_of(err) => {
+                self.serialize_payload(DeliveryDirection::MatrixToDiscord, event)
+            }
+            _ => payload,
+        };
+        self.settle_delivery(DeliveryDirection::MatrixToDiscord, payload, result)
+            .await
+    }
+
+    fn delivery_mode(&self, direction: DeliveryDirection) -> DeliveryMode {
+        let delivery = &self.matrix_client.config().delivery;
+        match direction {
+            DeliveryDirection::MatrixToDiscord => delivery.matrix_to_discord.mode,
+            DeliveryDirection::DiscordToMatrix => delivery.discord_to_matrix.mode,
+        }
+    }
+
+    /// The inbound message as it would be replayed from the retry queue, or
+    /// `None` when the direction is best-effort.
+    fn retry_payload<T: Serialize>(
+        &self,
+        direction: DeliveryDirection,
+        inbound: &T,
+    ) -> Option<Value> {
+        if self.delivery_mode(direction) != DeliveryMode::AtLeastOnce {
+            return None;
+        }
+        self.serialize_payload(direction, inbound)
+    }
+
+    fn serialize_payload<T: Serialize>(
+        &self,
+        direction: DeliveryDirection,
+        inbound: &T,
+    ) -> Option<Value> {
+        match serde_json::to_value(inbound) {
+            Ok(payload) => Some(payload),
+            Err(err) => {
+                warn!(
+                    "failed to serialize {} message for the retry queue: {}",
+                    direction.as_str(),
+                    err
+                );
+                None
+            }
+        }
+    }
+
+    /// Queues a new message instead of delivering it when older messages
+    /// for the same room are still waiting in the retry queue, so it cannot
+    /// overtake them. Returns whether the message was queued.
+    async fn queue_behind_backlog(
+        &self,
+        direction: DeliveryDirection,
+        payload: &Option<Value>,
+    ) -> bool {
+        let Some(payload) = payload else {
+            return false;
+        };
+        let Some(room) = delivery_room(direction, payload) else {
+            return false;
+        };
+        if !self.delivery_order.has_backlog(direction, &room) {
+            return false;
+        }
+
+        match self.queue_now(direction, payload.clone()).await {
+            Ok(id) => {
+                debug!(
+                    "{} message for {} queued behind older deliveries id={}",
+                    direction.as_str(),
+                    room,
+                    id
+                );
+                true
+            }
+            Err(err) => {
+                warn!(
+                    "failed to queue {} message behind the backlog for {}, delivering now: {}",
+                    direction.as_str(),
+                    room,
+                    err
+                );
+                false
+            }
+        }
+    }
+
+    /// Adds `payload` to the retry queue, due straight away; returns its id.
+    async fn queue_now(&self, direction: DeliveryDirection, payload: Value) -> Result<String> {
+        let now = Utc::now();
+        let delivery = PendingDelivery {
+            id: uuid::Uuid::new_v4().to_string(),
+            direction,
+            payload,
+            attempts: 0,
+            next_attempt_at: now,
+            last_error: None,
+            created_at: now,
+        };
+        self.db_manager
+            .delivery_store()
+            .enqueue_delivery(&delivery)
+            .await?;
+        self.delivery_order.track(&delivery);
+        Metrics::delivery(direction.as_str(), "queued");
+        Ok(delivery.id)
+    }
+
+    /// Decides what happens to a failed delivery. Best-effort messages are
+    /// dropped and the error returned; at-least-once messages are persisted
+    /// for `run_delivery_retries` to try again.
+    async fn settle_delivery(
+        &self,
+        direction: DeliveryDirection,
+        payload: Option<Value>,
+        result: Result<()>,
+    ) -> Result<()> {
+        let Err(err) = result else {
+            return Ok(());
+        };
+        let Some(payload) = payload else {
+            Metrics::delivery(direction.as_str(), "dropped");
+            return Err(err);
+        };
+
+        let config = &self.matrix_client.config().delivery;
+        if config.max_attempts <= 1 {
+            Metrics::delivery(direction.as_str(), "abandoned");
+            return Err(err);
+        }
+
+        let now = Utc::now();
+        let delay = chrono::Duration::from_std(retry_delay(config.retry_delay_ms, 1))
+            .unwrap_or_else(|_| chrono::Duration::zero());
+        let delivery = PendingDelivery {
+            id: uuid::Uuid::new_v4().to_string(),
+            direction,
+            payload,
+            attempts: 1,
+            next_attempt_at: now + delay,
+            last_error: Some(err.to_string()),
+            created_at: now,
+        };
+        if let Err(store_err) = self
+            .db_manager
+            .delivery_store()
+            .enqueue_delivery(&delivery)
+            .await
+        {
+            warn!(
+                "failed to queue {} message for retry: {}",
+                direction.as_str(),
+                store_err
+            );
+            Metrics::delivery(direction.as_str(), "dropped");
+            return Err(err);
+        }
+
+        self.delivery_order.track(&delivery);
+        Metrics::delivery(direction.as_str(), "queued");
+        warn!(
+            "{} delivery failed, queued for retry id={}: {}",
+            direction.as_str(),
+            delivery.id,
+            err
+        );
+        Ok(())
+    }
+
+    async fn run_emoji_usage_flush(&self) {
+        let mut ticker = tokio::time::interval(EMOJI_USAGE_FLUSH_INTERVAL);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = self.flush_emoji_usage().await {
+                warn!("failed to save emoji usage: {}", err);
+            }
+        }
+    }
+
+    /// Samples the rate-limit headroom of the routes messages are delivered
+    /// through, warning once each time a route drops below
+    /// `limits.discord_rate_limit_warn_percent`.
+    async fn run_rate_limit_sampling(&self) {
+        let warn_percent = self
+            .matrix_client
+            .config()
+            .limits
+            .discord_rate_limit_warn_percent;
+        let mut low_routes = HashSet::new();
+        let mut ticker = tokio::time::interval(RATE_LIMIT_SAMPLE_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let channel_ids = match self
+                .db_manager
+                .room_store()
+                .list_room_mappings(i64::MAX, 0)
+                .await
+            {
+                Ok(rooms) => rooms
+                    .into_iter()
+                    .map(|room| room.discord_channel_id)
+                    .collect::<Vec<_>>(),
+                Err(err) => {
+                    warn!("failed to list rooms for rate limit sampling: {}", err);
+                    continue;
+                }
+            };
+            let headroom = self.discord_client.rate_limit_headroom(&channel_ids).await;
+            Metrics::set_discord_rate_limits(&headroom);
+
+            for (route, state) in &headroom {
+                if state.percent() < warn_percent {
+                    if low_routes.insert(*route) {
+                        warn!(
+                            "discord rate limit headroom low on {}: {}/{} left, resets in {:.1}s",
+                            route,
+                            state.remaining,
+                            state.limit,
+                            state.reset_in.as_secs_f64()
+                        );
+                    }
+                } else if low_routes.remove(route) {
+                    info!("discord rate limit headroom recovered on {}", route);
+                }
+            }
+        }
+    }
+
+    /// When a message last made it across in `direction`, since startup.
+    pub fn last_bridged_at(&self, direction: DeliveryDirection) -> Option<DateTime<Utc>> {
+        self.last_bridged.lock().get(direction.as_str()).copied()
+    }
+
+    pub fn connected_guild_count(&self) -> usize {
+        self.discord_client.connected_guild_count()
+    }
+
+    pub fn missing_intents(&self) -> Vec<PrivilegedIntent> {
+        self.discord_client.missing_intents()
+    }
+
+    /// What `!discord status` and `!matrix status` answer for `mapping`.
+    /// Anyone may ask, so the webhook is only looked up, never created.
+    async fn room_status(&self, mapping: &RoomMapping) -> String {
+        let webhook = if self.matrix_client.config().channel.enable_webhook {
+            Some(
+                self.discord_client
+                    .has_webhook(&mapping.discord_channel_id)
+                    .await
+                    .map_err(|err| err.to_string()),
+            )
+        } else {
+            None
+        };
+        let encrypted = self
+            .matrix_client
+            .get_room_state(&mapping.matrix_room_id, "m.room.encryption", "")
+            .await
+            .ok()
+            .flatten()
+            .is_some_and(|content| content.get("algorithm").is_some());
+        let last_bridged = |direction: DeliveryDirection| {
+            self.room_last_bridged
+                .lock()
+                .get(&mapping.id)
+                .and_then(|last| last.get(direction.as_str()).copied())
+        };
+        room_status_reply(&RoomStatus {
+            mapping,
+            webhook,
+            last_matrix_to_discord: last_bridged(DeliveryDirection::MatrixToDiscord),
+            last_discord_to_matrix: last_bridged(DeliveryDirection::DiscordToMatrix),
+            maintenance_since: self.maintenance.since(),
+            encrypted,
+        })
+    }
+
+    /// Notes a message bridged through `mapping`. Its `last_active_at` is
+    /// written at most once per [`ROOM_ACTIVITY_RESOLUTION`].
+    async fn mark_bridged(&self, direction: DeliveryDirection, mapping: &RoomMapping) {
+        let now = Utc::now();
+        self.last_bridged.lock().insert(direction.as_str(), now);
+        self.room_last_bridged
+            .lock()
+            .entry(mapping.id)
+            .or_default()
+            .insert(direction.as_str(), now);
+
+        let stale = {
+            let mut written = self.room_activity.lock();
+            let last = written.get(&mapping.id).copied().or(mapping.last_active_at);
+            let stale = last.is_none_or(|last| now - last >= ROOM_ACTIVITY_RESOLUTION);
+            if stale {
+                written.insert(mapping.id, now);
+            }
+            stale
+        };
+        if stale
+            && let Err(err) = self
+                .db_manager
+                .room_store()
+                .touch_room_mapping(mapping.id, now)
+                .await
+        {
+            warn!(
+                "failed to record activity in {}: {}",
+                mapping.matrix_room_id, err
+            );
+        }
+    }
+
+    /// Writes the emoji and sticker uses counted so far to the database.
+    pub async fn flush_emoji_usage(&self) -> Result<usize> {
+        self.emoji_handler.flush_usage().await
+    }
+
+    async fn run_delivery_retries(&self) {
+        if let Err(err) = self.restore_delivery_order().await {
+            warn!("failed to load the delivery retry queue: {}", err);
+        }
+        let mut ticker = tokio::time::interval(RETRY_POLL_INTERVAL);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = self.retry_due_deliveries().await {
+                warn!("failed to process the delivery retry queue: {}", err);
+            }
+        }
+    }
+
+    /// Picks up deliveries queued before a restart so new messages keep
+    /// waiting behind them.
+    async fn restore_delivery_order(&self) -> Result<()> {
+        // Everything queued, however far off its next attempt is.
+        let horizon = Utc::now() + chrono::Duration::days(365);
+        let queued = self
+            .db_manager
+            .delivery_store()
+            .list_due_deliveries(horizon, i64::MAX)
+            .await?;
+        for delivery in &queued {
+            self.delivery_order.track(delivery);
+        }
+        Ok(())
+    }
+
+    /// Replays every queued delivery whose next attempt is due, removing the
+    /// ones that succeed or have run out of attempts. A delivery whose room
+    /// still has older ones queued is pushed back until the oldest is next
+    /// tried. Nothing is replayed while maintenance mode is on.
+    pub async fn retry_due_deliveries(&self) -> Result<usize> {
+        if self.maintenance.is_active() {
+            return Ok(0);
+        }
+        let store = self.db_manager.delivery_store();
+        let config = self.matrix_client.config().delivery.clone();
+        let due = store
+            .list_due_deliveries(Utc::now(), RETRY_BATCH_SIZE)
+            .await?;
+        let retried = due.len();
+
+        for delivery in due {
+            let direction = delivery.direction;
+            if let Some(wait_until) = self.delivery_order.blocked_until(&delivery) {
+                store
+                    .reschedule_delivery(
+                        &delivery.id,
+                        delivery.attempts,
+                        wait_until,
+                        delivery.last_error.as_deref(),
+                    )
+                    .await?;
+                self.delivery_order.rescheduled(&delivery, wait_until);
+                continue;
+            }
+            Metrics::delivery(direction.as_str(), "retried");
+            let attempts = delivery.attempts.saturating_add(1);
+            match self
+                .replay_delivery(delivery.direction, delivery.payload.clone())
+                .await
+            {
+                Ok(()) => {
+                    debug!(
+                        "{} delivery succeeded on attempt {} id={}",
+                        direction.as_str(),
+                        attempts,
+                        delivery.id
+                    );
+                    store.delete_delivery(&delivery.id).await?;
+                    self.delivery_order.finished(&delivery);
+                }
+                Err(err) if attempts as u32 >= config.max_attempts => {
+                    warn!(
+                        "giving up on {} delivery id={} after {} attempts: {}",
+                        direction.as_str(),
+                        delivery.id,
+                        attempts,
+                        err
+                    );
+                    Metrics::delivery(direction.as_str(), "abandoned");
+                    store.delete_delivery(&delivery.id).await?;
+                    self.delivery_order.finished(&delivery);
+                }
+                Err(err) => {
+                    let delay =
+                        chrono::Duration::from_std(retry_delay(config.retry_delay_ms, attempts))
+                            .unwrap_or_else(|_| chrono::Duration::zero());
+                    let next_attempt_at = Utc::now() + delay;
+                    store
+                        .reschedule_delivery(
+                            &delivery.id,
+                            attempts,
+                            next_attempt_at,
+                            Some(&err.to_string()),
+                        )
+                        .await?;
+                    self.delivery_order.rescheduled(&delivery, next_attempt_at);
+                }
+            }
+        }
+        Ok(retried)
+    }
+
+    async fn replay_delivery(&self, direction: DeliveryDirection, payload: Value) -> Result<()> {
+        match direction {
+            DeliveryDirection::MatrixToDiscord => {
+                let event: MatrixEvent = serde_json::from_value(payload)?;
+                match event.event_type.as_str() {
+                    "m.room.member" => self.process_matrix_member(&event).await,
+                    "m.room.name" => self.handle_matrix_room_name(&event).await,
+                    "m.room.topic" => self.handle_matrix_room_topic(&event).await,
+                    "m.room.power_levels" => self.handle_matrix_power_levels(&event).await,
+                    "m.room.pinned_events" => self.handle_matrix_pinned_events(&event).await,
+                    "m.reaction" => self.handle_matrix_reaction(&event).await,
+                    "m.room.redaction" => self.handle_matrix_redaction(&event).await,
+                    _ => self.process_matrix_message(&event).await,
+                }
+            }
+            DeliveryDirection::DiscordToMatrix => {
+                if let Some(event) = DiscordEvent::from_payload(&payload) {
+                    return self.replay_discord_event(event?).await;
+                }
+                let ctx: DiscordMessageContext = serde_json::from_value(payload)?;
+                // Queued messages without a send time are dated by their id.
+                let origin_server_ts = ctx
+                    .sent_at
+                    .or_else(|| {
+                        ctx.edit_of
+                            .is_none()
+                            .then_some(ctx.source_message_id.as_deref())
+                            .flatten()
+                            .and_then(snowflake::created_at)
+                    })
+                    .map(|sent_at| sent_at.timestamp_millis());
+                self.process_discord_message(ctx, origin_server_ts).await
+            }
+        }
+    }
+
+    async fn replay_discord_event(&self, event: DiscordEvent) -> Result<()> {
+        match event {
+            DiscordEvent::Reaction {
+                channel_id,
+                message_id,
+                user_id,
+                emoji,
+                custom_emoji_id,
+            } => {
+                self.handle_discord_reaction(
+                    &channel_id,
+                    &message_id,
+                    &user_id,
+                    &emoji,
+                    custom_emoji_id.as_deref(),
+                )
+                .await
+            }
+            DiscordEvent::MessageDelete {
+                channel_id,
+                message_id,
+            } => {
+                self.handle_discord_message_delete(&channel_id, &message_id)
+                    .await
+            }
+            DiscordEvent::ChannelUpdate {
+                channel_id,
+                name,
+                topic,
+                slowmode_seconds,
+            } => {
+                self.handle_discord_channel_update(
+                    &channel_id,
+                    &name,
+                    topic.as_deref(),
+                    slowmode_seconds,
+                )
+                .await
+            }
+            DiscordEvent::SlowmodeChange {
+                channel_id,
+                slowmode_seconds,
+            } => {
+                self.handle_discord_slowmode_change(&channel_id, slowmode_seconds)
+                    .await
+            }
+            DiscordEvent::ChannelDelete { channel_id } => {
+                self.handle_discord_channel_delete(&channel_id).await
+            }
+            DiscordEvent::ThreadDelete { thread_id } => {
+                self.handle_discord_thread_delete(&thread_id).await
+            }
+            DiscordEvent::GuildUpdate {
+                guild_id,
+                name,
+                icon_url,
+            } => {
+                self.handle_discord_guild_update(&guild_id, &name, icon_url.as_deref())
+                    .await
+            }
+            DiscordEvent::GuildDelete { guild_id } => {
+                self.handle_discord_guild_delete(&guild_id).await
+            }
+            DiscordEvent::GuildBan {
+                guild_id,
+                user_id,
+                banned,
+            } => {
+                self.handle_discord_guild_ban(&guild_id, &user_id, banned)
+                    .await
+            }
+            DiscordEvent::UserUpdate {
+                user_id,
+                username,
+                avatar_url,
+            } => {
+                self.handle_discord_user_update(&user_id, &username, avatar_url.as_deref())
+                    .await
+            }
+            DiscordEvent::MemberAdd {
+                guild_id,
+                user_id,
+                display_name,
+                avatar_url,
+                roles,
+            } => {
+                self.handle_discord_guild_member_add(
+                    &guild_id,
+                    &user_id,
+                    &display_name,
+                    avatar_url.as_deref(),
+                    &roles,
+                )
+                .await
+            }
+            DiscordEvent::MemberUpdate {
+                guild_id,
+                user_id,
+                display_name,
+                avatar_url,
+                roles,
+            } => {
+                self.handle_discord_guild_member_update(
+                    &guild_id,
+                    &user_id,
+                    &display_name,
+                    avatar_url.as_deref(),
+                    &roles,
+                )
+                .await
+            }
+            DiscordEvent::MemberChange {
+                guild_id,
+                user_id,
+                old_name,
+                new_name,
+                avatar_changed,
+            } => {
+                self.announce_discord_member_change(
+                    &guild_id,
+                    &user_id,
+                    &old_name,
+                    &new_name,
+                    avatar_changed,
+                )
+                .await
+            }
+            DiscordEvent::MemberRemove { guild_id, user_id } => {
+                self.handle_discord_guild_member_remove(&guild_id, &user_id)
+                    .await
+            }
+        }
+    }
+
+    async fn process_matrix_message(&self, event: &MatrixEvent) -> Result<()> {
+        if self.matrix_client.is_namespaced_user(&event.sender) {
+            debug!(
+                "matrix inbound dropped room_id={} sender={} reason=echo_from_ghost",
+                event.room_id, event.sender
+            );
+            return Ok(());
+        }
+
+        if let Some(tag) = event.content.as_ref().and_then(loop_guard::bridge_tag_of) {
+            debug!(
+                "matrix inbound dropped room_id={} sender={} tag={} reason=bridge_loop",
+                event.room_id, event.sender, tag
+            );
+            return Ok(());
+        }
+
+        let body = event
+            .content
+            .as_ref()
+            .map(crate::parsers::MessageUtils::extract_plain_text)
+            .unwrap_or_default();
+
+        debug!(
+            "matrix inbound message event_id={:?} room_id={} sender={} type={} body_len={} body_preview={}",
+            event.event_id,
+            event.room_id,
+            event.sender,
+            event.event_type,
+            body.len(),
+            preview_text(&body)
+        );
+
+        let room_mapping = self
+            .db_manager
+            .room_store()
+            .get_room_by_matrix_room(&event.room_id)
+            .await?;
+
+        debug!(
+            "matrix inbound mapping lookup room_id={} mapped={}",
+            event.room_id,
+            room_mapping.is_some()
+        );
+
+        if self.matrix_command_handler.is_command(&body) {
+            debug!(
+                "matrix inbound command detected room_id={} sender={} command_preview={}",
+                event.room_id,
+                event.sender,
+                preview_text(&body)
+            );
+            let has_permissions = self
+                .matrix_client
+                .check_permission(
+                    &event.sender,
+                    &event.room_id,
+                    50,
+                    "events",
+                    "m.room.power_levels",
+                )
+                .await
+                .unwrap_or(false);
+            debug!(
+                "matrix command permission result room_id={} sender={} granted={}",
+                event.room_id, event.sender, has_permissions
+            );
+            let sender = MatrixCommandSender {
+                room_id: &event.room_id,
+                user_id: &event.sender,
+                is_admin: self.matrix_client.config().bridge.admin_mxid.as_deref()
+                    == Some(event.sender.as_str()),
+            };
+            let outcome = self.matrix_command_handler.handle_from(
+                sender,
+                &body,
+                room_mapping.is_some(),
+                |_| Ok(has_permissions),
+            );
+            self.handle_matrix_command_outcome(outcome, event).await?;
+            return Ok(());
+        }
+
+        let Some(mapping) = room_mapping else {
+            debug!(
+                "matrix inbound dropped room_id={} reason=no_discord_mapping",
+                event.room_id
+            );
+            return Ok(());
+        };
+        let Some(message) = MessageFlow::parse_matrix_event(event) else {
+            debug!(
+                "matrix inbound dropped room_id={} event_id={:?} reason=unsupported_or_unparseable",
+                event.room_id, event.event_id
+            );
+            return Ok(());
+        };
+
+        if !self
+            .check_matrix_slowmode(event, &mapping.discord_channel_id)
+            .await
+        {
+            debug!(
+                "matrix inbound dropped room_id={} sender={} reason=slowmode",
+                event.room_id, event.sender
+            );
+            return Ok(());
+        }
+
+        // Only the room's power levels are fetched, and only for messages that
+        // would notify the whole channel.
+        let sender_can_notify_room = if self
+            .message_flow
+            .matrix_converter()
+            .mentions_whole_room(&message.body)
+        {
+            self.matrix_client
+                .can_notify_room(&event.room_id, &event.sender)
+                .await
+                .unwrap_or_else(|err| {
+                    warn!(
+                        "failed to read power levels of {}, defusing room mentions: {}",
+                        event.room_id, err
+                    );
+                    false
+                })
+        } else {
+            false
+        };
+        let mut outbound = self
+            .message_flow
+            .matrix_to_discord_resolved(&message, &mapping.discord_guild_id, sender_can_notify_room)
+            .await;
+        outbound.attachment_policy = self
+            .room_attachment_policy(&event.room_id, DeliveryDirection::MatrixToDiscord)
+            .await?;
+        let mut discord_channel_id = mapping.discord_channel_id.clone();
+        let mut edited_event = None;
+        if let Some(edited_event_id) = outbound.edit_of.take() {
+            let Some(edited) = self
+                .discord_message_for_edit(&event.room_id, &edited_event_id)
+                .await?
+            else {
+                debug!(
+                    "matrix inbound dropped room_id={} event_id={:?} edit_of={} reason=edit_of_unbridged_message",
+                    event.room_id, event.event_id, edited_event_id
+                );
+                return Ok(());
+            };
+            // An edit only changes the text; its media went out with the
+            // original message, in the channel or thread it is still in.
+            outbound.edit_of = Some(edited.discord_message_id);
+            if let Some(channel_id) = edited.discord_channel_id {
+                discord_channel_id = channel_id;
+            }
+            outbound.attachments.clear();
+            edited_event = Some(edited_event_id);
+        }
+        // Thread replies go to the Discord thread; without one they reply to
+        // the root in the channel.
+        if let Some(root_event_id) = message
+            .relation
+            .as_ref()
+            .and_then(MessageRelation::thread_root)
+        {
+            match self
+                .discord_thread_for(&mapping, root_event_id, &message.body)
+                .await?
+            {
+                Some(thread_id) => discord_channel_id = thread_id,
+                None => {
+                    outbound
+                        .reply_to
+                        .get_or_insert_with(|| root_event_id.to_string());
+                }
+            }
+        }
+        debug!(
+            "matrix->discord outbound prepared room_id={} discord_channel={} reply_to={:?} edit_of={:?} attachments={} content_len={} content_preview={}",
+            mapping.matrix_room_id,
+            discord_channel_id,
+            outbound.reply_to,
+            outbound.edit_of,
+            outbound.attachments.len(),
+            outbound.content.len(),
+            preview_text(&outbound.content)
+        );
+
+        let delivered: Result<Vec<String>> = async {
+            // Attachments go out one per message, so only the text can be
+            // too large; check it before any of the message is sent.
+            crate::discord::preflight::check_message(
+                &outbound.content,
+                outbound.embed.as_ref(),
+                0,
+            )?;
+            let downloaded_attachments = self
+                .download_matrix_attachments(
+                    &outbound.attachments,
+                    &outbound.attachment_names,
+                    outbound.attachment_policy,
+                )
+                .await;
+            self.send_to_discord_with_attachments(
+                &discord_channel_id,
+                &event.room_id,
+                outbound,
+                &event.sender,
+                downloaded_attachments,
+            )
+            .await
+        }
+        .await;
+        let discord_message_ids = match delivered {
+            Ok(discord_message_ids) => discord_message_ids,
+            Err(err) => {
+                // Discord no longer shows what the sender last wrote.
+                if let Some(edited_event) = edited_event.as_deref() {
+                    self.withdraw_delivery_confirmation(&event.room_id, edited_event)
+                        .await;
+                }
+                return Err(err);
+            }
+        };
+        self.mark_bridged(DeliveryDirection::MatrixToDiscord, &mapping)
+            .await;
+
+        // Edits keep pointing at the original event; everything else maps
+        // each Discord message back to the event it came from.
+        if let Some(event_id) = event.event_id.as_deref()
+            && edited_event.is_none()
+        {
+            self.store_matrix_message_mappings(
+                &event.room_id,
+                event_id,
+                &discord_channel_id,
+                discord_message_ids,
+            )
+            .await;
+        }
+        if let Some(delivered_event) = edited_event.as_deref().or(event.event_id.as_deref()) {
+            self.confirm_delivery(&event.room_id, delivered_event).await;
+        }
+        Ok(())
+    }
+
+    /// Reacts with ✅ to `event_id` if the room asked for delivery
+    /// confirmations and it has none yet. Failures only cost the reaction.
+    async fn confirm_delivery(&self, room_id: &str, event_id: &str) {
+        if self.confirmations.is_confirmed(event_id) {
+            return;
+        }
+        match self.room_settings(room_id).await {
+            Ok(settings) if settings.delivery_confirmations => {}
+            Ok(_) => return,
+            Err(err) => {
+                warn!(
+                    "delivery confirmation skipped room_id={} event_id={}: {:#}",
+                    room_id, event_id, err
+                );
+                return;
+            }
+        }
+        match self
+            .matrix_client
+            .send_reaction(room_id, event_id, CONFIRMATION_KEY)
+            .await
+        {
+            Ok(reaction_id) => self.confirmations.record(event_id, &reaction_id),
+            Err(err) => warn!(
+                "delivery confirmation failed room_id={} event_id={}: {:#}",
+                room_id, event_id, err
+            ),
+        }
+    }
+
+    /// Redacts the ✅ reaction on `event_id`, if the bridge put one there.
+    async fn withdraw_delivery_confirmation(&self, room_id: &str, event_id: &str) {
+        let Some(reaction_id) = self.confirmations.take(event_id) else {
+            return;
+        };
+        if let Err(err) = self
+            .matrix_client
+            .redact_message(room_id, &reaction_id, Some("Edit not delivered to Discord"))
+            .await
+        {
+            warn!(
+                "delivery confirmation not withdrawn room_id={} event_id={}: {:#}",
+                room_id, event_id, err
+            );
+        }
+    }
+
+    /// The mapping of the Discord message a Matrix edit of `edited_event_id`
+    /// should update, if that event was bridged from this room.
+    async fn discord_message_for_edit(
+        &self,
+        matrix_room_id: &str,
+        edited_event_id: &str,
+    ) -> Result<Option<MessageMapping>> {
+        let mapping = self
+            .db_manager
+            .message_store()
+            .get_by_matrix_event_id(edited_event_id)
+            .await?;
+        Ok(mapping.filter(|mapping| mapping.matrix_room_id == matrix_room_id))
+    }
+
+    /// Records where a Matrix message ended up on Discord. The message was
+    /// already sent, so a failure here is logged rather than retried.
+    async fn store_matrix_message_mappings(
+        &self,
+        matrix_room_id: &str,
+        matrix_event_id: &str,
+        discord_channel_id: &str,
+        discord_message_ids: Vec<String>,
+    ) {
+        let message_store = self.db_manager.message_store();
+        for discord_message_id in discord_message_ids {
+            if dry_run::is_placeholder(&discord_message_id) {
+                continue;
+            }
+            let now = Utc::now();
+            if let Err(err) = message_store
+                .upsert_message_mapping(&MessageMapping {
+                    id: 0,
+                    discord_message_id: discord_message_id.clone(),
+                    matrix_room_id: matrix_room_id.to_string(),
+                    matrix_event_id: matrix_event_id.to_string(),
+                    discord_channel_id: Some(discord_channel_id.to_string()),
+                    created_at: now,
+                    updated_at: now,
+                })
+                .await
+            {
+                warn!(
+                    "failed to map matrix event {} to discord message {}: {}",
+                    matrix_event_id, discord_message_id, err
+                );
+            }
+        }
+    }
+
+    /// Downloads the attachments that fit Discord's upload limit. Each comes
+    /// with a link to send instead when it is missing: the homeserver's
+    /// download URL for `mxc://` media. Nothing is downloaded when `policy`
+    /// only sends links. Downloads are named as in `names`, so audio and
+    /// video keep the extension Discord needs to play them inline.
+    async fn download_matrix_attachments(
         &self,
         urls: &[String],
+        names: &BTreeMap<String, String>,
+        policy: AttachmentPolicy,
     ) -> Vec<(String, Option<crate::media::MediaInfo>)> {
         let mut results = Vec::new();
         for url in urls {
-            if url.starts_with("mxc://") {
-                match self.media_handler.download_matrix_media(url).await {
-                    Ok(media) => {
-                        if media.size > 8 * 1024 * 1024 {
-                            warn!(
-                                "matrix attachment too large for discord: {} bytes, sending URL instead",
-                                media.size
-                            );
-                            results.push((url.clone(), None));
-                        } else {
-                            results.push((url.clone(), Some(media)));
-                        }
-                    }
-                    Err(e) => {
-                        warn!("failed to download matrix attachment {}: {}", url, e);
-                        results.push((url.clone(), None));
-                    }
-                }
-            } else {
+            let Ok(link) = self.media_handler.matrix_download_url(url) else {
                 results.push((url.clone(), None));
+                continue;
+            };
+            if !policy.reuploads() {
+                results.push((link, None));
+                continue;
+            }
+            match self
+                .media_handler
+                .download_matrix_media_for_discord(url)
+                .await
+            {
+                Ok(media) => {
+                    let media = match names.get(url) {
+                        Some(name) => media.with_filename(name),
+                        None => media,
+                    };
+                    results.push((link, Some(media)));
+                }
+                Err(e) => {
+                    warn!(
+                        "not uploading matrix attachment {} to discord, sending a link instead: {}",
+                        url, e
+                    );
+                    results.push((link, None));
+                }
             }
         }
         results
@@ -367,7 +2268,7 @@ impl BridgeCore {
     ) -> Result<()> {
         for (original_url, media_opt) in &attachments {
             if let Some(media) = media_opt {
-                if media.size > 8 * 1024 * 1024 {
+                if MediaHandler::exceeds_discord_limit(media.size) {
                     warn!(
                         "matrix attachment too large for discord: {} bytes, sending URL instead",
                         media.size
@@ -431,7 +2332,7 @@ impl BridgeCore {
                     .send_embed_as_user(discord_channel_id, embed, None, None)
                     .await?;
             }
-        } else if !outbound.content.is_empty() {
+        } else if !outbound.content.trim().is_empty() {
             self.discord_client
                 .send_message(discord_channel_id, &outbound.content)
                 .await?;
@@ -440,19 +2341,33 @@ impl BridgeCore {
         Ok(())
     }
 
+    /// Sends the attachments, then the text, and returns the ids of the
+    /// Discord messages that were sent or edited in that order.
     pub async fn send_to_discord_with_attachments(
         &self,
         discord_channel_id: &str,
+        matrix_room_id: &str,
         outbound: OutboundDiscordMessage,
         matrix_sender: &str,
         attachments: Vec<(String, Option<crate::media::MediaInfo>)>,
-    ) -> Result<()> {
-        let (username, avatar_url) = self
+    ) -> Result<Vec<String>> {
+        let (displayname, avatar_url) = self
             .matrix_client
             .get_user_profile(matrix_sender)
             .await
             .unwrap_or(None)
             .unwrap_or_else(|| (matrix_sender.to_string(), None));
+        let username = match self.webhook_nick(matrix_room_id, matrix_sender).await {
+            Ok(Some(nick)) => nick,
+            Ok(None) => displayname,
+            Err(err) => {
+                warn!(
+                    "failed to look up the webhook nick of {} in {}: {}",
+                    matrix_sender, matrix_room_id, err
+                );
+                displayname
+            }
+        };
 
         let avatar_for_discord = avatar_url.as_ref().map(|url| {
             if url.starts_with("mxc://") {
@@ -468,15 +2383,17 @@ impl BridgeCore {
             }
         });
 
+        let mut sent = Vec::new();
         for (original_url, media_opt) in &attachments {
             if let Some(media) = media_opt {
-                if media.size > 8 * 1024 * 1024 {
+                if MediaHandler::exceeds_discord_limit(media.size) {
                     warn!(
                         "matrix attachment too large for discord: {} bytes, sending URL instead",
                         media.size
                     );
                     let content = format!("{}: {}", media.filename, original_url);
-                    self.discord_client
+                    let message_id = self
+                        .discord_client
                         .send_message_with_metadata_as_user(
                             discord_channel_id,
                             &content,
@@ -487,6 +2404,7 @@ impl BridgeCore {
                             avatar_for_discord.as_deref(),
                         )
                         .await?;
+                    sent.push(message_id);
                 } else {
                     match self
                         .discord_client
@@ -500,12 +2418,28 @@ impl BridgeCore {
                         )
                         .await
                     {
-                        Ok(msg_id) => {
+                        Ok(message_id) => {
                             info!(
                                 "uploaded matrix attachment to discord channel={} file={} size={}",
                                 discord_channel_id, media.filename, media.size
                             );
-                            let _ = msg_id;
+                            sent.push(message_id);
+                            if outbound.attachment_policy.links() {
+                                let content = format!("{}: {}", media.filename, original_url);
+                                let message_id = self
+                                    .discord_client
+                                    .send_message_with_metadata_as_user(
+                                        discord_channel_id,
+                                        &content,
+                                        &[],
+                                        None,
+                                        None,
+                                        Some(&username),
+                                        avatar_for_discord.as_deref(),
+                                    )
+                                    .await?;
+                                sent.push(message_id);
+                            }
                         }
                         Err(e) => {
                             warn!(
@@ -513,7 +2447,8 @@ impl BridgeCore {
                                 e
                             );
                             let content = format!("{}: {}", media.filename, original_url);
-                            self.discord_client
+                            let message_id = self
+                                .discord_client
                                 .send_message_with_metadata_as_user(
                                     discord_channel_id,
                                     &content,
@@ -524,12 +2459,14 @@ impl BridgeCore {
                                     avatar_for_discord.as_deref(),
                                 )
                                 .await?;
+                            sent.push(message_id);
                         }
                     }
                 }
             } else {
                 let content = format!("Attachment: {}", original_url);
-                self.discord_client
+                let message_id = self
+                    .discord_client
                     .send_message_with_metadata_as_user(
                         discord_channel_id,
                         &content,
@@ -540,11 +2477,13 @@ impl BridgeCore {
                         avatar_for_discord.as_deref(),
                     )
                     .await?;
+                sent.push(message_id);
             }
         }
 
-        if !outbound.content.is_empty() {
-            self.discord_client
+        if !outbound.content.trim().is_empty() {
+            let message_id = self
+                .discord_client
                 .send_message_with_metadata_as_user(
                     discord_channel_id,
                     &outbound.content,
@@ -555,9 +2494,56 @@ impl BridgeCore {
                     avatar_for_discord.as_deref(),
                 )
                 .await?;
+            sent.push(message_id);
         }
 
-        Ok(())
+        Ok(sent)
+    }
+
+    /// The name set with `!discord nick` for the user in the room.
+    async fn webhook_nick(
+        &self,
+        matrix_room_id: &str,
+        matrix_user_id: &str,
+    ) -> Result<Option<String>> {
+        let settings = self
+            .db_manager
+            .user_store()
+            .get_user_room_settings(matrix_room_id, matrix_user_id)
+            .await?;
+        Ok(settings.and_then(|settings| settings.webhook_nick))
+    }
+
+    /// Appends an entry to the audit log. Failures are logged and otherwise
+    /// ignored so auditing never blocks the action itself.
+    pub async fn record_audit(
+        &self,
+        actor: &str,
+        source: AuditSource,
+        action: &str,
+        target: Option<&str>,
+        parameters: serde_json::Value,
+    ) {
+        let entry = AuditLogEntry {
+            id: 0,
+            actor: actor.to_string(),
+            source,
+            action: action.to_string(),
+            target: target.map(ToOwned::to_owned),
+            parameters,
+            created_at: Utc::now(),
+        };
+        if let Err(err) = self
+            .db_manager
+            .audit_store()
+            .record_audit_entry(&entry)
+            .await
+        {
+            warn!(
+                "failed to record audit entry action={} actor={}: {}",
+                action, actor, err
+            );
+        }
     }
 
     async fn handle_matrix_command_outcome(
@@ -584,12 +2570,241 @@ impl BridgeCore {
                         &channel_id,
                     )
                     .await?;
+                self.record_audit(
+                    &event.sender,
+                    AuditSource::MatrixCommand,
+                    "bridge",
+                    Some(&event.room_id),
+                    json!({ "guild_id": guild_id, "channel_id": channel_id, "result": reply }),
+                )
+                .await;
+                self.matrix_client
+                    .send_notice(&event.room_id, &reply)
+                    .await?;
+            }
+            MatrixCommandOutcome::AutoInviteStatus => {
+                let enabled = self.room_auto_invites_members(&event.room_id).await?;
+                self.matrix_client
+                    .send_notice(&event.room_id, &auto_invite_reply(enabled))
+                    .await?;
+            }
+            MatrixCommandOutcome::AutoInviteRequested { enabled } => {
+                self.set_room_auto_invite_members(&event.room_id, enabled)
+                    .await?;
+                self.record_audit(
+                    &event.sender,
+                    AuditSource::MatrixCommand,
+                    "set_auto_invite",
+                    Some(&event.room_id),
+                    json!({ "enabled": enabled }),
+                )
+                .await;
+                self.matrix_client
+                    .send_notice(&event.room_id, &auto_invite_reply(enabled))
+                    .await?;
+            }
+            MatrixCommandOutcome::BadgeStatus => {
+                let enabled = self.room_attribution_badge(&event.room_id).await?;
+                self.matrix_client
+                    .send_notice(&event.room_id, &badge_reply(enabled))
+                    .await?;
+            }
+            MatrixCommandOutcome::BadgeRequested { enabled } => {
+                let mut settings = self.room_settings(&event.room_id).await?;
+                settings.attribution_badge = enabled;
+                settings.updated_at = Utc::now();
+                self.db_manager
+                    .room_store()
+                    .set_room_settings(&settings)
+                    .await?;
+                self.record_audit(
+                    &event.sender,
+                    AuditSource::MatrixCommand,
+                    "set_attribution_badge",
+                    Some(&event.room_id),
+                    json!({ "enabled": enabled }),
+                )
+                .await;
+                self.matrix_client
+                    .send_notice(&event.room_id, &badge_reply(enabled))
+                    .await?;
+            }
+            MatrixCommandOutcome::AttachmentPolicyStatus => {
+                let mut reply = String::from("Attachments in this room:");
+                for direction in [
+                    DeliveryDirection::MatrixToDiscord,
+                    DeliveryDirection::DiscordToMatrix,
+                ] {
+                    let policy = self
+                        .room_attachment_policy(&event.room_id, direction)
+                        .await?;
+                    reply.push_str(&format!("\n{}: {}", direction.as_str(), policy.as_str()));
+                }
+                self.matrix_client
+                    .send_notice(&event.room_id, &reply)
+                    .await?;
+            }
+            MatrixCommandOutcome::AttachmentPolicyRequested { direction, policy } => {
+                let mut settings = self.room_settings(&event.room_id).await?;
+                match direction {
+                    DeliveryDirection::MatrixToDiscord => {
+                        settings.attachment_policy.matrix_to_discord = policy
+                    }
+                    DeliveryDirection::DiscordToMatrix => {
+                        settings.attachment_policy.discord_to_matrix = policy
+                    }
+                }
+                settings.updated_at = Utc::now();
+                self.db_manager
+                    .room_store()
+                    .set_room_settings(&settings)
+                    .await?;
+                self.record_audit(
+                    &event.sender,
+                    AuditSource::MatrixCommand,
+                    "set_attachment_policy",
+                    Some(&event.room_id),
+                    json!({
+                        "direction": direction.as_str(),
+                        "policy": policy.map(|policy| policy.as_str()),
+                    }),
+                )
+                .await;
+                let policy = self
+                    .room_attachment_policy(&event.room_id, direction)
+                    .await?;
+                self.matrix_client
+                    .send_notice(
+                        &event.room_id,
+                        &format!(
+                            "Attachments going {} now use {}.",
+                            direction.as_str(),
+                            policy.as_str()
+                        ),
+                    )
+                    .await?;
+            }
+            MatrixCommandOutcome::ConfirmStatus => {
+                let settings = self.room_settings(&event.room_id).await?;
+                self.matrix_client
+                    .send_notice(
+                        &event.room_id,
+                        &confirm_reply(settings.delivery_confirmations),
+                    )
+                    .await?;
+            }
+            MatrixCommandOutcome::ConfirmRequested { enabled } => {
+                let mut settings = self.room_settings(&event.room_id).await?;
+                settings.delivery_confirmations = enabled;
+                settings.updated_at = Utc::now();
+                self.db_manager
+                    .room_store()
+                    .set_room_settings(&settings)
+                    .await?;
+                self.record_audit(
+                    &event.sender,
+                    AuditSource::MatrixCommand,
+                    "set_delivery_confirmations",
+                    Some(&event.room_id),
+                    json!({ "enabled": enabled }),
+                )
+                .await;
+                self.matrix_client
+                    .send_notice(&event.room_id, &confirm_reply(enabled))
+                    .await?;
+            }
+            MatrixCommandOutcome::RoleKeywordsStatus => {
+                let settings = self.room_settings(&event.room_id).await?;
+                self.matrix_client
+                    .send_notice(
+                        &event.room_id,
+                        &role_keywords_reply(&settings.role_keywords),
+                    )
+                    .await?;
+            }
+            MatrixCommandOutcome::RoleKeywordRequested { role_id, keyword } => {
+                let mut settings = self.room_settings(&event.room_id).await?;
+                match &keyword {
+                    Some(keyword) => settings
+                        .role_keywords
+                        .insert(role_id.clone(), keyword.clone()),
+                    None => settings.role_keywords.remove(&role_id),
+                };
+                settings.updated_at = Utc::now();
+                self.db_manager
+                    .room_store()
+                    .set_room_settings(&settings)
+                    .await?;
+                self.record_audit(
+                    &event.sender,
+                    AuditSource::MatrixCommand,
+                    "set_role_keyword",
+                    Some(&event.room_id),
+                    json!({ "role_id": role_id, "keyword": keyword }),
+                )
+                .await;
+                self.matrix_client
+                    .send_notice(
+                        &event.room_id,
+                        &role_keywords_reply(&settings.role_keywords),
+                    )
+                    .await?;
+            }
+            MatrixCommandOutcome::NickStatus => {
+                let nick = self.webhook_nick(&event.room_id, &event.sender).await?;
+                self.matrix_client
+                    .send_notice(&event.room_id, &nick_reply(nick.as_deref()))
+                    .await?;
+            }
+            MatrixCommandOutcome::NickRequested { nick } => {
+                self.db_manager
+                    .user_store()
+                    .set_user_room_settings(&UserRoomSettings {
+                        matrix_room_id: event.room_id.clone(),
+                        matrix_user_id: event.sender.clone(),
+                        webhook_nick: nick.clone(),
+                        updated_at: Utc::now(),
+                    })
+                    .await?;
+                self.matrix_client
+                    .send_notice(&event.room_id, &nick_reply(nick.as_deref()))
+                    .await?;
+            }
+            MatrixCommandOutcome::StatusRequested => {
+                let reply = match self.get_room_mapping_cached(&event.room_id).await? {
+                    Some(mapping) => self.room_status(&mapping).await,
+                    None => "This room is not bridged.".to_string(),
+                };
+                self.matrix_client
+                    .send_notice(&event.room_id, &reply)
+                    .await?;
+            }
+            MatrixCommandOutcome::SyncBansRequested { dry_run } => {
+                let reply = self
+                    .sync_discord_bans_to_matrix(&event.room_id, &event.sender, dry_run)
+                    .await?;
+                self.record_audit(
+                    &event.sender,
+                    AuditSource::MatrixCommand,
+                    "sync_bans",
+                    Some(&event.room_id),
+                    json!({ "dry_run": dry_run, "result": reply }),
+                )
+                .await;
                 self.matrix_client
                     .send_notice(&event.room_id, &reply)
                     .await?;
             }
             MatrixCommandOutcome::UnbridgeRequested => {
                 let reply = self.unbridge_matrix_room(&event.room_id).await?;
+                self.record_audit(
+                    &event.sender,
+                    AuditSource::MatrixCommand,
+                    "unbridge",
+                    Some(&event.room_id),
+                    json!({ "result": reply }),
+                )
+                .await;
                 self.matrix_client
                     .send_notice(&event.room_id, &reply)
                     .await?;
@@ -599,6 +2814,13 @@ impl BridgeCore {
     }
 
     pub async fn handle_matrix_member(&self, event: &MatrixEvent) -> Result<()> {
+        if self.defer_until_discord_ready(event).await {
+            return Ok(());
+        }
+        self.process_matrix_member(event).await
+    }
+
+    async fn process_matrix_member(&self, event: &MatrixEvent) -> Result<()> {
         if let Some(content) = event.content.as_ref().and_then(|c| c.as_object())
             && let Some(membership) = content.get("membership").and_then(|v| v.as_str())
         {
@@ -661,6 +2883,22 @@ impl BridgeCore {
                     .and_then(|v| v.as_str())
                     .unwrap_or("No reason provided");
 
+                let modlog_action = if membership == "ban" {
+                    ModlogAction::Banned
+                } else {
+                    ModlogAction::Kicked
+                };
+                self.post_modlog(
+                    ModlogEntry::new(modlog_action, &mapping.discord_guild_id)
+                        .with("source", Some("matrix"))
+                        .with("target", Some(discord_user_id.as_str()))
+                        .with("matrix_user", Some(state_key.as_str()))
+                        .with("actor", Some(event.sender.as_str()))
+                        .with("room", Some(event.room_id.as_str()))
+                        .with("reason", Some(reason)),
+                )
+                .await;
+
                 if let Err(err) = self
                     .discord_client
                     .deny_channel_member_permissions(&mapping.discord_channel_id, &discord_user_id)
@@ -670,6 +2908,16 @@ impl BridgeCore {
                         "failed to apply discord deny overwrite for user={} channel={} room={} membership={}: {}",
                         discord_user_id, mapping.discord_channel_id, event.room_id, membership, err
                     );
+                    self.post_modlog(
+                        ModlogEntry::new(ModlogAction::BridgeError, &mapping.discord_guild_id)
+                            .with("target", Some(discord_user_id.as_str()))
+                            .with("channel", Some(mapping.discord_channel_id.as_str()))
+                            .with(
+                                "error",
+                                Some(format!("failed to restrict on Discord: {err}")),
+                            ),
+                    )
+                    .await;
                 }
 
                 let action_word = if membership == "ban" {
@@ -765,11 +3013,16 @@ impl BridgeCore {
 
         self.room_cache.remove(&event.room_id).await;
 
+        self.receipts.forget_room(&event.room_id);
+
         info!("removed room mapping for encrypted room {}", event.room_id);
         Ok(())
     }
 
     pub async fn handle_matrix_room_name(&self, event: &MatrixEvent) -> Result<()> {
+        if self.hold_matrix_event(event).await {
+            return Ok(());
+        }
         if self
             .matrix_client
             .config()
@@ -816,6 +3069,9 @@ impl BridgeCore {
     }
 
     pub async fn handle_matrix_room_topic(&self, event: &MatrixEvent) -> Result<()> {
+        if self.hold_matrix_event(event).await {
+            return Ok(());
+        }
         if self
             .matrix_client
             .config()
@@ -865,7 +3121,209 @@ impl BridgeCore {
         Ok(())
     }
 
+    /// Mirrors pins added or removed on Matrix to the Discord messages they
+    /// were bridged from or to. Pins the bot is not allowed to change are
+    /// skipped.
+    pub async fn handle_matrix_pinned_events(&self, event: &MatrixEvent) -> Result<()> {
+        if self.hold_matrix_event(event).await {
+            return Ok(());
+        }
+        if self.matrix_client.is_namespaced_user(&event.sender) {
+            return Ok(());
+        }
+        let Some(mapping) = self.get_room_mapping_cached(&event.room_id).await? else {
+            return Ok(());
+        };
+
+        let changes = diff_pinned_events(event.prev_content.as_ref(), event.content.as_ref());
+        let updates = changes
+            .pinned
+            .iter()
+            .map(|event_id| (event_id, true))
+            .chain(changes.unpinned.iter().map(|event_id| (event_id, false)));
+        for (matrix_event_id, pinned) in updates {
+            let Some(link) = self
+                .db_manager
+                .message_store()
+                .get_by_matrix_event_id(matrix_event_id)
+                .await?
+            else {
+                debug!(
+                    "matrix pin ignored room_id={} event_id={} reason=no_discord_message",
+                    event.room_id, matrix_event_id
+                );
+                continue;
+            };
+            let discord_channel_id = link.discord_channel(&mapping.discord_channel_id);
+            let applied = self
+                .discord_client
+                .set_message_pinned(discord_channel_id, &link.discord_message_id, pinned)
+                .await?;
+            if !applied {
+                warn!(
+                    "cannot change pins in discord channel {}: bot lacks MANAGE_MESSAGES",
+                    discord_channel_id
+                );
+                break;
+            }
+            debug!(
+                "matrix pin forwarded discord_channel={} message={} pinned={}",
+                discord_channel_id, link.discord_message_id, pinned
+            );
+        }
+        Ok(())
+    }
+
+    /// Mirrors a Matrix reaction onto the Discord message it annotates; the
+    /// bot reacts, as Discord has no way to react for someone else. Custom
+    /// emoji are sent by their mxc URL and only work for emoji the bridge
+    /// knows from Discord.
+    pub async fn handle_matrix_reaction(&self, event: &MatrixEvent) -> Result<()> {
+        if self.hold_matrix_event(event).await {
+            return Ok(());
+        }
+        // The bot's own reactions are delivery confirmations.
+        if self.matrix_client.is_namespaced_user(&event.sender)
+            || event.sender == self.matrix_client.bot_user_id()
+        {
+            return Ok(());
+        }
+        let Some(reaction) = parse_matrix_reaction(event.content.as_ref()) else {
+            return Ok(());
+        };
+        let Some(mapping) = self.get_room_mapping_cached(&event.room_id).await? else {
+            return Ok(());
+        };
+        let Some(link) = self
+            .db_manager
+            .message_store()
+            .get_by_matrix_event_id(&reaction.event_id)
+            .await?
+        else {
+            debug!(
+                "matrix reaction ignored room_id={} event_id={} reason=no_discord_message",
+                event.room_id, reaction.event_id
+            );
+            return Ok(());
+        };
+
+        let emoji = if reaction.key.starts_with("mxc://") {
+            let Some(custom) = self
+                .db_manager
+                .emoji_store()
+                .get_emoji_by_mxc(&reaction.key)
+                .await?
+            else {
+                debug!(
+                    "matrix reaction ignored room_id={} key={} reason=unknown_custom_emoji",
+                    event.room_id, reaction.key
+                );
+                return Ok(());
+            };
+            format!(
+                "<{}:{}:{}>",
+                if custom.animated { "a" } else { "" },
+                custom.emoji_name,
+                custom.discord_emoji_id
+            )
+        } else {
+            reaction.key
+        };
+        let discord_channel_id = link.discord_channel(&mapping.discord_channel_id);
+        self.discord_client
+            .add_reaction(discord_channel_id, &link.discord_message_id, &emoji)
+            .await?;
+        debug!(
+            "matrix reaction forwarded discord_channel={} message={}",
+            discord_channel_id, link.discord_message_id
+        );
+        Ok(())
+    }
+
+    /// Records where Matrix users read up to in a bridged room, unless
+    /// `bridge.disable_read_receipts` is set.
+    pub async fn handle_matrix_receipt(&self, event: &MatrixEvent) -> Result<()> {
+        if self.matrix_client.config().bridge.disable_read_receipts {
+            return Ok(());
+        }
+        let Some(content) = &event.content else {
+            return Ok(());
+        };
+        if self
+            .get_room_mapping_cached(&event.room_id)
+            .await?
+            .is_none()
+        {
+            return Ok(());
+        }
+        for receipt in parse_receipts(content) {
+            if self.matrix_client.is_namespaced_user(&receipt.user_id) {
+                continue;
+            }
+            self.receipts.record(&event.room_id, receipt);
+        }
+        Ok(())
+    }
+
+    /// Read markers of Matrix users in a bridged room, keyed by user id.
+    pub fn read_markers(&self, matrix_room_id: &str) -> BTreeMap<String, ReadMarker> {
+        self.receipts.markers(matrix_room_id)
+    }
+
+    /// Deletes the Discord messages a redacted Matrix event was bridged to,
+    /// unless `bridge.disable_deletion_forwarding` is set.
+    pub async fn handle_matrix_redaction(&self, event: &MatrixEvent) -> Result<()> {
+        if self.hold_matrix_event(event).await {
+            return Ok(());
+        }
+        if self.matrix_client.is_namespaced_user(&event.sender) {
+            return Ok(());
+        }
+        if self
+            .matrix_client
+            .config()
+            .bridge
+            .disable_deletion_forwarding
+        {
+            debug!(
+                "matrix redaction ignored room_id={} reason=deletion_forwarding_disabled",
+                event.room_id
+            );
+            return Ok(());
+        }
+        let Some(redacted_event_id) = parse_matrix_redaction(event.content.as_ref()) else {
+            return Ok(());
+        };
+        let Some(mapping) = self.get_room_mapping_cached(&event.room_id).await? else {
+            return Ok(());
+        };
+
+        // Attachments and text may have gone out as separate messages.
+        let message_store = self.db_manager.message_store();
+        while let Some(link) = message_store
+            .get_by_matrix_event_id(&redacted_event_id)
+            .await?
+            .filter(|link| link.matrix_room_id == event.room_id)
+        {
+            let discord_channel_id = link.discord_channel(&mapping.discord_channel_id);
+            self.discord_client
+                .delete_message(discord_channel_id, &link.discord_message_id)
+                .await?;
+            message_store
+                .delete_by_discord_message_id(&link.discord_message_id)
+                .await?;
+            debug!(
+                "matrix redaction forwarded discord_channel={} message={}",
+                discord_channel_id, link.discord_message_id
+            );
+        }
+        Ok(())
+    }
+
     pub async fn handle_matrix_power_levels(&self, event: &MatrixEvent) -> Result<()> {
+        if self.hold_matrix_event(event).await {
+            return Ok(());
+        }
         let room_mapping = self.get_room_mapping_cached(&event.room_id).await?;
 
         let Some(mapping) = room_mapping else {
@@ -966,12 +3424,23 @@ impl BridgeCore {
 
         match self
             .provisioning
-            .ask_bridge_permission(self.discord_client.as_ref(), &channel.id, matrix_requestor)
+            .ask_bridge_permission(
+                self.discord_client.as_ref(),
+                &channel,
+                matrix_requestor,
+                matrix_room_id,
+            )
             .await
         {
             Ok(()) => {
-                self.bridge_matrix_room(matrix_room_id, guild_id, channel_id)
-                    .await
+                self.bridge_matrix_room(
+                    matrix_room_id,
+                    guild_id,
+                    channel_id,
+                    RoomOrigin::SelfService,
+                    matrix_requestor,
+                )
+                .await
             }
             Err(ProvisioningError::TimedOut) => {
                 Ok("Timed out waiting for a response from the Discord owners.".to_string())
@@ -992,11 +3461,15 @@ impl BridgeCore {
         }
     }
 
+    /// Bridges an existing Matrix room to a Discord channel, recording
+    /// `origin` and `created_by` on the new mapping.
     pub async fn bridge_matrix_room(
         &self,
         matrix_room_id: &str,
         guild_id: &str,
         channel_id: &str,
+        origin: RoomOrigin,
+        created_by: &str,
     ) -> Result<String> {
         if let Some(limit_message) = self.check_room_limit().await? {
             return Ok(limit_message);
@@ -1024,6 +3497,9 @@ impl BridgeCore {
             discord_channel_id: channel.id.clone(),
             discord_channel_name: channel.name.clone(),
             discord_guild_id: guild_id.to_string(),
+            created_by: Some(created_by.to_string()),
+            origin: Some(origin),
+            last_active_at: None,
             created_at: Utc::now(),
             updated_at: Utc::now(),
         };
@@ -1031,12 +3507,22 @@ impl BridgeCore {
             .room_store()
             .create_room_mapping(&mapping)
             .await?;
+        if self.room_auto_invites_members(matrix_room_id).await? {
+            self.queue_member_sync(matrix_room_id).await?;
+        }
 
+        let guild_name = self
+            .discord_client
+            .metadata()
+            .guild(&channel.guild_id)
+            .await
+            .map(|guild| guild.name)
+            .unwrap_or_else(|| channel.guild_id.clone());
         let name_pattern = &self.matrix_client.config().channel.name_pattern;
         let formatted_name = crate::utils::formatting::apply_pattern_string(
             name_pattern,
             &[
-                ("guild", &channel.guild_id.clone()),
+                ("guild", &guild_name),
                 ("name", &format!("#{}", mapping.discord_channel_name)),
             ],
         );
@@ -1050,6 +3536,7 @@ impl BridgeCore {
             .client
             .send_state_event(matrix_room_id, "m.room.name", "", &event_content)
             .await;
+        self.publish_bridge_info(&mapping, Some(&guild_name)).await;
 
         Ok("I have bridged this room to your channel".to_string())
     }
@@ -1108,16 +3595,9 @@ impl BridgeCore {
         }
 
         if delete_options.unset_room_alias {
-            let alias_localpart = format!(
-                "{}{}",
-                self.matrix_client.config().room.room_alias_prefix,
-                mapping.discord_channel_id
-            );
-            let alias = format!(
-                "#{}:{}",
-                alias_localpart,
-                self.matrix_client.config().bridge.domain
-            );
+            let alias = self
+                .matrix_client
+                .portal_room_alias(&mapping.discord_channel_id);
             let _ = client.delete_room_alias(&alias).await;
         }
 
@@ -1128,9 +3608,84 @@ impl BridgeCore {
 
         self.room_cache.remove(&mapping.matrix_room_id).await;
 
+        self.receipts.forget_room(&mapping.matrix_room_id);
+        self.spawn_ghost_cleanup(&mapping.matrix_room_id);
+
         Ok("This room has been unbridged".to_string())
     }
 
+    /// Applies `ghosts_leave` (and `deactivate_ghosts`) to a room that was
+    /// just unbridged, in the background since a room can hold many ghosts.
+    fn spawn_ghost_cleanup(&self, matrix_room_id: &str) {
+        let config = self.matrix_client.config();
+        if !config.channel.delete_options.ghosts_leave {
+            return;
+        }
+        let admin = HomeserverAdminClient::for_ghost_deactivation(&config);
+        let bridge = self.clone();
+        let room_id = matrix_room_id.to_string();
+        tokio::spawn(async move {
+            if let Err(err) = bridge.remove_ghosts_from_room(&room_id, admin).await {
+                warn!(
+                    "failed to remove ghosts from unbridged room {}: {}",
+                    room_id, err
+                );
+            }
+        });
+    }
+
+    async fn remove_ghosts_from_room(
+        &self,
+        matrix_room_id: &str,
+        admin: Option<HomeserverAdminClient>,
+    ) -> Result<()> {
+        let bot_user_id = self.matrix_client.bot_user_id();
+        let ghosts: Vec<String> = self
+            .matrix_client
+            .get_room_members(matrix_room_id)
+            .await?
+            .into_iter()
+            .filter(|user_id| {
+                *user_id != bot_user_id && self.matrix_client.is_namespaced_user(user_id)
+            })
+            .collect();
+
+        let mut deactivated = 0;
+        for ghost in &ghosts {
+            if let Err(err) = self
+                .matrix_client
+                .leave_room_as(ghost, matrix_room_id)
+                .await
+            {
+                warn!(
+                    "ghost {} failed to leave unbridged room {}: {}",
+                    ghost, matrix_room_id, err
+                );
+                continue;
+            }
+            let Some(admin) = &admin else {
+                continue;
+            };
+            // Ghosts still in other rooms are kept; they are live elsewhere.
+            match self.matrix_client.get_joined_rooms_as(ghost).await {
+                Ok(rooms) if rooms.is_empty() => match admin.deactivate_user(ghost).await {
+                    Ok(()) => deactivated += 1,
+                    Err(err) => warn!("{}", err),
+                },
+                Ok(_) => {}
+                Err(err) => warn!("failed to list rooms of ghost {}: {}", ghost, err),
+            }
+        }
+
+        info!(
+            "removed {} ghosts from unbridged room {} and deactivated {}",
+            ghosts.len(),
+            matrix_room_id,
+            deactivated
+        );
+        Ok(())
+    }
+
     pub async fn send_to_discord_message(
         &self,
         discord_channel_id: &str,
@@ -1248,9 +3803,11 @@ impl BridgeCore {
                 matrix_room_id,
                 discord_sender,
                 &body,
-                &outbound.attachments,
-                outbound.reply_to.as_deref(),
+                outbound.formatted_body.as_deref(),
+                outbound.attribution_badge,
+                outbound.relates_to(),
                 outbound.edit_of.as_deref(),
+                outbound.origin_server_ts,
             )
             .await?;
         debug!(
@@ -1271,9 +3828,27 @@ impl BridgeCore {
         let mut last_event_id: Option<String> = None;
 
         for attachment_url in &outbound.attachments {
+            if !outbound.attachment_policy.reuploads() {
+                let body = format!("Attachment: {}", attachment_url);
+                last_event_id = Some(
+                    self.matrix_client
+                        .send_message_with_metadata(
+                            matrix_room_id,
+                            discord_sender,
+                            &body,
+                            None,
+                            false,
+                            outbound.relates_to(),
+                            None,
+                            outbound.origin_server_ts,
+                        )
+                        .await?,
+                );
+                continue;
+            }
             match self.media_handler.download_from_url(attachment_url).await {
                 Ok(media) => {
-                    if media.size > 50 * 1024 * 1024 {
+                    if MediaHandler::exceeds_matrix_limit(media.size) {
                         warn!(
                             "attachment too large for Matrix: {} bytes, sending as URL instead",
                             media.size
@@ -1285,9 +3860,11 @@ impl BridgeCore {
                                     matrix_room_id,
                                     discord_sender,
                                     &body,
-                                    &[],
-                                    outbound.reply_to.as_deref(),
                                     None,
+                                    false,
+                                    outbound.relates_to(),
+                                    None,
+                                    outbound.origin_server_ts,
                                 )
                                 .await?,
                         );
@@ -1301,10 +3878,14 @@ impl BridgeCore {
 
                         match self.matrix_client.upload_media(&media).await {
                             Ok(mxc_url) => {
-                                let info = json!({
-                                    "mimetype": media.content_type,
-                                    "size": media.size,
-                                });
+                                let mut info = media.matrix_info();
+                                if msgtype == "m.video"
+                                    && let Some((thumbnail_url, thumbnail_info)) =
+                                        self.rehost_video_thumbnail(attachment_url).await
+                                {
+                                    info["thumbnail_url"] = thumbnail_url.into();
+                                    info["thumbnail_info"] = thumbnail_info;
+                                }
 
                                 last_event_id = Some(
                                     self.matrix_client
@@ -1315,7 +3896,8 @@ impl BridgeCore {
                                             &media.filename,
                                             &mxc_url,
                                             Some(&info),
-                                            outbound.reply_to.as_deref(),
+                                            outbound.relates_to(),
+                                            outbound.origin_server_ts,
                                         )
                                         .await?,
                                 );
@@ -1323,6 +3905,26 @@ impl BridgeCore {
                                     "uploaded discord attachment to matrix room={} file={} size={} mxc={}",
                                     matrix_room_id, media.filename, media.size, mxc_url
                                 );
+                                if outbound.attachment_policy.links() {
+                                    let body = format!("{}: {}", media.filename, attachment_url);
+                                    last_event_id = Some(
+                                        self.matrix_client
+                                            .send_message_with_metadata(
+                                                matrix_room_id,
+                                                discord_sender,
+                                                &body,
+                                                None,
+                                                false,
+                                                RelatesTo {
+                                                    reply_to: None,
+                                                    thread_root: outbound.thread_root.as_deref(),
+                                                },
+                                                None,
+                                                outbound.origin_server_ts,
+                                            )
+                                            .await?,
+                                    );
+                                }
                             }
                             Err(e) => {
                                 warn!("failed to upload attachment to matrix: {}, sending URL", e);
@@ -1333,9 +3935,11 @@ impl BridgeCore {
                                             matrix_room_id,
                                             discord_sender,
                                             &body,
-                                            &[],
-                                            outbound.reply_to.as_deref(),
                                             None,
+                                            false,
+                                            outbound.relates_to(),
+                                            None,
+                                            outbound.origin_server_ts,
                                         )
                                         .await?,
                                 );
@@ -1355,37 +3959,233 @@ impl BridgeCore {
                                 matrix_room_id,
                                 discord_sender,
                                 &body,
-                                &[],
-                                outbound.reply_to.as_deref(),
                                 None,
+                                false,
+                                outbound.relates_to(),
+                                None,
+                                outbound.origin_server_ts,
                             )
                             .await?,
                     );
                 }
             }
         }
-
-        if !outbound.body.is_empty() {
-            last_event_id = Some(
-                self.matrix_client
-                    .send_message_with_metadata(
-                        matrix_room_id,
-                        discord_sender,
-                        &outbound.body,
-                        &[],
-                        outbound.reply_to.as_deref(),
-                        outbound.edit_of.as_deref(),
-                    )
-                    .await?,
+
+        if !outbound.body.trim().is_empty() {
+            last_event_id = Some(
+                self.matrix_client
+                    .send_message_with_metadata(
+                        matrix_room_id,
+                        discord_sender,
+                        &outbound.body,
+                        outbound.formatted_body.as_deref(),
+                        outbound.attribution_badge,
+                        outbound.relates_to(),
+                        outbound.edit_of.as_deref(),
+                        outbound.origin_server_ts,
+                    )
+                    .await?,
+            );
+        }
+
+        last_event_id.ok_or_else(|| anyhow::anyhow!("no message was sent"))
+    }
+
+    /// Uploads a still of a Discord video for Matrix clients to show before
+    /// playing it. Videos go without one when that fails.
+    async fn rehost_video_thumbnail(&self, video_url: &str) -> Option<(String, Value)> {
+        let thumbnail_url = crate::media::discord_video_thumbnail_url(video_url)?;
+        let result = async {
+            let thumbnail = self.media_handler.download_from_url(&thumbnail_url).await?;
+            let mxc_url = self.matrix_client.upload_media(&thumbnail).await?;
+            anyhow::Ok((mxc_url, thumbnail.matrix_info()))
+        }
+        .await;
+        match result {
+            Ok(thumbnail) => Some(thumbnail),
+            Err(err) => {
+                debug!("no thumbnail for discord video {}: {}", video_url, err);
+                None
+            }
+        }
+    }
+
+    pub async fn handle_discord_message_with_context(
+        &self,
+        ctx: DiscordMessageContext,
+    ) -> Result<()> {
+        if self
+            .hold_for_maintenance(DeliveryDirection::DiscordToMatrix, &ctx)
+            .await
+        {
+            return Ok(());
+        }
+        let payload = self.retry_payload(DeliveryDirection::DiscordToMatrix, &ctx);
+        if self
+            .queue_behind_backlog(DeliveryDirection::DiscordToMatrix, &payload)
+            .await
+        {
+            return Ok(());
+        }
+        let result = self.process_discord_message(ctx, None).await;
+        self.settle_delivery(DeliveryDirection::DiscordToMatrix, payload, result)
+            .await
+    }
+
+    async fn register_discord_ghost(&self, discord_user_id: &str) -> Result<()> {
+        // A failed lookup fails the message, which then goes to the retry
+        // queue; only a user Discord says is gone gets a bare ghost.
+        let discord_user = self
+            .discord_client
+            .get_user(discord_user_id)
+            .await
+            .with_context(|| format!("failed to look up discord user {discord_user_id}"))?;
+        if let Some(discord_user) = discord_user {
+            let vars = [
+                ("id", discord_user.id.as_str()),
+                ("tag", discord_user.discriminator.as_str()),
+                ("username", discord_user.username.as_str()),
+            ];
+            let display_name = crate::utils::formatting::apply_pattern_string(
+                &self.matrix_client.config().ghosts.username_pattern,
+                &vars,
+            );
+            self.matrix_client
+                .ensure_ghost_user_registered(discord_user_id, Some(&display_name))
+                .await?;
+        } else {
+            self.matrix_client
+                .ensure_ghost_user_registered(discord_user_id, None)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Announces a message from an unbridged channel that replies to or
+    /// forwards one from a bridged channel, with a notice in its room. Only
+    /// public channels of the same guild are announced, and never for
+    /// senders whose ghost is banned from the room.
+    async fn bridge_cross_channel_context(
+        &self,
+        ctx: &DiscordMessageContext,
+        reference: &DiscordReference,
+    ) -> Result<()> {
+        let Some(mapping) = self
+            .db_manager
+            .room_store()
+            .get_room_by_discord_channel(&reference.channel_id)
+            .await?
+        else {
+            return Ok(());
+        };
+        let Some(source) = self.discord_client.get_channel(&ctx.channel_id).await? else {
+            return Ok(());
+        };
+        let parent_hidden = match &source.thread_parent_id {
+            Some(parent_id) => !self
+                .discord_client
+                .get_channel(parent_id)
+                .await?
+                .is_some_and(|parent| parent.everyone_can_view),
+            None => false,
+        };
+        if source.guild_id != mapping.discord_guild_id || !source.everyone_can_view || parent_hidden
+        {
+            debug!(
+                "cross-channel context from {} dropped reason=not_public_in_guild",
+                ctx.channel_id
+            );
+            return Ok(());
+        }
+        if self.blocker().check_and_update().await? != BridgeState::Active {
+            debug!(
+                "cross-channel context from {} dropped reason=bridge_blocked",
+                ctx.channel_id
+            );
+            return Ok(());
+        }
+        let ghost = self.matrix_client.ghost_user_id(&ctx.sender_id);
+        if self
+            .matrix_client
+            .get_banned_users(&mapping.matrix_room_id)
+            .await?
+            .contains(&ghost)
+        {
+            debug!(
+                "cross-channel context from {} dropped reason=sender_banned sender={}",
+                ctx.channel_id, ghost
             );
+            return Ok(());
         }
 
-        last_event_id.ok_or_else(|| anyhow::anyhow!("no message was sent"))
+        let sender = match &ctx.relayed_name {
+            Some(name) => name.clone(),
+            None => match self
+                .discord_client
+                .metadata()
+                .display_name(&mapping.discord_guild_id, &ctx.sender_id)
+                .await
+            {
+                Some(name) => name,
+                None => self
+                    .discord_client
+                    .get_user(&ctx.sender_id)
+                    .await
+                    .ok()
+                    .flatten()
+                    .map_or_else(|| ctx.sender_id.clone(), |user| user.username),
+            },
+        };
+        let in_reply_to = match &reference.message_id {
+            Some(message_id) => self
+                .db_manager
+                .message_store()
+                .get_by_discord_message_id(message_id)
+                .await?
+                .filter(|original| original.matrix_room_id == mapping.matrix_room_id)
+                .map(|original| original.matrix_event_id),
+            None => None,
+        };
+        // The quote is the referenced message, which was posted in the
+        // bridged channel, not the text of the unbridged one.
+        let quoted = self
+            .message_flow
+            .discord_to_matrix_resolved(&DiscordInboundMessage {
+                channel_id: reference.channel_id.clone(),
+                sender_id: ctx.sender_id.clone(),
+                content: reference.content.clone(),
+                attachments: Vec::new(),
+                reply_to: None,
+                edit_of: None,
+                sender_can_mention_everyone: false,
+            })
+            .await;
+
+        debug!(
+            "posting context from unbridged channel {} to {}",
+            ctx.channel_id, mapping.matrix_room_id
+        );
+        let content = context_notice(
+            &sender,
+            &source.name,
+            Some((
+                &quoted.body,
+                quoted.formatted_body.as_deref().unwrap_or(&quoted.body),
+            )),
+            reference.forwarded,
+            in_reply_to.as_deref(),
+        );
+        self.matrix_client
+            .send_bot_message_content(&mapping.matrix_room_id, &content)
+            .await
     }
 
-    pub async fn handle_discord_message_with_context(
+    /// `origin_server_ts` backdates the Matrix events when the message is
+    /// replayed from the retry queue.
+    async fn process_discord_message(
         &self,
         ctx: DiscordMessageContext,
+        origin_server_ts: Option<i64>,
     ) -> Result<()> {
         debug!(
             "discord inbound message channel_id={} sender={} reply_to={:?} edit_of={:?} attachments={} content_len={} content_preview={}",
@@ -1427,41 +4227,77 @@ impl BridgeCore {
             return Ok(());
         }
 
+        let room_mapping = match room_mapping {
+            Some(mapping) => Some(mapping),
+            None => self.thread_parent_mapping(&ctx).await?,
+        };
         let Some(mapping) = room_mapping else {
+            if self.matrix_client.config().bridge.cross_channel_context
+                && ctx.edit_of.is_none()
+                && let Some(reference) = &ctx.reference
+            {
+                return self.bridge_cross_channel_context(&ctx, reference).await;
+            }
             debug!(
                 "discord inbound dropped channel_id={} reason=no_matrix_mapping",
                 ctx.channel_id
             );
             return Ok(());
         };
+        let thread_id =
+            (ctx.channel_id != mapping.discord_channel_id).then(|| ctx.channel_id.clone());
 
-        if let Some(discord_user) = self.discord_client.get_user(&ctx.sender_id).await? {
-            let vars = [
-                ("id", discord_user.id.as_str()),
-                ("tag", discord_user.discriminator.as_str()),
-                ("username", discord_user.username.as_str()),
-            ];
-            let display_name = crate::utils::formatting::apply_pattern_string(
-                &self.matrix_client.config().ghosts.username_pattern,
-                &vars,
+        if ctx.edit_of.is_none()
+            && let Some(source_message_id) = &ctx.source_message_id
+            && self
+                .db_manager
+                .message_store()
+                .get_by_discord_message_id(source_message_id)
+                .await?
+                .is_some()
+        {
+            // Already bridged, or an edit of it arrived first and was sent
+            // in its place.
+            debug!(
+                "discord inbound dropped channel_id={} message_id={} reason=already_bridged",
+                ctx.channel_id, source_message_id
+            );
+            return Ok(());
+        }
+
+        for sticker in &ctx.stickers {
+            self.emoji_handler
+                .record_usage(EmojiUsageKind::Sticker, &sticker.id, &sticker.name);
+        }
+
+        if self.matrix_client.config().bridge.member_change_notices {
+            self.member_notices.record_message(
+                &mapping.matrix_room_id,
+                &ctx.sender_id,
+                std::time::Instant::now(),
             );
+        }
+
+        if let Some(name) = &ctx.relayed_name {
             self.matrix_client
-                .ensure_ghost_user_registered(&ctx.sender_id, Some(&display_name))
+                .ensure_ghost_user_registered(&ctx.sender_id, Some(name))
                 .await?;
         } else {
-            self.matrix_client
-                .ensure_ghost_user_registered(&ctx.sender_id, None)
-                .await?;
+            self.register_discord_ghost(&ctx.sender_id).await?;
         }
 
-        let mut outbound = self.message_flow.discord_to_matrix(&DiscordInboundMessage {
-            channel_id: ctx.channel_id,
-            sender_id: ctx.sender_id.clone(),
-            content: ctx.content,
-            attachments: ctx.attachments,
-            reply_to: ctx.reply_to,
-            edit_of: ctx.edit_of,
-        });
+        let mut outbound = self
+            .message_flow
+            .discord_to_matrix_resolved(&DiscordInboundMessage {
+                channel_id: ctx.channel_id,
+                sender_id: ctx.sender_id.clone(),
+                content: ctx.content,
+                attachments: ctx.attachments,
+                reply_to: ctx.reply_to,
+                edit_of: ctx.edit_of,
+                sender_can_mention_everyone: ctx.permissions.contains("MENTION_EVERYONE"),
+            })
+            .await;
 
         let reply_mapping = if let Some(reply_discord_message_id) = outbound.reply_to.clone() {
             self.db_manager
@@ -1486,6 +4322,19 @@ impl BridgeCore {
             reply_mapping.as_ref(),
             edit_mapping.as_ref(),
         );
+        if let Some(thread_id) = &thread_id {
+            outbound.thread_root = self.matrix_thread_root(thread_id, &mapping).await?;
+        }
+        outbound.origin_server_ts = origin_server_ts;
+        if let Some(attributed_to) = &ctx.attributed_to
+            && !outbound.body.trim().is_empty()
+        {
+            outbound.prefix_body(&format!("{attributed_to}:\n"));
+        }
+        outbound.attribution_badge = self.room_attribution_badge(&mapping.matrix_room_id).await?;
+        outbound.attachment_policy = self
+            .room_attachment_policy(&mapping.matrix_room_id, DeliveryDirection::DiscordToMatrix)
+            .await?;
         debug!(
             "discord->matrix outbound prepared channel_id={} matrix_room={} sender={} reply_to={:?} edit_of={:?} attachments={} body_len={} body_preview={}",
             mapping.discord_channel_id,
@@ -1498,6 +4347,23 @@ impl BridgeCore {
             preview_text(&outbound.body)
         );
 
+        if outbound.body.trim().is_empty() && outbound.attachments.is_empty() {
+            debug!(
+                "discord inbound dropped channel_id={} sender={} reason=empty",
+                mapping.discord_channel_id, ctx.sender_id
+            );
+            return Ok(());
+        }
+
+        let is_replacement = outbound.edit_of.is_some();
+        // The first message of a thread with no root yet becomes its root.
+        let starts_thread =
+            thread_id.is_some() && outbound.thread_root.is_none() && !is_replacement;
+        let locations = if self.matrix_client.config().bridge.geo_uri_locations && !is_replacement {
+            find_geo_uris(&outbound.body)
+        } else {
+            Vec::new()
+        };
         let matrix_event_id = if !outbound.attachments.is_empty() {
             self.send_to_matrix_with_attachments(&mapping.matrix_room_id, &ctx.sender_id, &outbound)
                 .await?
@@ -1505,8 +4371,48 @@ impl BridgeCore {
             self.send_to_matrix_message(&mapping.matrix_room_id, &ctx.sender_id, outbound)
                 .await?
         };
+        for location in locations {
+            if let Err(err) = self
+                .matrix_client
+                .send_location_message(
+                    &mapping.matrix_room_id,
+                    &ctx.sender_id,
+                    &format!("Location: {}", location.map_url()),
+                    &location.uri,
+                    origin_server_ts,
+                )
+                .await
+            {
+                warn!(
+                    "failed to send location {} to {}: {}",
+                    location.uri, mapping.matrix_room_id, err
+                );
+            }
+        }
+        self.mark_bridged(DeliveryDirection::DiscordToMatrix, &mapping)
+            .await;
+        if dry_run::is_placeholder(&matrix_event_id) {
+            return Ok(());
+        }
+
+        if let Some(thread_id) = thread_id.as_ref().filter(|_| starts_thread) {
+            self.db_manager
+                .room_store()
+                .upsert_thread_mapping(&ThreadMapping {
+                    discord_thread_id: thread_id.clone(),
+                    discord_parent_channel_id: mapping.discord_channel_id.clone(),
+                    matrix_room_id: mapping.matrix_room_id.clone(),
+                    matrix_root_event_id: matrix_event_id.clone(),
+                    created_at: Utc::now(),
+                })
+                .await?;
+        }
 
-        if let Some(source_message_id) = ctx.source_message_id {
+        // Replacements keep the mapping on the original event, which later
+        // edits, replies and deletes have to target.
+        if let Some(source_message_id) = ctx.source_message_id
+            && !is_replacement
+        {
             self.db_manager
                 .message_store()
                 .upsert_message_mapping(&MessageMapping {
@@ -1514,6 +4420,9 @@ impl BridgeCore {
                     discord_message_id: source_message_id,
                     matrix_room_id: mapping.matrix_room_id.clone(),
                     matrix_event_id,
+                    discord_channel_id: Some(
+                        thread_id.unwrap_or_else(|| mapping.discord_channel_id.clone()),
+                    ),
                     created_at: Utc::now(),
                     updated_at: Utc::now(),
                 })
@@ -1522,11 +4431,246 @@ impl BridgeCore {
         Ok(())
     }
 
+    /// The mapping of the bridged channel a message's thread belongs to,
+    /// for messages in a thread.
+    async fn thread_parent_mapping(
+        &self,
+        ctx: &DiscordMessageContext,
+    ) -> Result<Option<RoomMapping>> {
+        let parent_id = match &ctx.thread_parent_id {
+            Some(parent_id) => Some(parent_id.clone()),
+            None => match self.discord_client.get_channel(&ctx.channel_id).await {
+                Ok(channel) => channel.and_then(|channel| channel.thread_parent_id),
+                Err(err) => {
+                    warn!(
+                        "no thread lookup for discord channel {}, treating it as unthreaded: {}",
+                        ctx.channel_id, err
+                    );
+                    None
+                }
+            },
+        };
+        let Some(parent_id) = parent_id else {
+            return Ok(None);
+        };
+        Ok(self
+            .db_manager
+            .room_store()
+            .get_room_by_discord_channel(&parent_id)
+            .await?)
+    }
+
+    /// The root of the Matrix thread for a Discord thread: the event already
+    /// paired with it, or else the bridged message the thread was started
+    /// from. `None` when the thread has no root on Matrix yet.
+    async fn matrix_thread_root(
+        &self,
+        discord_thread_id: &str,
+        mapping: &RoomMapping,
+    ) -> Result<Option<String>> {
+        let room_store = self.db_manager.room_store();
+        if let Some(thread) = room_store
+            .get_thread_by_discord_thread(discord_thread_id)
+            .await?
+        {
+            return Ok(Some(thread.matrix_root_event_id));
+        }
+
+        // A thread started from a message shares the message's id.
+        let Some(starter) = self
+            .db_manager
+            .message_store()
+            .get_by_discord_message_id(discord_thread_id)
+            .await?
+            .filter(|starter| starter.matrix_room_id == mapping.matrix_room_id)
+        else {
+            return Ok(None);
+        };
+        room_store
+            .upsert_thread_mapping(&ThreadMapping {
+                discord_thread_id: discord_thread_id.to_string(),
+                discord_parent_channel_id: mapping.discord_channel_id.clone(),
+                matrix_room_id: mapping.matrix_room_id.clone(),
+                matrix_root_event_id: starter.matrix_event_id.clone(),
+                created_at: Utc::now(),
+            })
+            .await?;
+        Ok(Some(starter.matrix_event_id))
+    }
+
+    /// The Discord thread for replies in the Matrix thread rooted at
+    /// `root_event_id`, starting one on the root's Discord message when
+    /// there is none yet. `None` when the root was never bridged or the
+    /// thread could not be started.
+    async fn discord_thread_for(
+        &self,
+        mapping: &RoomMapping,
+        root_event_id: &str,
+        body: &str,
+    ) -> Result<Option<String>> {
+        let room_store = self.db_manager.room_store();
+        if let Some(thread) = room_store
+            .get_thread_by_matrix_root(root_event_id)
+            .await?
+            .filter(|thread| thread.matrix_room_id == mapping.matrix_room_id)
+        {
+            return Ok(Some(thread.discord_thread_id));
+        }
+        let Some(root_message_id) = self
+            .discord_message_for_edit(&mapping.matrix_room_id, root_event_id)
+            .await?
+            .map(|root| root.discord_message_id)
+        else {
+            return Ok(None);
+        };
+
+        // Someone may have started the thread on Discord already.
+        let existing = self
+            .discord_client
+            .get_channel(&root_message_id)
+            .await
+            .ok()
+            .flatten()
+            .filter(|channel| {
+                channel.thread_parent_id.as_deref() == Some(mapping.discord_channel_id.as_str())
+            });
+        let thread_id = match existing {
+            Some(thread) => thread.id,
+            None => match self
+                .discord_client
+                .create_thread_from_message(
+                    &mapping.discord_channel_id,
+                    &root_message_id,
+                    &thread_name(body),
+                )
+                .await
+            {
+                Ok(thread_id) => thread_id,
+                Err(err) => {
+                    warn!(
+                        "matrix thread {} in {} stays in the channel: {:#}",
+                        root_event_id, mapping.matrix_room_id, err
+                    );
+                    return Ok(None);
+                }
+            },
+        };
+        if dry_run::is_placeholder(&thread_id) {
+            return Ok(Some(thread_id));
+        }
+        room_store
+            .upsert_thread_mapping(&ThreadMapping {
+                discord_thread_id: thread_id.clone(),
+                discord_parent_channel_id: mapping.discord_channel_id.clone(),
+                matrix_room_id: mapping.matrix_room_id.clone(),
+                matrix_root_event_id: root_event_id.to_string(),
+                created_at: Utc::now(),
+            })
+            .await?;
+        Ok(Some(thread_id))
+    }
+
+    /// Forgets the Matrix thread of a deleted Discord thread; the messages
+    /// bridged from it stay in the room.
+    pub async fn handle_discord_thread_delete(&self, discord_thread_id: &str) -> Result<()> {
+        if self
+            .hold_discord_event(|| DiscordEvent::ThreadDelete {
+                thread_id: discord_thread_id.to_string(),
+            })
+            .await
+        {
+            return Ok(());
+        }
+        self.db_manager
+            .room_store()
+            .delete_thread_mapping(discord_thread_id)
+            .await?;
+        Ok(())
+    }
+
+    /// Mirrors a Discord reaction onto the Matrix event bridged from its
+    /// message, sent by the reacting user's ghost. Custom emoji are sent by
+    /// their uploaded mxc URL and skipped when the bridge has not seen them.
+    pub async fn handle_discord_reaction(
+        &self,
+        discord_channel_id: &str,
+        discord_message_id: &str,
+        discord_user_id: &str,
+        emoji: &str,
+        custom_emoji_id: Option<&str>,
+    ) -> Result<()> {
+        if self
+            .hold_discord_event(|| DiscordEvent::Reaction {
+                channel_id: discord_channel_id.to_string(),
+                message_id: discord_message_id.to_string(),
+                user_id: discord_user_id.to_string(),
+                emoji: emoji.to_string(),
+                custom_emoji_id: custom_emoji_id.map(str::to_string),
+            })
+            .await
+        {
+            return Ok(());
+        }
+        let Some(link) = self
+            .db_manager
+            .message_store()
+            .get_by_discord_message_id(discord_message_id)
+            .await?
+        else {
+            return Ok(());
+        };
+
+        let key = match custom_emoji_id {
+            Some(emoji_id) => {
+                let Some(custom) = self
+                    .db_manager
+                    .emoji_store()
+                    .get_emoji_by_discord_id(emoji_id)
+                    .await?
+                else {
+                    debug!(
+                        "discord reaction ignored message={} emoji={} reason=unknown_custom_emoji",
+                        discord_message_id, emoji_id
+                    );
+                    return Ok(());
+                };
+                custom.mxc_url
+            }
+            None => emoji.to_string(),
+        };
+
+        self.matrix_client
+            .ensure_ghost_user_registered(discord_user_id, None)
+            .await?;
+        self.matrix_client
+            .send_ghost_reaction(
+                &link.matrix_room_id,
+                discord_user_id,
+                &link.matrix_event_id,
+                &key,
+            )
+            .await?;
+        debug!(
+            "discord reaction forwarded room_id={} event_id={}",
+            link.matrix_room_id, link.matrix_event_id
+        );
+        Ok(())
+    }
+
     pub async fn handle_discord_message_delete(
         &self,
-        _discord_channel_id: &str,
+        discord_channel_id: &str,
         discord_message_id: &str,
     ) -> Result<()> {
+        if self
+            .hold_discord_event(|| DiscordEvent::MessageDelete {
+                channel_id: discord_channel_id.to_string(),
+                message_id: discord_message_id.to_string(),
+            })
+            .await
+        {
+            return Ok(());
+        }
         let link = self
             .db_manager
             .message_store()
@@ -1537,14 +4681,158 @@ impl BridgeCore {
             return Ok(());
         };
 
+        let guild_id = self
+            .db_manager
+            .room_store()
+            .get_room_by_discord_channel(discord_channel_id)
+            .await?
+            .map(|mapping| mapping.discord_guild_id)
+            .filter(|guild_id| self.modlog_room(guild_id).is_some());
+
+        if let Err(err) = self
+            .matrix_client
+            .redact_message(&request.room_id, &request.event_id, Some(request.reason))
+            .await
+        {
+            if let Some(guild_id) = &guild_id {
+                self.post_modlog(
+                    ModlogEntry::new(ModlogAction::BridgeError, guild_id)
+                        .with("channel", Some(discord_channel_id))
+                        .with("message", Some(discord_message_id))
+                        .with("error", Some(format!("failed to redact on Matrix: {err}"))),
+                )
+                .await;
+            }
+            return Err(err);
+        }
+        self.db_manager
+            .message_store()
+            .delete_by_discord_message_id(discord_message_id)
+            .await?;
+
+        // Discord only logs deletes by someone other than the author, which
+        // are the ones worth a modlog entry.
+        if let Some(guild_id) = guild_id {
+            match self
+                .discord_client
+                .recent_audit_entry(
+                    &guild_id,
+                    AuditedAction::MessageDelete,
+                    None,
+                    Some(discord_channel_id),
+                )
+                .await
+            {
+                Ok(Some(attribution)) => {
+                    self.post_modlog(
+                        ModlogEntry::new(ModlogAction::MessageDeleted, &guild_id)
+                            .with("channel", Some(discord_channel_id))
+                            .with("message", Some(discord_message_id))
+                            .with("matrix_event", Some(request.event_id.as_str()))
+                            .with("author", attribution.target_id)
+                            .with("actor", Some(attribution.actor_id))
+                            .with("reason", attribution.reason),
+                    )
+                    .await;
+                }
+                Ok(None) => {}
+                Err(err) => warn!(
+                    "failed to attribute delete of message {}: {}",
+                    discord_message_id, err
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    /// Logs a ban or unban on Discord to the guild's modlog room, attributed
+    /// through the guild's audit log.
+    pub async fn handle_discord_guild_ban(
+        &self,
+        guild_id: &str,
+        discord_user_id: &str,
+        banned: bool,
+    ) -> Result<()> {
+        if self
+            .hold_discord_event(|| DiscordEvent::GuildBan {
+                guild_id: guild_id.to_string(),
+                user_id: discord_user_id.to_string(),
+                banned,
+            })
+            .await
+        {
+            return Ok(());
+        }
+        if self.modlog_room(guild_id).is_none() {
+            return Ok(());
+        }
+        let (action, audited) = if banned {
+            (ModlogAction::Banned, AuditedAction::BanAdd)
+        } else {
+            (ModlogAction::Unbanned, AuditedAction::BanRemove)
+        };
+        let attribution = match self
+            .discord_client
+            .recent_audit_entry(guild_id, audited, Some(discord_user_id), None)
+            .await
+        {
+            Ok(attribution) => attribution,
+            Err(err) => {
+                warn!(
+                    "failed to attribute {} of {} in guild {}: {}",
+                    action.tag(),
+                    discord_user_id,
+                    guild_id,
+                    err
+                );
+                None
+            }
+        };
+        let (actor, reason) = match attribution {
+            Some(attribution) => (Some(attribution.actor_id), attribution.reason),
+            None => (None, None),
+        };
+        self.post_modlog(
+            ModlogEntry::new(action, guild_id)
+                .with("source", Some("discord"))
+                .with("target", Some(discord_user_id))
+                .with(
+                    "matrix_user",
+                    Some(self.matrix_client.ghost_user_id(discord_user_id)),
+                )
+                .with("actor", actor)
+                .with("reason", reason),
+        )
+        .await;
+        Ok(())
+    }
+
+    /// Posts `entry` to the modlog room of its guild, if it has one.
+    pub async fn post_modlog(&self, entry: ModlogEntry) {
+        let Some(room_id) = self.modlog_room(&entry.guild_id) else {
+            return;
+        };
+        if let Err(err) = self
+            .matrix_client
+            .send_bot_message_content(&room_id, &entry.content())
+            .await
+        {
+            warn!(
+                "failed to post {} to modlog room {}: {}",
+                entry.action.tag(),
+                room_id,
+                err
+            );
+        }
+    }
+
+    fn modlog_room(&self, guild_id: &str) -> Option<String> {
         self.matrix_client
-            .redact_message(&request.room_id, &request.event_id, Some(request.reason))
-            .await?;
-        self.db_manager
-            .message_store()
-            .delete_by_discord_message_id(discord_message_id)
-            .await?;
-        Ok(())
+            .config()
+            .room
+            .guilds
+            .get(guild_id)
+            .and_then(|options| options.modlog_room.clone())
     }
 
     pub async fn handle_discord_typing(
@@ -1552,6 +4840,10 @@ impl BridgeCore {
         discord_channel_id: &str,
         discord_sender_id: &str,
     ) -> Result<()> {
+        // Typing is not worth queueing until maintenance ends.
+        if self.maintenance.is_active() {
+            return Ok(());
+        }
         let disable_typing_notifications = self
             .matrix_client
             .config()
@@ -1595,6 +4887,50 @@ impl BridgeCore {
         Ok(())
     }
 
+    /// Handles a press of the Approve/Deny button on a bridge approval
+    /// prompt, in the channel or in the guild owner's DM. Returns a reply shown only to the presser, or `None` when the
+    /// decision was applied and the prompt will be updated instead.
+    pub async fn handle_bridge_approval_interaction(
+        &self,
+        channel_id: &str,
+        discord_user_id: &str,
+        allow: bool,
+        can_manage_guild: bool,
+    ) -> Result<Option<String>> {
+        if !can_manage_guild
+            && !self
+                .provisioning
+                .is_escalated_to(channel_id, discord_user_id)
+        {
+            return Ok(Some(
+                "Only members with the Manage Server permission can answer this request."
+                    .to_string(),
+            ));
+        }
+
+        let status = self
+            .provisioning
+            .mark_approval(channel_id, allow, discord_user_id);
+        self.record_audit(
+            discord_user_id,
+            AuditSource::DiscordCommand,
+            if allow { "approve" } else { "deny" },
+            Some(channel_id),
+            json!({
+                "applied": status == ApprovalResponseStatus::Applied,
+                "via": "button",
+            }),
+        )
+        .await;
+        Ok(match status {
+            ApprovalResponseStatus::Applied => None,
+            ApprovalResponseStatus::Expired => Some(
+                "Thanks for your response, however it has arrived after the deadline - sorry!"
+                    .to_string(),
+            ),
+        })
+    }
+
     async fn handle_discord_command_outcome(
         &self,
         outcome: DiscordCommandOutcome,
@@ -1609,7 +4945,18 @@ impl BridgeCore {
                     .await?;
             }
             DiscordCommandOutcome::ApproveRequested => {
-                let reply = match self.provisioning.mark_approval(&ctx.channel_id, true) {
+                let status = self
+                    .provisioning
+                    .mark_approval(&ctx.channel_id, true, &ctx.sender_id);
+                self.record_audit(
+                    &ctx.sender_id,
+                    AuditSource::DiscordCommand,
+                    "approve",
+                    Some(&ctx.channel_id),
+                    json!({ "applied": status == ApprovalResponseStatus::Applied }),
+                )
+                .await;
+                let reply = match status {
                     ApprovalResponseStatus::Applied => {
                         "Thanks for your response! The matrix bridge has been approved."
                     }
@@ -1622,7 +4969,18 @@ impl BridgeCore {
                     .await?;
             }
             DiscordCommandOutcome::DenyRequested => {
-                let reply = match self.provisioning.mark_approval(&ctx.channel_id, false) {
+                let status =
+                    self.provisioning
+                        .mark_approval(&ctx.channel_id, false, &ctx.sender_id);
+                self.record_audit(
+                    &ctx.sender_id,
+                    AuditSource::DiscordCommand,
+                    "deny",
+                    Some(&ctx.channel_id),
+                    json!({ "applied": status == ApprovalResponseStatus::Applied }),
+                )
+                .await;
+                let reply = match status {
                     ApprovalResponseStatus::Applied => {
                         "Thanks for your response! The matrix bridge has been declined."
                     }
@@ -1728,10 +5086,57 @@ impl BridgeCore {
                                 room_id,
                                 err
                             );
+                            self.post_modlog(
+                                ModlogEntry::new(
+                                    ModlogAction::BridgeError,
+                                    &mapping.discord_guild_id,
+                                )
+                                .with("target", Some(matrix_user.as_str()))
+                                .with("room", Some(room_id.as_str()))
+                                .with(
+                                    "error",
+                                    Some(format!(
+                                        "failed to {} on Matrix: {err}",
+                                        action_keyword(&action)
+                                    )),
+                                ),
+                            )
+                            .await;
                         }
                     }
                 }
 
+                self.record_audit(
+                    &ctx.sender_id,
+                    AuditSource::DiscordCommand,
+                    action_keyword(&action),
+                    Some(&matrix_user),
+                    json!({
+                        "channel_id": ctx.channel_id,
+                        "rooms": target_rooms,
+                        "succeeded": success_count,
+                        "failed": failed_count,
+                    }),
+                )
+                .await;
+
+                let modlog_action = match action {
+                    ModerationAction::Kick => ModlogAction::Kicked,
+                    ModerationAction::Ban => ModlogAction::Banned,
+                    ModerationAction::Unban => ModlogAction::Unbanned,
+                };
+                if success_count > 0 {
+                    self.post_modlog(
+                        ModlogEntry::new(modlog_action, &mapping.discord_guild_id)
+                            .with("source", Some("!matrix command"))
+                            .with("matrix_user", Some(matrix_user.as_str()))
+                            .with("actor", Some(ctx.sender_id.as_str()))
+                            .with("channel", Some(ctx.channel_id.as_str()))
+                            .with("rooms", Some(success_count.to_string())),
+                    )
+                    .await;
+                }
+
                 let reply = if failed_count == 0 {
                     format!("{action_word} {matrix_user} in {success_count} bridged room(s).")
                 } else {
@@ -1751,6 +5156,15 @@ impl BridgeCore {
                         .delete_room_mapping(mapping.id)
                         .await?;
                     self.room_cache.remove(&matrix_room_id).await;
+                    self.receipts.forget_room(&matrix_room_id);
+                    self.record_audit(
+                        &ctx.sender_id,
+                        AuditSource::DiscordCommand,
+                        "unbridge",
+                        Some(&ctx.channel_id),
+                        json!({ "matrix_room_id": matrix_room_id }),
+                    )
+                    .await;
                     self.discord_client
                         .send_message(&ctx.channel_id, "This channel has been unbridged")
                         .await?;
@@ -1763,6 +5177,71 @@ impl BridgeCore {
                         .await?;
                 }
             }
+            DiscordCommandOutcome::StatusRequested => {
+                let reply = match room_mapping {
+                    Some(mapping) => self.room_status(mapping).await,
+                    None => "This channel is not bridged to a plumbed matrix room".to_string(),
+                };
+                self.discord_client
+                    .send_message(&ctx.channel_id, &reply)
+                    .await?;
+            }
+            DiscordCommandOutcome::PresenceOverrideRequested { presence } => {
+                let user_store = self.db_manager.user_store();
+                let reply = if user_store
+                    .get_user_by_discord_id(&ctx.sender_id)
+                    .await?
+                    .is_none()
+                {
+                    "The bridge has not seen you yet; send a message in a bridged channel first."
+                        .to_string()
+                } else {
+                    let value = presence.as_ref().map(MatrixPresenceState::as_str);
+                    user_store
+                        .set_presence_override(&ctx.sender_id, value)
+                        .await?;
+                    self.record_audit(
+                        &ctx.sender_id,
+                        AuditSource::DiscordCommand,
+                        "presence",
+                        Some(&ctx.sender_id),
+                        json!({ "presence_override": value }),
+                    )
+                    .await;
+                    match value {
+                        Some(value) => format!("Your Matrix presence is now pinned to {value}."),
+                        None => {
+                            "Your Matrix presence follows your Discord status again.".to_string()
+                        }
+                    }
+                };
+                self.discord_client
+                    .send_message(&ctx.channel_id, &reply)
+                    .await?;
+            }
+            DiscordCommandOutcome::SyncBansRequested { dry_run } => {
+                let Some(mapping) = room_mapping else {
+                    self.discord_client
+                        .send_message(
+                            &ctx.channel_id,
+                            "This channel is not bridged to a plumbed matrix room",
+                        )
+                        .await?;
+                    return Ok(());
+                };
+                let reply = self.sync_matrix_bans_to_discord(mapping, dry_run).await?;
+                self.record_audit(
+                    &ctx.sender_id,
+                    AuditSource::DiscordCommand,
+                    "sync_bans",
+                    Some(&ctx.channel_id),
+                    json!({ "dry_run": dry_run, "result": reply }),
+                )
+                .await;
+                self.discord_client
+                    .send_message(&ctx.channel_id, &reply)
+                    .await?;
+            }
             DiscordCommandOutcome::BridgeRequested {
                 guild_id,
                 channel_id,
@@ -1775,6 +5254,14 @@ impl BridgeCore {
                         &channel_id,
                     )
                     .await?;
+                self.record_audit(
+                    &ctx.sender_id,
+                    AuditSource::DiscordCommand,
+                    "bridge",
+                    Some(&channel_id),
+                    json!({ "guild_id": guild_id, "result": reply }),
+                )
+                .await;
                 self.discord_client
                     .send_message(&ctx.channel_id, &reply)
                     .await?;
@@ -1783,10 +5270,164 @@ impl BridgeCore {
         Ok(())
     }
 
+    /// Bans the ghosts and linked Matrix accounts of everyone banned from
+    /// the room's Discord guild; returns the reply for the command.
+    async fn sync_discord_bans_to_matrix(
+        &self,
+        room_id: &str,
+        actor: &str,
+        dry_run: bool,
+    ) -> Result<String> {
+        let Some(mapping) = self.get_room_mapping_cached(room_id).await? else {
+            return Ok("This room is not bridged.".to_string());
+        };
+        let discord_bans = self
+            .discord_client
+            .guild_ban_ids(&mapping.discord_guild_id)
+            .await?;
+
+        let mut wanted = Vec::new();
+        for discord_id in &discord_bans {
+            wanted.push(self.matrix_client.ghost_user_id(discord_id));
+            match self
+                .db_manager
+                .user_store()
+                .get_user_by_discord_id(discord_id)
+                .await
+            {
+                Ok(Some(user)) => wanted.push(user.matrix_user_id),
+                Ok(None) => {}
+                Err(e) => warn!("failed to look up linked account of {}: {}", discord_id, e),
+            }
+        }
+        let banned = self.matrix_client.get_banned_users(room_id).await?;
+        let planned = missing_bans(wanted, &banned);
+
+        let mut failed = 0usize;
+        if !dry_run {
+            for user_id in &planned {
+                if let Err(e) = self
+                    .matrix_client
+                    .ban_user_from_room(room_id, user_id, Some("Banned on Discord"))
+                    .await
+                {
+                    failed += 1;
+                    warn!("failed to ban {} from room {}: {}", user_id, room_id, e);
+                    self.post_modlog(
+                        ModlogEntry::new(ModlogAction::BridgeError, &mapping.discord_guild_id)
+                            .with("matrix_user", Some(user_id.as_str()))
+                            .with("room", Some(room_id))
+                            .with("error", Some(format!("sync-bans failed to ban: {e}"))),
+                    )
+                    .await;
+                }
+            }
+            if failed < planned.len() {
+                self.post_modlog(
+                    ModlogEntry::new(ModlogAction::Banned, &mapping.discord_guild_id)
+                        .with("source", Some("!discord sync-bans"))
+                        .with("actor", Some(actor))
+                        .with("room", Some(room_id))
+                        .with("banned", Some((planned.len() - failed).to_string())),
+                )
+                .await;
+            }
+        }
+        Ok(sync_bans_reply("Matrix", &planned, failed, dry_run))
+    }
+
+    /// Bans from the guild the Discord users whose ghosts or linked Matrix
+    /// accounts are banned in the mapped room; returns the reply for the
+    /// command.
+    async fn sync_matrix_bans_to_discord(
+        &self,
+        mapping: &RoomMapping,
+        dry_run: bool,
+    ) -> Result<String> {
+        let matrix_bans = self
+            .matrix_client
+            .get_banned_users(&mapping.matrix_room_id)
+            .await?;
+
+        let mut wanted = Vec::new();
+        for mxid in &matrix_bans {
+            if let Some(discord_id) = self.discord_user_id_from_mxid(mxid) {
+                wanted.push(discord_id);
+                continue;
+            }
+            match self
+                .db_manager
+                .user_store()
+                .get_user_by_matrix_id(mxid)
+                .await
+            {
+                Ok(Some(user)) => wanted.push(user.discord_user_id),
+                Ok(None) => {}
+                Err(e) => warn!("failed to look up linked account of {}: {}", mxid, e),
+            }
+        }
+        let banned = self
+            .discord_client
+            .guild_ban_ids(&mapping.discord_guild_id)
+            .await?;
+        let planned = missing_bans(wanted, &banned);
+
+        let mut failed = 0usize;
+        if !dry_run {
+            for discord_id in &planned {
+                if let Err(e) = self
+                    .discord_client
+                    .ban_member(&mapping.discord_guild_id, discord_id, "Banned on Matrix")
+                    .await
+                {
+                    failed += 1;
+                    warn!(
+                        "failed to ban {} from guild {}: {}",
+                        discord_id, mapping.discord_guild_id, e
+                    );
+                    self.post_modlog(
+                        ModlogEntry::new(ModlogAction::BridgeError, &mapping.discord_guild_id)
+                            .with("target", Some(discord_id.as_str()))
+                            .with("error", Some(format!("sync-bans failed to ban: {e}"))),
+                    )
+                    .await;
+                }
+            }
+        }
+        Ok(sync_bans_reply("Discord", &planned, failed, dry_run))
+    }
+
+    /// Matrix accounts linked to the guild's moderators, seeded at moderator
+    /// power in new portal rooms. Lookup failures only cost the seeding.
+    async fn linked_guild_moderators(&self, guild_id: &str) -> Vec<String> {
+        let discord_ids = match self.discord_client.guild_moderator_ids(guild_id).await {
+            Ok(ids) => ids,
+            Err(e) => {
+                warn!("failed to look up moderators of guild {}: {}", guild_id, e);
+                return Vec::new();
+            }
+        };
+
+        let mut matrix_ids = Vec::new();
+        for discord_id in discord_ids {
+            match self
+                .db_manager
+                .user_store()
+                .get_user_by_discord_id(&discord_id)
+                .await
+            {
+                Ok(Some(user)) => matrix_ids.push(user.matrix_user_id),
+                Ok(None) => {}
+                Err(e) => warn!("failed to look up linked account of {}: {}", discord_id, e),
+            }
+        }
+        matrix_ids
+    }
+
     async fn request_bridge_discord_channel(
         &self,
         _discord_channel_id: &str,
-        _requestor_id: &str,
+        requestor_id: &str,
         guild_id: &str,
         channel_id: &str,
     ) -> Result<String> {
@@ -1804,12 +5445,15 @@ impl BridgeCore {
             return Ok("Could not find the specified Discord channel.".to_string());
         };
 
+        let moderators = self.linked_guild_moderators(guild_id).await;
         let matrix_room_id = match self
             .matrix_client
             .create_room(
                 &channel.id,
+                guild_id,
                 &format!("[Discord] #{}", channel.name),
                 channel.topic.as_deref(),
+                &moderators,
             )
             .await
         {
@@ -1820,12 +5464,22 @@ impl BridgeCore {
             }
         };
 
+        if dry_run::is_placeholder(&matrix_room_id) {
+            return Ok(format!(
+                "Dry run: would have bridged to a new Matrix room for #{}.",
+                channel.name
+            ));
+        }
+
         let mapping = RoomMapping {
             id: 0,
             matrix_room_id: matrix_room_id.clone(),
             discord_channel_id: channel.id.clone(),
             discord_channel_name: channel.name.clone(),
             discord_guild_id: guild_id.to_string(),
+            created_by: Some(requestor_id.to_string()),
+            origin: Some(RoomOrigin::Portal),
+            last_active_at: None,
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
         };
@@ -1834,6 +5488,14 @@ impl BridgeCore {
             .room_store()
             .create_room_mapping(&mapping)
             .await?;
+        let guild_name = self
+            .discord_client
+            .metadata()
+            .guild(guild_id)
+            .await
+            .map(|guild| guild.name);
+        self.publish_bridge_info(&mapping, guild_name.as_deref())
+            .await;
 
         info!(
             "created bridge from discord channel {} to matrix room {}",
@@ -1860,20 +5522,77 @@ impl BridgeCore {
             reply_to: None,
             edit_of: None,
             permissions: HashSet::new(),
+            sent_at: None,
+            relayed_name: None,
+            attributed_to: None,
+            stickers: Vec::new(),
+            reference: None,
+            thread_parent_id: None,
         })
         .await
     }
 
+    /// Whether messages from other Discord bots are bridged.
+    pub fn bridges_bot_messages(&self) -> bool {
+        self.matrix_client.config().bridge.bridge_bot_messages
+    }
+
+    /// Splits a relay bot's message into its author and text when one of
+    /// `bridge.relay_extractors` matches.
+    pub fn unwrap_relayed(&self, author_id: &str, content: &str) -> Option<RelayedMessage> {
+        self.relay_extractors.unwrap(author_id, content)
+    }
+
     pub fn enqueue_discord_presence(&self, presence: DiscordPresence) {
         self.presence_handler.enqueue_user(presence);
     }
 
+    /// Queues the presence set Discord sends for a guild when the gateway
+    /// connects, so ghosts do not stay offline until each member's status
+    /// changes. Guilds without bridged rooms are skipped.
+    pub async fn bootstrap_guild_presences(
+        &self,
+        discord_guild_id: &str,
+        presences: Vec<DiscordPresence>,
+    ) -> Result<usize> {
+        if presences.is_empty() || self.matrix_client.config().bridge.disable_presence {
+            return Ok(0);
+        }
+        let rooms = self
+            .db_manager
+            .room_store()
+            .get_rooms_by_guild(discord_guild_id)
+            .await?;
+        if rooms.is_empty() {
+            return Ok(0);
+        }
+
+        let queued = self.presence_handler.enqueue_users(presences);
+        info!(
+            "queued {} initial presences for guild {}",
+            queued, discord_guild_id
+        );
+        Ok(queued)
+    }
+
     pub async fn handle_discord_channel_update(
         &self,
         discord_channel_id: &str,
         new_name: &str,
         new_topic: Option<&str>,
+        slowmode_seconds: u16,
     ) -> Result<()> {
+        if self
+            .hold_discord_event(|| DiscordEvent::ChannelUpdate {
+                channel_id: discord_channel_id.to_string(),
+                name: new_name.to_string(),
+                topic: new_topic.map(str::to_string),
+                slowmode_seconds,
+            })
+            .await
+        {
+            return Ok(());
+        }
         let room_mapping = self
             .db_manager
             .room_store()
@@ -1913,6 +5632,14 @@ impl BridgeCore {
                 .room_store()
                 .update_room_mapping(&updated)
                 .await?;
+            let guild_name = self
+                .discord_client
+                .metadata()
+                .guild(&updated.discord_guild_id)
+                .await
+                .map(|guild| guild.name);
+            self.publish_bridge_info(&updated, guild_name.as_deref())
+                .await;
 
             info!(
                 "updated room name for channel {} to {}",
@@ -1920,14 +5647,21 @@ impl BridgeCore {
             );
         }
 
-        if let Some(topic) = new_topic {
+        let topic = if self.matrix_client.config().channel.mirror_slowmode {
+            // Always set once mirroring, so a suffix is cleared again when
+            // slowmode is turned off on a channel without a topic.
+            Some(topic_with_slowmode(new_topic, slowmode_seconds).unwrap_or_default())
+        } else {
+            new_topic.map(ToOwned::to_owned)
+        };
+        if let Some(topic) = topic {
             let current_topic = self
                 .matrix_client
                 .get_room_topic(&mapping.matrix_room_id)
                 .await?;
-            if current_topic.as_deref() != Some(topic) {
+            if current_topic.unwrap_or_default() != topic {
                 self.matrix_client
-                    .set_room_topic(&mapping.matrix_room_id, topic)
+                    .set_room_topic(&mapping.matrix_room_id, &topic)
                     .await?;
                 info!("updated room topic for channel {}", discord_channel_id);
             }
@@ -1936,7 +5670,118 @@ impl BridgeCore {
         Ok(())
     }
 
+    /// Tells the bridged room that Discord slowmode was turned on, changed or
+    /// turned off.
+    pub async fn handle_discord_slowmode_change(
+        &self,
+        discord_channel_id: &str,
+        slowmode_seconds: u16,
+    ) -> Result<()> {
+        if self
+            .hold_discord_event(|| DiscordEvent::SlowmodeChange {
+                channel_id: discord_channel_id.to_string(),
+                slowmode_seconds,
+            })
+            .await
+        {
+            return Ok(());
+        }
+        if !self.matrix_client.config().channel.mirror_slowmode {
+            return Ok(());
+        }
+        let Some(mapping) = self
+            .db_manager
+            .room_store()
+            .get_room_by_discord_channel(discord_channel_id)
+            .await?
+        else {
+            return Ok(());
+        };
+
+        let notice = if slowmode_seconds == 0 {
+            "Slowmode was turned off on Discord.".to_string()
+        } else {
+            format!(
+                "Slowmode is on in the Discord channel: members can send one message every {}. Moderators are exempt.",
+                slowmode_label(slowmode_seconds)
+            )
+        };
+        self.matrix_client
+            .send_notice(&mapping.matrix_room_id, &notice)
+            .await?;
+        Ok(())
+    }
+
+    /// Applies Discord slowmode to a Matrix sender. Returns `false` and
+    /// notifies the room when the message has to be dropped.
+    async fn check_matrix_slowmode(&self, event: &MatrixEvent, discord_channel_id: &str) -> bool {
+        if !self.matrix_client.config().channel.mirror_slowmode {
+            return true;
+        }
+        let slowmode_seconds = match self.discord_client.get_channel(discord_channel_id).await {
+            Ok(Some(channel)) => channel.slowmode_seconds,
+            Ok(None) => 0,
+            Err(err) => {
+                debug!(
+                    "slowmode lookup failed for discord channel {}: {}",
+                    discord_channel_id, err
+                );
+                0
+            }
+        };
+        if slowmode_seconds == 0 {
+            return true;
+        }
+        let is_moderator = self
+            .matrix_client
+            .check_permission(
+                &event.sender,
+                &event.room_id,
+                50,
+                "events",
+                "m.room.power_levels",
+            )
+            .await
+            .unwrap_or(false);
+        if is_moderator {
+            return true;
+        }
+
+        let Err(wait) = self.slowmode.try_send(
+            discord_channel_id,
+            &event.sender,
+            slowmode_seconds,
+            std::time::Instant::now(),
+        ) else {
+            return true;
+        };
+        let notice = format!(
+            "{}: slowmode is on in this channel, your message was not sent to Discord. Try again in {}s.",
+            event.sender,
+            wait.as_secs().max(1)
+        );
+        if let Err(err) = self
+            .matrix_client
+            .send_notice(&event.room_id, &notice)
+            .await
+        {
+            warn!(
+                "failed to send slowmode notice to {}: {}",
+                event.room_id, err
+            );
+        }
+        false
+    }
+
     pub async fn handle_discord_channel_delete(&self, discord_channel_id: &str) -> Result<()> {
+        if self
+            .hold_discord_event(|| DiscordEvent::ChannelDelete {
+                channel_id: discord_channel_id.to_string(),
+            })
+            .await
+        {
+            return Ok(());
+        }
         let room_mapping = self
             .db_manager
             .room_store()
@@ -1987,6 +5832,9 @@ impl BridgeCore {
 
         self.room_cache.remove(&mapping.matrix_room_id).await;
 
+        self.receipts.forget_room(&mapping.matrix_room_id);
+        self.spawn_ghost_cleanup(&mapping.matrix_room_id);
+
         info!(
             "removed room mapping for deleted channel {}",
             discord_channel_id
@@ -1996,16 +5844,130 @@ impl BridgeCore {
 
     pub async fn handle_discord_guild_update(
         &self,
-        _discord_guild_id: &str,
-        _new_name: &str,
-        _new_icon_url: Option<&str>,
+        discord_guild_id: &str,
+        new_name: &str,
+        new_icon_url: Option<&str>,
+    ) -> Result<()> {
+        if self
+            .hold_discord_event(|| DiscordEvent::GuildUpdate {
+                guild_id: discord_guild_id.to_string(),
+                name: new_name.to_string(),
+                icon_url: new_icon_url.map(str::to_string),
+            })
+            .await
+        {
+            return Ok(());
+        }
+        debug!("guild update event received, guild_id={}", discord_guild_id);
+        self.sync_guild_bridge_info(discord_guild_id, new_name)
+            .await
+    }
+
+    /// Publishes bridge info in every bridged room of a guild, e.g. after it
+    /// was renamed or when the gateway reconnects.
+    pub async fn sync_guild_bridge_info(
+        &self,
+        discord_guild_id: &str,
+        guild_name: &str,
+    ) -> Result<()> {
+        let rooms = self
+            .db_manager
+            .room_store()
+            .get_rooms_by_guild(discord_guild_id)
+            .await?;
+        for room in rooms {
+            self.publish_bridge_info(&room, Some(guild_name)).await;
+        }
+        Ok(())
+    }
+
+    /// Sets the `m.bridge` and `uk.half-shot.bridge` state for a bridged
+    /// channel, skipping events that are already current. Failures are
+    /// logged: the bridge works without them.
+    async fn publish_bridge_info(&self, mapping: &RoomMapping, guild_name: Option<&str>) {
+        let content = bridge_info_content(&self.matrix_client.bot_user_id(), mapping, guild_name);
+        let state_key = bridge_info_state_key(mapping);
+        for event_type in BRIDGE_INFO_EVENT_TYPES {
+            let current = self
+                .matrix_client
+                .get_room_state(&mapping.matrix_room_id, event_type, &state_key)
+                .await
+                .ok()
+                .flatten();
+            if current.as_ref() == Some(&content) {
+                continue;
+            }
+            if let Err(err) = self
+                .matrix_client
+                .set_room_state(&mapping.matrix_room_id, event_type, &state_key, &content)
+                .await
+            {
+                warn!(
+                    "failed to publish {} in {}: {}",
+                    event_type, mapping.matrix_room_id, err
+                );
+            }
+        }
+    }
+
+    /// Publishes a guild's custom emoji as an image pack in each of its
+    /// bridged rooms. Only new emoji are uploaded, and the state event is
+    /// skipped in rooms whose pack is already current.
+    pub async fn sync_guild_emoji_pack(
+        &self,
+        discord_guild_id: &str,
+        guild_name: &str,
+        mut emojis: Vec<GuildEmoji>,
     ) -> Result<()> {
-        // Future: Update all bridged rooms with new guild info
-        // For now, we just log the event
-        debug!(
-            "guild update event received, guild_id={}",
-            _discord_guild_id
-        );
+        let rooms = self
+            .db_manager
+            .room_store()
+            .get_rooms_by_guild(discord_guild_id)
+            .await?;
+        if rooms.is_empty() {
+            return Ok(());
+        }
+
+        // Sorted so duplicate names get the same shortcodes on every sync.
+        emojis.sort_by(|a, b| snowflake::compare(&a.id, &b.id));
+        let emotes = self.emoji_handler.sync_guild_emojis(&emojis).await?;
+        let content = emote_pack_content(guild_name, &emotes);
+
+        for room in rooms {
+            let current = self
+                .matrix_client
+                .get_room_state(
+                    &room.matrix_room_id,
+                    EMOTE_PACK_EVENT_TYPE,
+                    discord_guild_id,
+                )
+                .await?;
+            if current.as_ref() == Some(&content) {
+                continue;
+            }
+            if let Err(err) = self
+                .matrix_client
+                .set_room_state(
+                    &room.matrix_room_id,
+                    EMOTE_PACK_EVENT_TYPE,
+                    discord_guild_id,
+                    &content,
+                )
+                .await
+            {
+                warn!(
+                    "failed to publish emoji pack for guild {} in {}: {}",
+                    discord_guild_id, room.matrix_room_id, err
+                );
+                continue;
+            }
+            info!(
+                "published {} emoji for guild {} in {}",
+                emotes.len(),
+                discord_guild_id,
+                room.matrix_room_id
+            );
+        }
         Ok(())
     }
 
@@ -2015,6 +5977,16 @@ impl BridgeCore {
         new_username: &str,
         new_avatar_url: Option<&str>,
     ) -> Result<()> {
+        if self
+            .hold_discord_event(|| DiscordEvent::UserUpdate {
+                user_id: discord_user_id.to_string(),
+                username: new_username.to_string(),
+                avatar_url: new_avatar_url.map(str::to_string),
+            })
+            .await
+        {
+            return Ok(());
+        }
         let user_mapping = self
             .db_manager
             .user_store()
@@ -2050,6 +6022,18 @@ impl BridgeCore {
         new_avatar_url: Option<&str>,
         roles: &[String],
     ) -> Result<()> {
+        if self
+            .hold_discord_event(|| DiscordEvent::MemberUpdate {
+                guild_id: discord_guild_id.to_string(),
+                user_id: discord_user_id.to_string(),
+                display_name: new_nick.to_string(),
+                avatar_url: new_avatar_url.map(str::to_string),
+                roles: roles.to_vec(),
+            })
+            .await
+        {
+            return Ok(());
+        }
         let user_mapping = self
             .db_manager
             .user_store()
@@ -2074,19 +6058,72 @@ impl BridgeCore {
             .update_user_mapping(&updated)
             .await?;
 
+        // The guild's nick and avatar only show in the guild's rooms.
+        let username = self
+            .discord_client
+            .metadata()
+            .user(discord_user_id)
+            .await
+            .map(|user| user.username)
+            .unwrap_or_else(|| mapping.discord_username.clone());
+        let displayname = crate::utils::formatting::apply_pattern_string(
+            &self.matrix_client.config().ghosts.nick_pattern,
+            &[
+                ("id", discord_user_id),
+                ("nick", new_nick),
+                ("tag", mapping.discord_discriminator.as_str()),
+                ("username", username.as_str()),
+            ],
+        );
+        let avatar_mxc = match new_avatar_url {
+            Some(avatar_url) => match self
+                .upload_discord_avatar(discord_user_id, avatar_url)
+                .await
+            {
+                Ok(mxc_url) => Some(mxc_url),
+                Err(err) => {
+                    warn!(
+                        "failed to upload guild avatar of {}: {}",
+                        discord_user_id, err
+                    );
+                    None
+                }
+            },
+            None => None,
+        };
+
+        let ghost_user_id = self.matrix_client.ghost_user_id(discord_user_id);
         let room_mappings = self
             .db_manager
             .room_store()
             .get_rooms_by_guild(discord_guild_id)
             .await?;
         for room in room_mappings {
-            if let Err(err) = self
+            // Setting the member event of a room the ghost is not in would
+            // join it.
+            let joined = self
                 .matrix_client
-                .set_ghost_room_roles(discord_user_id, &room.matrix_room_id, roles)
+                .get_joined_members(&room.matrix_room_id)
                 .await
-            {
+                .map(|members| members.contains(&ghost_user_id));
+            let result = match joined {
+                Ok(true) => {
+                    self.matrix_client
+                        .set_ghost_room_profile(
+                            discord_user_id,
+                            &room.matrix_room_id,
+                            &displayname,
+                            avatar_mxc.as_deref(),
+                            roles,
+                        )
+                        .await
+                }
+                Ok(false) => continue,
+                Err(err) => Err(err),
+            };
+            if let Err(err) = result {
                 warn!(
-                    "failed to sync member roles for user={} guild={} room={}: {}",
+                    "failed to sync member profile for user={} guild={} room={}: {}",
                     discord_user_id, discord_guild_id, room.matrix_room_id, err
                 );
             }
@@ -2099,7 +6136,67 @@ impl BridgeCore {
         Ok(())
     }
 
+    /// Posts a notice about a member's new name or avatar in the guild's
+    /// rooms where they recently posted, when `bridge.member_change_notices`
+    /// is on. Notices are rate limited per room.
+    pub async fn announce_discord_member_change(
+        &self,
+        discord_guild_id: &str,
+        discord_user_id: &str,
+        old_name: &str,
+        new_name: &str,
+        avatar_changed: bool,
+    ) -> Result<()> {
+        if self
+            .hold_discord_event(|| DiscordEvent::MemberChange {
+                guild_id: discord_guild_id.to_string(),
+                user_id: discord_user_id.to_string(),
+                old_name: old_name.to_string(),
+                new_name: new_name.to_string(),
+                avatar_changed,
+            })
+            .await
+        {
+            return Ok(());
+        }
+        if !self.matrix_client.config().bridge.member_change_notices {
+            return Ok(());
+        }
+        let Some(notice) = member_change_notice(old_name, new_name, avatar_changed) else {
+            return Ok(());
+        };
+
+        let room_ids: Vec<String> = self
+            .db_manager
+            .room_store()
+            .get_rooms_by_guild(discord_guild_id)
+            .await?
+            .into_iter()
+            .map(|room| room.matrix_room_id)
+            .collect();
+        let targets =
+            self.member_notices
+                .notice_rooms(discord_user_id, &room_ids, std::time::Instant::now());
+        for room_id in targets {
+            if let Err(err) = self.matrix_client.send_notice(&room_id, &notice).await {
+                warn!(
+                    "failed to post member change notice user={} room={}: {}",
+                    discord_user_id, room_id, err
+                );
+            }
+        }
+        Ok(())
+    }
+
     pub async fn handle_discord_guild_delete(&self, discord_guild_id: &str) -> Result<()> {
+        if self
+            .hold_discord_event(|| DiscordEvent::GuildDelete {
+                guild_id: discord_guild_id.to_string(),
+            })
+            .await
+        {
+            return Ok(());
+        }
         let room_mappings = self
             .db_manager
             .room_store()
@@ -2136,25 +6233,32 @@ impl BridgeCore {
         discord_guild_id: &str,
         discord_user_id: &str,
         display_name: &str,
-        _avatar_url: Option<&str>,
+        avatar_url: Option<&str>,
         roles: &[String],
     ) -> Result<()> {
+        if self
+            .hold_discord_event(|| DiscordEvent::MemberAdd {
+                guild_id: discord_guild_id.to_string(),
+                user_id: discord_user_id.to_string(),
+                display_name: display_name.to_string(),
+                avatar_url: avatar_url.map(str::to_string),
+                roles: roles.to_vec(),
+            })
+            .await
+        {
+            return Ok(());
+        }
         debug!(
             "discord guild member add guild_id={} user_id={} display_name={}",
             discord_guild_id, discord_user_id, display_name
         );
 
-        let room_mappings = self
+        let guild_rooms = self
             .db_manager
             .room_store()
-            .list_room_mappings(i64::MAX, 0)
+            .get_rooms_by_guild(discord_guild_id)
             .await?;
 
-        let guild_rooms: Vec<_> = room_mappings
-            .iter()
-            .filter(|m| m.discord_guild_id == discord_guild_id)
-            .collect();
-
         if guild_rooms.is_empty() {
             debug!(
                 "no rooms mapped for guild {}, skipping member add",
@@ -2166,35 +6270,30 @@ impl BridgeCore {
         self.matrix_client
             .ensure_ghost_user_registered(discord_user_id, Some(display_name))
             .await?;
+        if let Some(avatar_url) = avatar_url {
+            self.sync_ghost_avatar(discord_user_id, avatar_url).await;
+        }
 
-        for mapping in guild_rooms {
-            if !self
-                .matrix_client
-                .config()
-                .bridge
-                .disable_join_leave_notifications
+        for mapping in &guild_rooms {
+            if self
+                .room_auto_invites_members(&mapping.matrix_room_id)
+                .await?
             {
-                let ghost_user_id = format!(
-                    "@_discord_{}:{}",
-                    discord_user_id,
-                    self.matrix_client.config().bridge.domain
-                );
-
                 match self
                     .matrix_client
-                    .invite_user_to_room(&mapping.matrix_room_id, &ghost_user_id)
+                    .join_ghost_to_room(discord_user_id, &mapping.matrix_room_id)
                     .await
                 {
-                    Ok(_) => {
+                    Ok(()) => {
                         info!(
-                            "invited ghost user {} to room {} for guild member add",
-                            ghost_user_id, mapping.matrix_room_id
+                            "joined ghost of {} to room {} for guild member add",
+                            discord_user_id, mapping.matrix_room_id
                         );
                     }
                     Err(e) => {
                         warn!(
-                            "failed to invite ghost user {} to room {}: {}",
-                            ghost_user_id, mapping.matrix_room_id, e
+                            "failed to join ghost of {} to room {}: {}",
+                            discord_user_id, mapping.matrix_room_id, e
                         );
                     }
                 }
@@ -2215,11 +6314,182 @@ impl BridgeCore {
         Ok(())
     }
 
+    /// Whether ghosts of new guild members are invited into this room. Rooms
+    /// without their own setting follow `room.auto_invite_members`.
+    pub async fn room_auto_invites_members(&self, matrix_room_id: &str) -> Result<bool> {
+        let settings = self
+            .db_manager
+            .room_store()
+            .get_room_settings(matrix_room_id)
+            .await?;
+        Ok(settings
+            .map(|settings| settings.auto_invite_members)
+            .unwrap_or(self.matrix_client.config().room.auto_invite_members))
+    }
+
+    /// Whether messages from Discord get a "via Discord" badge in this
+    /// room. Rooms without their own setting follow `room.attribution_badge`.
+    pub async fn room_attribution_badge(&self, matrix_room_id: &str) -> Result<bool> {
+        let settings = self
+            .db_manager
+            .room_store()
+            .get_room_settings(matrix_room_id)
+            .await?;
+        Ok(settings
+            .map(|settings| settings.attribution_badge)
+            .unwrap_or(self.matrix_client.config().room.attribution_badge))
+    }
+
+    /// How attachments of messages going `direction` are carried in this
+    /// room. Rooms without their own setting follow `media.attachment_policy`.
+    pub async fn room_attachment_policy(
+        &self,
+        matrix_room_id: &str,
+        direction: DeliveryDirection,
+    ) -> Result<AttachmentPolicy> {
+        let overrides = self.room_settings(matrix_room_id).await?.attachment_policy;
+        let defaults = self.matrix_client.config().media.attachment_policy;
+        Ok(match direction {
+            DeliveryDirection::MatrixToDiscord => overrides
+                .matrix_to_discord
+                .unwrap_or(defaults.matrix_to_discord),
+            DeliveryDirection::DiscordToMatrix => overrides
+                .discord_to_matrix
+                .unwrap_or(defaults.discord_to_matrix),
+        })
+    }
+
+    /// The room's stored settings, or the configured defaults for rooms
+    /// that never changed any.
+    async fn room_settings(&self, matrix_room_id: &str) -> Result<RoomSettings> {
+        let settings = self
+            .db_manager
+            .room_store()
+            .get_room_settings(matrix_room_id)
+            .await?;
+        Ok(settings.unwrap_or_else(|| RoomSettings {
+            auto_invite_members: self.matrix_client.config().room.auto_invite_members,
+            attribution_badge: self.matrix_client.config().room.attribution_badge,
+            ..RoomSettings::new(matrix_room_id)
+        }))
+    }
+
+    async fn set_inactivity_exempt(
+        &self,
+        matrix_room_id: &str,
+        exempt: bool,
+        actor: &str,
+    ) -> Result<String> {
+        if self
+            .get_room_mapping_cached(matrix_room_id)
+            .await?
+            .is_none()
+        {
+            return Ok(format!("{} is not bridged.", matrix_room_id));
+        }
+        let mut settings = self.room_settings(matrix_room_id).await?;
+        settings.inactivity_exempt = exempt;
+        settings.updated_at = Utc::now();
+        self.db_manager
+            .room_store()
+            .set_room_settings(&settings)
+            .await?;
+        self.record_audit(
+            actor,
+            AuditSource::AdminRoom,
+            "set_inactivity_exempt",
+            Some(matrix_room_id),
+            json!({ "exempt": exempt }),
+        )
+        .await;
+        Ok(if exempt {
+            format!("{} will not be unbridged for inactivity.", matrix_room_id)
+        } else {
+            format!("{} may be unbridged for inactivity again.", matrix_room_id)
+        })
+    }
+
+    async fn set_room_auto_invite_members(
+        &self,
+        matrix_room_id: &str,
+        enabled: bool,
+    ) -> Result<()> {
+        let mut settings = self.room_settings(matrix_room_id).await?;
+        settings.auto_invite_members = enabled;
+        // Members already in the guild are brought in too; turning it off
+        // cancels a sync that is still running.
+        settings.member_sync = if enabled {
+            Some(settings.member_sync.take().unwrap_or_default())
+        } else {
+            None
+        };
+        settings.updated_at = Utc::now();
+        self.db_manager
+            .room_store()
+            .set_room_settings(&settings)
+            .await?;
+        Ok(())
+    }
+
+    /// Uploads the Discord avatar and sets it on the ghost. Failures only
+    /// cost the avatar, so they are logged rather than returned.
+    async fn sync_ghost_avatar(&self, discord_user_id: &str, avatar_url: &str) {
+        let result = async {
+            let mxc_url = self
+                .upload_discord_avatar(discord_user_id, avatar_url)
+                .await?;
+            self.matrix_client
+                .set_ghost_avatar(discord_user_id, &mxc_url)
+                .await
+        }
+        .await;
+        if let Err(err) = result {
+            warn!(
+                "failed to set avatar for discord user {}: {}",
+                discord_user_id, err
+            );
+        }
+    }
+
+    /// The Matrix copy of a Discord avatar, uploading it the first time.
+    async fn upload_discord_avatar(
+        &self,
+        discord_user_id: &str,
+        avatar_url: &str,
+    ) -> Result<String> {
+        if let Some(mxc_url) = self.ghost_avatars.lock().get(avatar_url) {
+            return Ok(mxc_url.clone());
+        }
+        let media = self.media_handler.download_from_url(avatar_url).await?;
+        let mxc_url = self
+            .matrix_client
+            .upload_media_for_ghost(
+                discord_user_id,
+                &media.data,
+                &media.content_type,
+                &media.filename,
+            )
+            .await?;
+        self.ghost_avatars
+            .lock()
+            .insert(avatar_url.to_string(), mxc_url.clone());
+        Ok(mxc_url)
+    }
+
     pub async fn handle_discord_guild_member_remove(
         &self,
         discord_guild_id: &str,
         discord_user_id: &str,
     ) -> Result<()> {
+        if self
+            .hold_discord_event(|| DiscordEvent::MemberRemove {
+                guild_id: discord_guild_id.to_string(),
+                user_id: discord_user_id.to_string(),
+            })
+            .await
+        {
+            return Ok(());
+        }
         debug!(
             "discord guild member remove guild_id={} user_id={}",
             discord_guild_id, discord_user_id
@@ -2244,41 +6514,47 @@ impl BridgeCore {
             return Ok(());
         }
 
+        if self
+            .matrix_client
+            .config()
+            .bridge
+            .disable_join_leave_notifications
+            || self.matrix_client.uses_per_message_profiles()
+        {
+            return Ok(());
+        }
+        let ghost_user_id = self.matrix_client.ghost_user_id(discord_user_id);
         for mapping in guild_rooms {
-            if !self
+            let joined = match self
                 .matrix_client
-                .config()
-                .bridge
-                .disable_join_leave_notifications
+                .get_joined_members(&mapping.matrix_room_id)
+                .await
             {
-                let ghost_user_id = format!(
-                    "@_discord_{}:{}",
-                    discord_user_id,
-                    self.matrix_client.config().bridge.domain
-                );
-
-                match self
-                    .matrix_client
-                    .kick_user_from_room(
-                        &mapping.matrix_room_id,
-                        &ghost_user_id,
-                        Some("Left Discord server"),
-                    )
-                    .await
-                {
-                    Ok(_) => {
-                        info!(
-                            "kicked ghost user {} from room {} for guild member remove",
-                            ghost_user_id, mapping.matrix_room_id
-                        );
-                    }
-                    Err(e) => {
-                        warn!(
-                            "failed to kick ghost user {} from room {}: {}",
-                            ghost_user_id, mapping.matrix_room_id, e
-                        );
-                    }
+                Ok(members) => members.contains(&ghost_user_id),
+                Err(err) => {
+                    warn!(
+                        "failed to list members of room {}: {}",
+                        mapping.matrix_room_id, err
+                    );
+                    continue;
                 }
+            };
+            if !joined {
+                continue;
+            }
+            match self
+                .matrix_client
+                .leave_room_as(&ghost_user_id, &mapping.matrix_room_id)
+                .await
+            {
+                Ok(()) => info!(
+                    "ghost user {} left room {} for guild member remove",
+                    ghost_user_id, mapping.matrix_room_id
+                ),
+                Err(err) => warn!(
+                    "ghost user {} failed to leave room {}: {}",
+                    ghost_user_id, mapping.matrix_room_id, err
+                ),
             }
         }
 
@@ -2309,12 +6585,7 @@ impl MatrixPresenceTarget for MatrixAppservice {
         presence: MatrixPresenceState,
         status_message: &str,
     ) -> Result<()> {
-        let presence = match presence {
-            MatrixPresenceState::Online => "online",
-            MatrixPresenceState::Offline => "offline",
-            MatrixPresenceState::Unavailable => "unavailable",
-        };
-        self.set_discord_user_presence(discord_user_id, presence, status_message)
+        self.set_discord_user_presence(discord_user_id, presence.as_str(), status_message)
             .await
     }
 
@@ -2327,3 +6598,112 @@ impl MatrixPresenceTarget for MatrixAppservice {
             .await
     }
 }
+
+/// Presence target that also honours per-user overrides stored on the user
+/// mapping.
+struct GhostPresenceTarget<'a> {
+    matrix: &'a MatrixAppservice,
+    db: &'a DatabaseManager,
+}
+
+#[async_trait]
+impl MatrixPresenceTarget for GhostPresenceTarget<'_> {
+    async fn set_presence(
+        &self,
+        discord_user_id: &str,
+        presence: MatrixPresenceState,
+        status_message: &str,
+    ) -> Result<()> {
+        self.matrix
+            .set_presence(discord_user_id, presence, status_message)
+            .await
+    }
+
+    async fn ensure_user_registered(
+        &self,
+        discord_user_id: &str,
+        username: Option<&str>,
+    ) -> Result<()> {
+        self.matrix
+            .ensure_user_registered(discord_user_id, username)
+            .await
+    }
+
+    async fn presence_override(
+        &self,
+        discord_user_id: &str,
+    ) -> Result<Option<MatrixPresenceState>> {
+        let mapping = self
+            .db
+            .user_store()
+            .get_user_by_discord_id(discord_user_id)
+            .await?;
+        Ok(mapping
+            .and_then(|mapping| mapping.presence_override)
+            .and_then(|value| MatrixPresenceState::parse(&value)))
+    }
+}
+
+fn nick_reply(nick: Option<&str>) -> String {
+    match nick {
+        Some(nick) => format!("Your messages in this room appear on Discord as \"{nick}\"."),
+        None => "Your messages in this room appear on Discord under your Matrix displayname."
+            .to_string(),
+    }
+}
+
+fn role_keywords_reply(role_keywords: &BTreeMap<String, String>) -> String {
+    if role_keywords.is_empty() {
+        return "No role keywords are set for this room.".to_string();
+    }
+    let mut reply = "Role keywords in this room:".to_string();
+    for (role_id, keyword) in role_keywords {
+        reply.push_str(&format!("\n - role {}: {}", role_id, keyword));
+    }
+    reply
+}
+
+/// Names the privileged intents the bridge runs without and what that
+/// turns off; `None` when it has them all.
+fn missing_intents_notice(missing: &[PrivilegedIntent]) -> Option<String> {
+    if missing.is_empty() {
+        return None;
+    }
+    let mut notice = "The Discord bot runs without these privileged intents:".to_string();
+    for intent in missing {
+        notice.push_str(&format!(
+            "\n - {}: no {}",
+            intent.as_str(),
+            intent.disables()
+        ));
+    }
+    notice.push_str(
+        "\nEnable them for the application in the Discord developer portal and set \
+         auth.use_privileged_intents.",
+    );
+    Some(notice)
+}
+
+fn badge_reply(enabled: bool) -> String {
+    if enabled {
+        "Messages from Discord will be marked with a \"via Discord\" badge.".to_string()
+    } else {
+        "Messages from Discord will not be marked with a badge.".to_string()
+    }
+}
+
+fn confirm_reply(enabled: bool) -> String {
+    if enabled {
+        "Messages will get a ✅ reaction once they reached Discord.".to_string()
+    } else {
+        "Messages will not get a reaction when they reach Discord.".to_string()
+    }
+}
+
+fn auto_invite_reply(enabled: bool) -> String {
+    if enabled {
+        "New Discord members will be invited to this room.".to_string()
+    } else {
+        "New Discord members will not be invited to this room.".to_string()
+    }
+}
@@ -0,0 +1,63 @@
+//! Planning for `sync-bans`, which copies the ban list of one side of a
+//! bridge onto the other.
+
+use std::collections::HashSet;
+
+/// The entries of `wanted` not already in `banned`, deduplicated and in
+/// their original order.
+pub fn missing_bans(wanted: impl IntoIterator<Item = String>, banned: &[String]) -> Vec<String> {
+    let mut seen: HashSet<String> = banned.iter().cloned().collect();
+    wanted
+        .into_iter()
+        .filter(|id| seen.insert(id.clone()))
+        .collect()
+}
+
+/// The reply to a `sync-bans` run that banned `planned` on `side`, of which
+/// `failed` did not go through.
+pub fn sync_bans_reply(side: &str, planned: &[String], failed: usize, dry_run: bool) -> String {
+    if planned.is_empty() {
+        return format!("No bans to sync; {side} already has every ban.");
+    }
+    if dry_run {
+        return format!(
+            "Dry run: would ban {} user(s) on {side}: {}",
+            planned.len(),
+            planned.join(", ")
+        );
+    }
+    let banned = planned.len() - failed;
+    if failed == 0 {
+        format!("Banned {banned} user(s) on {side}.")
+    } else {
+        format!("Banned {banned} user(s) on {side}, failed to ban {failed}.")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{missing_bans, sync_bans_reply};
+
+    #[test]
+    fn only_users_not_yet_banned_are_planned() {
+        let banned = vec!["@a:example.org".to_string()];
+        let wanted = ["@b:example.org", "@a:example.org", "@b:example.org"]
+            .into_iter()
+            .map(str::to_string);
+        let planned = missing_bans(wanted, &banned);
+        assert_eq!(planned, ["@b:example.org"]);
+
+        assert_eq!(
+            sync_bans_reply("Matrix", &planned, 0, true),
+            "Dry run: would ban 1 user(s) on Matrix: @b:example.org"
+        );
+        assert_eq!(
+            sync_bans_reply("Matrix", &planned, 1, false),
+            "Banned 0 user(s) on Matrix, failed to ban 1."
+        );
+        assert_eq!(
+            sync_bans_reply("Discord", &[], 0, false),
+            "No bans to sync; Discord already has every ban."
+        );
+    }
+}
@@ -0,0 +1,87 @@
+//! The MSC2346 bridge info state event, which clients such as Element read to
+//! show which Discord guild and channel a room is bridged to.
+
+use serde_json::{Value, json};
+
+use crate::db::RoomMapping;
+
+/// The stable type and the one clients used before it was stabilised; both
+/// are published.
+pub const BRIDGE_INFO_EVENT_TYPES: [&str; 2] = ["m.bridge", "uk.half-shot.bridge"];
+
+/// One state key per bridged channel, so a room bridged to several channels
+/// carries one event for each.
+pub fn bridge_info_state_key(mapping: &RoomMapping) -> String {
+    format!(
+        "discord://discord/{}/{}",
+        mapping.discord_guild_id, mapping.discord_channel_id
+    )
+}
+
+/// Bridge info for the room in `mapping`. `guild_name` falls back to the
+/// guild id while the guild is not cached.
+pub fn bridge_info_content(
+    bot_user_id: &str,
+    mapping: &RoomMapping,
+    guild_name: Option<&str>,
+) -> Value {
+    json!({
+        "bridgebot": bot_user_id,
+        "creator": bot_user_id,
+        "protocol": {
+            "id": "discord",
+            "displayname": "Discord",
+            "external_url": "https://discord.com/",
+        },
+        "network": {
+            "id": mapping.discord_guild_id,
+            "displayname": guild_name.unwrap_or(&mapping.discord_guild_id),
+        },
+        "channel": {
+            "id": mapping.discord_channel_id,
+            "displayname": format!("#{}", mapping.discord_channel_name),
+            "external_url": format!(
+                "https://discord.com/channels/{}/{}",
+                mapping.discord_guild_id, mapping.discord_channel_id
+            ),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::{bridge_info_content, bridge_info_state_key};
+    use crate::db::RoomMapping;
+
+    #[test]
+    fn bridge_info_describes_guild_and_channel() {
+        let mapping = RoomMapping {
+            id: 1,
+            matrix_room_id: "!room:example.org".to_string(),
+            discord_channel_id: "20".to_string(),
+            discord_channel_name: "general".to_string(),
+            discord_guild_id: "10".to_string(),
+            created_by: None,
+            origin: None,
+            last_active_at: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        assert_eq!(bridge_info_state_key(&mapping), "discord://discord/10/20");
+
+        let content = bridge_info_content("@_discord_:example.org", &mapping, Some("Rust"));
+        assert_eq!(content["bridgebot"], "@_discord_:example.org");
+        assert_eq!(content["protocol"]["id"], "discord");
+        assert_eq!(content["network"]["displayname"], "Rust");
+        assert_eq!(content["channel"]["displayname"], "#general");
+        assert_eq!(
+            content["channel"]["external_url"],
+            "https://discord.com/channels/10/20"
+        );
+
+        let uncached = bridge_info_content("@_discord_:example.org", &mapping, None);
+        assert_eq!(uncached["network"]["displayname"], "10");
+    }
+}
@@ -0,0 +1,107 @@
+//! Scrubs configured patterns, e.g. email addresses or tokens, from message
+//! text before it is bridged to the other platform.
+
+use std::borrow::Cow;
+
+use regex::Regex;
+use tracing::info;
+
+use crate::config::ContentRedactionConfig;
+use crate::web::Metrics;
+
+struct RedactionRule {
+    name: String,
+    pattern: Regex,
+    replacement: String,
+}
+
+/// The configured `bridge.content_redaction`, compiled once.
+#[derive(Default)]
+pub struct ContentRedactor {
+    rules: Vec<RedactionRule>,
+    test_mode: bool,
+}
+
+impl ContentRedactor {
+    pub fn new(config: &ContentRedactionConfig) -> Result<Self, regex::Error> {
+        let rules = config
+            .rules
+            .iter()
+            .map(|rule| {
+                Ok(RedactionRule {
+                    name: rule.name.clone(),
+                    pattern: Regex::new(&rule.pattern)?,
+                    replacement: rule.replacement.clone(),
+                })
+            })
+            .collect::<Result<_, regex::Error>>()?;
+        Ok(Self {
+            rules,
+            test_mode: config.test_mode,
+        })
+    }
+
+    /// Applies every rule to `text`, written on `origin` (`matrix` or
+    /// `discord`). In test mode matches are only logged and counted.
+    pub fn apply<'a>(&self, origin: &'static str, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for rule in &self.rules {
+            let matches = rule.pattern.find_iter(&text).count();
+            if matches == 0 {
+                continue;
+            }
+            if self.test_mode {
+                info!(
+                    "content redaction test: rule {} matched {} time(s) in a {} message",
+                    rule.name, matches, origin
+                );
+                Metrics::content_redacted(&rule.name, origin, "test");
+                continue;
+            }
+            Metrics::content_redacted(&rule.name, origin, "redacted");
+            text = Cow::Owned(
+                rule.pattern
+                    .replace_all(&text, rule.replacement.as_str())
+                    .into_owned(),
+            );
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ContentRedactor;
+    use crate::config::{ContentRedactionConfig, RedactionRuleConfig};
+
+    fn config(test_mode: bool) -> ContentRedactionConfig {
+        ContentRedactionConfig {
+            rules: vec![
+                RedactionRuleConfig {
+                    name: "email".to_string(),
+                    pattern: r"[\w.+-]+@[\w-]+\.[\w.]+".to_string(),
+                    replacement: "[email]".to_string(),
+                },
+                RedactionRuleConfig {
+                    name: "token".to_string(),
+                    pattern: r"(token=)\w+".to_string(),
+                    replacement: "${1}[redacted]".to_string(),
+                },
+            ],
+            test_mode,
+        }
+    }
+
+    #[test]
+    fn matches_are_replaced_unless_testing() {
+        let redactor = ContentRedactor::new(&config(false)).unwrap();
+        assert_eq!(
+            redactor.apply("matrix", "mail a@b.org, token=abc123 please"),
+            "mail [email], token=[redacted] please"
+        );
+        assert_eq!(redactor.apply("discord", "nothing here"), "nothing here");
+
+        let tester = ContentRedactor::new(&config(true)).unwrap();
+        assert_eq!(tester.apply("matrix", "mail a@b.org"), "mail a@b.org");
+    }
+}
@@ -0,0 +1,93 @@
+//! Cross-channel context: with `bridge.cross_channel_context` on, a message
+//! in an unbridged channel that replies to or forwards one from a bridged
+//! channel is announced with a notice in the bridged room, so Matrix users
+//! can follow conversations that move to another channel. Only the
+//! referenced message, which came from the bridged channel, is quoted; the
+//! text posted in the unbridged channel stays there.
+
+use serde_json::{Value, json};
+
+/// The `m.room.message` content of the notice, a reply to `in_reply_to`
+/// (the Matrix event of the referenced message) when it is known.
+/// `quoted` is the referenced message as plain text and HTML; it is left
+/// out of replies to a known event, which already show it.
+pub fn context_notice(
+    sender: &str,
+    channel_name: &str,
+    quoted: Option<(&str, &str)>,
+    forwarded: bool,
+    in_reply_to: Option<&str>,
+) -> Value {
+    let action = if forwarded {
+        "forwarded a message from this room to"
+    } else if in_reply_to.is_some() {
+        "replied to this in"
+    } else {
+        "replied to a message from this room in"
+    };
+    let mut body = format!("{} {} #{}", sender, action, channel_name);
+    let mut html = escape_html(&body);
+    if let Some((text, formatted)) = quoted.filter(|_| forwarded || in_reply_to.is_none())
+        && !text.trim().is_empty()
+    {
+        body.push_str(":\n");
+        body.push_str(text.trim());
+        html.push_str(&format!(":<blockquote>{}</blockquote>", formatted.trim()));
+    }
+
+    let mut content = json!({
+        "msgtype": "m.notice",
+        "body": body,
+        "format": "org.matrix.custom.html",
+        "formatted_body": html,
+    });
+    if let Some(event_id) = in_reply_to {
+        content["m.relates_to"] = json!({ "m.in_reply_to": { "event_id": event_id } });
+    }
+    content
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::context_notice;
+
+    #[test]
+    fn notice_names_the_channel_and_replies_to_the_original() {
+        let reply = context_notice(
+            "alice",
+            "off-topic",
+            Some(("the original", "the original")),
+            false,
+            Some("$original"),
+        );
+        assert_eq!(reply["msgtype"], "m.notice");
+        assert_eq!(reply["body"], "alice replied to this in #off-topic");
+        assert_eq!(
+            reply["m.relates_to"]["m.in_reply_to"]["event_id"],
+            "$original"
+        );
+
+        let forward = context_notice(
+            "bob",
+            "news",
+            Some((" big <news> ", "big &lt;news&gt;")),
+            true,
+            None,
+        );
+        assert_eq!(
+            forward["body"],
+            "bob forwarded a message from this room to #news:\nbig <news>"
+        );
+        assert_eq!(
+            forward["formatted_body"],
+            "bob forwarded a message from this room to #news:<blockquote>big &lt;news&gt;</blockquote>"
+        );
+        assert!(forward.get("m.relates_to").is_none());
+    }
+}
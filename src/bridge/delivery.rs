@@ -0,0 +1,283 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::db::{DeliveryDirection, PendingDelivery};
+
+/// Pending deliveries replayed per poll of the retry queue.
+pub const RETRY_BATCH_SIZE: i64 = 50;
+/// How often the retry queue is checked for due deliveries.
+pub const RETRY_POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Longest wait between two attempts, however many have failed.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// Wait before the next attempt once `attempts` have failed: `base_ms`
+/// after the first failure, doubling after each further one.
+pub fn retry_delay(base_ms: u64, attempts: i32) -> Duration {
+    let doublings = attempts.saturating_sub(1).clamp(0, 31) as u32;
+    Duration::from_millis(base_ms.saturating_mul(1u64 << doublings)).min(MAX_RETRY_DELAY)
+}
+
+/// The room a queued payload belongs to: the Matrix room for outbound
+/// Matrix events, the Discord channel for inbound Discord messages.
+pub fn delivery_room(direction: DeliveryDirection, payload: &Value) -> Option<String> {
+    let key = match direction {
+        DeliveryDirection::MatrixToDiscord => "room_id",
+        DeliveryDirection::DiscordToMatrix => "channel_id",
+    };
+    payload
+        .get(key)
+        .and_then(Value::as_str)
+        .map(ToOwned::to_owned)
+}
+
+/// A Discord event other than a message, queued while maintenance mode is
+/// on and replayed through its handler afterwards. Events of a channel carry
+/// its id as `channel_id` so they stay in order with its messages.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "discord_event", rename_all = "snake_case")]
+pub enum DiscordEvent {
+    Reaction {
+        channel_id: String,
+        message_id: String,
+        user_id: String,
+        emoji: String,
+        custom_emoji_id: Option<String>,
+    },
+    MessageDelete {
+        channel_id: String,
+        message_id: String,
+    },
+    ChannelUpdate {
+        channel_id: String,
+        name: String,
+        topic: Option<String>,
+        slowmode_seconds: u16,
+    },
+    SlowmodeChange {
+        channel_id: String,
+        slowmode_seconds: u16,
+    },
+    ChannelDelete {
+        channel_id: String,
+    },
+    ThreadDelete {
+        thread_id: String,
+    },
+    GuildUpdate {
+        guild_id: String,
+        name: String,
+        icon_url: Option<String>,
+    },
+    GuildDelete {
+        guild_id: String,
+    },
+    GuildBan {
+        guild_id: String,
+        user_id: String,
+        banned: bool,
+    },
+    UserUpdate {
+        user_id: String,
+        username: String,
+        avatar_url: Option<String>,
+    },
+    MemberAdd {
+        guild_id: String,
+        user_id: String,
+        display_name: String,
+        avatar_url: Option<String>,
+        roles: Vec<String>,
+    },
+    MemberUpdate {
+        guild_id: String,
+        user_id: String,
+        display_name: String,
+        avatar_url: Option<String>,
+        roles: Vec<String>,
+    },
+    MemberChange {
+        guild_id: String,
+        user_id: String,
+        old_name: String,
+        new_name: String,
+        avatar_changed: bool,
+    },
+    MemberRemove {
+        guild_id: String,
+        user_id: String,
+    },
+}
+
+impl DiscordEvent {
+    /// The queued event in a Discord payload, `None` for a queued message.
+    pub fn from_payload(payload: &Value) -> Option<serde_json::Result<Self>> {
+        payload
+            .get("discord_event")
+            .map(|_| serde_json::from_value(payload.clone()))
+    }
+}
+
+type RoomKey = (DeliveryDirection, String);
+/// A room's queued deliveries by `(created_at, id)`, with the time of their
+/// next attempt.
+type RoomQueue = BTreeMap<(DateTime<Utc>, String), DateTime<Utc>>;
+
+/// Keeps queued deliveries in order per room: a delivery may only go out
+/// once everything queued before it for the same room has, and new
+/// messages for a room with a backlog join the back of the queue.
+#[derive(Default)]
+pub struct DeliverySequencer {
+    queued: Mutex<HashMap<RoomKey, RoomQueue>>,
+}
+
+impl DeliverySequencer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn track(&self, delivery: &PendingDelivery) {
+        let Some(room) = delivery_room(delivery.direction, &delivery.payload) else {
+            return;
+        };
+        self.queued
+            .lock()
+            .entry((delivery.direction, room))
+            .or_default()
+            .insert(
+                (delivery.created_at, delivery.id.clone()),
+                delivery.next_attempt_at,
+            );
+    }
+
+    /// Whether anything is still queued for `room`.
+    pub fn has_backlog(&self, direction: DeliveryDirection, room: &str) -> bool {
+        self.queued
+            .lock()
+            .get(&(direction, room.to_string()))
+            .is_some_and(|queue| !queue.is_empty())
+    }
+
+    /// `None` when `delivery` is the oldest queued for its room and may be
+    /// attempted, otherwise the next attempt of the delivery it must wait
+    /// for.
+    pub fn blocked_until(&self, delivery: &PendingDelivery) -> Option<DateTime<Utc>> {
+        let room = delivery_room(delivery.direction, &delivery.payload)?;
+        let queued = self.queued.lock();
+        let ((created_at, id), next_attempt_at) =
+            queued.get(&(delivery.direction, room))?.first_key_value()?;
+        if *created_at == delivery.created_at && *id == delivery.id {
+            None
+        } else {
+            Some(*next_attempt_at)
+        }
+    }
+
+    pub fn rescheduled(&self, delivery: &PendingDelivery, next_attempt_at: DateTime<Utc>) {
+        let Some(room) = delivery_room(delivery.direction, &delivery.payload) else {
+            return;
+        };
+        if let Some(queue) = self.queued.lock().get_mut(&(delivery.direction, room))
+            && let Some(next) = queue.get_mut(&(delivery.created_at, delivery.id.clone()))
+        {
+            *next = next_attempt_at;
+        }
+    }
+
+    /// Forgets a delivery that was sent or given up on.
+    pub fn finished(&self, delivery: &PendingDelivery) {
+        let Some(room) = delivery_room(delivery.direction, &delivery.payload) else {
+            return;
+        };
+        let key = (delivery.direction, room);
+        let mut queued = self.queued.lock();
+        if let Some(queue) = queued.get_mut(&key) {
+            queue.remove(&(delivery.created_at, delivery.id.clone()));
+            if queue.is_empty() {
+                queued.remove(&key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+
+    use super::{DeliverySequencer, DiscordEvent, delivery_room, retry_delay};
+    use crate::db::{DeliveryDirection, PendingDelivery};
+
+    fn delivery(id: &str, channel_id: &str, created_secs: i64) -> PendingDelivery {
+        let created_at = Utc.timestamp_opt(created_secs, 0).unwrap();
+        PendingDelivery {
+            id: id.to_string(),
+            direction: DeliveryDirection::DiscordToMatrix,
+            payload: json!({ "channel_id": channel_id }),
+            attempts: 1,
+            next_attempt_at: created_at,
+            last_error: None,
+            created_at,
+        }
+    }
+
+    #[test]
+    fn newer_deliveries_wait_for_older_ones_in_the_same_room() {
+        let sequencer = DeliverySequencer::new();
+        let older = delivery("a", "1", 10);
+        let newer = delivery("b", "1", 20);
+        let other_room = delivery("c", "2", 30);
+        for queued in [&newer, &other_room, &older] {
+            sequencer.track(queued);
+        }
+
+        assert!(sequencer.has_backlog(DeliveryDirection::DiscordToMatrix, "1"));
+        assert!(!sequencer.has_backlog(DeliveryDirection::MatrixToDiscord, "1"));
+        assert_eq!(sequencer.blocked_until(&older), None);
+        assert_eq!(sequencer.blocked_until(&other_room), None);
+
+        let retry_at = Utc.timestamp_opt(99, 0).unwrap();
+        sequencer.rescheduled(&older, retry_at);
+        assert_eq!(sequencer.blocked_until(&newer), Some(retry_at));
+
+        sequencer.finished(&older);
+        assert_eq!(sequencer.blocked_until(&newer), None);
+        sequencer.finished(&newer);
+        assert!(!sequencer.has_backlog(DeliveryDirection::DiscordToMatrix, "1"));
+    }
+
+    #[test]
+    fn queued_discord_events_are_told_apart_from_messages() {
+        let event = DiscordEvent::MessageDelete {
+            channel_id: "1".to_string(),
+            message_id: "2".to_string(),
+        };
+        let payload = serde_json::to_value(&event).unwrap();
+        assert_eq!(payload["discord_event"], "message_delete");
+        assert_eq!(
+            delivery_room(DeliveryDirection::DiscordToMatrix, &payload).as_deref(),
+            Some("1")
+        );
+        assert_eq!(
+            DiscordEvent::from_payload(&payload).unwrap().unwrap(),
+            event
+        );
+        assert!(DiscordEvent::from_payload(&json!({ "channel_id": "1" })).is_none());
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_an_hour() {
+        assert_eq!(retry_delay(2000, 1), Duration::from_secs(2));
+        assert_eq!(retry_delay(2000, 2), Duration::from_secs(4));
+        assert_eq!(retry_delay(2000, 4), Duration::from_secs(16));
+        assert_eq!(retry_delay(2000, 0), Duration::from_secs(2));
+        assert_eq!(retry_delay(2000, 40), Duration::from_secs(3600));
+        assert_eq!(retry_delay(u64::MAX, 3), Duration::from_secs(3600));
+    }
+}
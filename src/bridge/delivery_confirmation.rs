@@ -0,0 +1,83 @@
+//! ✅ reactions the bridge puts on Matrix messages that reached Discord, in
+//! rooms that opted in with `!discord confirm on`. The reaction is redacted
+//! again when a later edit of the message fails to go out, so it always says
+//! whether Discord shows the message as the user last wrote it.
+
+use std::collections::{HashMap, VecDeque};
+
+use parking_lot::Mutex;
+
+/// The annotation key of the confirmation reaction.
+pub const CONFIRMATION_KEY: &str = "✅";
+/// Confirmations remembered for withdrawal; older ones are forgotten and
+/// stay on their messages.
+const MAX_TRACKED: usize = 10_000;
+
+/// The confirmation reaction of each recently delivered Matrix event.
+#[derive(Default)]
+pub struct DeliveryConfirmations {
+    state: Mutex<Tracked>,
+}
+
+#[derive(Default)]
+struct Tracked {
+    /// Reaction event ids keyed by the event they confirm.
+    reactions: HashMap<String, String>,
+    /// Confirmed events, oldest first.
+    order: VecDeque<String>,
+}
+
+impl DeliveryConfirmations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_confirmed(&self, event_id: &str) -> bool {
+        self.state.lock().reactions.contains_key(event_id)
+    }
+
+    /// Remembers that `reaction_id` confirms `event_id`.
+    pub fn record(&self, event_id: &str, reaction_id: &str) {
+        let mut state = self.state.lock();
+        if state
+            .reactions
+            .insert(event_id.to_string(), reaction_id.to_string())
+            .is_none()
+        {
+            state.order.push_back(event_id.to_string());
+        }
+        while state.order.len() > MAX_TRACKED {
+            if let Some(oldest) = state.order.pop_front() {
+                state.reactions.remove(&oldest);
+            }
+        }
+    }
+
+    /// Forgets and returns the reaction confirming `event_id`.
+    pub fn take(&self, event_id: &str) -> Option<String> {
+        let mut state = self.state.lock();
+        let reaction_id = state.reactions.remove(event_id)?;
+        state.order.retain(|tracked| tracked != event_id);
+        Some(reaction_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeliveryConfirmations, MAX_TRACKED};
+
+    #[test]
+    fn confirmations_are_taken_once_and_bounded() {
+        let confirmations = DeliveryConfirmations::new();
+        confirmations.record("$a", "$reaction_a");
+        assert!(confirmations.is_confirmed("$a"));
+        assert_eq!(confirmations.take("$a").as_deref(), Some("$reaction_a"));
+        assert_eq!(confirmations.take("$a"), None);
+
+        for i in 0..=MAX_TRACKED {
+            confirmations.record(&format!("${i}"), "$reaction");
+        }
+        assert!(!confirmations.is_confirmed("$0"));
+        assert!(confirmations.is_confirmed(&format!("${MAX_TRACKED}")));
+    }
+}
@@ -0,0 +1,98 @@
+//! Backfill behind `--hide-ghosts-from-directory`: hides the ghosts that
+//! were registered before `ghosts.hide_from_directory` was turned on.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use anyhow::{Result, bail};
+use tracing::warn;
+
+use crate::db::DatabaseManager;
+use crate::matrix::MatrixAppservice;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GhostDirectoryReport {
+    pub rooms: usize,
+    pub ghosts: usize,
+    pub hidden: usize,
+    pub failed: usize,
+}
+
+impl fmt::Display for GhostDirectoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "found {} ghosts in {} bridged rooms: {} hidden from the user directory, {} failed",
+            self.ghosts, self.rooms, self.hidden, self.failed
+        )
+    }
+}
+
+/// Hides every ghost that is a member of a bridged room.
+pub async fn hide_existing_ghosts(
+    matrix: &MatrixAppservice,
+    db: &DatabaseManager,
+) -> Result<GhostDirectoryReport> {
+    let config = matrix.config();
+    if !config.ghosts.hide_from_directory {
+        bail!("set ghosts.hide_from_directory and homeserver_admin.token to hide ghosts");
+    }
+
+    let rooms = db.room_store().list_room_mappings(i64::MAX, 0).await?;
+    let bot_user_id = matrix.bot_user_id();
+    let mut ghosts = BTreeSet::new();
+    for room in &rooms {
+        match matrix.get_room_members(&room.matrix_room_id).await {
+            Ok(members) => ghosts.extend(bridge_ghosts(members, &bot_user_id, |user_id| {
+                matrix.is_namespaced_user(user_id)
+            })),
+            Err(err) => warn!("failed to list members of {}: {}", room.matrix_room_id, err),
+        }
+    }
+
+    let mut report = GhostDirectoryReport {
+        rooms: rooms.len(),
+        ghosts: ghosts.len(),
+        ..Default::default()
+    };
+    for ghost in &ghosts {
+        match matrix.hide_ghost_from_directory(ghost).await {
+            Ok(true) => report.hidden += 1,
+            Ok(false) => bail!("the homeserver does not support hiding users from the directory"),
+            Err(err) => {
+                warn!("{}", err);
+                report.failed += 1;
+            }
+        }
+    }
+    Ok(report)
+}
+
+fn bridge_ghosts(
+    members: Vec<String>,
+    bot_user_id: &str,
+    is_namespaced: impl Fn(&str) -> bool,
+) -> Vec<String> {
+    members
+        .into_iter()
+        .filter(|user_id| user_id != bot_user_id && is_namespaced(user_id))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::bridge_ghosts;
+
+    #[test]
+    fn only_namespaced_ghosts_are_collected() {
+        let members = vec![
+            "@_discord_1:example.org".to_string(),
+            "@_discord_bot:example.org".to_string(),
+            "@alice:example.org".to_string(),
+        ];
+        let ghosts = bridge_ghosts(members, "@_discord_bot:example.org", |user_id| {
+            user_id.starts_with("@_discord_")
+        });
+        assert_eq!(ghosts, ["@_discord_1:example.org"]);
+    }
+}
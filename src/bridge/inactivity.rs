@@ -0,0 +1,123 @@
+//! Unbridging of rooms nothing was bridged through for
+//! `bridge.inactive_rooms.unbridge_after_days`. Rooms are warned first and
+//! keep at least `warn_days_before` days to become active again. Warnings
+//! are kept in memory, so after a restart a room is warned anew.
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::config::InactiveRoomsConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InactivityAction {
+    /// Active enough; any earlier warning no longer applies.
+    Active,
+    Warn,
+    /// Warned, but the notice period has not run out yet.
+    Wait,
+    Unbridge,
+}
+
+/// What to do with a room last active at `last_active`, given when it was
+/// warned, if it was.
+pub fn inactivity_action(
+    policy: &InactiveRoomsConfig,
+    last_active: DateTime<Utc>,
+    warned_at: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> InactivityAction {
+    if policy.unbridge_after_days == 0 {
+        return InactivityAction::Active;
+    }
+    let unbridge_after = TimeDelta::days(policy.unbridge_after_days.into());
+    let notice = TimeDelta::days(
+        policy
+            .warn_days_before
+            .min(policy.unbridge_after_days)
+            .into(),
+    );
+    let idle = now - last_active;
+    // A warning sent before the last activity is stale.
+    let warned_at = warned_at.filter(|&warned_at| warned_at > last_active);
+
+    if idle < unbridge_after - notice {
+        return InactivityAction::Active;
+    }
+    match warned_at {
+        None if notice > TimeDelta::zero() => InactivityAction::Warn,
+        Some(warned_at) if now - warned_at < notice => InactivityAction::Wait,
+        _ if idle >= unbridge_after => InactivityAction::Unbridge,
+        _ => InactivityAction::Wait,
+    }
+}
+
+pub fn inactivity_warning(policy: &InactiveRoomsConfig) -> String {
+    format!(
+        "Nothing has been bridged here for a while. This room will be unbridged from Discord in {} day(s) unless someone posts.",
+        policy.warn_days_before.min(policy.unbridge_after_days)
+    )
+}
+
+pub fn inactivity_unbridge_notice(policy: &InactiveRoomsConfig) -> String {
+    format!(
+        "This room is being unbridged from Discord after {} day(s) without bridged messages.",
+        policy.unbridge_after_days
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeDelta, Utc};
+
+    use super::{InactivityAction, inactivity_action};
+    use crate::config::InactiveRoomsConfig;
+
+    #[test]
+    fn rooms_are_warned_then_unbridged_after_the_notice_period() {
+        let policy = InactiveRoomsConfig {
+            unbridge_after_days: 30,
+            warn_days_before: 7,
+        };
+        let now = Utc::now();
+        let days = TimeDelta::days;
+
+        assert_eq!(
+            inactivity_action(&policy, now - days(22), None, now),
+            InactivityAction::Active
+        );
+        assert_eq!(
+            inactivity_action(&policy, now - days(23), None, now),
+            InactivityAction::Warn
+        );
+        // Warned late (e.g. after a restart): the full notice still applies.
+        assert_eq!(
+            inactivity_action(&policy, now - days(40), Some(now - days(2)), now),
+            InactivityAction::Wait
+        );
+        assert_eq!(
+            inactivity_action(&policy, now - days(30), Some(now - days(7)), now),
+            InactivityAction::Unbridge
+        );
+        // Activity after the warning makes it stale.
+        assert_eq!(
+            inactivity_action(&policy, now - days(25), Some(now - days(26)), now),
+            InactivityAction::Warn
+        );
+
+        let disabled = InactiveRoomsConfig {
+            unbridge_after_days: 0,
+            warn_days_before: 7,
+        };
+        assert_eq!(
+            inactivity_action(&disabled, now - days(365), None, now),
+            InactivityAction::Active
+        );
+        let no_warning = InactiveRoomsConfig {
+            unbridge_after_days: 30,
+            warn_days_before: 0,
+        };
+        assert_eq!(
+            inactivity_action(&no_warning, now - days(30), None, now),
+            InactivityAction::Unbridge
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use serde_json::Value;
+
 use super::message_flow::OutboundMatrixMessage;
 use crate::db::{MessageMapping, RoomMapping};
 use crate::discord::ModerationAction;
@@ -17,6 +19,19 @@ pub(crate) struct TypingRequest {
     pub(crate) timeout_ms: Option<u64>,
 }
 
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct PinChanges {
+    pub(crate) pinned: Vec<String>,
+    pub(crate) unpinned: Vec<String>,
+}
+
+/// An `m.annotation` relation: `key` was reacted onto `event_id`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MatrixReaction {
+    pub(crate) event_id: String,
+    pub(crate) key: String,
+}
+
 pub(crate) const DISCORD_TYPING_TIMEOUT_MS: u64 = 4000;
 const MAX_PREVIEW_CHARS: usize = 120;
 
@@ -30,6 +45,9 @@ pub(crate) fn preview_text(value: &str) -> String {
     }
 }
 
+/// Points replies and edits at the bridged Matrix events. An edit of a
+/// message that was never bridged is sent as a new message marked
+/// "(edited)" instead.
 pub(crate) fn apply_message_relation_mappings(
     outbound: &mut OutboundMatrixMessage,
     reply_mapping: Option<&MessageMapping>,
@@ -39,8 +57,13 @@ pub(crate) fn apply_message_relation_mappings(
         outbound.reply_to = Some(link.matrix_event_id.clone());
     }
 
-    if let Some(link) = edit_mapping {
-        outbound.edit_of = Some(link.matrix_event_id.clone());
+    match edit_mapping {
+        Some(link) => outbound.edit_of = Some(link.matrix_event_id.clone()),
+        None if outbound.edit_of.is_some() => {
+            outbound.edit_of = None;
+            outbound.prefix_body("(edited) ");
+        }
+        None => {}
     }
 }
 
@@ -77,6 +100,63 @@ pub(crate) fn should_forward_discord_typing(
     !disable_typing_notifications && room_mapping.is_some()
 }
 
+/// Compares the `pinned` lists of two `m.room.pinned_events` contents.
+pub(crate) fn diff_pinned_events(previous: Option<&Value>, current: Option<&Value>) -> PinChanges {
+    let pinned_ids = |content: Option<&Value>| -> Vec<String> {
+        content
+            .and_then(|content| content.get("pinned"))
+            .and_then(Value::as_array)
+            .map(|ids| {
+                ids.iter()
+                    .filter_map(Value::as_str)
+                    .map(ToOwned::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let previous = pinned_ids(previous);
+    let current = pinned_ids(current);
+    PinChanges {
+        pinned: current
+            .iter()
+            .filter(|id| !previous.contains(id))
+            .cloned()
+            .collect(),
+        unpinned: previous
+            .iter()
+            .filter(|id| !current.contains(id))
+            .cloned()
+            .collect(),
+    }
+}
+
+/// Reads the annotation out of an `m.reaction` content.
+pub(crate) fn parse_matrix_reaction(content: Option<&Value>) -> Option<MatrixReaction> {
+    let relation = content?.get("m.relates_to")?;
+    if relation.get("rel_type").and_then(Value::as_str) != Some("m.annotation") {
+        return None;
+    }
+    let event_id = relation.get("event_id").and_then(Value::as_str)?;
+    let key = relation.get("key").and_then(Value::as_str)?.trim();
+    if key.is_empty() {
+        return None;
+    }
+    Some(MatrixReaction {
+        event_id: event_id.to_string(),
+        key: key.to_string(),
+    })
+}
+
+/// The event an `m.room.redaction` removes. Room versions before 11 carry it
+/// at the top level, which the appservice handler copies into the content.
+pub(crate) fn parse_matrix_redaction(content: Option<&Value>) -> Option<String> {
+    content?
+        .get("redacts")
+        .and_then(Value::as_str)
+        .filter(|event_id| !event_id.is_empty())
+        .map(ToOwned::to_owned)
+}
+
 pub(crate) fn action_keyword(action: &ModerationAction) -> &'static str {
     match action {
         ModerationAction::Kick => "kick",
@@ -88,12 +168,15 @@ pub(crate) fn action_keyword(action: &ModerationAction) -> &'static str {
 #[cfg(test)]
 mod tests {
     use chrono::Utc;
+    use serde_json::json;
 
     use super::{
-        OutboundMatrixMessage, action_keyword, apply_message_relation_mappings,
-        build_discord_delete_redaction_request, build_discord_typing_request,
-        discord_delete_redaction_request, preview_text, should_forward_discord_typing,
+        MatrixReaction, OutboundMatrixMessage, action_keyword, apply_message_relation_mappings,
+        build_discord_delete_redaction_request, build_discord_typing_request, diff_pinned_events,
+        discord_delete_redaction_request, parse_matrix_reaction, parse_matrix_redaction,
+        preview_text, should_forward_discord_typing,
     };
+    use crate::config::AttachmentPolicy;
     use crate::db::{MessageMapping, RoomMapping};
     use crate::discord::ModerationAction;
 
@@ -103,6 +186,7 @@ mod tests {
             discord_message_id: discord_message_id.to_string(),
             matrix_room_id: "!room:example.org".to_string(),
             matrix_event_id: matrix_event_id.to_string(),
+            discord_channel_id: None,
             created_at: Utc::now(),
             updated_at: Utc::now(),
         }
@@ -114,7 +198,12 @@ mod tests {
             body: "hello".to_string(),
             reply_to: Some("discord-reply-id".to_string()),
             edit_of: Some("discord-edit-id".to_string()),
+            thread_root: None,
             attachments: Vec::new(),
+            origin_server_ts: None,
+            formatted_body: None,
+            attribution_badge: false,
+            attachment_policy: AttachmentPolicy::Reupload,
         };
 
         let reply = mapping("discord-reply-id", "$matrix-reply");
@@ -131,14 +220,39 @@ mod tests {
         let mut outbound = OutboundMatrixMessage {
             body: "hello".to_string(),
             reply_to: Some("discord-reply-id".to_string()),
-            edit_of: Some("discord-edit-id".to_string()),
+            edit_of: None,
+            thread_root: None,
             attachments: Vec::new(),
+            origin_server_ts: None,
+            formatted_body: None,
+            attribution_badge: false,
+            attachment_policy: AttachmentPolicy::Reupload,
         };
 
         apply_message_relation_mappings(&mut outbound, None, None);
 
         assert_eq!(outbound.reply_to, Some("discord-reply-id".to_string()));
-        assert_eq!(outbound.edit_of, Some("discord-edit-id".to_string()));
+        assert_eq!(outbound.body, "hello");
+    }
+
+    #[test]
+    fn apply_message_relation_mappings_sends_unmapped_edits_as_new_messages() {
+        let mut outbound = OutboundMatrixMessage {
+            body: "hello".to_string(),
+            reply_to: None,
+            edit_of: Some("discord-edit-id".to_string()),
+            thread_root: None,
+            attachments: Vec::new(),
+            origin_server_ts: None,
+            formatted_body: None,
+            attribution_badge: false,
+            attachment_policy: AttachmentPolicy::Reupload,
+        };
+
+        apply_message_relation_mappings(&mut outbound, None, None);
+
+        assert_eq!(outbound.edit_of, None);
+        assert_eq!(outbound.body, "(edited) hello");
     }
 
     #[test]
@@ -193,6 +307,9 @@ mod tests {
             discord_channel_id: "123".to_string(),
             discord_channel_name: "general".to_string(),
             discord_guild_id: "456".to_string(),
+            created_by: None,
+            origin: None,
+            last_active_at: None,
             created_at: Utc::now(),
             updated_at: Utc::now(),
         }
@@ -238,4 +355,56 @@ mod tests {
         assert_eq!(action_keyword(&ModerationAction::Ban), "ban");
         assert_eq!(action_keyword(&ModerationAction::Unban), "unban");
     }
+
+    #[test]
+    fn parse_matrix_reaction_reads_annotations_only() {
+        let reaction = json!({
+            "m.relates_to": { "rel_type": "m.annotation", "event_id": "$a", "key": "👍" }
+        });
+        assert_eq!(
+            parse_matrix_reaction(Some(&reaction)),
+            Some(MatrixReaction {
+                event_id: "$a".to_string(),
+                key: "👍".to_string(),
+            })
+        );
+
+        let reply = json!({
+            "m.relates_to": { "rel_type": "m.thread", "event_id": "$a", "key": "👍" }
+        });
+        assert_eq!(parse_matrix_reaction(Some(&reply)), None);
+        let blank = json!({
+            "m.relates_to": { "rel_type": "m.annotation", "event_id": "$a", "key": " " }
+        });
+        assert_eq!(parse_matrix_reaction(Some(&blank)), None);
+        assert_eq!(parse_matrix_reaction(None), None);
+    }
+
+    #[test]
+    fn parse_matrix_redaction_reads_the_redacted_event() {
+        assert_eq!(
+            parse_matrix_redaction(Some(&json!({ "redacts": "$a", "reason": "spam" }))),
+            Some("$a".to_string())
+        );
+        assert_eq!(
+            parse_matrix_redaction(Some(&json!({ "redacts": "" }))),
+            None
+        );
+        assert_eq!(parse_matrix_redaction(Some(&json!({}))), None);
+        assert_eq!(parse_matrix_redaction(None), None);
+    }
+
+    #[test]
+    fn diff_pinned_events_reports_added_and_removed_pins() {
+        let previous = json!({ "pinned": ["$a", "$b"] });
+        let current = json!({ "pinned": ["$b", "$c"] });
+
+        let changes = diff_pinned_events(Some(&previous), Some(&current));
+        assert_eq!(changes.pinned, vec!["$c".to_string()]);
+        assert_eq!(changes.unpinned, vec!["$a".to_string()]);
+
+        let first = diff_pinned_events(None, Some(&current));
+        assert_eq!(first.pinned, vec!["$b".to_string(), "$c".to_string()]);
+        assert!(first.unpinned.is_empty());
+    }
 }
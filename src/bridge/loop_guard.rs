@@ -0,0 +1,83 @@
+//! Loop detection for rooms that more than one bridge relays into, so a
+//! message does not bounce between Discord, Matrix and a third network.
+
+use serde_json::{Value, json};
+
+/// Content field every Matrix event the bridge sends is tagged with.
+pub const BRIDGE_TAG: &str = "dev.palpo.bridge";
+
+/// Content fields other bridges are known to tag their events with.
+const FOREIGN_BRIDGE_TAGS: &[&str] = &["fi.mau.bridge", "uk.half-shot.discord", "de.sorunome.irc"];
+
+/// Webhook name suffixes Matrix bridges use to mark relayed Matrix users.
+const MATRIX_RELAY_SUFFIXES: &[&str] = &["[m]", "(matrix)", "[matrix]"];
+
+/// The value stored under [`BRIDGE_TAG`] on outgoing events.
+pub fn bridge_tag() -> Value {
+    json!({
+        "protocol": "discord",
+        "bridge": env!("CARGO_PKG_NAME"),
+    })
+}
+
+/// Returns the tag that marks `content` as relayed by a bridge, ours or a
+/// recognized foreign one.
+pub fn bridge_tag_of(content: &Value) -> Option<&'static str> {
+    std::iter::once(BRIDGE_TAG)
+        .chain(FOREIGN_BRIDGE_TAGS.iter().copied())
+        .find(|tag| content.get(tag).is_some())
+}
+
+/// Whether a foreign webhook's display name looks like a Matrix user relayed
+/// into Discord by another bridge: a full Matrix id or a Matrix suffix.
+pub fn looks_like_matrix_relay(webhook_name: &str) -> bool {
+    let name = webhook_name.trim().to_ascii_lowercase();
+    if MATRIX_RELAY_SUFFIXES
+        .iter()
+        .any(|suffix| name.ends_with(suffix))
+    {
+        return true;
+    }
+    name.split(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | '<' | '>'))
+        .any(is_matrix_user_id)
+}
+
+fn is_matrix_user_id(word: &str) -> bool {
+    let Some(rest) = word.strip_prefix('@') else {
+        return false;
+    };
+    match rest.split_once(':') {
+        Some((localpart, server)) => {
+            !localpart.is_empty() && server.contains('.') && !server.ends_with('.')
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{BRIDGE_TAG, bridge_tag, bridge_tag_of, looks_like_matrix_relay};
+
+    #[test]
+    fn tagged_content_is_recognized() {
+        let mut content = json!({ "msgtype": "m.text", "body": "hi" });
+        assert_eq!(bridge_tag_of(&content), None);
+        content[BRIDGE_TAG] = bridge_tag();
+        assert_eq!(bridge_tag_of(&content), Some(BRIDGE_TAG));
+        let foreign = json!({ "body": "hi", "fi.mau.bridge": {} });
+        assert_eq!(bridge_tag_of(&foreign), Some("fi.mau.bridge"));
+    }
+
+    #[test]
+    fn matrix_relay_names_are_detected() {
+        assert!(looks_like_matrix_relay("alice [m]"));
+        assert!(looks_like_matrix_relay("Alice (Matrix)"));
+        assert!(looks_like_matrix_relay("Alice (@alice:example.org)"));
+        assert!(looks_like_matrix_relay("@alice:example.org"));
+        assert!(!looks_like_matrix_relay("Alice"));
+        assert!(!looks_like_matrix_relay("@everyone"));
+        assert!(!looks_like_matrix_relay("GitHub"));
+    }
+}
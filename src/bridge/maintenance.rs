@@ -0,0 +1,237 @@
+//! Maintenance mode: bridging pauses while both connections stay up.
+//! Messages, reactions, redactions, pins, room and channel changes and guild
+//! and member events wait in the delivery queue until it is switched off
+//! again; typing is dropped. The bridge admin toggles it with `!bridgectl`; the
+//! admin API has the same switch. It is not persisted, so a restart ends it.
+//! `!bridgectl list` shows every bridged room with where it came from and
+//! `!bridgectl exempt` keeps a room from being unbridged for inactivity.
+//! `!bridgectl replay` re-bridges Discord messages missed during an outage.
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+
+use crate::bridge::replay::parse_since;
+use crate::db::RoomMapping;
+use crate::parsers::parse_prefixed_command;
+
+pub const BRIDGECTL_PREFIX: &str = "!bridgectl";
+
+const BRIDGECTL_USAGE: &str = "Usage: `!bridgectl maintenance [on|off]`, `!bridgectl list`, `!bridgectl exempt <room id> on|off` or `!bridgectl replay --room <room id> --since <RFC 3339 time or Unix seconds>`";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BridgectlCommand {
+    MaintenanceStatus,
+    Maintenance {
+        enabled: bool,
+    },
+    List,
+    /// Exempt a room from, or subject it again to, inactivity unbridging.
+    Exempt {
+        matrix_room_id: String,
+        exempt: bool,
+    },
+    /// Bridge the Discord messages since `since` that have no mapping yet.
+    Replay {
+        matrix_room_id: String,
+        since: DateTime<Utc>,
+    },
+    Reply(String),
+}
+
+/// Parses a `!bridgectl` command; `None` when `body` is not one.
+pub fn parse_bridgectl(body: &str) -> Option<BridgectlCommand> {
+    let parsed = parse_prefixed_command(BRIDGECTL_PREFIX, body)?;
+    let command = match (parsed.command.as_str(), parsed.args.as_slice()) {
+        ("maintenance", []) => BridgectlCommand::MaintenanceStatus,
+        ("maintenance", [state]) if state == "on" => {
+            BridgectlCommand::Maintenance { enabled: true }
+        }
+        ("maintenance", [state]) if state == "off" => {
+            BridgectlCommand::Maintenance { enabled: false }
+        }
+        ("list", []) => BridgectlCommand::List,
+        ("exempt", [room, state]) if room.starts_with('!') && (state == "on" || state == "off") => {
+            BridgectlCommand::Exempt {
+                matrix_room_id: room.clone(),
+                exempt: state == "on",
+            }
+        }
+        ("replay", [room_flag, room, since_flag, since])
+            if room_flag == "--room" && since_flag == "--since" && room.starts_with('!') =>
+        {
+            match parse_since(since) {
+                Some(since) => BridgectlCommand::Replay {
+                    matrix_room_id: room.clone(),
+                    since,
+                },
+                None => BridgectlCommand::Reply(BRIDGECTL_USAGE.to_string()),
+            }
+        }
+        _ => BridgectlCommand::Reply(BRIDGECTL_USAGE.to_string()),
+    };
+    Some(command)
+}
+
+/// Whether maintenance mode is on, and since when.
+#[derive(Default)]
+pub struct MaintenanceMode {
+    since: Mutex<Option<DateTime<Utc>>>,
+}
+
+impl MaintenanceMode {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.since.lock().is_some()
+    }
+
+    pub fn since(&self) -> Option<DateTime<Utc>> {
+        *self.since.lock()
+    }
+
+    /// Switches maintenance on or off; returns whether that changed
+    /// anything.
+    pub fn set(&self, enabled: bool) -> bool {
+        let mut since = self.since.lock();
+        if since.is_some() == enabled {
+            return false;
+        }
+        *since = enabled.then(Utc::now);
+        true
+    }
+}
+
+pub fn maintenance_toggle_reply(enabled: bool, changed: bool) -> String {
+    match (enabled, changed) {
+        (true, true) => "Maintenance mode is on; bridging is paused and messages are queued.",
+        (false, true) => "Maintenance mode is off; queued messages are being delivered.",
+        (true, false) => "Maintenance mode is already on.",
+        (false, false) => "Maintenance mode is already off.",
+    }
+    .to_string()
+}
+
+pub fn maintenance_status_reply(since: Option<DateTime<Utc>>, queued: i64) -> String {
+    match since {
+        Some(since) => format!(
+            "Maintenance mode is on since {}; {} message(s) queued.",
+            since.to_rfc3339(),
+            queued
+        ),
+        None => "Maintenance mode is off.".to_string(),
+    }
+}
+
+/// One line per bridged room: the rooms it joins, who bridged it and how,
+/// and when it was last used.
+pub fn bridged_rooms_reply(rooms: &[RoomMapping]) -> String {
+    if rooms.is_empty() {
+        return "No rooms are bridged.".to_string();
+    }
+    let mut reply = format!("{} bridged room(s):", rooms.len());
+    for room in rooms {
+        let origin = room
+            .origin
+            .map_or("unknown origin", |origin| origin.as_str());
+        let creator = room.created_by.as_deref().unwrap_or("unknown");
+        let last_active = room
+            .last_active_at
+            .map_or_else(|| "never".to_string(), |at| at.to_rfc3339());
+        reply.push_str(&format!(
+            "\n- {} <-> #{} ({} in guild {}): {} by {} on {}, last active {}",
+            room.matrix_room_id,
+            room.discord_channel_name,
+            room.discord_channel_id,
+            room.discord_guild_id,
+            origin,
+            creator,
+            room.created_at.format("%Y-%m-%d"),
+            last_active
+        ));
+    }
+    reply
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::{BridgectlCommand, MaintenanceMode, bridged_rooms_reply, parse_bridgectl};
+    use crate::db::{RoomMapping, RoomOrigin};
+
+    #[test]
+    fn bridgectl_toggles_maintenance_once() {
+        assert_eq!(
+            parse_bridgectl("!bridgectl maintenance on"),
+            Some(BridgectlCommand::Maintenance { enabled: true })
+        );
+        assert_eq!(
+            parse_bridgectl("!bridgectl maintenance"),
+            Some(BridgectlCommand::MaintenanceStatus)
+        );
+        assert!(matches!(
+            parse_bridgectl("!bridgectl maintenance later"),
+            Some(BridgectlCommand::Reply(_))
+        ));
+        assert_eq!(parse_bridgectl("!discord help"), None);
+
+        let mode = MaintenanceMode::new();
+        assert!(!mode.set(false));
+        assert!(mode.set(true));
+        assert!(!mode.set(true));
+        assert!(mode.is_active() && mode.since().is_some());
+        assert!(mode.set(false));
+        assert!(!mode.is_active());
+    }
+
+    #[test]
+    fn list_shows_origin_creator_and_activity() {
+        assert_eq!(
+            parse_bridgectl("!bridgectl list"),
+            Some(BridgectlCommand::List)
+        );
+        assert_eq!(
+            parse_bridgectl("!bridgectl exempt !a:x on"),
+            Some(BridgectlCommand::Exempt {
+                matrix_room_id: "!a:x".to_string(),
+                exempt: true
+            })
+        );
+        assert!(matches!(
+            parse_bridgectl("!bridgectl exempt a:x on"),
+            Some(BridgectlCommand::Reply(_))
+        ));
+        assert_eq!(
+            parse_bridgectl("!bridgectl replay --room !a:x --since 1714557600"),
+            Some(BridgectlCommand::Replay {
+                matrix_room_id: "!a:x".to_string(),
+                since: Utc.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap(),
+            })
+        );
+        assert!(matches!(
+            parse_bridgectl("!bridgectl replay --room !a:x --since soon"),
+            Some(BridgectlCommand::Reply(_))
+        ));
+        assert_eq!(bridged_rooms_reply(&[]), "No rooms are bridged.");
+
+        let created = Utc.with_ymd_and_hms(2024, 3, 1, 9, 0, 0).unwrap();
+        let room = RoomMapping {
+            id: 1,
+            matrix_room_id: "!a:x".to_string(),
+            discord_channel_id: "10".to_string(),
+            discord_channel_name: "general".to_string(),
+            discord_guild_id: "20".to_string(),
+            created_by: Some("@alice:x".to_string()),
+            origin: Some(RoomOrigin::SelfService),
+            last_active_at: None,
+            created_at: created,
+            updated_at: created,
+        };
+        assert_eq!(
+            bridged_rooms_reply(&[room]),
+            "1 bridged room(s):\n- !a:x <-> #general (10 in guild 20): self_service by @alice:x on 2024-03-01, last active never"
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+/// A user counts as frequently seen in a room after posting there within
+/// this window.
+const ACTIVE_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+/// At most `NOTICE_BURST` notices go to one room per `NOTICE_WINDOW`, so a
+/// bot renaming a whole guild cannot flood it.
+const NOTICE_BURST: usize = 3;
+const NOTICE_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// Decides which rooms hear about a Discord user's name or avatar change:
+/// only rooms the user recently posted in, and only within each room's
+/// notice budget.
+#[derive(Default)]
+pub struct MemberNoticeTracker {
+    last_seen: Mutex<HashMap<(String, String), Instant>>,
+    sent: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl MemberNoticeTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_message(&self, room_id: &str, discord_user_id: &str, now: Instant) {
+        let mut last_seen = self.last_seen.lock();
+        last_seen.insert((room_id.to_string(), discord_user_id.to_string()), now);
+        if last_seen.len() > 4096 {
+            last_seen.retain(|_, seen| now.saturating_duration_since(*seen) < ACTIVE_WINDOW);
+        }
+    }
+
+    /// The subset of `room_ids` that should get a notice about
+    /// `discord_user_id`. Each returned room has its budget charged.
+    pub fn notice_rooms(
+        &self,
+        discord_user_id: &str,
+        room_ids: &[String],
+        now: Instant,
+    ) -> Vec<String> {
+        let last_seen = self.last_seen.lock();
+        let mut sent = self.sent.lock();
+        room_ids
+            .iter()
+            .filter(|room_id| {
+                last_seen
+                    .get(&(room_id.to_string(), discord_user_id.to_string()))
+                    .is_some_and(|seen| now.saturating_duration_since(*seen) < ACTIVE_WINDOW)
+            })
+            .filter(|room_id| {
+                let history = sent.entry(room_id.to_string()).or_default();
+                while history
+                    .front()
+                    .is_some_and(|at| now.saturating_duration_since(*at) >= NOTICE_WINDOW)
+                {
+                    history.pop_front();
+                }
+                if history.len() >= NOTICE_BURST {
+                    return false;
+                }
+                history.push_back(now);
+                true
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+/// Notice text for a member whose display name and/or avatar changed, or
+/// `None` when nothing visible did.
+pub fn member_change_notice(
+    old_name: &str,
+    new_name: &str,
+    avatar_changed: bool,
+) -> Option<String> {
+    match (old_name != new_name, avatar_changed) {
+        (true, true) => Some(format!(
+            "{} is now known as {} and changed their avatar",
+            old_name, new_name
+        )),
+        (true, false) => Some(format!("{} is now known as {}", old_name, new_name)),
+        (false, true) => Some(format!("{} changed their avatar", new_name)),
+        (false, false) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{MemberNoticeTracker, member_change_notice};
+
+    #[test]
+    fn notices_go_to_recent_rooms_within_budget() {
+        let tracker = MemberNoticeTracker::new();
+        let start = Instant::now();
+        let rooms = vec!["!a:x".to_string(), "!b:x".to_string()];
+
+        tracker.record_message("!a:x", "1", start);
+        assert_eq!(tracker.notice_rooms("1", &rooms, start), ["!a:x"]);
+        assert!(tracker.notice_rooms("2", &rooms, start).is_empty());
+
+        for user in ["2", "3", "4"] {
+            tracker.record_message("!a:x", user, start);
+        }
+        assert_eq!(tracker.notice_rooms("2", &rooms, start).len(), 1);
+        assert_eq!(tracker.notice_rooms("3", &rooms, start).len(), 1);
+        assert!(tracker.notice_rooms("4", &rooms, start).is_empty());
+        assert_eq!(
+            tracker
+                .notice_rooms("4", &rooms, start + Duration::from_secs(600))
+                .len(),
+            1
+        );
+
+        let next_day = start + Duration::from_secs(24 * 60 * 60);
+        assert!(tracker.notice_rooms("1", &rooms, next_day).is_empty());
+    }
+
+    #[test]
+    fn notice_describes_what_changed() {
+        assert_eq!(
+            member_change_notice("alice", "bob", false).as_deref(),
+            Some("alice is now known as bob")
+        );
+        assert_eq!(
+            member_change_notice("alice", "alice", true).as_deref(),
+            Some("alice changed their avatar")
+        );
+        assert_eq!(
+            member_change_notice("alice", "bob", true).as_deref(),
+            Some("alice is now known as bob and changed their avatar")
+        );
+        assert_eq!(member_change_notice("alice", "alice", false), None);
+    }
+}
@@ -0,0 +1,66 @@
+//! Joining the ghosts of a guild's existing members into a room that
+//! auto-invites members. Members are fetched in batches of
+//! `limits.room_ghost_join_batch_size`, with `limits.room_ghost_join_delay`
+//! plus up to `limits.room_ghost_join_jitter` milliseconds between batches.
+//! Progress is saved after every batch so a restart resumes the sync, and it
+//! stops once `bridge.user_limit` ghosts were joined.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use crate::config::LimitsConfig;
+
+/// Largest page the Discord member list endpoint returns.
+pub const MAX_MEMBER_BATCH: usize = 1000;
+
+pub fn batch_size(limits: &LimitsConfig) -> usize {
+    limits.room_ghost_join_batch_size.clamp(1, MAX_MEMBER_BATCH)
+}
+
+/// The pause before the next batch, for a random `seed`.
+pub fn batch_pause(limits: &LimitsConfig, seed: u64) -> Duration {
+    let jitter = seed % limits.room_ghost_join_jitter.saturating_add(1);
+    Duration::from_millis(limits.room_ghost_join_delay.saturating_add(jitter))
+}
+
+/// A seed for [`batch_pause`]; `RandomState` is randomly keyed per instance.
+pub fn jitter_seed() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pauses_stay_within_the_jitter_window() {
+        let limits = LimitsConfig {
+            room_ghost_join_delay: 500,
+            room_ghost_join_jitter: 100,
+            ..LimitsConfig::default()
+        };
+        for seed in [0, 1, 100, 101, u64::MAX] {
+            let pause = batch_pause(&limits, seed);
+            assert!(pause >= Duration::from_millis(500));
+            assert!(pause <= Duration::from_millis(600));
+        }
+
+        let fixed = LimitsConfig {
+            room_ghost_join_jitter: 0,
+            ..limits
+        };
+        assert_eq!(batch_pause(&fixed, 12345), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn batch_size_is_clamped_to_the_discord_page() {
+        let mut limits = LimitsConfig {
+            room_ghost_join_batch_size: 0,
+            ..LimitsConfig::default()
+        };
+        assert_eq!(batch_size(&limits), 1);
+        limits.room_ghost_join_batch_size = 5000;
+        assert_eq!(batch_size(&limits), MAX_MEMBER_BATCH);
+    }
+}
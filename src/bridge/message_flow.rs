@@ -1,18 +1,54 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 use serde_json::Value;
 
+use super::content_redaction::ContentRedactor;
+use crate::config::AttachmentPolicy;
+use crate::db::{EmojiUsageKind, RoomStore};
 use crate::discord::{DiscordClient, DiscordEmbed, EmbedAuthor, EmbedFooter};
 use crate::emoji::EmojiHandler;
-use crate::matrix::{MatrixAppservice, MatrixEvent};
-use crate::parsers::{DiscordToMatrixConverter, MatrixToDiscordConverter, MessageUtils};
+use crate::matrix::{MatrixAppservice, MatrixEvent, RelatesTo};
+use crate::media::ensure_filename_extension;
+use crate::parsers::{
+    DiscordToMatrixConverter, MatrixEmoticon, MatrixToDiscordConverter, MatrixUserPill,
+    MessageUtils, formatter, location,
+};
 
 const ATTACHMENT_TYPES: &[&str] = &["m.image", "m.audio", "m.video", "m.file", "m.sticker"];
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MessageRelation {
-    Reply { event_id: String },
-    Replace { event_id: String },
+    Reply {
+        event_id: String,
+    },
+    Replace {
+        event_id: String,
+    },
+    /// A message in the thread rooted at `root_event_id`, replying to
+    /// `reply_to` unless the reply is only the fallback for clients without
+    /// thread support.
+    Thread {
+        root_event_id: String,
+        reply_to: Option<String>,
+    },
+}
+
+impl MessageRelation {
+    pub fn thread_root(&self) -> Option<&str> {
+        match self {
+            Self::Thread { root_event_id, .. } => Some(root_event_id),
+            _ => None,
+        }
+    }
+
+    fn reply_to(&self) -> Option<String> {
+        match self {
+            Self::Reply { event_id } => Some(event_id.clone()),
+            Self::Thread { reply_to, .. } => reply_to.clone(),
+            Self::Replace { .. } => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -30,6 +66,7 @@ pub struct MatrixInboundMessage {
     pub body: String,
     pub relation: Option<MessageRelation>,
     pub attachments: Vec<MessageAttachment>,
+    pub emoticons: Vec<MatrixEmoticon>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -40,6 +77,7 @@ pub struct DiscordInboundMessage {
     pub attachments: Vec<String>,
     pub reply_to: Option<String>,
     pub edit_of: Option<String>,
+    pub sender_can_mention_everyone: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -48,8 +86,11 @@ pub struct OutboundDiscordMessage {
     pub reply_to: Option<String>,
     pub edit_of: Option<String>,
     pub attachments: Vec<String>,
+    /// File names the Matrix sender gave the attachments, keyed by URL.
+    pub attachment_names: BTreeMap<String, String>,
     pub embed: Option<DiscordEmbed>,
     pub use_embed: bool,
+    pub attachment_policy: AttachmentPolicy,
 }
 
 impl OutboundDiscordMessage {
@@ -59,8 +100,10 @@ impl OutboundDiscordMessage {
             reply_to: None,
             edit_of: None,
             attachments: Vec::new(),
+            attachment_names: BTreeMap::new(),
             embed: None,
             use_embed: false,
+            attachment_policy: AttachmentPolicy::Reupload,
         }
     }
 
@@ -93,10 +136,37 @@ pub struct OutboundMatrixMessage {
     pub body: String,
     pub reply_to: Option<String>,
     pub edit_of: Option<String>,
+    /// Root event of the Matrix thread the message goes in, for messages
+    /// in a Discord thread.
+    pub thread_root: Option<String>,
     pub attachments: Vec<String>,
+    /// Original send time in milliseconds, set when the message is replayed
+    /// late so Matrix shows it where it belongs in the conversation.
+    pub origin_server_ts: Option<i64>,
+    /// HTML of `body`, rendered from the Discord markdown; `None` renders
+    /// `body` itself.
+    pub formatted_body: Option<String>,
+    /// End the formatted body with a "via Discord" badge.
+    pub attribution_badge: bool,
+    pub attachment_policy: AttachmentPolicy,
 }
 
 impl OutboundMatrixMessage {
+    pub fn relates_to(&self) -> RelatesTo<'_> {
+        RelatesTo {
+            reply_to: self.reply_to.as_deref(),
+            thread_root: self.thread_root.as_deref(),
+        }
+    }
+
+    /// Puts `prefix` in front of the body, as text in the formatted body.
+    pub fn prefix_body(&mut self, prefix: &str) {
+        self.body = format!("{prefix}{}", self.body);
+        if let Some(formatted_body) = &mut self.formatted_body {
+            *formatted_body = format!("{}{formatted_body}", formatter::text_to_html(prefix));
+        }
+    }
+
     pub fn render_body(&self) -> String {
         let mut body = self.body.clone();
         if let Some(reply_to) = &self.reply_to {
@@ -119,6 +189,8 @@ impl OutboundMatrixMessage {
 pub struct MessageFlow {
     matrix_converter: Arc<MatrixToDiscordConverter>,
     discord_converter: Arc<DiscordToMatrixConverter>,
+    emoji_handler: Option<Arc<EmojiHandler>>,
+    redactor: Arc<ContentRedactor>,
 }
 
 impl MessageFlow {
@@ -130,17 +202,51 @@ impl MessageFlow {
         matrix_client: Arc<MatrixAppservice>,
         discord_client: Arc<DiscordClient>,
         emoji_handler: Option<Arc<EmojiHandler>>,
+    ) -> Self {
+        Self::with_room_store(matrix_client, discord_client, emoji_handler, None)
+    }
+
+    pub fn with_room_store(
+        matrix_client: Arc<MatrixAppservice>,
+        discord_client: Arc<DiscordClient>,
+        emoji_handler: Option<Arc<EmojiHandler>>,
+        room_store: Option<Arc<dyn RoomStore>>,
     ) -> Self {
         let domain = matrix_client.config().bridge.domain.clone();
-        let mut converter = DiscordToMatrixConverter::new(discord_client).with_domain(domain);
+        let room_mention_roles = matrix_client.config().bridge.room_mention_roles.clone();
+        let determine_code_language = matrix_client.config().bridge.determine_code_language;
+        let mut converter = DiscordToMatrixConverter::new(discord_client)
+            .with_domain(domain)
+            .with_room_mention_roles(room_mention_roles)
+            .with_code_language_detection(determine_code_language);
 
-        if let Some(handler) = emoji_handler {
-            converter = converter.with_emoji_handler(handler);
+        if let Some(handler) = &emoji_handler {
+            converter = converter.with_emoji_handler(handler.clone());
+        }
+        let redactor = ContentRedactor::new(&matrix_client.config().bridge.content_redaction)
+            .unwrap_or_else(|err| {
+                tracing::warn!("ignoring bridge.content_redaction: {}", err);
+                ContentRedactor::default()
+            });
+        let bridge_config = &matrix_client.config().bridge;
+        let convert_iso_timestamps = bridge_config.convert_iso_timestamps;
+        let (everyone_mentions, here_mentions) = (
+            !bridge_config.disable_everyone_mention,
+            !bridge_config.disable_here_mention,
+        );
+        let mut matrix_converter = MatrixToDiscordConverter::new(matrix_client)
+            .with_iso_timestamps(convert_iso_timestamps)
+            .with_channel_mentions(everyone_mentions, here_mentions);
+        if let Some(room_store) = room_store {
+            converter = converter.with_room_store(room_store.clone());
+            matrix_converter = matrix_converter.with_room_store(room_store);
         }
 
         Self {
-            matrix_converter: Arc::new(MatrixToDiscordConverter::new(matrix_client)),
+            matrix_converter: Arc::new(matrix_converter),
             discord_converter: Arc::new(converter),
+            emoji_handler,
+            redactor: Arc::new(redactor),
         }
     }
 
@@ -170,6 +276,35 @@ impl MessageFlow {
 
         let relation = parse_relation(content);
         let attachments = parse_attachments(content_for_body, &msgtype);
+        // A media event's body is only its file name unless a separate
+        // `filename` turns it into a caption.
+        let body = if !attachments.is_empty() {
+            media_caption(content_for_body)
+        } else if msgtype == "m.location" {
+            location::matrix_location_to_discord(content_for_body).unwrap_or(body)
+        } else {
+            body
+        };
+        let formatted_body = content_for_body
+            .get("formatted_body")
+            .and_then(Value::as_str);
+        let emoticons = formatted_body
+            .map(MessageUtils::extract_matrix_emoticons)
+            .unwrap_or_default();
+        let body = match formatted_body {
+            Some(html) => {
+                // The formatting of a text message goes over as Discord markdown.
+                let is_html = content_for_body.get("format").and_then(Value::as_str)
+                    == Some("org.matrix.custom.html");
+                let body = if is_html && attachments.is_empty() && msgtype != "m.location" {
+                    formatter::html_to_markdown(html)
+                } else {
+                    body
+                };
+                mention_ghost_pills(&body, &MessageUtils::extract_matrix_user_pills(html))
+            }
+            None => body,
+        };
 
         if body.is_empty() && attachments.is_empty() {
             return None;
@@ -182,14 +317,24 @@ impl MessageFlow {
             body,
             relation,
             attachments,
+            emoticons,
         })
     }
 
     pub fn matrix_to_discord(&self, message: &MatrixInboundMessage) -> OutboundDiscordMessage {
-        let reply_to = match &message.relation {
-            Some(MessageRelation::Reply { event_id }) => Some(event_id.clone()),
-            _ => None,
-        };
+        let body = self.redactor.apply("matrix", &message.body);
+        self.discord_message(message, self.matrix_converter.format_for_discord(&body))
+    }
+
+    fn discord_message(
+        &self,
+        message: &MatrixInboundMessage,
+        content: String,
+    ) -> OutboundDiscordMessage {
+        let reply_to = message
+            .relation
+            .as_ref()
+            .and_then(MessageRelation::reply_to);
         let edit_of = match &message.relation {
             Some(MessageRelation::Replace { event_id }) => Some(event_id.clone()),
             _ => None,
@@ -201,12 +346,110 @@ impl MessageFlow {
             .collect();
 
         OutboundDiscordMessage {
-            content: self.matrix_converter.format_for_discord(&message.body),
+            content,
             reply_to,
             edit_of,
             attachments,
+            attachment_names: attachment_names(&message.attachments),
             embed: None,
             use_embed: false,
+            attachment_policy: AttachmentPolicy::Reupload,
+        }
+    }
+
+    /// Like [`Self::matrix_to_discord`], but also turns references to Matrix
+    /// rooms bridged within `guild_id` into Discord channel mentions. Room
+    /// mentions only notify the channel when `sender_can_notify_room`.
+    pub async fn matrix_to_discord_resolved(
+        &self,
+        message: &MatrixInboundMessage,
+        guild_id: &str,
+        sender_can_notify_room: bool,
+    ) -> OutboundDiscordMessage {
+        let body = self.redactor.apply("matrix", &message.body);
+        let channels = self
+            .matrix_converter
+            .resolve_room_references(&body, guild_id)
+            .await;
+        let content = self.matrix_converter.format_for_discord_with_channels(
+            &body,
+            &channels,
+            sender_can_notify_room,
+        );
+        let mut outbound = self.discord_message(message, content);
+        self.resolve_emoticons(&message.emoticons, &mut outbound)
+            .await;
+        self.resolve_stickers(&message.attachments, &mut outbound)
+            .await;
+        outbound
+    }
+
+    /// Stickers showing an emoji that came from Discord go back as its
+    /// `<:name:id>` token instead of as an image.
+    async fn resolve_stickers(
+        &self,
+        attachments: &[MessageAttachment],
+        outbound: &mut OutboundDiscordMessage,
+    ) {
+        let Some(emoji_handler) = &self.emoji_handler else {
+            return;
+        };
+        for sticker in attachments.iter().filter(|a| a.kind == "m.sticker") {
+            match emoji_handler.get_emoji_by_mxc(&sticker.url).await {
+                Ok(Some(mapping)) => {
+                    emoji_handler.record_usage(
+                        EmojiUsageKind::Emoji,
+                        &mapping.discord_emoji_id,
+                        &mapping.emoji_name,
+                    );
+                    outbound.attachments.retain(|url| url != &sticker.url);
+                    if !outbound.content.is_empty() {
+                        outbound.content.push(' ');
+                    }
+                    outbound.content.push_str(&mapping.discord_token());
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    tracing::warn!("failed to look up sticker {}: {}", sticker.url, err);
+                }
+            }
+        }
+    }
+
+    /// Emoticons that came from Discord go back as `<:name:id>` tokens; any
+    /// other emoticon is attached as an image so it does not degrade to its
+    /// shortcode.
+    async fn resolve_emoticons(
+        &self,
+        emoticons: &[MatrixEmoticon],
+        outbound: &mut OutboundDiscordMessage,
+    ) {
+        let Some(emoji_handler) = &self.emoji_handler else {
+            return;
+        };
+        for emoticon in emoticons {
+            match emoji_handler.get_emoji_by_mxc(&emoticon.mxc_url).await {
+                Ok(Some(mapping)) => {
+                    emoji_handler.record_usage(
+                        EmojiUsageKind::Emoji,
+                        &mapping.discord_emoji_id,
+                        &mapping.emoji_name,
+                    );
+                    outbound.content = replace_shortcode(
+                        &outbound.content,
+                        &emoticon.shortcode,
+                        &mapping.discord_token(),
+                    );
+                }
+                Ok(None) => {
+                    if !outbound.attachments.contains(&emoticon.mxc_url) {
+                        outbound.attachments.push(emoticon.mxc_url.clone());
+                    }
+                }
+                Err(err) => {
+                    tracing::warn!("failed to look up emoticon {}: {}", emoticon.mxc_url, err);
+                }
+            }
         }
     }
 
@@ -217,10 +460,10 @@ impl MessageFlow {
         sender_avatar_url: Option<&str>,
         reply_info: Option<(&str, &str)>,
     ) -> OutboundDiscordMessage {
-        let reply_to = match &message.relation {
-            Some(MessageRelation::Reply { event_id }) => Some(event_id.clone()),
-            _ => None,
-        };
+        let reply_to = message
+            .relation
+            .as_ref()
+            .and_then(MessageRelation::reply_to);
         let edit_of = match &message.relation {
             Some(MessageRelation::Replace { event_id }) => Some(event_id.clone()),
             _ => None,
@@ -231,10 +474,11 @@ impl MessageFlow {
             .map(|attachment| attachment.url.clone())
             .collect();
 
+        let body = self.redactor.apply("matrix", &message.body);
         let embed = crate::discord::build_matrix_message_embed(
             sender_displayname,
             sender_avatar_url,
-            &message.body,
+            &body,
             reply_info,
         );
 
@@ -243,17 +487,59 @@ impl MessageFlow {
             reply_to,
             edit_of,
             attachments,
+            attachment_names: attachment_names(&message.attachments),
             embed: Some(embed),
             use_embed: true,
+            attachment_policy: AttachmentPolicy::Reupload,
         }
     }
 
     pub fn discord_to_matrix(&self, message: &DiscordInboundMessage) -> OutboundMatrixMessage {
+        let content = self.redactor.apply("discord", &message.content);
+        OutboundMatrixMessage {
+            body: self.discord_converter.format_for_matrix(&content),
+            reply_to: message.reply_to.clone(),
+            edit_of: message.edit_of.clone(),
+            thread_root: None,
+            attachments: message.attachments.clone(),
+            origin_server_ts: None,
+            formatted_body: Some(self.discord_converter.format_as_html(&content)),
+            attribution_badge: false,
+            attachment_policy: AttachmentPolicy::Reupload,
+        }
+    }
+
+    /// Like [`Self::discord_to_matrix`], but resolves role and channel
+    /// mentions through the Discord metadata cache and the room store.
+    pub async fn discord_to_matrix_resolved(
+        &self,
+        message: &DiscordInboundMessage,
+    ) -> OutboundMatrixMessage {
+        let content = self.redactor.apply("discord", &message.content);
+        let mentions = self
+            .discord_converter
+            .resolve_mentions(
+                &content,
+                Some(&message.channel_id),
+                message.sender_can_mention_everyone,
+            )
+            .await;
         OutboundMatrixMessage {
-            body: self.discord_converter.format_for_matrix(&message.content),
+            body: self
+                .discord_converter
+                .format_for_matrix_with_mentions(&content, &mentions),
             reply_to: message.reply_to.clone(),
             edit_of: message.edit_of.clone(),
+            thread_root: None,
             attachments: message.attachments.clone(),
+            origin_server_ts: None,
+            formatted_body: Some(
+                self.discord_converter
+                    .format_as_html_async_with_mentions(&content, &mentions)
+                    .await,
+            ),
+            attribution_badge: false,
+            attachment_policy: AttachmentPolicy::Reupload,
         }
     }
 
@@ -261,10 +547,21 @@ impl MessageFlow {
         &self,
         message: &DiscordInboundMessage,
     ) -> (String, Option<String>) {
-        let plain = self.discord_converter.format_for_matrix(&message.content);
+        let content = self.redactor.apply("discord", &message.content);
+        let mentions = self
+            .discord_converter
+            .resolve_mentions(
+                &content,
+                Some(&message.channel_id),
+                message.sender_can_mention_everyone,
+            )
+            .await;
+        let plain = self
+            .discord_converter
+            .format_for_matrix_with_mentions(&content, &mentions);
         let formatted = self
             .discord_converter
-            .format_as_html_async(&message.content)
+            .format_as_html_async_with_mentions(&content, &mentions)
             .await;
         (plain, Some(formatted))
     }
@@ -272,10 +569,33 @@ impl MessageFlow {
     pub fn discord_converter(&self) -> &DiscordToMatrixConverter {
         &self.discord_converter
     }
+
+    pub fn matrix_converter(&self) -> &MatrixToDiscordConverter {
+        &self.matrix_converter
+    }
 }
 
 fn parse_relation(content: &Value) -> Option<MessageRelation> {
     let relates_to = content.get("m.relates_to")?;
+    let rel_type = relates_to.get("rel_type").and_then(Value::as_str);
+    if rel_type == Some("m.thread")
+        && let Some(root_event_id) = relates_to.get("event_id").and_then(Value::as_str)
+    {
+        let falling_back = relates_to
+            .get("is_falling_back")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        let reply_to = relates_to
+            .get("m.in_reply_to")
+            .and_then(|inner| inner.get("event_id"))
+            .and_then(Value::as_str)
+            .filter(|_| !falling_back)
+            .map(ToOwned::to_owned);
+        return Some(MessageRelation::Thread {
+            root_event_id: root_event_id.to_string(),
+            reply_to,
+        });
+    }
     if let Some(reply_event_id) = relates_to
         .get("m.in_reply_to")
         .and_then(|inner| inner.get("event_id"))
@@ -285,10 +605,7 @@ fn parse_relation(content: &Value) -> Option<MessageRelation> {
             event_id: reply_event_id.to_string(),
         });
     }
-    if relates_to
-        .get("rel_type")
-        .and_then(Value::as_str)
-        .is_some_and(|kind| kind == "m.replace")
+    if rel_type == Some("m.replace")
         && let Some(edit_event_id) = relates_to.get("event_id").and_then(Value::as_str)
     {
         return Some(MessageRelation::Replace {
@@ -298,6 +615,50 @@ fn parse_relation(content: &Value) -> Option<MessageRelation> {
     None
 }
 
+/// Puts the user id of each pill linking a Discord ghost in place of the
+/// name it shows, so the ghost is mentioned on Discord.
+fn mention_ghost_pills(body: &str, pills: &[MatrixUserPill]) -> String {
+    let mut result = String::with_capacity(body.len());
+    let mut rest = body;
+    for pill in pills {
+        if !pill.user_id.starts_with("@_discord_") || pill.text.is_empty() {
+            continue;
+        }
+        if let Some(index) = rest.find(&pill.text) {
+            result.push_str(&rest[..index]);
+            result.push_str(&pill.user_id);
+            rest = &rest[index + pill.text.len()..];
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Replaces `:shortcode:` with `token`, leaving longer shortcodes that merely
+/// contain it alone.
+fn replace_shortcode(content: &str, shortcode: &str, token: &str) -> String {
+    let needle = format!(":{}:", shortcode);
+    let mut result = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(index) = rest.find(&needle) {
+        let before = &rest[..index];
+        let after = &rest[index + needle.len()..];
+        let joined = |c: char| c.is_alphanumeric() || c == '_';
+        if before.chars().next_back().is_some_and(joined)
+            || after.chars().next().is_some_and(joined)
+        {
+            result.push_str(&rest[..index + 1]);
+            rest = &rest[index + 1..];
+            continue;
+        }
+        result.push_str(before);
+        result.push_str(token);
+        rest = after;
+    }
+    result.push_str(rest);
+    result
+}
+
 fn parse_attachments(content: &Value, msgtype: &str) -> Vec<MessageAttachment> {
     if !ATTACHMENT_TYPES.contains(&msgtype) {
         return Vec::new();
@@ -306,10 +667,18 @@ fn parse_attachments(content: &Value, msgtype: &str) -> Vec<MessageAttachment> {
         return Vec::new();
     };
     let name = content
-        .get("body")
+        .get("filename")
+        .or_else(|| content.get("body"))
         .and_then(Value::as_str)
-        .unwrap_or("matrix-media")
-        .to_string();
+        .unwrap_or("matrix-media");
+    let name = match content
+        .get("info")
+        .and_then(|info| info.get("mimetype"))
+        .and_then(Value::as_str)
+    {
+        Some(mimetype) => ensure_filename_extension(name, mimetype),
+        None => name.to_string(),
+    };
 
     vec![MessageAttachment {
         name,
@@ -318,13 +687,30 @@ fn parse_attachments(content: &Value, msgtype: &str) -> Vec<MessageAttachment> {
     }]
 }
 
+fn attachment_names(attachments: &[MessageAttachment]) -> BTreeMap<String, String> {
+    attachments
+        .iter()
+        .map(|attachment| (attachment.url.clone(), attachment.name.clone()))
+        .collect()
+}
+
+fn media_caption(content: &Value) -> String {
+    let body = content.get("body").and_then(Value::as_str);
+    match content.get("filename").and_then(Value::as_str) {
+        Some(filename) if body.is_some_and(|body| body != filename) => {
+            body.unwrap_or_default().to_string()
+        }
+        _ => String::new(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
 
     use serde_json::json;
 
-    use super::{DiscordInboundMessage, MessageFlow, MessageRelation};
+    use super::{DiscordInboundMessage, MessageFlow, MessageRelation, replace_shortcode};
     use crate::config::{
         AuthConfig, BridgeConfig, ChannelConfig, ChannelDeleteOptionsConfig, Config,
         DatabaseConfig, GhostsConfig, LimitsConfig, LoggingConfig, MetricsConfig,
@@ -342,6 +728,7 @@ mod tests {
                 homeserver_url: "http://localhost:8008".to_string(),
                 presence_interval: 500,
                 disable_presence: false,
+                dry_run: false,
                 disable_typing_notifications: false,
                 disable_discord_mentions: false,
                 disable_deletion_forwarding: false,
@@ -358,15 +745,30 @@ mod tests {
                 admin_mxid: None,
                 invalid_token_message: "Your Discord bot token seems to be invalid".to_string(),
                 user_activity: None,
+                presence_mapping: Default::default(),
+                room_mention_roles: Vec::new(),
+                convert_iso_timestamps: false,
+                member_change_notices: false,
+                geo_uri_locations: false,
+                cross_channel_context: false,
+                approval_dm_after_secs: 0,
+                relay_extractors: Vec::new(),
+                bridge_bot_messages: false,
+                content_redaction: Default::default(),
+                maintenance: Default::default(),
+                inactive_rooms: Default::default(),
+                listen: None,
+                socket_permissions: None,
             },
             registration: RegistrationConfig {
                 bridge_id: "test-bridge".to_string(),
-                appservice_token: "test_as_token".to_string(),
-                homeserver_token: "test_hs_token".to_string(),
+                appservice_token: "test_as_token".into(),
+                homeserver_token: "test_hs_token".into(),
                 ..Default::default()
             },
             auth: AuthConfig {
-                bot_token: "token".to_string(),
+                bot_token: "token".into(),
+                bot_token_file: None,
                 client_id: None,
                 client_secret: None,
                 use_privileged_intents: false,
@@ -386,12 +788,16 @@ mod tests {
                 room_store_path: None,
                 max_connections: Some(1),
                 min_connections: Some(1),
+                max_blocking_threads: None,
             },
             room: RoomConfig {
                 default_visibility: "private".to_string(),
                 room_alias_prefix: "_discord".to_string(),
                 enable_room_creation: true,
                 kick_for: 30000,
+                auto_invite_members: false,
+                attribution_badge: false,
+                guilds: Default::default(),
             },
             channel: ChannelConfig {
                 enable_channel_creation: false,
@@ -402,6 +808,7 @@ mod tests {
                 enable_webhook: true,
                 webhook_name: "_matrix".to_string(),
                 webhook_avatar: String::new(),
+                mirror_slowmode: false,
             },
             limits: LimitsConfig::default(),
             ghosts: GhostsConfig {
@@ -410,8 +817,19 @@ mod tests {
                 username_template: "_discord_:id".to_string(),
                 displayname_template: ":username".to_string(),
                 avatar_url_template: None,
+                hide_from_directory: false,
+                per_message_profiles: false,
+                protected_names: Vec::new(),
+                impersonation_suffix: String::new(),
             },
             metrics: MetricsConfig::default(),
+            chaos: crate::config::ChaosConfig::default(),
+            admin_api: crate::config::AdminApiConfig::default(),
+            web: crate::config::WebConfig::default(),
+            delivery: crate::config::DeliveryConfig::default(),
+            homeserver_admin: crate::config::HomeserverAdminConfig::default(),
+            alerts: crate::config::AlertsConfig::default(),
+            media: crate::config::MediaConfig::default(),
         })
     }
 
@@ -433,6 +851,7 @@ mod tests {
                     }
                 }
             })),
+            prev_content: None,
             timestamp: None,
         };
 
@@ -445,6 +864,210 @@ mod tests {
         );
         assert_eq!(parsed.attachments.len(), 1);
         assert_eq!(parsed.attachments[0].url, "mxc://example.org/cat");
+        assert_eq!(parsed.body, "");
+    }
+
+    #[test]
+    fn parse_matrix_event_reads_thread_relation() {
+        let event = |relates_to| MatrixEvent {
+            event_id: Some("$event".to_string()),
+            event_type: "m.room.message".to_string(),
+            room_id: "!room:example.org".to_string(),
+            sender: "@alice:example.org".to_string(),
+            state_key: None,
+            content: Some(json!({
+                "msgtype": "m.text",
+                "body": "in a thread",
+                "m.relates_to": relates_to
+            })),
+            prev_content: None,
+            timestamp: None,
+        };
+
+        let fallback = MessageFlow::parse_matrix_event(&event(json!({
+            "rel_type": "m.thread",
+            "event_id": "$root",
+            "is_falling_back": true,
+            "m.in_reply_to": { "event_id": "$latest" }
+        })))
+        .expect("matrix message should parse");
+        assert_eq!(
+            fallback.relation,
+            Some(MessageRelation::Thread {
+                root_event_id: "$root".to_string(),
+                reply_to: None,
+            })
+        );
+
+        let reply = MessageFlow::parse_matrix_event(&event(json!({
+            "rel_type": "m.thread",
+            "event_id": "$root",
+            "m.in_reply_to": { "event_id": "$earlier" }
+        })))
+        .expect("matrix message should parse");
+        let relation = reply.relation.expect("thread relation");
+        assert_eq!(relation.thread_root(), Some("$root"));
+        assert_eq!(relation.reply_to().as_deref(), Some("$earlier"));
+    }
+
+    #[test]
+    fn parse_matrix_event_keeps_media_captions() {
+        let event = MatrixEvent {
+            event_id: Some("$event".to_string()),
+            event_type: "m.room.message".to_string(),
+            room_id: "!room:example.org".to_string(),
+            sender: "@alice:example.org".to_string(),
+            state_key: None,
+            content: Some(json!({
+                "msgtype": "m.image",
+                "body": "look at this",
+                "filename": "cat.png",
+                "url": "mxc://example.org/cat",
+            })),
+            prev_content: None,
+            timestamp: None,
+        };
+
+        let parsed = MessageFlow::parse_matrix_event(&event).expect("matrix message should parse");
+        assert_eq!(parsed.body, "look at this");
+        assert_eq!(parsed.attachments[0].name, "cat.png");
+    }
+
+    #[test]
+    fn parse_matrix_event_mentions_ghosts_behind_pills() {
+        let event = MatrixEvent {
+            event_id: Some("$event".to_string()),
+            event_type: "m.room.message".to_string(),
+            room_id: "!room:example.org".to_string(),
+            sender: "@alice:example.org".to_string(),
+            state_key: None,
+            content: Some(json!({
+                "msgtype": "m.text",
+                "body": "Bob: ask Bob & Carol",
+                "format": "org.matrix.custom.html",
+                "formatted_body": "<a href=\"https://matrix.to/#/@_discord_42:example.org\">Bob</a>: ask Bob &amp; <a href=\"https://matrix.to/#/@carol:example.org\">Carol</a>",
+            })),
+            prev_content: None,
+            timestamp: None,
+        };
+
+        let parsed = MessageFlow::parse_matrix_event(&event).expect("matrix message should parse");
+        assert_eq!(parsed.body, "@_discord_42:example.org: ask Bob & Carol");
+    }
+
+    #[test]
+    fn parse_matrix_event_turns_formatting_into_markdown() {
+        let event = MatrixEvent {
+            event_id: Some("$event".to_string()),
+            event_type: "m.room.message".to_string(),
+            room_id: "!room:example.org".to_string(),
+            sender: "@alice:example.org".to_string(),
+            state_key: None,
+            content: Some(json!({
+                "msgtype": "m.text",
+                "body": "> <@bob:example.org> earlier\n\n**see** the docs",
+                "format": "org.matrix.custom.html",
+                "formatted_body": "<mx-reply><blockquote>earlier</blockquote></mx-reply><strong>see</strong> <a href=\"https://example.org/docs\">the docs</a>",
+            })),
+            prev_content: None,
+            timestamp: None,
+        };
+
+        let parsed = MessageFlow::parse_matrix_event(&event).expect("matrix message should parse");
+        assert_eq!(parsed.body, "**see** [the docs](https://example.org/docs)");
+    }
+
+    #[test]
+    fn parse_matrix_event_sends_locations_as_map_links() {
+        let event = MatrixEvent {
+            event_id: Some("$event".to_string()),
+            event_type: "m.room.message".to_string(),
+            room_id: "!room:example.org".to_string(),
+            sender: "@alice:example.org".to_string(),
+            state_key: None,
+            content: Some(json!({
+                "msgtype": "m.location",
+                "body": "Home",
+                "geo_uri": "geo:52.52,13.405",
+            })),
+            prev_content: None,
+            timestamp: None,
+        };
+
+        let parsed = MessageFlow::parse_matrix_event(&event).expect("matrix message should parse");
+        assert!(parsed.attachments.is_empty());
+        assert_eq!(
+            parsed.body,
+            "📍 Home — 52.52, 13.405\nhttps://www.openstreetmap.org/?mlat=52.52&mlon=13.405#map=16/52.52/13.405"
+        );
+    }
+
+    #[test]
+    fn parse_matrix_event_names_media_after_its_mimetype() {
+        let event = MatrixEvent {
+            event_id: Some("$event".to_string()),
+            event_type: "m.room.message".to_string(),
+            room_id: "!room:example.org".to_string(),
+            sender: "@alice:example.org".to_string(),
+            state_key: None,
+            content: Some(json!({
+                "msgtype": "m.audio",
+                "body": "Voice message",
+                "url": "mxc://example.org/voice",
+                "info": { "mimetype": "audio/ogg", "duration": 4200 },
+            })),
+            prev_content: None,
+            timestamp: None,
+        };
+
+        let parsed = MessageFlow::parse_matrix_event(&event).expect("matrix message should parse");
+        assert_eq!(parsed.attachments[0].kind, "m.audio");
+        assert_eq!(parsed.attachments[0].name, "Voice message.ogg");
+    }
+
+    #[test]
+    fn parse_matrix_event_collects_emoticons() {
+        let event = MatrixEvent {
+            event_id: Some("$event".to_string()),
+            event_type: "m.room.message".to_string(),
+            room_id: "!room:example.org".to_string(),
+            sender: "@alice:example.org".to_string(),
+            state_key: None,
+            content: Some(json!({
+                "msgtype": "m.text",
+                "body": "hi :wave: :wave: :cat:",
+                "format": "org.matrix.custom.html",
+                "formatted_body": "hi <img data-mx-emoticon src=\"mxc://example.org/wave\" alt=\":wave:\" height=\"32\"> <img data-mx-emoticon src=\"mxc://example.org/wave\" alt=\":wave:\"> <img src=\"mxc://example.org/cat\" title=\"cat\" data-mx-emoticon> <img src=\"mxc://example.org/photo\" alt=\"photo\">"
+            })),
+            prev_content: None,
+            timestamp: None,
+        };
+
+        let parsed = MessageFlow::parse_matrix_event(&event).expect("matrix message should parse");
+        let emoticons: Vec<_> = parsed
+            .emoticons
+            .iter()
+            .map(|emoticon| (emoticon.shortcode.as_str(), emoticon.mxc_url.as_str()))
+            .collect();
+        assert_eq!(
+            emoticons,
+            vec![
+                ("wave", "mxc://example.org/wave"),
+                ("cat", "mxc://example.org/cat")
+            ]
+        );
+    }
+
+    #[test]
+    fn replace_shortcode_only_matches_whole_shortcodes() {
+        assert_eq!(
+            replace_shortcode(":wave: hi :wave:", "wave", "<:wave:1>"),
+            "<:wave:1> hi <:wave:1>"
+        );
+        assert_eq!(
+            replace_shortcode(":big_wave: x:wave:y", "wave", "<:wave:1>"),
+            ":big_wave: x:wave:y"
+        );
     }
 
     #[tokio::test]
@@ -472,6 +1095,7 @@ mod tests {
                     "body": "new body"
                 }
             })),
+            prev_content: None,
             timestamp: None,
         };
         let inbound = MessageFlow::parse_matrix_event(&event).expect("matrix message");
@@ -494,6 +1118,7 @@ mod tests {
             attachments: vec!["https://example.org/a.png".to_string()],
             reply_to: Some("discord-msg-1".to_string()),
             edit_of: None,
+            sender_can_mention_everyone: false,
         });
 
         assert_eq!(outbound.body, "*bold*".to_string());
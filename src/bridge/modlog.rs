@@ -0,0 +1,118 @@
+//! Notices for a guild's modlog room, one per moderation event. Each notice
+//! starts with `#modlog` and a hashtag for the action, lists its fields as
+//! `key: value`, and carries them as JSON under [`MODLOG_CONTENT_KEY`], so
+//! the room can be searched by text or read by tools.
+
+use serde_json::{Map, Value, json};
+
+/// Content key holding the entry's fields.
+pub const MODLOG_CONTENT_KEY: &str = "org.matrix.discord.modlog";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModlogAction {
+    MessageDeleted,
+    Kicked,
+    Banned,
+    Unbanned,
+    AutoMod,
+    BridgeError,
+}
+
+impl ModlogAction {
+    pub fn tag(self) -> &'static str {
+        match self {
+            Self::MessageDeleted => "delete",
+            Self::Kicked => "kick",
+            Self::Banned => "ban",
+            Self::Unbanned => "unban",
+            Self::AutoMod => "automod",
+            Self::BridgeError => "error",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModlogEntry {
+    pub action: ModlogAction,
+    pub guild_id: String,
+    pub fields: Vec<(&'static str, String)>,
+}
+
+impl ModlogEntry {
+    pub fn new(action: ModlogAction, guild_id: &str) -> Self {
+        Self {
+            action,
+            guild_id: guild_id.to_string(),
+            fields: Vec::new(),
+        }
+    }
+
+    /// Adds `key: value`; `None` leaves the field out.
+    pub fn with(mut self, key: &'static str, value: Option<impl Into<String>>) -> Self {
+        if let Some(value) = value {
+            self.fields.push((key, value.into()));
+        }
+        self
+    }
+
+    /// The `m.room.message` content of the notice.
+    pub fn content(&self) -> Value {
+        let mut fields = Map::new();
+        fields.insert("action".to_string(), json!(self.action.tag()));
+        fields.insert("guild_id".to_string(), json!(self.guild_id));
+        let mut body = format!("#modlog #{} guild: {}", self.action.tag(), self.guild_id);
+        let mut html = format!(
+            "<b>#modlog #{}</b><br>guild: <code>{}</code>",
+            self.action.tag(),
+            escape_html(&self.guild_id)
+        );
+        for (key, value) in &self.fields {
+            fields.insert(key.to_string(), json!(value));
+            body.push_str(&format!("\n{key}: {value}"));
+            html.push_str(&format!("<br>{key}: <code>{}</code>", escape_html(value)));
+        }
+        json!({
+            "msgtype": "m.notice",
+            "body": body,
+            "format": "org.matrix.custom.html",
+            "formatted_body": html,
+            MODLOG_CONTENT_KEY: fields,
+        })
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MODLOG_CONTENT_KEY, ModlogAction, ModlogEntry};
+
+    #[test]
+    fn entries_are_tagged_and_structured() {
+        let content = ModlogEntry::new(ModlogAction::Banned, "10")
+            .with("target", Some("20"))
+            .with("actor", Some("30"))
+            .with("reason", None::<String>)
+            .with("details", Some("<script>"))
+            .content();
+
+        assert_eq!(content["msgtype"], "m.notice");
+        assert_eq!(
+            content["body"],
+            "#modlog #ban guild: 10\ntarget: 20\nactor: 30\ndetails: <script>"
+        );
+        assert!(
+            content["formatted_body"]
+                .as_str()
+                .unwrap()
+                .ends_with("details: <code>&lt;script&gt;</code>")
+        );
+        assert_eq!(content[MODLOG_CONTENT_KEY]["action"], "ban");
+        assert_eq!(content[MODLOG_CONTENT_KEY]["target"], "20");
+        assert!(content[MODLOG_CONTENT_KEY].get("reason").is_none());
+    }
+}
@@ -1,10 +1,12 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 
 use anyhow::Result;
 use async_trait::async_trait;
 use parking_lot::Mutex;
 use tracing::warn;
 
+use crate::config::{MatrixPresenceState, PresenceMappingConfig, PresenceMappingEntry};
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DiscordPresenceState {
     Online,
@@ -28,13 +30,6 @@ pub struct DiscordPresence {
     pub activities: Vec<DiscordActivity>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum MatrixPresenceState {
-    Online,
-    Offline,
-    Unavailable,
-}
-
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PresenceDecision {
     pub presence: MatrixPresenceState,
@@ -56,17 +51,34 @@ pub trait MatrixPresenceTarget: Send + Sync {
         discord_user_id: &str,
         username: Option<&str>,
     ) -> Result<()>;
+
+    /// Matrix presence the user asked to always be shown with, if any.
+    async fn presence_override(
+        &self,
+        _discord_user_id: &str,
+    ) -> Result<Option<MatrixPresenceState>> {
+        Ok(None)
+    }
 }
 
 pub struct PresenceHandler {
     bot_discord_user_id: Option<String>,
+    mapping: PresenceMappingConfig,
     queue: Mutex<VecDeque<DiscordPresence>>,
 }
 
 impl PresenceHandler {
     pub fn new(bot_discord_user_id: Option<String>) -> Self {
+        Self::with_mapping(bot_discord_user_id, PresenceMappingConfig::default())
+    }
+
+    pub fn with_mapping(
+        bot_discord_user_id: Option<String>,
+        mapping: PresenceMappingConfig,
+    ) -> Self {
         Self {
             bot_discord_user_id,
+            mapping,
             queue: Mutex::new(VecDeque::new()),
         }
     }
@@ -89,6 +101,35 @@ impl PresenceHandler {
         queue.push_back(presence);
     }
 
+    /// Queues a batch, such as the presence set a guild reports on connect.
+    /// Returns how many presences were queued.
+    pub fn enqueue_users(&self, presences: impl IntoIterator<Item = DiscordPresence>) -> usize {
+        let mut batch: Vec<DiscordPresence> = Vec::new();
+        let mut positions = HashMap::new();
+        for presence in presences {
+            if self
+                .bot_discord_user_id
+                .as_ref()
+                .is_some_and(|bot_id| bot_id == &presence.user_id)
+            {
+                continue;
+            }
+            match positions.get(&presence.user_id) {
+                Some(&index) => batch[index] = presence,
+                None => {
+                    positions.insert(presence.user_id.clone(), batch.len());
+                    batch.push(presence);
+                }
+            }
+        }
+
+        let mut queue = self.queue.lock();
+        queue.retain(|item| !positions.contains_key(&item.user_id));
+        let queued = batch.len();
+        queue.extend(batch);
+        queued
+    }
+
     pub fn dequeue_user(&self, user_id: &str) -> bool {
         let mut queue = self.queue.lock();
         let before = queue.len();
@@ -104,7 +145,17 @@ impl PresenceHandler {
             return Ok(false);
         };
 
-        let decision = Self::map_presence(&presence);
+        let presence_override = match target.presence_override(&presence.user_id).await {
+            Ok(presence_override) => presence_override,
+            Err(err) => {
+                warn!(
+                    "Could not look up presence override for discord user {}: {}",
+                    presence.user_id, err
+                );
+                None
+            }
+        };
+        let decision = self.map_presence(&presence, presence_override);
         if let Err(err) = target
             .set_presence(
                 &presence.user_id,
@@ -124,10 +175,12 @@ impl PresenceHandler {
                     );
                 }
             } else {
-                warn!(
-                    "Could not update Matrix presence for discord user {}: {}",
-                    presence.user_id, err
-                );
+                // Keep the entry so the update is tried again on a later tick.
+                self.enqueue_user(presence.clone());
+                return Err(err.context(format!(
+                    "could not update Matrix presence for discord user {}",
+                    presence.user_id
+                )));
             }
         }
 
@@ -137,7 +190,11 @@ impl PresenceHandler {
         Ok(true)
     }
 
-    pub fn map_presence(presence: &DiscordPresence) -> PresenceDecision {
+    pub fn map_presence(
+        &self,
+        presence: &DiscordPresence,
+        presence_override: Option<MatrixPresenceState>,
+    ) -> PresenceDecision {
         let mut status_message = String::new();
 
         if let Some(activity) = presence.activities.first() {
@@ -158,38 +215,41 @@ impl PresenceHandler {
             }
         }
 
-        match presence.state {
-            DiscordPresenceState::Online => PresenceDecision {
-                presence: MatrixPresenceState::Online,
-                status_message,
-                should_drop: false,
-            },
-            DiscordPresenceState::Dnd => {
-                let status_message = if status_message.is_empty() {
-                    "Do not disturb".to_string()
-                } else {
-                    format!("Do not disturb | {status_message}")
-                };
-                PresenceDecision {
-                    presence: MatrixPresenceState::Online,
-                    status_message,
-                    should_drop: false,
-                }
-            }
-            DiscordPresenceState::Offline => PresenceDecision {
-                presence: MatrixPresenceState::Offline,
-                status_message,
-                should_drop: true,
-            },
-            DiscordPresenceState::Idle => PresenceDecision {
-                presence: MatrixPresenceState::Unavailable,
-                status_message,
-                should_drop: false,
-            },
+        let entry = match presence.state {
+            DiscordPresenceState::Online => &self.mapping.online,
+            DiscordPresenceState::Dnd => &self.mapping.dnd,
+            DiscordPresenceState::Idle => &self.mapping.idle,
+            DiscordPresenceState::Offline => &self.mapping.offline,
+        };
+
+        PresenceDecision {
+            presence: presence_override.unwrap_or_else(|| entry.presence.clone()),
+            status_message: decorate_status(entry, status_message),
+            // Offline users stop receiving presence refreshes until Discord
+            // reports them again.
+            should_drop: presence.state == DiscordPresenceState::Offline,
         }
     }
 }
 
+fn decorate_status(entry: &PresenceMappingEntry, mut status_message: String) -> String {
+    if let Some(prefix) = entry.status_prefix.as_deref().filter(|p| !p.is_empty()) {
+        status_message = if status_message.is_empty() {
+            prefix.to_string()
+        } else {
+            format!("{prefix} | {status_message}")
+        };
+    }
+    if let Some(suffix) = entry.status_suffix.as_deref().filter(|s| !s.is_empty()) {
+        status_message = if status_message.is_empty() {
+            suffix.to_string()
+        } else {
+            format!("{status_message} {suffix}")
+        };
+    }
+    status_message
+}
+
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
@@ -197,14 +257,16 @@ mod tests {
     use parking_lot::Mutex;
 
     use super::{
-        DiscordActivity, DiscordPresence, DiscordPresenceState, MatrixPresenceState,
-        MatrixPresenceTarget, PresenceHandler,
+        DiscordActivity, DiscordPresence, DiscordPresenceState, MatrixPresenceTarget,
+        PresenceHandler,
     };
+    use crate::config::{MatrixPresenceState, PresenceMappingConfig, PresenceMappingEntry};
 
     #[derive(Default, Clone)]
     struct MockPresenceTarget {
         calls: Arc<Mutex<Vec<(String, MatrixPresenceState, String)>>>,
         forbid_updates: bool,
+        fail_updates: bool,
     }
 
     #[async_trait::async_trait]
@@ -218,6 +280,9 @@ mod tests {
             if self.forbid_updates {
                 anyhow::bail!("M_FORBIDDEN");
             }
+            if self.fail_updates {
+                anyhow::bail!("503 Service Unavailable");
+            }
             self.calls.lock().push((
                 discord_user_id.to_string(),
                 presence,
@@ -254,6 +319,31 @@ mod tests {
         assert_eq!(handler.queue_count(), 1);
     }
 
+    #[test]
+    fn batch_enqueue_dedupes_and_skips_the_bot() {
+        let handler = PresenceHandler::new(Some("bot".to_string()));
+        let presence = |user_id: &str, state| DiscordPresence {
+            user_id: user_id.to_string(),
+            username: None,
+            state,
+            activities: vec![],
+        };
+        handler.enqueue_user(presence("1", DiscordPresenceState::Offline));
+
+        let queued = handler.enqueue_users([
+            presence("1", DiscordPresenceState::Online),
+            presence("2", DiscordPresenceState::Idle),
+            presence("bot", DiscordPresenceState::Online),
+            presence("2", DiscordPresenceState::Dnd),
+        ]);
+        assert_eq!(queued, 2);
+        assert_eq!(handler.queue_count(), 2);
+
+        let queue = handler.queue.lock();
+        assert_eq!(queue[0].state, DiscordPresenceState::Online);
+        assert_eq!(queue[1].state, DiscordPresenceState::Dnd);
+    }
+
     #[test]
     fn dnd_maps_to_online_with_prefix() {
         let presence = DiscordPresence {
@@ -267,7 +357,7 @@ mod tests {
             }],
         };
 
-        let decision = PresenceHandler::map_presence(&presence);
+        let decision = PresenceHandler::new(None).map_presence(&presence, None);
         assert_eq!(decision.presence, MatrixPresenceState::Online);
         assert_eq!(
             decision.status_message,
@@ -305,4 +395,67 @@ mod tests {
         handler.process_next(&target).await.expect("process_next");
         assert_eq!(handler.queue_count(), 1);
     }
+
+    #[tokio::test]
+    async fn failed_update_is_reported_and_kept_for_retry() {
+        let handler = PresenceHandler::new(None);
+        let target = MockPresenceTarget {
+            fail_updates: true,
+            ..MockPresenceTarget::default()
+        };
+        handler.enqueue_user(DiscordPresence {
+            user_id: "1".to_string(),
+            username: Some("alice".to_string()),
+            state: DiscordPresenceState::Offline,
+            activities: vec![],
+        });
+
+        assert!(handler.process_next(&target).await.is_err());
+        assert_eq!(handler.queue_count(), 1);
+    }
+
+    #[test]
+    fn configured_dnd_mapping_uses_suffix_and_presence() {
+        let handler = PresenceHandler::with_mapping(
+            None,
+            PresenceMappingConfig {
+                dnd: PresenceMappingEntry {
+                    presence: MatrixPresenceState::Unavailable,
+                    status_prefix: None,
+                    status_suffix: Some("(Do Not Disturb)".to_string()),
+                },
+                ..PresenceMappingConfig::default()
+            },
+        );
+        let presence = DiscordPresence {
+            user_id: "1".to_string(),
+            username: None,
+            state: DiscordPresenceState::Dnd,
+            activities: vec![DiscordActivity {
+                kind: "PLAYING".to_string(),
+                name: "chess".to_string(),
+                url: None,
+            }],
+        };
+
+        let decision = handler.map_presence(&presence, None);
+        assert_eq!(decision.presence, MatrixPresenceState::Unavailable);
+        assert_eq!(decision.status_message, "Playing chess (Do Not Disturb)");
+        assert!(!decision.should_drop);
+    }
+
+    #[test]
+    fn user_override_replaces_mapped_presence() {
+        let presence = DiscordPresence {
+            user_id: "1".to_string(),
+            username: None,
+            state: DiscordPresenceState::Online,
+            activities: vec![],
+        };
+
+        let decision =
+            PresenceHandler::new(None).map_presence(&presence, Some(MatrixPresenceState::Offline));
+        assert_eq!(decision.presence, MatrixPresenceState::Offline);
+        assert!(!decision.should_drop);
+    }
 }
@@ -3,11 +3,13 @@ use std::time::Duration;
 
 use parking_lot::Mutex;
 use tokio::sync::oneshot;
-use tracing::warn;
+use tracing::{info, warn};
 
-use crate::discord::DiscordClient;
+use crate::discord::{DiscordChannel, DiscordClient};
 
 const DEFAULT_PERMISSION_TIMEOUT: Duration = Duration::from_secs(300);
+/// Prefix of the custom ids on the Approve/Deny buttons of approval prompts.
+const APPROVAL_BUTTON_PREFIX: &str = "bridge_approval";
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ApprovalResponseStatus {
@@ -27,12 +29,27 @@ pub enum ProvisioningError {
     Cancelled,
 }
 
+struct Decision {
+    allow: bool,
+    decided_by: String,
+}
+
 struct PendingRequest {
-    decision_tx: oneshot::Sender<bool>,
+    decision_tx: oneshot::Sender<Decision>,
+    /// Discord user the request was escalated to by DM, who may answer it
+    /// without holding permissions in the channel.
+    escalated_to: Option<String>,
+}
+
+/// A prompt message carrying approval buttons.
+struct Prompt {
+    channel_id: String,
+    message_id: String,
 }
 
 pub struct ProvisioningCoordinator {
     timeout: Duration,
+    escalate_after: Option<Duration>,
     pending: Mutex<HashMap<String, PendingRequest>>,
 }
 
@@ -46,42 +63,187 @@ impl ProvisioningCoordinator {
     pub fn new(timeout: Duration) -> Self {
         Self {
             timeout,
+            escalate_after: None,
             pending: Mutex::new(HashMap::new()),
         }
     }
 
+    /// DMs the guild owner with the approval buttons when nobody has
+    /// answered in the channel after `after`.
+    pub fn with_escalation_after(mut self, after: Duration) -> Self {
+        self.escalate_after = Some(after);
+        self
+    }
+
     pub async fn ask_bridge_permission(
         &self,
         discord_client: &DiscordClient,
-        channel_id: &str,
+        channel: &DiscordChannel,
         requestor: &str,
+        matrix_room_id: &str,
     ) -> Result<(), ProvisioningError> {
-        let (decision_tx, decision_rx) = oneshot::channel();
-        self.pending
-            .lock()
-            .insert(channel_id.to_string(), PendingRequest { decision_tx });
+        let channel_id = channel.id.as_str();
+        let (decision_tx, mut decision_rx) = oneshot::channel();
+        self.pending.lock().insert(
+            channel_id.to_string(),
+            PendingRequest {
+                decision_tx,
+                escalated_to: None,
+            },
+        );
 
         let timeout_minutes = self.timeout.as_secs().max(60).div_ceil(60);
         let prompt = format!(
-            "{requestor} on matrix would like to bridge this channel. Someone with permission to manage webhooks please reply with `!matrix approve` or `!matrix deny` in the next {timeout_minutes} minutes."
+            "{requestor} on matrix would like to bridge this channel. Someone with the Manage Server permission please press Approve or Deny (or reply with `!matrix approve` or `!matrix deny`) in the next {timeout_minutes} minutes."
         );
 
-        if let Err(err) = discord_client.send_message(channel_id, &prompt).await {
-            warn!(
-                "failed to deliver bridge approval prompt to discord channel {}: {}",
-                channel_id, err
-            );
-            self.pending.lock().remove(channel_id);
-            return Err(ProvisioningError::DeliveryFailed);
+        let mut prompts = match discord_client
+            .send_approval_prompt(
+                channel_id,
+                &prompt,
+                &approval_button_id(channel_id, true),
+                &approval_button_id(channel_id, false),
+            )
+            .await
+        {
+            Ok(message_id) => vec![Prompt {
+                channel_id: channel_id.to_string(),
+                message_id,
+            }],
+            Err(err) => {
+                warn!(
+                    "failed to deliver bridge approval prompt to discord channel {}: {}",
+                    channel_id, err
+                );
+                self.pending.lock().remove(channel_id);
+                return Err(ProvisioningError::DeliveryFailed);
+            }
+        };
+
+        let mut remaining = self.timeout;
+        let mut answer = None;
+        if let Some(soft) = self.escalate_after.filter(|soft| *soft < self.timeout) {
+            match tokio::time::timeout(soft, &mut decision_rx).await {
+                Ok(received) => answer = Some(received),
+                Err(_) => {
+                    remaining -= soft;
+                    if let Some(prompt) = self
+                        .escalate_to_owner(
+                            discord_client,
+                            channel,
+                            requestor,
+                            matrix_room_id,
+                            remaining,
+                        )
+                        .await
+                    {
+                        prompts.push(prompt);
+                    }
+                }
+            }
         }
+        let answer = match answer {
+            Some(received) => Ok(received),
+            None => tokio::time::timeout(remaining, &mut decision_rx).await,
+        };
 
-        match tokio::time::timeout(self.timeout, decision_rx).await {
-            Ok(Ok(true)) => Ok(()),
-            Ok(Ok(false)) => Err(ProvisioningError::Declined),
-            Ok(Err(_)) => Err(ProvisioningError::Cancelled),
+        let (result, outcome) = match answer {
+            Ok(Ok(decision)) if decision.allow => {
+                (Ok(()), format!("approved by <@{}>", decision.decided_by))
+            }
+            Ok(Ok(decision)) => (
+                Err(ProvisioningError::Declined),
+                format!("declined by <@{}>", decision.decided_by),
+            ),
+            Ok(Err(_)) => (Err(ProvisioningError::Cancelled), "cancelled".to_string()),
             Err(_) => {
                 self.pending.lock().remove(channel_id);
-                Err(ProvisioningError::TimedOut)
+                (
+                    Err(ProvisioningError::TimedOut),
+                    "expired without a response".to_string(),
+                )
+            }
+        };
+
+        let resolved = format!(
+            "{requestor} on matrix asked to bridge <#{channel_id}> to {matrix_room_id}: {outcome}."
+        );
+        for prompt in prompts {
+            if let Err(err) = discord_client
+                .close_approval_prompt(&prompt.channel_id, &prompt.message_id, &resolved)
+                .await
+            {
+                warn!(
+                    "failed to update bridge approval prompt in discord channel {}: {}",
+                    prompt.channel_id, err
+                );
+            }
+        }
+        result
+    }
+
+    /// Sends the guild owner a DM with the same approval buttons. Failures
+    /// are logged and leave the in-channel prompt as the only way to answer.
+    async fn escalate_to_owner(
+        &self,
+        discord_client: &DiscordClient,
+        channel: &DiscordChannel,
+        requestor: &str,
+        matrix_room_id: &str,
+        remaining: Duration,
+    ) -> Option<Prompt> {
+        let owner_id = match discord_client.guild_owner_id(&channel.guild_id).await {
+            Ok(owner_id) => owner_id,
+            Err(err) => {
+                warn!(
+                    "failed to look up owner of discord guild {} for bridge approval: {}",
+                    channel.guild_id, err
+                );
+                return None;
+            }
+        };
+
+        match self.pending.lock().get_mut(&channel.id) {
+            Some(pending) => pending.escalated_to = Some(owner_id.clone()),
+            None => return None,
+        }
+
+        let remaining_minutes = remaining.as_secs().max(60).div_ceil(60);
+        let prompt = format!(
+            "{requestor} on matrix would like to bridge <#{}> (#{}) to the Matrix room {matrix_room_id}, and nobody has answered in the channel yet. Please approve or deny the request in the next {remaining_minutes} minutes.",
+            channel.id, channel.name
+        );
+        let sent = async {
+            let dm_channel_id = discord_client.open_dm_channel(&owner_id).await?;
+            let message_id = discord_client
+                .send_approval_prompt(
+                    &dm_channel_id,
+                    &prompt,
+                    &approval_button_id(&channel.id, true),
+                    &approval_button_id(&channel.id, false),
+                )
+                .await?;
+            anyhow::Ok(Prompt {
+                channel_id: dm_channel_id,
+                message_id,
+            })
+        }
+        .await;
+
+        match sent {
+            Ok(prompt) => {
+                info!(
+                    "escalated bridge approval for discord channel {} to guild owner {}",
+                    channel.id, owner_id
+                );
+                Some(prompt)
+            }
+            Err(err) => {
+                warn!(
+                    "failed to DM guild owner {} about bridge approval for channel {}: {}",
+                    owner_id, channel.id, err
+                );
+                None
             }
         }
     }
@@ -90,11 +252,84 @@ impl ProvisioningCoordinator {
         self.pending.lock().contains_key(channel_id)
     }
 
-    pub fn mark_approval(&self, channel_id: &str, allow: bool) -> ApprovalResponseStatus {
+    /// Whether the pending request for `channel_id` was escalated by DM to
+    /// `discord_user_id`.
+    pub fn is_escalated_to(&self, channel_id: &str, discord_user_id: &str) -> bool {
+        self.pending
+            .lock()
+            .get(channel_id)
+            .is_some_and(|pending| pending.escalated_to.as_deref() == Some(discord_user_id))
+    }
+
+    /// Resolves the pending request for `channel_id` on behalf of the Discord
+    /// user `decided_by`.
+    pub fn mark_approval(
+        &self,
+        channel_id: &str,
+        allow: bool,
+        decided_by: &str,
+    ) -> ApprovalResponseStatus {
         let Some(pending) = self.pending.lock().remove(channel_id) else {
             return ApprovalResponseStatus::Expired;
         };
-        let _ = pending.decision_tx.send(allow);
+        let _ = pending.decision_tx.send(Decision {
+            allow,
+            decided_by: decided_by.to_string(),
+        });
         ApprovalResponseStatus::Applied
     }
 }
+
+/// Custom id of the Approve or Deny button for the request on `channel_id`.
+/// The channel is part of the id so a click in a DM finds its request.
+pub fn approval_button_id(channel_id: &str, allow: bool) -> String {
+    let action = if allow { "approve" } else { "deny" };
+    format!("{APPROVAL_BUTTON_PREFIX}:{action}:{channel_id}")
+}
+
+/// The channel and decision carried by an approval button, or `None` for any
+/// other component.
+pub fn parse_approval_button(custom_id: &str) -> Option<(String, bool)> {
+    let mut parts = custom_id.splitn(3, ':');
+    if parts.next()? != APPROVAL_BUTTON_PREFIX {
+        return None;
+    }
+    let allow = match parts.next()? {
+        "approve" => true,
+        "deny" => false,
+        _ => return None,
+    };
+    let channel_id = parts.next().filter(|id| !id.is_empty())?;
+    Some((channel_id.to_string(), allow))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ApprovalResponseStatus, ProvisioningCoordinator, approval_button_id, parse_approval_button,
+    };
+
+    #[test]
+    fn approval_buttons_round_trip() {
+        assert_eq!(
+            parse_approval_button(&approval_button_id("123", true)),
+            Some(("123".to_string(), true))
+        );
+        assert_eq!(
+            parse_approval_button(&approval_button_id("123", false)),
+            Some(("123".to_string(), false))
+        );
+        assert_eq!(parse_approval_button("bridge_approval:approve:"), None);
+        assert_eq!(parse_approval_button("bridge_approval:maybe:123"), None);
+        assert_eq!(parse_approval_button("something_else"), None);
+    }
+
+    #[test]
+    fn approval_without_pending_request_is_expired() {
+        let coordinator = ProvisioningCoordinator::default();
+        assert_eq!(
+            coordinator.mark_approval("123", true, "456"),
+            ApprovalResponseStatus::Expired
+        );
+    }
+}
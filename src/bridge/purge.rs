@@ -0,0 +1,256 @@
+//! Right-to-erasure tooling behind `purge-user` and `POST /admin/users/purge`:
+//! removes everything the bridge holds or relayed for one person.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::json;
+use tracing::{info, warn};
+
+use crate::db::{AuditLogEntry, AuditSource, DatabaseManager, UserMapping};
+use crate::matrix::{HomeserverAdminClient, MatrixAppservice};
+
+/// The person to erase, by either of their identities.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PurgeTarget {
+    DiscordId(String),
+    MatrixId(String),
+}
+
+impl PurgeTarget {
+    /// Builds the target from the `--discord-id`/`--mxid` pair, exactly one
+    /// of which must be set.
+    pub fn from_ids(discord_id: Option<String>, mxid: Option<String>) -> Result<Self> {
+        match (discord_id, mxid) {
+            (Some(discord_id), None) => Ok(Self::DiscordId(discord_id)),
+            (None, Some(mxid)) => Ok(Self::MatrixId(mxid)),
+            _ => bail!("exactly one of the Discord id or the Matrix id is required"),
+        }
+    }
+
+    fn id(&self) -> &str {
+        match self {
+            Self::DiscordId(id) | Self::MatrixId(id) => id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PurgeReport {
+    pub user_mappings: usize,
+    pub redacted: usize,
+    pub redaction_failures: usize,
+    pub message_mappings: usize,
+    pub avatar_removed: bool,
+}
+
+impl fmt::Display for PurgeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "removed {} user mappings and {} message mappings, redacted {} events ({} failed), avatar {}",
+            self.user_mappings,
+            self.message_mappings,
+            self.redacted,
+            self.redaction_failures,
+            if self.avatar_removed {
+                "removed"
+            } else {
+                "not removed"
+            }
+        )
+    }
+}
+
+/// Erases `target`: redacts their bridged events in every bridged room,
+/// drops the message and user mappings that point at them, clears the
+/// ghost's avatar and records who asked for it in the audit log.
+pub async fn purge_user(
+    matrix: &MatrixAppservice,
+    db: &DatabaseManager,
+    target: &PurgeTarget,
+    actor: &str,
+    source: AuditSource,
+) -> Result<PurgeReport> {
+    let user_store = db.user_store();
+    let mapping = match target {
+        PurgeTarget::DiscordId(id) => user_store.get_user_by_discord_id(id).await?,
+        PurgeTarget::MatrixId(id) => user_store.get_user_by_matrix_id(id).await?,
+    };
+    let discord_user_id = match target {
+        PurgeTarget::DiscordId(id) => Some(id.clone()),
+        PurgeTarget::MatrixId(_) => mapping.as_ref().map(|m| m.discord_user_id.clone()),
+    };
+    let senders = purge_senders(target, mapping.as_ref(), |id| matrix.ghost_user_id(id));
+
+    let mut report = PurgeReport::default();
+    let delay = Duration::from_millis(matrix.config().limits.redaction_delay);
+    let message_store = db.message_store();
+    let rooms = db.room_store().list_room_mappings(i64::MAX, 0).await?;
+    for room in &rooms {
+        for sender in &senders {
+            let mut from = None;
+            loop {
+                let (events, next) = match matrix
+                    .get_events_by_sender(&room.matrix_room_id, sender, from.as_deref())
+                    .await
+                {
+                    Ok(page) => page,
+                    Err(err) => {
+                        warn!("{}", err);
+                        break;
+                    }
+                };
+                for event_id in &events {
+                    match matrix
+                        .redact_message(&room.matrix_room_id, event_id, Some("User data erased"))
+                        .await
+                    {
+                        Ok(()) => report.redacted += 1,
+                        Err(err) => {
+                            warn!("failed to redact {}: {}", event_id, err);
+                            report.redaction_failures += 1;
+                        }
+                    }
+                    if message_store
+                        .get_by_matrix_event_id(event_id)
+                        .await?
+                        .is_some()
+                    {
+                        message_store.delete_by_matrix_event_id(event_id).await?;
+                        report.message_mappings += 1;
+                    }
+                    tokio::time::sleep(delay).await;
+                }
+                match next {
+                    Some(next) => from = Some(next),
+                    None => break,
+                }
+            }
+        }
+    }
+
+    if let Some(discord_user_id) = &discord_user_id {
+        report.avatar_removed = remove_ghost_avatar(matrix, discord_user_id).await;
+    }
+
+    if let Some(mapping) = &mapping {
+        user_store.delete_user_mapping(mapping.id).await?;
+        report.user_mappings += 1;
+    }
+
+    let entry = AuditLogEntry {
+        id: 0,
+        actor: actor.to_string(),
+        source,
+        action: "purge_user".to_string(),
+        target: Some(target.id().to_string()),
+        parameters: json!({ "senders": senders, "report": report }),
+        created_at: Utc::now(),
+    };
+    if let Err(err) = db.audit_store().record_audit_entry(&entry).await {
+        warn!(
+            "failed to record audit entry for purge of {}: {}",
+            target.id(),
+            err
+        );
+    }
+    info!("purged {}: {}", target.id(), report);
+    Ok(report)
+}
+
+/// Clears the ghost's avatar and, when the homeserver admin API is
+/// configured, deletes the uploaded image itself.
+async fn remove_ghost_avatar(matrix: &MatrixAppservice, discord_user_id: &str) -> bool {
+    let ghost = matrix.ghost_user_id(discord_user_id);
+    let avatar = match matrix.get_user_profile(&ghost).await {
+        Ok(Some((_, Some(avatar)))) if !avatar.is_empty() => avatar,
+        _ => return false,
+    };
+    if let Err(err) = matrix.set_ghost_avatar(discord_user_id, "").await {
+        warn!("failed to clear the avatar of {}: {}", ghost, err);
+        return false;
+    }
+    if let Some(admin) = HomeserverAdminClient::from_config(&matrix.config())
+        && let Err(err) = admin.delete_media(&avatar).await
+    {
+        warn!("{}", err);
+    }
+    true
+}
+
+/// The Matrix users whose events belong to the person: their ghost and
+/// their own Matrix account, when known.
+fn purge_senders(
+    target: &PurgeTarget,
+    mapping: Option<&UserMapping>,
+    ghost_user_id: impl Fn(&str) -> String,
+) -> Vec<String> {
+    let mut senders = BTreeSet::new();
+    match target {
+        PurgeTarget::DiscordId(id) => {
+            senders.insert(ghost_user_id(id));
+        }
+        PurgeTarget::MatrixId(id) => {
+            senders.insert(id.clone());
+        }
+    }
+    if let Some(mapping) = mapping {
+        senders.insert(ghost_user_id(&mapping.discord_user_id));
+        senders.insert(mapping.matrix_user_id.clone());
+    }
+    senders.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::{PurgeTarget, purge_senders};
+    use crate::db::UserMapping;
+
+    fn ghost(id: &str) -> String {
+        format!("@_discord_{id}:example.org")
+    }
+
+    #[test]
+    fn senders_cover_both_identities() {
+        let mapping = UserMapping {
+            id: 1,
+            matrix_user_id: "@alice:example.org".to_string(),
+            discord_user_id: "42".to_string(),
+            discord_username: "alice".to_string(),
+            discord_discriminator: "0".to_string(),
+            discord_avatar: None,
+            presence_override: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        assert_eq!(
+            purge_senders(
+                &PurgeTarget::MatrixId("@alice:example.org".to_string()),
+                Some(&mapping),
+                ghost
+            ),
+            ["@_discord_42:example.org", "@alice:example.org"]
+        );
+        assert_eq!(
+            purge_senders(&PurgeTarget::DiscordId("7".to_string()), None, ghost),
+            ["@_discord_7:example.org"]
+        );
+    }
+
+    #[test]
+    fn target_needs_exactly_one_id() {
+        assert_eq!(
+            PurgeTarget::from_ids(Some("7".to_string()), None).unwrap(),
+            PurgeTarget::DiscordId("7".to_string())
+        );
+        assert!(PurgeTarget::from_ids(None, None).is_err());
+        assert!(PurgeTarget::from_ids(Some("7".to_string()), Some("@a:b".to_string())).is_err());
+    }
+}
@@ -0,0 +1,156 @@
+//! Last-read markers of Matrix users in bridged rooms, taken from the
+//! `m.receipt` EDUs the homeserver pushes to the appservice. Discord gives
+//! bots no read state of their own, so nothing is marked read there and no
+//! receipts come back the other way.
+
+use std::collections::{BTreeMap, HashMap};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::Value;
+
+/// One user's public read receipt from an `m.receipt` EDU.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatrixReadReceipt {
+    pub user_id: String,
+    pub event_id: String,
+    /// Milliseconds since the epoch, as sent by the homeserver.
+    pub ts: Option<i64>,
+}
+
+/// The event a user read up to in a room.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReadMarker {
+    pub event_id: String,
+    pub ts: Option<i64>,
+}
+
+/// Public `m.read` receipts in an `m.receipt` EDU's content, which maps
+/// event ids to receipt types to user ids.
+pub fn parse_receipts(content: &Value) -> Vec<MatrixReadReceipt> {
+    let Some(events) = content.as_object() else {
+        return Vec::new();
+    };
+    let mut receipts = Vec::new();
+    for (event_id, types) in events {
+        let Some(users) = types.get("m.read").and_then(Value::as_object) else {
+            continue;
+        };
+        for (user_id, receipt) in users {
+            // Receipts for a thread do not move the user's main marker.
+            if receipt
+                .get("thread_id")
+                .and_then(Value::as_str)
+                .is_some_and(|thread_id| thread_id != "main")
+            {
+                continue;
+            }
+            receipts.push(MatrixReadReceipt {
+                user_id: user_id.clone(),
+                event_id: event_id.clone(),
+                ts: receipt.get("ts").and_then(Value::as_i64),
+            });
+        }
+    }
+    receipts
+}
+
+/// Keeps each user's latest read marker per room.
+#[derive(Default)]
+pub struct ReceiptHandler {
+    markers: Mutex<HashMap<String, HashMap<String, ReadMarker>>>,
+}
+
+impl ReceiptHandler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores `receipt` unless the user already has a later one in the room.
+    pub fn record(&self, room_id: &str, receipt: MatrixReadReceipt) {
+        let mut markers = self.markers.lock();
+        let room = markers.entry(room_id.to_string()).or_default();
+        if let Some(current) = room.get(&receipt.user_id)
+            && current
+                .ts
+                .zip(receipt.ts)
+                .is_some_and(|(current, new)| new < current)
+        {
+            return;
+        }
+        room.insert(
+            receipt.user_id,
+            ReadMarker {
+                event_id: receipt.event_id,
+                ts: receipt.ts,
+            },
+        );
+    }
+
+    /// The room's read markers keyed by Matrix user id.
+    pub fn markers(&self, room_id: &str) -> BTreeMap<String, ReadMarker> {
+        self.markers
+            .lock()
+            .get(room_id)
+            .map(|room| {
+                room.iter()
+                    .map(|(user_id, marker)| (user_id.clone(), marker.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Drops the markers of a room that is no longer bridged.
+    pub fn forget_room(&self, room_id: &str) {
+        self.markers.lock().remove(room_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{MatrixReadReceipt, ReceiptHandler, parse_receipts};
+
+    #[test]
+    fn only_public_main_timeline_receipts_are_parsed() {
+        let receipts = parse_receipts(&json!({
+            "$a": {
+                "m.read": { "@alice:example.org": { "ts": 10 } },
+                "m.read.private": { "@bob:example.org": { "ts": 11 } },
+            },
+            "$b": {
+                "m.read": {
+                    "@carol:example.org": { "ts": 12, "thread_id": "$root" },
+                    "@dave:example.org": { "ts": 13, "thread_id": "main" },
+                },
+            },
+        }));
+        let users: Vec<_> = receipts
+            .iter()
+            .map(|receipt| receipt.user_id.as_str())
+            .collect();
+        assert_eq!(users, ["@alice:example.org", "@dave:example.org"]);
+        assert_eq!(receipts[0].event_id, "$a");
+        assert_eq!(receipts[0].ts, Some(10));
+    }
+
+    #[test]
+    fn older_receipts_do_not_move_the_marker_back() {
+        let handler = ReceiptHandler::new();
+        let receipt = |event_id: &str, ts| MatrixReadReceipt {
+            user_id: "@alice:example.org".to_string(),
+            event_id: event_id.to_string(),
+            ts: Some(ts),
+        };
+        handler.record("!room", receipt("$new", 20));
+        handler.record("!room", receipt("$old", 10));
+        assert_eq!(
+            handler.markers("!room")["@alice:example.org"].event_id,
+            "$new"
+        );
+
+        handler.forget_room("!room");
+        assert!(handler.markers("!room").is_empty());
+    }
+}
@@ -0,0 +1,116 @@
+//! Unwraps messages other bridges relay into Discord as their own bot,
+//! e.g. `<alice> hello`, so each author gets a ghost of their own.
+
+use regex::Regex;
+
+use crate::config::RelayExtractorConfig;
+
+/// A relayed message split into who wrote it and what they wrote.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelayedMessage {
+    pub name: String,
+    pub text: String,
+}
+
+struct RelayExtractor {
+    pattern: Regex,
+    bot_ids: Vec<String>,
+}
+
+/// The configured `bridge.relay_extractors`, compiled once.
+#[derive(Default)]
+pub struct RelayExtractors {
+    extractors: Vec<RelayExtractor>,
+}
+
+impl RelayExtractors {
+    pub fn new(configs: &[RelayExtractorConfig]) -> Result<Self, regex::Error> {
+        let extractors = configs
+            .iter()
+            .map(|config| {
+                Ok(RelayExtractor {
+                    pattern: Regex::new(&config.pattern)?,
+                    bot_ids: config.bot_ids.clone(),
+                })
+            })
+            .collect::<Result<_, regex::Error>>()?;
+        Ok(Self { extractors })
+    }
+
+    /// Applies the first extractor that covers `author_id` and matches
+    /// `content`. Empty names or texts do not count as a match.
+    pub fn unwrap(&self, author_id: &str, content: &str) -> Option<RelayedMessage> {
+        self.extractors
+            .iter()
+            .filter(|extractor| {
+                extractor.bot_ids.is_empty() || extractor.bot_ids.iter().any(|id| id == author_id)
+            })
+            .find_map(|extractor| {
+                let captures = extractor.pattern.captures(content)?;
+                let name = captures.name("name")?.as_str().trim();
+                let text = captures.name("text")?.as_str().trim();
+                (!name.is_empty() && !text.is_empty()).then(|| RelayedMessage {
+                    name: name.to_string(),
+                    text: text.to_string(),
+                })
+            })
+    }
+}
+
+/// The ghost id for `name` relayed by `bot_id`: the bot's id followed by
+/// the name reduced to characters a Matrix localpart allows.
+pub fn relayed_sender_id(bot_id: &str, name: &str) -> String {
+    let mut id = format!("{bot_id}_");
+    for c in name.chars() {
+        match c.to_ascii_lowercase() {
+            c @ ('a'..='z' | '0'..='9' | '.' | '-') => id.push(c),
+            c if c.is_ascii() => id.push('_'),
+            c => id.push_str(&format!("={:x}", c as u32)),
+        }
+    }
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RelayExtractors, RelayedMessage, relayed_sender_id};
+    use crate::config::RelayExtractorConfig;
+
+    fn extractors() -> RelayExtractors {
+        RelayExtractors::new(&[
+            RelayExtractorConfig {
+                pattern: r"^<(?P<name>[^>]+)> (?P<text>.*)$".to_string(),
+                bot_ids: vec!["10".to_string()],
+            },
+            RelayExtractorConfig {
+                pattern: r"^\*\*(?P<name>[^*]+)\*\*: (?P<text>.*)$".to_string(),
+                bot_ids: Vec::new(),
+            },
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn relayed_messages_are_unwrapped_per_bot() {
+        let extractors = extractors();
+        assert_eq!(
+            extractors.unwrap("10", "<alice> hello there"),
+            Some(RelayedMessage {
+                name: "alice".to_string(),
+                text: "hello there".to_string(),
+            })
+        );
+        assert_eq!(extractors.unwrap("11", "<alice> hello there"), None);
+        assert_eq!(
+            extractors.unwrap("11", "**bob**: hi").map(|m| m.name),
+            Some("bob".to_string())
+        );
+        assert_eq!(extractors.unwrap("10", "plain message"), None);
+    }
+
+    #[test]
+    fn relayed_sender_ids_are_valid_localparts() {
+        assert_eq!(relayed_sender_id("10", "Alice|away"), "10_alice_away");
+        assert_eq!(relayed_sender_id("10", "zoë"), "10_zo=eb");
+    }
+}
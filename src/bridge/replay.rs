@@ -0,0 +1,77 @@
+//! Recovery after an outage: `!bridgectl replay` and `POST /admin/replay`
+//! read a channel's Discord history back from a point in time and bridge
+//! the messages that never made it to Matrix, i.e. those without a message
+//! mapping. Discord keeps the history even while our gateway is down.
+
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// The most messages one replay reads from a channel's history.
+pub const REPLAY_MESSAGE_LIMIT: usize = 1000;
+
+/// Parses the start of the replay window: an RFC 3339 timestamp or Unix
+/// seconds.
+pub fn parse_since(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(at) = DateTime::parse_from_rfc3339(value) {
+        return Some(at.with_timezone(&Utc));
+    }
+    value
+        .parse::<i64>()
+        .ok()
+        .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ReplayReport {
+    /// Messages read from the channel's history.
+    pub fetched: usize,
+    pub already_bridged: usize,
+    pub replayed: usize,
+    pub failed: usize,
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "read {} Discord messages: {} replayed, {} already bridged, {} failed",
+            self.fetched, self.replayed, self.already_bridged, self.failed
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReplayReport, parse_since};
+
+    #[test]
+    fn since_accepts_rfc3339_and_unix_seconds() {
+        assert_eq!(
+            parse_since("2024-05-01T12:00:00+02:00")
+                .unwrap()
+                .to_rfc3339(),
+            "2024-05-01T10:00:00+00:00"
+        );
+        assert_eq!(
+            parse_since("1714557600").unwrap().to_rfc3339(),
+            "2024-05-01T10:00:00+00:00"
+        );
+        assert_eq!(parse_since("yesterday"), None);
+    }
+
+    #[test]
+    fn report_counts_every_message() {
+        let report = ReplayReport {
+            fetched: 5,
+            already_bridged: 2,
+            replayed: 2,
+            failed: 1,
+        };
+        assert_eq!(
+            report.to_string(),
+            "read 5 Discord messages: 2 replayed, 2 already bridged, 1 failed"
+        );
+    }
+}
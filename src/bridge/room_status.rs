@@ -0,0 +1,111 @@
+use chrono::{DateTime, Utc};
+
+use crate::db::RoomMapping;
+
+/// What `!discord status` and `!matrix status` report about one bridged
+/// room, gathered from the stores and the running bridge.
+#[derive(Debug, Clone)]
+pub struct RoomStatus<'a> {
+    pub mapping: &'a RoomMapping,
+    /// `None` when messages are not sent through a webhook, otherwise
+    /// whether the channel already has one, or why looking failed.
+    pub webhook: Option<Result<bool, String>>,
+    /// When a message last made it across this room, since startup.
+    pub last_matrix_to_discord: Option<DateTime<Utc>>,
+    pub last_discord_to_matrix: Option<DateTime<Utc>>,
+    /// Bridging is paused for maintenance since then.
+    pub maintenance_since: Option<DateTime<Utc>>,
+    pub encrypted: bool,
+}
+
+pub fn room_status_reply(status: &RoomStatus<'_>) -> String {
+    let mapping = status.mapping;
+    let at =
+        |at: Option<DateTime<Utc>>| at.map_or_else(|| "never".to_string(), |at| at.to_rfc3339());
+    let yes_no = |flag: bool| if flag { "yes" } else { "no" };
+
+    let webhook = match &status.webhook {
+        None => "not used".to_string(),
+        Some(Ok(true)) => "ok".to_string(),
+        Some(Ok(false)) => "missing, made with the next message".to_string(),
+        Some(Err(err)) => format!("failing: {err}"),
+    };
+    let paused = match status.maintenance_since {
+        Some(since) => format!("yes, maintenance mode since {}", since.to_rfc3339()),
+        None => "no".to_string(),
+    };
+
+    format!(
+        "Matrix room: {}\n\
+         Discord channel: #{} ({}) in guild {}\n\
+         Bridged: {} by {} via {}\n\
+         Last active: {}\n\
+         Last Matrix → Discord: {}\n\
+         Last Discord → Matrix: {}\n\
+         Webhook: {}\n\
+         Paused: {}\n\
+         Encrypted: {}",
+        mapping.matrix_room_id,
+        mapping.discord_channel_name,
+        mapping.discord_channel_id,
+        mapping.discord_guild_id,
+        mapping.created_at.to_rfc3339(),
+        mapping.created_by.as_deref().unwrap_or("unknown"),
+        mapping
+            .origin
+            .map_or("unknown origin", |origin| origin.as_str()),
+        at(mapping.last_active_at),
+        at(status.last_matrix_to_discord),
+        at(status.last_discord_to_matrix),
+        webhook,
+        paused,
+        yes_no(status.encrypted),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::{RoomStatus, room_status_reply};
+    use crate::db::{RoomMapping, RoomOrigin};
+
+    #[test]
+    fn status_lists_the_mapping_and_health() {
+        let created_at = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let mapping = RoomMapping {
+            id: 7,
+            matrix_room_id: "!room:example.org".to_string(),
+            discord_channel_id: "100".to_string(),
+            discord_channel_name: "general".to_string(),
+            discord_guild_id: "1".to_string(),
+            created_by: Some("@alice:example.org".to_string()),
+            origin: Some(RoomOrigin::SelfService),
+            last_active_at: None,
+            created_at,
+            updated_at: created_at,
+        };
+        let mut status = RoomStatus {
+            mapping: &mapping,
+            webhook: Some(Ok(true)),
+            last_matrix_to_discord: Some(created_at),
+            last_discord_to_matrix: None,
+            maintenance_since: None,
+            encrypted: false,
+        };
+
+        let reply = room_status_reply(&status);
+        assert!(reply.starts_with("Matrix room: !room:example.org\n"));
+        assert!(reply.contains("Discord channel: #general (100) in guild 1\n"));
+        assert!(reply.contains("by @alice:example.org via self_service\n"));
+        assert!(reply.contains("Last Matrix → Discord: 2024-05-01T12:00:00+00:00\n"));
+        assert!(reply.contains("Last Discord → Matrix: never\n"));
+        assert!(reply.contains("Webhook: ok\nPaused: no\nEncrypted: no"));
+
+        status.webhook = Some(Err("missing permissions".to_string()));
+        status.maintenance_since = Some(created_at);
+        let reply = room_status_reply(&status);
+        assert!(reply.contains("Webhook: failing: missing permissions\n"));
+        assert!(reply.contains("Paused: yes, maintenance mode since 2024-05-01T12:00:00+00:00\n"));
+    }
+}
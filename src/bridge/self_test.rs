@@ -0,0 +1,220 @@
+//! `--self-test`: exercises each capability the bridge relies on with the
+//! configured credentials and reports which ones work. Appservice users
+//! cannot be deleted, so every run reuses the same test ghost.
+
+use std::fmt;
+
+use anyhow::Result;
+
+use crate::discord::DiscordClient;
+use crate::matrix::MatrixAppservice;
+
+const TEST_GHOST_ID: &str = "selftest";
+const TEST_ROOM_NAME: &str = "Discord bridge self-test";
+const TEST_MESSAGE: &str = "Discord bridge self-test message";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckOutcome {
+    Passed(String),
+    Failed(String),
+    Skipped(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelfTestCheck {
+    pub name: &'static str,
+    pub outcome: CheckOutcome,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SelfTestReport {
+    pub checks: Vec<SelfTestCheck>,
+}
+
+impl SelfTestReport {
+    pub fn passed(&self) -> bool {
+        !self
+            .checks
+            .iter()
+            .any(|check| matches!(check.outcome, CheckOutcome::Failed(_)))
+    }
+
+    fn record<T>(
+        &mut self,
+        name: &'static str,
+        result: Result<T>,
+        detail: impl Fn(&T) -> String,
+    ) -> Option<T> {
+        match result {
+            Ok(value) => {
+                self.push(name, CheckOutcome::Passed(detail(&value)));
+                Some(value)
+            }
+            Err(err) => {
+                self.push(name, CheckOutcome::Failed(format!("{:#}", err)));
+                None
+            }
+        }
+    }
+
+    fn skip(&mut self, name: &'static str, reason: &str) {
+        self.push(name, CheckOutcome::Skipped(reason.to_string()));
+    }
+
+    fn push(&mut self, name: &'static str, outcome: CheckOutcome) {
+        self.checks.push(SelfTestCheck { name, outcome });
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            let (label, detail) = match &check.outcome {
+                CheckOutcome::Passed(detail) => ("PASS", detail),
+                CheckOutcome::Failed(detail) => ("FAIL", detail),
+                CheckOutcome::Skipped(detail) => ("SKIP", detail),
+            };
+            if detail.is_empty() {
+                writeln!(f, "{} {}", label, check.name)?;
+            } else {
+                writeln!(f, "{} {}: {}", label, check.name, detail)?;
+            }
+        }
+        write!(
+            f,
+            "self-test {}",
+            if self.passed() { "passed" } else { "failed" }
+        )
+    }
+}
+
+/// Runs every check; a failed step skips the steps that depend on it.
+/// Webhook creation is only tested when `test_channel` names a channel.
+pub async fn run_self_test(
+    matrix: &MatrixAppservice,
+    discord: &DiscordClient,
+    test_channel: Option<&str>,
+) -> SelfTestReport {
+    let mut report = SelfTestReport::default();
+    check_matrix(matrix, &mut report).await;
+    check_discord(discord, test_channel, &mut report).await;
+    report
+}
+
+async fn check_matrix(matrix: &MatrixAppservice, report: &mut SelfTestReport) {
+    let ghost = matrix.ghost_user_id(TEST_GHOST_ID);
+    let registered = matrix
+        .ensure_ghost_user_registered(TEST_GHOST_ID, Some("Bridge self-test"))
+        .await;
+    let registered = match registered {
+        Ok(()) => match matrix.get_user_profile(&ghost).await {
+            Ok(Some(_)) => Ok(()),
+            Ok(None) => Err(anyhow::anyhow!(
+                "{} has no profile after registering",
+                ghost
+            )),
+            Err(err) => Err(err),
+        },
+        Err(err) => Err(err),
+    };
+    let ghost_ready = report
+        .record("register ghost", registered, |_| ghost.clone())
+        .is_some();
+
+    let Some(room_id) = report.record(
+        "create room",
+        matrix.create_private_room(TEST_ROOM_NAME).await,
+        Clone::clone,
+    ) else {
+        report.skip("send message", "no test room");
+        return;
+    };
+
+    if ghost_ready {
+        let sent = match matrix.join_ghost_to_room(TEST_GHOST_ID, &room_id).await {
+            Ok(()) => matrix.send_message(&room_id, &ghost, TEST_MESSAGE).await,
+            Err(err) => Err(err),
+        };
+        report.record("send message", sent, |_| format!("as {}", ghost));
+        if let Err(err) = matrix.leave_room_as(&ghost, &room_id).await {
+            report.push("clean up ghost", CheckOutcome::Failed(format!("{:#}", err)));
+        }
+    } else {
+        report.skip("send message", "no test ghost");
+    }
+    report.record("clean up room", matrix.leave_room(&room_id).await, |_| {
+        room_id.clone()
+    });
+}
+
+async fn check_discord(
+    discord: &DiscordClient,
+    test_channel: Option<&str>,
+    report: &mut SelfTestReport,
+) {
+    discord.connect_http().await;
+    if report
+        .record(
+            "discord api",
+            discord.current_user_tag().await,
+            Clone::clone,
+        )
+        .is_none()
+    {
+        report.skip("test channel", "discord api is unreachable");
+        report.skip("webhook creation", "discord api is unreachable");
+        return;
+    }
+
+    let Some(channel_id) = test_channel else {
+        report.skip("test channel", "pass --self-test-channel to check it");
+        report.skip("webhook creation", "no test channel");
+        return;
+    };
+    let channel = match discord.get_channel(channel_id).await {
+        Ok(Some(channel)) => Ok(channel),
+        Ok(None) => Err(anyhow::anyhow!("channel {} does not exist", channel_id)),
+        Err(err) => Err(err),
+    };
+    if report
+        .record("test channel", channel, |channel| {
+            format!("#{}", channel.name)
+        })
+        .is_none()
+    {
+        report.skip("webhook creation", "no test channel");
+        return;
+    }
+    report.record(
+        "webhook creation",
+        discord.check_webhook_creation(channel_id).await,
+        |_| String::new(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CheckOutcome, SelfTestReport};
+
+    #[test]
+    fn any_failed_check_fails_the_report() {
+        let mut report = SelfTestReport::default();
+        report.record("discord api", Ok("bot (1)".to_string()), Clone::clone);
+        report.skip("webhook creation", "no test channel");
+        assert!(report.passed());
+        assert_eq!(
+            report.to_string(),
+            "PASS discord api: bot (1)\nSKIP webhook creation: no test channel\nself-test passed"
+        );
+
+        report.record::<()>("create room", Err(anyhow::anyhow!("forbidden")), |_| {
+            String::new()
+        });
+        assert!(!report.passed());
+        assert_eq!(
+            report.checks[2].outcome,
+            CheckOutcome::Failed("forbidden".to_string())
+        );
+        assert!(report.to_string().ends_with("self-test failed"));
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+/// Remembers when each Matrix user last had a message delivered to a
+/// slowmode channel, so the bridge can hold them to Discord's interval.
+#[derive(Default)]
+pub struct SlowmodeTracker {
+    last_sent: Mutex<HashMap<(String, String), Instant>>,
+}
+
+impl SlowmodeTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a send by `sender` in `channel_id`, or returns how long they
+    /// still have to wait when the previous one was too recent.
+    pub fn try_send(
+        &self,
+        channel_id: &str,
+        sender: &str,
+        slowmode_seconds: u16,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        let interval = Duration::from_secs(u64::from(slowmode_seconds));
+        let key = (channel_id.to_string(), sender.to_string());
+        let mut last_sent = self.last_sent.lock();
+        if let Some(previous) = last_sent.get(&key) {
+            let elapsed = now.saturating_duration_since(*previous);
+            if elapsed < interval {
+                return Err(interval - elapsed);
+            }
+        }
+        last_sent.insert(key, now);
+        // Entries older than Discord's longest slowmode (6 hours) can no
+        // longer hold anyone back.
+        if last_sent.len() > 1024 {
+            let horizon = Duration::from_secs(6 * 60 * 60);
+            last_sent.retain(|_, sent| now.saturating_duration_since(*sent) < horizon);
+        }
+        Ok(())
+    }
+}
+
+/// Short form of a slowmode interval, e.g. `30s`, `5m` or `2h`.
+pub fn slowmode_label(seconds: u16) -> String {
+    match seconds {
+        s if s >= 3600 && s % 3600 == 0 => format!("{}h", s / 3600),
+        s if s >= 60 && s % 60 == 0 => format!("{}m", s / 60),
+        s => format!("{}s", s),
+    }
+}
+
+/// The Matrix room topic for a Discord channel, with the slowmode appended
+/// while it is enabled.
+pub fn topic_with_slowmode(topic: Option<&str>, slowmode_seconds: u16) -> Option<String> {
+    let topic = topic.filter(|topic| !topic.is_empty());
+    if slowmode_seconds == 0 {
+        return topic.map(ToOwned::to_owned);
+    }
+    let suffix = format!("[Slowmode: {}]", slowmode_label(slowmode_seconds));
+    Some(match topic {
+        Some(topic) => format!("{} {}", topic, suffix),
+        None => suffix,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{SlowmodeTracker, slowmode_label, topic_with_slowmode};
+
+    #[test]
+    fn tracker_holds_each_sender_to_the_interval() {
+        let tracker = SlowmodeTracker::new();
+        let start = Instant::now();
+
+        assert!(tracker.try_send("1", "@a:x", 10, start).is_ok());
+        assert_eq!(
+            tracker.try_send("1", "@a:x", 10, start + Duration::from_secs(4)),
+            Err(Duration::from_secs(6))
+        );
+        assert!(tracker.try_send("1", "@b:x", 10, start).is_ok());
+        assert!(tracker.try_send("2", "@a:x", 10, start).is_ok());
+        assert!(
+            tracker
+                .try_send("1", "@a:x", 10, start + Duration::from_secs(10))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn topic_gets_a_slowmode_suffix_only_while_enabled() {
+        assert_eq!(slowmode_label(30), "30s");
+        assert_eq!(slowmode_label(300), "5m");
+        assert_eq!(slowmode_label(7200), "2h");
+        assert_eq!(slowmode_label(90), "90s");
+
+        assert_eq!(
+            topic_with_slowmode(Some("General chat"), 30).as_deref(),
+            Some("General chat [Slowmode: 30s]")
+        );
+        assert_eq!(
+            topic_with_slowmode(None, 60).as_deref(),
+            Some("[Slowmode: 1m]")
+        );
+        assert_eq!(
+            topic_with_slowmode(Some("General chat"), 0).as_deref(),
+            Some("General chat")
+        );
+        assert_eq!(topic_with_slowmode(None, 0), None);
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::web::Metrics;
+
+/// A task that ran at least this long before stopping is restarted without
+/// any backoff carried over from earlier failures.
+const STABLE_RUN: Duration = Duration::from_secs(60);
+const MIN_RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskState {
+    Running,
+    /// Stopped and waiting out its backoff before the next start.
+    Restarting,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskStatus {
+    pub name: &'static str,
+    pub state: TaskState,
+    pub restarts: u64,
+    pub last_error: Option<String>,
+    pub started_at: DateTime<Utc>,
+}
+
+/// Runs the bridge's background jobs as named tasks and starts them again,
+/// with exponential backoff, whenever one panics or returns.
+#[derive(Default)]
+pub struct TaskSupervisor {
+    tasks: Arc<Mutex<BTreeMap<&'static str, TaskStatus>>>,
+}
+
+impl TaskSupervisor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spawns `name`, calling `make_task` for the first run and every restart.
+    /// The returned handle only finishes if it is aborted.
+    pub fn spawn<F, Fut>(&self, name: &'static str, make_task: F) -> JoinHandle<()>
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let tasks = self.tasks.clone();
+        tokio::spawn(async move {
+            let mut failures = 0u32;
+            loop {
+                set_running(&tasks, name);
+                Metrics::set_task_up(name, true);
+                let started = Instant::now();
+
+                let reason = match tokio::spawn(make_task()).await {
+                    Ok(()) => "exited".to_string(),
+                    Err(err) if err.is_panic() => format!("panicked: {}", panic_message(err)),
+                    Err(err) => format!("was cancelled: {}", err),
+                };
+
+                failures = if started.elapsed() >= STABLE_RUN {
+                    1
+                } else {
+                    failures.saturating_add(1)
+                };
+                let delay = restart_delay(failures);
+                error!(
+                    "background task {} {}, restarting in {}s",
+                    name,
+                    reason,
+                    delay.as_secs()
+                );
+                set_restarting(&tasks, name, reason);
+                Metrics::set_task_up(name, false);
+                Metrics::task_restarted(name);
+                tokio::time::sleep(delay).await;
+                info!("restarting background task {}", name);
+            }
+        })
+    }
+
+    pub fn statuses(&self) -> Vec<TaskStatus> {
+        self.tasks.lock().values().cloned().collect()
+    }
+
+    /// Whether every supervised task is currently running.
+    pub fn is_healthy(&self) -> bool {
+        self.tasks
+            .lock()
+            .values()
+            .all(|task| task.state == TaskState::Running)
+    }
+}
+
+fn set_running(tasks: &Mutex<BTreeMap<&'static str, TaskStatus>>, name: &'static str) {
+    let mut tasks = tasks.lock();
+    let status = tasks.entry(name).or_insert_with(|| TaskStatus {
+        name,
+        state: TaskState::Running,
+        restarts: 0,
+        last_error: None,
+        started_at: Utc::now(),
+    });
+    status.state = TaskState::Running;
+    status.started_at = Utc::now();
+}
+
+fn set_restarting(
+    tasks: &Mutex<BTreeMap<&'static str, TaskStatus>>,
+    name: &'static str,
+    reason: String,
+) {
+    if let Some(status) = tasks.lock().get_mut(name) {
+        status.state = TaskState::Restarting;
+        status.restarts += 1;
+        status.last_error = Some(reason);
+    } else {
+        warn!("background task {} stopped before it was registered", name);
+    }
+}
+
+fn panic_message(err: tokio::task::JoinError) -> String {
+    let payload = err.into_panic();
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+/// Wait before restarting a task that has stopped `failures` times in quick
+/// succession: one second, doubling up to a minute.
+pub fn restart_delay(failures: u32) -> Duration {
+    let doublings = failures.saturating_sub(1).min(16);
+    MIN_RESTART_DELAY
+        .saturating_mul(1 << doublings)
+        .min(MAX_RESTART_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+
+    use super::{TaskState, TaskSupervisor, restart_delay};
+
+    #[test]
+    fn restart_delay_backs_off_to_a_minute() {
+        assert_eq!(restart_delay(1), Duration::from_secs(1));
+        assert_eq!(restart_delay(2), Duration::from_secs(2));
+        assert_eq!(restart_delay(4), Duration::from_secs(8));
+        assert_eq!(restart_delay(7), Duration::from_secs(60));
+        assert_eq!(restart_delay(u32::MAX), Duration::from_secs(60));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn panicking_task_is_reported_and_restarted() {
+        let supervisor = TaskSupervisor::new();
+        let runs = Arc::new(AtomicU32::new(0));
+        let counter = runs.clone();
+        let handle = supervisor.spawn("flaky", move || {
+            let counter = counter.clone();
+            async move {
+                if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                    panic!("boom");
+                }
+                std::future::pending::<()>().await;
+            }
+        });
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let status = supervisor.statuses().remove(0);
+        assert_eq!(status.name, "flaky");
+        assert_eq!(status.state, TaskState::Restarting);
+        assert_eq!(status.last_error.as_deref(), Some("panicked: boom"));
+        assert!(!supervisor.is_healthy());
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        let status = supervisor.statuses().remove(0);
+        assert_eq!(status.state, TaskState::Running);
+        assert_eq!(status.restarts, 1);
+        assert!(supervisor.is_healthy());
+        handle.abort();
+    }
+}
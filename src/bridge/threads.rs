@@ -0,0 +1,42 @@
+//! Threads: messages in a Discord thread of a bridged channel go to the
+//! channel's room as a Matrix thread (MSC3440), and Matrix thread replies go
+//! to the matching Discord thread. The thread's first bridged message, or
+//! the message it was started from, is the Matrix thread root; the pairing
+//! is kept in the `thread_mappings` table.
+
+/// Discord's limit on thread names, in characters.
+const MAX_THREAD_NAME_CHARS: usize = 100;
+
+/// The name of a Discord thread started from Matrix: the first line of the
+/// reply that started it.
+pub fn thread_name(body: &str) -> String {
+    let line = body
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+    if line.is_empty() {
+        return "Thread".to_string();
+    }
+    if line.chars().count() <= MAX_THREAD_NAME_CHARS {
+        return line.to_string();
+    }
+    let mut name: String = line.chars().take(MAX_THREAD_NAME_CHARS - 1).collect();
+    name.push('…');
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::thread_name;
+
+    #[test]
+    fn thread_names_come_from_the_first_line() {
+        assert_eq!(thread_name("\n  sounds good  \nsee you"), "sounds good");
+        assert_eq!(thread_name(""), "Thread");
+
+        let long = thread_name(&"é".repeat(150));
+        assert_eq!(long.chars().count(), 100);
+        assert!(long.ends_with('…'));
+    }
+}
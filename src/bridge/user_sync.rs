@@ -148,6 +148,7 @@ impl UserSyncHandler {
             discord_username: state.displayname.clone().unwrap_or_default(),
             discord_discriminator: "0000".to_string(),
             discord_avatar: state.avatar_url.clone(),
+            presence_override: None,
             created_at: Utc::now(),
             updated_at: Utc::now(),
         };
@@ -3,6 +3,13 @@ use std::time::{Duration, Instant};
 
 use tokio::sync::RwLock;
 
+pub mod discord_metadata;
+
+pub use self::discord_metadata::{
+    ChannelSnapshot, DiscordMetadataCache, GuildSnapshot, MemberSnapshot, RoleSnapshot,
+    UserSnapshot,
+};
+
 struct TimedValue<V> {
     value: V,
     inserted_at: Instant,
@@ -67,6 +74,13 @@ where
     pub fn cleanup_expired(&mut self) {
         self.map.retain(|_, tv| tv.inserted_at.elapsed() < self.ttl);
     }
+
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        self.map.retain(|key, tv| keep(key, &tv.value));
+    }
 }
 
 pub struct AsyncTimedCache<K, V> {
@@ -105,6 +119,13 @@ where
     pub async fn cleanup_expired(&self) {
         self.inner.write().await.cleanup_expired();
     }
+
+    pub async fn retain<F>(&self, keep: F)
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        self.inner.write().await.retain(keep);
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,300 @@
+use std::time::Duration;
+
+use super::AsyncTimedCache;
+
+const GUILD_TTL_SECS: u64 = 3600;
+const CHANNEL_TTL_SECS: u64 = 3600;
+const USER_TTL_SECS: u64 = 900;
+const MEMBER_TTL_SECS: u64 = 900;
+const ROLE_TTL_SECS: u64 = 3600;
+const MODERATORS_TTL_SECS: u64 = 900;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuildSnapshot {
+    pub id: String,
+    pub name: String,
+    pub icon_url: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelSnapshot {
+    pub id: String,
+    pub name: String,
+    pub guild_id: String,
+    pub topic: Option<String>,
+    /// Slowmode interval in seconds; 0 when disabled.
+    pub slowmode_seconds: u16,
+    /// The channel a thread belongs to; `None` for channels that are not
+    /// threads.
+    pub thread_parent_id: Option<String>,
+    /// Whether @everyone can see the channel: false for private threads and
+    /// channels whose overwrites hide them from the @everyone role.
+    pub everyone_can_view: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserSnapshot {
+    pub id: String,
+    pub username: String,
+    pub discriminator: String,
+    pub avatar_url: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemberSnapshot {
+    pub guild_id: String,
+    pub user_id: String,
+    pub nick: Option<String>,
+    pub roles: Vec<String>,
+    pub avatar_url: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoleSnapshot {
+    pub id: String,
+    pub guild_id: String,
+    pub name: String,
+    /// RGB color as reported by Discord; `0` means the role has no color.
+    pub color: u32,
+}
+
+/// Snapshots of Discord guild, channel and member metadata as last seen on the
+/// gateway. Entries expire so that stale data falls back to an API lookup.
+pub struct DiscordMetadataCache {
+    guilds: AsyncTimedCache<String, GuildSnapshot>,
+    channels: AsyncTimedCache<String, ChannelSnapshot>,
+    users: AsyncTimedCache<String, UserSnapshot>,
+    members: AsyncTimedCache<(String, String), MemberSnapshot>,
+    roles: AsyncTimedCache<String, RoleSnapshot>,
+    /// Per guild, the owner and the members who moderate channels; listing
+    /// them pages through the whole member list.
+    moderators: AsyncTimedCache<String, Vec<String>>,
+}
+
+impl Default for DiscordMetadataCache {
+    fn default() -> Self {
+        Self {
+            guilds: AsyncTimedCache::new(Duration::from_secs(GUILD_TTL_SECS)),
+            channels: AsyncTimedCache::new(Duration::from_secs(CHANNEL_TTL_SECS)),
+            users: AsyncTimedCache::new(Duration::from_secs(USER_TTL_SECS)),
+            members: AsyncTimedCache::new(Duration::from_secs(MEMBER_TTL_SECS)),
+            roles: AsyncTimedCache::new(Duration::from_secs(ROLE_TTL_SECS)),
+            moderators: AsyncTimedCache::new(Duration::from_secs(MODERATORS_TTL_SECS)),
+        }
+    }
+}
+
+impl DiscordMetadataCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_ttl(ttl: Duration) -> Self {
+        Self {
+            guilds: AsyncTimedCache::new(ttl),
+            channels: AsyncTimedCache::new(ttl),
+            users: AsyncTimedCache::new(ttl),
+            members: AsyncTimedCache::new(ttl),
+            roles: AsyncTimedCache::new(ttl),
+            moderators: AsyncTimedCache::new(ttl),
+        }
+    }
+
+    pub async fn guild(&self, guild_id: &str) -> Option<GuildSnapshot> {
+        self.guilds.get(&guild_id.to_string()).await
+    }
+
+    pub async fn upsert_guild(&self, guild: GuildSnapshot) {
+        self.guilds.insert(guild.id.clone(), guild).await;
+    }
+
+    /// Drops the guild together with every channel, member and role snapshot
+    /// that belongs to it.
+    pub async fn remove_guild(&self, guild_id: &str) {
+        self.guilds.remove(&guild_id.to_string()).await;
+        self.channels
+            .retain(|_, channel| channel.guild_id != guild_id)
+            .await;
+        self.members
+            .retain(|(member_guild_id, _), _| member_guild_id != guild_id)
+            .await;
+        self.roles.retain(|_, role| role.guild_id != guild_id).await;
+        self.moderators.remove(&guild_id.to_string()).await;
+    }
+
+    pub async fn channel(&self, channel_id: &str) -> Option<ChannelSnapshot> {
+        self.channels.get(&channel_id.to_string()).await
+    }
+
+    pub async fn upsert_channel(&self, channel: ChannelSnapshot) {
+        self.channels.insert(channel.id.clone(), channel).await;
+    }
+
+    pub async fn remove_channel(&self, channel_id: &str) {
+        self.channels.remove(&channel_id.to_string()).await;
+    }
+
+    pub async fn user(&self, user_id: &str) -> Option<UserSnapshot> {
+        self.users.get(&user_id.to_string()).await
+    }
+
+    pub async fn upsert_user(&self, user: UserSnapshot) {
+        self.users.insert(user.id.clone(), user).await;
+    }
+
+    pub async fn member(&self, guild_id: &str, user_id: &str) -> Option<MemberSnapshot> {
+        self.members
+            .get(&(guild_id.to_string(), user_id.to_string()))
+            .await
+    }
+
+    pub async fn upsert_member(&self, member: MemberSnapshot) {
+        self.members
+            .insert((member.guild_id.clone(), member.user_id.clone()), member)
+            .await;
+    }
+
+    pub async fn remove_member(&self, guild_id: &str, user_id: &str) {
+        self.members
+            .remove(&(guild_id.to_string(), user_id.to_string()))
+            .await;
+    }
+
+    pub async fn role(&self, role_id: &str) -> Option<RoleSnapshot> {
+        self.roles.get(&role_id.to_string()).await
+    }
+
+    pub async fn upsert_role(&self, role: RoleSnapshot) {
+        self.roles.insert(role.id.clone(), role).await;
+    }
+
+    pub async fn remove_role(&self, role_id: &str) {
+        self.roles.remove(&role_id.to_string()).await;
+    }
+
+    pub async fn moderators(&self, guild_id: &str) -> Option<Vec<String>> {
+        self.moderators.get(&guild_id.to_string()).await
+    }
+
+    pub async fn set_moderators(&self, guild_id: &str, moderators: Vec<String>) {
+        self.moderators
+            .insert(guild_id.to_string(), moderators)
+            .await;
+    }
+
+    /// Resolves the name a user is shown with in a guild: the guild nickname
+    /// when one is cached, otherwise the account username.
+    pub async fn display_name(&self, guild_id: &str, user_id: &str) -> Option<String> {
+        if let Some(nick) = self
+            .member(guild_id, user_id)
+            .await
+            .and_then(|member| member.nick)
+        {
+            return Some(nick);
+        }
+        self.user(user_id).await.map(|user| user.username)
+    }
+
+    pub async fn cleanup_expired(&self) {
+        self.guilds.cleanup_expired().await;
+        self.channels.cleanup_expired().await;
+        self.users.cleanup_expired().await;
+        self.members.cleanup_expired().await;
+        self.roles.cleanup_expired().await;
+        self.moderators.cleanup_expired().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn channel(id: &str, guild_id: &str) -> ChannelSnapshot {
+        ChannelSnapshot {
+            id: id.to_string(),
+            name: format!("channel-{id}"),
+            guild_id: guild_id.to_string(),
+            topic: None,
+            slowmode_seconds: 0,
+            thread_parent_id: None,
+            everyone_can_view: true,
+        }
+    }
+
+    fn member(guild_id: &str, user_id: &str, nick: Option<&str>) -> MemberSnapshot {
+        MemberSnapshot {
+            guild_id: guild_id.to_string(),
+            user_id: user_id.to_string(),
+            nick: nick.map(ToString::to_string),
+            roles: vec!["10".to_string()],
+            avatar_url: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn remove_guild_drops_its_channels_and_members() {
+        let cache = DiscordMetadataCache::new();
+        cache
+            .upsert_guild(GuildSnapshot {
+                id: "1".to_string(),
+                name: "Guild".to_string(),
+                icon_url: None,
+            })
+            .await;
+        cache.upsert_channel(channel("100", "1")).await;
+        cache.upsert_channel(channel("200", "2")).await;
+        cache.upsert_member(member("1", "42", None)).await;
+        cache.upsert_member(member("2", "42", None)).await;
+        cache
+            .upsert_role(RoleSnapshot {
+                id: "10".to_string(),
+                guild_id: "1".to_string(),
+                name: "Mods".to_string(),
+                color: 0x3498db,
+            })
+            .await;
+        cache.set_moderators("1", vec!["42".to_string()]).await;
+
+        cache.remove_guild("1").await;
+
+        assert!(cache.guild("1").await.is_none());
+        assert!(cache.channel("100").await.is_none());
+        assert!(cache.channel("200").await.is_some());
+        assert!(cache.member("1", "42").await.is_none());
+        assert!(cache.member("2", "42").await.is_some());
+        assert!(cache.role("10").await.is_none());
+        assert!(cache.moderators("1").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn display_name_prefers_guild_nick_over_username() {
+        let cache = DiscordMetadataCache::new();
+        cache
+            .upsert_user(UserSnapshot {
+                id: "42".to_string(),
+                username: "alice".to_string(),
+                discriminator: "0000".to_string(),
+                avatar_url: None,
+            })
+            .await;
+        cache.upsert_member(member("1", "42", Some("Ali"))).await;
+        cache.upsert_member(member("2", "42", None)).await;
+
+        assert_eq!(cache.display_name("1", "42").await.as_deref(), Some("Ali"));
+        assert_eq!(
+            cache.display_name("2", "42").await.as_deref(),
+            Some("alice")
+        );
+        assert_eq!(cache.display_name("1", "7").await, None);
+    }
+
+    #[tokio::test]
+    async fn snapshots_expire_after_ttl() {
+        let cache = DiscordMetadataCache::with_ttl(Duration::from_millis(20));
+        cache.upsert_channel(channel("100", "1")).await;
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(cache.channel("100").await.is_none());
+    }
+}
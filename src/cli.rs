@@ -15,6 +15,69 @@ pub struct Cli {
 
     #[arg(short, long, env = "REGISTRATION_PATH")]
     pub registration: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Push synthetic messages through the bridge pipeline and report throughput"
+    )]
+    pub bench_pipeline: bool,
+
+    #[arg(long, default_value = "1000", requires = "bench_pipeline")]
+    pub bench_messages: usize,
+
+    #[arg(long, default_value = "8", requires = "bench_pipeline")]
+    pub bench_concurrency: usize,
+
+    #[arg(
+        long,
+        requires = "bench_pipeline",
+        help = "Exit with an error when throughput falls below this many msgs/sec"
+    )]
+    pub bench_min_throughput: Option<f64>,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Write a default config.yaml and registration file with fresh tokens into DIR, then exit"
+    )]
+    pub bootstrap: Option<PathBuf>,
+
+    #[arg(long, default_value = "localhost", requires = "bootstrap")]
+    pub bootstrap_domain: String,
+
+    #[arg(long, default_value = "http://localhost:8008", requires = "bootstrap")]
+    pub bootstrap_homeserver_url: String,
+
+    #[arg(
+        long,
+        default_value = "http://localhost:9005",
+        requires = "bootstrap",
+        help = "Address the homeserver uses to reach the bridge"
+    )]
+    pub bootstrap_appservice_url: String,
+
+    #[arg(long, requires = "bootstrap", help = "Overwrite existing files")]
+    pub bootstrap_force: bool,
+
+    #[arg(
+        long,
+        help = "Hide the ghosts in all bridged rooms from the homeserver user directory, then exit"
+    )]
+    pub hide_ghosts_from_directory: bool,
+
+    #[arg(
+        long,
+        help = "Check Matrix and Discord access with the configured credentials, then exit"
+    )]
+    pub self_test: bool,
+
+    #[arg(
+        long,
+        value_name = "CHANNEL_ID",
+        requires = "self_test",
+        help = "Discord channel in which the self-test creates and deletes a webhook"
+    )]
+    pub self_test_channel: Option<String>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -58,6 +121,15 @@ pub enum Commands {
         dry_run: bool,
     },
 
+    #[command(about = "Import rooms, users and messages from a matrix-appservice-discord database")]
+    ImportLegacy {
+        #[arg(long, help = "Legacy database: a postgres:// URL or an SQLite file")]
+        from: String,
+
+        #[arg(short, long, help = "Report what would be imported without writing")]
+        dry_run: bool,
+    },
+
     #[command(about = "List all bridged rooms")]
     ListRooms {
         #[arg(short, long, help = "Filter by guild ID")]
@@ -81,19 +153,43 @@ pub enum Commands {
 
     #[command(about = "Show bridge status")]
     Status,
+
+    #[command(about = "Erase a user's bridged messages, mappings and avatar")]
+    PurgeUser {
+        #[arg(long, conflicts_with = "mxid", required_unless_present = "mxid")]
+        discord_id: Option<String>,
+
+        #[arg(long)]
+        mxid: Option<String>,
+    },
 }
 
 pub fn generate_registration(id: &str, homeserver_url: &str, domain: &str) -> String {
-    let as_token = generate_token();
-    let hs_token = generate_token();
+    registration_yaml(
+        id,
+        homeserver_url,
+        domain,
+        &generate_token(),
+        &generate_token(),
+    )
+}
 
+pub fn registration_yaml(
+    id: &str,
+    url: &str,
+    domain: &str,
+    as_token: &str,
+    hs_token: &str,
+) -> String {
     let registration = json!({
         "id": id,
-        "url": homeserver_url,
+        "url": url,
         "as_token": as_token,
         "hs_token": hs_token,
         "sender_localpart": "_discord_",
         "rate_limited": false,
+        "receive_ephemeral": true,
+        "de.sorunome.msc2409.push_ephemeral": true,
         "protocols": ["discord"],
         "namespaces": {
             "users": [{
@@ -111,19 +207,13 @@ pub fn generate_registration(id: &str, homeserver_url: &str, domain: &str) -> St
     serde_yaml::to_string(&registration).unwrap_or_default()
 }
 
-fn generate_token() -> String {
-    use std::collections::hash_map::RandomState;
-    use std::hash::{BuildHasher, Hasher};
-
-    let state = RandomState::new();
-    let mut hasher = state.build_hasher();
-    hasher.write_u64(
-        std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_nanos() as u64,
-    );
-    format!("{:x}", hasher.finish())
+/// A random 256-bit hex token.
+pub fn generate_token() -> String {
+    format!(
+        "{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    )
 }
 
 #[cfg(test)]
@@ -137,5 +227,6 @@ mod tests {
         assert!(yaml.contains("as_token:"));
         assert!(yaml.contains("hs_token:"));
         assert!(yaml.contains("protocols:"));
+        assert!(yaml.contains("receive_ephemeral: true"));
     }
 }
@@ -1,11 +1,17 @@
+pub use self::kdl_support::{is_kdl_file, parse_kdl_config};
 pub use self::parser::{
-    AuthConfig, BridgeConfig, ChannelConfig, ChannelDeleteOptionsConfig, Config, DatabaseConfig,
-    DbType, GhostsConfig, LimitsConfig, LoggingConfig, LoggingFileConfig, MetricsConfig,
-    RegistrationConfig, RoomConfig, UserActivityConfig,
+    AdminApiConfig, AlertFormat, AlertsConfig, ApiScope, ApiTokenConfig, AttachmentPolicy,
+    AttachmentPolicyConfig, AuthConfig, BridgeConfig, ChannelConfig, ChannelDeleteOptionsConfig,
+    ChaosConfig, Config, ContentRedactionConfig, DatabaseConfig, DbType, DeliveryConfig,
+    DeliveryMode, DirectionDeliveryConfig, GhostsConfig, HomeserverAdminConfig,
+    InactiveRoomsConfig, LimitsConfig, ListenAddress, LoggingConfig, LoggingFileConfig,
+    MaintenanceConfig, MatrixPresenceState, MediaConfig, MetricsConfig, PortalRoomConfig,
+    PresenceMappingConfig, PresenceMappingEntry, RedactionRuleConfig, RegistrationConfig,
+    RegistrationNamespaceEntry, RelayExtractorConfig, RoomConfig, UserActivityConfig, WebConfig,
 };
 pub use self::validator::ConfigError;
-pub use self::kdl_support::{is_kdl_file, parse_kdl_config};
 
+mod kdl_support;
+mod legacy;
 mod parser;
 mod validator;
-mod kdl_support;
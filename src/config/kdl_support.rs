@@ -3,7 +3,9 @@ use serde_json::{Map, Value};
 
 /// Parse a KDL config string into a deserializable type by converting KDL → JSON → T.
 pub fn parse_kdl_config<T: DeserializeOwned>(content: &str) -> Result<T, String> {
-    let doc: kdl::KdlDocument = content.parse().map_err(|e| format!("KDL parse error: {e}"))?;
+    let doc: kdl::KdlDocument = content
+        .parse()
+        .map_err(|e| format!("KDL parse error: {e}"))?;
     let json_value = kdl_document_to_json(&doc);
     serde_json::from_value(json_value).map_err(|e| format!("config deserialization error: {e}"))
 }
@@ -37,26 +39,25 @@ fn nodes_to_json(nodes: &[kdl::KdlNode]) -> Value {
 }
 
 fn node_to_json(node: &kdl::KdlNode) -> Value {
-    let has_children = node
-        .children()
-        .map_or(false, |c| !c.nodes().is_empty());
-    let args: Vec<_> = node.entries().iter().filter(|e| e.name().is_none()).collect();
-    let props: Vec<_> = node.entries().iter().filter(|e| e.name().is_some()).collect();
+    let has_children = node.children().is_some_and(|c| !c.nodes().is_empty());
+    let args: Vec<_> = node
+        .entries()
+        .iter()
+        .filter(|e| e.name().is_none())
+        .collect();
+    let props: Vec<_> = node
+        .entries()
+        .iter()
+        .filter(|e| e.name().is_some())
+        .collect();
 
     if has_children {
         let children_doc = node.children().unwrap();
-        let all_dash = children_doc
-            .nodes()
-            .iter()
-            .all(|n| n.name().value() == "-");
+        let all_dash = children_doc.nodes().iter().all(|n| n.name().value() == "-");
 
         if all_dash && !children_doc.nodes().is_empty() {
             // All children named "-" → array
-            let arr: Vec<Value> = children_doc
-                .nodes()
-                .iter()
-                .map(|n| dash_node_to_json(n))
-                .collect();
+            let arr: Vec<Value> = children_doc.nodes().iter().map(dash_node_to_json).collect();
             return Value::Array(arr);
         }
 
@@ -113,11 +114,17 @@ fn node_to_json(node: &kdl::KdlNode) -> Value {
 
 /// Convert a "-" (dash) node into a JSON value for array elements.
 fn dash_node_to_json(node: &kdl::KdlNode) -> Value {
-    let args: Vec<_> = node.entries().iter().filter(|e| e.name().is_none()).collect();
-    let props: Vec<_> = node.entries().iter().filter(|e| e.name().is_some()).collect();
-    let has_children = node
-        .children()
-        .map_or(false, |c| !c.nodes().is_empty());
+    let args: Vec<_> = node
+        .entries()
+        .iter()
+        .filter(|e| e.name().is_none())
+        .collect();
+    let props: Vec<_> = node
+        .entries()
+        .iter()
+        .filter(|e| e.name().is_some())
+        .collect();
+    let has_children = node.children().is_some_and(|c| !c.nodes().is_empty());
 
     if has_children {
         let mut obj = match kdl_document_to_json(node.children().unwrap()) {
@@ -162,7 +169,7 @@ fn kdl_value_to_json(value: &kdl::KdlValue) -> Value {
         | kdl::KdlValue::Base8(i)
         | kdl::KdlValue::Base10(i)
         | kdl::KdlValue::Base16(i) => Value::Number((*i).into()),
-        kdl::KdlValue::Float(f) => serde_json::Number::from_f64(*f)
+        kdl::KdlValue::Base10Float(f) => serde_json::Number::from_f64(*f)
             .map(Value::Number)
             .unwrap_or(Value::Null),
         kdl::KdlValue::Bool(b) => Value::Bool(*b),
@@ -172,7 +179,7 @@ fn kdl_value_to_json(value: &kdl::KdlValue) -> Value {
 
 /// Returns `true` if the file path has a `.kdl` extension.
 pub fn is_kdl_file(path: &std::path::Path) -> bool {
-    path.extension().map_or(false, |ext| ext == "kdl")
+    path.extension().is_some_and(|ext| ext == "kdl")
 }
 
 #[cfg(test)]
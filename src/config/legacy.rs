@@ -0,0 +1,322 @@
+//! Reads the config.yaml of matrix-appservice-discord, the Node.js bridge,
+//! so operators can switch over before rewriting their config. Its sections
+//! mostly match ours with camelCase keys; options with no counterpart here
+//! are dropped and reported.
+
+use serde_yaml::{Mapping, Value};
+
+/// Legacy options and where they live in this bridge's config.
+const LEGACY_OPTIONS: &[(&str, &str)] = &[
+    ("bridge.domain", "bridge.domain"),
+    ("bridge.homeserverUrl", "bridge.homeserver_url"),
+    ("bridge.presenceInterval", "bridge.presence_interval"),
+    ("bridge.disablePresence", "bridge.disable_presence"),
+    (
+        "bridge.disableTypingNotifications",
+        "bridge.disable_typing_notifications",
+    ),
+    (
+        "bridge.disableDiscordMentions",
+        "bridge.disable_discord_mentions",
+    ),
+    (
+        "bridge.disableDeletionForwarding",
+        "bridge.disable_deletion_forwarding",
+    ),
+    (
+        "bridge.enableSelfServiceBridging",
+        "bridge.enable_self_service_bridging",
+    ),
+    (
+        "bridge.disablePortalBridging",
+        "bridge.disable_portal_bridging",
+    ),
+    ("bridge.disableReadReceipts", "bridge.disable_read_receipts"),
+    (
+        "bridge.disableEveryoneMention",
+        "bridge.disable_everyone_mention",
+    ),
+    ("bridge.disableHereMention", "bridge.disable_here_mention"),
+    (
+        "bridge.disableJoinLeaveNotifications",
+        "bridge.disable_join_leave_notifications",
+    ),
+    (
+        "bridge.disableInviteNotifications",
+        "bridge.disable_invite_notifications",
+    ),
+    (
+        "bridge.disableRoomTopicNotifications",
+        "bridge.disable_room_topic_notifications",
+    ),
+    (
+        "bridge.determineCodeLanguage",
+        "bridge.determine_code_language",
+    ),
+    ("bridge.userLimit", "bridge.user_limit"),
+    ("bridge.adminMxid", "bridge.admin_mxid"),
+    ("bridge.invalidTokenMessage", "bridge.invalid_token_message"),
+    (
+        "bridge.userActivity.minUserActiveDays",
+        "bridge.user_activity.min_user_active_days",
+    ),
+    (
+        "bridge.userActivity.inactiveAfterDays",
+        "bridge.user_activity.inactive_after_days",
+    ),
+    ("auth.clientID", "auth.client_id"),
+    ("auth.clientSecret", "auth.client_secret"),
+    ("auth.botToken", "auth.bot_token"),
+    ("auth.usePrivilegedIntents", "auth.use_privileged_intents"),
+    ("logging.console", "logging.level"),
+    ("logging.lineDateFormat", "logging.line_date_format"),
+    ("logging.files", "logging.files"),
+    ("database.filename", "database.filename"),
+    ("database.connString", "database.conn_string"),
+    ("database.userStorePath", "database.user_store_path"),
+    ("database.roomStorePath", "database.room_store_path"),
+    ("room.defaultVisibility", "room.default_visibility"),
+    ("room.kickFor", "room.kick_for"),
+    ("channel.namePattern", "channel.name_pattern"),
+    (
+        "channel.deleteOptions.namePrefix",
+        "channel.delete_options.name_prefix",
+    ),
+    (
+        "channel.deleteOptions.topicPrefix",
+        "channel.delete_options.topic_prefix",
+    ),
+    (
+        "channel.deleteOptions.disableMessaging",
+        "channel.delete_options.disable_messaging",
+    ),
+    (
+        "channel.deleteOptions.unsetRoomAlias",
+        "channel.delete_options.unset_room_alias",
+    ),
+    (
+        "channel.deleteOptions.unlistFromDirectory",
+        "channel.delete_options.unlist_from_directory",
+    ),
+    (
+        "channel.deleteOptions.setInviteOnly",
+        "channel.delete_options.set_invite_only",
+    ),
+    (
+        "channel.deleteOptions.ghostsLeave",
+        "channel.delete_options.ghosts_leave",
+    ),
+    ("limits.roomGhostJoinDelay", "limits.room_ghost_join_delay"),
+    ("limits.discordSendDelay", "limits.discord_send_delay"),
+    ("limits.roomCount", "limits.room_count"),
+    ("ghosts.nickPattern", "ghosts.nick_pattern"),
+    ("ghosts.usernamePattern", "ghosts.username_pattern"),
+    ("metrics.enable", "metrics.enabled"),
+    ("metrics.port", "metrics.port"),
+    ("metrics.host", "metrics.bind_address"),
+];
+
+/// Sections this bridge requires even when the legacy config left them out.
+const REQUIRED_SECTIONS: &[&str] = &["logging", "database", "room", "channel", "ghosts"];
+
+/// Whether `root` is a legacy config: one whose `auth` or `bridge` section
+/// uses the Node bridge's camelCase keys.
+pub fn is_legacy_config(root: &Value) -> bool {
+    ["auth", "bridge"].iter().any(|section| {
+        root.get(section)
+            .and_then(Value::as_mapping)
+            .is_some_and(|mapping| {
+                mapping
+                    .keys()
+                    .filter_map(Value::as_str)
+                    .any(|key| key.chars().any(|c| c.is_ascii_uppercase()))
+            })
+    })
+}
+
+/// Rewrites a legacy config into this bridge's layout. Returns the
+/// rewritten config and the dotted paths of the options it dropped.
+pub fn convert_legacy_config(root: &Value) -> (Value, Vec<String>) {
+    let mut converted = Value::Mapping(Mapping::new());
+    let mut unsupported = Vec::new();
+    if let Some(root) = root.as_mapping() {
+        convert_mapping(root, "", &mut converted, &mut unsupported);
+    }
+    for section in REQUIRED_SECTIONS {
+        entry(&mut converted, section);
+    }
+    (converted, unsupported)
+}
+
+fn convert_mapping(
+    mapping: &Mapping,
+    prefix: &str,
+    converted: &mut Value,
+    unsupported: &mut Vec<String>,
+) {
+    for (key, value) in mapping {
+        let Some(key) = key.as_str() else {
+            continue;
+        };
+        let path = if prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{prefix}.{key}")
+        };
+        let has_legacy_options = LEGACY_OPTIONS
+            .iter()
+            .any(|(legacy, _)| legacy.starts_with(&format!("{path}.")));
+        if let Some((_, target)) = LEGACY_OPTIONS.iter().find(|(legacy, _)| *legacy == path) {
+            insert(converted, target, snake_case_keys(value));
+        } else if let Some(nested) = value.as_mapping()
+            && has_legacy_options
+        {
+            convert_mapping(nested, &path, converted, unsupported);
+        } else if prefix.is_empty() && !has_legacy_options {
+            // Sections the Node bridge never had, e.g. `registration`, are
+            // already in this bridge's layout.
+            insert(converted, &path, value.clone());
+        } else {
+            unsupported.push(path);
+        }
+    }
+}
+
+fn entry<'a>(value: &'a mut Value, key: &str) -> &'a mut Value {
+    if !value.is_mapping() {
+        *value = Value::Mapping(Mapping::new());
+    }
+    let mapping = value.as_mapping_mut().expect("replaced above");
+    let key = Value::String(key.to_string());
+    if !mapping.contains_key(&key) {
+        mapping.insert(key.clone(), Value::Mapping(Mapping::new()));
+    }
+    mapping.get_mut(&key).expect("inserted above")
+}
+
+fn insert(root: &mut Value, path: &str, value: Value) {
+    let (parents, leaf) = path.rsplit_once('.').unwrap_or(("", path));
+    let mut target = root;
+    for parent in parents.split('.').filter(|p| !p.is_empty()) {
+        target = entry(target, parent);
+    }
+    *entry(target, leaf) = value;
+}
+
+/// `value` with the keys of nested mappings, e.g. log file entries, in
+/// snake_case.
+fn snake_case_keys(value: &Value) -> Value {
+    match value {
+        Value::Mapping(mapping) => Value::Mapping(
+            mapping
+                .iter()
+                .map(|(key, value)| {
+                    let key = match key.as_str() {
+                        Some(key) => Value::String(snake_case(key)),
+                        None => key.clone(),
+                    };
+                    (key, snake_case_keys(value))
+                })
+                .collect(),
+        ),
+        Value::Sequence(items) => Value::Sequence(items.iter().map(snake_case_keys).collect()),
+        other => other.clone(),
+    }
+}
+
+fn snake_case(key: &str) -> String {
+    let mut snake = String::with_capacity(key.len() + 4);
+    let mut previous_lower = false;
+    for c in key.chars() {
+        if c.is_ascii_uppercase() {
+            if previous_lower {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+            previous_lower = false;
+        } else {
+            snake.push(c);
+            previous_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+        }
+    }
+    snake
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{convert_legacy_config, is_legacy_config, snake_case};
+
+    const LEGACY: &str = r#"
+bridge:
+  domain: "example.org"
+  homeserverUrl: "http://localhost:8008"
+  disablePresence: true
+  userActivity:
+    minUserActiveDays: 2
+  enableMetrics: true
+auth:
+  clientID: "12345"
+  botToken: "mfa.real-token"
+logging:
+  console: "warn"
+  files:
+    - file: "debug.log"
+      maxFiles: "14d"
+database:
+  filename: "discord.db"
+channel:
+  deleteOptions:
+    ghostsLeave: false
+    bogus: 1
+metrics:
+  enable: true
+  host: "127.0.0.1"
+registration:
+  id: "discord"
+"#;
+
+    #[test]
+    fn legacy_options_map_onto_the_config() {
+        let legacy: serde_yaml::Value = serde_yaml::from_str(LEGACY).unwrap();
+        assert!(is_legacy_config(&legacy));
+
+        let (converted, unsupported) = convert_legacy_config(&legacy);
+        assert!(!is_legacy_config(&converted));
+        assert_eq!(
+            unsupported,
+            ["bridge.enableMetrics", "channel.deleteOptions.bogus"]
+        );
+        assert_eq!(
+            converted["bridge"]["homeserver_url"].as_str(),
+            Some("http://localhost:8008")
+        );
+        assert_eq!(
+            converted["bridge"]["user_activity"]["min_user_active_days"].as_u64(),
+            Some(2)
+        );
+        assert_eq!(converted["auth"]["client_id"].as_str(), Some("12345"));
+        assert_eq!(converted["logging"]["level"].as_str(), Some("warn"));
+        assert_eq!(
+            converted["logging"]["files"][0]["max_files"].as_str(),
+            Some("14d")
+        );
+        assert_eq!(
+            converted["channel"]["delete_options"]["ghosts_leave"].as_bool(),
+            Some(false)
+        );
+        assert_eq!(converted["metrics"]["enabled"].as_bool(), Some(true));
+        assert_eq!(
+            converted["metrics"]["bind_address"].as_str(),
+            Some("127.0.0.1")
+        );
+        assert_eq!(converted["registration"]["id"].as_str(), Some("discord"));
+        assert!(converted["room"].is_mapping());
+    }
+
+    #[test]
+    fn camel_case_keys_become_snake_case() {
+        assert_eq!(snake_case("maxFiles"), "max_files");
+        assert_eq!(snake_case("clientID"), "client_id");
+        assert_eq!(snake_case("file"), "file");
+    }
+}
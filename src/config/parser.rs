@@ -1,6 +1,8 @@
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
-use serde::{Deserialize, Deserializer, Serialize};
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use super::ConfigError;
 
@@ -19,6 +21,20 @@ pub struct Config {
     pub ghosts: GhostsConfig,
     #[serde(default)]
     pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub chaos: ChaosConfig,
+    #[serde(default)]
+    pub admin_api: AdminApiConfig,
+    #[serde(default)]
+    pub web: WebConfig,
+    #[serde(default)]
+    pub delivery: DeliveryConfig,
+    #[serde(default)]
+    pub homeserver_admin: HomeserverAdminConfig,
+    #[serde(default)]
+    pub alerts: AlertsConfig,
+    #[serde(default)]
+    pub media: MediaConfig,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -34,6 +50,10 @@ pub struct BridgeConfig {
     pub presence_interval: u64,
     #[serde(default)]
     pub disable_presence: bool,
+    /// Log and count outbound Matrix and Discord calls instead of making
+    /// them. Database reads and writes still happen.
+    #[serde(default)]
+    pub dry_run: bool,
     #[serde(default)]
     pub disable_typing_notifications: bool,
     #[serde(default)]
@@ -44,10 +64,16 @@ pub struct BridgeConfig {
     pub enable_self_service_bridging: bool,
     #[serde(default)]
     pub disable_portal_bridging: bool,
+    /// Ignore Matrix read receipts instead of keeping each user's last-read
+    /// marker for the `receipts` provisioning endpoint.
     #[serde(default)]
     pub disable_read_receipts: bool,
+    /// Keep `@room` and `@everyone` in Matrix messages from notifying the
+    /// whole Discord channel.
     #[serde(default)]
     pub disable_everyone_mention: bool,
+    /// Keep `@here` in Matrix messages from notifying the channel's online
+    /// members.
     #[serde(default)]
     pub disable_here_mention: bool,
     #[serde(default)]
@@ -66,16 +92,121 @@ pub struct BridgeConfig {
     pub invalid_token_message: String,
     #[serde(default)]
     pub user_activity: Option<UserActivityConfig>,
+    #[serde(default)]
+    pub presence_mapping: PresenceMappingConfig,
+    /// Discord role ids whose mentions notify the whole Matrix room (`@room`)
+    /// when the sender is allowed to mention everyone.
+    #[serde(default)]
+    pub room_mention_roles: Vec<String>,
+    /// Convert ISO 8601 timestamps in Matrix messages into Discord timestamp
+    /// tokens, which Discord renders in each reader's time zone.
+    #[serde(default)]
+    pub convert_iso_timestamps: bool,
+    /// Post an `m.notice` when a Discord user who recently posted in a room
+    /// changes their name or avatar.
+    #[serde(default)]
+    pub member_change_notices: bool,
+    /// Follow Discord messages containing `geo:` URIs with a Matrix location
+    /// event for each, which clients show on a map.
+    #[serde(default)]
+    pub geo_uri_locations: bool,
+    /// When a message in a public unbridged channel of the same guild
+    /// replies to or forwards one from a bridged channel, announce it with a
+    /// notice in that channel's room.
+    #[serde(default)]
+    pub cross_channel_context: bool,
+    /// Seconds to wait for an answer in the channel before a bridge request
+    /// is also sent to the guild owner by DM. `0` never escalates.
+    #[serde(default)]
+    pub approval_dm_after_secs: u64,
+    /// Patterns that unwrap messages other bridges relay into Discord as a
+    /// bot, so the Matrix side shows the original author.
+    #[serde(default)]
+    pub relay_extractors: Vec<RelayExtractorConfig>,
+    /// Bridge messages from other Discord bots, including the output of
+    /// their slash commands, which is attributed to the user who ran the
+    /// command.
+    #[serde(default)]
+    pub bridge_bot_messages: bool,
+    /// Patterns scrubbed from message text before it leaves the platform it
+    /// was written on.
+    #[serde(default)]
+    pub content_redaction: ContentRedactionConfig,
+    /// Notices posted to every bridged room and channel when maintenance
+    /// mode is switched on or off.
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
+    /// Unbridging of rooms nothing was bridged through for a long time.
+    #[serde(default)]
+    pub inactive_rooms: InactiveRoomsConfig,
+    /// `unix:/path/to.sock` serves the appservice endpoint on a Unix domain
+    /// socket instead of `bind_address:port`. `tcp:host:port` is also accepted.
+    #[serde(default)]
+    pub listen: Option<String>,
+    /// Octal file mode for the Unix socket, e.g. `"660"`.
+    #[serde(default)]
+    pub socket_permissions: Option<String>,
+}
+
+/// Where the web server accepts connections.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddress {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl BridgeConfig {
+    pub fn listen_address(&self) -> Result<ListenAddress, ConfigError> {
+        let Some(listen) = self.listen.as_deref().map(str::trim) else {
+            return Ok(ListenAddress::Tcp(format!(
+                "{}:{}",
+                self.bind_address, self.port
+            )));
+        };
+        if let Some(path) = listen.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err(ConfigError::InvalidConfig(
+                    "bridge.listen needs a socket path after unix:".to_string(),
+                ));
+            }
+            return Ok(ListenAddress::Unix(PathBuf::from(path)));
+        }
+        match listen.strip_prefix("tcp:") {
+            Some(addr) if !addr.is_empty() => Ok(ListenAddress::Tcp(addr.to_string())),
+            _ => Err(ConfigError::InvalidConfig(format!(
+                "bridge.listen must be unix:/path or tcp:host:port (got {listen:?})"
+            ))),
+        }
+    }
+
+    pub fn socket_mode(&self) -> Result<Option<u32>, ConfigError> {
+        let Some(value) = self.socket_permissions.as_deref() else {
+            return Ok(None);
+        };
+        u32::from_str_radix(value.trim().trim_start_matches("0o"), 8)
+            .ok()
+            .filter(|mode| *mode <= 0o777)
+            .map(Some)
+            .ok_or_else(|| {
+                ConfigError::InvalidConfig(format!(
+                    "bridge.socket_permissions must be an octal mode such as 660 (got {value:?})"
+                ))
+            })
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct RegistrationConfig {
     #[serde(alias = "id")]
     pub bridge_id: String,
-    #[serde(default, alias = "as_token")]
-    pub appservice_token: String,
-    #[serde(default, alias = "hs_token")]
-    pub homeserver_token: String,
+    #[serde(default, alias = "as_token", serialize_with = "serialize_redacted")]
+    pub appservice_token: SecretString,
+    #[serde(default, alias = "as_token_file")]
+    pub appservice_token_file: Option<PathBuf>,
+    #[serde(default, alias = "hs_token", serialize_with = "serialize_redacted")]
+    pub homeserver_token: SecretString,
+    #[serde(default, alias = "hs_token_file")]
+    pub homeserver_token_file: Option<PathBuf>,
     #[serde(default = "default_sender_localpart")]
     pub sender_localpart: String,
     #[serde(default)]
@@ -94,8 +225,10 @@ impl Default for RegistrationConfig {
     fn default() -> Self {
         Self {
             bridge_id: String::new(),
-            appservice_token: String::new(),
-            homeserver_token: String::new(),
+            appservice_token: SecretString::default(),
+            appservice_token_file: None,
+            homeserver_token: SecretString::default(),
+            homeserver_token_file: None,
             sender_localpart: default_sender_localpart(),
             namespaces: RegistrationNamespaces::default(),
             rate_limited: false,
@@ -136,13 +269,182 @@ pub struct UserActivityConfig {
     pub inactive_after_days: u64,
 }
 
+/// Unwraps messages a relay bot posts on behalf of someone else, e.g.
+/// `<alice> hello` from an IRC bridge.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RelayExtractorConfig {
+    /// Regex with `name` and `text` capture groups, matched against the
+    /// whole message.
+    pub pattern: String,
+    /// Discord user or webhook ids the pattern applies to; empty means any
+    /// bot or webhook.
+    #[serde(default)]
+    pub bot_ids: Vec<String>,
+}
+
+/// Text removed from messages before they are bridged, e.g. email
+/// addresses or API tokens.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ContentRedactionConfig {
+    #[serde(default)]
+    pub rules: Vec<RedactionRuleConfig>,
+    /// Only log and count matches; messages are bridged unchanged.
+    #[serde(default)]
+    pub test_mode: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RedactionRuleConfig {
+    /// Shown in logs and the `rule` label of the redaction counter.
+    pub name: String,
+    /// Regex; every match is replaced.
+    pub pattern: String,
+    /// May refer to capture groups as `$1` or `$name`.
+    #[serde(default = "default_redaction_replacement")]
+    pub replacement: String,
+}
+
+fn default_redaction_replacement() -> String {
+    "[redacted]".to_string()
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MaintenanceConfig {
+    #[serde(default = "default_maintenance_enter_notice")]
+    pub enter_notice: String,
+    #[serde(default = "default_maintenance_exit_notice")]
+    pub exit_notice: String,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            enter_notice: default_maintenance_enter_notice(),
+            exit_notice: default_maintenance_exit_notice(),
+        }
+    }
+}
+
+fn default_maintenance_enter_notice() -> String {
+    "The bridge is down for maintenance. Messages sent meanwhile will be delivered once it is back."
+        .to_string()
+}
+
+fn default_maintenance_exit_notice() -> String {
+    "The bridge is back from maintenance.".to_string()
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct InactiveRoomsConfig {
+    /// Days without a bridged message after which a room is unbridged.
+    /// `0` never unbridges.
+    #[serde(default)]
+    pub unbridge_after_days: u32,
+    /// Days before unbridging that the room and channel are warned.
+    #[serde(default = "default_inactive_warn_days_before")]
+    pub warn_days_before: u32,
+}
+
+impl Default for InactiveRoomsConfig {
+    fn default() -> Self {
+        Self {
+            unbridge_after_days: 0,
+            warn_days_before: default_inactive_warn_days_before(),
+        }
+    }
+}
+
+fn default_inactive_warn_days_before() -> u32 {
+    7
+}
+
+/// How each Discord presence state is shown on the Matrix side.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PresenceMappingConfig {
+    #[serde(default = "default_presence_online")]
+    pub online: PresenceMappingEntry,
+    #[serde(default = "default_presence_idle")]
+    pub idle: PresenceMappingEntry,
+    #[serde(default = "default_presence_dnd")]
+    pub dnd: PresenceMappingEntry,
+    #[serde(default = "default_presence_offline")]
+    pub offline: PresenceMappingEntry,
+}
+
+impl Default for PresenceMappingConfig {
+    fn default() -> Self {
+        Self {
+            online: default_presence_online(),
+            idle: default_presence_idle(),
+            dnd: default_presence_dnd(),
+            offline: default_presence_offline(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MatrixPresenceState {
+    Online,
+    Offline,
+    Unavailable,
+}
+
+impl MatrixPresenceState {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "online" => Some(Self::Online),
+            "offline" => Some(Self::Offline),
+            "unavailable" => Some(Self::Unavailable),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Online => "online",
+            Self::Offline => "offline",
+            Self::Unavailable => "unavailable",
+        }
+    }
+}
+
+impl Serialize for MatrixPresenceState {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for MatrixPresenceState {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        Self::parse(&value).ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "presence must be one of online, unavailable or offline (got {value:?})"
+            ))
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct PresenceMappingEntry {
+    /// Matrix presence to set: `online`, `unavailable` or `offline`.
+    pub presence: MatrixPresenceState,
+    #[serde(default)]
+    pub status_prefix: Option<String>,
+    #[serde(default)]
+    pub status_suffix: Option<String>,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AuthConfig {
-    pub bot_token: String,
+    #[serde(default, serialize_with = "serialize_redacted")]
+    pub bot_token: SecretString,
     #[serde(default)]
-    pub client_id: Option<String>,
+    pub bot_token_file: Option<PathBuf>,
     #[serde(default)]
-    pub client_secret: Option<String>,
+    pub client_id: Option<String>,
+    #[serde(default, serialize_with = "serialize_redacted_option")]
+    pub client_secret: Option<SecretString>,
     #[serde(default = "default_use_privileged_intents")]
     pub use_privileged_intents: bool,
 }
@@ -194,6 +496,9 @@ pub struct DatabaseConfig {
     pub max_connections: Option<u32>,
     #[serde(default)]
     pub min_connections: Option<u32>,
+    /// Database calls that may run at once; further calls wait their turn.
+    #[serde(default)]
+    pub max_blocking_threads: Option<u32>,
 }
 
 impl DatabaseConfig {
@@ -242,6 +547,12 @@ impl DatabaseConfig {
             DbType::Sqlite => Some(1),
         }
     }
+
+    pub fn max_blocking_threads(&self) -> usize {
+        self.max_blocking_threads
+            .map(|threads| threads as usize)
+            .unwrap_or(crate::db::blocking::DEFAULT_MAX_THREADS)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -261,6 +572,51 @@ pub struct RoomConfig {
     pub enable_room_creation: bool,
     #[serde(default = "default_kick_for")]
     pub kick_for: u64,
+    /// Default for rooms without their own setting: invite ghosts of new
+    /// Discord guild members into the bridged rooms of that guild.
+    #[serde(default)]
+    pub auto_invite_members: bool,
+    /// Default for rooms without their own setting: end the formatted body
+    /// of messages from Discord with a small "via Discord" badge, for
+    /// clients that hide the bridge's avatars.
+    #[serde(default)]
+    pub attribution_badge: bool,
+    /// Creation options for portal rooms of a guild, keyed by guild id.
+    #[serde(default)]
+    pub guilds: BTreeMap<String, PortalRoomConfig>,
+}
+
+impl RoomConfig {
+    pub fn portal_options(&self, guild_id: &str) -> PortalRoomConfig {
+        self.guilds.get(guild_id).cloned().unwrap_or_default()
+    }
+}
+
+/// How the portal rooms of one guild are created, and where its moderation
+/// log goes. Unset fields keep the homeserver's defaults; `visibility` falls
+/// back to `default_visibility`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct PortalRoomConfig {
+    /// `public` or `private` in the room directory.
+    #[serde(default)]
+    pub visibility: Option<String>,
+    /// `public`, `invite`, `knock` or `restricted`.
+    #[serde(default)]
+    pub join_rule: Option<String>,
+    /// `world_readable`, `shared`, `invited` or `joined`.
+    #[serde(default)]
+    pub history_visibility: Option<String>,
+    /// Given power level 50: Matrix ids, or Discord user ids whose ghosts
+    /// are meant.
+    #[serde(default)]
+    pub moderators: Vec<String>,
+    #[serde(default)]
+    pub encryption: bool,
+    /// Matrix room id that gets a notice for every moderation event in the
+    /// guild: moderator deletes, bans, AutoMod actions and failed bridged
+    /// moderation.
+    #[serde(default)]
+    pub modlog_room: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -281,6 +637,10 @@ pub struct ChannelConfig {
     pub webhook_name: String,
     #[serde(default = "default_webhook_avatar")]
     pub webhook_avatar: String,
+    /// Mirror Discord slowmode: hold non-moderator Matrix senders to the
+    /// channel's interval and show it in the room topic.
+    #[serde(default)]
+    pub mirror_slowmode: bool,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -299,6 +659,10 @@ pub struct ChannelDeleteOptionsConfig {
     pub set_invite_only: bool,
     #[serde(default = "default_ghosts_leave")]
     pub ghosts_leave: bool,
+    /// With `ghosts_leave`, deactivate ghosts left in no room through the
+    /// homeserver admin API so they drop out of the user directory.
+    #[serde(default)]
+    pub deactivate_ghosts: bool,
 }
 
 impl Default for ChannelDeleteOptionsConfig {
@@ -311,29 +675,48 @@ impl Default for ChannelDeleteOptionsConfig {
             unlist_from_directory: true,
             set_invite_only: true,
             ghosts_leave: true,
+            deactivate_ghosts: false,
         }
     }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct LimitsConfig {
+    /// Milliseconds between batches of ghosts joined by a member sync.
     #[serde(default = "default_room_ghost_join_delay")]
     pub room_ghost_join_delay: u64,
+    /// Up to this many milliseconds are added at random to each pause.
+    #[serde(default = "default_room_ghost_join_jitter")]
+    pub room_ghost_join_jitter: u64,
+    /// Guild members handled per batch, at most 1000.
+    #[serde(default = "default_room_ghost_join_batch_size")]
+    pub room_ghost_join_batch_size: usize,
     #[serde(default = "default_discord_send_delay")]
     pub discord_send_delay: u64,
     #[serde(default = "default_room_count")]
     pub room_count: i32,
     #[serde(default = "default_matrix_event_age_limit_ms")]
     pub matrix_event_age_limit_ms: u64,
+    /// Milliseconds between redactions when purging a user's messages.
+    #[serde(default = "default_redaction_delay")]
+    pub redaction_delay: u64,
+    /// A warning is logged when a Discord delivery route has less than this
+    /// percentage of its rate limit left; 0 turns the warning off.
+    #[serde(default = "default_discord_rate_limit_warn_percent")]
+    pub discord_rate_limit_warn_percent: u64,
 }
 
 impl Default for LimitsConfig {
     fn default() -> Self {
         Self {
             room_ghost_join_delay: 6000,
+            room_ghost_join_jitter: 2000,
+            room_ghost_join_batch_size: 50,
             discord_send_delay: 1500,
             room_count: -1,
             matrix_event_age_limit_ms: 900_000,
+            redaction_delay: 250,
+            discord_rate_limit_warn_percent: 20,
         }
     }
 }
@@ -350,6 +733,23 @@ pub struct GhostsConfig {
     pub displayname_template: String,
     #[serde(default)]
     pub avatar_url_template: Option<String>,
+    /// Keep ghosts out of the homeserver user directory, set through the
+    /// homeserver admin API when a ghost is registered.
+    #[serde(default)]
+    pub hide_from_directory: bool,
+    /// Send Discord messages from the bridge bot with MSC4144 per-message
+    /// profiles instead of registering ghost users, for homeservers that
+    /// restrict appservice registrations.
+    #[serde(default)]
+    pub per_message_profiles: bool,
+    /// Matrix ids and display names Discord users must not pose as. A ghost
+    /// whose display name looks like one of them, after folding case and
+    /// lookalike characters, gets `impersonation_suffix` appended. For a
+    /// Matrix id both the id and its localpart are protected.
+    #[serde(default)]
+    pub protected_names: Vec<String>,
+    #[serde(default = "default_impersonation_suffix")]
+    pub impersonation_suffix: String,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, Default)]
@@ -362,6 +762,277 @@ pub struct MetricsConfig {
     pub bind_address: String,
 }
 
+/// Failure injection for soak testing. Never enable this in production: a
+/// share of Discord sends, Matrix sends and database calls fail on purpose.
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+pub struct ChaosConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Probability (0.0 to 1.0) that a Discord send fails.
+    #[serde(default)]
+    pub discord_failure_rate: f64,
+    /// Probability (0.0 to 1.0) that a Matrix send fails.
+    #[serde(default)]
+    pub matrix_failure_rate: f64,
+    /// Probability (0.0 to 1.0) that a database call fails.
+    #[serde(default)]
+    pub database_failure_rate: f64,
+    /// Upper bound of the random delay added before each affected call.
+    #[serde(default)]
+    pub max_latency_ms: u64,
+    /// Fixed seed so a soak run can be reproduced.
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+/// Access control for the admin and provisioning web API. While no token
+/// exists, either here or in the `api_tokens` table, the API stays open.
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+pub struct AdminApiConfig {
+    #[serde(default)]
+    pub tokens: Vec<ApiTokenConfig>,
+    /// IPs or CIDR ranges allowed to reach the admin and provisioning routes.
+    /// Matched against the connecting peer, so list the proxy when behind
+    /// one. Empty allows everyone.
+    #[serde(default)]
+    pub allowed_ips: Vec<String>,
+    /// Raw events kept per side for `/admin/debug/events`. Zero, the
+    /// default, records nothing and disables the endpoint.
+    #[serde(default)]
+    pub debug_events: usize,
+}
+
+/// Serve the web server over HTTPS when both paths are set.
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+pub struct WebConfig {
+    #[serde(default)]
+    pub tls_cert_path: Option<PathBuf>,
+    #[serde(default)]
+    pub tls_key_path: Option<PathBuf>,
+}
+
+/// Access to the Synapse-compatible homeserver admin API, used by
+/// `channel.delete_options.deactivate_ghosts` and `ghosts.hide_from_directory`.
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+pub struct HomeserverAdminConfig {
+    /// Defaults to `bridge.homeserver_url`.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Access token of a homeserver admin.
+    #[serde(default, serialize_with = "serialize_redacted_option")]
+    pub token: Option<SecretString>,
+}
+
+/// Webhook alerts for bridge incidents. Each condition alerts once when it
+/// starts and once when it clears.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AlertsConfig {
+    /// Where alerts are POSTed; alerting is off without one.
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+    #[serde(default)]
+    pub format: AlertFormat,
+    /// Minutes the Discord gateway may stay disconnected before alerting.
+    #[serde(default = "default_gateway_down_minutes")]
+    pub gateway_down_minutes: u64,
+    /// Queued deliveries that trigger an alert. `0` never alerts.
+    #[serde(default = "default_queue_depth_threshold")]
+    pub queue_depth_threshold: u64,
+    /// Seconds between checks of the gateway and queue depth.
+    #[serde(default = "default_alert_check_interval_secs")]
+    pub check_interval_secs: u64,
+}
+
+impl Default for AlertsConfig {
+    fn default() -> Self {
+        Self {
+            webhook_url: None,
+            format: AlertFormat::default(),
+            gateway_down_minutes: default_gateway_down_minutes(),
+            queue_depth_threshold: default_queue_depth_threshold(),
+            check_interval_secs: default_alert_check_interval_secs(),
+        }
+    }
+}
+
+/// The body of an alert request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertFormat {
+    /// JSON with `condition`, `status`, `message` and `bridge`.
+    #[default]
+    Generic,
+    /// A Slack incoming webhook.
+    Slack,
+    /// A Discord channel webhook.
+    Discord,
+    /// An ntfy topic URL; the message is the body, with title, priority and
+    /// tags as headers.
+    Ntfy,
+}
+
+fn default_gateway_down_minutes() -> u64 {
+    5
+}
+
+fn default_queue_depth_threshold() -> u64 {
+    1000
+}
+
+fn default_alert_check_interval_secs() -> u64 {
+    30
+}
+
+/// How hard the bridge tries to deliver a message in each direction.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DeliveryConfig {
+    #[serde(default)]
+    pub matrix_to_discord: DirectionDeliveryConfig,
+    #[serde(default)]
+    pub discord_to_matrix: DirectionDeliveryConfig,
+    /// Attempts before an at-least-once message is given up on, counting the
+    /// first one.
+    #[serde(default = "default_delivery_max_attempts")]
+    pub max_attempts: u32,
+    /// Delay before the first retry; each further retry waits twice as long.
+    #[serde(default = "default_delivery_retry_delay_ms")]
+    pub retry_delay_ms: u64,
+}
+
+impl Default for DeliveryConfig {
+    fn default() -> Self {
+        Self {
+            matrix_to_discord: DirectionDeliveryConfig::default(),
+            discord_to_matrix: DirectionDeliveryConfig::default(),
+            max_attempts: default_delivery_max_attempts(),
+            retry_delay_ms: default_delivery_retry_delay_ms(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, Default)]
+pub struct DirectionDeliveryConfig {
+    #[serde(default)]
+    pub mode: DeliveryMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryMode {
+    /// Send once and drop the message if that fails.
+    #[default]
+    BestEffort,
+    /// Persist failed messages and retry them until they go through.
+    AtLeastOnce,
+}
+
+/// How attachments are carried across the bridge.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct MediaConfig {
+    /// Rooms can override this per direction with `!discord attachments`.
+    #[serde(default)]
+    pub attachment_policy: AttachmentPolicyConfig,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+pub struct AttachmentPolicyConfig {
+    #[serde(default)]
+    pub matrix_to_discord: AttachmentPolicy,
+    #[serde(default)]
+    pub discord_to_matrix: AttachmentPolicy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AttachmentPolicy {
+    /// Upload the file to the other side.
+    #[default]
+    Reupload,
+    /// Send a link to the original instead, storing nothing.
+    LinkOnly,
+    /// Upload the file and also send a link to the original.
+    ReuploadWithLink,
+}
+
+impl AttachmentPolicy {
+    pub const ALL: [Self; 3] = [Self::Reupload, Self::LinkOnly, Self::ReuploadWithLink];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Reupload => "reupload",
+            Self::LinkOnly => "link_only",
+            Self::ReuploadWithLink => "reupload_with_link",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|policy| policy.as_str() == value)
+    }
+
+    pub fn reuploads(&self) -> bool {
+        !matches!(self, Self::LinkOnly)
+    }
+
+    pub fn links(&self) -> bool {
+        !matches!(self, Self::Reupload)
+    }
+}
+
+fn default_delivery_max_attempts() -> u32 {
+    10
+}
+
+fn default_delivery_retry_delay_ms() -> u64 {
+    2000
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ApiTokenConfig {
+    /// Shown in metrics and the audit log instead of the token itself.
+    pub name: String,
+    #[serde(serialize_with = "serialize_redacted")]
+    pub token: SecretString,
+    pub scopes: Vec<ApiScope>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiScope {
+    ReadOnly,
+    Provision,
+    Moderate,
+    Admin,
+}
+
+impl ApiScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ReadOnly => "read_only",
+            Self::Provision => "provision",
+            Self::Moderate => "moderate",
+            Self::Admin => "admin",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "read_only" => Some(Self::ReadOnly),
+            "provision" => Some(Self::Provision),
+            "moderate" => Some(Self::Moderate),
+            "admin" => Some(Self::Admin),
+            _ => None,
+        }
+    }
+
+    /// Whether holding `self` allows an endpoint that requires `required`.
+    /// `admin` allows everything and every scope allows read-only access.
+    pub fn grants(&self, required: ApiScope) -> bool {
+        *self == required || *self == Self::Admin || required == Self::ReadOnly
+    }
+}
+
 impl Config {
     pub fn load() -> Result<Self, ConfigError> {
         let config_path = std::env::var("CONFIG_PATH").ok().unwrap_or_else(|| {
@@ -383,23 +1054,26 @@ impl Config {
         } else {
             let registration_field_presence =
                 registration_field_presence_from_config_yaml(&content)?;
-            let mut config: Config = serde_yaml::from_str(&content)?;
+            let mut config = parse_yaml_config(serde_yaml::from_str(&content)?)?;
             config.load_registration(path.as_ref(), registration_field_presence)?;
-            config.apply_env_overrides();
+            config.load_secret_files()?;
+            config.apply_env_overrides()?;
             config.normalize();
             config.validate()?;
             return Ok(config);
         };
 
-        config.apply_env_overrides();
+        config.load_secret_files()?;
+        config.apply_env_overrides()?;
         config.normalize();
         config.validate()?;
         Ok(config)
     }
 
     pub fn load_from_bytes(bytes: &[u8]) -> Result<Self, ConfigError> {
-        let mut config: Config = serde_yaml::from_slice(bytes)?;
-        config.apply_env_overrides();
+        let mut config = parse_yaml_config(serde_yaml::from_slice(bytes)?)?;
+        config.load_secret_files()?;
+        config.apply_env_overrides()?;
         config.normalize();
         config.validate()?;
         Ok(config)
@@ -419,26 +1093,36 @@ impl Config {
             ));
         }
 
-        if self.registration.appservice_token.is_empty() {
+        if self
+            .registration
+            .appservice_token
+            .expose_secret()
+            .is_empty()
+        {
             return Err(ConfigError::InvalidConfig(
                 "registration as_token cannot be empty (set registration.as_token or provide discord-registration.yaml)"
                     .to_string(),
             ));
         }
 
-        if self.registration.homeserver_token.is_empty() {
+        if self
+            .registration
+            .homeserver_token
+            .expose_secret()
+            .is_empty()
+        {
             return Err(ConfigError::InvalidConfig(
                 "registration hs_token cannot be empty (set registration.hs_token or provide discord-registration.yaml)"
                     .to_string(),
             ));
         }
 
-        if self.auth.bot_token.is_empty() {
+        if self.auth.bot_token.expose_secret().is_empty() {
             return Err(ConfigError::InvalidConfig(
                 "auth.bot_token cannot be empty".to_string(),
             ));
         }
-        if looks_like_placeholder_bot_token(&self.auth.bot_token) {
+        if looks_like_placeholder_bot_token(self.auth.bot_token.expose_secret()) {
             return Err(ConfigError::InvalidConfig(
                 "auth.bot_token is still using a placeholder value; set a real Discord bot token"
                     .to_string(),
@@ -457,35 +1141,214 @@ impl Config {
             ));
         }
 
+        for extractor in &self.bridge.relay_extractors {
+            let regex = regex::Regex::new(&extractor.pattern).map_err(|err| {
+                ConfigError::InvalidConfig(format!(
+                    "bridge.relay_extractors pattern {:?}: {err}",
+                    extractor.pattern
+                ))
+            })?;
+            let groups: Vec<_> = regex.capture_names().flatten().collect();
+            if !groups.contains(&"name") || !groups.contains(&"text") {
+                return Err(ConfigError::InvalidConfig(format!(
+                    "bridge.relay_extractors pattern {:?} needs name and text capture groups",
+                    extractor.pattern
+                )));
+            }
+        }
+
+        if let Some(webhook_url) = &self.alerts.webhook_url
+            && !url::Url::parse(webhook_url)
+                .is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+        {
+            return Err(ConfigError::InvalidConfig(format!(
+                "alerts.webhook_url must be an http(s) URL (got {webhook_url:?})"
+            )));
+        }
+
+        let mut rule_names = std::collections::HashSet::new();
+        for rule in &self.bridge.content_redaction.rules {
+            if rule.name.is_empty() || !rule_names.insert(rule.name.as_str()) {
+                return Err(ConfigError::InvalidConfig(format!(
+                    "bridge.content_redaction rule names must be unique and not empty (got {:?})",
+                    rule.name
+                )));
+            }
+            regex::Regex::new(&rule.pattern).map_err(|err| {
+                ConfigError::InvalidConfig(format!(
+                    "bridge.content_redaction rule {:?}: {err}",
+                    rule.name
+                ))
+            })?;
+        }
+
+        for (guild_id, options) in &self.room.guilds {
+            if let Some(join_rule) = &options.join_rule
+                && !matches!(
+                    join_rule.as_str(),
+                    "public" | "invite" | "knock" | "restricted"
+                )
+            {
+                return Err(ConfigError::InvalidConfig(format!(
+                    "room.guilds.{guild_id}.join_rule must be public, invite, knock or restricted (got {join_rule:?})"
+                )));
+            }
+            if let Some(history) = &options.history_visibility
+                && !matches!(
+                    history.as_str(),
+                    "world_readable" | "shared" | "invited" | "joined"
+                )
+            {
+                return Err(ConfigError::InvalidConfig(format!(
+                    "room.guilds.{guild_id}.history_visibility must be world_readable, shared, invited or joined (got {history:?})"
+                )));
+            }
+            if let Some(room_id) = &options.modlog_room
+                && !(room_id.starts_with('!') && room_id.contains(':'))
+            {
+                return Err(ConfigError::InvalidConfig(format!(
+                    "room.guilds.{guild_id}.modlog_room must be a room id like !abc:example.org (got {room_id:?})"
+                )));
+            }
+        }
+
+        let listen = self.bridge.listen_address()?;
+        self.bridge.socket_mode()?;
+        if matches!(listen, ListenAddress::Unix(_)) && self.web.tls_cert_path.is_some() {
+            return Err(ConfigError::InvalidConfig(
+                "web TLS cannot be used with a unix socket listener".to_string(),
+            ));
+        }
+
+        for (name, rate) in [
+            ("discord_failure_rate", self.chaos.discord_failure_rate),
+            ("matrix_failure_rate", self.chaos.matrix_failure_rate),
+            ("database_failure_rate", self.chaos.database_failure_rate),
+        ] {
+            if !(0.0..=1.0).contains(&rate) {
+                return Err(ConfigError::InvalidConfig(format!(
+                    "chaos.{name} must be between 0.0 and 1.0 (got {rate})"
+                )));
+            }
+        }
+
+        for entry in &self.admin_api.allowed_ips {
+            if let Err(err) = entry.parse::<crate::utils::IpNetwork>() {
+                return Err(ConfigError::InvalidConfig(format!(
+                    "admin_api.allowed_ips: {err}"
+                )));
+            }
+        }
+
+        for (option, enabled) in [
+            (
+                "channel.delete_options.deactivate_ghosts",
+                self.channel.delete_options.deactivate_ghosts,
+            ),
+            (
+                "ghosts.hide_from_directory",
+                self.ghosts.hide_from_directory,
+            ),
+        ] {
+            if enabled && self.homeserver_admin.token.is_none() {
+                return Err(ConfigError::InvalidConfig(format!(
+                    "{option} requires homeserver_admin.token"
+                )));
+            }
+        }
+
+        if self.delivery.max_attempts == 0 {
+            return Err(ConfigError::InvalidConfig(
+                "delivery.max_attempts must be at least 1".to_string(),
+            ));
+        }
+
+        if self.web.tls_cert_path.is_some() != self.web.tls_key_path.is_some() {
+            return Err(ConfigError::InvalidConfig(
+                "web.tls_cert_path and web.tls_key_path must be set together".to_string(),
+            ));
+        }
+
+        let mut token_names = std::collections::HashSet::new();
+        for token in &self.admin_api.tokens {
+            if token.name.is_empty() || token.token.expose_secret().is_empty() {
+                return Err(ConfigError::InvalidConfig(
+                    "admin_api.tokens entries need a name and a token".to_string(),
+                ));
+            }
+            if token.scopes.is_empty() {
+                return Err(ConfigError::InvalidConfig(format!(
+                    "admin_api token {:?} must have at least one scope",
+                    token.name
+                )));
+            }
+            if !token_names.insert(token.name.as_str()) {
+                return Err(ConfigError::InvalidConfig(format!(
+                    "admin_api token name {:?} is used more than once",
+                    token.name
+                )));
+            }
+        }
+
         Ok(())
     }
 
     fn normalize(&mut self) {
-        self.auth.bot_token = sanitize_bot_token(&self.auth.bot_token);
+        self.auth.bot_token = sanitize_bot_token(self.auth.bot_token.expose_secret()).into();
     }
 
-    fn apply_env_overrides(&mut self) {
-        if let Ok(value) = std::env::var("APPSERVICE_DISCORD_AUTH_BOT_TOKEN") {
-            self.auth.bot_token = sanitize_bot_token(&value);
+    /// Reads the `*_file` tokens. Setting both a token and its file is an
+    /// error, since only one of them can win.
+    fn load_secret_files(&mut self) -> Result<(), ConfigError> {
+        if let Some(token) = read_secret_field(
+            "auth.bot_token",
+            &self.auth.bot_token,
+            self.auth.bot_token_file.as_deref(),
+        )? {
+            self.auth.bot_token = token;
+        }
+        if let Some(token) = read_secret_field(
+            "registration.as_token",
+            &self.registration.appservice_token,
+            self.registration.appservice_token_file.as_deref(),
+        )? {
+            self.registration.appservice_token = token;
+        }
+        if let Some(token) = read_secret_field(
+            "registration.hs_token",
+            &self.registration.homeserver_token,
+            self.registration.homeserver_token_file.as_deref(),
+        )? {
+            self.registration.homeserver_token = token;
+        }
+        Ok(())
+    }
+
+    /// Runs after [`Self::load_secret_files`], so a token from the
+    /// environment beats one from the config file.
+    fn apply_env_overrides(&mut self) -> Result<(), ConfigError> {
+        if let Some(token) = env_secret("APPSERVICE_DISCORD_AUTH_BOT_TOKEN")? {
+            self.auth.bot_token = sanitize_bot_token(token.expose_secret()).into();
         }
         if let Ok(value) = std::env::var("APPSERVICE_DISCORD_AUTH_CLIENT_ID") {
             self.auth.client_id = Some(value);
         }
         if let Ok(value) = std::env::var("APPSERVICE_DISCORD_AUTH_CLIENT_SECRET") {
-            self.auth.client_secret = Some(value);
+            self.auth.client_secret = Some(value.into());
         }
         if let Ok(value) = std::env::var("APPSERVICE_DISCORD_REGISTRATION_ID") {
             self.registration.bridge_id = value;
         }
-        if let Ok(value) = std::env::var("APPSERVICE_DISCORD_REGISTRATION_AS_TOKEN") {
-            self.registration.appservice_token = value;
+        if let Some(token) = env_secret("APPSERVICE_DISCORD_REGISTRATION_AS_TOKEN")? {
+            self.registration.appservice_token = token;
         }
-        if let Ok(value) = std::env::var("APPSERVICE_DISCORD_REGISTRATION_HS_TOKEN") {
-            self.registration.homeserver_token = value;
+        if let Some(token) = env_secret("APPSERVICE_DISCORD_REGISTRATION_HS_TOKEN")? {
+            self.registration.homeserver_token = token;
         }
         if let Ok(value) = std::env::var("APPSERVICE_DISCORD_REGISTRATION_SENDER_LOCALPART") {
             self.registration.sender_localpart = value;
         }
+        Ok(())
     }
 
     fn load_registration(
@@ -518,14 +1381,26 @@ impl Config {
             self.registration.bridge_id = registration.bridge_id;
         }
         if !registration_field_presence.appservice_token
-            && self.registration.appservice_token.is_empty()
+            && self
+                .registration
+                .appservice_token
+                .expose_secret()
+                .is_empty()
+            && self.registration.appservice_token_file.is_none()
         {
             self.registration.appservice_token = registration.appservice_token;
+            self.registration.appservice_token_file = registration.appservice_token_file;
         }
         if !registration_field_presence.homeserver_token
-            && self.registration.homeserver_token.is_empty()
+            && self
+                .registration
+                .homeserver_token
+                .expose_secret()
+                .is_empty()
+            && self.registration.homeserver_token_file.is_none()
         {
             self.registration.homeserver_token = registration.homeserver_token;
+            self.registration.homeserver_token_file = registration.homeserver_token_file;
         }
         if !registration_field_presence.sender_localpart
             && self.registration.sender_localpart == default_sender_localpart()
@@ -606,6 +1481,25 @@ fn registration_field_presence_from_config_yaml(
     })
 }
 
+/// Deserializes a YAML config, translating the legacy Node bridge layout
+/// first when that is what `root` is.
+fn parse_yaml_config(root: serde_yaml::Value) -> Result<Config, ConfigError> {
+    if !super::legacy::is_legacy_config(&root) {
+        return Ok(serde_yaml::from_value(root)?);
+    }
+    let (converted, unsupported) = super::legacy::convert_legacy_config(&root);
+    tracing::warn!(
+        "config.yaml uses the matrix-appservice-discord layout; consider rewriting it in this bridge's format"
+    );
+    if !unsupported.is_empty() {
+        tracing::warn!(
+            "ignoring unsupported legacy options: {}",
+            unsupported.join(", ")
+        );
+    }
+    Ok(serde_yaml::from_value(converted)?)
+}
+
 fn mapping_has_any_key(mapping: &serde_yaml::Mapping, keys: &[&str]) -> bool {
     mapping
         .keys()
@@ -646,6 +1540,33 @@ fn default_invalid_token_message() -> String {
     "Your Discord bot token seems to be invalid, and the bridge cannot function. Please update it in your bridge settings and restart the bridge".to_string()
 }
 
+fn presence_entry(
+    presence: MatrixPresenceState,
+    status_prefix: Option<&str>,
+) -> PresenceMappingEntry {
+    PresenceMappingEntry {
+        presence,
+        status_prefix: status_prefix.map(ToString::to_string),
+        status_suffix: None,
+    }
+}
+
+fn default_presence_online() -> PresenceMappingEntry {
+    presence_entry(MatrixPresenceState::Online, None)
+}
+
+fn default_presence_idle() -> PresenceMappingEntry {
+    presence_entry(MatrixPresenceState::Unavailable, None)
+}
+
+fn default_presence_dnd() -> PresenceMappingEntry {
+    presence_entry(MatrixPresenceState::Online, Some("Do not disturb"))
+}
+
+fn default_presence_offline() -> PresenceMappingEntry {
+    presence_entry(MatrixPresenceState::Offline, None)
+}
+
 fn default_use_privileged_intents() -> bool {
     false
 }
@@ -702,6 +1623,14 @@ fn default_ghosts_leave() -> bool {
     true
 }
 
+fn default_room_ghost_join_jitter() -> u64 {
+    2000
+}
+
+fn default_room_ghost_join_batch_size() -> usize {
+    50
+}
+
 fn default_room_ghost_join_delay() -> u64 {
     6000
 }
@@ -710,6 +1639,14 @@ fn default_discord_send_delay() -> u64 {
     1500
 }
 
+fn default_redaction_delay() -> u64 {
+    250
+}
+
+fn default_discord_rate_limit_warn_percent() -> u64 {
+    20
+}
+
 fn default_room_count() -> i32 {
     -1
 }
@@ -726,6 +1663,10 @@ fn default_username_pattern() -> String {
     ":username#:tag".to_string()
 }
 
+fn default_impersonation_suffix() -> String {
+    " (Discord)".to_string()
+}
+
 fn default_metrics_port() -> u16 {
     9001
 }
@@ -755,6 +1696,75 @@ fn sanitize_bot_token(token: &str) -> String {
     without_prefix.trim().to_string()
 }
 
+/// The token in `file`, or `None` when no file is set.
+fn read_secret_field(
+    field: &str,
+    value: &SecretString,
+    file: Option<&Path>,
+) -> Result<Option<SecretString>, ConfigError> {
+    let Some(path) = file else {
+        return Ok(None);
+    };
+    if !value.expose_secret().is_empty() {
+        return Err(ConfigError::InvalidConfig(format!(
+            "{field} and {field}_file are both set; keep only one"
+        )));
+    }
+    read_secret_file(&format!("{field}_file"), path).map(Some)
+}
+
+/// The token in the environment variable `name`, or in the file named by
+/// `{name}_FILE`.
+fn env_secret(name: &str) -> Result<Option<SecretString>, ConfigError> {
+    secret_from_env(
+        name,
+        std::env::var(name).ok(),
+        std::env::var(format!("{name}_FILE")).ok(),
+    )
+}
+
+fn secret_from_env(
+    name: &str,
+    value: Option<String>,
+    file: Option<String>,
+) -> Result<Option<SecretString>, ConfigError> {
+    match (value, file) {
+        (Some(_), Some(_)) => Err(ConfigError::InvalidConfig(format!(
+            "{name} and {name}_FILE are both set; keep only one"
+        ))),
+        (Some(value), None) => Ok(Some(value.into())),
+        (None, Some(path)) => read_secret_file(&format!("{name}_FILE"), Path::new(&path)).map(Some),
+        (None, None) => Ok(None),
+    }
+}
+
+fn read_secret_file(field: &str, path: &Path) -> Result<SecretString, ConfigError> {
+    let content = std::fs::read_to_string(path).map_err(|e| {
+        ConfigError::InvalidConfig(format!("failed to read {field} {}: {e}", path.display()))
+    })?;
+    Ok(content.trim_end_matches(['\r', '\n']).to_string().into())
+}
+
+fn serialize_redacted<S>(_secret: &SecretString, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str("[REDACTED]")
+}
+
+fn serialize_redacted_option<S>(
+    secret: &Option<SecretString>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match secret {
+        Some(_) => serializer.serialize_some("[REDACTED]"),
+        None => serializer.serialize_none(),
+    }
+}
+
 fn looks_like_placeholder_bot_token(token: &str) -> bool {
     let lower = token.trim().to_ascii_lowercase();
     lower == "your_discord_bot_token"
@@ -766,11 +1776,15 @@ fn looks_like_placeholder_bot_token(token: &str) -> bool {
 
 #[cfg(test)]
 mod tests {
+    use secrecy::ExposeSecret;
+
     use super::{
-        Config, RegistrationConfig, RegistrationFieldPresence, RegistrationNamespaceEntry,
-        RegistrationNamespaces, default_registration_protocols, default_sender_localpart,
-        looks_like_placeholder_bot_token, registration_field_presence_from_config_yaml,
-        sanitize_bot_token,
+        ApiScope, Config, ListenAddress, MatrixPresenceState, RedactionRuleConfig,
+        RegistrationConfig, RegistrationFieldPresence, RegistrationNamespaceEntry,
+        RegistrationNamespaces, RelayExtractorConfig, default_registration_protocols,
+        default_sender_localpart, looks_like_placeholder_bot_token, read_secret_field,
+        read_secret_file, registration_field_presence_from_config_yaml, sanitize_bot_token,
+        secret_from_env,
     };
 
     fn config_yaml(registration: &str) -> String {
@@ -822,8 +1836,10 @@ registration:
         config.merge_registration_from_fallback(
             RegistrationConfig {
                 bridge_id: "file-id".to_string(),
-                appservice_token: "file-as".to_string(),
-                homeserver_token: "file-hs".to_string(),
+                appservice_token: "file-as".into(),
+                appservice_token_file: None,
+                homeserver_token: "file-hs".into(),
+                homeserver_token_file: None,
                 sender_localpart: "_from_file_".to_string(),
                 namespaces: RegistrationNamespaces {
                     users: vec![RegistrationNamespaceEntry {
@@ -840,8 +1856,14 @@ registration:
         );
 
         assert_eq!(config.registration.bridge_id, "cfg-id");
-        assert_eq!(config.registration.appservice_token, "cfg-as");
-        assert_eq!(config.registration.homeserver_token, "cfg-hs");
+        assert_eq!(
+            config.registration.appservice_token.expose_secret(),
+            "cfg-as"
+        );
+        assert_eq!(
+            config.registration.homeserver_token.expose_secret(),
+            "cfg-hs"
+        );
         assert_eq!(config.registration.sender_localpart, "_discord_");
         assert!(!config.registration.rate_limited);
         assert_eq!(config.registration.protocols, vec!["discord".to_string()]);
@@ -868,8 +1890,10 @@ registration:
         config.merge_registration_from_fallback(
             RegistrationConfig {
                 bridge_id: "file-id".to_string(),
-                appservice_token: "file-as".to_string(),
-                homeserver_token: "file-hs".to_string(),
+                appservice_token: "file-as".into(),
+                appservice_token_file: None,
+                homeserver_token: "file-hs".into(),
+                homeserver_token_file: None,
                 sender_localpart: "_from_file_".to_string(),
                 namespaces: RegistrationNamespaces::default(),
                 rate_limited: true,
@@ -879,8 +1903,14 @@ registration:
         );
 
         assert_eq!(config.registration.bridge_id, "cfg-id");
-        assert_eq!(config.registration.appservice_token, "cfg-as");
-        assert_eq!(config.registration.homeserver_token, "cfg-hs");
+        assert_eq!(
+            config.registration.appservice_token.expose_secret(),
+            "cfg-as"
+        );
+        assert_eq!(
+            config.registration.homeserver_token.expose_secret(),
+            "cfg-hs"
+        );
         assert_eq!(config.registration.sender_localpart, "_from_file_");
         assert!(config.registration.rate_limited);
         assert_eq!(config.registration.protocols, vec!["other".to_string()]);
@@ -922,4 +1952,254 @@ registration:
             default_registration_protocols()
         );
     }
+
+    #[test]
+    fn secret_file_is_read_without_trailing_newline() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bot_token");
+        std::fs::write(&path, "mfa.from-file\n").unwrap();
+
+        let secret = read_secret_file("auth.bot_token_file", &path).unwrap();
+        assert_eq!(secret.expose_secret(), "mfa.from-file");
+    }
+
+    #[test]
+    fn missing_secret_file_reports_field_name() {
+        let err = read_secret_file("auth.bot_token_file", std::path::Path::new("/nonexistent"))
+            .unwrap_err();
+        assert!(err.to_string().contains("auth.bot_token_file"));
+    }
+
+    #[test]
+    fn token_and_token_file_together_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bot_token");
+        std::fs::write(&path, "mfa.from-file\n").unwrap();
+
+        let inline: secrecy::SecretString = "mfa.inline".into();
+        let err = read_secret_field("auth.bot_token", &inline, Some(&path)).unwrap_err();
+        assert!(err.to_string().contains("auth.bot_token_file are both set"));
+
+        let token = read_secret_field("auth.bot_token", &Default::default(), Some(&path))
+            .unwrap()
+            .unwrap();
+        assert_eq!(token.expose_secret(), "mfa.from-file");
+        assert!(
+            read_secret_field("auth.bot_token", &inline, None)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn env_token_comes_from_the_variable_or_its_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("token");
+        std::fs::write(&path, "from-env-file\n").unwrap();
+        let path = path.display().to_string();
+        let name = "APPSERVICE_DISCORD_AUTH_BOT_TOKEN";
+
+        let token = secret_from_env(name, None, Some(path.clone()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(token.expose_secret(), "from-env-file");
+        let token = secret_from_env(name, Some("from-env".to_string()), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(token.expose_secret(), "from-env");
+        let err = secret_from_env(name, Some("from-env".to_string()), Some(path)).unwrap_err();
+        assert!(err.to_string().contains("_FILE are both set"));
+        assert!(secret_from_env(name, None, None).unwrap().is_none());
+    }
+
+    #[test]
+    fn tokens_are_redacted_from_debug_and_serialized_output() {
+        let yaml = config_yaml(
+            r#"  id: "cfg-id"
+  as_token: "cfg-as-secret"
+  hs_token: "cfg-hs-secret""#,
+        );
+        let config: Config = serde_yaml::from_str(&yaml).unwrap();
+
+        let debug = format!("{config:?}");
+        let serialized = serde_yaml::to_string(&config).unwrap();
+        for output in [debug, serialized] {
+            assert!(!output.contains("mfa.real-token"));
+            assert!(!output.contains("cfg-as-secret"));
+            assert!(!output.contains("cfg-hs-secret"));
+        }
+    }
+
+    #[test]
+    fn invalid_presence_mapping_is_rejected() {
+        let registration = r#"  id: "cfg-id"
+  as_token: "cfg-as"
+  hs_token: "cfg-hs""#;
+        let config: Config = serde_yaml::from_str(&config_yaml(registration)).unwrap();
+        assert_eq!(
+            config.bridge.presence_mapping.dnd.presence,
+            MatrixPresenceState::Online
+        );
+
+        let with_dnd = |presence: &str| {
+            config_yaml(registration).replace(
+                "  domain: \"example.org\"\n",
+                &format!(
+                    "  domain: \"example.org\"\n  presence_mapping:\n    dnd:\n      presence: \"{presence}\"\n"
+                ),
+            )
+        };
+        let config: Config = serde_yaml::from_str(&with_dnd(" Unavailable ")).unwrap();
+        assert_eq!(
+            config.bridge.presence_mapping.dnd.presence,
+            MatrixPresenceState::Unavailable
+        );
+
+        let err = serde_yaml::from_str::<Config>(&with_dnd("busy")).unwrap_err();
+        assert!(err.to_string().contains("presence must be one of"));
+    }
+
+    #[test]
+    fn chaos_failure_rate_must_be_a_probability() {
+        let yaml = config_yaml(
+            r#"  id: "cfg-id"
+  as_token: "cfg-as"
+  hs_token: "cfg-hs""#,
+        );
+        let mut config: Config = serde_yaml::from_str(&yaml).unwrap();
+        assert!(!config.chaos.enabled);
+
+        config.chaos.database_failure_rate = 1.5;
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("chaos.database_failure_rate"));
+    }
+
+    #[test]
+    fn admin_api_token_names_must_be_unique() {
+        let yaml = config_yaml(
+            r#"  id: "cfg-id"
+  as_token: "cfg-as"
+  hs_token: "cfg-hs""#,
+        ) + r#"
+admin_api:
+  tokens:
+    - name: "ops"
+      token: "first"
+      scopes: ["provision", "moderate"]
+"#;
+        let mut config: Config = serde_yaml::from_str(&yaml).unwrap();
+        config.validate().unwrap();
+        assert_eq!(
+            config.admin_api.tokens[0].scopes,
+            [ApiScope::Provision, ApiScope::Moderate]
+        );
+
+        let mut duplicate = config.admin_api.tokens[0].clone();
+        duplicate.token = "second".into();
+        config.admin_api.tokens.push(duplicate);
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("used more than once"));
+    }
+
+    #[test]
+    fn web_tls_paths_and_allowed_ips_are_validated() {
+        let yaml = config_yaml(
+            r#"  id: "cfg-id"
+  as_token: "cfg-as"
+  hs_token: "cfg-hs""#,
+        ) + r#"
+admin_api:
+  allowed_ips: ["127.0.0.1", "10.0.0.0/8"]
+web:
+  tls_cert_path: "/etc/bridge/tls.crt"
+"#;
+        let mut config: Config = serde_yaml::from_str(&yaml).unwrap();
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("must be set together"));
+
+        config.web.tls_key_path = Some("/etc/bridge/tls.key".into());
+        config.validate().unwrap();
+
+        config.admin_api.allowed_ips.push("10.0.0.0/40".to_string());
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("admin_api.allowed_ips"));
+    }
+
+    #[test]
+    fn bridge_listen_accepts_unix_sockets_and_octal_modes() {
+        let yaml = config_yaml(
+            r#"  id: "cfg-id"
+  as_token: "cfg-as"
+  hs_token: "cfg-hs""#,
+        );
+        let mut config: Config = serde_yaml::from_str(&yaml).unwrap();
+        assert!(matches!(
+            config.bridge.listen_address().unwrap(),
+            ListenAddress::Tcp(_)
+        ));
+
+        config.bridge.listen = Some("unix:/run/discord-bridge/as.sock".to_string());
+        config.bridge.socket_permissions = Some("660".to_string());
+        config.validate().unwrap();
+        assert_eq!(
+            config.bridge.listen_address().unwrap(),
+            ListenAddress::Unix("/run/discord-bridge/as.sock".into())
+        );
+        assert_eq!(config.bridge.socket_mode().unwrap(), Some(0o660));
+
+        config.bridge.socket_permissions = Some("rw-rw----".to_string());
+        assert!(config.validate().is_err());
+
+        config.bridge.socket_permissions = None;
+        config.bridge.listen = Some("/run/discord-bridge/as.sock".to_string());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn relay_extractors_need_name_and_text_groups() {
+        let yaml = config_yaml(
+            r#"  id: "cfg-id"
+  as_token: "cfg-as"
+  hs_token: "cfg-hs""#,
+        );
+        let mut config: Config = serde_yaml::from_str(&yaml).unwrap();
+        config.bridge.relay_extractors.push(RelayExtractorConfig {
+            pattern: r"^<(?P<name>[^>]+)> (.*)$".to_string(),
+            bot_ids: Vec::new(),
+        });
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("name and text capture groups"));
+
+        config.bridge.relay_extractors[0].pattern = r"^<(?P<name>[^>]+)> (?P<text>.*)$".to_string();
+        config.validate().unwrap();
+
+        config.bridge.relay_extractors[0].pattern = "(".to_string();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn content_redaction_rules_need_unique_names_and_valid_patterns() {
+        let yaml = config_yaml(
+            r#"  id: "cfg-id"
+  as_token: "cfg-as"
+  hs_token: "cfg-hs""#,
+        );
+        let mut config: Config = serde_yaml::from_str(&yaml).unwrap();
+        let rule = RedactionRuleConfig {
+            name: "email".to_string(),
+            pattern: r"[\w.+-]+@[\w-]+\.[\w.]+".to_string(),
+            replacement: "[email]".to_string(),
+        };
+        config.bridge.content_redaction.rules.push(rule.clone());
+        config.validate().unwrap();
+
+        config.bridge.content_redaction.rules.push(rule);
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("unique"));
+
+        config.bridge.content_redaction.rules[1].name = "broken".to_string();
+        config.bridge.content_redaction.rules[1].pattern = "(".to_string();
+        let err = config.validate().unwrap_err();
+        assert!(err.to_string().contains("\"broken\""));
+    }
 }
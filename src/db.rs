@@ -1,12 +1,20 @@
 pub use self::error::DatabaseError;
 pub use self::manager::DatabaseManager;
 pub use self::models::{
-    EmojiMapping, MessageMapping, ProcessedEvent, RemoteRoomInfo, RemoteUserInfo, RoomMapping,
-    UserMapping,
+    ApiToken, AttachmentPolicyOverrides, AuditLogEntry, AuditLogFilter, AuditSource,
+    DeliveryDirection, EmojiMapping, EmojiUsage, EmojiUsageKind, MemberSyncProgress,
+    MessageMapping, MessageMappingFilter, PendingDelivery, ProcessedEvent, RemoteRoomInfo,
+    RemoteUserInfo, RoomMapping, RoomOrigin, RoomSettings, ThreadMapping, UserMapping,
+    UserRoomSettings,
+};
+pub use self::stores::{
+    ApiTokenStore, AuditStore, DeliveryStore, EmojiStore, MessageStore, RoomStore, UserStore,
 };
-pub use self::stores::{EmojiStore, MessageStore, RoomStore, UserStore};
 
+pub mod blocking;
+pub mod chaos;
 pub mod error;
+pub mod legacy_import;
 pub mod manager;
 pub mod models;
 #[cfg(feature = "postgres")]
@@ -16,6 +24,9 @@ pub mod stores;
 #[cfg(feature = "postgres")]
 pub mod postgres;
 
+#[cfg(feature = "async-postgres")]
+pub mod postgres_async;
+
 #[cfg(feature = "sqlite")]
 pub mod sqlite;
 
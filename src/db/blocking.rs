@@ -0,0 +1,89 @@
+//! The executor Diesel calls run on. It is a runtime of its own with a capped
+//! blocking pool, so database work queues behind its own limit instead of
+//! taking threads from media downloads and other `spawn_blocking` users.
+
+use once_cell::sync::OnceCell;
+use tokio::runtime::{Builder, Runtime};
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use crate::web::Metrics;
+
+/// Threads the executor may use when `database.max_blocking_threads` is
+/// unset.
+pub const DEFAULT_MAX_THREADS: usize = 16;
+
+static EXECUTOR: OnceCell<Runtime> = OnceCell::new();
+
+/// Builds the executor with room for `max_threads` concurrent database
+/// calls. Only the first call takes effect; calls made before it use
+/// [`DEFAULT_MAX_THREADS`].
+pub fn init(max_threads: usize) {
+    let max_threads = max_threads.max(1);
+    let mut built = false;
+    EXECUTOR.get_or_init(|| {
+        built = true;
+        build(max_threads)
+    });
+    if !built {
+        warn!(
+            "database executor already started, ignoring max_blocking_threads {}",
+            max_threads
+        );
+    }
+}
+
+fn build(max_threads: usize) -> Runtime {
+    Metrics::set_db_pool_capacity(max_threads as u64);
+    Builder::new_multi_thread()
+        .worker_threads(1)
+        .max_blocking_threads(max_threads)
+        .thread_name("db-blocking")
+        .build()
+        .expect("failed to build database executor")
+}
+
+/// Runs `f` on the database executor. Like `tokio::task::spawn_blocking`, but
+/// bounded and counted in the `bridge_db_pool_*` metrics.
+pub fn spawn<F, R>(f: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let executor = EXECUTOR.get_or_init(|| build(DEFAULT_MAX_THREADS));
+    Metrics::db_task_queued();
+    executor.spawn_blocking(move || {
+        let _running = RunningTask::start();
+        f()
+    })
+}
+
+/// Marks a task as running until dropped, so panicking tasks are counted
+/// as finished too.
+struct RunningTask;
+
+impl RunningTask {
+    fn start() -> Self {
+        Metrics::db_task_started();
+        Self
+    }
+}
+
+impl Drop for RunningTask {
+    fn drop(&mut self) {
+        Metrics::db_task_finished();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::spawn;
+
+    #[tokio::test]
+    async fn tasks_run_on_the_database_executor() {
+        let thread_name = spawn(|| std::thread::current().name().map(str::to_string))
+            .await
+            .unwrap();
+        assert_eq!(thread_name.as_deref(), Some("db-blocking"));
+    }
+}
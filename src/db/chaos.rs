@@ -0,0 +1,525 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+
+use super::DatabaseError;
+use super::models::{
+    ApiToken, AuditLogEntry, AuditLogFilter, EmojiMapping, EmojiUsage, MessageMapping,
+    MessageMappingFilter, PendingDelivery, RemoteRoomInfo, RemoteUserInfo, RoomMapping,
+    RoomSettings, ThreadMapping, UserMapping, UserRoomSettings,
+};
+use super::{
+    ApiTokenStore, AuditStore, DeliveryStore, EmojiStore, MessageStore, RoomStore, UserStore,
+};
+use crate::utils::{ChaosInjector, ChaosTarget};
+
+async fn inject(chaos: &ChaosInjector, operation: &str) -> Result<(), DatabaseError> {
+    chaos
+        .inject(ChaosTarget::Database, operation)
+        .await
+        .map_err(|e| DatabaseError::Connection(e.to_string()))
+}
+
+/// `RoomStore` wrapper that runs every call through the chaos injector first.
+pub struct ChaosRoomStore {
+    inner: Arc<dyn RoomStore>,
+    chaos: Arc<ChaosInjector>,
+}
+
+impl ChaosRoomStore {
+    pub fn new(inner: Arc<dyn RoomStore>, chaos: Arc<ChaosInjector>) -> Self {
+        Self { inner, chaos }
+    }
+}
+
+#[async_trait]
+impl RoomStore for ChaosRoomStore {
+    async fn get_room_by_discord_channel(
+        &self,
+        channel_id: &str,
+    ) -> Result<Option<RoomMapping>, DatabaseError> {
+        inject(&self.chaos, "get_room_by_discord_channel").await?;
+        self.inner.get_room_by_discord_channel(channel_id).await
+    }
+
+    async fn get_room_by_matrix_room(
+        &self,
+        room_id: &str,
+    ) -> Result<Option<RoomMapping>, DatabaseError> {
+        inject(&self.chaos, "get_room_by_matrix_room").await?;
+        self.inner.get_room_by_matrix_room(room_id).await
+    }
+
+    async fn get_room_by_id(&self, id: i64) -> Result<Option<RoomMapping>, DatabaseError> {
+        inject(&self.chaos, "get_room_by_id").await?;
+        self.inner.get_room_by_id(id).await
+    }
+
+    async fn count_rooms(&self) -> Result<i64, DatabaseError> {
+        inject(&self.chaos, "count_rooms").await?;
+        self.inner.count_rooms().await
+    }
+
+    async fn list_room_mappings(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<RoomMapping>, DatabaseError> {
+        inject(&self.chaos, "list_room_mappings").await?;
+        self.inner.list_room_mappings(limit, offset).await
+    }
+
+    async fn create_room_mapping(&self, mapping: &RoomMapping) -> Result<(), DatabaseError> {
+        inject(&self.chaos, "create_room_mapping").await?;
+        self.inner.create_room_mapping(mapping).await
+    }
+
+    async fn update_room_mapping(&self, mapping: &RoomMapping) -> Result<(), DatabaseError> {
+        inject(&self.chaos, "update_room_mapping").await?;
+        self.inner.update_room_mapping(mapping).await
+    }
+
+    async fn delete_room_mapping(&self, id: i64) -> Result<(), DatabaseError> {
+        inject(&self.chaos, "delete_room_mapping").await?;
+        self.inner.delete_room_mapping(id).await
+    }
+
+    async fn touch_room_mapping(&self, id: i64, at: DateTime<Utc>) -> Result<(), DatabaseError> {
+        inject(&self.chaos, "touch_room_mapping").await?;
+        self.inner.touch_room_mapping(id, at).await
+    }
+
+    async fn get_rooms_by_guild(&self, guild_id: &str) -> Result<Vec<RoomMapping>, DatabaseError> {
+        inject(&self.chaos, "get_rooms_by_guild").await?;
+        self.inner.get_rooms_by_guild(guild_id).await
+    }
+
+    async fn get_remote_room_info(
+        &self,
+        matrix_room_id: &str,
+    ) -> Result<Option<RemoteRoomInfo>, DatabaseError> {
+        inject(&self.chaos, "get_remote_room_info").await?;
+        self.inner.get_remote_room_info(matrix_room_id).await
+    }
+
+    async fn update_remote_room_info(
+        &self,
+        matrix_room_id: &str,
+        info: &RemoteRoomInfo,
+    ) -> Result<(), DatabaseError> {
+        inject(&self.chaos, "update_remote_room_info").await?;
+        self.inner
+            .update_remote_room_info(matrix_room_id, info)
+            .await
+    }
+
+    async fn get_room_settings(
+        &self,
+        matrix_room_id: &str,
+    ) -> Result<Option<RoomSettings>, DatabaseError> {
+        inject(&self.chaos, "get_room_settings").await?;
+        self.inner.get_room_settings(matrix_room_id).await
+    }
+
+    async fn set_room_settings(&self, settings: &RoomSettings) -> Result<(), DatabaseError> {
+        inject(&self.chaos, "set_room_settings").await?;
+        self.inner.set_room_settings(settings).await
+    }
+
+    async fn get_thread_by_discord_thread(
+        &self,
+        discord_thread_id: &str,
+    ) -> Result<Option<ThreadMapping>, DatabaseError> {
+        inject(&self.chaos, "get_thread_by_discord_thread").await?;
+        self.inner
+            .get_thread_by_discord_thread(discord_thread_id)
+            .await
+    }
+
+    async fn get_thread_by_matrix_root(
+        &self,
+        matrix_root_event_id: &str,
+    ) -> Result<Option<ThreadMapping>, DatabaseError> {
+        inject(&self.chaos, "get_thread_by_matrix_root").await?;
+        self.inner
+            .get_thread_by_matrix_root(matrix_root_event_id)
+            .await
+    }
+
+    async fn upsert_thread_mapping(&self, mapping: &ThreadMapping) -> Result<(), DatabaseError> {
+        inject(&self.chaos, "upsert_thread_mapping").await?;
+        self.inner.upsert_thread_mapping(mapping).await
+    }
+
+    async fn delete_thread_mapping(&self, discord_thread_id: &str) -> Result<(), DatabaseError> {
+        inject(&self.chaos, "delete_thread_mapping").await?;
+        self.inner.delete_thread_mapping(discord_thread_id).await
+    }
+}
+
+/// `UserStore` wrapper that runs every call through the chaos injector first.
+pub struct ChaosUserStore {
+    inner: Arc<dyn UserStore>,
+    chaos: Arc<ChaosInjector>,
+}
+
+impl ChaosUserStore {
+    pub fn new(inner: Arc<dyn UserStore>, chaos: Arc<ChaosInjector>) -> Self {
+        Self { inner, chaos }
+    }
+}
+
+#[async_trait]
+impl UserStore for ChaosUserStore {
+    async fn get_user_by_discord_id(
+        &self,
+        discord_id: &str,
+    ) -> Result<Option<UserMapping>, DatabaseError> {
+        inject(&self.chaos, "get_user_by_discord_id").await?;
+        self.inner.get_user_by_discord_id(discord_id).await
+    }
+
+    async fn get_user_by_matrix_id(
+        &self,
+        matrix_id: &str,
+    ) -> Result<Option<UserMapping>, DatabaseError> {
+        inject(&self.chaos, "get_user_by_matrix_id").await?;
+        self.inner.get_user_by_matrix_id(matrix_id).await
+    }
+
+    async fn create_user_mapping(&self, mapping: &UserMapping) -> Result<(), DatabaseError> {
+        inject(&self.chaos, "create_user_mapping").await?;
+        self.inner.create_user_mapping(mapping).await
+    }
+
+    async fn update_user_mapping(&self, mapping: &UserMapping) -> Result<(), DatabaseError> {
+        inject(&self.chaos, "update_user_mapping").await?;
+        self.inner.update_user_mapping(mapping).await
+    }
+
+    async fn delete_user_mapping(&self, id: i64) -> Result<(), DatabaseError> {
+        inject(&self.chaos, "delete_user_mapping").await?;
+        self.inner.delete_user_mapping(id).await
+    }
+
+    async fn get_remote_user_info(
+        &self,
+        discord_user_id: &str,
+    ) -> Result<Option<RemoteUserInfo>, DatabaseError> {
+        inject(&self.chaos, "get_remote_user_info").await?;
+        self.inner.get_remote_user_info(discord_user_id).await
+    }
+
+    async fn update_remote_user_info(
+        &self,
+        discord_user_id: &str,
+        info: &RemoteUserInfo,
+    ) -> Result<(), DatabaseError> {
+        inject(&self.chaos, "update_remote_user_info").await?;
+        self.inner
+            .update_remote_user_info(discord_user_id, info)
+            .await
+    }
+
+    async fn get_all_user_ids(&self) -> Result<Vec<String>, DatabaseError> {
+        inject(&self.chaos, "get_all_user_ids").await?;
+        self.inner.get_all_user_ids().await
+    }
+
+    async fn get_user_room_settings(
+        &self,
+        matrix_room_id: &str,
+        matrix_user_id: &str,
+    ) -> Result<Option<UserRoomSettings>, DatabaseError> {
+        inject(&self.chaos, "get_user_room_settings").await?;
+        self.inner
+            .get_user_room_settings(matrix_room_id, matrix_user_id)
+            .await
+    }
+
+    async fn set_user_room_settings(
+        &self,
+        settings: &UserRoomSettings,
+    ) -> Result<(), DatabaseError> {
+        inject(&self.chaos, "set_user_room_settings").await?;
+        self.inner.set_user_room_settings(settings).await
+    }
+
+    async fn search_users(
+        &self,
+        query: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<UserMapping>, DatabaseError> {
+        inject(&self.chaos, "search_users").await?;
+        self.inner.search_users(query, limit, offset).await
+    }
+
+    async fn set_presence_override(
+        &self,
+        discord_user_id: &str,
+        presence: Option<&str>,
+    ) -> Result<(), DatabaseError> {
+        inject(&self.chaos, "set_presence_override").await?;
+        self.inner
+            .set_presence_override(discord_user_id, presence)
+            .await
+    }
+}
+
+/// `MessageStore` wrapper that runs every call through the chaos injector first.
+pub struct ChaosMessageStore {
+    inner: Arc<dyn MessageStore>,
+    chaos: Arc<ChaosInjector>,
+}
+
+impl ChaosMessageStore {
+    pub fn new(inner: Arc<dyn MessageStore>, chaos: Arc<ChaosInjector>) -> Self {
+        Self { inner, chaos }
+    }
+}
+
+#[async_trait]
+impl MessageStore for ChaosMessageStore {
+    async fn get_by_discord_message_id(
+        &self,
+        discord_message_id: &str,
+    ) -> Result<Option<MessageMapping>, DatabaseError> {
+        inject(&self.chaos, "get_by_discord_message_id").await?;
+        self.inner
+            .get_by_discord_message_id(discord_message_id)
+            .await
+    }
+
+    async fn get_by_matrix_event_id(
+        &self,
+        matrix_event_id: &str,
+    ) -> Result<Option<MessageMapping>, DatabaseError> {
+        inject(&self.chaos, "get_by_matrix_event_id").await?;
+        self.inner.get_by_matrix_event_id(matrix_event_id).await
+    }
+
+    async fn upsert_message_mapping(&self, mapping: &MessageMapping) -> Result<(), DatabaseError> {
+        inject(&self.chaos, "upsert_message_mapping").await?;
+        self.inner.upsert_message_mapping(mapping).await
+    }
+
+    async fn delete_by_discord_message_id(
+        &self,
+        discord_message_id: &str,
+    ) -> Result<(), DatabaseError> {
+        inject(&self.chaos, "delete_by_discord_message_id").await?;
+        self.inner
+            .delete_by_discord_message_id(discord_message_id)
+            .await
+    }
+
+    async fn delete_by_matrix_event_id(&self, matrix_event_id: &str) -> Result<(), DatabaseError> {
+        inject(&self.chaos, "delete_by_matrix_event_id").await?;
+        self.inner.delete_by_matrix_event_id(matrix_event_id).await
+    }
+
+    async fn list_message_mappings(
+        &self,
+        filter: &MessageMappingFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<MessageMapping>, DatabaseError> {
+        inject(&self.chaos, "list_message_mappings").await?;
+        self.inner
+            .list_message_mappings(filter, limit, offset)
+            .await
+    }
+
+    async fn count_message_mappings(
+        &self,
+        filter: &MessageMappingFilter,
+    ) -> Result<i64, DatabaseError> {
+        inject(&self.chaos, "count_message_mappings").await?;
+        self.inner.count_message_mappings(filter).await
+    }
+}
+
+/// `EmojiStore` wrapper that runs every call through the chaos injector first.
+pub struct ChaosEmojiStore {
+    inner: Arc<dyn EmojiStore>,
+    chaos: Arc<ChaosInjector>,
+}
+
+impl ChaosEmojiStore {
+    pub fn new(inner: Arc<dyn EmojiStore>, chaos: Arc<ChaosInjector>) -> Self {
+        Self { inner, chaos }
+    }
+}
+
+#[async_trait]
+impl EmojiStore for ChaosEmojiStore {
+    async fn get_emoji_by_discord_id(
+        &self,
+        discord_emoji_id: &str,
+    ) -> Result<Option<EmojiMapping>, DatabaseError> {
+        inject(&self.chaos, "get_emoji_by_discord_id").await?;
+        self.inner.get_emoji_by_discord_id(discord_emoji_id).await
+    }
+
+    async fn get_emoji_by_mxc(&self, mxc_url: &str) -> Result<Option<EmojiMapping>, DatabaseError> {
+        inject(&self.chaos, "get_emoji_by_mxc").await?;
+        self.inner.get_emoji_by_mxc(mxc_url).await
+    }
+
+    async fn create_emoji(&self, emoji: &EmojiMapping) -> Result<(), DatabaseError> {
+        inject(&self.chaos, "create_emoji").await?;
+        self.inner.create_emoji(emoji).await
+    }
+
+    async fn update_emoji(&self, emoji: &EmojiMapping) -> Result<(), DatabaseError> {
+        inject(&self.chaos, "update_emoji").await?;
+        self.inner.update_emoji(emoji).await
+    }
+
+    async fn delete_emoji(&self, discord_emoji_id: &str) -> Result<(), DatabaseError> {
+        inject(&self.chaos, "delete_emoji").await?;
+        self.inner.delete_emoji(discord_emoji_id).await
+    }
+
+    async fn add_emoji_usage(&self, usage: &[EmojiUsage]) -> Result<(), DatabaseError> {
+        inject(&self.chaos, "add_emoji_usage").await?;
+        self.inner.add_emoji_usage(usage).await
+    }
+
+    async fn list_emoji_usage(&self, since: NaiveDate) -> Result<Vec<EmojiUsage>, DatabaseError> {
+        inject(&self.chaos, "list_emoji_usage").await?;
+        self.inner.list_emoji_usage(since).await
+    }
+}
+
+/// `AuditStore` wrapper that runs every call through the chaos injector first.
+pub struct ChaosAuditStore {
+    inner: Arc<dyn AuditStore>,
+    chaos: Arc<ChaosInjector>,
+}
+
+impl ChaosAuditStore {
+    pub fn new(inner: Arc<dyn AuditStore>, chaos: Arc<ChaosInjector>) -> Self {
+        Self { inner, chaos }
+    }
+}
+
+#[async_trait]
+impl AuditStore for ChaosAuditStore {
+    async fn record_audit_entry(&self, entry: &AuditLogEntry) -> Result<(), DatabaseError> {
+        inject(&self.chaos, "record_audit_entry").await?;
+        self.inner.record_audit_entry(entry).await
+    }
+
+    async fn list_audit_entries(
+        &self,
+        filter: &AuditLogFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<AuditLogEntry>, DatabaseError> {
+        inject(&self.chaos, "list_audit_entries").await?;
+        self.inner.list_audit_entries(filter, limit, offset).await
+    }
+
+    async fn count_audit_entries(&self, filter: &AuditLogFilter) -> Result<i64, DatabaseError> {
+        inject(&self.chaos, "count_audit_entries").await?;
+        self.inner.count_audit_entries(filter).await
+    }
+}
+
+/// `ApiTokenStore` wrapper that runs every call through the chaos injector first.
+pub struct ChaosApiTokenStore {
+    inner: Arc<dyn ApiTokenStore>,
+    chaos: Arc<ChaosInjector>,
+}
+
+impl ChaosApiTokenStore {
+    pub fn new(inner: Arc<dyn ApiTokenStore>, chaos: Arc<ChaosInjector>) -> Self {
+        Self { inner, chaos }
+    }
+}
+
+#[async_trait]
+impl ApiTokenStore for ChaosApiTokenStore {
+    async fn get_api_token_by_hash(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<ApiToken>, DatabaseError> {
+        inject(&self.chaos, "get_api_token_by_hash").await?;
+        self.inner.get_api_token_by_hash(token_hash).await
+    }
+
+    async fn list_api_tokens(&self) -> Result<Vec<ApiToken>, DatabaseError> {
+        inject(&self.chaos, "list_api_tokens").await?;
+        self.inner.list_api_tokens().await
+    }
+
+    async fn count_api_tokens(&self) -> Result<i64, DatabaseError> {
+        inject(&self.chaos, "count_api_tokens").await?;
+        self.inner.count_api_tokens().await
+    }
+
+    async fn create_api_token(&self, token: &ApiToken) -> Result<(), DatabaseError> {
+        inject(&self.chaos, "create_api_token").await?;
+        self.inner.create_api_token(token).await
+    }
+
+    async fn delete_api_token(&self, name: &str) -> Result<bool, DatabaseError> {
+        inject(&self.chaos, "delete_api_token").await?;
+        self.inner.delete_api_token(name).await
+    }
+}
+
+/// `DeliveryStore` wrapper that runs every call through the chaos injector first.
+pub struct ChaosDeliveryStore {
+    inner: Arc<dyn DeliveryStore>,
+    chaos: Arc<ChaosInjector>,
+}
+
+impl ChaosDeliveryStore {
+    pub fn new(inner: Arc<dyn DeliveryStore>, chaos: Arc<ChaosInjector>) -> Self {
+        Self { inner, chaos }
+    }
+}
+
+#[async_trait]
+impl DeliveryStore for ChaosDeliveryStore {
+    async fn enqueue_delivery(&self, delivery: &PendingDelivery) -> Result<(), DatabaseError> {
+        inject(&self.chaos, "enqueue_delivery").await?;
+        self.inner.enqueue_delivery(delivery).await
+    }
+
+    async fn list_due_deliveries(
+        &self,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<PendingDelivery>, DatabaseError> {
+        inject(&self.chaos, "list_due_deliveries").await?;
+        self.inner.list_due_deliveries(now, limit).await
+    }
+
+    async fn reschedule_delivery(
+        &self,
+        id: &str,
+        attempts: i32,
+        next_attempt_at: DateTime<Utc>,
+        last_error: Option<&str>,
+    ) -> Result<(), DatabaseError> {
+        inject(&self.chaos, "reschedule_delivery").await?;
+        self.inner
+            .reschedule_delivery(id, attempts, next_attempt_at, last_error)
+            .await
+    }
+
+    async fn delete_delivery(&self, id: &str) -> Result<(), DatabaseError> {
+        inject(&self.chaos, "delete_delivery").await?;
+        self.inner.delete_delivery(id).await
+    }
+
+    async fn count_deliveries(&self) -> Result<i64, DatabaseError> {
+        inject(&self.chaos, "count_deliveries").await?;
+        self.inner.count_deliveries().await
+    }
+}
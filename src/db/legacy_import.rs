@@ -0,0 +1,445 @@
+//! `import-legacy`: reads a matrix-appservice-discord (Node.js) database and
+//! turns its rooms, ghosts and bridged events into this bridge's room, user
+//! and message mappings.
+
+use std::fmt;
+
+use chrono::Utc;
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+use diesel::query_builder::SqlQuery;
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+use diesel::query_dsl::LoadQuery;
+use diesel::sql_types::{Nullable, Text};
+use diesel::{Connection, QueryableByName};
+use serde::Serialize;
+
+use super::{
+    DatabaseError, DatabaseManager, MessageMapping, RoomMapping, RoomOrigin, UserMapping, blocking,
+};
+
+/// Portal and plumbed rooms, with the channel each is bridged to.
+const ROOMS_QUERY: &str = "SELECT e.id AS entry_id, e.matrix_id, d.discord_guild, d.discord_channel, d.discord_name \
+     FROM room_entries e LEFT JOIN remote_room_data d ON d.room_id = e.remote_id";
+/// Ghosts, with the profile the Node bridge last gave them.
+const USERS_QUERY: &str = "SELECT e.matrix_id, e.remote_id, d.displayname, d.avatarurl \
+     FROM user_entries e LEFT JOIN remote_user_data d ON d.remote_id = e.remote_id";
+/// `matrix_id` is `<event id>;<room id>`.
+const EVENTS_QUERY: &str = "SELECT matrix_id, discord_id FROM event_store";
+
+#[derive(Debug, Clone, PartialEq, Eq, QueryableByName)]
+pub struct LegacyRoom {
+    #[diesel(sql_type = Text)]
+    pub entry_id: String,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub matrix_id: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub discord_guild: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub discord_channel: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub discord_name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, QueryableByName)]
+pub struct LegacyUser {
+    #[diesel(sql_type = Text)]
+    pub matrix_id: String,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub remote_id: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub displayname: Option<String>,
+    #[diesel(sql_type = Nullable<Text>)]
+    pub avatarurl: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, QueryableByName)]
+pub struct LegacyEvent {
+    #[diesel(sql_type = Text)]
+    pub matrix_id: String,
+    #[diesel(sql_type = Text)]
+    pub discord_id: String,
+}
+
+/// Everything read from the legacy tables.
+#[derive(Debug, Clone, Default)]
+pub struct LegacyData {
+    pub rooms: Vec<LegacyRoom>,
+    pub users: Vec<LegacyUser>,
+    pub events: Vec<LegacyEvent>,
+}
+
+/// A legacy row that was not imported, and why.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SkippedRow {
+    pub table: &'static str,
+    pub id: String,
+    pub reason: String,
+}
+
+impl SkippedRow {
+    fn new(table: &'static str, id: &str, reason: impl Into<String>) -> Self {
+        Self {
+            table,
+            id: id.to_string(),
+            reason: reason.into(),
+        }
+    }
+}
+
+/// The mappings a legacy database converts to.
+#[derive(Debug, Clone, Default)]
+pub struct LegacyImport {
+    pub rooms: Vec<RoomMapping>,
+    pub users: Vec<UserMapping>,
+    pub messages: Vec<MessageMapping>,
+    pub skipped: Vec<SkippedRow>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LegacyImportReport {
+    pub rooms: usize,
+    pub users: usize,
+    pub messages: usize,
+    pub skipped: Vec<SkippedRow>,
+    pub dry_run: bool,
+}
+
+impl fmt::Display for LegacyImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} rooms, {} users and {} messages, skipped {} rows",
+            if self.dry_run {
+                "would import"
+            } else {
+                "imported"
+            },
+            self.rooms,
+            self.users,
+            self.messages,
+            self.skipped.len()
+        )?;
+        for row in &self.skipped {
+            write!(f, "\n  {} {}: {}", row.table, row.id, row.reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// Converts the legacy rows, skipping those with nothing to map.
+pub fn convert(data: LegacyData) -> LegacyImport {
+    let now = Utc::now();
+    let mut import = LegacyImport::default();
+
+    for room in data.rooms {
+        let Some(matrix_room_id) = room.matrix_id.filter(|id| !id.is_empty()) else {
+            import.skipped.push(SkippedRow::new(
+                "room_entries",
+                &room.entry_id,
+                "no Matrix room",
+            ));
+            continue;
+        };
+        let (Some(guild_id), Some(channel_id)) = (room.discord_guild, room.discord_channel) else {
+            import.skipped.push(SkippedRow::new(
+                "room_entries",
+                &room.entry_id,
+                "no Discord channel",
+            ));
+            continue;
+        };
+        import.rooms.push(RoomMapping {
+            id: 0,
+            matrix_room_id,
+            discord_channel_id: channel_id,
+            discord_channel_name: room.discord_name.unwrap_or_default(),
+            discord_guild_id: guild_id,
+            created_by: None,
+            origin: Some(RoomOrigin::Admin),
+            last_active_at: None,
+            created_at: now,
+            updated_at: now,
+        });
+    }
+
+    for user in data.users {
+        let Some(discord_user_id) = user.remote_id.filter(|id| !id.is_empty()) else {
+            import.skipped.push(SkippedRow::new(
+                "user_entries",
+                &user.matrix_id,
+                "no Discord user",
+            ));
+            continue;
+        };
+        import.users.push(UserMapping {
+            id: 0,
+            matrix_user_id: user.matrix_id,
+            discord_username: user.displayname.unwrap_or_else(|| discord_user_id.clone()),
+            discord_user_id,
+            discord_discriminator: "0000".to_string(),
+            discord_avatar: user.avatarurl,
+            presence_override: None,
+            created_at: now,
+            updated_at: now,
+        });
+    }
+
+    for event in data.events {
+        let Some((event_id, room_id)) = event.matrix_id.split_once(';') else {
+            import.skipped.push(SkippedRow::new(
+                "event_store",
+                &event.matrix_id,
+                "no room id in matrix_id",
+            ));
+            continue;
+        };
+        // Newer versions append `;<guild>;<channel>` to the message id.
+        let mut discord_ids = event.discord_id.split(';');
+        let discord_message_id = discord_ids.next().unwrap_or_default();
+        let discord_channel_id = discord_ids.nth(1).filter(|id| !id.is_empty());
+        if event_id.is_empty() || room_id.is_empty() || discord_message_id.is_empty() {
+            import.skipped.push(SkippedRow::new(
+                "event_store",
+                &event.matrix_id,
+                "empty event, room or message id",
+            ));
+            continue;
+        }
+        import.messages.push(MessageMapping {
+            id: 0,
+            discord_message_id: discord_message_id.to_string(),
+            matrix_room_id: room_id.to_string(),
+            matrix_event_id: event_id.to_string(),
+            discord_channel_id: discord_channel_id.map(ToOwned::to_owned),
+            created_at: now,
+            updated_at: now,
+        });
+    }
+
+    import
+}
+
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+fn load<C, T>(conn: &mut C, table: &str, query: &str) -> Result<Vec<T>, DatabaseError>
+where
+    C: Connection,
+    SqlQuery: LoadQuery<'static, C, T>,
+{
+    use diesel::RunQueryDsl;
+
+    diesel::sql_query(query)
+        .load::<T>(conn)
+        .map_err(|e| DatabaseError::Query(format!("reading legacy {table}: {e}")))
+}
+
+#[cfg(any(feature = "postgres", feature = "sqlite"))]
+fn read_tables<C>(conn: &mut C) -> Result<LegacyData, DatabaseError>
+where
+    C: Connection,
+    SqlQuery: LoadQuery<'static, C, LegacyRoom>
+        + LoadQuery<'static, C, LegacyUser>
+        + LoadQuery<'static, C, LegacyEvent>,
+{
+    Ok(LegacyData {
+        rooms: load(conn, "room_entries", ROOMS_QUERY)?,
+        users: load(conn, "user_entries", USERS_QUERY)?,
+        events: load(conn, "event_store", EVENTS_QUERY)?,
+    })
+}
+
+/// Reads the legacy tables from a `postgres://` URL or an SQLite file,
+/// given as a path or a `sqlite://` URL.
+pub async fn read_legacy(url: &str) -> Result<LegacyData, DatabaseError> {
+    let url = url.to_string();
+    blocking::spawn(move || {
+        if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+            #[cfg(feature = "postgres")]
+            {
+                let mut conn = diesel::pg::PgConnection::establish(&url)
+                    .map_err(|e| DatabaseError::Connection(e.to_string()))?;
+                return read_tables(&mut conn);
+            }
+            #[cfg(not(feature = "postgres"))]
+            return Err(DatabaseError::Connection(
+                "built without the postgres feature".to_string(),
+            ));
+        }
+        #[cfg(feature = "sqlite")]
+        {
+            let path = url.strip_prefix("sqlite://").unwrap_or(&url);
+            let mut conn = diesel::sqlite::SqliteConnection::establish(path)
+                .map_err(|e| DatabaseError::Connection(e.to_string()))?;
+            read_tables(&mut conn)
+        }
+        #[cfg(not(feature = "sqlite"))]
+        Err(DatabaseError::Connection(
+            "built without the sqlite feature".to_string(),
+        ))
+    })
+    .await
+    .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+}
+
+/// Imports the legacy database at `url` into `db`. Rooms, users and
+/// messages this bridge already maps are skipped; with `dry_run` nothing is
+/// written.
+pub async fn import_legacy(
+    db: &DatabaseManager,
+    url: &str,
+    dry_run: bool,
+) -> Result<LegacyImportReport, DatabaseError> {
+    let import = convert(read_legacy(url).await?);
+    let mut report = LegacyImportReport {
+        skipped: import.skipped,
+        dry_run,
+        ..Default::default()
+    };
+
+    let room_store = db.room_store();
+    for room in &import.rooms {
+        if room_store
+            .get_room_by_discord_channel(&room.discord_channel_id)
+            .await?
+            .is_some()
+            || room_store
+                .get_room_by_matrix_room(&room.matrix_room_id)
+                .await?
+                .is_some()
+        {
+            report.skipped.push(SkippedRow::new(
+                "room_entries",
+                &room.matrix_room_id,
+                "already bridged",
+            ));
+            continue;
+        }
+        if !dry_run {
+            room_store.create_room_mapping(room).await?;
+        }
+        report.rooms += 1;
+    }
+
+    let user_store = db.user_store();
+    for user in &import.users {
+        if user_store
+            .get_user_by_discord_id(&user.discord_user_id)
+            .await?
+            .is_some()
+        {
+            report.skipped.push(SkippedRow::new(
+                "user_entries",
+                &user.matrix_user_id,
+                "already mapped",
+            ));
+            continue;
+        }
+        if !dry_run {
+            user_store.create_user_mapping(user).await?;
+        }
+        report.users += 1;
+    }
+
+    let message_store = db.message_store();
+    for message in &import.messages {
+        if !dry_run {
+            message_store.upsert_message_mapping(message).await?;
+        }
+        report.messages += 1;
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LegacyData, LegacyEvent, LegacyRoom, LegacyUser, convert};
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn legacy_sqlite_tables_are_read() {
+        use diesel::{Connection, RunQueryDsl};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("discord.db");
+        let path = path.to_str().unwrap().to_string();
+        let mut conn = diesel::sqlite::SqliteConnection::establish(&path).unwrap();
+        for statement in [
+            "CREATE TABLE room_entries (id TEXT NOT NULL PRIMARY KEY, matrix_id TEXT, remote_id TEXT)",
+            "CREATE TABLE remote_room_data (room_id TEXT NOT NULL PRIMARY KEY, discord_guild TEXT NOT NULL, discord_channel TEXT NOT NULL, discord_name TEXT DEFAULT NULL, plumbed NUMERIC DEFAULT 0)",
+            "CREATE TABLE user_entries (matrix_id TEXT, remote_id TEXT)",
+            "CREATE TABLE remote_user_data (remote_id TEXT NOT NULL PRIMARY KEY, displayname TEXT, avatarurl TEXT, avatarurl_mxc TEXT)",
+            "CREATE TABLE event_store (matrix_id TEXT NOT NULL, discord_id TEXT NOT NULL)",
+            "INSERT INTO room_entries VALUES ('a', '!room:example.org', 'r1')",
+            "INSERT INTO remote_room_data VALUES ('r1', '10', '20', 'general', 0)",
+            "INSERT INTO user_entries VALUES ('@_discord_30:example.org', '30')",
+            "INSERT INTO remote_user_data VALUES ('30', 'alice', NULL, NULL)",
+            "INSERT INTO event_store VALUES ('$event;!room:example.org', '40')",
+        ] {
+            diesel::sql_query(statement).execute(&mut conn).unwrap();
+        }
+        drop(conn);
+
+        let data = super::read_legacy(&format!("sqlite://{path}"))
+            .await
+            .unwrap();
+        assert_eq!(data.rooms[0].discord_channel.as_deref(), Some("20"));
+        assert_eq!(data.users[0].displayname.as_deref(), Some("alice"));
+        assert_eq!(data.events[0].discord_id, "40");
+    }
+
+    #[test]
+    fn legacy_rows_convert_to_mappings() {
+        let import = convert(LegacyData {
+            rooms: vec![
+                LegacyRoom {
+                    entry_id: "1".to_string(),
+                    matrix_id: Some("!room:example.org".to_string()),
+                    discord_guild: Some("10".to_string()),
+                    discord_channel: Some("20".to_string()),
+                    discord_name: Some("general".to_string()),
+                },
+                LegacyRoom {
+                    entry_id: "2".to_string(),
+                    matrix_id: None,
+                    discord_guild: Some("10".to_string()),
+                    discord_channel: Some("21".to_string()),
+                    discord_name: None,
+                },
+            ],
+            users: vec![LegacyUser {
+                matrix_id: "@_discord_30:example.org".to_string(),
+                remote_id: Some("30".to_string()),
+                displayname: None,
+                avatarurl: None,
+            }],
+            events: vec![
+                LegacyEvent {
+                    matrix_id: "$event;!room:example.org".to_string(),
+                    discord_id: "40;10;20".to_string(),
+                },
+                LegacyEvent {
+                    matrix_id: "$orphan".to_string(),
+                    discord_id: "41".to_string(),
+                },
+            ],
+        });
+
+        assert_eq!(import.rooms.len(), 1);
+        assert_eq!(import.rooms[0].discord_channel_id, "20");
+        assert_eq!(import.rooms[0].discord_channel_name, "general");
+        assert_eq!(import.users[0].discord_user_id, "30");
+        assert_eq!(import.users[0].discord_username, "30");
+        assert_eq!(import.messages.len(), 1);
+        assert_eq!(import.messages[0].discord_message_id, "40");
+        assert_eq!(import.messages[0].matrix_event_id, "$event");
+        assert_eq!(import.messages[0].matrix_room_id, "!room:example.org");
+        assert_eq!(import.messages[0].discord_channel_id.as_deref(), Some("20"));
+
+        let skipped: Vec<_> = import
+            .skipped
+            .iter()
+            .map(|row| (row.table, row.id.as_str()))
+            .collect();
+        assert_eq!(skipped, [("room_entries", "2"), ("event_store", "$orphan")]);
+    }
+}
@@ -10,13 +10,30 @@ use diesel::pg::PgConnection;
 use diesel::r2d2::{self, ConnectionManager};
 
 use crate::config::{DatabaseConfig as ConfigDatabaseConfig, DbType as ConfigDbType};
+use crate::db::chaos::{
+    ChaosApiTokenStore, ChaosAuditStore, ChaosDeliveryStore, ChaosEmojiStore, ChaosMessageStore,
+    ChaosRoomStore, ChaosUserStore,
+};
 #[cfg(feature = "mysql")]
-use crate::db::mysql::{MysqlEmojiStore, MysqlMessageStore, MysqlRoomStore, MysqlUserStore};
+use crate::db::mysql::{
+    MysqlApiTokenStore, MysqlAuditStore, MysqlDeliveryStore, MysqlEmojiStore, MysqlMessageStore,
+    MysqlRoomStore, MysqlUserStore,
+};
 #[cfg(feature = "postgres")]
 use crate::db::postgres::{
-    PostgresEmojiStore, PostgresMessageStore, PostgresRoomStore, PostgresUserStore,
+    PostgresApiTokenStore, PostgresAuditStore, PostgresDeliveryStore, PostgresEmojiStore,
+};
+#[cfg(all(feature = "postgres", not(feature = "async-postgres")))]
+use crate::db::postgres::{PostgresMessageStore, PostgresRoomStore, PostgresUserStore};
+#[cfg(feature = "async-postgres")]
+use crate::db::postgres_async::{
+    self, AsyncPostgresMessageStore, AsyncPostgresRoomStore, AsyncPostgresUserStore,
 };
-use crate::db::{DatabaseError, EmojiStore, MessageStore, RoomStore, UserStore};
+use crate::db::{
+    ApiTokenStore, AuditStore, DatabaseError, DeliveryStore, EmojiStore, MessageStore, RoomStore,
+    UserStore, blocking,
+};
+use crate::utils::ChaosInjector;
 
 #[cfg(feature = "postgres")]
 pub type Pool = r2d2::Pool<ConnectionManager<PgConnection>>;
@@ -29,7 +46,102 @@ use diesel::Connection;
 use diesel::sqlite::SqliteConnection;
 
 #[cfg(feature = "sqlite")]
-use crate::db::sqlite::{SqliteEmojiStore, SqliteMessageStore, SqliteRoomStore, SqliteUserStore};
+use crate::db::sqlite::{
+    SqliteApiTokenStore, SqliteAuditStore, SqliteDeliveryStore, SqliteEmojiStore,
+    SqliteMessageStore, SqliteRoomStore, SqliteUserStore,
+};
+
+/// Columns introduced after a table was first created. MySQL and SQLite lack
+/// `ADD COLUMN IF NOT EXISTS`, so these are checked against the live schema.
+#[cfg(feature = "mysql")]
+const MYSQL_ADDED_COLUMNS: [(&str, &str, &str); 11] = [
+    ("user_mappings", "presence_override", "TEXT NULL"),
+    ("room_settings", "role_keywords", "TEXT NULL"),
+    ("room_mappings", "created_by", "TEXT NULL"),
+    ("room_mappings", "origin", "VARCHAR(32) NULL"),
+    ("room_mappings", "last_active_at", "DATETIME(6) NULL"),
+    (
+        "room_settings",
+        "inactivity_exempt",
+        "BOOLEAN NOT NULL DEFAULT FALSE",
+    ),
+    ("room_settings", "member_sync", "TEXT NULL"),
+    (
+        "room_settings",
+        "attribution_badge",
+        "BOOLEAN NOT NULL DEFAULT FALSE",
+    ),
+    (
+        "room_settings",
+        "delivery_confirmations",
+        "BOOLEAN NOT NULL DEFAULT FALSE",
+    ),
+    ("room_settings", "attachment_policy", "TEXT NULL"),
+    ("message_mappings", "discord_channel_id", "VARCHAR(64) NULL"),
+];
+#[cfg(feature = "sqlite")]
+const SQLITE_ADDED_COLUMNS: [(&str, &str, &str); 11] = [
+    ("user_mappings", "presence_override", "TEXT"),
+    ("room_settings", "role_keywords", "TEXT"),
+    ("room_mappings", "created_by", "TEXT"),
+    ("room_mappings", "origin", "TEXT"),
+    ("room_mappings", "last_active_at", "TEXT"),
+    (
+        "room_settings",
+        "inactivity_exempt",
+        "INTEGER NOT NULL DEFAULT 0",
+    ),
+    ("room_settings", "member_sync", "TEXT"),
+    (
+        "room_settings",
+        "attribution_badge",
+        "INTEGER NOT NULL DEFAULT 0",
+    ),
+    (
+        "room_settings",
+        "delivery_confirmations",
+        "INTEGER NOT NULL DEFAULT 0",
+    ),
+    ("room_settings", "attachment_policy", "TEXT"),
+    ("message_mappings", "discord_channel_id", "TEXT"),
+];
+
+#[cfg(any(feature = "mysql", feature = "sqlite"))]
+#[derive(diesel::QueryableByName)]
+struct ColumnCount {
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    count: i64,
+}
+
+#[cfg(feature = "mysql")]
+fn mysql_column_exists(
+    conn: &mut MysqlConnection,
+    table: &str,
+    column: &str,
+) -> Result<bool, DatabaseError> {
+    diesel::sql_query(
+        "SELECT COUNT(*) AS count FROM information_schema.columns WHERE table_schema = DATABASE() AND table_name = ? AND column_name = ?",
+    )
+    .bind::<diesel::sql_types::Text, _>(table)
+    .bind::<diesel::sql_types::Text, _>(column)
+    .get_result::<ColumnCount>(conn)
+    .map(|row| row.count > 0)
+    .map_err(|e| DatabaseError::Migration(e.to_string()))
+}
+
+#[cfg(feature = "sqlite")]
+fn sqlite_column_exists(
+    conn: &mut SqliteConnection,
+    table: &str,
+    column: &str,
+) -> Result<bool, DatabaseError> {
+    diesel::sql_query("SELECT COUNT(*) AS count FROM pragma_table_info(?) WHERE name = ?")
+        .bind::<diesel::sql_types::Text, _>(table)
+        .bind::<diesel::sql_types::Text, _>(column)
+        .get_result::<ColumnCount>(conn)
+        .map(|row| row.count > 0)
+        .map_err(|e| DatabaseError::Migration(e.to_string()))
+}
 
 #[derive(Clone)]
 pub struct DatabaseManager {
@@ -43,6 +155,9 @@ pub struct DatabaseManager {
     user_store: Arc<dyn UserStore>,
     message_store: Arc<dyn MessageStore>,
     emoji_store: Arc<dyn EmojiStore>,
+    audit_store: Arc<dyn AuditStore>,
+    api_token_store: Arc<dyn ApiTokenStore>,
+    delivery_store: Arc<dyn DeliveryStore>,
     db_type: DbType,
 }
 
@@ -66,6 +181,7 @@ impl From<ConfigDbType> for DbType {
 impl DatabaseManager {
     pub async fn new(config: &ConfigDatabaseConfig) -> Result<Self, DatabaseError> {
         let db_type = DbType::from(config.db_type());
+        blocking::init(config.max_blocking_threads());
 
         match db_type {
             #[cfg(feature = "postgres")]
@@ -74,7 +190,7 @@ impl DatabaseManager {
                 let max_connections = config.max_connections();
                 let min_connections = config.min_connections();
 
-                let manager = ConnectionManager::<PgConnection>::new(connection_string);
+                let manager = ConnectionManager::<PgConnection>::new(&connection_string);
 
                 let builder = r2d2::Pool::builder()
                     .max_size(max_connections.unwrap_or(10))
@@ -84,10 +200,32 @@ impl DatabaseManager {
                     .build(manager)
                     .map_err(|e| DatabaseError::Connection(e.to_string()))?;
 
-                let room_store = Arc::new(PostgresRoomStore::new(pool.clone()));
-                let user_store = Arc::new(PostgresUserStore::new(pool.clone()));
-                let message_store = Arc::new(PostgresMessageStore::new(pool.clone()));
+                #[cfg(not(feature = "async-postgres"))]
+                let (room_store, user_store, message_store) = (
+                    Arc::new(PostgresRoomStore::new(pool.clone())),
+                    Arc::new(PostgresUserStore::new(pool.clone())),
+                    Arc::new(PostgresMessageStore::new(pool.clone())),
+                );
+                // The per-message lookups go through their own async pool so they
+                // never wait on the blocking executor.
+                #[cfg(feature = "async-postgres")]
+                let (room_store, user_store, message_store) = {
+                    let async_pool = postgres_async::build_pool(
+                        &connection_string,
+                        max_connections.unwrap_or(10),
+                        min_connections.unwrap_or(1),
+                    )
+                    .await?;
+                    (
+                        Arc::new(AsyncPostgresRoomStore::new(async_pool.clone())),
+                        Arc::new(AsyncPostgresUserStore::new(async_pool.clone())),
+                        Arc::new(AsyncPostgresMessageStore::new(async_pool)),
+                    )
+                };
                 let emoji_store = Arc::new(PostgresEmojiStore::new(pool.clone()));
+                let audit_store = Arc::new(PostgresAuditStore::new(pool.clone()));
+                let api_token_store = Arc::new(PostgresApiTokenStore::new(pool.clone()));
+                let delivery_store = Arc::new(PostgresDeliveryStore::new(pool.clone()));
 
                 Ok(Self {
                     postgres_pool: Some(pool),
@@ -99,6 +237,9 @@ impl DatabaseManager {
                     user_store,
                     message_store,
                     emoji_store,
+                    audit_store,
+                    api_token_store,
+                    delivery_store,
                     db_type,
                 })
             }
@@ -110,7 +251,10 @@ impl DatabaseManager {
                 let room_store = Arc::new(SqliteRoomStore::new(path_arc.clone()));
                 let user_store = Arc::new(SqliteUserStore::new(path_arc.clone()));
                 let message_store = Arc::new(SqliteMessageStore::new(Arc::new(path.clone())));
-                let emoji_store = Arc::new(SqliteEmojiStore::new(path_arc));
+                let emoji_store = Arc::new(SqliteEmojiStore::new(path_arc.clone()));
+                let audit_store = Arc::new(SqliteAuditStore::new(path_arc.clone()));
+                let api_token_store = Arc::new(SqliteApiTokenStore::new(path_arc.clone()));
+                let delivery_store = Arc::new(SqliteDeliveryStore::new(path_arc));
 
                 Ok(Self {
                     #[cfg(feature = "postgres")]
@@ -122,6 +266,9 @@ impl DatabaseManager {
                     user_store,
                     message_store,
                     emoji_store,
+                    audit_store,
+                    api_token_store,
+                    delivery_store,
                     db_type,
                 })
             }
@@ -145,6 +292,9 @@ impl DatabaseManager {
                 let user_store = Arc::new(MysqlUserStore::new(pool.clone()));
                 let message_store = Arc::new(MysqlMessageStore::new(pool.clone()));
                 let emoji_store = Arc::new(MysqlEmojiStore::new(pool.clone()));
+                let audit_store = Arc::new(MysqlAuditStore::new(pool.clone()));
+                let api_token_store = Arc::new(MysqlApiTokenStore::new(pool.clone()));
+                let delivery_store = Arc::new(MysqlDeliveryStore::new(pool.clone()));
 
                 Ok(Self {
                     #[cfg(feature = "postgres")]
@@ -156,6 +306,9 @@ impl DatabaseManager {
                     user_store,
                     message_store,
                     emoji_store,
+                    audit_store,
+                    api_token_store,
+                    delivery_store,
                     db_type,
                 })
             }
@@ -172,11 +325,9 @@ impl DatabaseManager {
                 ));
             }
             #[cfg(not(feature = "mysql"))]
-            DbType::Mysql => {
-                return Err(DatabaseError::Connection(
-                    "MySQL feature not enabled".to_string(),
-                ));
-            }
+            DbType::Mysql => Err(DatabaseError::Connection(
+                "MySQL feature not enabled".to_string(),
+            )),
         }
     }
 
@@ -189,7 +340,10 @@ impl DatabaseManager {
         let room_store = Arc::new(SqliteRoomStore::new(path_arc.clone()));
         let user_store = Arc::new(SqliteUserStore::new(path_arc.clone()));
         let message_store = Arc::new(SqliteMessageStore::new(path_arc.clone()));
-        let emoji_store = Arc::new(SqliteEmojiStore::new(path_arc));
+        let emoji_store = Arc::new(SqliteEmojiStore::new(path_arc.clone()));
+        let audit_store = Arc::new(SqliteAuditStore::new(path_arc.clone()));
+        let api_token_store = Arc::new(SqliteApiTokenStore::new(path_arc.clone()));
+        let delivery_store = Arc::new(SqliteDeliveryStore::new(path_arc));
 
         Ok(Self {
             #[cfg(feature = "postgres")]
@@ -201,6 +355,9 @@ impl DatabaseManager {
             user_store,
             message_store,
             emoji_store,
+            audit_store,
+            api_token_store,
+            delivery_store,
             db_type: DbType::Sqlite,
         })
     }
@@ -235,18 +392,16 @@ impl DatabaseManager {
                 ));
             }
             #[cfg(not(feature = "mysql"))]
-            DbType::Mysql => {
-                return Err(DatabaseError::Migration(
-                    "MySQL feature not enabled".to_string(),
-                ));
-            }
+            DbType::Mysql => Err(DatabaseError::Migration(
+                "MySQL feature not enabled".to_string(),
+            )),
         }
     }
 
     #[cfg(feature = "postgres")]
     async fn migrate_postgres(pool: &Pool) -> Result<(), DatabaseError> {
         let pool = pool.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = pool
                 .get()
                 .map_err(|e| DatabaseError::Connection(e.to_string()))?;
@@ -260,6 +415,7 @@ impl DatabaseManager {
                     discord_username TEXT NOT NULL,
                     discord_discriminator TEXT NOT NULL,
                     discord_avatar TEXT,
+                    presence_override TEXT,
                     created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
                     updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
                 )
@@ -271,6 +427,9 @@ impl DatabaseManager {
                     discord_channel_id TEXT NOT NULL UNIQUE,
                     discord_channel_name TEXT NOT NULL,
                     discord_guild_id TEXT NOT NULL,
+                    created_by TEXT,
+                    origin TEXT,
+                    last_active_at TIMESTAMP WITH TIME ZONE,
                     created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
                     updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
                 )
@@ -290,6 +449,7 @@ impl DatabaseManager {
                     discord_message_id TEXT NOT NULL UNIQUE,
                     matrix_room_id TEXT NOT NULL,
                     matrix_event_id TEXT NOT NULL,
+                    discord_channel_id TEXT,
                     created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
                     updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
                 )
@@ -314,6 +474,83 @@ impl DatabaseManager {
                     updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
                 )
                 "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS audit_log (
+                    id BIGSERIAL PRIMARY KEY,
+                    actor TEXT NOT NULL,
+                    source TEXT NOT NULL,
+                    action TEXT NOT NULL,
+                    target TEXT,
+                    parameters TEXT NOT NULL DEFAULT '{}',
+                    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+                )
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS room_settings (
+                    matrix_room_id TEXT PRIMARY KEY,
+                    auto_invite_members BOOLEAN NOT NULL DEFAULT FALSE,
+                    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+                )
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS user_room_settings (
+                    matrix_room_id TEXT NOT NULL,
+                    matrix_user_id TEXT NOT NULL,
+                    webhook_nick TEXT,
+                    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+                    PRIMARY KEY (matrix_room_id, matrix_user_id)
+                )
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS emoji_usage (
+                    day TEXT NOT NULL,
+                    kind TEXT NOT NULL,
+                    discord_id TEXT NOT NULL,
+                    name TEXT NOT NULL,
+                    uses BIGINT NOT NULL DEFAULT 0,
+                    PRIMARY KEY (day, kind, discord_id)
+                )
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS thread_mappings (
+                    discord_thread_id TEXT PRIMARY KEY,
+                    discord_parent_channel_id TEXT NOT NULL,
+                    matrix_room_id TEXT NOT NULL,
+                    matrix_root_event_id TEXT NOT NULL,
+                    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+                )
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS api_tokens (
+                    id BIGSERIAL PRIMARY KEY,
+                    name TEXT NOT NULL UNIQUE,
+                    token_hash TEXT NOT NULL UNIQUE,
+                    scopes TEXT NOT NULL,
+                    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+                )
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS pending_deliveries (
+                    id TEXT PRIMARY KEY,
+                    direction TEXT NOT NULL,
+                    payload TEXT NOT NULL,
+                    attempts INTEGER NOT NULL DEFAULT 0,
+                    next_attempt_at TIMESTAMP WITH TIME ZONE NOT NULL,
+                    last_error TEXT,
+                    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+                )
+                "#,
+                "ALTER TABLE user_mappings ADD COLUMN IF NOT EXISTS presence_override TEXT",
+                "ALTER TABLE room_settings ADD COLUMN IF NOT EXISTS role_keywords TEXT",
+                "ALTER TABLE room_mappings ADD COLUMN IF NOT EXISTS created_by TEXT",
+                "ALTER TABLE room_mappings ADD COLUMN IF NOT EXISTS origin TEXT",
+                "ALTER TABLE room_mappings ADD COLUMN IF NOT EXISTS last_active_at TIMESTAMP WITH TIME ZONE",
+                "ALTER TABLE room_settings ADD COLUMN IF NOT EXISTS inactivity_exempt BOOLEAN NOT NULL DEFAULT FALSE",
+                "ALTER TABLE room_settings ADD COLUMN IF NOT EXISTS member_sync TEXT",
+                "ALTER TABLE room_settings ADD COLUMN IF NOT EXISTS attribution_badge BOOLEAN NOT NULL DEFAULT FALSE",
+                "ALTER TABLE room_settings ADD COLUMN IF NOT EXISTS delivery_confirmations BOOLEAN NOT NULL DEFAULT FALSE",
+                "ALTER TABLE room_settings ADD COLUMN IF NOT EXISTS attachment_policy TEXT",
+                "ALTER TABLE message_mappings ADD COLUMN IF NOT EXISTS discord_channel_id TEXT",
                 "CREATE INDEX IF NOT EXISTS idx_user_mappings_matrix_id ON user_mappings(matrix_user_id)",
                 "CREATE INDEX IF NOT EXISTS idx_user_mappings_discord_id ON user_mappings(discord_user_id)",
                 "CREATE INDEX IF NOT EXISTS idx_room_mappings_matrix_id ON room_mappings(matrix_room_id)",
@@ -325,6 +562,10 @@ impl DatabaseManager {
                 "CREATE INDEX IF NOT EXISTS idx_user_activity_timestamp ON user_activity(timestamp)",
                 "CREATE INDEX IF NOT EXISTS idx_emoji_mappings_discord_id ON emoji_mappings(discord_emoji_id)",
                 "CREATE INDEX IF NOT EXISTS idx_emoji_mappings_mxc ON emoji_mappings(mxc_url)",
+                "CREATE INDEX IF NOT EXISTS idx_audit_log_created_at ON audit_log(created_at)",
+                "CREATE INDEX IF NOT EXISTS idx_audit_log_actor ON audit_log(actor)",
+                "CREATE INDEX IF NOT EXISTS idx_pending_deliveries_next_attempt ON pending_deliveries(next_attempt_at)",
+                "CREATE INDEX IF NOT EXISTS idx_thread_mappings_matrix_root ON thread_mappings(matrix_root_event_id)",
             ];
 
             for statement in statements {
@@ -342,7 +583,7 @@ impl DatabaseManager {
     #[cfg(feature = "mysql")]
     async fn migrate_mysql(pool: &MysqlPool) -> Result<(), DatabaseError> {
         let pool = pool.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = pool
                 .get()
                 .map_err(|e| DatabaseError::Connection(e.to_string()))?;
@@ -356,6 +597,7 @@ impl DatabaseManager {
                     discord_username VARCHAR(255) NOT NULL,
                     discord_discriminator VARCHAR(32) NOT NULL,
                     discord_avatar TEXT NULL,
+                    presence_override TEXT NULL,
                     created_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6),
                     updated_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6) ON UPDATE CURRENT_TIMESTAMP(6)
                 ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4
@@ -367,6 +609,9 @@ impl DatabaseManager {
                     discord_channel_id VARCHAR(64) NOT NULL UNIQUE,
                     discord_channel_name VARCHAR(255) NOT NULL,
                     discord_guild_id VARCHAR(64) NOT NULL,
+                    created_by TEXT NULL,
+                    origin VARCHAR(32) NULL,
+                    last_active_at DATETIME(6) NULL,
                     created_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6),
                     updated_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6) ON UPDATE CURRENT_TIMESTAMP(6),
                     KEY idx_room_mappings_guild (discord_guild_id)
@@ -387,6 +632,7 @@ impl DatabaseManager {
                     discord_message_id VARCHAR(64) NOT NULL UNIQUE,
                     matrix_room_id VARCHAR(255) NOT NULL,
                     matrix_event_id VARCHAR(255) NOT NULL,
+                    discord_channel_id VARCHAR(64) NULL,
                     created_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6),
                     updated_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6) ON UPDATE CURRENT_TIMESTAMP(6),
                     KEY idx_message_mappings_matrix_event (matrix_event_id)
@@ -417,6 +663,76 @@ impl DatabaseManager {
                     KEY idx_emoji_mappings_mxc (mxc_url)
                 ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4
                 "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS audit_log (
+                    id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                    actor VARCHAR(255) NOT NULL,
+                    source VARCHAR(64) NOT NULL,
+                    action VARCHAR(64) NOT NULL,
+                    target VARCHAR(255) NULL,
+                    parameters TEXT NOT NULL,
+                    created_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6),
+                    KEY idx_audit_log_created_at (created_at),
+                    KEY idx_audit_log_actor (actor)
+                ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS room_settings (
+                    matrix_room_id VARCHAR(255) NOT NULL PRIMARY KEY,
+                    auto_invite_members BOOLEAN NOT NULL DEFAULT FALSE,
+                    updated_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6)
+                ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS user_room_settings (
+                    matrix_room_id VARCHAR(255) NOT NULL,
+                    matrix_user_id VARCHAR(255) NOT NULL,
+                    webhook_nick VARCHAR(80) NULL,
+                    updated_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6),
+                    PRIMARY KEY (matrix_room_id, matrix_user_id)
+                ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS emoji_usage (
+                    day CHAR(10) NOT NULL,
+                    kind VARCHAR(16) NOT NULL,
+                    discord_id VARCHAR(32) NOT NULL,
+                    name VARCHAR(255) NOT NULL,
+                    uses BIGINT NOT NULL DEFAULT 0,
+                    PRIMARY KEY (day, kind, discord_id)
+                ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS thread_mappings (
+                    discord_thread_id VARCHAR(32) NOT NULL PRIMARY KEY,
+                    discord_parent_channel_id VARCHAR(32) NOT NULL,
+                    matrix_room_id VARCHAR(255) NOT NULL,
+                    matrix_root_event_id VARCHAR(255) NOT NULL,
+                    created_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6),
+                    KEY idx_thread_mappings_matrix_root (matrix_root_event_id)
+                ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS api_tokens (
+                    id BIGINT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                    name VARCHAR(255) NOT NULL UNIQUE,
+                    token_hash CHAR(64) NOT NULL UNIQUE,
+                    scopes VARCHAR(255) NOT NULL,
+                    created_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6)
+                ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS pending_deliveries (
+                    id VARCHAR(64) NOT NULL PRIMARY KEY,
+                    direction VARCHAR(32) NOT NULL,
+                    payload MEDIUMTEXT NOT NULL,
+                    attempts INT NOT NULL DEFAULT 0,
+                    next_attempt_at DATETIME(6) NOT NULL,
+                    last_error TEXT,
+                    created_at DATETIME(6) NOT NULL DEFAULT CURRENT_TIMESTAMP(6),
+                    KEY idx_pending_deliveries_next_attempt (next_attempt_at)
+                ) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4
+                "#,
             ];
 
             for statement in statements {
@@ -425,6 +741,14 @@ impl DatabaseManager {
                     .map_err(|e| DatabaseError::Migration(e.to_string()))?;
             }
 
+            for (table, column, definition) in MYSQL_ADDED_COLUMNS {
+                if !mysql_column_exists(&mut conn, table, column)? {
+                    diesel::sql_query(format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"))
+                        .execute(&mut conn)
+                        .map_err(|e| DatabaseError::Migration(e.to_string()))?;
+                }
+            }
+
             Ok(())
         })
         .await
@@ -434,7 +758,7 @@ impl DatabaseManager {
     #[cfg(feature = "sqlite")]
     async fn migrate_sqlite(path: &str) -> Result<(), DatabaseError> {
         let path = path.to_string();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let conn_string = format!("sqlite://{}", path);
             let mut conn = SqliteConnection::establish(&conn_string)
                 .map_err(|e| DatabaseError::Connection(e.to_string()))?;
@@ -448,6 +772,7 @@ impl DatabaseManager {
                     discord_username TEXT NOT NULL,
                     discord_discriminator TEXT NOT NULL,
                     discord_avatar TEXT,
+                    presence_override TEXT,
                     created_at TEXT NOT NULL DEFAULT (datetime('now')),
                     updated_at TEXT NOT NULL DEFAULT (datetime('now'))
                 )
@@ -459,6 +784,9 @@ impl DatabaseManager {
                     discord_channel_id TEXT NOT NULL UNIQUE,
                     discord_channel_name TEXT NOT NULL,
                     discord_guild_id TEXT NOT NULL,
+                    created_by TEXT,
+                    origin TEXT,
+                    last_active_at TEXT,
                     created_at TEXT NOT NULL DEFAULT (datetime('now')),
                     updated_at TEXT NOT NULL DEFAULT (datetime('now'))
                 )
@@ -478,6 +806,7 @@ impl DatabaseManager {
                     discord_message_id TEXT NOT NULL UNIQUE,
                     matrix_room_id TEXT NOT NULL,
                     matrix_event_id TEXT NOT NULL,
+                    discord_channel_id TEXT,
                     created_at TEXT NOT NULL DEFAULT (datetime('now')),
                     updated_at TEXT NOT NULL DEFAULT (datetime('now'))
                 )
@@ -502,6 +831,72 @@ impl DatabaseManager {
                     updated_at TEXT NOT NULL DEFAULT (datetime('now'))
                 )
                 "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS audit_log (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    actor TEXT NOT NULL,
+                    source TEXT NOT NULL,
+                    action TEXT NOT NULL,
+                    target TEXT,
+                    parameters TEXT NOT NULL DEFAULT '{}',
+                    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+                )
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS room_settings (
+                    matrix_room_id TEXT PRIMARY KEY,
+                    auto_invite_members INTEGER NOT NULL DEFAULT 0,
+                    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+                )
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS user_room_settings (
+                    matrix_room_id TEXT NOT NULL,
+                    matrix_user_id TEXT NOT NULL,
+                    webhook_nick TEXT,
+                    updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    PRIMARY KEY (matrix_room_id, matrix_user_id)
+                )
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS emoji_usage (
+                    day TEXT NOT NULL,
+                    kind TEXT NOT NULL,
+                    discord_id TEXT NOT NULL,
+                    name TEXT NOT NULL,
+                    uses INTEGER NOT NULL DEFAULT 0,
+                    PRIMARY KEY (day, kind, discord_id)
+                )
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS thread_mappings (
+                    discord_thread_id TEXT PRIMARY KEY,
+                    discord_parent_channel_id TEXT NOT NULL,
+                    matrix_room_id TEXT NOT NULL,
+                    matrix_root_event_id TEXT NOT NULL,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+                )
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS api_tokens (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    name TEXT NOT NULL UNIQUE,
+                    token_hash TEXT NOT NULL UNIQUE,
+                    scopes TEXT NOT NULL,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+                )
+                "#,
+                r#"
+                CREATE TABLE IF NOT EXISTS pending_deliveries (
+                    id TEXT PRIMARY KEY,
+                    direction TEXT NOT NULL,
+                    payload TEXT NOT NULL,
+                    attempts INTEGER NOT NULL DEFAULT 0,
+                    next_attempt_at TEXT NOT NULL,
+                    last_error TEXT,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+                )
+                "#,
                 "CREATE INDEX IF NOT EXISTS idx_user_mappings_matrix_id ON user_mappings(matrix_user_id)",
                 "CREATE INDEX IF NOT EXISTS idx_user_mappings_discord_id ON user_mappings(discord_user_id)",
                 "CREATE INDEX IF NOT EXISTS idx_room_mappings_matrix_id ON room_mappings(matrix_room_id)",
@@ -513,6 +908,10 @@ impl DatabaseManager {
                 "CREATE INDEX IF NOT EXISTS idx_user_activity_timestamp ON user_activity(timestamp)",
                 "CREATE INDEX IF NOT EXISTS idx_emoji_mappings_discord_id ON emoji_mappings(discord_emoji_id)",
                 "CREATE INDEX IF NOT EXISTS idx_emoji_mappings_mxc ON emoji_mappings(mxc_url)",
+                "CREATE INDEX IF NOT EXISTS idx_audit_log_created_at ON audit_log(created_at)",
+                "CREATE INDEX IF NOT EXISTS idx_audit_log_actor ON audit_log(actor)",
+                "CREATE INDEX IF NOT EXISTS idx_pending_deliveries_next_attempt ON pending_deliveries(next_attempt_at)",
+                "CREATE INDEX IF NOT EXISTS idx_thread_mappings_matrix_root ON thread_mappings(matrix_root_event_id)",
             ];
 
             for statement in statements {
@@ -521,12 +920,37 @@ impl DatabaseManager {
                     .map_err(|e| DatabaseError::Migration(e.to_string()))?;
             }
 
+            for (table, column, definition) in SQLITE_ADDED_COLUMNS {
+                if !sqlite_column_exists(&mut conn, table, column)? {
+                    diesel::sql_query(format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"))
+                        .execute(&mut conn)
+                        .map_err(|e| DatabaseError::Migration(e.to_string()))?;
+                }
+            }
+
             Ok(())
         })
         .await
         .map_err(|e| DatabaseError::Migration(format!("migration task failed: {e}")))?
     }
 
+    /// Routes every store call through `chaos` so database failures can be
+    /// simulated. Does nothing unless chaos mode is enabled.
+    pub fn with_chaos(mut self, chaos: Arc<ChaosInjector>) -> Self {
+        if !chaos.is_enabled() {
+            return self;
+        }
+        self.room_store = Arc::new(ChaosRoomStore::new(self.room_store, chaos.clone()));
+        self.user_store = Arc::new(ChaosUserStore::new(self.user_store, chaos.clone()));
+        self.message_store = Arc::new(ChaosMessageStore::new(self.message_store, chaos.clone()));
+        self.emoji_store = Arc::new(ChaosEmojiStore::new(self.emoji_store, chaos.clone()));
+        self.audit_store = Arc::new(ChaosAuditStore::new(self.audit_store, chaos.clone()));
+        self.api_token_store =
+            Arc::new(ChaosApiTokenStore::new(self.api_token_store, chaos.clone()));
+        self.delivery_store = Arc::new(ChaosDeliveryStore::new(self.delivery_store, chaos));
+        self
+    }
+
     pub fn room_store(&self) -> Arc<dyn RoomStore> {
         self.room_store.clone()
     }
@@ -543,6 +967,18 @@ impl DatabaseManager {
         self.emoji_store.clone()
     }
 
+    pub fn audit_store(&self) -> Arc<dyn AuditStore> {
+        self.audit_store.clone()
+    }
+
+    pub fn api_token_store(&self) -> Arc<dyn ApiTokenStore> {
+        self.api_token_store.clone()
+    }
+
+    pub fn delivery_store(&self) -> Arc<dyn DeliveryStore> {
+        self.delivery_store.clone()
+    }
+
     #[cfg(feature = "postgres")]
     pub fn pool(&self) -> Option<&Pool> {
         self.postgres_pool.as_ref()
@@ -552,3 +988,535 @@ impl DatabaseManager {
         self.db_type
     }
 }
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::{Days, Duration, Utc};
+    use diesel::{Connection, RunQueryDsl};
+    use serde_json::json;
+
+    use super::DatabaseManager;
+    use crate::config::{AttachmentPolicy, DatabaseConfig};
+    use crate::db::{
+        AttachmentPolicyOverrides, AuditLogEntry, AuditLogFilter, AuditSource, DeliveryDirection,
+        EmojiUsage, EmojiUsageKind, MemberSyncProgress, MessageMapping, MessageMappingFilter,
+        PendingDelivery, RoomMapping, RoomOrigin, RoomSettings, ThreadMapping, UserMapping,
+        UserRoomSettings,
+    };
+
+    async fn sqlite_manager(path: &str) -> DatabaseManager {
+        let manager = DatabaseManager::new(&DatabaseConfig {
+            url: Some(format!("sqlite://{path}")),
+            conn_string: None,
+            filename: None,
+            user_store_path: None,
+            room_store_path: None,
+            max_connections: None,
+            min_connections: None,
+            max_blocking_threads: None,
+        })
+        .await
+        .unwrap();
+        manager.migrate().await.unwrap();
+        manager
+    }
+
+    #[tokio::test]
+    async fn sqlite_migration_adds_columns_to_legacy_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("legacy.db");
+        let path = path.to_str().unwrap().to_string();
+
+        let mut conn = diesel::sqlite::SqliteConnection::establish(&path).unwrap();
+        diesel::sql_query(
+            "CREATE TABLE user_mappings (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                matrix_user_id TEXT NOT NULL UNIQUE,
+                discord_user_id TEXT NOT NULL UNIQUE,
+                discord_username TEXT NOT NULL,
+                discord_discriminator TEXT NOT NULL,
+                discord_avatar TEXT,
+                created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+            )",
+        )
+        .execute(&mut conn)
+        .unwrap();
+        drop(conn);
+
+        let manager = sqlite_manager(&path).await;
+        // Running twice must not try to add the column again.
+        manager.migrate().await.unwrap();
+
+        let store = manager.user_store();
+        store
+            .create_user_mapping(&UserMapping {
+                id: 0,
+                matrix_user_id: "@_discord_1:example.org".to_string(),
+                discord_user_id: "1".to_string(),
+                discord_username: "alice".to_string(),
+                discord_discriminator: "0000".to_string(),
+                discord_avatar: None,
+                presence_override: None,
+                created_at: Utc::now(),
+                updated_at: Utc::now(),
+            })
+            .await
+            .unwrap();
+        store
+            .set_presence_override("1", Some("offline"))
+            .await
+            .unwrap();
+
+        let mapping = store.get_user_by_discord_id("1").await.unwrap().unwrap();
+        assert_eq!(mapping.presence_override.as_deref(), Some("offline"));
+    }
+
+    #[tokio::test]
+    async fn room_mapping_keeps_its_origin_and_last_activity() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rooms.db");
+        let manager = sqlite_manager(path.to_str().unwrap()).await;
+        let store = manager.room_store();
+
+        let created_at = Utc::now() - Duration::days(1);
+        store
+            .create_room_mapping(&RoomMapping {
+                id: 0,
+                matrix_room_id: "!a:example.org".to_string(),
+                discord_channel_id: "10".to_string(),
+                discord_channel_name: "general".to_string(),
+                discord_guild_id: "20".to_string(),
+                created_by: Some("@alice:example.org".to_string()),
+                origin: Some(RoomOrigin::ProvisioningApi),
+                last_active_at: None,
+                created_at,
+                updated_at: created_at,
+            })
+            .await
+            .unwrap();
+        let room = store
+            .get_room_by_discord_channel("10")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(room.origin, Some(RoomOrigin::ProvisioningApi));
+        assert_eq!(room.created_by.as_deref(), Some("@alice:example.org"));
+        assert_eq!(room.last_active_at, None);
+
+        let active_at = Utc::now();
+        store.touch_room_mapping(room.id, active_at).await.unwrap();
+        let room = store.get_room_by_id(room.id).await.unwrap().unwrap();
+        assert_eq!(
+            room.last_active_at.map(|at| at.timestamp_millis()),
+            Some(active_at.timestamp_millis())
+        );
+    }
+
+    #[tokio::test]
+    async fn audit_entries_are_filtered_and_paginated_newest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.db");
+        let manager = sqlite_manager(path.to_str().unwrap()).await;
+        let store = manager.audit_store();
+
+        let start = Utc::now() - Duration::minutes(10);
+        for (offset, (actor, source, action)) in [
+            ("@alice:example.org", AuditSource::MatrixCommand, "bridge"),
+            ("123", AuditSource::DiscordCommand, "approve"),
+            ("@alice:example.org", AuditSource::MatrixCommand, "unbridge"),
+            ("api", AuditSource::Api, "bridge"),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            store
+                .record_audit_entry(&AuditLogEntry {
+                    id: 0,
+                    actor: actor.to_string(),
+                    source,
+                    action: action.to_string(),
+                    target: Some("!room:example.org".to_string()),
+                    parameters: json!({ "step": offset }),
+                    created_at: start + Duration::minutes(offset as i64),
+                })
+                .await
+                .unwrap();
+        }
+
+        let alice = AuditLogFilter {
+            actor: Some("@alice:example.org".to_string()),
+            ..AuditLogFilter::default()
+        };
+        let entries = store.list_audit_entries(&alice, 10, 0).await.unwrap();
+        let actions: Vec<_> = entries.iter().map(|e| e.action.as_str()).collect();
+        assert_eq!(actions, ["unbridge", "bridge"]);
+        assert_eq!(entries[0].parameters, json!({ "step": 2 }));
+        assert_eq!(store.count_audit_entries(&alice).await.unwrap(), 2);
+
+        let page = store
+            .list_audit_entries(&AuditLogFilter::default(), 2, 1)
+            .await
+            .unwrap();
+        let actions: Vec<_> = page.iter().map(|e| e.action.as_str()).collect();
+        assert_eq!(actions, ["unbridge", "approve"]);
+
+        let recent = AuditLogFilter {
+            source: Some(AuditSource::Api),
+            since: Some(start + Duration::minutes(2)),
+            ..AuditLogFilter::default()
+        };
+        let entries = store.list_audit_entries(&recent, 10, 0).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].actor, "api");
+    }
+
+    #[tokio::test]
+    async fn room_settings_are_inserted_then_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.db");
+        let manager = sqlite_manager(path.to_str().unwrap()).await;
+        let store = manager.room_store();
+
+        assert!(
+            store
+                .get_room_settings("!room:example.org")
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        for enabled in [true, false] {
+            let role_keywords = if enabled {
+                BTreeMap::from([("10".to_string(), "moderators".to_string())])
+            } else {
+                BTreeMap::new()
+            };
+            store
+                .set_room_settings(&RoomSettings {
+                    auto_invite_members: enabled,
+                    role_keywords: role_keywords.clone(),
+                    inactivity_exempt: enabled,
+                    member_sync: enabled.then(|| MemberSyncProgress {
+                        after: Some("42".to_string()),
+                        joined: 3,
+                    }),
+                    attribution_badge: enabled,
+                    delivery_confirmations: enabled,
+                    attachment_policy: AttachmentPolicyOverrides {
+                        matrix_to_discord: enabled.then_some(AttachmentPolicy::LinkOnly),
+                        discord_to_matrix: None,
+                    },
+                    ..RoomSettings::new("!room:example.org")
+                })
+                .await
+                .unwrap();
+            let settings = store
+                .get_room_settings("!room:example.org")
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(settings.auto_invite_members, enabled);
+            assert_eq!(settings.role_keywords, role_keywords);
+            assert_eq!(settings.inactivity_exempt, enabled);
+            assert_eq!(settings.member_sync.is_some(), enabled);
+            assert_eq!(settings.attribution_badge, enabled);
+            assert_eq!(settings.delivery_confirmations, enabled);
+            assert_eq!(
+                settings.attachment_policy.matrix_to_discord,
+                enabled.then_some(AttachmentPolicy::LinkOnly)
+            );
+            if let Some(progress) = settings.member_sync {
+                assert_eq!(progress.after.as_deref(), Some("42"));
+                assert_eq!(progress.joined, 3);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn user_room_settings_are_kept_per_user_and_room() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("user-settings.db");
+        let manager = sqlite_manager(path.to_str().unwrap()).await;
+        let store = manager.user_store();
+
+        for nick in [Some("Alice"), None, Some("Ally")] {
+            store
+                .set_user_room_settings(&UserRoomSettings {
+                    matrix_room_id: "!a:example.org".to_string(),
+                    matrix_user_id: "@alice:example.org".to_string(),
+                    webhook_nick: nick.map(ToOwned::to_owned),
+                    updated_at: Utc::now(),
+                })
+                .await
+                .unwrap();
+            let settings = store
+                .get_user_room_settings("!a:example.org", "@alice:example.org")
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(settings.webhook_nick.as_deref(), nick);
+        }
+        assert!(
+            store
+                .get_user_room_settings("!b:example.org", "@alice:example.org")
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn thread_mappings_are_found_from_either_side() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("threads.db");
+        let manager = sqlite_manager(path.to_str().unwrap()).await;
+        let store = manager.room_store();
+
+        for root in ["$root1", "$root2"] {
+            store
+                .upsert_thread_mapping(&ThreadMapping {
+                    discord_thread_id: "900".to_string(),
+                    discord_parent_channel_id: "100".to_string(),
+                    matrix_room_id: "!a:example.org".to_string(),
+                    matrix_root_event_id: root.to_string(),
+                    created_at: Utc::now(),
+                })
+                .await
+                .unwrap();
+        }
+        let thread = store
+            .get_thread_by_discord_thread("900")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(thread.matrix_root_event_id, "$root2");
+        assert_eq!(thread.discord_parent_channel_id, "100");
+        let thread = store
+            .get_thread_by_matrix_root("$root2")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(thread.discord_thread_id, "900");
+        assert!(
+            store
+                .get_thread_by_matrix_root("$root1")
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        store.delete_thread_mapping("900").await.unwrap();
+        assert!(
+            store
+                .get_thread_by_discord_thread("900")
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn pending_deliveries_are_listed_once_due_then_rescheduled() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("deliveries.db");
+        let manager = sqlite_manager(path.to_str().unwrap()).await;
+        let store = manager.delivery_store();
+        let now = Utc::now();
+
+        for (id, due_in) in [("later", 60), ("first", -10), ("second", -5)] {
+            store
+                .enqueue_delivery(&PendingDelivery {
+                    id: id.to_string(),
+                    direction: DeliveryDirection::DiscordToMatrix,
+                    payload: json!({ "content": id }),
+                    attempts: 1,
+                    next_attempt_at: now + Duration::seconds(due_in),
+                    last_error: None,
+                    created_at: now + Duration::seconds(due_in),
+                })
+                .await
+                .unwrap();
+        }
+
+        let due = store.list_due_deliveries(now, 10).await.unwrap();
+        let ids: Vec<_> = due.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, ["first", "second"]);
+        assert_eq!(due[0].payload, json!({ "content": "first" }));
+        assert_eq!(due[0].direction, DeliveryDirection::DiscordToMatrix);
+
+        store
+            .reschedule_delivery("first", 2, now + Duration::seconds(30), Some("timeout"))
+            .await
+            .unwrap();
+        store.delete_delivery("second").await.unwrap();
+
+        assert!(store.list_due_deliveries(now, 10).await.unwrap().is_empty());
+        let due = store
+            .list_due_deliveries(now + Duration::seconds(60), 10)
+            .await
+            .unwrap();
+        let ids: Vec<_> = due.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, ["first", "later"]);
+        assert_eq!(due[0].attempts, 2);
+        assert_eq!(due[0].last_error.as_deref(), Some("timeout"));
+        assert_eq!(store.count_deliveries().await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn users_are_searched_and_paginated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("users.db");
+        let manager = sqlite_manager(path.to_str().unwrap()).await;
+        let store = manager.user_store();
+
+        for (index, (matrix_id, username)) in [
+            ("@alice:example.org", "Alice"),
+            ("@bob:example.org", "bob_the_builder"),
+            ("@carol:example.org", "carol"),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            store
+                .create_user_mapping(&UserMapping {
+                    id: 0,
+                    matrix_user_id: matrix_id.to_string(),
+                    discord_user_id: format!("10{index}"),
+                    discord_username: username.to_string(),
+                    discord_discriminator: "0".to_string(),
+                    discord_avatar: None,
+                    presence_override: None,
+                    created_at: Utc::now(),
+                    updated_at: Utc::now(),
+                })
+                .await
+                .unwrap();
+        }
+
+        let names = |users: Vec<UserMapping>| {
+            users
+                .into_iter()
+                .map(|user| user.discord_username)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(store.search_users(None, 2, 1).await.unwrap()),
+            ["bob_the_builder", "carol"]
+        );
+        assert_eq!(
+            names(store.search_users(Some("ALICE"), 10, 0).await.unwrap()),
+            ["Alice"]
+        );
+        assert_eq!(
+            names(store.search_users(Some("101"), 10, 0).await.unwrap()),
+            ["bob_the_builder"]
+        );
+        // `_` is matched literally rather than as a wildcard.
+        assert_eq!(
+            names(store.search_users(Some("bo_"), 10, 0).await.unwrap()),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            names(store.search_users(Some("_the_"), 10, 0).await.unwrap()),
+            ["bob_the_builder"]
+        );
+    }
+
+    #[tokio::test]
+    async fn message_mappings_are_filtered_by_room_and_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("messages.db");
+        let manager = sqlite_manager(path.to_str().unwrap()).await;
+        let store = manager.message_store();
+
+        let now = Utc::now();
+        for (index, (room, age)) in [("!a:x", 30), ("!b:x", 20), ("!a:x", 10), ("!a:x", 0)]
+            .into_iter()
+            .enumerate()
+        {
+            let created_at = now - Duration::minutes(age);
+            store
+                .upsert_message_mapping(&MessageMapping {
+                    id: 0,
+                    discord_message_id: format!("{index}"),
+                    matrix_room_id: room.to_string(),
+                    matrix_event_id: format!("$event{index}"),
+                    discord_channel_id: None,
+                    created_at,
+                    updated_at: created_at,
+                })
+                .await
+                .unwrap();
+        }
+
+        let filter = MessageMappingFilter {
+            matrix_room_id: Some("!a:x".to_string()),
+            since: Some(now - Duration::minutes(15)),
+            until: None,
+        };
+        let ids = |messages: Vec<MessageMapping>| {
+            messages
+                .into_iter()
+                .map(|message| message.discord_message_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            ids(store.list_message_mappings(&filter, 10, 0).await.unwrap()),
+            ["3", "2"]
+        );
+        assert_eq!(store.count_message_mappings(&filter).await.unwrap(), 2);
+
+        let window = MessageMappingFilter {
+            until: Some(now - Duration::minutes(5)),
+            ..Default::default()
+        };
+        assert_eq!(
+            ids(store.list_message_mappings(&window, 2, 1).await.unwrap()),
+            ["1", "0"]
+        );
+        assert_eq!(store.count_message_mappings(&window).await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn emoji_usage_is_added_to_daily_counters() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("emoji_usage.db");
+        let manager = sqlite_manager(path.to_str().unwrap()).await;
+        let store = manager.emoji_store();
+        let today = Utc::now().date_naive();
+        let usage = |day, name: &str, uses| EmojiUsage {
+            day,
+            kind: EmojiUsageKind::Emoji,
+            discord_id: "42".to_string(),
+            name: name.to_string(),
+            uses,
+        };
+
+        store
+            .add_emoji_usage(&[usage(today - Days::new(3), "wave", 1)])
+            .await
+            .unwrap();
+        store
+            .add_emoji_usage(&[usage(today, "wave", 2)])
+            .await
+            .unwrap();
+        store
+            .add_emoji_usage(&[usage(today, "hello", 5)])
+            .await
+            .unwrap();
+
+        let recent = store.list_emoji_usage(today - Days::new(1)).await.unwrap();
+        assert_eq!(recent, [usage(today, "hello", 7)]);
+        assert_eq!(
+            store
+                .list_emoji_usage(today - Days::new(7))
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+}
@@ -1,6 +1,11 @@
-use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 
+use super::DatabaseError;
+use crate::config::{ApiScope, AttachmentPolicy};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RoomMapping {
     pub id: i64,
@@ -8,10 +13,194 @@ pub struct RoomMapping {
     pub discord_channel_id: String,
     pub discord_channel_name: String,
     pub discord_guild_id: String,
+    /// MXID or Discord user id of whoever bridged the room, when known.
+    #[serde(default)]
+    pub created_by: Option<String>,
+    /// How the mapping was made; `None` for mappings older than this field.
+    #[serde(default)]
+    pub origin: Option<RoomOrigin>,
+    /// When a message was last bridged through the room.
+    #[serde(default)]
+    pub last_active_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+/// How a room mapping came to be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoomOrigin {
+    /// A Matrix user's bridge command in their own room.
+    SelfService,
+    ProvisioningApi,
+    /// A portal room created for a Discord channel.
+    Portal,
+    Admin,
+}
+
+impl RoomOrigin {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::SelfService => "self_service",
+            Self::ProvisioningApi => "provisioning_api",
+            Self::Portal => "portal",
+            Self::Admin => "admin",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "self_service" => Some(Self::SelfService),
+            "provisioning_api" => Some(Self::ProvisioningApi),
+            "portal" => Some(Self::Portal),
+            "admin" => Some(Self::Admin),
+            _ => None,
+        }
+    }
+}
+
+/// Per-room bridge options. Rooms without a row use the config defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomSettings {
+    pub matrix_room_id: String,
+    /// Invite ghosts of new Discord guild members into the room.
+    pub auto_invite_members: bool,
+    /// Keywords written out next to mentions of these Discord roles, keyed
+    /// by role id, so Matrix users can be notified through keyword rules.
+    #[serde(default)]
+    pub role_keywords: BTreeMap<String, String>,
+    /// Never unbridge the room for inactivity.
+    #[serde(default)]
+    pub inactivity_exempt: bool,
+    /// Progress of a running sync of guild members into the room; `None`
+    /// when none is pending.
+    #[serde(default)]
+    pub member_sync: Option<MemberSyncProgress>,
+    /// Mark messages from Discord with a "via Discord" badge in their
+    /// formatted body.
+    #[serde(default)]
+    pub attribution_badge: bool,
+    /// React with ✅ to Matrix messages once they reached Discord.
+    #[serde(default)]
+    pub delivery_confirmations: bool,
+    /// Overrides of `media.attachment_policy` for this room.
+    #[serde(default)]
+    pub attachment_policy: AttachmentPolicyOverrides,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// How far a member sync got, so it resumes after a restart.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemberSyncProgress {
+    /// The last Discord user id already handled; members are listed in id
+    /// order.
+    pub after: Option<String>,
+    /// Ghosts joined so far.
+    pub joined: u32,
+}
+
+impl RoomSettings {
+    /// Settings for `matrix_room_id` with every option off.
+    pub fn new(matrix_room_id: impl Into<String>) -> Self {
+        Self {
+            matrix_room_id: matrix_room_id.into(),
+            auto_invite_members: false,
+            role_keywords: BTreeMap::new(),
+            inactivity_exempt: false,
+            member_sync: None,
+            attribution_badge: false,
+            delivery_confirmations: false,
+            attachment_policy: AttachmentPolicyOverrides::default(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    /// Reads `role_keywords` as stored in the database, where rooms that
+    /// never set any have no value.
+    pub fn parse_role_keywords(
+        stored: Option<&str>,
+    ) -> Result<BTreeMap<String, String>, DatabaseError> {
+        stored
+            .map(|json| {
+                serde_json::from_str(json)
+                    .map_err(|e| DatabaseError::Query(format!("invalid role keywords: {e}")))
+            })
+            .transpose()
+            .map(Option::unwrap_or_default)
+    }
+
+    pub fn role_keywords_json(&self) -> String {
+        serde_json::to_string(&self.role_keywords).unwrap_or_else(|_| "{}".to_string())
+    }
+
+    pub fn parse_member_sync(
+        stored: Option<&str>,
+    ) -> Result<Option<MemberSyncProgress>, DatabaseError> {
+        stored
+            .map(|json| {
+                serde_json::from_str(json)
+                    .map_err(|e| DatabaseError::Query(format!("invalid member sync: {e}")))
+            })
+            .transpose()
+    }
+
+    pub fn member_sync_json(&self) -> Option<String> {
+        self.member_sync
+            .as_ref()
+            .and_then(|progress| serde_json::to_string(progress).ok())
+    }
+
+    pub fn parse_attachment_policy(
+        stored: Option<&str>,
+    ) -> Result<AttachmentPolicyOverrides, DatabaseError> {
+        stored
+            .map(|json| {
+                serde_json::from_str(json)
+                    .map_err(|e| DatabaseError::Query(format!("invalid attachment policy: {e}")))
+            })
+            .transpose()
+            .map(Option::unwrap_or_default)
+    }
+
+    pub fn attachment_policy_json(&self) -> Option<String> {
+        (self.attachment_policy != AttachmentPolicyOverrides::default())
+            .then(|| serde_json::to_string(&self.attachment_policy).ok())
+            .flatten()
+    }
+}
+
+/// A room's attachment policy per direction; `None` follows the config.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttachmentPolicyOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matrix_to_discord: Option<AttachmentPolicy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discord_to_matrix: Option<AttachmentPolicy>,
+}
+
+/// One Matrix user's options in one bridged room.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserRoomSettings {
+    pub matrix_room_id: String,
+    pub matrix_user_id: String,
+    /// Name their messages are sent under by the Discord webhook, instead of
+    /// their Matrix displayname.
+    pub webhook_nick: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A Discord thread and the Matrix thread (MSC3440) it is bridged to: the
+/// thread's messages go to the parent channel's room as replies in the
+/// thread rooted at `matrix_root_event_id`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThreadMapping {
+    pub discord_thread_id: String,
+    pub discord_parent_channel_id: String,
+    pub matrix_room_id: String,
+    pub matrix_root_event_id: String,
+    pub created_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserMapping {
     pub id: i64,
@@ -20,6 +209,9 @@ pub struct UserMapping {
     pub discord_username: String,
     pub discord_discriminator: String,
     pub discord_avatar: Option<String>,
+    /// Matrix presence (`online`, `unavailable`, `offline`) to show for this
+    /// user regardless of their Discord status.
+    pub presence_override: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -39,10 +231,23 @@ pub struct MessageMapping {
     pub discord_message_id: String,
     pub matrix_room_id: String,
     pub matrix_event_id: String,
+    /// The channel or thread the Discord message is in. `None` for mappings
+    /// recorded before it was, which are in the room's channel.
+    pub discord_channel_id: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+impl MessageMapping {
+    /// The channel or thread the Discord message is in, falling back to
+    /// `room_channel_id` for mappings that do not record it.
+    pub fn discord_channel<'a>(&'a self, room_channel_id: &'a str) -> &'a str {
+        self.discord_channel_id
+            .as_deref()
+            .unwrap_or(room_channel_id)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmojiMapping {
     pub id: i64,
@@ -73,6 +278,12 @@ impl EmojiMapping {
         }
     }
 
+    /// The `<:name:id>` token Discord renders as this emoji.
+    pub fn discord_token(&self) -> String {
+        let prefix = if self.animated { "a" } else { "" };
+        format!("<{}:{}:{}>", prefix, self.emoji_name, self.discord_emoji_id)
+    }
+
     pub fn discord_url(&self) -> String {
         let ext = if self.animated { "gif" } else { "png" };
         format!(
@@ -82,6 +293,206 @@ impl EmojiMapping {
     }
 }
 
+/// What an [`EmojiUsage`] counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmojiUsageKind {
+    Emoji,
+    Sticker,
+}
+
+impl EmojiUsageKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Emoji => "emoji",
+            Self::Sticker => "sticker",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "emoji" => Some(Self::Emoji),
+            "sticker" => Some(Self::Sticker),
+            _ => None,
+        }
+    }
+}
+
+/// How often a Discord custom emoji or sticker was bridged on one UTC day.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmojiUsage {
+    pub day: NaiveDate,
+    pub kind: EmojiUsageKind,
+    pub discord_id: String,
+    /// The name it was last used under.
+    pub name: String,
+    pub uses: i64,
+}
+
+impl EmojiUsage {
+    /// Days are stored as `YYYY-MM-DD` text on every backend.
+    pub const DAY_FORMAT: &'static str = "%Y-%m-%d";
+
+    pub(crate) fn from_stored(
+        day: &str,
+        kind: &str,
+        discord_id: String,
+        name: String,
+        uses: i64,
+    ) -> Result<Self, DatabaseError> {
+        Ok(Self {
+            day: NaiveDate::parse_from_str(day, Self::DAY_FORMAT)
+                .map_err(|e| DatabaseError::Query(format!("invalid usage day {day}: {e}")))?,
+            kind: EmojiUsageKind::parse(kind)
+                .ok_or_else(|| DatabaseError::Query(format!("unknown emoji usage kind: {kind}")))?,
+            discord_id,
+            name,
+            uses,
+        })
+    }
+
+    pub fn stored_day(&self) -> String {
+        self.day.format(Self::DAY_FORMAT).to_string()
+    }
+}
+
+/// Which way a bridged message travels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryDirection {
+    MatrixToDiscord,
+    DiscordToMatrix,
+}
+
+impl DeliveryDirection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::MatrixToDiscord => "matrix_to_discord",
+            Self::DiscordToMatrix => "discord_to_matrix",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "matrix_to_discord" => Some(Self::MatrixToDiscord),
+            "discord_to_matrix" => Some(Self::DiscordToMatrix),
+            _ => None,
+        }
+    }
+}
+
+/// A message whose delivery failed in at-least-once mode, kept until a retry
+/// succeeds or the attempts run out.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingDelivery {
+    pub id: String,
+    pub direction: DeliveryDirection,
+    /// The inbound event, replayed as if it had just arrived.
+    pub payload: serde_json::Value,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// One provisioning or moderation action, kept for `GET /admin/audit`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditLogEntry {
+    pub id: i64,
+    /// Matrix or Discord id of whoever triggered the action.
+    pub actor: String,
+    pub source: AuditSource,
+    pub action: String,
+    /// Room, channel or user the action applied to.
+    pub target: Option<String>,
+    pub parameters: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditSource {
+    MatrixCommand,
+    DiscordCommand,
+    Api,
+    AdminRoom,
+    Cli,
+    /// The bridge itself, e.g. a background policy.
+    Scheduled,
+}
+
+impl AuditSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::MatrixCommand => "matrix_command",
+            Self::DiscordCommand => "discord_command",
+            Self::Api => "api",
+            Self::AdminRoom => "admin_room",
+            Self::Cli => "cli",
+            Self::Scheduled => "scheduled",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "matrix_command" => Some(Self::MatrixCommand),
+            "discord_command" => Some(Self::DiscordCommand),
+            "api" => Some(Self::Api),
+            "admin_room" => Some(Self::AdminRoom),
+            "cli" => Some(Self::Cli),
+            "scheduled" => Some(Self::Scheduled),
+            _ => None,
+        }
+    }
+}
+
+/// Filters for listing message mappings; `None` fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct MessageMappingFilter {
+    pub matrix_room_id: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// Filters for listing audit entries; `None` fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct AuditLogFilter {
+    pub actor: Option<String>,
+    pub source: Option<AuditSource>,
+    pub action: Option<String>,
+    pub target: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// Admin API token managed at runtime. Only the SHA-256 of the token is
+/// stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub id: i64,
+    pub name: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub scopes: Vec<ApiScope>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ApiToken {
+    /// Scopes as stored in the `scopes` column.
+    pub fn scopes_to_column(scopes: &[ApiScope]) -> String {
+        scopes
+            .iter()
+            .map(ApiScope::as_str)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// Unknown scope names are dropped rather than failing the lookup.
+    pub fn scopes_from_column(value: &str) -> Vec<ApiScope> {
+        value.split(',').filter_map(ApiScope::parse).collect()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RemoteRoomInfo {
     pub discord_guild_id: String,
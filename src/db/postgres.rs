@@ -1,25 +1,35 @@
 use async_trait::async_trait;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
 
-use super::DatabaseError;
 use super::models::{
-    EmojiMapping, MessageMapping, RemoteRoomInfo, RemoteUserInfo, RoomMapping, UserMapping,
+    ApiToken, AuditLogEntry, AuditLogFilter, AuditSource, DeliveryDirection, EmojiMapping,
+    EmojiUsage, MessageMapping, MessageMappingFilter, PendingDelivery, RemoteRoomInfo,
+    RemoteUserInfo, RoomMapping, RoomOrigin, RoomSettings, ThreadMapping, UserMapping,
+    UserRoomSettings,
 };
+use super::stores::contains_pattern;
+use super::{DatabaseError, blocking};
 use crate::db::manager::Pool;
-use crate::db::schema::{message_mappings, room_mappings, user_mappings};
+use crate::db::schema::{
+    api_tokens, audit_log, emoji_usage, message_mappings, pending_deliveries, room_mappings,
+    room_settings, thread_mappings, user_mappings, user_room_settings,
+};
 
 #[derive(Debug, Clone, Queryable, Selectable)]
 #[diesel(table_name = room_mappings)]
-struct DbRoomMapping {
-    id: i64,
-    matrix_room_id: String,
-    discord_channel_id: String,
-    discord_channel_name: String,
-    discord_guild_id: String,
-    created_at: DateTime<Utc>,
-    updated_at: DateTime<Utc>,
+pub(super) struct DbRoomMapping {
+    pub(super) id: i64,
+    pub(super) matrix_room_id: String,
+    pub(super) discord_channel_id: String,
+    pub(super) discord_channel_name: String,
+    pub(super) discord_guild_id: String,
+    pub(super) created_by: Option<String>,
+    pub(super) origin: Option<String>,
+    pub(super) last_active_at: Option<DateTime<Utc>>,
+    pub(super) created_at: DateTime<Utc>,
+    pub(super) updated_at: DateTime<Utc>,
 }
 
 impl From<DbRoomMapping> for RoomMapping {
@@ -30,6 +40,9 @@ impl From<DbRoomMapping> for RoomMapping {
             discord_channel_id: value.discord_channel_id,
             discord_channel_name: value.discord_channel_name,
             discord_guild_id: value.discord_guild_id,
+            created_by: value.created_by,
+            origin: value.origin.as_deref().and_then(RoomOrigin::parse),
+            last_active_at: value.last_active_at,
             created_at: value.created_at,
             updated_at: value.updated_at,
         }
@@ -38,36 +51,39 @@ impl From<DbRoomMapping> for RoomMapping {
 
 #[derive(Insertable)]
 #[diesel(table_name = room_mappings)]
-struct NewRoomMapping<'a> {
-    matrix_room_id: &'a str,
-    discord_channel_id: &'a str,
-    discord_channel_name: &'a str,
-    discord_guild_id: &'a str,
-    created_at: &'a DateTime<Utc>,
-    updated_at: &'a DateTime<Utc>,
+pub(super) struct NewRoomMapping<'a> {
+    pub(super) matrix_room_id: &'a str,
+    pub(super) discord_channel_id: &'a str,
+    pub(super) discord_channel_name: &'a str,
+    pub(super) discord_guild_id: &'a str,
+    pub(super) created_by: Option<&'a str>,
+    pub(super) origin: Option<&'a str>,
+    pub(super) created_at: &'a DateTime<Utc>,
+    pub(super) updated_at: &'a DateTime<Utc>,
 }
 
 #[derive(AsChangeset)]
 #[diesel(table_name = room_mappings)]
-struct UpdateRoomMapping<'a> {
-    matrix_room_id: &'a str,
-    discord_channel_id: &'a str,
-    discord_channel_name: &'a str,
-    discord_guild_id: &'a str,
-    updated_at: &'a DateTime<Utc>,
+pub(super) struct UpdateRoomMapping<'a> {
+    pub(super) matrix_room_id: &'a str,
+    pub(super) discord_channel_id: &'a str,
+    pub(super) discord_channel_name: &'a str,
+    pub(super) discord_guild_id: &'a str,
+    pub(super) updated_at: &'a DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, Queryable, Selectable)]
 #[diesel(table_name = user_mappings)]
-struct DbUserMapping {
-    id: i64,
-    matrix_user_id: String,
-    discord_user_id: String,
-    discord_username: String,
-    discord_discriminator: String,
-    discord_avatar: Option<String>,
-    created_at: DateTime<Utc>,
-    updated_at: DateTime<Utc>,
+pub(super) struct DbUserMapping {
+    pub(super) id: i64,
+    pub(super) matrix_user_id: String,
+    pub(super) discord_user_id: String,
+    pub(super) discord_username: String,
+    pub(super) discord_discriminator: String,
+    pub(super) discord_avatar: Option<String>,
+    pub(super) presence_override: Option<String>,
+    pub(super) created_at: DateTime<Utc>,
+    pub(super) updated_at: DateTime<Utc>,
 }
 
 impl From<DbUserMapping> for UserMapping {
@@ -79,6 +95,7 @@ impl From<DbUserMapping> for UserMapping {
             discord_username: value.discord_username,
             discord_discriminator: value.discord_discriminator,
             discord_avatar: value.discord_avatar,
+            presence_override: value.presence_override,
             created_at: value.created_at,
             updated_at: value.updated_at,
         }
@@ -87,34 +104,36 @@ impl From<DbUserMapping> for UserMapping {
 
 #[derive(Insertable)]
 #[diesel(table_name = user_mappings)]
-struct NewUserMapping<'a> {
-    matrix_user_id: &'a str,
-    discord_user_id: &'a str,
-    discord_username: &'a str,
-    discord_discriminator: &'a str,
-    discord_avatar: Option<&'a str>,
-    created_at: &'a DateTime<Utc>,
-    updated_at: &'a DateTime<Utc>,
+pub(super) struct NewUserMapping<'a> {
+    pub(super) matrix_user_id: &'a str,
+    pub(super) discord_user_id: &'a str,
+    pub(super) discord_username: &'a str,
+    pub(super) discord_discriminator: &'a str,
+    pub(super) discord_avatar: Option<&'a str>,
+    pub(super) presence_override: Option<&'a str>,
+    pub(super) created_at: &'a DateTime<Utc>,
+    pub(super) updated_at: &'a DateTime<Utc>,
 }
 
 #[derive(AsChangeset)]
 #[diesel(table_name = user_mappings)]
-struct UpdateUserMapping<'a> {
-    discord_username: &'a str,
-    discord_discriminator: &'a str,
-    discord_avatar: Option<&'a str>,
-    updated_at: &'a DateTime<Utc>,
+pub(super) struct UpdateUserMapping<'a> {
+    pub(super) discord_username: &'a str,
+    pub(super) discord_discriminator: &'a str,
+    pub(super) discord_avatar: Option<&'a str>,
+    pub(super) updated_at: &'a DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, Queryable, Selectable)]
 #[diesel(table_name = message_mappings)]
-struct DbMessageMapping {
-    id: i64,
-    discord_message_id: String,
-    matrix_room_id: String,
-    matrix_event_id: String,
-    created_at: DateTime<Utc>,
-    updated_at: DateTime<Utc>,
+pub(super) struct DbMessageMapping {
+    pub(super) id: i64,
+    pub(super) discord_message_id: String,
+    pub(super) matrix_room_id: String,
+    pub(super) matrix_event_id: String,
+    pub(super) discord_channel_id: Option<String>,
+    pub(super) created_at: DateTime<Utc>,
+    pub(super) updated_at: DateTime<Utc>,
 }
 
 impl From<DbMessageMapping> for MessageMapping {
@@ -124,6 +143,7 @@ impl From<DbMessageMapping> for MessageMapping {
             discord_message_id: value.discord_message_id,
             matrix_room_id: value.matrix_room_id,
             matrix_event_id: value.matrix_event_id,
+            discord_channel_id: value.discord_channel_id,
             created_at: value.created_at,
             updated_at: value.updated_at,
         }
@@ -132,20 +152,22 @@ impl From<DbMessageMapping> for MessageMapping {
 
 #[derive(Insertable)]
 #[diesel(table_name = message_mappings)]
-struct NewMessageMapping<'a> {
-    discord_message_id: &'a str,
-    matrix_room_id: &'a str,
-    matrix_event_id: &'a str,
-    created_at: &'a DateTime<Utc>,
-    updated_at: &'a DateTime<Utc>,
+pub(super) struct NewMessageMapping<'a> {
+    pub(super) discord_message_id: &'a str,
+    pub(super) matrix_room_id: &'a str,
+    pub(super) matrix_event_id: &'a str,
+    pub(super) discord_channel_id: Option<&'a str>,
+    pub(super) created_at: &'a DateTime<Utc>,
+    pub(super) updated_at: &'a DateTime<Utc>,
 }
 
 #[derive(AsChangeset)]
 #[diesel(table_name = message_mappings)]
-struct UpdateMessageMapping<'a> {
-    matrix_room_id: &'a str,
-    matrix_event_id: &'a str,
-    updated_at: &'a DateTime<Utc>,
+pub(super) struct UpdateMessageMapping<'a> {
+    pub(super) matrix_room_id: &'a str,
+    pub(super) matrix_event_id: &'a str,
+    pub(super) discord_channel_id: Option<&'a str>,
+    pub(super) updated_at: &'a DateTime<Utc>,
 }
 
 async fn with_connection<T, F>(pool: Pool, operation: F) -> Result<T, DatabaseError>
@@ -153,7 +175,7 @@ where
     T: Send + 'static,
     F: FnOnce(&mut PgConnection) -> Result<T, DatabaseError> + Send + 'static,
 {
-    tokio::task::spawn_blocking(move || {
+    blocking::spawn(move || {
         let mut conn = pool
             .get()
             .map_err(|e| DatabaseError::Connection(e.to_string()))?;
@@ -163,6 +185,40 @@ where
     .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
 }
 
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = room_settings)]
+pub(super) struct DbRoomSettings {
+    pub(super) matrix_room_id: String,
+    pub(super) auto_invite_members: bool,
+    pub(super) role_keywords: Option<String>,
+    pub(super) inactivity_exempt: bool,
+    pub(super) member_sync: Option<String>,
+    pub(super) attribution_badge: bool,
+    pub(super) delivery_confirmations: bool,
+    pub(super) attachment_policy: Option<String>,
+    pub(super) updated_at: DateTime<Utc>,
+}
+
+impl TryFrom<DbRoomSettings> for RoomSettings {
+    type Error = DatabaseError;
+
+    fn try_from(value: DbRoomSettings) -> Result<Self, Self::Error> {
+        Ok(Self {
+            role_keywords: RoomSettings::parse_role_keywords(value.role_keywords.as_deref())?,
+            matrix_room_id: value.matrix_room_id,
+            auto_invite_members: value.auto_invite_members,
+            inactivity_exempt: value.inactivity_exempt,
+            member_sync: RoomSettings::parse_member_sync(value.member_sync.as_deref())?,
+            attribution_badge: value.attribution_badge,
+            delivery_confirmations: value.delivery_confirmations,
+            attachment_policy: RoomSettings::parse_attachment_policy(
+                value.attachment_policy.as_deref(),
+            )?,
+            updated_at: value.updated_at,
+        })
+    }
+}
+
 pub struct PostgresRoomStore {
     pool: Pool,
 }
@@ -269,6 +325,8 @@ impl super::RoomStore for PostgresRoomStore {
                 discord_channel_id: &mapping.discord_channel_id,
                 discord_channel_name: &mapping.discord_channel_name,
                 discord_guild_id: &mapping.discord_guild_id,
+                created_by: mapping.created_by.as_deref(),
+                origin: mapping.origin.as_ref().map(RoomOrigin::as_str),
                 created_at: &mapping.created_at,
                 updated_at: &mapping.updated_at,
             };
@@ -314,6 +372,18 @@ impl super::RoomStore for PostgresRoomStore {
         .await
     }
 
+    async fn touch_room_mapping(&self, id: i64, at: DateTime<Utc>) -> Result<(), DatabaseError> {
+        let pool = self.pool.clone();
+        with_connection(pool, move |conn| {
+            diesel::update(room_mappings::table.filter(room_mappings::id.eq(id)))
+                .set(room_mappings::last_active_at.eq(Some(at)))
+                .execute(conn)
+                .map(|_| ())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
     async fn get_rooms_by_guild(&self, guild_id: &str) -> Result<Vec<RoomMapping>, DatabaseError> {
         let pool = self.pool.clone();
         let guild_id = guild_id.to_string();
@@ -343,6 +413,199 @@ impl super::RoomStore for PostgresRoomStore {
     ) -> Result<(), DatabaseError> {
         Ok(())
     }
+
+    async fn get_room_settings(
+        &self,
+        room_id: &str,
+    ) -> Result<Option<RoomSettings>, DatabaseError> {
+        let pool = self.pool.clone();
+        let room_id = room_id.to_string();
+        with_connection(pool, move |conn| {
+            room_settings::table
+                .filter(room_settings::matrix_room_id.eq(room_id))
+                .select(DbRoomSettings::as_select())
+                .first::<DbRoomSettings>(conn)
+                .optional()
+                .map_err(|e| DatabaseError::Query(e.to_string()))?
+                .map(TryInto::try_into)
+                .transpose()
+        })
+        .await
+    }
+
+    async fn set_room_settings(&self, settings: &RoomSettings) -> Result<(), DatabaseError> {
+        let pool = self.pool.clone();
+        let settings = settings.clone();
+        with_connection(pool, move |conn| {
+            let role_keywords = settings.role_keywords_json();
+            let member_sync = settings.member_sync_json();
+            let attachment_policy = settings.attachment_policy_json();
+            conn.transaction(|conn| {
+                let updated = diesel::update(
+                    room_settings::table
+                        .filter(room_settings::matrix_room_id.eq(&settings.matrix_room_id)),
+                )
+                .set((
+                    room_settings::auto_invite_members.eq(settings.auto_invite_members),
+                    room_settings::role_keywords.eq(&role_keywords),
+                    room_settings::inactivity_exempt.eq(settings.inactivity_exempt),
+                    room_settings::member_sync.eq(&member_sync),
+                    room_settings::attachment_policy.eq(&attachment_policy),
+                    room_settings::attribution_badge.eq(settings.attribution_badge),
+                    room_settings::delivery_confirmations.eq(settings.delivery_confirmations),
+                    room_settings::updated_at.eq(settings.updated_at),
+                ))
+                .execute(conn)?;
+                if updated == 0 {
+                    diesel::insert_into(room_settings::table)
+                        .values((
+                            room_settings::matrix_room_id.eq(&settings.matrix_room_id),
+                            room_settings::auto_invite_members.eq(settings.auto_invite_members),
+                            room_settings::role_keywords.eq(&role_keywords),
+                            room_settings::inactivity_exempt.eq(settings.inactivity_exempt),
+                            room_settings::member_sync.eq(&member_sync),
+                            room_settings::attachment_policy.eq(&attachment_policy),
+                            room_settings::attribution_badge.eq(settings.attribution_badge),
+                            room_settings::delivery_confirmations
+                                .eq(settings.delivery_confirmations),
+                            room_settings::updated_at.eq(settings.updated_at),
+                        ))
+                        .execute(conn)?;
+                }
+                Ok(())
+            })
+            .map_err(|e: diesel::result::Error| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn get_thread_by_discord_thread(
+        &self,
+        discord_thread_id: &str,
+    ) -> Result<Option<ThreadMapping>, DatabaseError> {
+        let thread_id = discord_thread_id.to_string();
+        let pool = self.pool.clone();
+        with_connection(pool, move |conn| {
+            thread_mappings::table
+                .filter(thread_mappings::discord_thread_id.eq(thread_id))
+                .select(DbThreadMapping::as_select())
+                .first::<DbThreadMapping>(conn)
+                .optional()
+                .map(|value| value.map(Into::into))
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn get_thread_by_matrix_root(
+        &self,
+        matrix_root_event_id: &str,
+    ) -> Result<Option<ThreadMapping>, DatabaseError> {
+        let event_id = matrix_root_event_id.to_string();
+        let pool = self.pool.clone();
+        with_connection(pool, move |conn| {
+            thread_mappings::table
+                .filter(thread_mappings::matrix_root_event_id.eq(event_id))
+                .select(DbThreadMapping::as_select())
+                .first::<DbThreadMapping>(conn)
+                .optional()
+                .map(|value| value.map(Into::into))
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn upsert_thread_mapping(&self, mapping: &ThreadMapping) -> Result<(), DatabaseError> {
+        let mapping = mapping.clone();
+        let pool = self.pool.clone();
+        with_connection(pool, move |conn| {
+            let created_at = mapping.created_at;
+            conn.transaction(|conn| {
+                let updated = diesel::update(
+                    thread_mappings::table
+                        .filter(thread_mappings::discord_thread_id.eq(&mapping.discord_thread_id)),
+                )
+                .set((
+                    thread_mappings::discord_parent_channel_id
+                        .eq(&mapping.discord_parent_channel_id),
+                    thread_mappings::matrix_room_id.eq(&mapping.matrix_room_id),
+                    thread_mappings::matrix_root_event_id.eq(&mapping.matrix_root_event_id),
+                ))
+                .execute(conn)?;
+                if updated == 0 {
+                    diesel::insert_into(thread_mappings::table)
+                        .values((
+                            thread_mappings::discord_thread_id.eq(&mapping.discord_thread_id),
+                            thread_mappings::discord_parent_channel_id
+                                .eq(&mapping.discord_parent_channel_id),
+                            thread_mappings::matrix_room_id.eq(&mapping.matrix_room_id),
+                            thread_mappings::matrix_root_event_id.eq(&mapping.matrix_root_event_id),
+                            thread_mappings::created_at.eq(created_at),
+                        ))
+                        .execute(conn)?;
+                }
+                Ok(())
+            })
+            .map_err(|e: diesel::result::Error| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn delete_thread_mapping(&self, discord_thread_id: &str) -> Result<(), DatabaseError> {
+        let thread_id = discord_thread_id.to_string();
+        let pool = self.pool.clone();
+        with_connection(pool, move |conn| {
+            diesel::delete(
+                thread_mappings::table.filter(thread_mappings::discord_thread_id.eq(thread_id)),
+            )
+            .execute(conn)
+            .map(|_| ())
+            .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = thread_mappings)]
+pub(super) struct DbThreadMapping {
+    pub(super) discord_thread_id: String,
+    pub(super) discord_parent_channel_id: String,
+    pub(super) matrix_room_id: String,
+    pub(super) matrix_root_event_id: String,
+    pub(super) created_at: DateTime<Utc>,
+}
+
+impl From<DbThreadMapping> for ThreadMapping {
+    fn from(value: DbThreadMapping) -> Self {
+        Self {
+            discord_thread_id: value.discord_thread_id,
+            discord_parent_channel_id: value.discord_parent_channel_id,
+            matrix_room_id: value.matrix_room_id,
+            matrix_root_event_id: value.matrix_root_event_id,
+            created_at: value.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = user_room_settings)]
+pub(super) struct DbUserRoomSettings {
+    pub(super) matrix_room_id: String,
+    pub(super) matrix_user_id: String,
+    pub(super) webhook_nick: Option<String>,
+    pub(super) updated_at: DateTime<Utc>,
+}
+
+impl From<DbUserRoomSettings> for UserRoomSettings {
+    fn from(value: DbUserRoomSettings) -> Self {
+        Self {
+            matrix_room_id: value.matrix_room_id,
+            matrix_user_id: value.matrix_user_id,
+            webhook_nick: value.webhook_nick,
+            updated_at: value.updated_at,
+        }
+    }
 }
 
 pub struct PostgresUserStore {
@@ -386,6 +649,7 @@ impl super::UserStore for PostgresUserStore {
                 discord_username: &mapping.discord_username,
                 discord_discriminator: &mapping.discord_discriminator,
                 discord_avatar: mapping.discord_avatar.as_deref(),
+                presence_override: mapping.presence_override.as_deref(),
                 created_at: &mapping.created_at,
                 updated_at: &mapping.updated_at,
             };
@@ -475,6 +739,130 @@ impl super::UserStore for PostgresUserStore {
         })
         .await
     }
+
+    async fn search_users(
+        &self,
+        query: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<UserMapping>, DatabaseError> {
+        let pool = self.pool.clone();
+        let pattern = query.map(contains_pattern);
+        with_connection(pool, move |conn| {
+            use crate::db::schema::user_mappings::dsl::*;
+            let mut statement = user_mappings.into_boxed();
+            if let Some(pattern) = pattern {
+                statement = statement.filter(
+                    matrix_user_id
+                        .ilike(pattern.clone())
+                        .escape('\\')
+                        .or(discord_user_id.ilike(pattern.clone()).escape('\\'))
+                        .or(discord_username.ilike(pattern).escape('\\')),
+                );
+            }
+            statement
+                .order(id.asc())
+                .limit(limit)
+                .offset(offset)
+                .select(DbUserMapping::as_select())
+                .load::<DbUserMapping>(conn)
+                .map(|rows| rows.into_iter().map(Into::into).collect())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn set_presence_override(
+        &self,
+        discord_user_id_param: &str,
+        presence: Option<&str>,
+    ) -> Result<(), DatabaseError> {
+        let pool = self.pool.clone();
+        let discord_user_id_param = discord_user_id_param.to_string();
+        let presence = presence.map(ToString::to_string);
+        with_connection(pool, move |conn| {
+            use crate::db::schema::user_mappings::dsl::*;
+            diesel::update(user_mappings.filter(discord_user_id.eq(discord_user_id_param)))
+                .set((presence_override.eq(presence), updated_at.eq(Utc::now())))
+                .execute(conn)
+                .map(|_| ())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn get_user_room_settings(
+        &self,
+        matrix_room_id: &str,
+        matrix_user_id: &str,
+    ) -> Result<Option<UserRoomSettings>, DatabaseError> {
+        let pool = self.pool.clone();
+        let room_id = matrix_room_id.to_string();
+        let user_id = matrix_user_id.to_string();
+        with_connection(pool, move |conn| {
+            user_room_settings::table
+                .filter(user_room_settings::matrix_room_id.eq(room_id))
+                .filter(user_room_settings::matrix_user_id.eq(user_id))
+                .select(DbUserRoomSettings::as_select())
+                .first::<DbUserRoomSettings>(conn)
+                .optional()
+                .map(|value| value.map(Into::into))
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn set_user_room_settings(
+        &self,
+        settings: &UserRoomSettings,
+    ) -> Result<(), DatabaseError> {
+        let pool = self.pool.clone();
+        let settings = settings.clone();
+        with_connection(pool, move |conn| {
+            let updated_at = settings.updated_at;
+            conn.transaction(|conn| {
+                let updated = diesel::update(
+                    user_room_settings::table
+                        .filter(user_room_settings::matrix_room_id.eq(&settings.matrix_room_id))
+                        .filter(user_room_settings::matrix_user_id.eq(&settings.matrix_user_id)),
+                )
+                .set((
+                    user_room_settings::webhook_nick.eq(&settings.webhook_nick),
+                    user_room_settings::updated_at.eq(updated_at),
+                ))
+                .execute(conn)?;
+                if updated == 0 {
+                    diesel::insert_into(user_room_settings::table)
+                        .values((
+                            user_room_settings::matrix_room_id.eq(&settings.matrix_room_id),
+                            user_room_settings::matrix_user_id.eq(&settings.matrix_user_id),
+                            user_room_settings::webhook_nick.eq(&settings.webhook_nick),
+                            user_room_settings::updated_at.eq(updated_at),
+                        ))
+                        .execute(conn)?;
+                }
+                Ok(())
+            })
+            .map_err(|e: diesel::result::Error| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+}
+
+pub(super) fn filtered_message_mappings(
+    filter: &MessageMappingFilter,
+) -> message_mappings::BoxedQuery<'static, diesel::pg::Pg> {
+    let mut query = message_mappings::table.into_boxed();
+    if let Some(room_id) = &filter.matrix_room_id {
+        query = query.filter(message_mappings::matrix_room_id.eq(room_id.clone()));
+    }
+    if let Some(since) = &filter.since {
+        query = query.filter(message_mappings::created_at.ge(*since));
+    }
+    if let Some(until) = &filter.until {
+        query = query.filter(message_mappings::created_at.le(*until));
+    }
+    query
 }
 
 pub struct PostgresMessageStore {
@@ -544,6 +932,7 @@ impl super::MessageStore for PostgresMessageStore {
                 let changes = UpdateMessageMapping {
                     matrix_room_id: &mapping.matrix_room_id,
                     matrix_event_id: &mapping.matrix_event_id,
+                    discord_channel_id: mapping.discord_channel_id.as_deref(),
                     updated_at: &mapping.updated_at,
                 };
                 diesel::update(message_mappings.filter(id.eq(existing.id)))
@@ -556,6 +945,7 @@ impl super::MessageStore for PostgresMessageStore {
                     discord_message_id: &mapping.discord_message_id,
                     matrix_room_id: &mapping.matrix_room_id,
                     matrix_event_id: &mapping.matrix_event_id,
+                    discord_channel_id: mapping.discord_channel_id.as_deref(),
                     created_at: &mapping.created_at,
                     updated_at: &mapping.updated_at,
                 };
@@ -600,6 +990,42 @@ impl super::MessageStore for PostgresMessageStore {
         })
         .await
     }
+
+    async fn list_message_mappings(
+        &self,
+        filter: &MessageMappingFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<MessageMapping>, DatabaseError> {
+        let pool = self.pool.clone();
+        let filter = filter.clone();
+        with_connection(pool, move |conn| {
+            filtered_message_mappings(&filter)
+                .order(message_mappings::id.desc())
+                .limit(limit)
+                .offset(offset)
+                .select(DbMessageMapping::as_select())
+                .load::<DbMessageMapping>(conn)
+                .map(|rows| rows.into_iter().map(Into::into).collect())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn count_message_mappings(
+        &self,
+        filter: &MessageMappingFilter,
+    ) -> Result<i64, DatabaseError> {
+        let pool = self.pool.clone();
+        let filter = filter.clone();
+        with_connection(pool, move |conn| {
+            filtered_message_mappings(&filter)
+                .count()
+                .get_result::<i64>(conn)
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
 }
 
 pub struct PostgresEmojiStore {
@@ -658,6 +1084,22 @@ struct UpdateEmojiMapping<'a> {
     updated_at: &'a DateTime<Utc>,
 }
 
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = emoji_usage)]
+struct DbEmojiUsage {
+    day: String,
+    kind: String,
+    discord_id: String,
+    name: String,
+    uses: i64,
+}
+
+impl DbEmojiUsage {
+    fn into_emoji_usage(self) -> Result<EmojiUsage, DatabaseError> {
+        EmojiUsage::from_stored(&self.day, &self.kind, self.discord_id, self.name, self.uses)
+    }
+}
+
 #[async_trait]
 impl super::EmojiStore for PostgresEmojiStore {
     async fn get_emoji_by_discord_id(
@@ -752,4 +1194,445 @@ impl super::EmojiStore for PostgresEmojiStore {
         })
         .await
     }
+
+    async fn add_emoji_usage(&self, usage: &[EmojiUsage]) -> Result<(), DatabaseError> {
+        let pool = self.pool.clone();
+        let usage = usage.to_vec();
+        with_connection(pool, move |conn| {
+            conn.transaction(|conn| {
+                for entry in &usage {
+                    let day = entry.stored_day();
+                    let existing = emoji_usage::table
+                        .filter(emoji_usage::day.eq(&day))
+                        .filter(emoji_usage::kind.eq(entry.kind.as_str()))
+                        .filter(emoji_usage::discord_id.eq(&entry.discord_id));
+                    let updated = diesel::update(existing)
+                        .set((
+                            emoji_usage::name.eq(&entry.name),
+                            emoji_usage::uses.eq(emoji_usage::uses + entry.uses),
+                        ))
+                        .execute(conn)?;
+                    if updated == 0 {
+                        diesel::insert_into(emoji_usage::table)
+                            .values((
+                                emoji_usage::day.eq(&day),
+                                emoji_usage::kind.eq(entry.kind.as_str()),
+                                emoji_usage::discord_id.eq(&entry.discord_id),
+                                emoji_usage::name.eq(&entry.name),
+                                emoji_usage::uses.eq(entry.uses),
+                            ))
+                            .execute(conn)?;
+                    }
+                }
+                Ok(())
+            })
+            .map_err(|e: diesel::result::Error| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn list_emoji_usage(&self, since: NaiveDate) -> Result<Vec<EmojiUsage>, DatabaseError> {
+        let pool = self.pool.clone();
+        let since = since.format(EmojiUsage::DAY_FORMAT).to_string();
+        with_connection(pool, move |conn| {
+            emoji_usage::table
+                .filter(emoji_usage::day.ge(&since))
+                .order((emoji_usage::day.asc(), emoji_usage::discord_id.asc()))
+                .select(DbEmojiUsage::as_select())
+                .load::<DbEmojiUsage>(conn)
+                .map_err(|e| DatabaseError::Query(e.to_string()))?
+                .into_iter()
+                .map(DbEmojiUsage::into_emoji_usage)
+                .collect()
+        })
+        .await
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = audit_log)]
+struct DbAuditLogEntry {
+    id: i64,
+    actor: String,
+    source: String,
+    action: String,
+    target: Option<String>,
+    parameters: String,
+    created_at: DateTime<Utc>,
+}
+
+impl TryFrom<DbAuditLogEntry> for AuditLogEntry {
+    type Error = DatabaseError;
+
+    fn try_from(value: DbAuditLogEntry) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: value.id,
+            actor: value.actor,
+            source: AuditSource::parse(&value.source).ok_or_else(|| {
+                DatabaseError::Query(format!("unknown audit source: {}", value.source))
+            })?,
+            action: value.action,
+            target: value.target,
+            parameters: serde_json::from_str(&value.parameters)
+                .map_err(|e| DatabaseError::Query(format!("invalid audit parameters: {e}")))?,
+            created_at: value.created_at,
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = audit_log)]
+struct NewAuditLogEntry<'a> {
+    actor: &'a str,
+    source: &'a str,
+    action: &'a str,
+    target: Option<&'a str>,
+    parameters: String,
+    created_at: DateTime<Utc>,
+}
+
+fn filtered_audit_log(filter: &AuditLogFilter) -> audit_log::BoxedQuery<'static, diesel::pg::Pg> {
+    let mut query = audit_log::table.into_boxed();
+    if let Some(actor) = &filter.actor {
+        query = query.filter(audit_log::actor.eq(actor.clone()));
+    }
+    if let Some(source) = filter.source {
+        query = query.filter(audit_log::source.eq(source.as_str()));
+    }
+    if let Some(action) = &filter.action {
+        query = query.filter(audit_log::action.eq(action.clone()));
+    }
+    if let Some(target) = &filter.target {
+        query = query.filter(audit_log::target.eq(target.clone()));
+    }
+    if let Some(since) = &filter.since {
+        query = query.filter(audit_log::created_at.ge(*since));
+    }
+    if let Some(until) = &filter.until {
+        query = query.filter(audit_log::created_at.le(*until));
+    }
+    query
+}
+
+pub struct PostgresAuditStore {
+    pool: Pool,
+}
+
+impl PostgresAuditStore {
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl super::AuditStore for PostgresAuditStore {
+    async fn record_audit_entry(&self, entry: &AuditLogEntry) -> Result<(), DatabaseError> {
+        let pool = self.pool.clone();
+        let entry = entry.clone();
+        with_connection(pool, move |conn| {
+            let new_entry = NewAuditLogEntry {
+                actor: &entry.actor,
+                source: entry.source.as_str(),
+                action: &entry.action,
+                target: entry.target.as_deref(),
+                parameters: entry.parameters.to_string(),
+                created_at: entry.created_at,
+            };
+            diesel::insert_into(audit_log::table)
+                .values(&new_entry)
+                .execute(conn)
+                .map(|_| ())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn list_audit_entries(
+        &self,
+        filter: &AuditLogFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<AuditLogEntry>, DatabaseError> {
+        let pool = self.pool.clone();
+        let filter = filter.clone();
+        with_connection(pool, move |conn| {
+            filtered_audit_log(&filter)
+                .order(audit_log::id.desc())
+                .limit(limit)
+                .offset(offset)
+                .select(DbAuditLogEntry::as_select())
+                .load::<DbAuditLogEntry>(conn)
+                .map_err(|e| DatabaseError::Query(e.to_string()))?
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect()
+        })
+        .await
+    }
+
+    async fn count_audit_entries(&self, filter: &AuditLogFilter) -> Result<i64, DatabaseError> {
+        let pool = self.pool.clone();
+        let filter = filter.clone();
+        with_connection(pool, move |conn| {
+            filtered_audit_log(&filter)
+                .count()
+                .get_result::<i64>(conn)
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = api_tokens)]
+struct DbApiToken {
+    id: i64,
+    name: String,
+    token_hash: String,
+    scopes: String,
+    created_at: DateTime<Utc>,
+}
+
+impl From<DbApiToken> for ApiToken {
+    fn from(value: DbApiToken) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            token_hash: value.token_hash,
+            scopes: ApiToken::scopes_from_column(&value.scopes),
+            created_at: value.created_at,
+        }
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = api_tokens)]
+struct NewApiToken<'a> {
+    name: &'a str,
+    token_hash: &'a str,
+    scopes: String,
+    created_at: DateTime<Utc>,
+}
+
+pub struct PostgresApiTokenStore {
+    pool: Pool,
+}
+
+impl PostgresApiTokenStore {
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl super::ApiTokenStore for PostgresApiTokenStore {
+    async fn get_api_token_by_hash(&self, hash: &str) -> Result<Option<ApiToken>, DatabaseError> {
+        let pool = self.pool.clone();
+        let hash = hash.to_string();
+        with_connection(pool, move |conn| {
+            api_tokens::table
+                .filter(api_tokens::token_hash.eq(&hash))
+                .select(DbApiToken::as_select())
+                .first::<DbApiToken>(conn)
+                .optional()
+                .map(|token| token.map(Into::into))
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn list_api_tokens(&self) -> Result<Vec<ApiToken>, DatabaseError> {
+        let pool = self.pool.clone();
+        with_connection(pool, move |conn| {
+            api_tokens::table
+                .order(api_tokens::name.asc())
+                .select(DbApiToken::as_select())
+                .load::<DbApiToken>(conn)
+                .map(|rows| rows.into_iter().map(Into::into).collect())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn count_api_tokens(&self) -> Result<i64, DatabaseError> {
+        let pool = self.pool.clone();
+        with_connection(pool, move |conn| {
+            api_tokens::table
+                .count()
+                .get_result::<i64>(conn)
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn create_api_token(&self, token: &ApiToken) -> Result<(), DatabaseError> {
+        let pool = self.pool.clone();
+        let token = token.clone();
+        with_connection(pool, move |conn| {
+            let new_token = NewApiToken {
+                name: &token.name,
+                token_hash: &token.token_hash,
+                scopes: ApiToken::scopes_to_column(&token.scopes),
+                created_at: token.created_at,
+            };
+            diesel::insert_into(api_tokens::table)
+                .values(&new_token)
+                .execute(conn)
+                .map(|_| ())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn delete_api_token(&self, name: &str) -> Result<bool, DatabaseError> {
+        let pool = self.pool.clone();
+        let name = name.to_string();
+        with_connection(pool, move |conn| {
+            diesel::delete(api_tokens::table.filter(api_tokens::name.eq(&name)))
+                .execute(conn)
+                .map(|deleted| deleted > 0)
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Selectable, Insertable)]
+#[diesel(table_name = pending_deliveries)]
+struct DbPendingDelivery {
+    id: String,
+    direction: String,
+    payload: String,
+    attempts: i32,
+    next_attempt_at: DateTime<Utc>,
+    last_error: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+impl DbPendingDelivery {
+    fn from_delivery(delivery: &PendingDelivery) -> Result<Self, DatabaseError> {
+        Ok(Self {
+            id: delivery.id.clone(),
+            direction: delivery.direction.as_str().to_string(),
+            payload: serde_json::to_string(&delivery.payload)
+                .map_err(|e| DatabaseError::Query(format!("invalid delivery payload: {e}")))?,
+            attempts: delivery.attempts,
+            next_attempt_at: delivery.next_attempt_at,
+            last_error: delivery.last_error.clone(),
+            created_at: delivery.created_at,
+        })
+    }
+}
+
+impl TryFrom<DbPendingDelivery> for PendingDelivery {
+    type Error = DatabaseError;
+
+    fn try_from(value: DbPendingDelivery) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: value.id,
+            direction: DeliveryDirection::parse(&value.direction).ok_or_else(|| {
+                DatabaseError::Query(format!("unknown delivery direction: {}", value.direction))
+            })?,
+            payload: serde_json::from_str(&value.payload)
+                .map_err(|e| DatabaseError::Query(format!("invalid delivery payload: {e}")))?,
+            attempts: value.attempts,
+            next_attempt_at: value.next_attempt_at,
+            last_error: value.last_error,
+            created_at: value.created_at,
+        })
+    }
+}
+
+pub struct PostgresDeliveryStore {
+    pool: Pool,
+}
+
+impl PostgresDeliveryStore {
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl super::DeliveryStore for PostgresDeliveryStore {
+    async fn enqueue_delivery(&self, delivery: &PendingDelivery) -> Result<(), DatabaseError> {
+        let pool = self.pool.clone();
+        let row = DbPendingDelivery::from_delivery(delivery)?;
+        with_connection(pool, move |conn| {
+            diesel::insert_into(pending_deliveries::table)
+                .values(&row)
+                .execute(conn)
+                .map(|_| ())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn list_due_deliveries(
+        &self,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<PendingDelivery>, DatabaseError> {
+        let pool = self.pool.clone();
+        with_connection(pool, move |conn| {
+            pending_deliveries::table
+                .filter(pending_deliveries::next_attempt_at.le(now))
+                .order(pending_deliveries::created_at.asc())
+                .limit(limit)
+                .select(DbPendingDelivery::as_select())
+                .load::<DbPendingDelivery>(conn)
+                .map_err(|e| DatabaseError::Query(e.to_string()))?
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect()
+        })
+        .await
+    }
+
+    async fn reschedule_delivery(
+        &self,
+        id: &str,
+        attempts: i32,
+        next_attempt_at: DateTime<Utc>,
+        last_error: Option<&str>,
+    ) -> Result<(), DatabaseError> {
+        let pool = self.pool.clone();
+        let id = id.to_string();
+        let last_error = last_error.map(ToOwned::to_owned);
+        with_connection(pool, move |conn| {
+            diesel::update(pending_deliveries::table.filter(pending_deliveries::id.eq(&id)))
+                .set((
+                    pending_deliveries::attempts.eq(attempts),
+                    pending_deliveries::next_attempt_at.eq(next_attempt_at),
+                    pending_deliveries::last_error.eq(last_error),
+                ))
+                .execute(conn)
+                .map(|_| ())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn delete_delivery(&self, id: &str) -> Result<(), DatabaseError> {
+        let pool = self.pool.clone();
+        let id = id.to_string();
+        with_connection(pool, move |conn| {
+            diesel::delete(pending_deliveries::table.filter(pending_deliveries::id.eq(&id)))
+                .execute(conn)
+                .map(|_| ())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
+
+    async fn count_deliveries(&self) -> Result<i64, DatabaseError> {
+        let pool = self.pool.clone();
+        with_connection(pool, move |conn| {
+            pending_deliveries::table
+                .count()
+                .get_result::<i64>(conn)
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+    }
 }
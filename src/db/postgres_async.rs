@@ -0,0 +1,716 @@
+//! Room, user and message stores on a `diesel-async` connection pool. Their
+//! lookups run once per bridged message, and here they await the database
+//! directly instead of hopping onto the blocking executor.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel_async::pooled_connection::AsyncDieselConnectionManager;
+use diesel_async::pooled_connection::bb8::{self, PooledConnection};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
+
+use super::DatabaseError;
+use super::models::{
+    MessageMapping, MessageMappingFilter, RemoteRoomInfo, RemoteUserInfo, RoomMapping, RoomOrigin,
+    RoomSettings, ThreadMapping, UserMapping, UserRoomSettings,
+};
+use super::postgres::{
+    DbMessageMapping, DbRoomMapping, DbRoomSettings, DbThreadMapping, DbUserMapping,
+    DbUserRoomSettings, NewMessageMapping, NewRoomMapping, NewUserMapping, UpdateMessageMapping,
+    UpdateRoomMapping, UpdateUserMapping, filtered_message_mappings,
+};
+use super::stores::contains_pattern;
+use crate::db::schema::{
+    message_mappings, room_mappings, room_settings, thread_mappings, user_mappings,
+    user_room_settings,
+};
+
+pub type AsyncPool = bb8::Pool<AsyncPgConnection>;
+
+/// Opens the pool the async stores share.
+pub async fn build_pool(
+    connection_string: &str,
+    max_connections: u32,
+    min_connections: u32,
+) -> Result<AsyncPool, DatabaseError> {
+    let manager = AsyncDieselConnectionManager::<AsyncPgConnection>::new(connection_string);
+    bb8::Pool::builder()
+        .max_size(max_connections)
+        .min_idle(Some(min_connections))
+        .build(manager)
+        .await
+        .map_err(|e| DatabaseError::Connection(e.to_string()))
+}
+
+async fn connection(
+    pool: &AsyncPool,
+) -> Result<PooledConnection<'_, AsyncPgConnection>, DatabaseError> {
+    pool.get()
+        .await
+        .map_err(|e| DatabaseError::Connection(e.to_string()))
+}
+
+fn query_error(err: diesel::result::Error) -> DatabaseError {
+    DatabaseError::Query(err.to_string())
+}
+
+pub struct AsyncPostgresRoomStore {
+    pool: AsyncPool,
+}
+
+impl AsyncPostgresRoomStore {
+    pub fn new(pool: AsyncPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl super::RoomStore for AsyncPostgresRoomStore {
+    async fn get_room_by_discord_channel(
+        &self,
+        channel_id: &str,
+    ) -> Result<Option<RoomMapping>, DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        room_mappings::table
+            .filter(room_mappings::discord_channel_id.eq(channel_id))
+            .select(DbRoomMapping::as_select())
+            .first::<DbRoomMapping>(&mut conn)
+            .await
+            .optional()
+            .map(|value| value.map(Into::into))
+            .map_err(query_error)
+    }
+
+    async fn get_room_by_matrix_room(
+        &self,
+        room_id: &str,
+    ) -> Result<Option<RoomMapping>, DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        room_mappings::table
+            .filter(room_mappings::matrix_room_id.eq(room_id))
+            .select(DbRoomMapping::as_select())
+            .first::<DbRoomMapping>(&mut conn)
+            .await
+            .optional()
+            .map(|value| value.map(Into::into))
+            .map_err(query_error)
+    }
+
+    async fn get_room_by_id(&self, mapping_id: i64) -> Result<Option<RoomMapping>, DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        room_mappings::table
+            .filter(room_mappings::id.eq(mapping_id))
+            .select(DbRoomMapping::as_select())
+            .first::<DbRoomMapping>(&mut conn)
+            .await
+            .optional()
+            .map(|value| value.map(Into::into))
+            .map_err(query_error)
+    }
+
+    async fn count_rooms(&self) -> Result<i64, DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        room_mappings::table
+            .count()
+            .get_result(&mut conn)
+            .await
+            .map_err(query_error)
+    }
+
+    async fn list_room_mappings(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<RoomMapping>, DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        room_mappings::table
+            .order(room_mappings::id.desc())
+            .limit(limit)
+            .offset(offset)
+            .select(DbRoomMapping::as_select())
+            .load::<DbRoomMapping>(&mut conn)
+            .await
+            .map(|rows| rows.into_iter().map(Into::into).collect())
+            .map_err(query_error)
+    }
+
+    async fn create_room_mapping(&self, mapping: &RoomMapping) -> Result<(), DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        let new_mapping = NewRoomMapping {
+            matrix_room_id: &mapping.matrix_room_id,
+            discord_channel_id: &mapping.discord_channel_id,
+            discord_channel_name: &mapping.discord_channel_name,
+            discord_guild_id: &mapping.discord_guild_id,
+            created_by: mapping.created_by.as_deref(),
+            origin: mapping.origin.as_ref().map(RoomOrigin::as_str),
+            created_at: &mapping.created_at,
+            updated_at: &mapping.updated_at,
+        };
+        diesel::insert_into(room_mappings::table)
+            .values(&new_mapping)
+            .execute(&mut conn)
+            .await
+            .map(|_| ())
+            .map_err(query_error)
+    }
+
+    async fn update_room_mapping(&self, mapping: &RoomMapping) -> Result<(), DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        let changes = UpdateRoomMapping {
+            matrix_room_id: &mapping.matrix_room_id,
+            discord_channel_id: &mapping.discord_channel_id,
+            discord_channel_name: &mapping.discord_channel_name,
+            discord_guild_id: &mapping.discord_guild_id,
+            updated_at: &mapping.updated_at,
+        };
+        diesel::update(room_mappings::table.filter(room_mappings::id.eq(mapping.id)))
+            .set(changes)
+            .execute(&mut conn)
+            .await
+            .map(|_| ())
+            .map_err(query_error)
+    }
+
+    async fn delete_room_mapping(&self, id: i64) -> Result<(), DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        diesel::delete(room_mappings::table.filter(room_mappings::id.eq(id)))
+            .execute(&mut conn)
+            .await
+            .map(|_| ())
+            .map_err(query_error)
+    }
+
+    async fn touch_room_mapping(&self, id: i64, at: DateTime<Utc>) -> Result<(), DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        diesel::update(room_mappings::table.filter(room_mappings::id.eq(id)))
+            .set(room_mappings::last_active_at.eq(Some(at)))
+            .execute(&mut conn)
+            .await
+            .map(|_| ())
+            .map_err(query_error)
+    }
+
+    async fn get_rooms_by_guild(&self, guild_id: &str) -> Result<Vec<RoomMapping>, DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        room_mappings::table
+            .filter(room_mappings::discord_guild_id.eq(guild_id))
+            .select(DbRoomMapping::as_select())
+            .load::<DbRoomMapping>(&mut conn)
+            .await
+            .map(|rows| rows.into_iter().map(Into::into).collect())
+            .map_err(query_error)
+    }
+
+    async fn get_remote_room_info(
+        &self,
+        _matrix_room_id: &str,
+    ) -> Result<Option<RemoteRoomInfo>, DatabaseError> {
+        Ok(None)
+    }
+
+    async fn update_remote_room_info(
+        &self,
+        _matrix_room_id: &str,
+        _info: &RemoteRoomInfo,
+    ) -> Result<(), DatabaseError> {
+        Ok(())
+    }
+
+    async fn get_room_settings(
+        &self,
+        room_id: &str,
+    ) -> Result<Option<RoomSettings>, DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        room_settings::table
+            .filter(room_settings::matrix_room_id.eq(room_id))
+            .select(DbRoomSettings::as_select())
+            .first::<DbRoomSettings>(&mut conn)
+            .await
+            .optional()
+            .map_err(query_error)?
+            .map(TryInto::try_into)
+            .transpose()
+    }
+
+    async fn set_room_settings(&self, settings: &RoomSettings) -> Result<(), DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        let role_keywords = settings.role_keywords_json();
+        let member_sync = settings.member_sync_json();
+        let attachment_policy = settings.attachment_policy_json();
+        conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            async move {
+                let updated = diesel::update(
+                    room_settings::table
+                        .filter(room_settings::matrix_room_id.eq(&settings.matrix_room_id)),
+                )
+                .set((
+                    room_settings::auto_invite_members.eq(settings.auto_invite_members),
+                    room_settings::role_keywords.eq(&role_keywords),
+                    room_settings::inactivity_exempt.eq(settings.inactivity_exempt),
+                    room_settings::member_sync.eq(&member_sync),
+                    room_settings::attachment_policy.eq(&attachment_policy),
+                    room_settings::attribution_badge.eq(settings.attribution_badge),
+                    room_settings::delivery_confirmations.eq(settings.delivery_confirmations),
+                    room_settings::updated_at.eq(settings.updated_at),
+                ))
+                .execute(conn)
+                .await?;
+                if updated == 0 {
+                    diesel::insert_into(room_settings::table)
+                        .values((
+                            room_settings::matrix_room_id.eq(&settings.matrix_room_id),
+                            room_settings::auto_invite_members.eq(settings.auto_invite_members),
+                            room_settings::role_keywords.eq(&role_keywords),
+                            room_settings::inactivity_exempt.eq(settings.inactivity_exempt),
+                            room_settings::member_sync.eq(&member_sync),
+                            room_settings::attachment_policy.eq(&attachment_policy),
+                            room_settings::attribution_badge.eq(settings.attribution_badge),
+                            room_settings::delivery_confirmations
+                                .eq(settings.delivery_confirmations),
+                            room_settings::updated_at.eq(settings.updated_at),
+                        ))
+                        .execute(conn)
+                        .await?;
+                }
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+        .map_err(query_error)
+    }
+
+    async fn get_thread_by_discord_thread(
+        &self,
+        discord_thread_id: &str,
+    ) -> Result<Option<ThreadMapping>, DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        thread_mappings::table
+            .filter(thread_mappings::discord_thread_id.eq(discord_thread_id))
+            .select(DbThreadMapping::as_select())
+            .first::<DbThreadMapping>(&mut conn)
+            .await
+            .optional()
+            .map(|value| value.map(Into::into))
+            .map_err(query_error)
+    }
+
+    async fn get_thread_by_matrix_root(
+        &self,
+        matrix_root_event_id: &str,
+    ) -> Result<Option<ThreadMapping>, DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        thread_mappings::table
+            .filter(thread_mappings::matrix_root_event_id.eq(matrix_root_event_id))
+            .select(DbThreadMapping::as_select())
+            .first::<DbThreadMapping>(&mut conn)
+            .await
+            .optional()
+            .map(|value| value.map(Into::into))
+            .map_err(query_error)
+    }
+
+    async fn upsert_thread_mapping(&self, mapping: &ThreadMapping) -> Result<(), DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            async move {
+                let updated = diesel::update(
+                    thread_mappings::table
+                        .filter(thread_mappings::discord_thread_id.eq(&mapping.discord_thread_id)),
+                )
+                .set((
+                    thread_mappings::discord_parent_channel_id
+                        .eq(&mapping.discord_parent_channel_id),
+                    thread_mappings::matrix_room_id.eq(&mapping.matrix_room_id),
+                    thread_mappings::matrix_root_event_id.eq(&mapping.matrix_root_event_id),
+                ))
+                .execute(conn)
+                .await?;
+                if updated == 0 {
+                    diesel::insert_into(thread_mappings::table)
+                        .values((
+                            thread_mappings::discord_thread_id.eq(&mapping.discord_thread_id),
+                            thread_mappings::discord_parent_channel_id
+                                .eq(&mapping.discord_parent_channel_id),
+                            thread_mappings::matrix_room_id.eq(&mapping.matrix_room_id),
+                            thread_mappings::matrix_root_event_id.eq(&mapping.matrix_root_event_id),
+                            thread_mappings::created_at.eq(mapping.created_at),
+                        ))
+                        .execute(conn)
+                        .await?;
+                }
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+        .map_err(query_error)
+    }
+
+    async fn delete_thread_mapping(&self, discord_thread_id: &str) -> Result<(), DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        diesel::delete(
+            thread_mappings::table.filter(thread_mappings::discord_thread_id.eq(discord_thread_id)),
+        )
+        .execute(&mut conn)
+        .await
+        .map(|_| ())
+        .map_err(query_error)
+    }
+}
+
+pub struct AsyncPostgresUserStore {
+    pool: AsyncPool,
+}
+
+impl AsyncPostgresUserStore {
+    pub fn new(pool: AsyncPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl super::UserStore for AsyncPostgresUserStore {
+    async fn get_user_by_discord_id(
+        &self,
+        discord_id: &str,
+    ) -> Result<Option<UserMapping>, DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        user_mappings::table
+            .filter(user_mappings::discord_user_id.eq(discord_id))
+            .select(DbUserMapping::as_select())
+            .first::<DbUserMapping>(&mut conn)
+            .await
+            .optional()
+            .map(|value| value.map(Into::into))
+            .map_err(query_error)
+    }
+
+    async fn get_user_by_matrix_id(
+        &self,
+        matrix_id: &str,
+    ) -> Result<Option<UserMapping>, DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        user_mappings::table
+            .filter(user_mappings::matrix_user_id.eq(matrix_id))
+            .select(DbUserMapping::as_select())
+            .first::<DbUserMapping>(&mut conn)
+            .await
+            .optional()
+            .map(|value| value.map(Into::into))
+            .map_err(query_error)
+    }
+
+    async fn create_user_mapping(&self, mapping: &UserMapping) -> Result<(), DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        let new_mapping = NewUserMapping {
+            matrix_user_id: &mapping.matrix_user_id,
+            discord_user_id: &mapping.discord_user_id,
+            discord_username: &mapping.discord_username,
+            discord_discriminator: &mapping.discord_discriminator,
+            discord_avatar: mapping.discord_avatar.as_deref(),
+            presence_override: mapping.presence_override.as_deref(),
+            created_at: &mapping.created_at,
+            updated_at: &mapping.updated_at,
+        };
+        diesel::insert_into(user_mappings::table)
+            .values(new_mapping)
+            .execute(&mut conn)
+            .await
+            .map(|_| ())
+            .map_err(query_error)
+    }
+
+    async fn update_user_mapping(&self, mapping: &UserMapping) -> Result<(), DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        let changes = UpdateUserMapping {
+            discord_username: &mapping.discord_username,
+            discord_discriminator: &mapping.discord_discriminator,
+            discord_avatar: mapping.discord_avatar.as_deref(),
+            updated_at: &mapping.updated_at,
+        };
+        diesel::update(user_mappings::table.filter(user_mappings::id.eq(mapping.id)))
+            .set(changes)
+            .execute(&mut conn)
+            .await
+            .map(|_| ())
+            .map_err(query_error)
+    }
+
+    async fn delete_user_mapping(&self, id: i64) -> Result<(), DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        diesel::delete(user_mappings::table.filter(user_mappings::id.eq(id)))
+            .execute(&mut conn)
+            .await
+            .map(|_| ())
+            .map_err(query_error)
+    }
+
+    async fn get_remote_user_info(
+        &self,
+        _discord_user_id: &str,
+    ) -> Result<Option<RemoteUserInfo>, DatabaseError> {
+        Ok(None)
+    }
+
+    async fn update_remote_user_info(
+        &self,
+        _discord_user_id: &str,
+        _info: &RemoteUserInfo,
+    ) -> Result<(), DatabaseError> {
+        Ok(())
+    }
+
+    async fn get_all_user_ids(&self) -> Result<Vec<String>, DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        user_mappings::table
+            .select(user_mappings::matrix_user_id)
+            .load::<String>(&mut conn)
+            .await
+            .map_err(query_error)
+    }
+
+    async fn get_user_room_settings(
+        &self,
+        matrix_room_id: &str,
+        matrix_user_id: &str,
+    ) -> Result<Option<UserRoomSettings>, DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        user_room_settings::table
+            .filter(user_room_settings::matrix_room_id.eq(matrix_room_id))
+            .filter(user_room_settings::matrix_user_id.eq(matrix_user_id))
+            .select(DbUserRoomSettings::as_select())
+            .first::<DbUserRoomSettings>(&mut conn)
+            .await
+            .optional()
+            .map(|value| value.map(Into::into))
+            .map_err(query_error)
+    }
+
+    async fn set_user_room_settings(
+        &self,
+        settings: &UserRoomSettings,
+    ) -> Result<(), DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            async move {
+                let updated = diesel::update(
+                    user_room_settings::table
+                        .filter(user_room_settings::matrix_room_id.eq(&settings.matrix_room_id))
+                        .filter(user_room_settings::matrix_user_id.eq(&settings.matrix_user_id)),
+                )
+                .set((
+                    user_room_settings::webhook_nick.eq(&settings.webhook_nick),
+                    user_room_settings::updated_at.eq(settings.updated_at),
+                ))
+                .execute(conn)
+                .await?;
+                if updated == 0 {
+                    diesel::insert_into(user_room_settings::table)
+                        .values((
+                            user_room_settings::matrix_room_id.eq(&settings.matrix_room_id),
+                            user_room_settings::matrix_user_id.eq(&settings.matrix_user_id),
+                            user_room_settings::webhook_nick.eq(&settings.webhook_nick),
+                            user_room_settings::updated_at.eq(settings.updated_at),
+                        ))
+                        .execute(conn)
+                        .await?;
+                }
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+        .map_err(query_error)
+    }
+
+    async fn search_users(
+        &self,
+        query: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<UserMapping>, DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        let mut statement = user_mappings::table.into_boxed();
+        if let Some(pattern) = query.map(contains_pattern) {
+            statement = statement.filter(
+                user_mappings::matrix_user_id
+                    .ilike(pattern.clone())
+                    .escape('\\')
+                    .or(user_mappings::discord_user_id
+                        .ilike(pattern.clone())
+                        .escape('\\'))
+                    .or(user_mappings::discord_username.ilike(pattern).escape('\\')),
+            );
+        }
+        statement
+            .order(user_mappings::id.asc())
+            .limit(limit)
+            .offset(offset)
+            .select(DbUserMapping::as_select())
+            .load::<DbUserMapping>(&mut conn)
+            .await
+            .map(|rows| rows.into_iter().map(Into::into).collect())
+            .map_err(query_error)
+    }
+
+    async fn set_presence_override(
+        &self,
+        discord_user_id: &str,
+        presence: Option<&str>,
+    ) -> Result<(), DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        diesel::update(
+            user_mappings::table.filter(user_mappings::discord_user_id.eq(discord_user_id)),
+        )
+        .set((
+            user_mappings::presence_override.eq(presence),
+            user_mappings::updated_at.eq(Utc::now()),
+        ))
+        .execute(&mut conn)
+        .await
+        .map(|_| ())
+        .map_err(query_error)
+    }
+}
+
+pub struct AsyncPostgresMessageStore {
+    pool: AsyncPool,
+}
+
+impl AsyncPostgresMessageStore {
+    pub fn new(pool: AsyncPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl super::MessageStore for AsyncPostgresMessageStore {
+    async fn get_by_discord_message_id(
+        &self,
+        discord_message_id: &str,
+    ) -> Result<Option<MessageMapping>, DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        message_mappings::table
+            .filter(message_mappings::discord_message_id.eq(discord_message_id))
+            .select(DbMessageMapping::as_select())
+            .first::<DbMessageMapping>(&mut conn)
+            .await
+            .optional()
+            .map(|value| value.map(Into::into))
+            .map_err(query_error)
+    }
+
+    async fn get_by_matrix_event_id(
+        &self,
+        matrix_event_id: &str,
+    ) -> Result<Option<MessageMapping>, DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        message_mappings::table
+            .filter(message_mappings::matrix_event_id.eq(matrix_event_id))
+            .select(DbMessageMapping::as_select())
+            .first::<DbMessageMapping>(&mut conn)
+            .await
+            .optional()
+            .map(|value| value.map(Into::into))
+            .map_err(query_error)
+    }
+
+    async fn upsert_message_mapping(&self, mapping: &MessageMapping) -> Result<(), DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        let existing = message_mappings::table
+            .filter(message_mappings::discord_message_id.eq(&mapping.discord_message_id))
+            .select(DbMessageMapping::as_select())
+            .first::<DbMessageMapping>(&mut conn)
+            .await
+            .optional()
+            .map_err(query_error)?;
+
+        if let Some(existing) = existing {
+            let changes = UpdateMessageMapping {
+                matrix_room_id: &mapping.matrix_room_id,
+                matrix_event_id: &mapping.matrix_event_id,
+                discord_channel_id: mapping.discord_channel_id.as_deref(),
+                updated_at: &mapping.updated_at,
+            };
+            diesel::update(message_mappings::table.filter(message_mappings::id.eq(existing.id)))
+                .set(changes)
+                .execute(&mut conn)
+                .await
+                .map(|_| ())
+                .map_err(query_error)
+        } else {
+            let new_mapping = NewMessageMapping {
+                discord_message_id: &mapping.discord_message_id,
+                matrix_room_id: &mapping.matrix_room_id,
+                matrix_event_id: &mapping.matrix_event_id,
+                discord_channel_id: mapping.discord_channel_id.as_deref(),
+                created_at: &mapping.created_at,
+                updated_at: &mapping.updated_at,
+            };
+            diesel::insert_into(message_mappings::table)
+                .values(new_mapping)
+                .execute(&mut conn)
+                .await
+                .map(|_| ())
+                .map_err(query_error)
+        }
+    }
+
+    async fn delete_by_discord_message_id(
+        &self,
+        discord_message_id: &str,
+    ) -> Result<(), DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        diesel::delete(
+            message_mappings::table
+                .filter(message_mappings::discord_message_id.eq(discord_message_id)),
+        )
+        .execute(&mut conn)
+        .await
+        .map(|_| ())
+        .map_err(query_error)
+    }
+
+    async fn delete_by_matrix_event_id(&self, matrix_event_id: &str) -> Result<(), DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        diesel::delete(
+            message_mappings::table.filter(message_mappings::matrix_event_id.eq(matrix_event_id)),
+        )
+        .execute(&mut conn)
+        .await
+        .map(|_| ())
+        .map_err(query_error)
+    }
+
+    async fn list_message_mappings(
+        &self,
+        filter: &MessageMappingFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<MessageMapping>, DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        filtered_message_mappings(filter)
+            .order(message_mappings::id.desc())
+            .limit(limit)
+            .offset(offset)
+            .select(DbMessageMapping::as_select())
+            .load::<DbMessageMapping>(&mut conn)
+            .await
+            .map(|rows| rows.into_iter().map(Into::into).collect())
+            .map_err(query_error)
+    }
+
+    async fn count_message_mappings(
+        &self,
+        filter: &MessageMappingFilter,
+    ) -> Result<i64, DatabaseError> {
+        let mut conn = connection(&self.pool).await?;
+        filtered_message_mappings(filter)
+            .count()
+            .get_result::<i64>(&mut conn)
+            .await
+            .map_err(query_error)
+    }
+}
@@ -5,6 +5,9 @@ diesel::table! {
         discord_channel_id -> Text,
         discord_channel_name -> Text,
         discord_guild_id -> Text,
+        created_by -> Nullable<Text>,
+        origin -> Nullable<Text>,
+        last_active_at -> Nullable<Timestamptz>,
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
     }
@@ -18,6 +21,7 @@ diesel::table! {
         discord_username -> Text,
         discord_discriminator -> Text,
         discord_avatar -> Nullable<Text>,
+        presence_override -> Nullable<Text>,
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
     }
@@ -39,6 +43,7 @@ diesel::table! {
         discord_message_id -> Text,
         matrix_room_id -> Text,
         matrix_event_id -> Text,
+        discord_channel_id -> Nullable<Text>,
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
     }
@@ -56,10 +61,94 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    audit_log (id) {
+        id -> BigInt,
+        actor -> Text,
+        source -> Text,
+        action -> Text,
+        target -> Nullable<Text>,
+        parameters -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    api_tokens (id) {
+        id -> BigInt,
+        name -> Text,
+        token_hash -> Text,
+        scopes -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    room_settings (matrix_room_id) {
+        matrix_room_id -> Text,
+        auto_invite_members -> Bool,
+        role_keywords -> Nullable<Text>,
+        inactivity_exempt -> Bool,
+        member_sync -> Nullable<Text>,
+        attribution_badge -> Bool,
+        delivery_confirmations -> Bool,
+        attachment_policy -> Nullable<Text>,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    pending_deliveries (id) {
+        id -> Text,
+        direction -> Text,
+        payload -> Text,
+        attempts -> Integer,
+        next_attempt_at -> Timestamptz,
+        last_error -> Nullable<Text>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    user_room_settings (matrix_room_id, matrix_user_id) {
+        matrix_room_id -> Text,
+        matrix_user_id -> Text,
+        webhook_nick -> Nullable<Text>,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    emoji_usage (day, kind, discord_id) {
+        day -> Text,
+        kind -> Text,
+        discord_id -> Text,
+        name -> Text,
+        uses -> BigInt,
+    }
+}
+
+diesel::table! {
+    thread_mappings (discord_thread_id) {
+        discord_thread_id -> Text,
+        discord_parent_channel_id -> Text,
+        matrix_room_id -> Text,
+        matrix_root_event_id -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::allow_tables_to_appear_in_same_query!(
     room_mappings,
     user_mappings,
     processed_events,
     message_mappings,
     emoji_mappings,
+    audit_log,
+    api_tokens,
+    room_settings,
+    pending_deliveries,
+    user_room_settings,
+    emoji_usage,
+    thread_mappings,
 );
@@ -1,15 +1,22 @@
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use diesel::prelude::*;
 use diesel::sqlite::SqliteConnection;
 
-use super::DatabaseError;
 use super::models::{
-    EmojiMapping, MessageMapping, RemoteRoomInfo, RemoteUserInfo, RoomMapping, UserMapping,
+    ApiToken, AuditLogEntry, AuditLogFilter, AuditSource, DeliveryDirection, EmojiMapping,
+    EmojiUsage, MessageMapping, MessageMappingFilter, PendingDelivery, RemoteRoomInfo,
+    RemoteUserInfo, RoomMapping, RoomOrigin, RoomSettings, ThreadMapping, UserMapping,
+    UserRoomSettings,
+};
+use super::stores::contains_pattern;
+use super::{DatabaseError, blocking};
+use crate::db::schema_sqlite::{
+    api_tokens, audit_log, emoji_usage, message_mappings, pending_deliveries, room_mappings,
+    room_settings, thread_mappings, user_mappings, user_room_settings,
 };
-use crate::db::schema_sqlite::{message_mappings, room_mappings, user_mappings};
 
 // Helper function to convert DateTime to ISO string for SQLite
 fn datetime_to_string(dt: &DateTime<Utc>) -> String {
@@ -32,6 +39,9 @@ struct DbRoomMapping {
     discord_channel_id: String,
     discord_channel_name: String,
     discord_guild_id: String,
+    created_by: Option<String>,
+    origin: Option<String>,
+    last_active_at: Option<String>,
     created_at: String,
     updated_at: String,
 }
@@ -44,6 +54,13 @@ impl DbRoomMapping {
             discord_channel_id: self.discord_channel_id.clone(),
             discord_channel_name: self.discord_channel_name.clone(),
             discord_guild_id: self.discord_guild_id.clone(),
+            created_by: self.created_by.clone(),
+            origin: self.origin.as_deref().and_then(RoomOrigin::parse),
+            last_active_at: self
+                .last_active_at
+                .as_deref()
+                .map(string_to_datetime)
+                .transpose()?,
             created_at: string_to_datetime(&self.created_at)?,
             updated_at: string_to_datetime(&self.updated_at)?,
         })
@@ -57,6 +74,8 @@ struct NewRoomMapping<'a> {
     discord_channel_id: &'a str,
     discord_channel_name: &'a str,
     discord_guild_id: &'a str,
+    created_by: Option<&'a str>,
+    origin: Option<&'a str>,
     created_at: String,
     updated_at: String,
 }
@@ -80,6 +99,7 @@ struct DbUserMapping {
     discord_username: String,
     discord_discriminator: String,
     discord_avatar: Option<String>,
+    presence_override: Option<String>,
     created_at: String,
     updated_at: String,
 }
@@ -93,6 +113,7 @@ impl DbUserMapping {
             discord_username: self.discord_username.clone(),
             discord_discriminator: self.discord_discriminator.clone(),
             discord_avatar: self.discord_avatar.clone(),
+            presence_override: self.presence_override.clone(),
             created_at: string_to_datetime(&self.created_at)?,
             updated_at: string_to_datetime(&self.updated_at)?,
         })
@@ -107,6 +128,7 @@ struct NewUserMapping<'a> {
     discord_username: &'a str,
     discord_discriminator: &'a str,
     discord_avatar: Option<&'a str>,
+    presence_override: Option<&'a str>,
     created_at: String,
     updated_at: String,
 }
@@ -127,6 +149,7 @@ struct DbMessageMapping {
     discord_message_id: String,
     matrix_room_id: String,
     matrix_event_id: String,
+    discord_channel_id: Option<String>,
     created_at: String,
     updated_at: String,
 }
@@ -138,6 +161,7 @@ impl DbMessageMapping {
             discord_message_id: self.discord_message_id.clone(),
             matrix_room_id: self.matrix_room_id.clone(),
             matrix_event_id: self.matrix_event_id.clone(),
+            discord_channel_id: self.discord_channel_id.clone(),
             created_at: string_to_datetime(&self.created_at)?,
             updated_at: string_to_datetime(&self.updated_at)?,
         })
@@ -150,6 +174,7 @@ struct NewMessageMapping<'a> {
     discord_message_id: &'a str,
     matrix_room_id: &'a str,
     matrix_event_id: &'a str,
+    discord_channel_id: Option<&'a str>,
     created_at: String,
     updated_at: String,
 }
@@ -159,11 +184,54 @@ struct NewMessageMapping<'a> {
 struct UpdateMessageMapping<'a> {
     matrix_room_id: &'a str,
     matrix_event_id: &'a str,
+    discord_channel_id: Option<&'a str>,
     updated_at: String,
 }
 
+/// How long a connection waits for another writer before failing with
+/// "database is locked"; every store call opens its own connection, so
+/// concurrent bridge tasks contend for the file lock.
+const SQLITE_BUSY_TIMEOUT_MS: u32 = 5000;
+
 fn establish_connection(path: &str) -> Result<SqliteConnection, DatabaseError> {
-    SqliteConnection::establish(path).map_err(|e| DatabaseError::Connection(e.to_string()))
+    let mut conn =
+        SqliteConnection::establish(path).map_err(|e| DatabaseError::Connection(e.to_string()))?;
+    diesel::sql_query(format!("PRAGMA busy_timeout = {SQLITE_BUSY_TIMEOUT_MS}"))
+        .execute(&mut conn)
+        .map_err(|e| DatabaseError::Connection(e.to_string()))?;
+    Ok(conn)
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = room_settings)]
+struct DbRoomSettings {
+    matrix_room_id: String,
+    auto_invite_members: bool,
+    role_keywords: Option<String>,
+    inactivity_exempt: bool,
+    member_sync: Option<String>,
+    attribution_badge: bool,
+    delivery_confirmations: bool,
+    attachment_policy: Option<String>,
+    updated_at: String,
+}
+
+impl DbRoomSettings {
+    fn to_room_settings(&self) -> Result<RoomSettings, DatabaseError> {
+        Ok(RoomSettings {
+            matrix_room_id: self.matrix_room_id.clone(),
+            auto_invite_members: self.auto_invite_members,
+            role_keywords: RoomSettings::parse_role_keywords(self.role_keywords.as_deref())?,
+            inactivity_exempt: self.inactivity_exempt,
+            member_sync: RoomSettings::parse_member_sync(self.member_sync.as_deref())?,
+            attribution_badge: self.attribution_badge,
+            delivery_confirmations: self.delivery_confirmations,
+            attachment_policy: RoomSettings::parse_attachment_policy(
+                self.attachment_policy.as_deref(),
+            )?,
+            updated_at: string_to_datetime(&self.updated_at)?,
+        })
+    }
 }
 
 pub struct SqliteRoomStore {
@@ -184,7 +252,7 @@ impl super::RoomStore for SqliteRoomStore {
     ) -> Result<Option<RoomMapping>, DatabaseError> {
         let channel_id = channel_id.to_string();
         let db_path = self.db_path.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = establish_connection(&db_path)?;
             use crate::db::schema_sqlite::room_mappings::dsl::*;
             room_mappings
@@ -206,7 +274,7 @@ impl super::RoomStore for SqliteRoomStore {
     ) -> Result<Option<RoomMapping>, DatabaseError> {
         let room_id = room_id.to_string();
         let db_path = self.db_path.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = establish_connection(&db_path)?;
             use crate::db::schema_sqlite::room_mappings::dsl::*;
             room_mappings
@@ -225,7 +293,7 @@ impl super::RoomStore for SqliteRoomStore {
     async fn get_room_by_id(&self, mapping_id: i64) -> Result<Option<RoomMapping>, DatabaseError> {
         let mapping_id = mapping_id as i32;
         let db_path = self.db_path.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = establish_connection(&db_path)?;
             use crate::db::schema_sqlite::room_mappings::dsl::*;
             room_mappings
@@ -243,7 +311,7 @@ impl super::RoomStore for SqliteRoomStore {
 
     async fn count_rooms(&self) -> Result<i64, DatabaseError> {
         let db_path = self.db_path.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = establish_connection(&db_path)?;
             use crate::db::schema_sqlite::room_mappings::dsl::*;
             room_mappings
@@ -261,7 +329,7 @@ impl super::RoomStore for SqliteRoomStore {
         offset: i64,
     ) -> Result<Vec<RoomMapping>, DatabaseError> {
         let db_path = self.db_path.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = establish_connection(&db_path)?;
             use crate::db::schema_sqlite::room_mappings::dsl::*;
             let results = room_mappings
@@ -281,13 +349,15 @@ impl super::RoomStore for SqliteRoomStore {
     async fn create_room_mapping(&self, mapping: &RoomMapping) -> Result<(), DatabaseError> {
         let mapping = mapping.clone();
         let db_path = self.db_path.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = establish_connection(&db_path)?;
             let new_mapping = NewRoomMapping {
                 matrix_room_id: &mapping.matrix_room_id,
                 discord_channel_id: &mapping.discord_channel_id,
                 discord_channel_name: &mapping.discord_channel_name,
                 discord_guild_id: &mapping.discord_guild_id,
+                created_by: mapping.created_by.as_deref(),
+                origin: mapping.origin.as_ref().map(RoomOrigin::as_str),
                 created_at: datetime_to_string(&mapping.created_at),
                 updated_at: datetime_to_string(&mapping.updated_at),
             };
@@ -305,7 +375,7 @@ impl super::RoomStore for SqliteRoomStore {
     async fn update_room_mapping(&self, mapping: &RoomMapping) -> Result<(), DatabaseError> {
         let mapping = mapping.clone();
         let db_path = self.db_path.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = establish_connection(&db_path)?;
             let changes = UpdateRoomMapping {
                 matrix_room_id: &mapping.matrix_room_id,
@@ -328,7 +398,7 @@ impl super::RoomStore for SqliteRoomStore {
     async fn delete_room_mapping(&self, id: i64) -> Result<(), DatabaseError> {
         let id = id as i32;
         let db_path = self.db_path.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = establish_connection(&db_path)?;
             diesel::delete(room_mappings::table.filter(room_mappings::id.eq(id)))
                 .execute(&mut conn)
@@ -339,10 +409,25 @@ impl super::RoomStore for SqliteRoomStore {
         .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
     }
 
+    async fn touch_room_mapping(&self, id: i64, at: DateTime<Utc>) -> Result<(), DatabaseError> {
+        let id = id as i32;
+        let db_path = self.db_path.clone();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            diesel::update(room_mappings::table.filter(room_mappings::id.eq(id)))
+                .set(room_mappings::last_active_at.eq(Some(datetime_to_string(&at))))
+                .execute(&mut conn)
+                .map(|_| ())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
     async fn get_rooms_by_guild(&self, guild_id: &str) -> Result<Vec<RoomMapping>, DatabaseError> {
         let guild_id = guild_id.to_string();
         let db_path = self.db_path.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = establish_connection(&db_path)?;
             use crate::db::schema_sqlite::room_mappings::dsl::*;
             let results = room_mappings
@@ -370,6 +455,214 @@ impl super::RoomStore for SqliteRoomStore {
     ) -> Result<(), DatabaseError> {
         Ok(())
     }
+
+    async fn get_room_settings(
+        &self,
+        room_id: &str,
+    ) -> Result<Option<RoomSettings>, DatabaseError> {
+        let room_id = room_id.to_string();
+        let db_path = self.db_path.clone();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            room_settings::table
+                .filter(room_settings::matrix_room_id.eq(&room_id))
+                .select(DbRoomSettings::as_select())
+                .first::<DbRoomSettings>(&mut conn)
+                .optional()
+                .map_err(|e| DatabaseError::Query(e.to_string()))?
+                .map(|s| s.to_room_settings())
+                .transpose()
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn set_room_settings(&self, settings: &RoomSettings) -> Result<(), DatabaseError> {
+        let settings = settings.clone();
+        let db_path = self.db_path.clone();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            let updated_at = datetime_to_string(&settings.updated_at);
+            let role_keywords = settings.role_keywords_json();
+            let member_sync = settings.member_sync_json();
+            let attachment_policy = settings.attachment_policy_json();
+            conn.transaction(|conn| {
+                let updated = diesel::update(
+                    room_settings::table
+                        .filter(room_settings::matrix_room_id.eq(&settings.matrix_room_id)),
+                )
+                .set((
+                    room_settings::auto_invite_members.eq(settings.auto_invite_members),
+                    room_settings::role_keywords.eq(&role_keywords),
+                    room_settings::inactivity_exempt.eq(settings.inactivity_exempt),
+                    room_settings::member_sync.eq(&member_sync),
+                    room_settings::attachment_policy.eq(&attachment_policy),
+                    room_settings::attribution_badge.eq(settings.attribution_badge),
+                    room_settings::delivery_confirmations.eq(settings.delivery_confirmations),
+                    room_settings::updated_at.eq(&updated_at),
+                ))
+                .execute(conn)?;
+                if updated == 0 {
+                    diesel::insert_into(room_settings::table)
+                        .values((
+                            room_settings::matrix_room_id.eq(&settings.matrix_room_id),
+                            room_settings::auto_invite_members.eq(settings.auto_invite_members),
+                            room_settings::role_keywords.eq(&role_keywords),
+                            room_settings::inactivity_exempt.eq(settings.inactivity_exempt),
+                            room_settings::member_sync.eq(&member_sync),
+                            room_settings::attachment_policy.eq(&attachment_policy),
+                            room_settings::attribution_badge.eq(settings.attribution_badge),
+                            room_settings::delivery_confirmations
+                                .eq(settings.delivery_confirmations),
+                            room_settings::updated_at.eq(&updated_at),
+                        ))
+                        .execute(conn)?;
+                }
+                Ok(())
+            })
+            .map_err(|e: diesel::result::Error| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn get_thread_by_discord_thread(
+        &self,
+        discord_thread_id: &str,
+    ) -> Result<Option<ThreadMapping>, DatabaseError> {
+        let thread_id = discord_thread_id.to_string();
+        let db_path = self.db_path.clone();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            thread_mappings::table
+                .filter(thread_mappings::discord_thread_id.eq(thread_id))
+                .select(DbThreadMapping::as_select())
+                .first::<DbThreadMapping>(&mut conn)
+                .optional()
+                .map_err(|e| DatabaseError::Query(e.to_string()))?
+                .map(|m| m.to_thread_mapping())
+                .transpose()
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn get_thread_by_matrix_root(
+        &self,
+        matrix_root_event_id: &str,
+    ) -> Result<Option<ThreadMapping>, DatabaseError> {
+        let event_id = matrix_root_event_id.to_string();
+        let db_path = self.db_path.clone();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            thread_mappings::table
+                .filter(thread_mappings::matrix_root_event_id.eq(event_id))
+                .select(DbThreadMapping::as_select())
+                .first::<DbThreadMapping>(&mut conn)
+                .optional()
+                .map_err(|e| DatabaseError::Query(e.to_string()))?
+                .map(|m| m.to_thread_mapping())
+                .transpose()
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn upsert_thread_mapping(&self, mapping: &ThreadMapping) -> Result<(), DatabaseError> {
+        let mapping = mapping.clone();
+        let db_path = self.db_path.clone();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            let created_at = datetime_to_string(&mapping.created_at);
+            conn.transaction(|conn| {
+                let updated = diesel::update(
+                    thread_mappings::table
+                        .filter(thread_mappings::discord_thread_id.eq(&mapping.discord_thread_id)),
+                )
+                .set((
+                    thread_mappings::discord_parent_channel_id
+                        .eq(&mapping.discord_parent_channel_id),
+                    thread_mappings::matrix_room_id.eq(&mapping.matrix_room_id),
+                    thread_mappings::matrix_root_event_id.eq(&mapping.matrix_root_event_id),
+                ))
+                .execute(conn)?;
+                if updated == 0 {
+                    diesel::insert_into(thread_mappings::table)
+                        .values((
+                            thread_mappings::discord_thread_id.eq(&mapping.discord_thread_id),
+                            thread_mappings::discord_parent_channel_id
+                                .eq(&mapping.discord_parent_channel_id),
+                            thread_mappings::matrix_room_id.eq(&mapping.matrix_room_id),
+                            thread_mappings::matrix_root_event_id.eq(&mapping.matrix_root_event_id),
+                            thread_mappings::created_at.eq(&created_at),
+                        ))
+                        .execute(conn)?;
+                }
+                Ok(())
+            })
+            .map_err(|e: diesel::result::Error| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn delete_thread_mapping(&self, discord_thread_id: &str) -> Result<(), DatabaseError> {
+        let thread_id = discord_thread_id.to_string();
+        let db_path = self.db_path.clone();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            diesel::delete(
+                thread_mappings::table.filter(thread_mappings::discord_thread_id.eq(thread_id)),
+            )
+            .execute(&mut conn)
+            .map(|_| ())
+            .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = thread_mappings)]
+struct DbThreadMapping {
+    discord_thread_id: String,
+    discord_parent_channel_id: String,
+    matrix_room_id: String,
+    matrix_root_event_id: String,
+    created_at: String,
+}
+
+impl DbThreadMapping {
+    fn to_thread_mapping(&self) -> Result<ThreadMapping, DatabaseError> {
+        Ok(ThreadMapping {
+            discord_thread_id: self.discord_thread_id.clone(),
+            discord_parent_channel_id: self.discord_parent_channel_id.clone(),
+            matrix_room_id: self.matrix_room_id.clone(),
+            matrix_root_event_id: self.matrix_root_event_id.clone(),
+            created_at: string_to_datetime(&self.created_at)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = user_room_settings)]
+struct DbUserRoomSettings {
+    matrix_room_id: String,
+    matrix_user_id: String,
+    webhook_nick: Option<String>,
+    updated_at: String,
+}
+
+impl DbUserRoomSettings {
+    fn to_user_room_settings(&self) -> Result<UserRoomSettings, DatabaseError> {
+        Ok(UserRoomSettings {
+            matrix_room_id: self.matrix_room_id.clone(),
+            matrix_user_id: self.matrix_user_id.clone(),
+            webhook_nick: self.webhook_nick.clone(),
+            updated_at: string_to_datetime(&self.updated_at)?,
+        })
+    }
 }
 
 pub struct SqliteUserStore {
@@ -390,7 +683,7 @@ impl super::UserStore for SqliteUserStore {
     ) -> Result<Option<UserMapping>, DatabaseError> {
         let discord_id = discord_id.to_string();
         let db_path = self.db_path.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = establish_connection(&db_path)?;
             use crate::db::schema_sqlite::user_mappings::dsl::*;
             user_mappings
@@ -409,7 +702,7 @@ impl super::UserStore for SqliteUserStore {
     async fn create_user_mapping(&self, mapping: &UserMapping) -> Result<(), DatabaseError> {
         let mapping = mapping.clone();
         let db_path = self.db_path.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = establish_connection(&db_path)?;
             let new_mapping = NewUserMapping {
                 matrix_user_id: &mapping.matrix_user_id,
@@ -417,6 +710,7 @@ impl super::UserStore for SqliteUserStore {
                 discord_username: &mapping.discord_username,
                 discord_discriminator: &mapping.discord_discriminator,
                 discord_avatar: mapping.discord_avatar.as_deref(),
+                presence_override: mapping.presence_override.as_deref(),
                 created_at: datetime_to_string(&mapping.created_at),
                 updated_at: datetime_to_string(&mapping.updated_at),
             };
@@ -434,7 +728,7 @@ impl super::UserStore for SqliteUserStore {
     async fn update_user_mapping(&self, mapping: &UserMapping) -> Result<(), DatabaseError> {
         let mapping = mapping.clone();
         let db_path = self.db_path.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = establish_connection(&db_path)?;
             let changes = UpdateUserMapping {
                 discord_username: &mapping.discord_username,
@@ -456,7 +750,7 @@ impl super::UserStore for SqliteUserStore {
     async fn delete_user_mapping(&self, id: i64) -> Result<(), DatabaseError> {
         let id = id as i32;
         let db_path = self.db_path.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = establish_connection(&db_path)?;
             diesel::delete(user_mappings::table.filter(user_mappings::id.eq(id)))
                 .execute(&mut conn)
@@ -473,7 +767,7 @@ impl super::UserStore for SqliteUserStore {
     ) -> Result<Option<UserMapping>, DatabaseError> {
         let matrix_id = matrix_id.to_string();
         let db_path = self.db_path.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = establish_connection(&db_path)?;
             use crate::db::schema_sqlite::user_mappings::dsl::*;
             user_mappings
@@ -506,7 +800,7 @@ impl super::UserStore for SqliteUserStore {
 
     async fn get_all_user_ids(&self) -> Result<Vec<String>, DatabaseError> {
         let db_path = self.db_path.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = establish_connection(&db_path)?;
             use crate::db::schema_sqlite::user_mappings::dsl::*;
             user_mappings
@@ -517,6 +811,145 @@ impl super::UserStore for SqliteUserStore {
         .await
         .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
     }
+
+    async fn search_users(
+        &self,
+        query: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<UserMapping>, DatabaseError> {
+        let pattern = query.map(contains_pattern);
+        let db_path = self.db_path.clone();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            use crate::db::schema_sqlite::user_mappings::dsl::*;
+            let mut statement = user_mappings.into_boxed();
+            if let Some(pattern) = pattern {
+                // SQLite's LIKE already ignores ASCII case.
+                statement = statement.filter(
+                    matrix_user_id
+                        .like(pattern.clone())
+                        .escape('\\')
+                        .or(discord_user_id.like(pattern.clone()).escape('\\'))
+                        .or(discord_username.like(pattern).escape('\\')),
+                );
+            }
+            statement
+                .order(id.asc())
+                .limit(limit)
+                .offset(offset)
+                .select(DbUserMapping::as_select())
+                .load::<DbUserMapping>(&mut conn)
+                .map_err(|e| DatabaseError::Query(e.to_string()))?
+                .into_iter()
+                .map(|m| m.to_user_mapping())
+                .collect()
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn set_presence_override(
+        &self,
+        discord_user_id_param: &str,
+        presence: Option<&str>,
+    ) -> Result<(), DatabaseError> {
+        let discord_user_id_param = discord_user_id_param.to_string();
+        let presence = presence.map(ToString::to_string);
+        let db_path = self.db_path.clone();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            use crate::db::schema_sqlite::user_mappings::dsl::*;
+            diesel::update(user_mappings.filter(discord_user_id.eq(discord_user_id_param)))
+                .set((
+                    presence_override.eq(presence),
+                    updated_at.eq(datetime_to_string(&Utc::now())),
+                ))
+                .execute(&mut conn)
+                .map(|_| ())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn get_user_room_settings(
+        &self,
+        matrix_room_id: &str,
+        matrix_user_id: &str,
+    ) -> Result<Option<UserRoomSettings>, DatabaseError> {
+        let room_id = matrix_room_id.to_string();
+        let user_id = matrix_user_id.to_string();
+        let db_path = self.db_path.clone();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            user_room_settings::table
+                .filter(user_room_settings::matrix_room_id.eq(&room_id))
+                .filter(user_room_settings::matrix_user_id.eq(&user_id))
+                .select(DbUserRoomSettings::as_select())
+                .first::<DbUserRoomSettings>(&mut conn)
+                .optional()
+                .map_err(|e| DatabaseError::Query(e.to_string()))?
+                .map(|s| s.to_user_room_settings())
+                .transpose()
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn set_user_room_settings(
+        &self,
+        settings: &UserRoomSettings,
+    ) -> Result<(), DatabaseError> {
+        let settings = settings.clone();
+        let db_path = self.db_path.clone();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            let updated_at = datetime_to_string(&settings.updated_at);
+            conn.transaction(|conn| {
+                let updated = diesel::update(
+                    user_room_settings::table
+                        .filter(user_room_settings::matrix_room_id.eq(&settings.matrix_room_id))
+                        .filter(user_room_settings::matrix_user_id.eq(&settings.matrix_user_id)),
+                )
+                .set((
+                    user_room_settings::webhook_nick.eq(&settings.webhook_nick),
+                    user_room_settings::updated_at.eq(&updated_at),
+                ))
+                .execute(conn)?;
+                if updated == 0 {
+                    diesel::insert_into(user_room_settings::table)
+                        .values((
+                            user_room_settings::matrix_room_id.eq(&settings.matrix_room_id),
+                            user_room_settings::matrix_user_id.eq(&settings.matrix_user_id),
+                            user_room_settings::webhook_nick.eq(&settings.webhook_nick),
+                            user_room_settings::updated_at.eq(&updated_at),
+                        ))
+                        .execute(conn)?;
+                }
+                Ok(())
+            })
+            .map_err(|e: diesel::result::Error| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+}
+
+fn filtered_message_mappings(
+    filter: &MessageMappingFilter,
+) -> message_mappings::BoxedQuery<'static, diesel::sqlite::Sqlite> {
+    let mut query = message_mappings::table.into_boxed();
+    if let Some(room_id) = &filter.matrix_room_id {
+        query = query.filter(message_mappings::matrix_room_id.eq(room_id.clone()));
+    }
+    if let Some(since) = &filter.since {
+        query = query.filter(message_mappings::created_at.ge(datetime_to_string(since)));
+    }
+    if let Some(until) = &filter.until {
+        query = query.filter(message_mappings::created_at.le(datetime_to_string(until)));
+    }
+    query
 }
 
 pub struct SqliteMessageStore {
@@ -537,7 +970,7 @@ impl super::MessageStore for SqliteMessageStore {
     ) -> Result<Option<MessageMapping>, DatabaseError> {
         let discord_message_id_param = discord_message_id_param.to_string();
         let db_path = self.db_path.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = establish_connection(&db_path)?;
             use crate::db::schema_sqlite::message_mappings::dsl::*;
             message_mappings
@@ -559,7 +992,7 @@ impl super::MessageStore for SqliteMessageStore {
     ) -> Result<Option<MessageMapping>, DatabaseError> {
         let matrix_event_id_param = matrix_event_id_param.to_string();
         let db_path = self.db_path.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = establish_connection(&db_path)?;
             use crate::db::schema_sqlite::message_mappings::dsl::*;
             message_mappings
@@ -578,7 +1011,7 @@ impl super::MessageStore for SqliteMessageStore {
     async fn upsert_message_mapping(&self, mapping: &MessageMapping) -> Result<(), DatabaseError> {
         let mapping = mapping.clone();
         let db_path = self.db_path.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = establish_connection(&db_path)?;
             use crate::db::schema_sqlite::message_mappings::dsl::*;
 
@@ -593,6 +1026,7 @@ impl super::MessageStore for SqliteMessageStore {
                 let changes = UpdateMessageMapping {
                     matrix_room_id: &mapping.matrix_room_id,
                     matrix_event_id: &mapping.matrix_event_id,
+                    discord_channel_id: mapping.discord_channel_id.as_deref(),
                     updated_at: datetime_to_string(&mapping.updated_at),
                 };
 
@@ -606,6 +1040,7 @@ impl super::MessageStore for SqliteMessageStore {
                     discord_message_id: &mapping.discord_message_id,
                     matrix_room_id: &mapping.matrix_room_id,
                     matrix_event_id: &mapping.matrix_event_id,
+                    discord_channel_id: mapping.discord_channel_id.as_deref(),
                     created_at: datetime_to_string(&mapping.created_at),
                     updated_at: datetime_to_string(&mapping.updated_at),
                 };
@@ -627,7 +1062,7 @@ impl super::MessageStore for SqliteMessageStore {
     ) -> Result<(), DatabaseError> {
         let discord_message_id_param = discord_message_id_param.to_string();
         let db_path = self.db_path.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = establish_connection(&db_path)?;
             use crate::db::schema_sqlite::message_mappings::dsl::*;
             diesel::delete(message_mappings.filter(discord_message_id.eq(discord_message_id_param)))
@@ -645,7 +1080,7 @@ impl super::MessageStore for SqliteMessageStore {
     ) -> Result<(), DatabaseError> {
         let matrix_event_id_param = matrix_event_id_param.to_string();
         let db_path = self.db_path.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = establish_connection(&db_path)?;
             use crate::db::schema_sqlite::message_mappings::dsl::*;
             diesel::delete(message_mappings.filter(matrix_event_id.eq(matrix_event_id_param)))
@@ -656,6 +1091,48 @@ impl super::MessageStore for SqliteMessageStore {
         .await
         .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
     }
+
+    async fn list_message_mappings(
+        &self,
+        filter: &MessageMappingFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<MessageMapping>, DatabaseError> {
+        let filter = filter.clone();
+        let db_path = self.db_path.clone();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            filtered_message_mappings(&filter)
+                .order(message_mappings::id.desc())
+                .limit(limit)
+                .offset(offset)
+                .select(DbMessageMapping::as_select())
+                .load::<DbMessageMapping>(&mut conn)
+                .map_err(|e| DatabaseError::Query(e.to_string()))?
+                .iter()
+                .map(|m| m.to_message_mapping())
+                .collect()
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn count_message_mappings(
+        &self,
+        filter: &MessageMappingFilter,
+    ) -> Result<i64, DatabaseError> {
+        let filter = filter.clone();
+        let db_path = self.db_path.clone();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            filtered_message_mappings(&filter)
+                .count()
+                .get_result::<i64>(&mut conn)
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
 }
 
 pub struct SqliteEmojiStore {
@@ -694,6 +1171,22 @@ impl DbEmojiMapping {
     }
 }
 
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = emoji_usage)]
+struct DbEmojiUsage {
+    day: String,
+    kind: String,
+    discord_id: String,
+    name: String,
+    uses: i64,
+}
+
+impl DbEmojiUsage {
+    fn into_emoji_usage(self) -> Result<EmojiUsage, DatabaseError> {
+        EmojiUsage::from_stored(&self.day, &self.kind, self.discord_id, self.name, self.uses)
+    }
+}
+
 #[async_trait]
 impl super::EmojiStore for SqliteEmojiStore {
     async fn get_emoji_by_discord_id(
@@ -702,7 +1195,7 @@ impl super::EmojiStore for SqliteEmojiStore {
     ) -> Result<Option<EmojiMapping>, DatabaseError> {
         let discord_emoji_id = discord_emoji_id.to_string();
         let db_path = self.db_path.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = establish_connection(&db_path)?;
             diesel::sql_query(
                 "SELECT id, discord_emoji_id, emoji_name, animated, mxc_url, created_at, updated_at FROM emoji_mappings WHERE discord_emoji_id = ?"
@@ -721,7 +1214,7 @@ impl super::EmojiStore for SqliteEmojiStore {
     async fn get_emoji_by_mxc(&self, mxc_url: &str) -> Result<Option<EmojiMapping>, DatabaseError> {
         let mxc_url = mxc_url.to_string();
         let db_path = self.db_path.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = establish_connection(&db_path)?;
             diesel::sql_query(
                 "SELECT id, discord_emoji_id, emoji_name, animated, mxc_url, created_at, updated_at FROM emoji_mappings WHERE mxc_url = ?"
@@ -740,7 +1233,7 @@ impl super::EmojiStore for SqliteEmojiStore {
     async fn create_emoji(&self, emoji: &EmojiMapping) -> Result<(), DatabaseError> {
         let emoji = emoji.clone();
         let db_path = self.db_path.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = establish_connection(&db_path)?;
             diesel::sql_query(
                 "INSERT INTO emoji_mappings (discord_emoji_id, emoji_name, animated, mxc_url, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?)"
@@ -762,7 +1255,7 @@ impl super::EmojiStore for SqliteEmojiStore {
     async fn update_emoji(&self, emoji: &EmojiMapping) -> Result<(), DatabaseError> {
         let emoji = emoji.clone();
         let db_path = self.db_path.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = establish_connection(&db_path)?;
             diesel::sql_query(
                 "UPDATE emoji_mappings SET emoji_name = ?, animated = ?, mxc_url = ?, updated_at = ? WHERE discord_emoji_id = ?"
@@ -783,7 +1276,7 @@ impl super::EmojiStore for SqliteEmojiStore {
     async fn delete_emoji(&self, discord_emoji_id: &str) -> Result<(), DatabaseError> {
         let discord_emoji_id = discord_emoji_id.to_string();
         let db_path = self.db_path.clone();
-        tokio::task::spawn_blocking(move || {
+        blocking::spawn(move || {
             let mut conn = establish_connection(&db_path)?;
             diesel::sql_query("DELETE FROM emoji_mappings WHERE discord_emoji_id = ?")
                 .bind::<diesel::sql_types::Text, _>(&discord_emoji_id)
@@ -794,4 +1287,475 @@ impl super::EmojiStore for SqliteEmojiStore {
         .await
         .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
     }
+
+    async fn add_emoji_usage(&self, usage: &[EmojiUsage]) -> Result<(), DatabaseError> {
+        let db_path = self.db_path.clone();
+        let usage = usage.to_vec();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            conn.transaction(|conn| {
+                for entry in &usage {
+                    let day = entry.stored_day();
+                    let existing = emoji_usage::table
+                        .filter(emoji_usage::day.eq(&day))
+                        .filter(emoji_usage::kind.eq(entry.kind.as_str()))
+                        .filter(emoji_usage::discord_id.eq(&entry.discord_id));
+                    let updated = diesel::update(existing)
+                        .set((
+                            emoji_usage::name.eq(&entry.name),
+                            emoji_usage::uses.eq(emoji_usage::uses + entry.uses),
+                        ))
+                        .execute(conn)?;
+                    if updated == 0 {
+                        diesel::insert_into(emoji_usage::table)
+                            .values((
+                                emoji_usage::day.eq(&day),
+                                emoji_usage::kind.eq(entry.kind.as_str()),
+                                emoji_usage::discord_id.eq(&entry.discord_id),
+                                emoji_usage::name.eq(&entry.name),
+                                emoji_usage::uses.eq(entry.uses),
+                            ))
+                            .execute(conn)?;
+                    }
+                }
+                Ok(())
+            })
+            .map_err(|e: diesel::result::Error| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn list_emoji_usage(&self, since: NaiveDate) -> Result<Vec<EmojiUsage>, DatabaseError> {
+        let db_path = self.db_path.clone();
+        let since = since.format(EmojiUsage::DAY_FORMAT).to_string();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            emoji_usage::table
+                .filter(emoji_usage::day.ge(&since))
+                .order((emoji_usage::day.asc(), emoji_usage::discord_id.asc()))
+                .select(DbEmojiUsage::as_select())
+                .load::<DbEmojiUsage>(&mut conn)
+                .map_err(|e| DatabaseError::Query(e.to_string()))?
+                .into_iter()
+                .map(DbEmojiUsage::into_emoji_usage)
+                .collect()
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = audit_log)]
+struct DbAuditLogEntry {
+    id: i32,
+    actor: String,
+    source: String,
+    action: String,
+    target: Option<String>,
+    parameters: String,
+    created_at: String,
+}
+
+impl DbAuditLogEntry {
+    fn to_audit_entry(&self) -> Result<AuditLogEntry, DatabaseError> {
+        Ok(AuditLogEntry {
+            id: self.id as i64,
+            actor: self.actor.clone(),
+            source: AuditSource::parse(&self.source).ok_or_else(|| {
+                DatabaseError::Query(format!("unknown audit source: {}", self.source))
+            })?,
+            action: self.action.clone(),
+            target: self.target.clone(),
+            parameters: serde_json::from_str(&self.parameters)
+                .map_err(|e| DatabaseError::Query(format!("invalid audit parameters: {e}")))?,
+            created_at: string_to_datetime(&self.created_at)?,
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = audit_log)]
+struct NewAuditLogEntry<'a> {
+    actor: &'a str,
+    source: &'a str,
+    action: &'a str,
+    target: Option<&'a str>,
+    parameters: String,
+    created_at: String,
+}
+
+// Timestamps are stored as UTC RFC 3339 strings, which sort lexically.
+fn filtered_audit_log(
+    filter: &AuditLogFilter,
+) -> audit_log::BoxedQuery<'static, diesel::sqlite::Sqlite> {
+    let mut query = audit_log::table.into_boxed();
+    if let Some(actor) = &filter.actor {
+        query = query.filter(audit_log::actor.eq(actor.clone()));
+    }
+    if let Some(source) = filter.source {
+        query = query.filter(audit_log::source.eq(source.as_str()));
+    }
+    if let Some(action) = &filter.action {
+        query = query.filter(audit_log::action.eq(action.clone()));
+    }
+    if let Some(target) = &filter.target {
+        query = query.filter(audit_log::target.eq(target.clone()));
+    }
+    if let Some(since) = &filter.since {
+        query = query.filter(audit_log::created_at.ge(datetime_to_string(since)));
+    }
+    if let Some(until) = &filter.until {
+        query = query.filter(audit_log::created_at.le(datetime_to_string(until)));
+    }
+    query
+}
+
+pub struct SqliteAuditStore {
+    db_path: Arc<String>,
+}
+
+impl SqliteAuditStore {
+    pub fn new(db_path: Arc<String>) -> Self {
+        Self { db_path }
+    }
+}
+
+#[async_trait]
+impl super::AuditStore for SqliteAuditStore {
+    async fn record_audit_entry(&self, entry: &AuditLogEntry) -> Result<(), DatabaseError> {
+        let entry = entry.clone();
+        let db_path = self.db_path.clone();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            let new_entry = NewAuditLogEntry {
+                actor: &entry.actor,
+                source: entry.source.as_str(),
+                action: &entry.action,
+                target: entry.target.as_deref(),
+                parameters: entry.parameters.to_string(),
+                created_at: datetime_to_string(&entry.created_at),
+            };
+
+            diesel::insert_into(audit_log::table)
+                .values(&new_entry)
+                .execute(&mut conn)
+                .map(|_| ())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn list_audit_entries(
+        &self,
+        filter: &AuditLogFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<AuditLogEntry>, DatabaseError> {
+        let filter = filter.clone();
+        let db_path = self.db_path.clone();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            let results = filtered_audit_log(&filter)
+                .order(audit_log::id.desc())
+                .limit(limit)
+                .offset(offset)
+                .select(DbAuditLogEntry::as_select())
+                .load::<DbAuditLogEntry>(&mut conn)
+                .map_err(|e| DatabaseError::Query(e.to_string()))?;
+
+            results.iter().map(|e| e.to_audit_entry()).collect()
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn count_audit_entries(&self, filter: &AuditLogFilter) -> Result<i64, DatabaseError> {
+        let filter = filter.clone();
+        let db_path = self.db_path.clone();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            filtered_audit_log(&filter)
+                .count()
+                .get_result::<i64>(&mut conn)
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = api_tokens)]
+struct DbApiToken {
+    id: i32,
+    name: String,
+    token_hash: String,
+    scopes: String,
+    created_at: String,
+}
+
+impl DbApiToken {
+    fn to_api_token(&self) -> Result<ApiToken, DatabaseError> {
+        Ok(ApiToken {
+            id: self.id as i64,
+            name: self.name.clone(),
+            token_hash: self.token_hash.clone(),
+            scopes: ApiToken::scopes_from_column(&self.scopes),
+            created_at: string_to_datetime(&self.created_at)?,
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = api_tokens)]
+struct NewApiToken<'a> {
+    name: &'a str,
+    token_hash: &'a str,
+    scopes: String,
+    created_at: String,
+}
+
+pub struct SqliteApiTokenStore {
+    db_path: Arc<String>,
+}
+
+impl SqliteApiTokenStore {
+    pub fn new(db_path: Arc<String>) -> Self {
+        Self { db_path }
+    }
+}
+
+#[async_trait]
+impl super::ApiTokenStore for SqliteApiTokenStore {
+    async fn get_api_token_by_hash(&self, hash: &str) -> Result<Option<ApiToken>, DatabaseError> {
+        let hash = hash.to_string();
+        let db_path = self.db_path.clone();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            api_tokens::table
+                .filter(api_tokens::token_hash.eq(&hash))
+                .select(DbApiToken::as_select())
+                .first::<DbApiToken>(&mut conn)
+                .optional()
+                .map_err(|e| DatabaseError::Query(e.to_string()))?
+                .map(|t| t.to_api_token())
+                .transpose()
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn list_api_tokens(&self) -> Result<Vec<ApiToken>, DatabaseError> {
+        let db_path = self.db_path.clone();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            let results = api_tokens::table
+                .order(api_tokens::name.asc())
+                .select(DbApiToken::as_select())
+                .load::<DbApiToken>(&mut conn)
+                .map_err(|e| DatabaseError::Query(e.to_string()))?;
+
+            results.iter().map(|t| t.to_api_token()).collect()
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn count_api_tokens(&self) -> Result<i64, DatabaseError> {
+        let db_path = self.db_path.clone();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            api_tokens::table
+                .count()
+                .get_result::<i64>(&mut conn)
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn create_api_token(&self, token: &ApiToken) -> Result<(), DatabaseError> {
+        let token = token.clone();
+        let db_path = self.db_path.clone();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            let new_token = NewApiToken {
+                name: &token.name,
+                token_hash: &token.token_hash,
+                scopes: ApiToken::scopes_to_column(&token.scopes),
+                created_at: datetime_to_string(&token.created_at),
+            };
+
+            diesel::insert_into(api_tokens::table)
+                .values(&new_token)
+                .execute(&mut conn)
+                .map(|_| ())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn delete_api_token(&self, name: &str) -> Result<bool, DatabaseError> {
+        let name = name.to_string();
+        let db_path = self.db_path.clone();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            diesel::delete(api_tokens::table.filter(api_tokens::name.eq(&name)))
+                .execute(&mut conn)
+                .map(|deleted| deleted > 0)
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Selectable, Insertable)]
+#[diesel(table_name = pending_deliveries)]
+struct DbPendingDelivery {
+    id: String,
+    direction: String,
+    payload: String,
+    attempts: i32,
+    next_attempt_at: String,
+    last_error: Option<String>,
+    created_at: String,
+}
+
+impl DbPendingDelivery {
+    fn from_delivery(delivery: &PendingDelivery) -> Result<Self, DatabaseError> {
+        Ok(Self {
+            id: delivery.id.clone(),
+            direction: delivery.direction.as_str().to_string(),
+            payload: serde_json::to_string(&delivery.payload)
+                .map_err(|e| DatabaseError::Query(format!("invalid delivery payload: {e}")))?,
+            attempts: delivery.attempts,
+            next_attempt_at: datetime_to_string(&delivery.next_attempt_at),
+            last_error: delivery.last_error.clone(),
+            created_at: datetime_to_string(&delivery.created_at),
+        })
+    }
+
+    fn to_delivery(&self) -> Result<PendingDelivery, DatabaseError> {
+        Ok(PendingDelivery {
+            id: self.id.clone(),
+            direction: DeliveryDirection::parse(&self.direction).ok_or_else(|| {
+                DatabaseError::Query(format!("unknown delivery direction: {}", self.direction))
+            })?,
+            payload: serde_json::from_str(&self.payload)
+                .map_err(|e| DatabaseError::Query(format!("invalid delivery payload: {e}")))?,
+            attempts: self.attempts,
+            next_attempt_at: string_to_datetime(&self.next_attempt_at)?,
+            last_error: self.last_error.clone(),
+            created_at: string_to_datetime(&self.created_at)?,
+        })
+    }
+}
+
+pub struct SqliteDeliveryStore {
+    db_path: Arc<String>,
+}
+
+impl SqliteDeliveryStore {
+    pub fn new(db_path: Arc<String>) -> Self {
+        Self { db_path }
+    }
+}
+
+#[async_trait]
+impl super::DeliveryStore for SqliteDeliveryStore {
+    async fn enqueue_delivery(&self, delivery: &PendingDelivery) -> Result<(), DatabaseError> {
+        let row = DbPendingDelivery::from_delivery(delivery)?;
+        let db_path = self.db_path.clone();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            diesel::insert_into(pending_deliveries::table)
+                .values(&row)
+                .execute(&mut conn)
+                .map(|_| ())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    // Timestamps are stored as UTC RFC 3339 strings, which sort lexically.
+    async fn list_due_deliveries(
+        &self,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<PendingDelivery>, DatabaseError> {
+        let db_path = self.db_path.clone();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            let results = pending_deliveries::table
+                .filter(pending_deliveries::next_attempt_at.le(datetime_to_string(&now)))
+                .order(pending_deliveries::created_at.asc())
+                .limit(limit)
+                .select(DbPendingDelivery::as_select())
+                .load::<DbPendingDelivery>(&mut conn)
+                .map_err(|e| DatabaseError::Query(e.to_string()))?;
+
+            results.iter().map(|d| d.to_delivery()).collect()
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn reschedule_delivery(
+        &self,
+        id: &str,
+        attempts: i32,
+        next_attempt_at: DateTime<Utc>,
+        last_error: Option<&str>,
+    ) -> Result<(), DatabaseError> {
+        let id = id.to_string();
+        let last_error = last_error.map(ToOwned::to_owned);
+        let db_path = self.db_path.clone();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            diesel::update(pending_deliveries::table.filter(pending_deliveries::id.eq(&id)))
+                .set((
+                    pending_deliveries::attempts.eq(attempts),
+                    pending_deliveries::next_attempt_at.eq(datetime_to_string(&next_attempt_at)),
+                    pending_deliveries::last_error.eq(last_error),
+                ))
+                .execute(&mut conn)
+                .map(|_| ())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn delete_delivery(&self, id: &str) -> Result<(), DatabaseError> {
+        let id = id.to_string();
+        let db_path = self.db_path.clone();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            diesel::delete(pending_deliveries::table.filter(pending_deliveries::id.eq(&id)))
+                .execute(&mut conn)
+                .map(|_| ())
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
+
+    async fn count_deliveries(&self) -> Result<i64, DatabaseError> {
+        let db_path = self.db_path.clone();
+        blocking::spawn(move || {
+            let mut conn = establish_connection(&db_path)?;
+            pending_deliveries::table
+                .count()
+                .get_result::<i64>(&mut conn)
+                .map_err(|e| DatabaseError::Query(e.to_string()))
+        })
+        .await
+        .map_err(|e| DatabaseError::Query(format!("database task failed: {e}")))?
+    }
 }
@@ -1,8 +1,12 @@
 use async_trait::async_trait;
 
 use super::DatabaseError;
+use chrono::{DateTime, NaiveDate, Utc};
+
 use super::models::{
-    EmojiMapping, MessageMapping, RemoteRoomInfo, RemoteUserInfo, RoomMapping, UserMapping,
+    ApiToken, AuditLogEntry, AuditLogFilter, EmojiMapping, EmojiUsage, MessageMapping,
+    MessageMappingFilter, PendingDelivery, RemoteRoomInfo, RemoteUserInfo, RoomMapping,
+    RoomSettings, ThreadMapping, UserMapping, UserRoomSettings,
 };
 
 #[async_trait]
@@ -25,6 +29,8 @@ pub trait RoomStore: Send + Sync {
     async fn create_room_mapping(&self, mapping: &RoomMapping) -> Result<(), DatabaseError>;
     async fn update_room_mapping(&self, mapping: &RoomMapping) -> Result<(), DatabaseError>;
     async fn delete_room_mapping(&self, id: i64) -> Result<(), DatabaseError>;
+    /// Records that a message was bridged through the mapping at `at`.
+    async fn touch_room_mapping(&self, id: i64, at: DateTime<Utc>) -> Result<(), DatabaseError>;
     async fn get_rooms_by_guild(&self, guild_id: &str) -> Result<Vec<RoomMapping>, DatabaseError>;
     async fn get_remote_room_info(
         &self,
@@ -35,6 +41,23 @@ pub trait RoomStore: Send + Sync {
         matrix_room_id: &str,
         info: &RemoteRoomInfo,
     ) -> Result<(), DatabaseError>;
+    async fn get_room_settings(
+        &self,
+        matrix_room_id: &str,
+    ) -> Result<Option<RoomSettings>, DatabaseError>;
+    /// Inserts or replaces the settings row for the room.
+    async fn set_room_settings(&self, settings: &RoomSettings) -> Result<(), DatabaseError>;
+    async fn get_thread_by_discord_thread(
+        &self,
+        discord_thread_id: &str,
+    ) -> Result<Option<ThreadMapping>, DatabaseError>;
+    async fn get_thread_by_matrix_root(
+        &self,
+        matrix_root_event_id: &str,
+    ) -> Result<Option<ThreadMapping>, DatabaseError>;
+    /// Inserts or replaces the mapping for the Discord thread.
+    async fn upsert_thread_mapping(&self, mapping: &ThreadMapping) -> Result<(), DatabaseError>;
+    async fn delete_thread_mapping(&self, discord_thread_id: &str) -> Result<(), DatabaseError>;
 }
 
 #[async_trait]
@@ -60,6 +83,29 @@ pub trait UserStore: Send + Sync {
         info: &RemoteUserInfo,
     ) -> Result<(), DatabaseError>;
     async fn get_all_user_ids(&self) -> Result<Vec<String>, DatabaseError>;
+    async fn get_user_room_settings(
+        &self,
+        matrix_room_id: &str,
+        matrix_user_id: &str,
+    ) -> Result<Option<UserRoomSettings>, DatabaseError>;
+    /// Inserts or replaces the settings row for the user in the room.
+    async fn set_user_room_settings(
+        &self,
+        settings: &UserRoomSettings,
+    ) -> Result<(), DatabaseError>;
+    /// User mappings whose Matrix id, Discord id or Discord username contains
+    /// `query` (case-insensitive), oldest first. `None` lists everyone.
+    async fn search_users(
+        &self,
+        query: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<UserMapping>, DatabaseError>;
+    async fn set_presence_override(
+        &self,
+        discord_user_id: &str,
+        presence: Option<&str>,
+    ) -> Result<(), DatabaseError>;
 }
 
 #[async_trait]
@@ -78,6 +124,17 @@ pub trait MessageStore: Send + Sync {
         discord_message_id: &str,
     ) -> Result<(), DatabaseError>;
     async fn delete_by_matrix_event_id(&self, matrix_event_id: &str) -> Result<(), DatabaseError>;
+    /// Newest first.
+    async fn list_message_mappings(
+        &self,
+        filter: &MessageMappingFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<MessageMapping>, DatabaseError>;
+    async fn count_message_mappings(
+        &self,
+        filter: &MessageMappingFilter,
+    ) -> Result<i64, DatabaseError>;
 }
 
 #[async_trait]
@@ -90,4 +147,69 @@ pub trait EmojiStore: Send + Sync {
     async fn create_emoji(&self, emoji: &EmojiMapping) -> Result<(), DatabaseError>;
     async fn update_emoji(&self, emoji: &EmojiMapping) -> Result<(), DatabaseError>;
     async fn delete_emoji(&self, discord_emoji_id: &str) -> Result<(), DatabaseError>;
+    /// Adds each entry's `uses` to the counter for its day, kind and id.
+    async fn add_emoji_usage(&self, usage: &[EmojiUsage]) -> Result<(), DatabaseError>;
+    /// Every daily counter from `since` on.
+    async fn list_emoji_usage(&self, since: NaiveDate) -> Result<Vec<EmojiUsage>, DatabaseError>;
+}
+
+#[async_trait]
+pub trait AuditStore: Send + Sync {
+    async fn record_audit_entry(&self, entry: &AuditLogEntry) -> Result<(), DatabaseError>;
+    /// Newest first.
+    async fn list_audit_entries(
+        &self,
+        filter: &AuditLogFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<AuditLogEntry>, DatabaseError>;
+    async fn count_audit_entries(&self, filter: &AuditLogFilter) -> Result<i64, DatabaseError>;
+}
+
+#[async_trait]
+pub trait ApiTokenStore: Send + Sync {
+    async fn get_api_token_by_hash(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<ApiToken>, DatabaseError>;
+    async fn list_api_tokens(&self) -> Result<Vec<ApiToken>, DatabaseError>;
+    async fn count_api_tokens(&self) -> Result<i64, DatabaseError>;
+    async fn create_api_token(&self, token: &ApiToken) -> Result<(), DatabaseError>;
+    /// Returns whether a token with that name existed.
+    async fn delete_api_token(&self, name: &str) -> Result<bool, DatabaseError>;
+}
+
+#[async_trait]
+pub trait DeliveryStore: Send + Sync {
+    async fn enqueue_delivery(&self, delivery: &PendingDelivery) -> Result<(), DatabaseError>;
+    /// Deliveries whose next attempt is due, oldest first.
+    async fn list_due_deliveries(
+        &self,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<PendingDelivery>, DatabaseError>;
+    async fn reschedule_delivery(
+        &self,
+        id: &str,
+        attempts: i32,
+        next_attempt_at: DateTime<Utc>,
+        last_error: Option<&str>,
+    ) -> Result<(), DatabaseError>;
+    async fn delete_delivery(&self, id: &str) -> Result<(), DatabaseError>;
+    async fn count_deliveries(&self) -> Result<i64, DatabaseError>;
+}
+
+/// `LIKE` pattern matching `query` anywhere, with `\` as the escape
+/// character so `%` and `_` in the query match literally.
+pub(crate) fn contains_pattern(query: &str) -> String {
+    let mut pattern = String::with_capacity(query.len() + 2);
+    pattern.push('%');
+    for ch in query.chars() {
+        if matches!(ch, '\\' | '%' | '_') {
+            pattern.push('\\');
+        }
+        pattern.push(ch);
+    }
+    pattern.push('%');
+    pattern
 }
@@ -1,32 +1,85 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use secrecy::ExposeSecret;
 use serde::{Deserialize, Serialize};
 use serenity::all::{
-    ChannelId, Client as SerenityClient, Context as SerenityContext, CreateAttachment,
-    CreateMessage, EventHandler as SerenityEventHandler, ExecuteWebhook, GatewayIntents, GuildId,
-    Http, Message as SerenityMessage, MessageId, MessageUpdateEvent, OnlineStatus,
-    PermissionOverwrite, PermissionOverwriteType, Permissions, Presence, Ready, TypingStartEvent,
-    UserId, Webhook, WebhookType,
+    ApplicationFlags, ButtonStyle, ChannelId, ChannelType, Client as SerenityClient,
+    Context as SerenityContext, CreateActionRow, CreateAttachment, CreateButton,
+    CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage, CreateThread,
+    EditMessage, Emoji, EmojiId, Event, EventHandler as SerenityEventHandler, ExecuteWebhook,
+    GatewayIntents, GetMessages, GuildId, Http, Interaction, Message as SerenityMessage, MessageId,
+    MessageInteraction, MessageInteractionMetadata, MessageReferenceKind, MessageUpdateEvent,
+    ModelError, Nonce, OnlineStatus, PermissionOverwrite, PermissionOverwriteType, Permissions,
+    Presence, RatelimitInfo, RawEventHandler, Reaction, ReactionType, Ready, RoleId,
+    TypingStartEvent, UserId, UserPagination, Webhook, WebhookType,
 };
+use serenity::gateway::{ConnectionStage, ShardStageUpdateEvent};
+use serenity::model::guild::audit_log::{Action as AuditAction, MemberAction, MessageAction};
+use serenity::model::guild::automod::{Action as AutoModAction, ActionExecution, TriggerType};
 use tokio::sync::{Mutex as AsyncMutex, RwLock, oneshot};
 use tracing::{debug, error, info, warn};
 
+use crate::bridge::loop_guard::looks_like_matrix_relay;
+use crate::bridge::modlog::{ModlogAction, ModlogEntry};
 use crate::bridge::presence_handler::{DiscordActivity, DiscordPresence, DiscordPresenceState};
-use crate::bridge::{BridgeCore, DiscordMessageContext};
+use crate::bridge::provisioning::parse_approval_button;
+use crate::bridge::relay_unwrap::relayed_sender_id;
+use crate::bridge::{BridgeCore, DiscordMessageContext, DiscordReference, DiscordSticker};
+use crate::cache::{
+    ChannelSnapshot, DiscordMetadataCache, GuildSnapshot, MemberSnapshot, RoleSnapshot,
+    UserSnapshot,
+};
 use crate::config::Config;
+use crate::emoji::GuildEmoji;
+use crate::utils::dry_run;
+use crate::utils::event_log::{self, EventSource};
+use crate::utils::snowflake;
+use crate::utils::{ChaosInjector, ChaosTarget};
+use crate::web::Metrics;
 
 const INITIAL_LOGIN_RETRY_SECONDS: u64 = 2;
 const MAX_LOGIN_RETRY_SECONDS: u64 = 300;
+/// The most bans Discord returns per page.
+const MAX_BANS_PER_PAGE: u8 = 250;
+/// Audit log entries read when attributing a moderation event.
+const AUDIT_LOG_LOOKUP_LIMIT: u8 = 10;
+/// How old an audit log entry may be and still explain a gateway event.
+const AUDIT_LOG_LOOKBACK_SECONDS: i64 = 300;
+/// Recent messages searched for a send that failed with an unknown outcome.
+const SENT_MESSAGE_LOOKBACK: u8 = 20;
+/// Allowed drift between our clock and Discord's when matching a sent message.
+const SENT_MESSAGE_CLOCK_SKEW: chrono::Duration = chrono::Duration::seconds(30);
+/// The most messages Discord returns per page of channel history.
+const MAX_MESSAGES_PER_PAGE: u8 = 100;
 
 pub mod command_handler;
 pub mod embed;
+pub mod preflight;
+pub mod rate_limits;
 
+use self::command_handler::can_answer_approval;
 pub use self::command_handler::{DiscordCommandHandler, DiscordCommandOutcome, ModerationAction};
 pub use self::embed::{
     DiscordEmbed, EmbedAuthor, EmbedFooter, build_matrix_message_embed, build_reply_embed,
 };
+pub use self::preflight::PreflightError;
+pub use self::rate_limits::RouteHeadroom;
+
+/// There is no HTTP client yet because the gateway has not logged in (or is
+/// logging in again). Deliveries that fail with it are queued for retry.
+#[derive(Debug, thiserror::Error)]
+#[error("discord http client not available")]
+pub struct DiscordNotReady;
+
+impl DiscordNotReady {
+    pub fn is_cause_of(err: &anyhow::Error) -> bool {
+        err.chain().any(|cause| cause.is::<Self>())
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DiscordUser {
@@ -36,12 +89,27 @@ pub struct DiscordUser {
     pub avatar: Option<String>,
 }
 
+/// A guild member as listed for member syncs.
+#[derive(Debug, Clone)]
+pub struct DiscordMember {
+    pub user_id: String,
+    pub display_name: String,
+    pub avatar_url: Option<String>,
+    pub roles: Vec<String>,
+    pub bot: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DiscordChannel {
     pub id: String,
     pub name: String,
     pub guild_id: String,
     pub topic: Option<String>,
+    pub slowmode_seconds: u16,
+    /// The channel this thread belongs to, for threads.
+    pub thread_parent_id: Option<String>,
+    /// Whether @everyone can see the channel.
+    pub everyone_can_view: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -56,6 +124,105 @@ pub struct DiscordMessage {
     pub timestamp: String,
 }
 
+/// Audit log actions looked up to attribute moderation events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditedAction {
+    MessageDelete,
+    BanAdd,
+    BanRemove,
+}
+
+impl AuditedAction {
+    fn audit_action(self) -> AuditAction {
+        match self {
+            Self::MessageDelete => AuditAction::Message(MessageAction::Delete),
+            Self::BanAdd => AuditAction::Member(MemberAction::BanAdd),
+            Self::BanRemove => AuditAction::Member(MemberAction::BanRemove),
+        }
+    }
+}
+
+/// Gateway intents Discord only sends once enabled for the application in
+/// the developer portal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrivilegedIntent {
+    GuildMembers,
+    GuildPresences,
+    MessageContent,
+}
+
+impl PrivilegedIntent {
+    pub const ALL: [Self; 3] = [
+        Self::GuildMembers,
+        Self::GuildPresences,
+        Self::MessageContent,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::GuildMembers => "GUILD_MEMBERS",
+            Self::GuildPresences => "GUILD_PRESENCES",
+            Self::MessageContent => "MESSAGE_CONTENT",
+        }
+    }
+
+    /// What the bridge cannot do without the intent.
+    pub fn disables(self) -> &'static str {
+        match self {
+            Self::GuildMembers => "member sync and guild member join/leave handling",
+            Self::GuildPresences => "presence bridging",
+            Self::MessageContent => "the text of Discord messages that do not mention the bot",
+        }
+    }
+
+    fn gateway_intent(self) -> GatewayIntents {
+        match self {
+            Self::GuildMembers => GatewayIntents::GUILD_MEMBERS,
+            Self::GuildPresences => GatewayIntents::GUILD_PRESENCES,
+            Self::MessageContent => GatewayIntents::MESSAGE_CONTENT,
+        }
+    }
+
+    fn granted_by(self, flags: ApplicationFlags) -> bool {
+        flags.intersects(match self {
+            Self::GuildMembers => {
+                ApplicationFlags::GATEWAY_GUILD_MEMBERS
+                    | ApplicationFlags::GATEWAY_GUILD_MEMBERS_LIMITED
+            }
+            Self::GuildPresences => {
+                ApplicationFlags::GATEWAY_PRESENCE | ApplicationFlags::GATEWAY_PRESENCE_LIMITED
+            }
+            Self::MessageContent => {
+                ApplicationFlags::GATEWAY_MESSAGE_CONTENT
+                    | ApplicationFlags::GATEWAY_MESSAGE_CONTENT_LIMITED
+            }
+        })
+    }
+}
+
+/// The intents to identify with: every non-privileged one plus the
+/// privileged ones `flags` grant. Without flags all are requested, as the
+/// gateway will then tell.
+fn granted_intents(flags: Option<ApplicationFlags>) -> GatewayIntents {
+    let Some(flags) = flags else {
+        return GatewayIntents::all();
+    };
+    PrivilegedIntent::ALL
+        .into_iter()
+        .filter(|intent| intent.granted_by(flags))
+        .fold(GatewayIntents::non_privileged(), |intents, intent| {
+            intents | intent.gateway_intent()
+        })
+}
+
+/// Who a Discord audit log entry says acted, on whom, and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditAttribution {
+    pub actor_id: String,
+    pub target_id: Option<String>,
+    pub reason: Option<String>,
+}
+
 #[derive(Clone)]
 pub struct DiscordClient {
     _config: Arc<Config>,
@@ -65,6 +232,11 @@ pub struct DiscordClient {
     http: Arc<RwLock<Option<Arc<Http>>>>,
     webhook_cache: Arc<RwLock<std::collections::HashMap<String, WebhookInfo>>>,
     our_webhook_ids: Arc<RwLock<std::collections::HashSet<u64>>>,
+    metadata: Arc<DiscordMetadataCache>,
+    chaos: Arc<ChaosInjector>,
+    gateway_down_since: GatewayDownSince,
+    connected_guilds: ConnectedGuilds,
+    missing_intents: Arc<parking_lot::Mutex<Vec<PrivilegedIntent>>>,
 }
 
 #[derive(Default)]
@@ -77,6 +249,9 @@ struct DiscordLoginState {
 struct WebhookInfo {
     id: u64,
     url: String,
+    /// The thread to post in, when sending to a thread through its parent
+    /// channel's webhook.
+    thread_id: Option<ChannelId>,
 }
 
 struct ReadySignalHandler {
@@ -84,6 +259,29 @@ struct ReadySignalHandler {
     bridge: Arc<RwLock<Option<Arc<BridgeCore>>>>,
     http_sender: Arc<tokio::sync::Mutex<Option<oneshot::Sender<Arc<Http>>>>>,
     our_webhook_ids: Arc<RwLock<std::collections::HashSet<u64>>>,
+    metadata: Arc<DiscordMetadataCache>,
+    dry_run: bool,
+    gateway_down_since: GatewayDownSince,
+    connected_guilds: ConnectedGuilds,
+}
+
+/// When the gateway last lost its connection; `None` while connected.
+type GatewayDownSince = Arc<parking_lot::Mutex<Option<Instant>>>;
+
+/// Guilds the gateway delivered and that are currently available.
+type ConnectedGuilds = Arc<parking_lot::Mutex<std::collections::HashSet<u64>>>;
+
+/// Keeps raw gateway events for `/admin/debug/events`.
+struct DebugEventRecorder;
+
+#[serenity::async_trait]
+impl RawEventHandler for DebugEventRecorder {
+    async fn raw_event(&self, _ctx: SerenityContext, event: Event) {
+        event_log::record(EventSource::Discord, || {
+            serde_json::to_value(&event)
+                .unwrap_or_else(|err| serde_json::json!({ "error": err.to_string() }))
+        });
+    }
 }
 
 #[serenity::async_trait]
@@ -93,6 +291,14 @@ impl SerenityEventHandler for ReadySignalHandler {
             "discord gateway ready as {} ({})",
             ready.user.name, ready.user.id
         );
+        *self.gateway_down_since.lock() = None;
+        // Guilds left while disconnected are missing from the ready payload;
+        // the rest come back through guild_create.
+        let ready_guilds: std::collections::HashSet<u64> =
+            ready.guilds.iter().map(|guild| guild.id.get()).collect();
+        self.connected_guilds
+            .lock()
+            .retain(|id| ready_guilds.contains(id));
         if let Some(sender) = self.ready_sender.lock().await.take() {
             let _ = sender.send(());
         }
@@ -101,11 +307,122 @@ impl SerenityEventHandler for ReadySignalHandler {
         }
     }
 
-    async fn message(&self, ctx: SerenityContext, msg: SerenityMessage) {
-        if msg.author.bot {
+    async fn shard_stage_update(&self, _ctx: SerenityContext, event: ShardStageUpdateEvent) {
+        let mut down_since = self.gateway_down_since.lock();
+        if event.new == ConnectionStage::Connected {
+            *down_since = None;
+        } else if down_since.is_none() {
+            *down_since = Some(Instant::now());
+        }
+    }
+
+    async fn guild_create(
+        &self,
+        _ctx: SerenityContext,
+        guild: serenity::model::guild::Guild,
+        _is_new: Option<bool>,
+    ) {
+        self.connected_guilds.lock().insert(guild.id.get());
+        self.metadata
+            .upsert_guild(GuildSnapshot {
+                id: guild.id.to_string(),
+                name: guild.name.clone(),
+                icon_url: guild.icon_url(),
+            })
+            .await;
+        for channel in guild.channels.values().chain(&guild.threads) {
+            self.metadata
+                .upsert_channel(channel_snapshot(channel))
+                .await;
+        }
+        for member in guild.members.values() {
+            self.metadata.upsert_user(user_snapshot(&member.user)).await;
+            self.metadata.upsert_member(member_snapshot(member)).await;
+        }
+        for role in guild.roles.values() {
+            self.metadata.upsert_role(role_snapshot(role)).await;
+        }
+
+        let bridge = self.bridge.read().await.clone();
+        let Some(bridge) = bridge else {
+            return;
+        };
+        // Guild presences carry partial users, so fall back to the member list
+        // for the bot flag and username.
+        let presences: Vec<_> = guild
+            .presences
+            .iter()
+            .filter_map(|(user_id, presence)| {
+                let member = guild.members.get(user_id);
+                let is_bot = presence
+                    .user
+                    .bot
+                    .or_else(|| member.map(|member| member.user.bot))
+                    .unwrap_or(false);
+                if is_bot {
+                    return None;
+                }
+                let mut presence = discord_presence(presence);
+                if presence.username.is_none() {
+                    presence.username = member.map(|member| member.user.name.clone());
+                }
+                Some(presence)
+            })
+            .collect();
+        if let Err(err) = bridge
+            .bootstrap_guild_presences(&guild.id.to_string(), presences)
+            .await
+        {
+            warn!(
+                "failed to queue initial presences for guild {}: {}",
+                guild.id, err
+            );
+        }
+
+        if let Err(err) = bridge
+            .sync_guild_bridge_info(&guild.id.to_string(), &guild.name)
+            .await
+        {
+            warn!("failed to sync bridge info for guild {}: {}", guild.id, err);
+        }
+
+        let emojis = guild.emojis.values().map(guild_emoji).collect();
+        if let Err(err) = bridge
+            .sync_guild_emoji_pack(&guild.id.to_string(), &guild.name, emojis)
+            .await
+        {
+            warn!("failed to sync emoji pack for guild {}: {}", guild.id, err);
+        }
+    }
+
+    async fn guild_emojis_update(
+        &self,
+        _ctx: SerenityContext,
+        guild_id: GuildId,
+        current_state: HashMap<EmojiId, Emoji>,
+    ) {
+        let bridge = self.bridge.read().await.clone();
+        let Some(bridge) = bridge else {
             return;
+        };
+
+        let guild_id = guild_id.to_string();
+        let guild_name = self
+            .metadata
+            .guild(&guild_id)
+            .await
+            .map(|guild| guild.name)
+            .unwrap_or_else(|| guild_id.clone());
+        let emojis = current_state.values().map(guild_emoji).collect();
+        if let Err(err) = bridge
+            .sync_guild_emoji_pack(&guild_id, &guild_name, emojis)
+            .await
+        {
+            error!("failed to handle discord emoji update: {err}");
         }
+    }
 
+    async fn message(&self, ctx: SerenityContext, msg: SerenityMessage) {
         if let Some(webhook_id) = msg.webhook_id {
             let our_ids = self.our_webhook_ids.read().await;
             if our_ids.contains(&webhook_id.get()) {
@@ -115,6 +432,46 @@ impl SerenityEventHandler for ReadySignalHandler {
                 );
                 return;
             }
+            if looks_like_matrix_relay(&msg.author.name) {
+                debug!(
+                    "ignoring discord message relayed from matrix by another bridge webhook_id={} message_id={} name={}",
+                    webhook_id, msg.id, msg.author.name
+                );
+                return;
+            }
+        }
+
+        let relayed = if msg.author.bot {
+            let bridge = self.bridge.read().await.clone();
+            let relayed = bridge
+                .as_ref()
+                .and_then(|bridge| bridge.unwrap_relayed(&msg.author.id.to_string(), &msg.content));
+            if relayed.is_none()
+                && !(bridge.is_some_and(|bridge| bridge.bridges_bot_messages())
+                    && msg.author.id != ctx.cache.current_user().id)
+            {
+                return;
+            }
+            relayed
+        } else {
+            None
+        };
+        #[allow(deprecated)]
+        let attributed_to = command_attribution(
+            &msg.author,
+            msg.interaction.as_deref(),
+            msg.interaction_metadata.as_deref(),
+        );
+
+        if relayed.is_none() {
+            self.metadata.upsert_user(user_snapshot(&msg.author)).await;
+        }
+        if relayed.is_none()
+            && let (Some(guild_id), Some(member)) = (msg.guild_id, msg.member.as_ref())
+        {
+            self.metadata
+                .upsert_member(partial_member_snapshot(guild_id, &msg.author, member))
+                .await;
         }
 
         let bridge = self.bridge.read().await.clone();
@@ -132,17 +489,42 @@ impl SerenityEventHandler for ReadySignalHandler {
             .unwrap_or_else(Permissions::empty);
 
         let permissions = permissions_to_names(permission_flags);
+        let thread_parent_id = self
+            .metadata
+            .channel(&msg.channel_id.to_string())
+            .await
+            .and_then(|channel| channel.thread_parent_id);
 
         if let Err(err) = bridge
             .handle_discord_message_with_context(DiscordMessageContext {
                 channel_id: msg.channel_id.to_string(),
                 source_message_id: Some(msg.id.to_string()),
-                sender_id: msg.author.id.to_string(),
-                content: msg.content.clone(),
+                sender_id: match &relayed {
+                    Some(relayed) => relayed_sender_id(&msg.author.id.to_string(), &relayed.name),
+                    None => msg.author.id.to_string(),
+                },
+                content: relayed
+                    .as_ref()
+                    .map_or_else(|| msg.content.clone(), |relayed| relayed.text.clone()),
                 attachments,
                 reply_to,
                 edit_of: None,
                 permissions,
+                sent_at: chrono::DateTime::from_timestamp_millis(
+                    (msg.timestamp.unix_timestamp_nanos() / 1_000_000) as i64,
+                ),
+                relayed_name: relayed.map(|relayed| relayed.name),
+                attributed_to,
+                stickers: msg
+                    .sticker_items
+                    .iter()
+                    .map(|sticker| DiscordSticker {
+                        id: sticker.id.to_string(),
+                        name: sticker.name.clone(),
+                    })
+                    .collect(),
+                reference: message_reference(&msg),
+                thread_parent_id,
             })
             .await
         {
@@ -150,51 +532,107 @@ impl SerenityEventHandler for ReadySignalHandler {
         }
     }
 
-    async fn presence_update(&self, _ctx: SerenityContext, new_data: Presence) {
+    async fn interaction_create(&self, ctx: SerenityContext, interaction: Interaction) {
+        let Interaction::Component(component) = interaction else {
+            return;
+        };
+        let Some((channel_id, allow)) = parse_approval_button(&component.data.custom_id) else {
+            return;
+        };
+
         let bridge = self.bridge.read().await.clone();
         let Some(bridge) = bridge else {
             return;
         };
 
-        if new_data.user.bot.unwrap_or(false) {
+        let can_manage_guild = component
+            .member
+            .as_ref()
+            .and_then(|member| member.permissions)
+            .is_some_and(|permissions| can_answer_approval(&permissions_to_names(permissions)));
+
+        let reply = match bridge
+            .handle_bridge_approval_interaction(
+                &channel_id,
+                &component.user.id.to_string(),
+                allow,
+                can_manage_guild,
+            )
+            .await
+        {
+            Ok(reply) => reply,
+            Err(err) => {
+                error!("failed to handle bridge approval interaction: {err}");
+                Some("Something went wrong while recording your response.".to_string())
+            }
+        };
+
+        // The prompt itself is edited once the approval resolves, so an
+        // accepted click only needs acknowledging.
+        let response = match reply {
+            Some(reply) => CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(reply)
+                    .ephemeral(true),
+            ),
+            None => CreateInteractionResponse::Acknowledge,
+        };
+        if dry_run::skip(
+            self.dry_run,
+            ChaosTarget::Discord,
+            "interaction_response",
+            &channel_id,
+        ) {
             return;
         }
+        if let Err(err) = component.create_response(&ctx.http, response).await {
+            warn!("failed to respond to bridge approval interaction: {err}");
+        }
+    }
 
-        let state = match new_data.status {
-            OnlineStatus::Online => DiscordPresenceState::Online,
-            OnlineStatus::DoNotDisturb => DiscordPresenceState::Dnd,
-            OnlineStatus::Idle => DiscordPresenceState::Idle,
-            OnlineStatus::Offline | OnlineStatus::Invisible => DiscordPresenceState::Offline,
-            _ => DiscordPresenceState::Offline,
+    async fn presence_update(&self, _ctx: SerenityContext, new_data: Presence) {
+        let bridge = self.bridge.read().await.clone();
+        let Some(bridge) = bridge else {
+            return;
         };
 
-        let activities = new_data
-            .activities
-            .iter()
-            .map(|activity| DiscordActivity {
-                kind: format!("{:?}", activity.kind),
-                name: activity.name.clone(),
-                url: activity.url.as_ref().map(ToString::to_string),
-            })
-            .collect();
+        if new_data.user.bot.unwrap_or(false) {
+            return;
+        }
 
-        bridge.enqueue_discord_presence(DiscordPresence {
-            user_id: new_data.user.id.to_string(),
-            username: new_data.user.name.clone(),
-            state,
-            activities,
-        });
+        bridge.enqueue_discord_presence(discord_presence(&new_data));
     }
 
     async fn message_update(
         &self,
-        _ctx: SerenityContext,
+        ctx: SerenityContext,
         _old_if_available: Option<SerenityMessage>,
         _new_if_available: Option<SerenityMessage>,
         update: MessageUpdateEvent,
     ) {
-        if update.author.as_ref().is_some_and(|author| author.bot) {
-            return;
+        // Deferred slash command output arrives as an edit of the bot's
+        // "thinking" message.
+        #[allow(deprecated)]
+        let attributed_to = update.author.as_ref().and_then(|author| {
+            command_attribution(
+                author,
+                update.interaction.clone().flatten().as_deref(),
+                update.interaction_metadata.clone().flatten().as_deref(),
+            )
+        });
+        if let Some(author) = &update.author
+            && author.bot
+        {
+            let bridges_bots = self
+                .bridge
+                .read()
+                .await
+                .as_ref()
+                .is_some_and(|bridge| bridge.bridges_bot_messages());
+            if attributed_to.is_none() || !bridges_bots || author.id == ctx.cache.current_user().id
+            {
+                return;
+            }
         }
 
         let Some(content) = update.content.clone() else {
@@ -225,6 +663,12 @@ impl SerenityEventHandler for ReadySignalHandler {
                 reply_to: None,
                 edit_of: Some(update.id.to_string()),
                 permissions: std::collections::HashSet::new(),
+                sent_at: None,
+                relayed_name: None,
+                attributed_to,
+                stickers: Vec::new(),
+                reference: None,
+                thread_parent_id: None,
             })
             .await
         {
@@ -252,6 +696,91 @@ impl SerenityEventHandler for ReadySignalHandler {
         }
     }
 
+    async fn reaction_add(&self, ctx: SerenityContext, reaction: Reaction) {
+        // The bot's own reactions are the ones it mirrors from Matrix.
+        let Some(user_id) = reaction.user_id else {
+            return;
+        };
+        if user_id == ctx.cache.current_user().id {
+            return;
+        }
+        let (emoji, custom_emoji_id) = match &reaction.emoji {
+            ReactionType::Unicode(emoji) => (emoji.clone(), None),
+            ReactionType::Custom { id, name, .. } => {
+                (name.clone().unwrap_or_default(), Some(id.to_string()))
+            }
+            _ => return,
+        };
+        let bridge = self.bridge.read().await.clone();
+        let Some(bridge) = bridge else {
+            return;
+        };
+
+        if let Err(err) = bridge
+            .handle_discord_reaction(
+                &reaction.channel_id.to_string(),
+                &reaction.message_id.to_string(),
+                &user_id.to_string(),
+                &emoji,
+                custom_emoji_id.as_deref(),
+            )
+            .await
+        {
+            error!("failed to handle discord reaction: {err}");
+        }
+    }
+
+    async fn guild_ban_addition(
+        &self,
+        _ctx: SerenityContext,
+        guild_id: GuildId,
+        banned_user: serenity::model::user::User,
+    ) {
+        let bridge = self.bridge.read().await.clone();
+        let Some(bridge) = bridge else {
+            return;
+        };
+
+        if let Err(err) = bridge
+            .handle_discord_guild_ban(&guild_id.to_string(), &banned_user.id.to_string(), true)
+            .await
+        {
+            error!("failed to handle discord ban: {err}");
+        }
+    }
+
+    async fn guild_ban_removal(
+        &self,
+        _ctx: SerenityContext,
+        guild_id: GuildId,
+        unbanned_user: serenity::model::user::User,
+    ) {
+        let bridge = self.bridge.read().await.clone();
+        let Some(bridge) = bridge else {
+            return;
+        };
+
+        if let Err(err) = bridge
+            .handle_discord_guild_ban(&guild_id.to_string(), &unbanned_user.id.to_string(), false)
+            .await
+        {
+            error!("failed to handle discord unban: {err}");
+        }
+    }
+
+    async fn auto_moderation_action_execution(
+        &self,
+        _ctx: SerenityContext,
+        execution: ActionExecution,
+    ) {
+        let bridge = self.bridge.read().await.clone();
+        let Some(bridge) = bridge else {
+            return;
+        };
+
+        bridge.post_modlog(automod_modlog_entry(&execution)).await;
+    }
+
     async fn message_delete_bulk(
         &self,
         _ctx: SerenityContext,
@@ -303,16 +832,19 @@ impl SerenityEventHandler for ReadySignalHandler {
     async fn guild_member_update(
         &self,
         _ctx: SerenityContext,
-        _old: Option<serenity::model::guild::Member>,
+        old: Option<serenity::model::guild::Member>,
         new: Option<serenity::model::guild::Member>,
         _event: serenity::model::event::GuildMemberUpdateEvent,
     ) {
-        let bridge = self.bridge.read().await.clone();
-        let Some(bridge) = bridge else {
+        let Some(new) = new else {
             return;
         };
 
-        let Some(new) = new else {
+        self.metadata.upsert_user(user_snapshot(&new.user)).await;
+        self.metadata.upsert_member(member_snapshot(&new)).await;
+
+        let bridge = self.bridge.read().await.clone();
+        let Some(bridge) = bridge else {
             return;
         };
 
@@ -340,6 +872,24 @@ impl SerenityEventHandler for ReadySignalHandler {
         {
             error!("failed to handle discord guild member update: {err}");
         }
+
+        // Without the cached member there is nothing to compare against.
+        if let Some(old) = old {
+            let old_name = old.nick.as_ref().unwrap_or(&old.user.name);
+            let old_avatar = old.avatar_url().or_else(|| old.user.avatar_url());
+            if let Err(err) = bridge
+                .announce_discord_member_change(
+                    &new.guild_id.to_string(),
+                    &new.user.id.to_string(),
+                    old_name,
+                    display_name,
+                    old_avatar != avatar_url,
+                )
+                .await
+            {
+                error!("failed to announce discord member change: {err}");
+            }
+        }
     }
 
     async fn typing_start(&self, _ctx: SerenityContext, event: TypingStartEvent) {
@@ -356,23 +906,54 @@ impl SerenityEventHandler for ReadySignalHandler {
         }
     }
 
+    async fn channel_create(
+        &self,
+        _ctx: SerenityContext,
+        channel: serenity::model::channel::GuildChannel,
+    ) {
+        self.metadata
+            .upsert_channel(channel_snapshot(&channel))
+            .await;
+    }
+
     async fn channel_update(
         &self,
         _ctx: SerenityContext,
         _old: Option<serenity::model::channel::GuildChannel>,
         new: serenity::model::channel::GuildChannel,
     ) {
+        let previous_slowmode = self
+            .metadata
+            .channel(&new.id.to_string())
+            .await
+            .map(|snapshot| snapshot.slowmode_seconds);
+        let snapshot = channel_snapshot(&new);
+        let slowmode_seconds = snapshot.slowmode_seconds;
+        self.metadata.upsert_channel(snapshot).await;
+
         let bridge = self.bridge.read().await.clone();
         let Some(bridge) = bridge else {
             return;
         };
 
         if let Err(err) = bridge
-            .handle_discord_channel_update(&new.id.to_string(), &new.name, new.topic.as_deref())
+            .handle_discord_channel_update(
+                &new.id.to_string(),
+                &new.name,
+                new.topic.as_deref(),
+                slowmode_seconds,
+            )
             .await
         {
             error!("failed to handle discord channel update: {err}");
         }
+        if previous_slowmode.is_some_and(|previous| previous != slowmode_seconds)
+            && let Err(err) = bridge
+                .handle_discord_slowmode_change(&new.id.to_string(), slowmode_seconds)
+                .await
+        {
+            error!("failed to announce discord slowmode change: {err}");
+        }
     }
 
     async fn channel_delete(
@@ -381,6 +962,8 @@ impl SerenityEventHandler for ReadySignalHandler {
         channel: serenity::model::channel::GuildChannel,
         _messages: Option<Vec<SerenityMessage>>,
     ) {
+        self.metadata.remove_channel(&channel.id.to_string()).await;
+
         let bridge = self.bridge.read().await.clone();
         let Some(bridge) = bridge else {
             return;
@@ -394,19 +977,77 @@ impl SerenityEventHandler for ReadySignalHandler {
         }
     }
 
-    async fn guild_update(
+    async fn thread_create(
         &self,
         _ctx: SerenityContext,
-        _old: Option<serenity::model::guild::Guild>,
-        new: serenity::model::guild::PartialGuild,
+        thread: serenity::model::channel::GuildChannel,
+    ) {
+        self.metadata
+            .upsert_channel(channel_snapshot(&thread))
+            .await;
+    }
+
+    async fn thread_update(
+        &self,
+        _ctx: SerenityContext,
+        _old: Option<serenity::model::channel::GuildChannel>,
+        new: serenity::model::channel::GuildChannel,
+    ) {
+        self.metadata.upsert_channel(channel_snapshot(&new)).await;
+    }
+
+    async fn thread_list_sync(
+        &self,
+        _ctx: SerenityContext,
+        thread_list_sync: serenity::model::event::ThreadListSyncEvent,
     ) {
+        for thread in &thread_list_sync.threads {
+            self.metadata.upsert_channel(channel_snapshot(thread)).await;
+        }
+    }
+
+    async fn thread_delete(
+        &self,
+        _ctx: SerenityContext,
+        thread: serenity::model::channel::PartialGuildChannel,
+        _full_thread_data: Option<serenity::model::channel::GuildChannel>,
+    ) {
+        self.metadata.remove_channel(&thread.id.to_string()).await;
+
         let bridge = self.bridge.read().await.clone();
         let Some(bridge) = bridge else {
             return;
         };
 
         if let Err(err) = bridge
-            .handle_discord_guild_update(&new.id.to_string(), &new.name, new.icon_url().as_deref())
+            .handle_discord_thread_delete(&thread.id.to_string())
+            .await
+        {
+            error!("failed to handle discord thread delete: {err}");
+        }
+    }
+
+    async fn guild_update(
+        &self,
+        _ctx: SerenityContext,
+        _old: Option<serenity::model::guild::Guild>,
+        new: serenity::model::guild::PartialGuild,
+    ) {
+        self.metadata
+            .upsert_guild(GuildSnapshot {
+                id: new.id.to_string(),
+                name: new.name.clone(),
+                icon_url: new.icon_url(),
+            })
+            .await;
+
+        let bridge = self.bridge.read().await.clone();
+        let Some(bridge) = bridge else {
+            return;
+        };
+
+        if let Err(err) = bridge
+            .handle_discord_guild_update(&new.id.to_string(), &new.name, new.icon_url().as_deref())
             .await
         {
             error!("failed to handle discord guild update: {err}");
@@ -419,6 +1060,13 @@ impl SerenityEventHandler for ReadySignalHandler {
         incomplete: serenity::model::guild::UnavailableGuild,
         _full: Option<serenity::model::guild::Guild>,
     ) {
+        self.connected_guilds.lock().remove(&incomplete.id.get());
+        // An unavailable guild is an outage rather than a removal, so keep its
+        // snapshots around until they expire.
+        if !incomplete.unavailable {
+            self.metadata.remove_guild(&incomplete.id.to_string()).await;
+        }
+
         let bridge = self.bridge.read().await.clone();
         let Some(bridge) = bridge else {
             return;
@@ -432,6 +1080,31 @@ impl SerenityEventHandler for ReadySignalHandler {
         }
     }
 
+    async fn guild_role_create(&self, _ctx: SerenityContext, new: serenity::model::guild::Role) {
+        self.metadata.upsert_role(role_snapshot(&new)).await;
+    }
+
+    async fn guild_role_update(
+        &self,
+        _ctx: SerenityContext,
+        _old: Option<serenity::model::guild::Role>,
+        new: serenity::model::guild::Role,
+    ) {
+        self.metadata.upsert_role(role_snapshot(&new)).await;
+    }
+
+    async fn guild_role_delete(
+        &self,
+        _ctx: SerenityContext,
+        _guild_id: GuildId,
+        removed_role_id: serenity::model::id::RoleId,
+        _removed_role: Option<serenity::model::guild::Role>,
+    ) {
+        self.metadata
+            .remove_role(&removed_role_id.to_string())
+            .await;
+    }
+
     async fn guild_member_addition(
         &self,
         _ctx: SerenityContext,
@@ -441,6 +1114,9 @@ impl SerenityEventHandler for ReadySignalHandler {
             return;
         }
 
+        self.metadata.upsert_user(user_snapshot(&member.user)).await;
+        self.metadata.upsert_member(member_snapshot(&member)).await;
+
         let bridge = self.bridge.read().await.clone();
         let Some(bridge) = bridge else {
             return;
@@ -479,6 +1155,10 @@ impl SerenityEventHandler for ReadySignalHandler {
             return;
         }
 
+        self.metadata
+            .remove_member(&guild_id.to_string(), &user.id.to_string())
+            .await;
+
         let bridge = self.bridge.read().await.clone();
         let Some(bridge) = bridge else {
             return;
@@ -491,6 +1171,74 @@ impl SerenityEventHandler for ReadySignalHandler {
             error!("failed to handle discord guild member removal: {err}");
         }
     }
+
+    async fn ratelimit(&self, data: RatelimitInfo) {
+        let route = rate_limits::route_template(&data.path);
+        warn!(
+            "discord rate limit hit on {} {} (global={}), waiting {}ms",
+            data.method.reqwest_method(),
+            route,
+            data.global,
+            data.timeout.as_millis()
+        );
+        Metrics::discord_rate_limit_wait(route);
+    }
+}
+
+/// The context of a message read back from channel history rather than
+/// received over the gateway, which carries no cached permissions.
+fn history_message_context(msg: &SerenityMessage) -> DiscordMessageContext {
+    DiscordMessageContext {
+        channel_id: msg.channel_id.to_string(),
+        source_message_id: Some(msg.id.to_string()),
+        sender_id: msg.author.id.to_string(),
+        content: msg.content.clone(),
+        attachments: msg.attachments.iter().map(|a| a.url.clone()).collect(),
+        reply_to: msg.referenced_message.as_ref().map(|m| m.id.to_string()),
+        edit_of: None,
+        permissions: permissions_to_names(
+            msg.member
+                .as_ref()
+                .and_then(|member| member.permissions)
+                .unwrap_or_else(Permissions::empty),
+        ),
+        sent_at: chrono::DateTime::from_timestamp_millis(
+            (msg.timestamp.unix_timestamp_nanos() / 1_000_000) as i64,
+        ),
+        relayed_name: None,
+        attributed_to: None,
+        stickers: msg
+            .sticker_items
+            .iter()
+            .map(|sticker| DiscordSticker {
+                id: sticker.id.to_string(),
+                name: sticker.name.clone(),
+            })
+            .collect(),
+        reference: message_reference(msg),
+        thread_parent_id: None,
+    }
+}
+
+/// Where a reply or forward points, from the message's reference.
+fn message_reference(msg: &SerenityMessage) -> Option<DiscordReference> {
+    let reference = msg.message_reference.as_ref()?;
+    let forwarded = reference.kind == MessageReferenceKind::Forward;
+    let content = if forwarded {
+        msg.message_snapshots
+            .first()
+            .map(|snapshot| snapshot.content.clone())
+    } else {
+        msg.referenced_message
+            .as_ref()
+            .map(|referenced| referenced.content.clone())
+    };
+    Some(DiscordReference {
+        channel_id: reference.channel_id.to_string(),
+        message_id: reference.message_id.map(|id| id.to_string()),
+        forwarded,
+        content: content.unwrap_or_default(),
+    })
 }
 
 fn permissions_to_names(perms: Permissions) -> std::collections::HashSet<String> {
@@ -498,10 +1246,15 @@ fn permissions_to_names(perms: Permissions) -> std::collections::HashSet<String>
     // Discord's ADMINISTRATOR bit bypasses channel-level checks, so treat it
     // as granting the command permissions this bridge requires.
     if perms.contains(Permissions::ADMINISTRATOR) {
+        names.insert("MANAGE_GUILD".to_string());
         names.insert("MANAGE_WEBHOOKS".to_string());
         names.insert("MANAGE_CHANNELS".to_string());
         names.insert("BAN_MEMBERS".to_string());
         names.insert("KICK_MEMBERS".to_string());
+        names.insert("MENTION_EVERYONE".to_string());
+    }
+    if perms.contains(Permissions::MANAGE_GUILD) {
+        names.insert("MANAGE_GUILD".to_string());
     }
     if perms.contains(Permissions::MANAGE_WEBHOOKS) {
         names.insert("MANAGE_WEBHOOKS".to_string());
@@ -515,9 +1268,239 @@ fn permissions_to_names(perms: Permissions) -> std::collections::HashSet<String>
     if perms.contains(Permissions::KICK_MEMBERS) {
         names.insert("KICK_MEMBERS".to_string());
     }
+    if perms.contains(Permissions::MENTION_EVERYONE) {
+        names.insert("MENTION_EVERYONE".to_string());
+    }
     names
 }
 
+/// Discord answers lookups of unknown or inaccessible ids with 404 (or 403 for
+/// channels the bot cannot see); anything else is a real failure.
+fn is_not_found(err: &serenity::Error) -> bool {
+    let serenity::Error::Http(http_err) = err else {
+        return false;
+    };
+    is_not_found_status(http_err.status_code().map(|status| status.as_u16()))
+}
+
+fn is_forbidden(err: &serenity::Error) -> bool {
+    let serenity::Error::Http(http_err) = err else {
+        return false;
+    };
+    http_err.status_code().map(|status| status.as_u16()) == Some(403)
+}
+
+fn is_not_found_status(status: Option<u16>) -> bool {
+    matches!(status, Some(403 | 404))
+}
+
+/// Counts a failed webhook or direct send under its reason and turns it into
+/// the error the caller returns.
+fn send_error(mechanism: &'static str, context: &str, err: serenity::Error) -> anyhow::Error {
+    let reason = match &err {
+        serenity::Error::Model(ModelError::InvalidPermissions { .. }) => "missing_permission",
+        serenity::Error::Http(http_err) => {
+            send_failure_reason(http_err.status_code().map(|status| status.as_u16()))
+        }
+        _ => "other",
+    };
+    Metrics::discord_send_failed(mechanism, reason);
+    anyhow!("{}: {}", context, err)
+}
+
+/// Whether a failed send may still have created the message: Discord
+/// answered with a server error, or not at all.
+fn send_outcome_unknown(err: &serenity::Error) -> bool {
+    match err {
+        serenity::Error::Http(http_err) => {
+            status_leaves_send_unknown(http_err.status_code().map(|status| status.as_u16()))
+        }
+        _ => false,
+    }
+}
+
+/// Whether our webhook posted `message` under `username`; the webhook is
+/// shared by every Matrix user of the channel.
+fn sent_by_webhook(message: &SerenityMessage, webhook: &WebhookInfo, username: &str) -> bool {
+    message.webhook_id.map(|id| id.get()) == Some(webhook.id) && message.author.name == username
+}
+
+fn status_leaves_send_unknown(status: Option<u16>) -> bool {
+    status.is_none_or(|status| status >= 500)
+}
+
+fn send_failure_reason(status: Option<u16>) -> &'static str {
+    match status {
+        Some(401 | 403) => "missing_permission",
+        Some(404) => "not_found",
+        Some(429) => "rate_limited",
+        Some(_) => "http_error",
+        None => "other",
+    }
+}
+
+fn guild_emoji(emoji: &Emoji) -> GuildEmoji {
+    GuildEmoji {
+        id: emoji.id.to_string(),
+        name: emoji.name.clone(),
+        animated: emoji.animated,
+    }
+}
+
+fn discord_presence(presence: &Presence) -> DiscordPresence {
+    let state = match presence.status {
+        OnlineStatus::Online => DiscordPresenceState::Online,
+        OnlineStatus::DoNotDisturb => DiscordPresenceState::Dnd,
+        OnlineStatus::Idle => DiscordPresenceState::Idle,
+        OnlineStatus::Offline | OnlineStatus::Invisible => DiscordPresenceState::Offline,
+        _ => DiscordPresenceState::Offline,
+    };
+
+    let activities = presence
+        .activities
+        .iter()
+        .map(|activity| DiscordActivity {
+            kind: format!("{:?}", activity.kind),
+            name: activity.name.clone(),
+            url: activity.url.as_ref().map(ToString::to_string),
+        })
+        .collect();
+
+    DiscordPresence {
+        user_id: presence.user.id.to_string(),
+        username: presence.user.name.clone(),
+        state,
+        activities,
+    }
+}
+
+/// Who a bot's message answering an interaction is attributed to: the user
+/// who ran the command or clicked the component. `None` for other messages.
+fn command_attribution(
+    bot: &serenity::model::user::User,
+    interaction: Option<&MessageInteraction>,
+    metadata: Option<&MessageInteractionMetadata>,
+) -> Option<String> {
+    let user = match metadata {
+        Some(MessageInteractionMetadata::Command(metadata)) => Some(&metadata.user),
+        Some(MessageInteractionMetadata::Component(metadata)) => Some(&metadata.user),
+        Some(MessageInteractionMetadata::ModalSubmit(metadata)) => Some(&metadata.user),
+        _ => None,
+    }
+    .or(interaction.map(|interaction| &interaction.user))?;
+    Some(interaction_attribution(
+        bot.display_name(),
+        interaction.map(|interaction| interaction.name.as_str()),
+        user.display_name(),
+    ))
+}
+
+fn interaction_attribution(bot: &str, command: Option<&str>, user: &str) -> String {
+    match command {
+        Some(command) => format!("{bot} (command: /{command} by {user})"),
+        None => format!("{bot} (interaction by {user})"),
+    }
+}
+
+fn user_snapshot(user: &serenity::model::user::User) -> UserSnapshot {
+    let discriminator = user
+        .discriminator
+        .map(|value| format!("{:04}", value.get()))
+        .unwrap_or_else(|| "0000".to_string());
+    let username = user
+        .global_name
+        .clone()
+        .unwrap_or_else(|| user.name.clone());
+
+    UserSnapshot {
+        id: user.id.to_string(),
+        username,
+        discriminator,
+        avatar_url: user.avatar_url(),
+    }
+}
+
+fn member_snapshot(member: &serenity::model::guild::Member) -> MemberSnapshot {
+    MemberSnapshot {
+        guild_id: member.guild_id.to_string(),
+        user_id: member.user.id.to_string(),
+        nick: member.nick.clone(),
+        roles: member.roles.iter().map(ToString::to_string).collect(),
+        avatar_url: member.avatar_url().or_else(|| member.user.avatar_url()),
+    }
+}
+
+/// Snapshot of the partial member Discord attaches to guild messages, which
+/// lacks the user and guild the full member carries.
+fn partial_member_snapshot(
+    guild_id: GuildId,
+    author: &serenity::model::user::User,
+    member: &serenity::model::guild::PartialMember,
+) -> MemberSnapshot {
+    let mut member = member.clone();
+    member.guild_id = Some(guild_id);
+    member.user = Some(author.clone());
+    member_snapshot(&member.into())
+}
+
+fn channel_snapshot(channel: &serenity::model::channel::GuildChannel) -> ChannelSnapshot {
+    ChannelSnapshot {
+        id: channel.id.to_string(),
+        name: channel.name.clone(),
+        guild_id: channel.guild_id.to_string(),
+        topic: channel.topic.clone(),
+        slowmode_seconds: channel.rate_limit_per_user.unwrap_or(0),
+        thread_parent_id: channel
+            .thread_metadata
+            .as_ref()
+            .and(channel.parent_id)
+            .map(|id| id.to_string()),
+        everyone_can_view: everyone_can_view(channel),
+    }
+}
+
+/// The @everyone role shares the guild's id.
+fn everyone_can_view(channel: &serenity::model::channel::GuildChannel) -> bool {
+    let everyone = RoleId::new(channel.guild_id.get());
+    channel.kind != ChannelType::PrivateThread
+        && !channel.permission_overwrites.iter().any(|overwrite| {
+            overwrite.kind == PermissionOverwriteType::Role(everyone)
+                && overwrite.deny.contains(Permissions::VIEW_CHANNEL)
+        })
+}
+
+fn role_snapshot(role: &serenity::model::guild::Role) -> RoleSnapshot {
+    RoleSnapshot {
+        id: role.id.to_string(),
+        guild_id: role.guild_id.to_string(),
+        name: role.name.clone(),
+        color: role.colour.0,
+    }
+}
+
+fn automod_modlog_entry(execution: &ActionExecution) -> ModlogEntry {
+    let action = match &execution.action {
+        AutoModAction::BlockMessage { .. } => "block message".to_string(),
+        AutoModAction::Alert(channel_id) => format!("alert in {channel_id}"),
+        AutoModAction::Timeout(duration) => format!("timeout for {}s", duration.as_secs()),
+        other => format!("{other:?}"),
+    };
+    let trigger = match execution.trigger_type {
+        TriggerType::Keyword => "keyword".to_string(),
+        TriggerType::Spam => "spam".to_string(),
+        TriggerType::KeywordPreset => "keyword preset".to_string(),
+        TriggerType::MentionSpam => "mention spam".to_string(),
+        other => format!("{other:?}"),
+    };
+    ModlogEntry::new(ModlogAction::AutoMod, &execution.guild_id.to_string())
+        .with("target", Some(execution.user_id.to_string()))
+        .with("channel", execution.channel_id.map(|id| id.to_string()))
+        .with("rule", Some(execution.rule_id.to_string()))
+        .with("trigger", Some(trigger))
+        .with("matched", execution.matched_keyword.clone())
+        .with("response", Some(action))
+}
+
 fn unique_message_ids(ids: Vec<MessageId>) -> Vec<MessageId> {
     let mut seen = HashSet::new();
     ids.into_iter().filter(|id| seen.insert(*id)).collect()
@@ -534,6 +1517,186 @@ impl DiscordClient {
             http: Arc::new(RwLock::new(None)),
             webhook_cache: Arc::new(RwLock::new(std::collections::HashMap::new())),
             our_webhook_ids: Arc::new(RwLock::new(std::collections::HashSet::new())),
+            metadata: Arc::new(DiscordMetadataCache::new()),
+            chaos: Arc::new(ChaosInjector::disabled()),
+            gateway_down_since: Arc::new(parking_lot::Mutex::new(Some(Instant::now()))),
+            connected_guilds: Arc::default(),
+            missing_intents: Arc::default(),
+        })
+    }
+
+    /// Privileged intents the gateway session runs without, because they
+    /// are turned off in the config or not granted to the application.
+    pub fn missing_intents(&self) -> Vec<PrivilegedIntent> {
+        self.missing_intents.lock().clone()
+    }
+
+    pub fn has_intent(&self, intent: PrivilegedIntent) -> bool {
+        !self.missing_intents.lock().contains(&intent)
+    }
+
+    /// Headroom on the routes messages to `channel_ids` go through, keyed by
+    /// route and taken from the tightest channel. Routes nothing was sent
+    /// on yet are left out.
+    pub async fn rate_limit_headroom(
+        &self,
+        channel_ids: &[String],
+    ) -> BTreeMap<&'static str, RouteHeadroom> {
+        let mut headroom = BTreeMap::new();
+        let Some(http) = self.http.read().await.clone() else {
+            return headroom;
+        };
+        let Some(ratelimiter) = &http.ratelimiter else {
+            return headroom;
+        };
+        let buckets: Vec<_> = {
+            let webhooks = self.webhook_cache.read().await;
+            channel_ids
+                .iter()
+                .filter_map(|channel_id| {
+                    let webhook_id = webhooks.get(channel_id).map(|info| info.id);
+                    Some(rate_limits::delivery_buckets(
+                        snowflake::parse(channel_id)?,
+                        webhook_id,
+                    ))
+                })
+                .flatten()
+                .collect()
+        };
+
+        // Serenity write-locks the route map for every request and sleeps out
+        // rate limits with the bucket locked, so the map is only held long
+        // enough to copy the buckets out and busy buckets are skipped.
+        let ratelimits: Vec<_> = {
+            let routes = ratelimiter.routes();
+            let routes = routes.read().await;
+            buckets
+                .into_iter()
+                .filter_map(|(route, bucket)| Some((route, routes.get(&bucket)?.clone())))
+                .collect()
+        };
+        let now = std::time::SystemTime::now();
+        for (route, ratelimit) in ratelimits {
+            let Ok(ratelimit) = ratelimit.try_lock() else {
+                continue;
+            };
+            if ratelimit.limit() <= 0 {
+                continue;
+            }
+            let state = RouteHeadroom::from_bucket(
+                ratelimit.limit(),
+                ratelimit.remaining(),
+                ratelimit.reset(),
+                now,
+            );
+            headroom
+                .entry(route)
+                .and_modify(|tightest: &mut RouteHeadroom| *tightest = tightest.tighter(state))
+                .or_insert(state);
+        }
+        headroom
+    }
+
+    /// The application's flags, which tell the privileged intents it was
+    /// granted. `None` if they could not be fetched.
+    async fn application_flags(&self) -> Option<ApplicationFlags> {
+        let http = Http::new(self._config.auth.bot_token.expose_secret());
+        match http.get_current_application_info().await {
+            Ok(info) => info.flags,
+            Err(err) => {
+                warn!("failed to look up the granted discord intents: {err}");
+                None
+            }
+        }
+    }
+
+    pub fn with_chaos(mut self, chaos: Arc<ChaosInjector>) -> Self {
+        self.chaos = chaos;
+        self
+    }
+
+    /// Whether `bridge.dry_run` skips this outbound call.
+    fn dry_run(&self, operation: &'static str, subject: &str) -> bool {
+        dry_run::skip(
+            self._config.bridge.dry_run,
+            ChaosTarget::Discord,
+            operation,
+            subject,
+        )
+    }
+
+    pub fn metadata(&self) -> &Arc<DiscordMetadataCache> {
+        &self.metadata
+    }
+
+    /// Whether the gateway login finished and the HTTP client is available
+    /// for sends.
+    pub async fn is_ready(&self) -> bool {
+        self.http.read().await.is_some()
+    }
+
+    /// How long the gateway has been disconnected, counting from startup
+    /// until the first login; `None` while connected.
+    pub fn gateway_down_for(&self) -> Option<Duration> {
+        self.gateway_down_since.lock().map(|since| since.elapsed())
+    }
+
+    /// How many guilds the gateway reported as available.
+    pub fn connected_guild_count(&self) -> usize {
+        self.connected_guilds.lock().len()
+    }
+
+    /// Uses `http` for REST calls without logging in to the gateway, e.g. an
+    /// [`Http`] built with a proxy pointing at a stub API server.
+    pub async fn attach_http(&self, http: Arc<Http>) {
+        *self.http.write().await = Some(http);
+    }
+
+    /// Builds the REST client from the bot token without connecting to the
+    /// gateway; enough for one-off commands that only make API calls.
+    pub async fn connect_http(&self) {
+        let http = Http::new(self._config.auth.bot_token.expose_secret());
+        self.attach_http(Arc::new(http)).await;
+    }
+
+    /// The bot's `name (id)`, fetched from the API to prove the token works.
+    pub async fn current_user_tag(&self) -> Result<String> {
+        let http_guard = self.http.read().await;
+        let Some(http) = http_guard.as_ref() else {
+            return Err(DiscordNotReady.into());
+        };
+        let user = http
+            .get_current_user()
+            .await
+            .map_err(|e| anyhow!("failed to fetch the bot user: {}", e))?;
+        Ok(format!("{} ({})", user.name, user.id))
+    }
+
+    /// Creates a webhook in `channel_id` and deletes it again, proving the
+    /// bot may manage webhooks there without touching the bridge's own.
+    pub async fn check_webhook_creation(&self, channel_id: &str) -> Result<()> {
+        use serenity::builder::CreateWebhook;
+
+        if self.dry_run("check_webhook_creation", channel_id) {
+            return Ok(());
+        }
+
+        let channel = ChannelId::new(snowflake::parse_id("channel", channel_id)?);
+        let http_guard = self.http.read().await;
+        let Some(http) = http_guard.as_ref() else {
+            return Err(DiscordNotReady.into());
+        };
+        let name = format!("{} self-test", self._config.channel.webhook_name);
+        let webhook = channel
+            .create_webhook(http, CreateWebhook::new(name))
+            .await
+            .map_err(|e| anyhow!("failed to create webhook: {}", e))?;
+        webhook.delete(http).await.map_err(|e| {
+            anyhow!(
+                "created webhook {} but failed to delete it: {}",
+                webhook.id,
+                e
+            )
         })
     }
 
@@ -548,10 +1711,22 @@ impl DiscordClient {
         }
 
         let intents = if self._config.auth.use_privileged_intents {
-            GatewayIntents::all()
+            granted_intents(self.application_flags().await)
         } else {
             GatewayIntents::non_privileged()
         };
+        let missing: Vec<PrivilegedIntent> = PrivilegedIntent::ALL
+            .into_iter()
+            .filter(|intent| !intents.contains(intent.gateway_intent()))
+            .collect();
+        for intent in &missing {
+            warn!(
+                "discord intent {} is not available, disabling {}",
+                intent.as_str(),
+                intent.disables()
+            );
+        }
+        *self.missing_intents.lock() = missing;
 
         let (ready_tx, ready_rx) = oneshot::channel();
         let (http_tx, http_rx) = oneshot::channel();
@@ -560,17 +1735,28 @@ impl DiscordClient {
             bridge: self.bridge.clone(),
             http_sender: Arc::new(tokio::sync::Mutex::new(Some(http_tx))),
             our_webhook_ids: self.our_webhook_ids.clone(),
+            metadata: self.metadata.clone(),
+            dry_run: self._config.bridge.dry_run,
+            gateway_down_since: self.gateway_down_since.clone(),
+            connected_guilds: self.connected_guilds.clone(),
         };
 
-        let mut gateway_client = SerenityClient::builder(&self._config.auth.bot_token, intents)
-            .event_handler(event_handler)
+        let mut builder =
+            SerenityClient::builder(self._config.auth.bot_token.expose_secret(), intents)
+                .event_handler(event_handler);
+        if event_log::is_enabled() {
+            builder = builder.raw_event_handler(DebugEventRecorder);
+        }
+        let mut gateway_client = builder
             .await
             .map_err(|err| anyhow!("failed to build discord gateway client: {err}"))?;
 
+        let gateway_down_since = self.gateway_down_since.clone();
         let gateway_task = tokio::spawn(async move {
             if let Err(err) = gateway_client.start_autosharded().await {
                 error!("discord gateway stopped: {err}");
             }
+            gateway_down_since.lock().get_or_insert_with(Instant::now);
         });
 
         match tokio::time::timeout(std::time::Duration::from_secs(30), ready_rx).await {
@@ -642,6 +1828,9 @@ impl DiscordClient {
         }
 
         state.is_logged_in = false;
+        self.gateway_down_since
+            .lock()
+            .get_or_insert_with(Instant::now);
         info!("discord client stopped");
         Ok(())
     }
@@ -682,6 +1871,10 @@ impl DiscordClient {
         username: Option<&str>,
         avatar_url: Option<&str>,
     ) -> Result<String> {
+        preflight::check_message(content, None, attachments.len())?;
+        if self.dry_run("send_message", channel_id) {
+            return Ok(dry_run::placeholder_id(""));
+        }
         debug!(
             "Discord send channel={} reply_to={:?} edit_of={:?} attachments={} username={:?} content={}",
             channel_id,
@@ -698,16 +1891,17 @@ impl DiscordClient {
         if delay > 0 {
             tokio::time::sleep(tokio::time::Duration::from_millis(delay)).await;
         }
+        self.chaos
+            .inject(ChaosTarget::Discord, "send_message")
+            .await?;
 
         let http_guard = self.http.read().await;
         let Some(http) = http_guard.as_ref() else {
             warn!("discord http client not available");
-            return Err(anyhow!("discord http client not available"));
+            return Err(DiscordNotReady.into());
         };
 
-        let channel_id_num: u64 = channel_id
-            .parse()
-            .map_err(|_| anyhow!("invalid channel id: {}", channel_id))?;
+        let channel_id_num = snowflake::parse_id("channel", channel_id)?;
 
         if self._config.channel.enable_webhook
             && let Some(username) = username
@@ -754,6 +1948,10 @@ impl DiscordClient {
         username: Option<&str>,
         avatar_url: Option<&str>,
     ) -> Result<String> {
+        preflight::check_embed(embed)?;
+        if self.dry_run("send_embed", channel_id) {
+            return Ok(dry_run::placeholder_id(""));
+        }
         debug!(
             "Discord send embed channel={} username={:?}",
             channel_id, username
@@ -765,16 +1963,17 @@ impl DiscordClient {
         if delay > 0 {
             tokio::time::sleep(tokio::time::Duration::from_millis(delay)).await;
         }
+        self.chaos
+            .inject(ChaosTarget::Discord, "send_embed")
+            .await?;
 
         let http_guard = self.http.read().await;
         let Some(http) = http_guard.as_ref() else {
             warn!("discord http client not available");
-            return Err(anyhow!("discord http client not available"));
+            return Err(DiscordNotReady.into());
         };
 
-        let channel_id_num: u64 = channel_id
-            .parse()
-            .map_err(|_| anyhow!("invalid channel id: {}", channel_id))?;
+        let channel_id_num = snowflake::parse_id("channel", channel_id)?;
 
         if self._config.channel.enable_webhook
             && let Some(username) = username
@@ -825,8 +2024,9 @@ impl DiscordClient {
         let message = channel
             .send_message(http, CreateMessage::new().embed(embed_builder))
             .await
-            .map_err(|e| anyhow!("failed to send embed to discord: {}", e))?;
+            .map_err(|e| send_error("direct", "failed to send embed to discord", e))?;
 
+        Metrics::discord_sent("direct");
         info!(
             "sent embed to channel {}, message_id={}",
             channel_id, message.id
@@ -844,7 +2044,7 @@ impl DiscordClient {
     ) -> Result<String> {
         let webhook = Webhook::from_url(http, &webhook_info.url)
             .await
-            .map_err(|e| anyhow!("failed to parse webhook url: {}", e))?;
+            .map_err(|e| send_error("webhook", "failed to parse webhook url", e))?;
 
         use serenity::builder::CreateEmbed;
 
@@ -880,13 +2080,17 @@ impl DiscordClient {
         if let Some(avatar) = avatar_url {
             builder = builder.avatar_url(avatar);
         }
+        if let Some(thread_id) = webhook_info.thread_id {
+            builder = builder.in_thread(thread_id);
+        }
 
         let message = webhook
             .execute(http, false, builder)
             .await
-            .map_err(|e| anyhow!("webhook embed send failed: {}", e))?
+            .map_err(|e| send_error("webhook", "webhook embed send failed", e))?
             .ok_or_else(|| anyhow!("webhook execution returned no message"))?;
 
+        Metrics::discord_sent("webhook");
         info!(
             "sent embed via webhook to channel, message_id={}",
             message.id
@@ -894,47 +2098,123 @@ impl DiscordClient {
         Ok(message.id.to_string())
     }
 
-    async fn get_or_create_webhook(&self, http: &Http, channel_id: u64) -> Result<WebhookInfo> {
-        if let Some(info) = self.webhook_cache.read().await.get(&channel_id.to_string()) {
-            return Ok(info.clone());
+    /// Deletes the webhook the bridge posts through in `channel_id`, for
+    /// channels that are no longer bridged.
+    pub async fn remove_bridge_webhook(&self, channel_id: &str) -> Result<()> {
+        let channel = ChannelId::new(snowflake::parse_id("channel", channel_id)?);
+        if self.dry_run("delete_webhook", channel_id) {
+            return Ok(());
         }
+        let http_guard = self.http.read().await;
+        let Some(http) = http_guard.as_ref() else {
+            return Err(DiscordNotReady.into());
+        };
 
-        let channel = ChannelId::new(channel_id);
+        self.webhook_cache.write().await.remove(channel_id);
+        let webhook_name = &self._config.channel.webhook_name;
         let webhooks = channel
             .webhooks(http)
             .await
             .map_err(|e| anyhow!("failed to fetch webhooks: {}", e))?;
-
-        let webhook_name = &self._config.channel.webhook_name;
-        let existing = webhooks
+        for webhook in webhooks
             .iter()
-            .find(|w| w.name.as_deref() == Some(webhook_name));
-
-        let info = if let Some(webhook) = existing {
-            let url = webhook
-                .url()
-                .map_err(|e| anyhow!("webhook has no usable url: {}", e))?;
-            WebhookInfo {
-                id: webhook.id.get(),
-                url,
-            }
-        } else {
-            use serenity::builder::CreateWebhook;
-            let webhook: serenity::model::webhook::Webhook = channel
-                .create_webhook(http, CreateWebhook::new(webhook_name))
+            .filter(|webhook| webhook.name.as_deref() == Some(webhook_name))
+        {
+            webhook
+                .delete(http)
                 .await
-                .map_err(|e| anyhow!("failed to create webhook: {}", e))?;
+                .map_err(|e| anyhow!("failed to delete webhook {}: {}", webhook.id, e))?;
+            self.our_webhook_ids.write().await.remove(&webhook.id.get());
+        }
+        Ok(())
+    }
 
-            let url = webhook
-                .url()
-                .map_err(|e| anyhow!("created webhook has no usable url: {}", e))?;
+    /// The webhook to post to `channel_id` through. Threads have none of
+    /// their own, so the parent channel's posts into them.
+    async fn get_or_create_webhook(&self, http: &Http, channel_id: u64) -> Result<WebhookInfo> {
+        let Some(parent_id) = self.thread_parent(http, channel_id).await else {
+            return self.channel_webhook(http, channel_id).await;
+        };
+        let mut info = self.channel_webhook(http, parent_id).await?;
+        info.thread_id = Some(ChannelId::new(channel_id));
+        Ok(info)
+    }
 
-            WebhookInfo {
-                id: webhook.id.get(),
-                url,
-            }
+    /// The parent channel of `channel_id` if it is a thread, from the
+    /// metadata cache or else the API.
+    async fn thread_parent(&self, http: &Http, channel_id: u64) -> Option<u64> {
+        let snapshot = match self.metadata.channel(&channel_id.to_string()).await {
+            Some(snapshot) => snapshot,
+            None => match ChannelId::new(channel_id).to_channel(http).await {
+                Ok(serenity::all::Channel::Guild(channel)) => {
+                    let snapshot = channel_snapshot(&channel);
+                    self.metadata.upsert_channel(snapshot.clone()).await;
+                    snapshot
+                }
+                Ok(_) => return None,
+                Err(err) => {
+                    debug!("failed to look up discord channel {}: {}", channel_id, err);
+                    return None;
+                }
+            },
+        };
+        snowflake::parse(&snapshot.thread_parent_id?)
+    }
+
+    async fn channel_webhook(&self, http: &Http, channel_id: u64) -> Result<WebhookInfo> {
+        if let Some(info) = self.existing_webhook(http, channel_id).await? {
+            return Ok(info);
+        }
+
+        use serenity::builder::CreateWebhook;
+        let webhook_name = &self._config.channel.webhook_name;
+        let webhook: serenity::model::webhook::Webhook = ChannelId::new(channel_id)
+            .create_webhook(http, CreateWebhook::new(webhook_name))
+            .await
+            .map_err(|e| send_error("webhook", "failed to create webhook", e))?;
+        let url = webhook
+            .url()
+            .map_err(|e| send_error("webhook", "created webhook has no usable url", e))?;
+        let info = WebhookInfo {
+            id: webhook.id.get(),
+            url,
+            thread_id: None,
+        };
+        self.remember_webhook(channel_id, &info).await;
+        Ok(info)
+    }
+
+    /// Our webhook in `channel_id`, from the cache or else the channel's
+    /// webhook list. Never creates one.
+    async fn existing_webhook(&self, http: &Http, channel_id: u64) -> Result<Option<WebhookInfo>> {
+        if let Some(info) = self.webhook_cache.read().await.get(&channel_id.to_string()) {
+            return Ok(Some(info.clone()));
+        }
+
+        let webhooks = ChannelId::new(channel_id)
+            .webhooks(http)
+            .await
+            .map_err(|e| send_error("webhook", "failed to fetch webhooks", e))?;
+        let webhook_name = &self._config.channel.webhook_name;
+        let Some(webhook) = webhooks
+            .iter()
+            .find(|w| w.name.as_deref() == Some(webhook_name))
+        else {
+            return Ok(None);
+        };
+        let url = webhook
+            .url()
+            .map_err(|e| send_error("webhook", "webhook has no usable url", e))?;
+        let info = WebhookInfo {
+            id: webhook.id.get(),
+            url,
+            thread_id: None,
         };
+        self.remember_webhook(channel_id, &info).await;
+        Ok(Some(info))
+    }
 
+    async fn remember_webhook(&self, channel_id: u64, info: &WebhookInfo) {
         self.our_webhook_ids.write().await.insert(info.id);
         debug!(
             "recorded our webhook id={} for channel={}",
@@ -945,7 +2225,6 @@ impl DiscordClient {
             .write()
             .await
             .insert(channel_id.to_string(), info.clone());
-        Ok(info)
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -964,20 +2243,22 @@ impl DiscordClient {
 
         let webhook = Webhook::from_url(http, &webhook_info.url)
             .await
-            .map_err(|e| anyhow!("failed to parse webhook url: {}", e))?;
+            .map_err(|e| send_error("webhook", "failed to parse webhook url", e))?;
 
         if let Some(message_id_str) = edit_of {
-            let message_id: u64 = message_id_str
-                .parse()
-                .map_err(|e| anyhow!("invalid message id for edit: {}", e))?;
+            let message_id = snowflake::parse_id("message", message_id_str)?;
 
-            let builder = EditWebhookMessage::new().content(content);
+            let mut builder = EditWebhookMessage::new().content(content);
+            if let Some(thread_id) = webhook_info.thread_id {
+                builder = builder.in_thread(thread_id);
+            }
 
             webhook
                 .edit_message(http, MessageId::new(message_id), builder)
                 .await
-                .map_err(|e| anyhow!("webhook edit failed: {}", e))?;
+                .map_err(|e| send_error("webhook", "webhook edit failed", e))?;
 
+            Metrics::discord_sent("webhook");
             info!("edited message via webhook, message_id={}", message_id_str);
             return Ok(message_id_str.to_string());
         }
@@ -987,18 +2268,41 @@ impl DiscordClient {
         if let Some(avatar) = avatar_url {
             builder = builder.avatar_url(avatar);
         }
+        if let Some(thread_id) = webhook_info.thread_id {
+            builder = builder.in_thread(thread_id);
+        }
 
-        let message = webhook
-            .execute(http, false, builder)
-            .await
-            .map_err(|e| anyhow!("webhook send failed: {}", e))?
-            .ok_or_else(|| anyhow!("webhook execution returned no message"))?;
+        let started_at = Utc::now();
+        let message_id = match webhook.execute(http, false, builder).await {
+            Ok(message) => {
+                message
+                    .ok_or_else(|| anyhow!("webhook execution returned no message"))?
+                    .id
+            }
+            Err(err) => {
+                let sent = match webhook_info.thread_id.or(webhook.channel_id) {
+                    Some(channel_id) if send_outcome_unknown(&err) => {
+                        self.find_sent_message(http, channel_id, started_at, |message| {
+                            sent_by_webhook(message, webhook_info, username)
+                                && message.content.trim() == content.trim()
+                        })
+                        .await
+                    }
+                    _ => None,
+                };
+                match sent {
+                    Some(message_id) => message_id,
+                    None => return Err(send_error("webhook", "webhook send failed", err)),
+                }
+            }
+        };
 
+        Metrics::discord_sent("webhook");
         info!(
             "sent message via webhook to channel, message_id={}",
-            message.id
+            message_id
         );
-        Ok(message.id.to_string())
+        Ok(message_id.to_string())
     }
 
     async fn send_direct_message(
@@ -1010,8 +2314,6 @@ impl DiscordClient {
         _reply_to: Option<&str>,
         edit_of: Option<&str>,
     ) -> Result<String> {
-        use serenity::builder::{CreateMessage, EditMessage};
-
         let channel = ChannelId::new(channel_id);
 
         let mut message_content = content.to_string();
@@ -1023,9 +2325,7 @@ impl DiscordClient {
         }
 
         if let Some(message_id_str) = edit_of {
-            let message_id: u64 = message_id_str
-                .parse()
-                .map_err(|e| anyhow!("invalid message id for edit: {}", e))?;
+            let message_id = snowflake::parse_id("message", message_id_str)?;
 
             let message = channel
                 .edit_message(
@@ -1034,8 +2334,9 @@ impl DiscordClient {
                     EditMessage::new().content(&message_content),
                 )
                 .await
-                .map_err(|e| anyhow!("direct message edit failed: {}", e))?;
+                .map_err(|e| send_error("direct", "direct message edit failed", e))?;
 
+            Metrics::discord_sent("direct");
             info!(
                 "edited message directly in channel {}, message_id={}",
                 channel_id, message.id
@@ -1043,16 +2344,85 @@ impl DiscordClient {
             return Ok(message.id.to_string());
         }
 
-        let message = channel
-            .send_message(http, CreateMessage::new().content(&message_content))
-            .await
-            .map_err(|e| anyhow!("direct message send failed: {}", e))?;
+        // Discord hands back the message already created with the same
+        // nonce, so a send whose outcome is unknown can be repeated once.
+        let nonce = Nonce::Number(uuid::Uuid::new_v4().as_u64_pair().0);
+        let builder = || {
+            CreateMessage::new()
+                .content(&message_content)
+                .nonce(nonce.clone())
+                .enforce_nonce(true)
+        };
+        let message_id = match channel.send_message(http, builder()).await {
+            Ok(message) => message.id,
+            Err(err) if send_outcome_unknown(&err) => {
+                channel
+                    .send_message(http, builder())
+                    .await
+                    .map_err(|e| send_error("direct", "direct message send failed", e))?
+                    .id
+            }
+            Err(err) => return Err(send_error("direct", "direct message send failed", err)),
+        };
 
+        Metrics::discord_sent("direct");
         info!(
             "sent message directly to channel {}, message_id={}",
-            channel_id, message.id
+            channel_id, message_id
         );
-        Ok(message.id.to_string())
+        Ok(message_id.to_string())
+    }
+
+    /// Looks for a message a failed send may have created anyway, so the
+    /// retry that would follow does not post it twice: among the channel's
+    /// latest messages, one that `is_sent` matches, no older than the attempt
+    /// (give or take some clock skew) and not already bridged.
+    async fn find_sent_message(
+        &self,
+        http: &Http,
+        channel: ChannelId,
+        started_at: DateTime<Utc>,
+        is_sent: impl Fn(&SerenityMessage) -> bool,
+    ) -> Option<MessageId> {
+        let earliest = (started_at - SENT_MESSAGE_CLOCK_SKEW).timestamp();
+        let recent = match channel
+            .messages(http, GetMessages::new().limit(SENT_MESSAGE_LOOKBACK))
+            .await
+        {
+            Ok(recent) => recent,
+            Err(err) => {
+                warn!(
+                    "cannot tell whether a failed send to {} went through: {}",
+                    channel, err
+                );
+                return None;
+            }
+        };
+        let bridge = self.bridge.read().await.clone();
+        for message in recent
+            .iter()
+            .filter(|message| message.timestamp.unix_timestamp() >= earliest && is_sent(message))
+        {
+            // An earlier message with the same content is mapped already.
+            if let Some(bridge) = &bridge
+                && !matches!(
+                    bridge
+                        .db()
+                        .message_store()
+                        .get_by_discord_message_id(&message.id.to_string())
+                        .await,
+                    Ok(None)
+                )
+            {
+                continue;
+            }
+            info!(
+                "send to {} failed but message {} went through, not sending it again",
+                channel, message.id
+            );
+            return Some(message.id);
+        }
+        None
     }
 
     pub async fn send_file_as_user(
@@ -1064,6 +2434,9 @@ impl DiscordClient {
         username: Option<&str>,
         avatar_url: Option<&str>,
     ) -> Result<String> {
+        if self.dry_run("send_file", channel_id) {
+            return Ok(dry_run::placeholder_id(""));
+        }
         debug!(
             "Discord send file channel={} filename={} size={} username={:?}",
             channel_id,
@@ -1078,16 +2451,15 @@ impl DiscordClient {
         if delay > 0 {
             tokio::time::sleep(tokio::time::Duration::from_millis(delay)).await;
         }
+        self.chaos.inject(ChaosTarget::Discord, "send_file").await?;
 
         let http_guard = self.http.read().await;
         let Some(http) = http_guard.as_ref() else {
             warn!("discord http client not available");
-            return Err(anyhow!("discord http client not available"));
+            return Err(DiscordNotReady.into());
         };
 
-        let channel_id_num: u64 = channel_id
-            .parse()
-            .map_err(|_| anyhow!("invalid channel id: {}", channel_id))?;
+        let channel_id_num = snowflake::parse_id("channel", channel_id)?;
 
         if self._config.channel.enable_webhook
             && let Some(username) = username
@@ -1115,18 +2487,31 @@ impl DiscordClient {
         }
 
         let channel = ChannelId::new(channel_id_num);
-        let attachment = CreateAttachment::bytes(data.to_vec(), filename);
-
-        let message = channel
-            .send_message(http, CreateMessage::new().add_file(attachment))
-            .await
-            .map_err(|e| anyhow!("failed to send file to discord: {}", e))?;
+        let nonce = Nonce::Number(uuid::Uuid::new_v4().as_u64_pair().0);
+        let builder = || {
+            CreateMessage::new()
+                .add_file(CreateAttachment::bytes(data.to_vec(), filename))
+                .nonce(nonce.clone())
+                .enforce_nonce(true)
+        };
+        let message_id = match channel.send_message(http, builder()).await {
+            Ok(message) => message.id,
+            Err(err) if send_outcome_unknown(&err) => {
+                channel
+                    .send_message(http, builder())
+                    .await
+                    .map_err(|e| send_error("direct", "failed to send file to discord", e))?
+                    .id
+            }
+            Err(err) => return Err(send_error("direct", "failed to send file to discord", err)),
+        };
 
+        Metrics::discord_sent("direct");
         info!(
             "sent file to channel {}, message_id={}",
-            channel_id, message.id
+            channel_id, message_id
         );
-        Ok(message.id.to_string())
+        Ok(message_id.to_string())
     }
 
     async fn send_file_via_webhook(
@@ -1140,7 +2525,7 @@ impl DiscordClient {
     ) -> Result<String> {
         let webhook = Webhook::from_url(http, &webhook_info.url)
             .await
-            .map_err(|e| anyhow!("failed to parse webhook url: {}", e))?;
+            .map_err(|e| send_error("webhook", "failed to parse webhook url", e))?;
 
         let attachment = CreateAttachment::bytes(data.to_vec(), filename);
 
@@ -1149,72 +2534,591 @@ impl DiscordClient {
         if let Some(avatar) = avatar_url {
             builder = builder.avatar_url(avatar);
         }
+        if let Some(thread_id) = webhook_info.thread_id {
+            builder = builder.in_thread(thread_id);
+        }
 
         builder = builder.add_file(attachment);
 
-        let message = webhook
-            .execute(http, false, builder)
-            .await
-            .map_err(|e| anyhow!("webhook file send failed: {}", e))?
-            .ok_or_else(|| anyhow!("webhook execution returned no message"))?;
+        let started_at = Utc::now();
+        let message_id = match webhook.execute(http, false, builder).await {
+            Ok(message) => {
+                message
+                    .ok_or_else(|| anyhow!("webhook execution returned no message"))?
+                    .id
+            }
+            Err(err) => {
+                let sent = match webhook_info.thread_id.or(webhook.channel_id) {
+                    Some(channel_id) if send_outcome_unknown(&err) => {
+                        self.find_sent_message(http, channel_id, started_at, |message| {
+                            sent_by_webhook(message, webhook_info, username)
+                                && message
+                                    .attachments
+                                    .iter()
+                                    .any(|attachment| attachment.filename == filename)
+                        })
+                        .await
+                    }
+                    _ => None,
+                };
+                match sent {
+                    Some(message_id) => message_id,
+                    None => return Err(send_error("webhook", "webhook file send failed", err)),
+                }
+            }
+        };
 
+        Metrics::discord_sent("webhook");
         info!(
             "sent file via webhook to channel, message_id={}",
-            message.id
+            message_id
         );
-        Ok(message.id.to_string())
+        Ok(message_id.to_string())
+    }
+
+    /// Reads a channel's history from `since` on, oldest first, stopping
+    /// after `limit` messages. Messages by bots and webhooks, including our
+    /// own, are left out: replaying them would echo bridged messages back.
+    pub async fn channel_messages_since(
+        &self,
+        channel_id: &str,
+        since: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<DiscordMessageContext>> {
+        let channel = ChannelId::new(snowflake::parse_id("channel", channel_id)?);
+        let http_guard = self.http.read().await;
+        let Some(http) = http_guard.as_ref() else {
+            return Err(DiscordNotReady.into());
+        };
+
+        let mut after = MessageId::new(snowflake::from_timestamp_ms(since.timestamp_millis()));
+        let mut messages = Vec::new();
+        while messages.len() < limit {
+            let mut page = channel
+                .messages(
+                    http,
+                    GetMessages::new().after(after).limit(MAX_MESSAGES_PER_PAGE),
+                )
+                .await
+                .map_err(|e| anyhow!("failed to read history of channel {}: {}", channel_id, e))?;
+            page.sort_by_key(|message| message.id);
+            let Some(last) = page.last() else {
+                break;
+            };
+            after = last.id;
+            let full_page = page.len() == usize::from(MAX_MESSAGES_PER_PAGE);
+            messages.extend(
+                page.iter()
+                    .filter(|message| !message.author.bot && message.webhook_id.is_none())
+                    .map(history_message_context),
+            );
+            if !full_page {
+                break;
+            }
+        }
+        messages.truncate(limit);
+        Ok(messages)
     }
 
     pub async fn get_user(&self, user_id: &str) -> Result<Option<DiscordUser>> {
-        let user_id_num: u64 = user_id
-            .parse()
-            .map_err(|_| anyhow!("invalid user id: {}", user_id))?;
+        let user_id_num = snowflake::parse_id("user", user_id)?;
+
+        if let Some(snapshot) = self.metadata.user(user_id).await {
+            return Ok(Some(DiscordUser {
+                id: snapshot.id,
+                username: snapshot.username,
+                discriminator: snapshot.discriminator,
+                avatar: snapshot.avatar_url,
+            }));
+        }
 
         let http_guard = self.http.read().await;
         let Some(http) = http_guard.as_ref() else {
-            return Err(anyhow!("discord http client not available"));
+            return Err(DiscordNotReady.into());
         };
 
         let user = match UserId::new(user_id_num).to_user(http).await {
             Ok(user) => user,
-            Err(err) => {
-                warn!("failed to fetch discord user {}: {}", user_id, err);
+            Err(err) if is_not_found(&err) => {
+                debug!("discord user {} does not exist", user_id);
                 return Ok(None);
             }
+            Err(err) => return Err(anyhow!("failed to fetch discord user {}: {}", user_id, err)),
         };
 
-        let discriminator = user
-            .discriminator
-            .map(|value| format!("{:04}", value.get()))
-            .unwrap_or_else(|| "0000".to_string());
-        let username = user
-            .global_name
-            .clone()
-            .unwrap_or_else(|| user.name.clone());
+        let snapshot = user_snapshot(&user);
+        self.metadata.upsert_user(snapshot.clone()).await;
 
         Ok(Some(DiscordUser {
-            id: user.id.to_string(),
-            username,
-            discriminator,
-            avatar: user.avatar_url(),
+            id: snapshot.id,
+            username: snapshot.username,
+            discriminator: snapshot.discriminator,
+            avatar: snapshot.avatar_url,
+        }))
+    }
+
+    /// Posts `content` as the bot with Approve and Deny buttons carrying the
+    /// given custom ids. Returns the prompt's message id.
+    pub async fn send_approval_prompt(
+        &self,
+        channel_id: &str,
+        content: &str,
+        approve_id: &str,
+        deny_id: &str,
+    ) -> Result<String> {
+        if self.dry_run("send_message", channel_id) {
+            return Ok(dry_run::placeholder_id(""));
+        }
+        let channel_id_num = snowflake::parse_id("channel", channel_id)?;
+        self.chaos
+            .inject(ChaosTarget::Discord, "send_message")
+            .await?;
+
+        let http_guard = self.http.read().await;
+        let Some(http) = http_guard.as_ref() else {
+            return Err(DiscordNotReady.into());
+        };
+
+        let buttons = CreateActionRow::Buttons(vec![
+            CreateButton::new(approve_id)
+                .label("Approve")
+                .style(ButtonStyle::Success),
+            CreateButton::new(deny_id)
+                .label("Deny")
+                .style(ButtonStyle::Danger),
+        ]);
+        let message = ChannelId::new(channel_id_num)
+            .send_message(
+                http,
+                CreateMessage::new()
+                    .content(content)
+                    .components(vec![buttons]),
+            )
+            .await
+            .map_err(|e| anyhow!("failed to send approval prompt: {}", e))?;
+        Ok(message.id.to_string())
+    }
+
+    /// Opens (or reuses) the bot's DM channel with a user and returns its id.
+    pub async fn open_dm_channel(&self, user_id: &str) -> Result<String> {
+        let user_id_num = snowflake::parse_id("user", user_id)?;
+
+        let http_guard = self.http.read().await;
+        let Some(http) = http_guard.as_ref() else {
+            return Err(DiscordNotReady.into());
+        };
+
+        let channel = UserId::new(user_id_num)
+            .create_dm_channel(http)
+            .await
+            .map_err(|e| anyhow!("failed to open DM with discord user {}: {}", user_id, e))?;
+        Ok(channel.id.to_string())
+    }
+
+    pub async fn guild_owner_id(&self, guild_id: &str) -> Result<String> {
+        let guild_id_num = snowflake::parse_id("guild", guild_id)?;
+
+        let http_guard = self.http.read().await;
+        let Some(http) = http_guard.as_ref() else {
+            return Err(DiscordNotReady.into());
+        };
+
+        let guild = GuildId::new(guild_id_num)
+            .to_partial_guild(http)
+            .await
+            .map_err(|e| anyhow!("failed to fetch discord guild {}: {}", guild_id, e))?;
+        Ok(guild.owner_id.to_string())
+    }
+
+    /// Returns the ids of the guild's owner and of every human member whose
+    /// roles grant `MANAGE_CHANNELS` or `ADMINISTRATOR`. The list is cached
+    /// per guild, since building it pages through every member.
+    pub async fn guild_moderator_ids(&self, guild_id: &str) -> Result<Vec<String>> {
+        if let Some(moderators) = self.metadata.moderators(guild_id).await {
+            return Ok(moderators);
+        }
+        let guild_id_num = snowflake::parse_id("guild", guild_id)?;
+
+        let http_guard = self.http.read().await;
+        let Some(http) = http_guard.as_ref() else {
+            return Err(DiscordNotReady.into());
+        };
+
+        let guild_id = GuildId::new(guild_id_num);
+        let guild = guild_id
+            .to_partial_guild(http)
+            .await
+            .map_err(|e| anyhow!("failed to fetch discord guild {}: {}", guild_id, e))?;
+        let role_permissions: HashMap<u64, Permissions> = guild
+            .roles
+            .iter()
+            .map(|(id, role)| (id.get(), role.permissions))
+            .collect();
+
+        let mut moderators = vec![guild.owner_id.to_string()];
+        let mut after = None;
+        loop {
+            let members = guild_id
+                .members(http, Some(1000), after)
+                .await
+                .map_err(|e| anyhow!("failed to list members of guild {}: {}", guild_id, e))?;
+            for member in &members {
+                if member.user.bot || member.user.id == guild.owner_id {
+                    continue;
+                }
+                let roles: Vec<u64> = member.roles.iter().map(|role| role.get()).collect();
+                if moderates_channels(guild_id_num, &roles, &role_permissions) {
+                    moderators.push(member.user.id.to_string());
+                }
+            }
+            match members.last() {
+                Some(last) if members.len() == 1000 => after = Some(last.user.id),
+                _ => break,
+            }
+        }
+        self.metadata
+            .set_moderators(&guild_id.to_string(), moderators.clone())
+            .await;
+        Ok(moderators)
+    }
+
+    /// Up to `limit` members of the guild with ids above `after`, in id
+    /// order.
+    pub async fn guild_members_after(
+        &self,
+        guild_id: &str,
+        after: Option<&str>,
+        limit: u64,
+    ) -> Result<Vec<DiscordMember>> {
+        let guild_id_num = snowflake::parse_id("guild", guild_id)?;
+        let after = after
+            .map(|after| snowflake::parse_id("user", after).map(UserId::new))
+            .transpose()?;
+
+        let http_guard = self.http.read().await;
+        let Some(http) = http_guard.as_ref() else {
+            return Err(DiscordNotReady.into());
+        };
+
+        let members = GuildId::new(guild_id_num)
+            .members(http, Some(limit), after)
+            .await
+            .map_err(|e| anyhow!("failed to list members of guild {}: {}", guild_id, e))?;
+        Ok(members
+            .iter()
+            .map(|member| DiscordMember {
+                user_id: member.user.id.to_string(),
+                display_name: member.display_name().to_string(),
+                avatar_url: member.avatar_url().or_else(|| member.user.avatar_url()),
+                roles: member.roles.iter().map(ToString::to_string).collect(),
+                bot: member.user.bot,
+            })
+            .collect())
+    }
+
+    /// Ids of the users banned from `guild_id`.
+    pub async fn guild_ban_ids(&self, guild_id: &str) -> Result<Vec<String>> {
+        let guild_id_num = snowflake::parse_id("guild", guild_id)?;
+
+        let http_guard = self.http.read().await;
+        let Some(http) = http_guard.as_ref() else {
+            return Err(DiscordNotReady.into());
+        };
+
+        let guild_id = GuildId::new(guild_id_num);
+        let mut ban_ids = Vec::new();
+        let mut after = None;
+        loop {
+            let bans = guild_id
+                .bans(
+                    http,
+                    after.map(UserPagination::After),
+                    Some(MAX_BANS_PER_PAGE),
+                )
+                .await
+                .map_err(|e| anyhow!("failed to list bans of guild {}: {}", guild_id, e))?;
+            ban_ids.extend(bans.iter().map(|ban| ban.user.id.to_string()));
+            match bans.last() {
+                Some(last) if bans.len() == usize::from(MAX_BANS_PER_PAGE) => {
+                    after = Some(last.user.id)
+                }
+                _ => break,
+            }
+        }
+        Ok(ban_ids)
+    }
+
+    /// The newest `action` audit log entry of `guild_id` from the last few
+    /// minutes whose target is `target_id` and whose channel is
+    /// `channel_id`, where given. Self-deletes and similar are not logged
+    /// by Discord, so `None` is common.
+    pub async fn recent_audit_entry(
+        &self,
+        guild_id: &str,
+        action: AuditedAction,
+        target_id: Option<&str>,
+        channel_id: Option<&str>,
+    ) -> Result<Option<AuditAttribution>> {
+        let guild_id_num = snowflake::parse_id("guild", guild_id)?;
+
+        let http_guard = self.http.read().await;
+        let Some(http) = http_guard.as_ref() else {
+            return Err(DiscordNotReady.into());
+        };
+
+        let logs = GuildId::new(guild_id_num)
+            .audit_logs(
+                http,
+                Some(action.audit_action()),
+                None,
+                None,
+                Some(AUDIT_LOG_LOOKUP_LIMIT),
+            )
+            .await
+            .map_err(|e| anyhow!("failed to read audit log of guild {}: {}", guild_id, e))?;
+        let now = chrono::Utc::now().timestamp();
+        let entry = logs.entries.into_iter().find(|entry| {
+            now - entry.id.created_at().unix_timestamp() <= AUDIT_LOG_LOOKBACK_SECONDS
+                && target_id.is_none_or(|target| {
+                    entry.target_id.map(|id| id.to_string()).as_deref() == Some(target)
+                })
+                && channel_id.is_none_or(|channel| {
+                    entry
+                        .options
+                        .as_ref()
+                        .and_then(|options| options.channel_id)
+                        .map(|id| id.to_string())
+                        .as_deref()
+                        == Some(channel)
+                })
+        });
+        Ok(entry.map(|entry| AuditAttribution {
+            actor_id: entry.user_id.to_string(),
+            target_id: entry.target_id.map(|id| id.to_string()),
+            reason: entry.reason,
         }))
     }
 
+    /// Bans `user_id` from `guild_id` without deleting their messages.
+    pub async fn ban_member(&self, guild_id: &str, user_id: &str, reason: &str) -> Result<()> {
+        if self.dry_run("ban", guild_id) {
+            return Ok(());
+        }
+        let guild_id_num = snowflake::parse_id("guild", guild_id)?;
+        let user_id_num = snowflake::parse_id("user", user_id)?;
+
+        let http_guard = self.http.read().await;
+        let Some(http) = http_guard.as_ref() else {
+            return Err(DiscordNotReady.into());
+        };
+
+        GuildId::new(guild_id_num)
+            .ban_with_reason(http, UserId::new(user_id_num), 0, reason)
+            .await
+            .map_err(|e| anyhow!("failed to ban {} from guild {}: {}", user_id, guild_id, e))
+    }
+
+    /// Replaces an approval prompt's text with `content` and removes its
+    /// buttons.
+    pub async fn close_approval_prompt(
+        &self,
+        channel_id: &str,
+        message_id: &str,
+        content: &str,
+    ) -> Result<()> {
+        if self.dry_run("edit_message", channel_id) {
+            return Ok(());
+        }
+        let channel_id_num = snowflake::parse_id("channel", channel_id)?;
+        let message_id_num = snowflake::parse_id("message", message_id)?;
+
+        let http_guard = self.http.read().await;
+        let Some(http) = http_guard.as_ref() else {
+            return Err(DiscordNotReady.into());
+        };
+
+        ChannelId::new(channel_id_num)
+            .edit_message(
+                http,
+                MessageId::new(message_id_num),
+                EditMessage::new().content(content).components(Vec::new()),
+            )
+            .await
+            .map_err(|e| anyhow!("failed to edit approval prompt: {}", e))?;
+        Ok(())
+    }
+
+    /// Pins or unpins a message. Returns `false` when Discord refuses because
+    /// the bot lacks MANAGE_MESSAGES in the channel.
+    pub async fn set_message_pinned(
+        &self,
+        channel_id: &str,
+        message_id: &str,
+        pinned: bool,
+    ) -> Result<bool> {
+        if self.dry_run("set_pinned", channel_id) {
+            return Ok(true);
+        }
+        let channel_id_num = snowflake::parse_id("channel", channel_id)?;
+        let message_id_num = snowflake::parse_id("message", message_id)?;
+
+        let http_guard = self.http.read().await;
+        let Some(http) = http_guard.as_ref() else {
+            return Err(DiscordNotReady.into());
+        };
+
+        let channel = ChannelId::new(channel_id_num);
+        let message = MessageId::new(message_id_num);
+        let result = if pinned {
+            channel.pin(http, message).await
+        } else {
+            channel.unpin(http, message).await
+        };
+        match result {
+            Ok(()) => Ok(true),
+            Err(err) if is_forbidden(&err) => Ok(false),
+            Err(err) => Err(anyhow!(
+                "failed to {} discord message {}: {}",
+                if pinned { "pin" } else { "unpin" },
+                message_id,
+                err
+            )),
+        }
+    }
+
+    /// Starts a thread named `name` on a message and returns its id, which
+    /// Discord makes the message's id.
+    pub async fn create_thread_from_message(
+        &self,
+        channel_id: &str,
+        message_id: &str,
+        name: &str,
+    ) -> Result<String> {
+        if self.dry_run("create_thread", channel_id) {
+            return Ok(dry_run::placeholder_id(""));
+        }
+        let channel_id_num = snowflake::parse_id("channel", channel_id)?;
+        let message_id_num = snowflake::parse_id("message", message_id)?;
+
+        let http_guard = self.http.read().await;
+        let Some(http) = http_guard.as_ref() else {
+            return Err(DiscordNotReady.into());
+        };
+
+        let thread = ChannelId::new(channel_id_num)
+            .create_thread_from_message(
+                http,
+                MessageId::new(message_id_num),
+                CreateThread::new(name),
+            )
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "failed to start a thread on discord message {}: {}",
+                    message_id,
+                    e
+                )
+            })?;
+        self.metadata
+            .upsert_channel(channel_snapshot(&thread))
+            .await;
+        Ok(thread.id.to_string())
+    }
+
+    /// Reacts to a message as the bot. `emoji` is a unicode emoji or a
+    /// custom one written `<:name:id>`.
+    pub async fn add_reaction(
+        &self,
+        channel_id: &str,
+        message_id: &str,
+        emoji: &str,
+    ) -> Result<()> {
+        if self.dry_run("add_reaction", channel_id) {
+            return Ok(());
+        }
+        let channel_id_num = snowflake::parse_id("channel", channel_id)?;
+        let message_id_num = snowflake::parse_id("message", message_id)?;
+        let reaction = ReactionType::try_from(emoji)
+            .map_err(|e| anyhow!("invalid reaction emoji {:?}: {}", emoji, e))?;
+
+        let http_guard = self.http.read().await;
+        let Some(http) = http_guard.as_ref() else {
+            return Err(DiscordNotReady.into());
+        };
+
+        ChannelId::new(channel_id_num)
+            .create_reaction(http, MessageId::new(message_id_num), reaction)
+            .await
+            .map_err(|e| anyhow!("failed to react to discord message {}: {}", message_id, e))
+    }
+
+    /// Deletes a message, through the channel's webhook when the bridge has
+    /// one so its own posts go without Manage Messages. A message that is
+    /// already gone counts as deleted.
+    pub async fn delete_message(&self, channel_id: &str, message_id: &str) -> Result<()> {
+        if self.dry_run("delete_message", channel_id) {
+            return Ok(());
+        }
+        let channel_id_num = snowflake::parse_id("channel", channel_id)?;
+        let message = MessageId::new(snowflake::parse_id("message", message_id)?);
+
+        let http_guard = self.http.read().await;
+        let Some(http) = http_guard.as_ref() else {
+            return Err(DiscordNotReady.into());
+        };
+
+        // Messages in a thread were posted by the parent channel's webhook.
+        let thread_parent = self.thread_parent(http, channel_id_num).await;
+        let webhook_url = self
+            .webhook_cache
+            .read()
+            .await
+            .get(&thread_parent.unwrap_or(channel_id_num).to_string())
+            .map(|info| info.url.clone());
+        let in_thread = thread_parent.map(|_| ChannelId::new(channel_id_num));
+        if let Some(url) = webhook_url
+            && let Ok(webhook) = Webhook::from_url(http, &url).await
+            && webhook
+                .delete_message(http, in_thread, message)
+                .await
+                .is_ok()
+        {
+            return Ok(());
+        }
+
+        match ChannelId::new(channel_id_num)
+            .delete_message(http, message)
+            .await
+        {
+            Ok(()) => Ok(()),
+            Err(serenity::Error::Http(http_err))
+                if http_err.status_code().map(|status| status.as_u16()) == Some(404) =>
+            {
+                debug!("discord message {} was already deleted", message_id);
+                Ok(())
+            }
+            Err(err) => Err(anyhow!(
+                "failed to delete discord message {}: {}",
+                message_id,
+                err
+            )),
+        }
+    }
+
     pub async fn clear_channel_member_overwrite(
         &self,
         channel_id: &str,
         user_id: &str,
     ) -> Result<()> {
-        let channel_id_num: u64 = channel_id
-            .parse()
-            .map_err(|_| anyhow!("invalid channel id: {}", channel_id))?;
-        let user_id_num: u64 = user_id
-            .parse()
-            .map_err(|_| anyhow!("invalid user id: {}", user_id))?;
+        if self.dry_run("set_permissions", channel_id) {
+            return Ok(());
+        }
+        let channel_id_num = snowflake::parse_id("channel", channel_id)?;
+        let user_id_num = snowflake::parse_id("user", user_id)?;
 
         let http_guard = self.http.read().await;
         let Some(http) = http_guard.as_ref() else {
-            return Err(anyhow!("discord http client not available"));
+            return Err(DiscordNotReady.into());
         };
 
         ChannelId::new(channel_id_num)
@@ -1233,16 +3137,15 @@ impl DiscordClient {
         channel_id: &str,
         user_id: &str,
     ) -> Result<()> {
-        let channel_id_num: u64 = channel_id
-            .parse()
-            .map_err(|_| anyhow!("invalid channel id: {}", channel_id))?;
-        let user_id_num: u64 = user_id
-            .parse()
-            .map_err(|_| anyhow!("invalid user id: {}", user_id))?;
+        if self.dry_run("set_permissions", channel_id) {
+            return Ok(());
+        }
+        let channel_id_num = snowflake::parse_id("channel", channel_id)?;
+        let user_id_num = snowflake::parse_id("user", user_id)?;
 
         let http_guard = self.http.read().await;
         let Some(http) = http_guard.as_ref() else {
-            return Err(anyhow!("discord http client not available"));
+            return Err(DiscordNotReady.into());
         };
 
         let overwrite = PermissionOverwrite {
@@ -1260,21 +3163,38 @@ impl DiscordClient {
     }
 
     pub async fn get_channel(&self, channel_id: &str) -> Result<Option<DiscordChannel>> {
-        let channel_id_num: u64 = channel_id
-            .parse()
-            .map_err(|_| anyhow!("invalid channel id: {}", channel_id))?;
+        let channel_id_num = snowflake::parse_id("channel", channel_id)?;
+
+        if let Some(snapshot) = self.metadata.channel(channel_id).await {
+            return Ok(Some(DiscordChannel {
+                id: snapshot.id,
+                name: snapshot.name,
+                guild_id: snapshot.guild_id,
+                topic: snapshot.topic,
+                slowmode_seconds: snapshot.slowmode_seconds,
+                thread_parent_id: snapshot.thread_parent_id,
+                everyone_can_view: snapshot.everyone_can_view,
+            }));
+        }
 
         let http_guard = self.http.read().await;
         let Some(http) = http_guard.as_ref() else {
-            return Err(anyhow!("discord http client not available"));
+            return Err(DiscordNotReady.into());
         };
 
         let channel = match ChannelId::new(channel_id_num).to_channel(http).await {
             Ok(channel) => channel,
-            Err(err) => {
-                warn!("failed to fetch discord channel {}: {}", channel_id, err);
+            Err(err) if is_not_found(&err) => {
+                debug!("discord channel {} does not exist", channel_id);
                 return Ok(None);
             }
+            Err(err) => {
+                return Err(anyhow!(
+                    "failed to fetch discord channel {}: {}",
+                    channel_id,
+                    err
+                ));
+            }
         };
 
         let serenity::all::Channel::Guild(channel) = channel else {
@@ -1285,24 +3205,276 @@ impl DiscordClient {
             return Ok(None);
         };
 
+        let snapshot = channel_snapshot(&channel);
+        self.metadata.upsert_channel(snapshot.clone()).await;
+
         Ok(Some(DiscordChannel {
-            id: channel.id.to_string(),
-            name: channel.name.clone(),
-            guild_id: channel.guild_id.to_string(),
-            topic: channel.topic.clone(),
+            id: snapshot.id,
+            name: snapshot.name,
+            guild_id: snapshot.guild_id,
+            topic: snapshot.topic,
+            slowmode_seconds: snapshot.slowmode_seconds,
+            thread_parent_id: snapshot.thread_parent_id,
+            everyone_can_view: snapshot.everyone_can_view,
         }))
     }
+
+    /// Looks up a guild, caching it. `None` when the guild does not exist
+    /// or the bot is not a member of it.
+    pub async fn get_guild(&self, guild_id: &str) -> Result<Option<GuildSnapshot>> {
+        let guild_id_num = snowflake::parse_id("guild", guild_id)?;
+        if let Some(snapshot) = self.metadata.guild(guild_id).await {
+            return Ok(Some(snapshot));
+        }
+
+        let http_guard = self.http.read().await;
+        let Some(http) = http_guard.as_ref() else {
+            return Err(DiscordNotReady.into());
+        };
+        let guild = match GuildId::new(guild_id_num).to_partial_guild(http).await {
+            Ok(guild) => guild,
+            Err(err) if is_not_found(&err) => {
+                debug!("discord guild {} is not available to the bot", guild_id);
+                return Ok(None);
+            }
+            Err(err) => {
+                return Err(anyhow!(
+                    "failed to fetch discord guild {}: {}",
+                    guild_id,
+                    err
+                ));
+            }
+        };
+        let snapshot = GuildSnapshot {
+            id: guild.id.to_string(),
+            name: guild.name.clone(),
+            icon_url: guild.icon_url(),
+        };
+        self.metadata.upsert_guild(snapshot.clone()).await;
+        Ok(Some(snapshot))
+    }
+
+    /// Looks up the bridge's webhook in `channel_id`, creating it if it is
+    /// missing, so the first message sent there finds it cached.
+    /// Whether the channel, or the parent of a thread, already has our
+    /// webhook. Unlike [`Self::warm_webhook`] this never creates one.
+    pub async fn has_webhook(&self, channel_id: &str) -> Result<bool> {
+        let channel_id = snowflake::parse_id("channel", channel_id)?;
+        let http_guard = self.http.read().await;
+        let Some(http) = http_guard.as_ref() else {
+            return Err(DiscordNotReady.into());
+        };
+        let channel_id = self
+            .thread_parent(http, channel_id)
+            .await
+            .unwrap_or(channel_id);
+        Ok(self.existing_webhook(http, channel_id).await?.is_some())
+    }
+
+    pub async fn warm_webhook(&self, channel_id: &str) -> Result<()> {
+        if self.dry_run("warm_webhook", channel_id) {
+            return Ok(());
+        }
+        let channel_id = snowflake::parse_id("channel", channel_id)?;
+        let http_guard = self.http.read().await;
+        let Some(http) = http_guard.as_ref() else {
+            return Err(DiscordNotReady.into());
+        };
+        self.get_or_create_webhook(http, channel_id).await?;
+        Ok(())
+    }
+
+    /// Looks up a guild role, refreshing the guild's role list from the API
+    /// when the role is not cached.
+    pub async fn get_role(&self, guild_id: &str, role_id: &str) -> Result<Option<RoleSnapshot>> {
+        if let Some(snapshot) = self.metadata.role(role_id).await {
+            return Ok(Some(snapshot));
+        }
+
+        let guild_id_num = snowflake::parse_id("guild", guild_id)?;
+
+        let http_guard = self.http.read().await;
+        let Some(http) = http_guard.as_ref() else {
+            return Err(DiscordNotReady.into());
+        };
+
+        let roles = match GuildId::new(guild_id_num).roles(http).await {
+            Ok(roles) => roles,
+            Err(err) if is_not_found(&err) => {
+                debug!("discord guild {} roles are not accessible", guild_id);
+                return Ok(None);
+            }
+            Err(err) => {
+                return Err(anyhow!(
+                    "failed to fetch roles of discord guild {}: {}",
+                    guild_id,
+                    err
+                ));
+            }
+        };
+
+        let mut found = None;
+        for role in roles.values() {
+            let snapshot = role_snapshot(role);
+            if snapshot.id == role_id {
+                found = Some(snapshot.clone());
+            }
+            self.metadata.upsert_role(snapshot).await;
+        }
+        Ok(found)
+    }
+}
+
+/// Whether a member with `roles` may manage the guild's channels, counting
+/// the `@everyone` role, whose id is the guild's own.
+fn moderates_channels(
+    guild_id: u64,
+    roles: &[u64],
+    role_permissions: &HashMap<u64, Permissions>,
+) -> bool {
+    std::iter::once(&guild_id)
+        .chain(roles)
+        .filter_map(|role| role_permissions.get(role))
+        .any(|permissions| {
+            permissions.intersects(Permissions::MANAGE_CHANNELS | Permissions::ADMINISTRATOR)
+        })
 }
 
 #[cfg(test)]
 mod tests {
-    use serenity::all::{MessageId, Permissions};
+    use std::collections::HashMap;
 
-    use super::{permissions_to_names, unique_message_ids};
+    use serenity::all::{ApplicationFlags, GatewayIntents, GuildId, MessageId, Permissions};
+
+    use super::{
+        DiscordNotReady, automod_modlog_entry, granted_intents, interaction_attribution,
+        is_not_found_status, moderates_channels, partial_member_snapshot, permissions_to_names,
+        send_failure_reason, status_leaves_send_unknown, unique_message_ids,
+    };
+
+    #[test]
+    fn message_members_prefer_their_guild_avatar() {
+        let author: serenity::model::user::User = serde_json::from_value(serde_json::json!({
+            "id": "42",
+            "username": "alice",
+            "discriminator": "0",
+            "avatar": "a1b2c3d4e5f60718293a4b5c6d7e8f90",
+        }))
+        .unwrap();
+        let member = |avatar: Option<&str>| -> serenity::model::guild::PartialMember {
+            serde_json::from_value(serde_json::json!({
+                "deaf": false,
+                "mute": false,
+                "roles": ["7"],
+                "nick": "Ally",
+                "avatar": avatar,
+            }))
+            .unwrap()
+        };
+
+        let snapshot = partial_member_snapshot(
+            GuildId::new(1),
+            &author,
+            &member(Some("0f1e2d3c4b5a69788796a5b4c3d2e1f0")),
+        );
+        assert_eq!(snapshot.user_id, "42");
+        assert_eq!(snapshot.nick.as_deref(), Some("Ally"));
+        assert_eq!(snapshot.roles, vec!["7".to_string()]);
+        assert!(
+            snapshot
+                .avatar_url
+                .unwrap()
+                .contains("/guilds/1/users/42/avatars/0f1e2d3c4b5a69788796a5b4c3d2e1f0")
+        );
+
+        let snapshot = partial_member_snapshot(GuildId::new(1), &author, &member(None));
+        assert_eq!(snapshot.avatar_url, author.avatar_url());
+    }
+
+    #[test]
+    fn interaction_output_names_the_command_and_its_user() {
+        assert_eq!(
+            interaction_attribution("Dice", Some("roll"), "alice"),
+            "Dice (command: /roll by alice)"
+        );
+        assert_eq!(
+            interaction_attribution("Dice", None, "alice"),
+            "Dice (interaction by alice)"
+        );
+    }
+
+    #[test]
+    fn channel_managers_count_as_moderators() {
+        let role_permissions = HashMap::from([
+            (1, Permissions::SEND_MESSAGES),
+            (2, Permissions::MANAGE_CHANNELS),
+            (3, Permissions::ADMINISTRATOR),
+        ]);
+        assert!(!moderates_channels(1, &[], &role_permissions));
+        assert!(moderates_channels(1, &[2], &role_permissions));
+        assert!(moderates_channels(1, &[3], &role_permissions));
+        assert!(!moderates_channels(1, &[4], &role_permissions));
+        assert!(moderates_channels(2, &[], &role_permissions));
+    }
+
+    #[test]
+    fn is_not_found_only_matches_missing_or_hidden_resources() {
+        assert!(is_not_found_status(Some(404)));
+        assert!(is_not_found_status(Some(403)));
+        assert!(!is_not_found_status(Some(500)));
+        assert!(!is_not_found_status(Some(429)));
+        assert!(!is_not_found_status(None));
+    }
+
+    #[test]
+    fn not_ready_is_found_behind_context() {
+        let err = anyhow::Error::from(DiscordNotReady).context("failed to send message");
+        assert!(DiscordNotReady::is_cause_of(&err));
+        assert!(!DiscordNotReady::is_cause_of(&anyhow::anyhow!(
+            "discord http client not available"
+        )));
+    }
+
+    #[test]
+    fn only_granted_privileged_intents_are_requested() {
+        let intents = granted_intents(Some(
+            ApplicationFlags::GATEWAY_GUILD_MEMBERS_LIMITED
+                | ApplicationFlags::GATEWAY_MESSAGE_CONTENT,
+        ));
+        assert!(intents.contains(GatewayIntents::GUILD_MEMBERS));
+        assert!(intents.contains(GatewayIntents::MESSAGE_CONTENT));
+        assert!(!intents.contains(GatewayIntents::GUILD_PRESENCES));
+        assert!(intents.contains(GatewayIntents::non_privileged()));
+
+        assert_eq!(
+            granted_intents(Some(ApplicationFlags::empty())),
+            GatewayIntents::non_privileged()
+        );
+        assert_eq!(granted_intents(None), GatewayIntents::all());
+    }
+
+    #[test]
+    fn send_failures_are_labelled_by_status() {
+        assert_eq!(send_failure_reason(Some(403)), "missing_permission");
+        assert_eq!(send_failure_reason(Some(404)), "not_found");
+        assert_eq!(send_failure_reason(Some(429)), "rate_limited");
+        assert_eq!(send_failure_reason(Some(500)), "http_error");
+        assert_eq!(send_failure_reason(None), "other");
+    }
+
+    #[test]
+    fn only_server_errors_leave_a_send_unknown() {
+        assert!(status_leaves_send_unknown(Some(500)));
+        assert!(status_leaves_send_unknown(Some(503)));
+        assert!(status_leaves_send_unknown(None));
+        assert!(!status_leaves_send_unknown(Some(400)));
+        assert!(!status_leaves_send_unknown(Some(429)));
+    }
 
     #[test]
     fn permissions_to_names_maps_expected_flags() {
-        let perms = Permissions::MANAGE_WEBHOOKS
+        let perms = Permissions::MANAGE_GUILD
+            | Permissions::MANAGE_WEBHOOKS
             | Permissions::MANAGE_CHANNELS
             | Permissions::BAN_MEMBERS
             | Permissions::KICK_MEMBERS;
@@ -1313,7 +3485,8 @@ mod tests {
         assert!(names.contains("MANAGE_CHANNELS"));
         assert!(names.contains("BAN_MEMBERS"));
         assert!(names.contains("KICK_MEMBERS"));
-        assert_eq!(names.len(), 4);
+        assert!(names.contains("MANAGE_GUILD"));
+        assert_eq!(names.len(), 5);
     }
 
     #[test]
@@ -1325,10 +3498,33 @@ mod tests {
     #[test]
     fn permissions_to_names_maps_administrator_to_command_permissions() {
         let names = permissions_to_names(Permissions::ADMINISTRATOR);
+        assert!(names.contains("MANAGE_GUILD"));
         assert!(names.contains("MANAGE_WEBHOOKS"));
         assert!(names.contains("MANAGE_CHANNELS"));
         assert!(names.contains("BAN_MEMBERS"));
         assert!(names.contains("KICK_MEMBERS"));
+        assert!(names.contains("MENTION_EVERYONE"));
+    }
+
+    #[test]
+    fn automod_executions_become_modlog_entries() {
+        let execution = serde_json::from_value(serde_json::json!({
+            "guild_id": "10",
+            "action": { "type": 3, "metadata": { "duration_seconds": 60 } },
+            "rule_id": "30",
+            "rule_trigger_type": 1,
+            "user_id": "20",
+            "channel_id": "40",
+            "content": "",
+            "matched_keyword": "spam*",
+        }))
+        .unwrap();
+
+        let content = automod_modlog_entry(&execution).content();
+        assert_eq!(
+            content["body"],
+            "#modlog #automod guild: 10\ntarget: 20\nchannel: 40\nrule: 30\ntrigger: keyword\nmatched: spam*\nresponse: timeout for 60s"
+        );
     }
 
     #[test]
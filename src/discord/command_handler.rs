@@ -1,6 +1,7 @@
 use std::collections::HashSet;
 
-use crate::parsers::parse_prefixed_command;
+use crate::config::MatrixPresenceState;
+use crate::parsers::{CommandPermission, CommandSpec, DISCORD_COMMANDS, parse_prefixed_command};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ModerationAction {
@@ -24,6 +25,17 @@ pub enum DiscordCommandOutcome {
         guild_id: String,
         channel_id: String,
     },
+    /// Mirror the bridged Matrix room's bans onto the guild; `dry_run` only
+    /// reports who would be banned.
+    SyncBansRequested {
+        dry_run: bool,
+    },
+    /// Report the channel's mapping and bridging health.
+    StatusRequested,
+    /// Pin the sender's Matrix presence; `None` follows Discord again.
+    PresenceOverrideRequested {
+        presence: Option<MatrixPresenceState>,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -33,7 +45,9 @@ pub struct DiscordCommandHandler {
 
 impl Default for DiscordCommandHandler {
     fn default() -> Self {
-        Self { prefix: "!matrix" }
+        Self {
+            prefix: DISCORD_COMMANDS.prefix,
+        }
     }
 }
 
@@ -57,71 +71,78 @@ impl DiscordCommandHandler {
             None => return DiscordCommandOutcome::Ignored,
         };
 
-        match parsed.command.as_str() {
-            "help" => DiscordCommandOutcome::Reply(
-                self.render_help(parsed.args.first().map(String::as_str)),
-            ),
-            "approve" => {
-                if !has_all_permissions(granted_permissions, &["MANAGE_WEBHOOKS"]) {
-                    return permission_denied();
+        let command = parsed.command.as_str();
+        if command == "help" {
+            return DiscordCommandOutcome::Reply(
+                DISCORD_COMMANDS.render_help(parsed.args.first().map(String::as_str)),
+            );
+        }
+        let Some(spec) = DISCORD_COMMANDS.get(command) else {
+            return DiscordCommandOutcome::Reply(DISCORD_COMMANDS.unknown_command(command));
+        };
+        if !is_permitted(spec, granted_permissions) {
+            return permission_denied();
+        }
+
+        match command {
+            "approve" => DiscordCommandOutcome::ApproveRequested,
+            "deny" => DiscordCommandOutcome::DenyRequested,
+            "bridge" => self.handle_bridge(parsed.args, is_channel_bridged),
+            "unbridge" => {
+                if !is_channel_bridged {
+                    return DiscordCommandOutcome::Reply(
+                        "This channel is not bridged to a plumbed matrix room".to_string(),
+                    );
                 }
-                DiscordCommandOutcome::ApproveRequested
+                DiscordCommandOutcome::UnbridgeRequested
             }
-            "deny" => {
-                if !has_all_permissions(granted_permissions, &["MANAGE_WEBHOOKS"]) {
-                    return permission_denied();
+            "status" => {
+                if !is_channel_bridged {
+                    return DiscordCommandOutcome::Reply(
+                        "This channel is not bridged to a plumbed matrix room".to_string(),
+                    );
                 }
-                DiscordCommandOutcome::DenyRequested
+                DiscordCommandOutcome::StatusRequested
             }
-            "bridge" => self.handle_bridge(parsed.args, granted_permissions, is_channel_bridged),
-            "unbridge" => {
-                if !has_all_permissions(
-                    granted_permissions,
-                    &["MANAGE_WEBHOOKS", "MANAGE_CHANNELS"],
-                ) {
-                    return permission_denied();
-                }
+            "kick" => self.handle_moderation(parsed.args, ModerationAction::Kick),
+            "ban" => self.handle_moderation(parsed.args, ModerationAction::Ban),
+            "unban" => self.handle_moderation(parsed.args, ModerationAction::Unban),
+            "sync-bans" => {
                 if !is_channel_bridged {
                     return DiscordCommandOutcome::Reply(
                         "This channel is not bridged to a plumbed matrix room".to_string(),
                     );
                 }
-                DiscordCommandOutcome::UnbridgeRequested
+                match parsed.args.as_slice() {
+                    [] => DiscordCommandOutcome::SyncBansRequested { dry_run: false },
+                    [flag] if flag == "--dry-run" => {
+                        DiscordCommandOutcome::SyncBansRequested { dry_run: true }
+                    }
+                    _ => DiscordCommandOutcome::Reply(
+                        "**ERROR:** Invalid syntax. Usage: `!matrix sync-bans [--dry-run]`"
+                            .to_string(),
+                    ),
+                }
             }
-            "kick" => self.handle_moderation(
-                parsed.args,
-                granted_permissions,
-                "KICK_MEMBERS",
-                ModerationAction::Kick,
-            ),
-            "ban" => self.handle_moderation(
-                parsed.args,
-                granted_permissions,
-                "BAN_MEMBERS",
-                ModerationAction::Ban,
-            ),
-            "unban" => self.handle_moderation(
-                parsed.args,
-                granted_permissions,
-                "BAN_MEMBERS",
-                ModerationAction::Unban,
-            ),
-            _ => DiscordCommandOutcome::Reply(
-                "**ERROR:** unknown command. Try `!matrix help` to see all commands".to_string(),
-            ),
+            "presence" => match parsed.args.as_slice() {
+                [state] if state.eq_ignore_ascii_case("clear") => {
+                    DiscordCommandOutcome::PresenceOverrideRequested { presence: None }
+                }
+                [state] if MatrixPresenceState::parse(state).is_some() => {
+                    DiscordCommandOutcome::PresenceOverrideRequested {
+                        presence: MatrixPresenceState::parse(state),
+                    }
+                }
+                _ => DiscordCommandOutcome::Reply(
+                    "**ERROR:** Invalid syntax. Usage: `!matrix presence <online|unavailable|offline|clear>`"
+                        .to_string(),
+                ),
+            },
+            _ => DiscordCommandOutcome::Reply(DISCORD_COMMANDS.unknown_command(command)),
         }
     }
 
-    fn handle_bridge(
-        &self,
-        args: Vec<String>,
-        granted_permissions: &HashSet<String>,
-        is_channel_bridged: bool,
-    ) -> DiscordCommandOutcome {
-        if !has_all_permissions(granted_permissions, &["MANAGE_WEBHOOKS", "MANAGE_CHANNELS"]) {
-            return permission_denied();
-        }
-
+    fn handle_bridge(&self, args: Vec<String>, is_channel_bridged: bool) -> DiscordCommandOutcome {
         if is_channel_bridged {
             return DiscordCommandOutcome::Reply(
                 "This channel is already bridged. Use `!matrix unbridge` to remove the bridge first.".to_string(),
@@ -147,13 +168,8 @@ impl DiscordCommandHandler {
     fn handle_moderation(
         &self,
         args: Vec<String>,
-        granted_permissions: &HashSet<String>,
-        needed_permission: &str,
         action: ModerationAction,
     ) -> DiscordCommandOutcome {
-        if !has_all_permissions(granted_permissions, &[needed_permission]) {
-            return permission_denied();
-        }
         let matrix_user = args.join(" ").trim().to_string();
         if matrix_user.is_empty() {
             return DiscordCommandOutcome::Reply(format!(
@@ -166,23 +182,6 @@ impl DiscordCommandHandler {
             matrix_user,
         }
     }
-
-    fn render_help(&self, command: Option<&str>) -> String {
-        match command {
-            Some("approve") => "`!matrix approve`: Approve a pending bridge request".to_string(),
-            Some("deny") => "`!matrix deny`: Deny a pending bridge request".to_string(),
-            Some("bridge") => "`!matrix bridge <guild_id> <channel_id>`: Bridge this channel to a Matrix room".to_string(),
-            Some("kick") => "`!matrix kick <name>`: Kicks a user on the Matrix side".to_string(),
-            Some("ban") => "`!matrix ban <name>`: Bans a user on the Matrix side".to_string(),
-            Some("unban") => "`!matrix unban <name>`: Unbans a user on the Matrix side".to_string(),
-            Some("unbridge") => "`!matrix unbridge`: Unbridge Matrix rooms from this channel".to_string(),
-            Some(_) => "**ERROR:** unknown command! Try `!matrix help` to see all commands"
-                .to_string(),
-            None => {
-                "Available Commands:\n - `!matrix approve`: Approve a pending bridge request\n - `!matrix deny`: Deny a pending bridge request\n - `!matrix bridge <guild_id> <channel_id>`: Bridge this channel to a Matrix room\n - `!matrix kick <name>`: Kicks a user on the Matrix side\n - `!matrix ban <name>`: Bans a user on the Matrix side\n - `!matrix unban <name>`: Unbans a user on the Matrix side\n - `!matrix unbridge`: Unbridge Matrix rooms from this channel".to_string()
-            }
-        }
-    }
 }
 
 fn action_keyword(action: &ModerationAction) -> &'static str {
@@ -193,8 +192,21 @@ fn action_keyword(action: &ModerationAction) -> &'static str {
     }
 }
 
-fn has_all_permissions(granted: &HashSet<String>, required: &[&str]) -> bool {
-    required.iter().all(|perm| granted.contains(*perm))
+/// Whether `granted` lets a member answer a bridge approval, by command or
+/// with the prompt's buttons alike.
+pub fn can_answer_approval(granted: &HashSet<String>) -> bool {
+    DISCORD_COMMANDS
+        .get("approve")
+        .is_some_and(|spec| is_permitted(spec, granted))
+}
+
+/// Whether `granted` covers the Discord permissions `spec` is registered
+/// with.
+fn is_permitted(spec: &CommandSpec, granted: &HashSet<String>) -> bool {
+    match spec.permission {
+        CommandPermission::Discord(required) => required.iter().all(|perm| granted.contains(*perm)),
+        _ => true,
+    }
 }
 
 fn permission_denied() -> DiscordCommandOutcome {
@@ -207,7 +219,10 @@ fn permission_denied() -> DiscordCommandOutcome {
 mod tests {
     use std::collections::HashSet;
 
-    use super::{DiscordCommandHandler, DiscordCommandOutcome, ModerationAction};
+    use super::{
+        DiscordCommandHandler, DiscordCommandOutcome, ModerationAction, can_answer_approval,
+    };
+    use crate::config::MatrixPresenceState;
 
     #[test]
     fn ban_requires_permission() {
@@ -234,6 +249,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn sync_bans_accepts_dry_run_flag() {
+        let handler = DiscordCommandHandler::new();
+        let permissions = HashSet::from(["BAN_MEMBERS".to_string()]);
+        assert_eq!(
+            handler.handle("!matrix sync-bans --dry-run", true, &permissions),
+            DiscordCommandOutcome::SyncBansRequested { dry_run: true }
+        );
+        assert_eq!(
+            handler.handle("!matrix sync-bans", true, &HashSet::new()),
+            DiscordCommandOutcome::Reply("**ERROR:** insufficient permissions to use this command! Try `!matrix help` to see all available commands".to_string()),
+        );
+        assert!(matches!(
+            handler.handle("!matrix sync-bans", false, &permissions),
+            DiscordCommandOutcome::Reply(_)
+        ));
+    }
+
     #[test]
     fn unbridge_requires_both_permissions() {
         let handler = DiscordCommandHandler::new();
@@ -245,6 +278,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn approvals_need_manage_server_by_command_and_button_alike() {
+        let handler = DiscordCommandHandler::new();
+        let webhooks_only = HashSet::from(["MANAGE_WEBHOOKS".to_string()]);
+        assert!(!can_answer_approval(&webhooks_only));
+        assert!(matches!(
+            handler.handle("!matrix approve", true, &webhooks_only),
+            DiscordCommandOutcome::Reply(_)
+        ));
+
+        let manage_server = HashSet::from(["MANAGE_GUILD".to_string()]);
+        assert!(can_answer_approval(&manage_server));
+        assert_eq!(
+            handler.handle("!matrix approve", true, &manage_server),
+            DiscordCommandOutcome::ApproveRequested
+        );
+    }
+
     #[test]
     fn unbridge_rejects_when_not_bridged() {
         let handler = DiscordCommandHandler::new();
@@ -284,4 +335,40 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn status_needs_no_permissions_but_a_bridged_channel() {
+        let handler = DiscordCommandHandler::new();
+        let permissions = HashSet::new();
+        assert_eq!(
+            handler.handle("!matrix status", true, &permissions),
+            DiscordCommandOutcome::StatusRequested
+        );
+        assert_eq!(
+            handler.handle("!matrix status", false, &permissions),
+            DiscordCommandOutcome::Reply(
+                "This channel is not bridged to a plumbed matrix room".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn presence_takes_a_matrix_state_or_clear() {
+        let handler = DiscordCommandHandler::new();
+        let permissions = HashSet::new();
+        assert_eq!(
+            handler.handle("!matrix presence Unavailable", false, &permissions),
+            DiscordCommandOutcome::PresenceOverrideRequested {
+                presence: Some(MatrixPresenceState::Unavailable)
+            }
+        );
+        assert_eq!(
+            handler.handle("!matrix presence clear", true, &permissions),
+            DiscordCommandOutcome::PresenceOverrideRequested { presence: None }
+        );
+        assert!(matches!(
+            handler.handle("!matrix presence busy", true, &permissions),
+            DiscordCommandOutcome::Reply(_)
+        ));
+    }
 }
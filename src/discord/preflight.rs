@@ -0,0 +1,206 @@
+//! Checks an outbound message against Discord's size limits before it is
+//! sent, so an oversized message fails with an explanation the sender can
+//! act on instead of an API error.
+
+use super::embed::DiscordEmbed;
+
+pub const MAX_CONTENT_CHARS: usize = 2000;
+pub const MAX_ATTACHMENTS: usize = 10;
+pub const MAX_EMBED_TITLE_CHARS: usize = 256;
+pub const MAX_EMBED_DESCRIPTION_CHARS: usize = 4096;
+pub const MAX_EMBED_FIELDS: usize = 25;
+pub const MAX_EMBED_FIELD_NAME_CHARS: usize = 256;
+pub const MAX_EMBED_FIELD_VALUE_CHARS: usize = 1024;
+pub const MAX_EMBED_FOOTER_CHARS: usize = 2048;
+pub const MAX_EMBED_AUTHOR_CHARS: usize = 256;
+/// The limit on all of an embed's text taken together.
+pub const MAX_EMBED_TOTAL_CHARS: usize = 6000;
+
+/// A message Discord would reject as too large.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum PreflightError {
+    #[error("message is {length} characters long, Discord allows {limit}")]
+    ContentTooLong { length: usize, limit: usize },
+    #[error("message has {count} attachments, Discord allows {limit}")]
+    TooManyAttachments { count: usize, limit: usize },
+    #[error("embed {part} is {length} characters long, Discord allows {limit}")]
+    EmbedPartTooLong {
+        part: &'static str,
+        length: usize,
+        limit: usize,
+    },
+    #[error("embed has {count} fields, Discord allows {limit}")]
+    TooManyEmbedFields { count: usize, limit: usize },
+    #[error("embed text is {length} characters long in total, Discord allows {limit}")]
+    EmbedTooLong { length: usize, limit: usize },
+}
+
+impl PreflightError {
+    pub fn find_in(err: &anyhow::Error) -> Option<&Self> {
+        err.chain().find_map(|cause| cause.downcast_ref::<Self>())
+    }
+
+    /// What to tell the Matrix user whose message was not sent.
+    pub fn sender_notice(&self) -> String {
+        let advice = match self {
+            Self::ContentTooLong { .. } => "Please split it into shorter messages.",
+            Self::TooManyAttachments { .. } => "Please send fewer files at once.",
+            _ => "Please shorten it.",
+        };
+        format!("Your message was not sent to Discord: {self}. {advice}")
+    }
+}
+
+pub fn check_message(
+    content: &str,
+    embed: Option<&DiscordEmbed>,
+    attachments: usize,
+) -> Result<(), PreflightError> {
+    let length = content.chars().count();
+    if length > MAX_CONTENT_CHARS {
+        return Err(PreflightError::ContentTooLong {
+            length,
+            limit: MAX_CONTENT_CHARS,
+        });
+    }
+    if attachments > MAX_ATTACHMENTS {
+        return Err(PreflightError::TooManyAttachments {
+            count: attachments,
+            limit: MAX_ATTACHMENTS,
+        });
+    }
+    match embed {
+        Some(embed) => check_embed(embed),
+        None => Ok(()),
+    }
+}
+
+pub fn check_embed(embed: &DiscordEmbed) -> Result<(), PreflightError> {
+    if embed.fields.len() > MAX_EMBED_FIELDS {
+        return Err(PreflightError::TooManyEmbedFields {
+            count: embed.fields.len(),
+            limit: MAX_EMBED_FIELDS,
+        });
+    }
+
+    let mut parts = vec![
+        ("title", embed.title.as_deref(), MAX_EMBED_TITLE_CHARS),
+        (
+            "description",
+            embed.description.as_deref(),
+            MAX_EMBED_DESCRIPTION_CHARS,
+        ),
+        (
+            "footer",
+            embed.footer.as_ref().map(|footer| footer.text.as_str()),
+            MAX_EMBED_FOOTER_CHARS,
+        ),
+        (
+            "author name",
+            embed.author.as_ref().map(|author| author.name.as_str()),
+            MAX_EMBED_AUTHOR_CHARS,
+        ),
+    ];
+    for field in &embed.fields {
+        parts.push((
+            "field name",
+            Some(field.name.as_str()),
+            MAX_EMBED_FIELD_NAME_CHARS,
+        ));
+        parts.push((
+            "field value",
+            Some(field.value.as_str()),
+            MAX_EMBED_FIELD_VALUE_CHARS,
+        ));
+    }
+
+    let mut total = 0;
+    for (part, text, limit) in parts {
+        let length = text.map_or(0, |text| text.chars().count());
+        if length > limit {
+            return Err(PreflightError::EmbedPartTooLong {
+                part,
+                length,
+                limit,
+            });
+        }
+        total += length;
+    }
+    if total > MAX_EMBED_TOTAL_CHARS {
+        return Err(PreflightError::EmbedTooLong {
+            length: total,
+            limit: MAX_EMBED_TOTAL_CHARS,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_and_attachments_are_limited() {
+        assert!(check_message(&"a".repeat(MAX_CONTENT_CHARS), None, 0).is_ok());
+        // Characters count, not bytes.
+        assert!(check_message(&"é".repeat(MAX_CONTENT_CHARS), None, 0).is_ok());
+        assert_eq!(
+            check_message(&"a".repeat(MAX_CONTENT_CHARS + 1), None, 0),
+            Err(PreflightError::ContentTooLong {
+                length: 2001,
+                limit: 2000
+            })
+        );
+        assert_eq!(
+            check_message("hi", None, 11),
+            Err(PreflightError::TooManyAttachments {
+                count: 11,
+                limit: 10
+            })
+        );
+    }
+
+    #[test]
+    fn embed_parts_fields_and_total_are_limited() {
+        let long_title = DiscordEmbed::new().title("t".repeat(257));
+        assert_eq!(
+            check_embed(&long_title),
+            Err(PreflightError::EmbedPartTooLong {
+                part: "title",
+                length: 257,
+                limit: 256
+            })
+        );
+
+        let mut many_fields = DiscordEmbed::new();
+        for i in 0..26 {
+            many_fields = many_fields.field(format!("f{i}"), "v", false);
+        }
+        assert!(matches!(
+            check_embed(&many_fields),
+            Err(PreflightError::TooManyEmbedFields { count: 26, .. })
+        ));
+
+        let mut too_long = DiscordEmbed::new().description("d".repeat(4000));
+        for _ in 0..3 {
+            too_long = too_long.field("name", "v".repeat(1000), false);
+        }
+        assert!(matches!(
+            check_embed(&too_long),
+            Err(PreflightError::EmbedTooLong { length: 7012, .. })
+        ));
+    }
+
+    #[test]
+    fn notice_explains_the_limit() {
+        let err = anyhow::Error::new(PreflightError::ContentTooLong {
+            length: 2500,
+            limit: 2000,
+        })
+        .context("send failed");
+        let notice = PreflightError::find_in(&err)
+            .expect("preflight error")
+            .sender_notice();
+        assert!(notice.contains("2500 characters long, Discord allows 2000"));
+    }
+}
@@ -0,0 +1,180 @@
+//! Headroom left in Discord's rate limits on the routes the bridge delivers
+//! messages through, as tracked by serenity's ratelimiter.
+
+use std::time::{Duration, SystemTime};
+
+use serenity::all::{ChannelId, MessageId, WebhookId};
+use serenity::http::{RatelimitingBucket, Route};
+
+/// The state of the tightest bucket on one route.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RouteHeadroom {
+    pub limit: i64,
+    pub remaining: i64,
+    /// Until the bucket refills.
+    pub reset_in: Duration,
+}
+
+impl RouteHeadroom {
+    /// Reads a bucket as of `now`; one whose reset time has passed is full
+    /// again even though serenity only refills it on the next request.
+    pub fn from_bucket(
+        limit: i64,
+        remaining: i64,
+        reset: Option<SystemTime>,
+        now: SystemTime,
+    ) -> Self {
+        match reset.and_then(|reset| reset.duration_since(now).ok()) {
+            Some(reset_in) if !reset_in.is_zero() => Self {
+                limit,
+                remaining: remaining.clamp(0, limit),
+                reset_in,
+            },
+            _ => Self {
+                limit,
+                remaining: limit,
+                reset_in: Duration::ZERO,
+            },
+        }
+    }
+
+    /// Remaining requests as a percentage of the limit.
+    pub fn percent(&self) -> u64 {
+        if self.limit <= 0 {
+            return 100;
+        }
+        (self.remaining.max(0) * 100 / self.limit) as u64
+    }
+
+    /// Whichever of the two leaves less room, the later reset on a tie.
+    pub fn tighter(self, other: Self) -> Self {
+        if (other.percent(), std::cmp::Reverse(other.reset_in))
+            < (self.percent(), std::cmp::Reverse(self.reset_in))
+        {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+/// The routes messages to `channel_id` are sent, edited and deleted through,
+/// including its webhook when it has one, with their ratelimit buckets.
+pub fn delivery_buckets(
+    channel_id: u64,
+    webhook_id: Option<u64>,
+) -> Vec<(&'static str, RatelimitingBucket)> {
+    let channel_id = ChannelId::new(channel_id);
+    // Buckets are keyed by the channel or webhook alone.
+    let message_id = MessageId::new(1);
+    let mut buckets = vec![
+        (
+            "channel_messages",
+            Route::ChannelMessages { channel_id }.ratelimiting_bucket(),
+        ),
+        (
+            "channel_message",
+            Route::ChannelMessage {
+                channel_id,
+                message_id,
+            }
+            .ratelimiting_bucket(),
+        ),
+    ];
+    if let Some(webhook_id) = webhook_id {
+        let webhook_id = WebhookId::new(webhook_id);
+        buckets.push((
+            "webhook",
+            Route::WebhookWithToken {
+                webhook_id,
+                token: "",
+            }
+            .ratelimiting_bucket(),
+        ));
+        buckets.push((
+            "webhook_message",
+            Route::WebhookMessage {
+                webhook_id,
+                token: "",
+                message_id,
+            }
+            .ratelimiting_bucket(),
+        ));
+    }
+    buckets
+}
+
+/// Labels a request path by its route: ids and webhook tokens are replaced
+/// by placeholders and the API prefix is dropped.
+pub fn route_template(path: &str) -> String {
+    let path = path
+        .split_once("/api/")
+        .and_then(|(_, versioned)| versioned.split_once('/'))
+        .map_or(path, |(_, route)| route);
+    let mut template = Vec::new();
+    let mut after_webhook_id = false;
+    for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+        let is_id = segment.bytes().all(|byte| byte.is_ascii_digit());
+        if after_webhook_id && !is_id {
+            template.push("{token}");
+        } else if is_id {
+            template.push("{id}");
+        } else {
+            template.push(segment);
+        }
+        after_webhook_id =
+            is_id && template.len() >= 2 && template[template.len() - 2] == "webhooks";
+    }
+    template.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expired_buckets_count_as_full() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        let live = RouteHeadroom::from_bucket(5, 1, Some(now + Duration::from_secs(2)), now);
+        assert_eq!(live.remaining, 1);
+        assert_eq!(live.percent(), 20);
+        assert_eq!(live.reset_in, Duration::from_secs(2));
+
+        let expired = RouteHeadroom::from_bucket(5, 0, Some(now - Duration::from_secs(1)), now);
+        assert_eq!(expired.remaining, 5);
+        assert_eq!(expired.percent(), 100);
+        assert_eq!(RouteHeadroom::from_bucket(5, 0, None, now).percent(), 100);
+
+        assert_eq!(live.tighter(expired), live);
+        assert_eq!(expired.tighter(live), live);
+    }
+
+    #[test]
+    fn webhook_buckets_are_added_when_the_channel_has_one() {
+        assert_eq!(delivery_buckets(10, None).len(), 2);
+        let buckets = delivery_buckets(10, Some(20));
+        assert_eq!(buckets.len(), 4);
+        // Message ids do not split a channel's bucket.
+        assert_eq!(
+            buckets[1].1,
+            Route::ChannelMessage {
+                channel_id: ChannelId::new(10),
+                message_id: MessageId::new(99),
+            }
+            .ratelimiting_bucket()
+        );
+    }
+
+    #[test]
+    fn route_templates_hide_ids_and_tokens() {
+        assert_eq!(
+            route_template("https://discord.com/api/v10/channels/123/messages"),
+            "channels/{id}/messages"
+        );
+        assert_eq!(
+            route_template("https://discord.com/api/v10/webhooks/456/s3cret/messages/789"),
+            "webhooks/{id}/{token}/messages/{id}"
+        );
+        assert_eq!(route_template("/guilds/1/members"), "guilds/{id}/members");
+    }
+}
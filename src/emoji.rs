@@ -1,15 +1,38 @@
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 
 use anyhow::Result;
+use chrono::{NaiveDate, Utc};
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::{Value, json};
 use tracing::{debug, info, warn};
 
-use crate::db::{DatabaseManager, EmojiMapping};
+use crate::db::{DatabaseManager, EmojiMapping, EmojiUsage, EmojiUsageKind};
 use crate::media::MediaHandler;
+use crate::utils::dry_run;
+
+/// MSC2545 image pack state event, one per guild keyed by the guild id.
+pub const EMOTE_PACK_EVENT_TYPE: &str = "im.ponies.room_emotes";
+
+/// A custom emoji as Discord lists it for a guild.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuildEmoji {
+    pub id: String,
+    pub name: String,
+    pub animated: bool,
+}
+
+type UsageKey = (NaiveDate, EmojiUsageKind, String);
 
 pub struct EmojiHandler {
     db: Arc<DatabaseManager>,
     media_handler: Arc<MediaHandler>,
     homeserver_url: String,
+    /// Sent as the bearer token of emoji uploads.
+    access_token: Option<String>,
+    /// Uses counted since the last flush, with the name last seen.
+    usage: Mutex<HashMap<UsageKey, (String, i64)>>,
 }
 
 impl EmojiHandler {
@@ -22,7 +45,55 @@ impl EmojiHandler {
             db,
             media_handler,
             homeserver_url,
+            access_token: None,
+            usage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_access_token(mut self, access_token: String) -> Self {
+        self.access_token = Some(access_token);
+        self
+    }
+
+    /// Counts one bridged use of a custom emoji or sticker. Counts stay in
+    /// memory until [`Self::flush_usage`] adds them to the daily counters.
+    pub fn record_usage(&self, kind: EmojiUsageKind, discord_id: &str, name: &str) {
+        let key = (Utc::now().date_naive(), kind, discord_id.to_string());
+        let mut usage = self.usage.lock();
+        let entry = usage.entry(key).or_insert_with(|| (String::new(), 0));
+        entry.0 = name.to_string();
+        entry.1 += 1;
+    }
+
+    /// Writes the counted uses to the database; returns how many counters
+    /// were updated. On failure the counts are kept for the next flush.
+    pub async fn flush_usage(&self) -> Result<usize> {
+        let pending: Vec<EmojiUsage> = self
+            .usage
+            .lock()
+            .drain()
+            .map(|((day, kind, discord_id), (name, uses))| EmojiUsage {
+                day,
+                kind,
+                discord_id,
+                name,
+                uses,
+            })
+            .collect();
+        if pending.is_empty() {
+            return Ok(0);
         }
+        if let Err(err) = self.db.emoji_store().add_emoji_usage(&pending).await {
+            let mut usage = self.usage.lock();
+            for entry in pending {
+                let kept = usage
+                    .entry((entry.day, entry.kind, entry.discord_id))
+                    .or_insert_with(|| (entry.name, 0));
+                kept.1 += entry.uses;
+            }
+            return Err(err.into());
+        }
+        Ok(pending.len())
     }
 
     pub async fn get_or_upload_emoji(
@@ -31,8 +102,8 @@ impl EmojiHandler {
         emoji_name: &str,
         animated: bool,
     ) -> Result<String> {
-        if !emoji_id.chars().all(|c| c.is_ascii_digit()) {
-            return Err(anyhow::anyhow!("Non-numerical emoji ID: {}", emoji_id));
+        if !crate::utils::snowflake::is_valid(emoji_id) {
+            return Err(anyhow::anyhow!("Invalid emoji ID: {}", emoji_id));
         }
 
         if let Some(cached) = self
@@ -45,8 +116,7 @@ impl EmojiHandler {
             return Ok(cached.mxc_url);
         }
 
-        let ext = if animated { "gif" } else { "png" };
-        let url = format!("https://cdn.discordapp.com/emojis/{}.{}", emoji_id, ext);
+        let url = discord_emoji_url(emoji_id, animated);
 
         info!("Downloading emoji {} from {}", emoji_name, url);
 
@@ -58,6 +128,9 @@ impl EmojiHandler {
             .upload_to_matrix(&media.data, content_type, &media.filename)
             .await?;
 
+        if dry_run::is_placeholder(&mxc_url) {
+            return Ok(mxc_url);
+        }
         let emoji = EmojiMapping::new(
             emoji_id.to_string(),
             emoji_name.to_string(),
@@ -84,12 +157,14 @@ impl EmojiHandler {
         );
 
         let client = reqwest::Client::new();
-        let response = client
+        let mut request = client
             .post(&upload_url)
             .header("Content-Type", content_type)
-            .body(data.to_vec())
-            .send()
-            .await?;
+            .body(data.to_vec());
+        if let Some(access_token) = &self.access_token {
+            request = request.bearer_auth(access_token);
+        }
+        let response = request.send().await?;
 
         if !response.status().is_success() {
             let body = response.text().await.unwrap_or_default();
@@ -106,6 +181,45 @@ impl EmojiHandler {
         Ok(content_uri)
     }
 
+    /// Makes sure every emoji of a guild has a Matrix upload and returns
+    /// `(shortcode, mxc)` pairs. Emoji that are already cached are not
+    /// downloaded again; renamed ones only get their mapping updated.
+    pub async fn sync_guild_emojis(&self, emojis: &[GuildEmoji]) -> Result<Vec<(String, String)>> {
+        let mut synced = Vec::with_capacity(emojis.len());
+        for emoji in emojis {
+            let cached = self
+                .db
+                .emoji_store()
+                .get_emoji_by_discord_id(&emoji.id)
+                .await?;
+            let mxc_url = match cached {
+                Some(mut mapping) => {
+                    if mapping.emoji_name != emoji.name {
+                        mapping.emoji_name = emoji.name.clone();
+                        mapping.updated_at = chrono::Utc::now();
+                        self.db.emoji_store().update_emoji(&mapping).await?;
+                    }
+                    mapping.mxc_url
+                }
+                None => match self
+                    .get_or_upload_emoji(&emoji.id, &emoji.name, emoji.animated)
+                    .await
+                {
+                    Ok(mxc_url) => mxc_url,
+                    Err(err) => {
+                        warn!(
+                            "failed to upload emoji {} ({}): {}",
+                            emoji.name, emoji.id, err
+                        );
+                        continue;
+                    }
+                },
+            };
+            synced.push((emoji.name.clone(), mxc_url));
+        }
+        Ok(synced)
+    }
+
     pub async fn get_emoji_mxc(&self, emoji_id: &str) -> Result<Option<String>> {
         Ok(self
             .db
@@ -115,6 +229,10 @@ impl EmojiHandler {
             .map(|e| e.mxc_url))
     }
 
+    pub async fn get_emoji_by_mxc(&self, mxc_url: &str) -> Result<Option<EmojiMapping>> {
+        Ok(self.db.emoji_store().get_emoji_by_mxc(mxc_url).await?)
+    }
+
     pub async fn delete_emoji(&self, emoji_id: &str) -> Result<()> {
         self.db.emoji_store().delete_emoji(emoji_id).await?;
         info!("Deleted emoji cache for {}", emoji_id);
@@ -122,10 +240,7 @@ impl EmojiHandler {
     }
 
     pub fn emoji_to_matrix_html(&self, mxc_url: &str, emoji_name: &str) -> String {
-        format!(
-            r#"<img data-mx-emoticon src="{}" alt=":{}:" title=":{}:" height="32" width="32" />"#,
-            mxc_url, emoji_name, emoji_name
-        )
+        emoticon_html(mxc_url, emoji_name)
     }
 
     pub fn emoji_to_matrix_plain(&self, emoji_name: &str) -> String {
@@ -133,6 +248,80 @@ impl EmojiHandler {
     }
 }
 
+/// An inline emoticon image showing `src` in place of `:name:`.
+pub fn emoticon_html(src: &str, name: &str) -> String {
+    format!(
+        r#"<img data-mx-emoticon src="{}" alt=":{}:" title=":{}:" height="32" width="32" />"#,
+        src, name, name
+    )
+}
+
+/// A custom emoji's image on Discord's CDN.
+pub fn discord_emoji_url(emoji_id: &str, animated: bool) -> String {
+    let ext = if animated { "gif" } else { "png" };
+    format!("https://cdn.discordapp.com/emojis/{}.{}", emoji_id, ext)
+}
+
+/// Uses of one emoji or sticker over the days asked for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EmojiUsageSummary {
+    pub kind: EmojiUsageKind,
+    pub discord_id: String,
+    pub name: String,
+    pub uses: i64,
+    pub days_used: usize,
+    pub last_used: NaiveDate,
+}
+
+/// Totals the daily counters per emoji or sticker, most used first. The
+/// name is the one from the latest day.
+pub fn summarize_emoji_usage(usage: &[EmojiUsage]) -> Vec<EmojiUsageSummary> {
+    let mut totals: BTreeMap<(EmojiUsageKind, &str), EmojiUsageSummary> = BTreeMap::new();
+    for entry in usage {
+        let summary = totals
+            .entry((entry.kind, &entry.discord_id))
+            .or_insert_with(|| EmojiUsageSummary {
+                kind: entry.kind,
+                discord_id: entry.discord_id.clone(),
+                name: entry.name.clone(),
+                uses: 0,
+                days_used: 0,
+                last_used: entry.day,
+            });
+        summary.uses += entry.uses;
+        summary.days_used += 1;
+        if entry.day >= summary.last_used {
+            summary.last_used = entry.day;
+            summary.name = entry.name.clone();
+        }
+    }
+    let mut summaries: Vec<_> = totals.into_values().collect();
+    summaries.sort_by_key(|summary| std::cmp::Reverse(summary.uses));
+    summaries
+}
+
+/// Content of an MSC2545 room image pack. Discord allows duplicate emoji
+/// names, so later duplicates get a numeric suffix to keep shortcodes unique.
+pub fn emote_pack_content(display_name: &str, emotes: &[(String, String)]) -> Value {
+    let mut images = serde_json::Map::new();
+    for (shortcode, mxc_url) in emotes {
+        let mut key = shortcode.clone();
+        let mut suffix = 2;
+        while images.contains_key(&key) {
+            key = format!("{}~{}", shortcode, suffix);
+            suffix += 1;
+        }
+        images.insert(key, json!({ "url": mxc_url }));
+    }
+    json!({
+        "images": images,
+        "pack": {
+            "display_name": display_name,
+            "usage": ["emoticon"],
+        },
+    })
+}
+
 mod urlencoding {
     pub fn encode(s: &str) -> String {
         url::form_urlencoded::byte_serialize(s.as_bytes()).collect()
@@ -157,6 +346,24 @@ mod tests {
         assert!(html.contains("data-mx-emoticon"));
     }
 
+    #[test]
+    fn emote_pack_content_keeps_duplicate_names_apart() {
+        let content = emote_pack_content(
+            "Guild",
+            &[
+                ("wave".to_string(), "mxc://example.org/a".to_string()),
+                ("wave".to_string(), "mxc://example.org/b".to_string()),
+                ("cat".to_string(), "mxc://example.org/c".to_string()),
+            ],
+        );
+
+        assert_eq!(content["pack"]["display_name"], "Guild");
+        assert_eq!(content["pack"]["usage"][0], "emoticon");
+        assert_eq!(content["images"]["wave"]["url"], "mxc://example.org/a");
+        assert_eq!(content["images"]["wave~2"]["url"], "mxc://example.org/b");
+        assert_eq!(content["images"]["cat"]["url"], "mxc://example.org/c");
+    }
+
     #[test]
     fn emoji_to_matrix_plain_creates_correct_format() {
         let handler = EmojiHandler::new(
@@ -168,4 +375,30 @@ mod tests {
         let plain = handler.emoji_to_matrix_plain("smile");
         assert_eq!(plain, ":smile:");
     }
+
+    #[test]
+    fn usage_is_totalled_per_emoji_most_used_first() {
+        let day = |d| NaiveDate::from_ymd_opt(2026, 10, d).unwrap();
+        let usage = |d, kind, id: &str, name: &str, uses| EmojiUsage {
+            day: day(d),
+            kind,
+            discord_id: id.to_string(),
+            name: name.to_string(),
+            uses,
+        };
+        let summaries = summarize_emoji_usage(&[
+            usage(1, EmojiUsageKind::Emoji, "1", "wave", 2),
+            usage(1, EmojiUsageKind::Sticker, "9", "party", 1),
+            usage(2, EmojiUsageKind::Emoji, "1", "hello", 3),
+            usage(2, EmojiUsageKind::Emoji, "2", "cat", 4),
+        ]);
+
+        let ids: Vec<_> = summaries.iter().map(|s| s.discord_id.as_str()).collect();
+        assert_eq!(ids, ["1", "2", "9"]);
+        assert_eq!(summaries[0].uses, 5);
+        assert_eq!(summaries[0].days_used, 2);
+        assert_eq!(summaries[0].name, "hello");
+        assert_eq!(summaries[0].last_used, day(2));
+        assert_eq!(summaries[2].kind, EmojiUsageKind::Sticker);
+    }
 }
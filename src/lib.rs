@@ -0,0 +1,20 @@
+#![forbid(unsafe_code)]
+#![allow(dead_code)]
+#![allow(unused_imports)]
+#![allow(unused_comparisons)]
+
+pub mod admin;
+pub mod bench;
+pub mod bootstrap;
+pub mod bridge;
+pub mod cache;
+pub mod cli;
+pub mod config;
+pub mod db;
+pub mod discord;
+pub mod emoji;
+pub mod matrix;
+pub mod media;
+pub mod parsers;
+pub mod utils;
+pub mod web;
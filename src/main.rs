@@ -1,42 +1,123 @@
 #![forbid(unsafe_code)]
-#![allow(dead_code)]
-#![allow(unused_imports)]
-#![allow(unused_comparisons)]
 
 use std::sync::Arc;
 
 use anyhow::Result;
-use tracing::{error, info};
-
-mod admin;
-mod bridge;
-mod cache;
-mod cli;
-mod config;
-mod db;
-mod discord;
-mod emoji;
-mod matrix;
-mod media;
-mod parsers;
-mod utils;
-mod web;
-
-use config::Config;
-use web::WebServer;
+use clap::Parser;
+use matrix_bridge_discord::bench::{PipelineBenchOptions, run_pipeline_bench};
+use matrix_bridge_discord::bootstrap::{BootstrapOptions, run_bootstrap};
+use matrix_bridge_discord::cli::{Cli, Commands};
+use matrix_bridge_discord::config::Config;
+use matrix_bridge_discord::web::WebServer;
+use matrix_bridge_discord::{bridge, db, discord, matrix, utils};
+use tracing::{error, info, warn};
 
 #[tokio::main]
 async fn main() -> Result<()> {
     utils::logging::init_tracing();
 
+    let cli = Cli::parse();
+    if let Some(dir) = cli.bootstrap {
+        let report = run_bootstrap(&BootstrapOptions {
+            dir,
+            domain: cli.bootstrap_domain,
+            homeserver_url: cli.bootstrap_homeserver_url,
+            appservice_url: cli.bootstrap_appservice_url,
+            force: cli.bootstrap_force,
+        })?;
+        println!("{report}");
+        return Ok(());
+    }
+    if cli.bench_pipeline {
+        let report = run_pipeline_bench(PipelineBenchOptions {
+            messages: cli.bench_messages,
+            concurrency: cli.bench_concurrency,
+        })
+        .await?;
+        println!("{report}");
+        if let Some(min_throughput) = cli.bench_min_throughput
+            && report.messages_per_sec() < min_throughput
+        {
+            anyhow::bail!(
+                "pipeline throughput {:.1} msgs/sec is below the target of {:.1}",
+                report.messages_per_sec(),
+                min_throughput
+            );
+        }
+        return Ok(());
+    }
+
     let config = Arc::new(Config::load()?);
     info!("matrix-discord bridge starting up");
+    utils::event_log::set_capacity(config.admin_api.debug_events);
+    if config.bridge.dry_run {
+        warn!("dry run enabled: outbound Matrix and Discord calls are logged, not made");
+    }
 
-    let db_manager = Arc::new(db::DatabaseManager::new(&config.database).await?);
-    db_manager.migrate().await?;
+    let chaos = Arc::new(utils::ChaosInjector::new(&config.chaos));
+
+    let db_manager = Arc::new(
+        db::DatabaseManager::new(&config.database)
+            .await?
+            .with_chaos(chaos.clone()),
+    );
+    if let Err(err) = db_manager.migrate().await {
+        utils::WebhookAlerter::new(&config)
+            .raise(
+                utils::AlertCondition::MigrationFailed,
+                &format!("The bridge could not start: {err:#}"),
+            )
+            .await;
+        return Err(err.into());
+    }
+    if let Some(Commands::ImportLegacy { from, dry_run }) = &cli.command {
+        let report = db::legacy_import::import_legacy(&db_manager, from, *dry_run).await?;
+        println!("{report}");
+        return Ok(());
+    }
 
-    let matrix_client = Arc::new(matrix::MatrixAppservice::new(config.clone()).await?);
-    let discord_client = Arc::new(discord::DiscordClient::new(config.clone()).await?);
+    let matrix_client = Arc::new(
+        matrix::MatrixAppservice::new(config.clone())
+            .await?
+            .with_chaos(chaos.clone()),
+    );
+    if cli.hide_ghosts_from_directory {
+        let report =
+            bridge::ghost_directory::hide_existing_ghosts(&matrix_client, &db_manager).await?;
+        println!("{report}");
+        return Ok(());
+    }
+    if let Some(Commands::PurgeUser { discord_id, mxid }) = cli.command {
+        let target = bridge::purge::PurgeTarget::from_ids(discord_id, mxid)?;
+        let report = bridge::purge::purge_user(
+            &matrix_client,
+            &db_manager,
+            &target,
+            "cli",
+            db::AuditSource::Cli,
+        )
+        .await?;
+        println!("{report}");
+        return Ok(());
+    }
+    let discord_client = Arc::new(
+        discord::DiscordClient::new(config.clone())
+            .await?
+            .with_chaos(chaos),
+    );
+    if cli.self_test {
+        let report = bridge::self_test::run_self_test(
+            &matrix_client,
+            &discord_client,
+            cli.self_test_channel.as_deref(),
+        )
+        .await;
+        println!("{report}");
+        if !report.passed() {
+            anyhow::bail!("self-test failed");
+        }
+        return Ok(());
+    }
 
     let mut event_handler = matrix::MatrixEventHandlerImpl::new(matrix_client.clone());
 
@@ -1,23 +1,46 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use matrix_bot_sdk::appservice::{Appservice, AppserviceHandler};
 use matrix_bot_sdk::client::{MatrixAuth, MatrixClient};
 use matrix_bot_sdk::models::CreateRoom;
+use matrix_bot_sdk::models::events::Membership;
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 use tokio::sync::RwLock;
 use tracing::{debug, error, info, warn};
 use url::Url;
 
+use crate::bridge::loop_guard::{BRIDGE_TAG, bridge_tag};
+use crate::cache::AsyncTimedCache;
 use crate::config::Config;
+use crate::emoji::emoticon_html;
+use crate::parsers::formatter;
+use crate::utils::dry_run;
+use crate::utils::event_log::{self, EventSource};
+use crate::utils::{ChaosInjector, ChaosTarget};
+use crate::web::Metrics;
 
 pub mod command_handler;
 pub mod event_handler;
+pub mod homeserver_admin;
+pub mod impersonation;
+pub mod message_profiles;
+pub mod namespaces;
+
+use self::impersonation::ImpersonationGuard;
+use self::message_profiles::{MessageProfiles, apply_profile};
+use self::namespaces::NamespaceFilter;
 
 pub use self::command_handler::{
-    MatrixCommandHandler, MatrixCommandOutcome, MatrixCommandPermission,
+    MatrixCommandHandler, MatrixCommandOutcome, MatrixCommandPermission, MatrixCommandSender,
 };
 pub use self::event_handler::{MatrixEventHandler, MatrixEventHandlerImpl, MatrixEventProcessor};
+pub use self::homeserver_admin::HomeserverAdminClient;
 
 mod urlencoding {
     pub fn encode(s: &str) -> String {
@@ -27,6 +50,7 @@ mod urlencoding {
 
 pub struct BridgeAppserviceHandler {
     processor: Option<Arc<MatrixEventProcessor>>,
+    namespaces: NamespaceFilter,
 }
 
 #[async_trait::async_trait]
@@ -38,6 +62,7 @@ impl AppserviceHandler for BridgeAppserviceHandler {
 
         if let Some(events) = body.get("events").and_then(|v| v.as_array()) {
             for event in events {
+                event_log::record(EventSource::Matrix, || event.clone());
                 let Some(room_id) = event.get("room_id").and_then(|v| v.as_str()) else {
                     continue;
                 };
@@ -47,6 +72,23 @@ impl AppserviceHandler for BridgeAppserviceHandler {
                 let Some(event_type) = event.get("type").and_then(|v| v.as_str()) else {
                     continue;
                 };
+                if let Some(reason) = self
+                    .namespaces
+                    .filter_reason(
+                        event_type,
+                        room_id,
+                        sender,
+                        processor.is_bridged_room(room_id),
+                    )
+                    .await
+                {
+                    debug!(
+                        "matrix event filtered room_id={} sender={} type={} reason={}",
+                        room_id, sender, event_type, reason
+                    );
+                    Metrics::matrix_event_filtered(reason);
+                    continue;
+                }
 
                 let matrix_event = MatrixEvent {
                     event_id: event
@@ -60,7 +102,12 @@ impl AppserviceHandler for BridgeAppserviceHandler {
                         .get("state_key")
                         .and_then(|v| v.as_str())
                         .map(ToOwned::to_owned),
-                    content: event.get("content").cloned(),
+                    content: redaction_content(event),
+                    prev_content: event
+                        .get("unsigned")
+                        .and_then(|unsigned| unsigned.get("prev_content"))
+                        .or_else(|| event.get("prev_content"))
+                        .cloned(),
                     timestamp: event.get("origin_server_ts").map(|v| v.to_string()),
                 };
 
@@ -69,18 +116,72 @@ impl AppserviceHandler for BridgeAppserviceHandler {
                 }
             }
         }
+
+        // EDUs pushed to the appservice (MSC2409); they carry no sender or
+        // event id.
+        if let Some(edus) = body
+            .get("ephemeral")
+            .or_else(|| body.get("de.sorunome.msc2409.ephemeral"))
+            .and_then(Value::as_array)
+        {
+            for edu in edus {
+                event_log::record(EventSource::Matrix, || edu.clone());
+                let (Some(event_type), Some(room_id)) = (
+                    edu.get("type").and_then(Value::as_str),
+                    edu.get("room_id").and_then(Value::as_str),
+                ) else {
+                    continue;
+                };
+                let matrix_event = MatrixEvent {
+                    event_id: None,
+                    event_type: event_type.to_owned(),
+                    room_id: room_id.to_owned(),
+                    sender: String::new(),
+                    state_key: None,
+                    content: edu.get("content").cloned(),
+                    prev_content: None,
+                    timestamp: None,
+                };
+                if let Err(e) = processor.process_event(matrix_event).await {
+                    error!("error processing ephemeral event: {}", e);
+                }
+            }
+        }
         Ok(())
     }
 }
 
+/// The event's content, with the `redacts` of an `m.room.redaction` from a
+/// pre-v11 room moved into it where v11 rooms keep it.
+fn redaction_content(event: &Value) -> Option<Value> {
+    let mut content = event.get("content").cloned();
+    if event.get("type").and_then(Value::as_str) == Some("m.room.redaction")
+        && let Some(redacts) = event.get("redacts")
+        && let Some(Value::Object(fields)) = content.as_mut()
+    {
+        fields.entry("redacts").or_insert_with(|| redacts.clone());
+    }
+    content
+}
+
 #[derive(Clone)]
 pub struct MatrixAppservice {
     config: Arc<Config>,
     pub appservice: Appservice,
     handler: Arc<RwLock<BridgeAppserviceHandler>>,
+    chaos: Arc<ChaosInjector>,
+    /// Set when `ghosts.hide_from_directory` is on.
+    directory_admin: Option<HomeserverAdminClient>,
+    /// Set when `ghosts.per_message_profiles` replaces ghost users.
+    message_profiles: Option<Arc<MessageProfiles>>,
+    /// Marks ghost display names that look like `ghosts.protected_names`.
+    impersonation: ImpersonationGuard,
+    /// Room ids of recently resolved aliases, `None` for aliases that do not
+    /// exist, so aliases repeated across messages are looked up once.
+    alias_cache: Arc<AsyncTimedCache<String, Option<String>>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MatrixEvent {
     pub event_id: Option<String>,
     pub event_type: String,
@@ -88,42 +189,194 @@ pub struct MatrixEvent {
     pub sender: String,
     pub state_key: Option<String>,
     pub content: Option<Value>,
+    /// Previous content of a state event, as sent in `unsigned.prev_content`.
+    pub prev_content: Option<Value>,
     pub timestamp: Option<String>,
 }
 
-fn build_matrix_message_content(
+/// Appended to the formatted body of messages from Discord in rooms that
+/// mark where messages came from.
+/// How long a resolved room alias is trusted before asking the homeserver
+/// again.
+const ALIAS_CACHE_TTL_SECS: u64 = 300;
+
+const ATTRIBUTION_BADGE_HTML: &str =
+    " <font color=\"#5865F2\" data-mx-color=\"#5865F2\"><sub>• via Discord</sub></font>";
+
+/// A text message. `formatted_body` is its HTML where the caller rendered
+/// one; otherwise Discord markdown in `body` is rendered into it.
+fn text_content(
     body: &str,
-    reply_to: Option<&str>,
-    edit_of: Option<&str>,
+    formatted_body: Option<&str>,
+    attribution_badge: bool,
+    determine_code_language: bool,
 ) -> Value {
     let mut content = json!({
         "msgtype": "m.text",
         "body": body,
     });
+    let mut formatted_body = match formatted_body {
+        Some(formatted_body) => formatted_body.to_string(),
+        None => formatter::markdown_to_html(body, determine_code_language),
+    };
+    if attribution_badge || formatted_body != formatter::text_to_html(body) {
+        if attribution_badge {
+            formatted_body.push_str(ATTRIBUTION_BADGE_HTML);
+        }
+        content["format"] = "org.matrix.custom.html".into();
+        content["formatted_body"] = formatted_body.into();
+    }
+    content
+}
 
-    if let Some(reply_id) = reply_to {
-        content["m.relates_to"] = json!({
-            "m.in_reply_to": {
-                "event_id": reply_id
-            }
-        });
+/// What a bridged message relates to: the event it replies to and the root
+/// of the thread (MSC3440) it is posted in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RelatesTo<'a> {
+    pub reply_to: Option<&'a str>,
+    pub thread_root: Option<&'a str>,
+}
+
+impl<'a> RelatesTo<'a> {
+    pub fn reply(reply_to: Option<&'a str>) -> Self {
+        Self {
+            reply_to,
+            thread_root: None,
+        }
+    }
+
+    /// The `m.relates_to` of the content. Messages in a thread that reply
+    /// to nothing in particular fall back to a reply to the root, which is
+    /// what clients without thread support show.
+    fn content(&self) -> Option<Value> {
+        match (self.thread_root, self.reply_to) {
+            (Some(root), reply_to) => Some(json!({
+                "rel_type": "m.thread",
+                "event_id": root,
+                "is_falling_back": reply_to.is_none(),
+                "m.in_reply_to": {
+                    "event_id": reply_to.unwrap_or(root)
+                }
+            })),
+            (None, Some(reply_to)) => Some(json!({
+                "m.in_reply_to": {
+                    "event_id": reply_to
+                }
+            })),
+            (None, None) => None,
+        }
+    }
+}
+
+fn build_matrix_message_content(
+    body: &str,
+    formatted_body: Option<&str>,
+    relates_to: RelatesTo<'_>,
+    edit_of: Option<&str>,
+    attribution_badge: bool,
+    determine_code_language: bool,
+) -> Value {
+    let mut content = text_content(
+        body,
+        formatted_body,
+        attribution_badge,
+        determine_code_language,
+    );
+
+    if let Some(relates_to) = relates_to.content() {
+        content["m.relates_to"] = relates_to;
     }
 
     if let Some(edit_event_id) = edit_of {
-        content["m.new_content"] = json!({
-            "msgtype": "m.text",
-            "body": body,
-        });
+        content["m.new_content"] = text_content(
+            body,
+            formatted_body,
+            attribution_badge,
+            determine_code_language,
+        );
         content["m.relates_to"] = json!({
             "rel_type": "m.replace",
             "event_id": edit_event_id,
         });
         content["body"] = format!("* {body}").into();
+        if let Some(formatted_body) = content["formatted_body"].as_str() {
+            content["formatted_body"] = format!("* {formatted_body}").into();
+        }
     }
 
+    content[BRIDGE_TAG] = bridge_tag();
     content
 }
 
+/// The `createRoom` body for a portal room: the guild's visibility, join
+/// rule and history settings, power level 100 for the bridge bot and 50
+/// for the guild's moderators.
+fn portal_room_request(
+    config: &Config,
+    bot_user_id: &str,
+    discord_channel_id: &str,
+    discord_guild_id: &str,
+    name: &str,
+    topic: Option<&str>,
+    linked_moderators: &[String],
+) -> Value {
+    let options = config.room.portal_options(discord_guild_id);
+    let visibility = options
+        .visibility
+        .as_deref()
+        .unwrap_or(&config.room.default_visibility)
+        .to_lowercase();
+
+    let mut users = serde_json::Map::new();
+    for moderator in &options.moderators {
+        let user_id = if moderator.starts_with('@') {
+            moderator.clone()
+        } else {
+            ghost_user_id(moderator, &config.bridge.domain)
+        };
+        users.insert(user_id, json!(50));
+    }
+    for moderator in linked_moderators {
+        users.insert(moderator.clone(), json!(50));
+    }
+    users.insert(bot_user_id.to_string(), json!(100));
+
+    let mut initial_state = Vec::new();
+    if let Some(join_rule) = &options.join_rule {
+        initial_state.push(json!({
+            "type": "m.room.join_rules",
+            "state_key": "",
+            "content": { "join_rule": join_rule },
+        }));
+    }
+    if let Some(history_visibility) = &options.history_visibility {
+        initial_state.push(json!({
+            "type": "m.room.history_visibility",
+            "state_key": "",
+            "content": { "history_visibility": history_visibility },
+        }));
+    }
+    if options.encryption {
+        initial_state.push(json!({
+            "type": "m.room.encryption",
+            "state_key": "",
+            "content": { "algorithm": "m.megolm.v1.aes-sha2" },
+        }));
+    }
+
+    let mut request = json!({
+        "visibility": if visibility == "public" { "public" } else { "private" },
+        "room_alias_name": format!("_discord_{}", discord_channel_id),
+        "name": name,
+        "power_level_content_override": { "users": users },
+        "initial_state": initial_state,
+    });
+    if let Some(topic) = topic {
+        request["topic"] = json!(topic);
+    }
+    request
+}
+
 fn ghost_user_id(discord_user_id: &str, domain: &str) -> String {
     format!("@_discord_{}:{}", discord_user_id, domain)
 }
@@ -140,10 +393,13 @@ impl MatrixAppservice {
         );
 
         let homeserver_url = Url::parse(&config.bridge.homeserver_url)?;
-        let auth = MatrixAuth::new(&config.registration.appservice_token);
+        let auth = MatrixAuth::new(config.registration.appservice_token.expose_secret());
         let client = MatrixClient::new(homeserver_url, auth);
 
-        let handler = Arc::new(RwLock::new(BridgeAppserviceHandler { processor: None }));
+        let handler = Arc::new(RwLock::new(BridgeAppserviceHandler {
+            processor: None,
+            namespaces: NamespaceFilter::from_config(&config),
+        }));
 
         // Use a wrapper to bridge AppserviceHandler to our internal handler
         struct HandlerWrapper(Arc<RwLock<BridgeAppserviceHandler>>);
@@ -155,24 +411,61 @@ impl MatrixAppservice {
         }
 
         let appservice = Appservice::new(
-            &config.registration.homeserver_token,
-            &config.registration.appservice_token,
+            config.registration.homeserver_token.expose_secret(),
+            config.registration.appservice_token.expose_secret(),
             client,
         )
         .with_appservice_id(&config.registration.bridge_id)
         .with_handler(Arc::new(HandlerWrapper(handler.clone())));
 
+        let directory_admin = if config.ghosts.hide_from_directory {
+            HomeserverAdminClient::from_config(&config)
+        } else {
+            None
+        };
+
+        let message_profiles = config
+            .ghosts
+            .per_message_profiles
+            .then(|| Arc::new(MessageProfiles::new()));
+        let impersonation = ImpersonationGuard::new(
+            &config.ghosts.protected_names,
+            &config.ghosts.impersonation_suffix,
+        );
+
         Ok(Self {
             config,
             appservice,
             handler,
+            chaos: Arc::new(ChaosInjector::disabled()),
+            directory_admin,
+            message_profiles,
+            impersonation,
+            alias_cache: Arc::new(AsyncTimedCache::new(Duration::from_secs(
+                ALIAS_CACHE_TTL_SECS,
+            ))),
         })
     }
 
+    pub fn with_chaos(mut self, chaos: Arc<ChaosInjector>) -> Self {
+        self.chaos = chaos;
+        self
+    }
+
     pub fn config(&self) -> Arc<Config> {
         self.config.clone()
     }
 
+    /// Whether `bridge.dry_run` skips this outbound call.
+    fn dry_run(&self, operation: &'static str, subject: &str) -> bool {
+        dry_run::skip(
+            self.config.bridge.dry_run,
+            ChaosTarget::Matrix,
+            operation,
+            subject,
+        )
+    }
+
     pub fn bot_user_id(&self) -> String {
         format!(
             "@{}:{}",
@@ -180,10 +473,29 @@ impl MatrixAppservice {
         )
     }
 
+    /// The ghost MXID for a Discord user.
+    pub fn ghost_user_id(&self, discord_user_id: &str) -> String {
+        ghost_user_id(discord_user_id, &self.config.bridge.domain)
+    }
+
+    /// The alias the bridge gives the portal room of a Discord channel.
+    pub fn portal_room_alias(&self, discord_channel_id: &str) -> String {
+        format!(
+            "#{}{}:{}",
+            self.config.room.room_alias_prefix, discord_channel_id, self.config.bridge.domain
+        )
+    }
+
     pub fn is_namespaced_user(&self, user_id: &str) -> bool {
         is_namespaced_user(user_id)
     }
 
+    /// Whether Discord users are shown through per-message profiles on the
+    /// bot's messages rather than as ghost users.
+    pub fn uses_per_message_profiles(&self) -> bool {
+        self.message_profiles.is_some()
+    }
+
     async fn ensure_bot_joined_room(&self, room_id: &str) -> Result<bool> {
         let bot_user_id = self.bot_user_id();
         let membership = self
@@ -253,15 +565,34 @@ impl MatrixAppservice {
     ) -> Result<String> {
         let localpart = format!("_discord_{}", discord_user_id);
         let user_id = format!("@{}:{}", localpart, self.config.bridge.domain);
+        if self.dry_run("register_ghost", &user_id) {
+            return Ok(user_id);
+        }
+        let display_name = display_name.map(|name| self.impersonation.guard(name));
+        let display_name = display_name.as_deref();
+        if let Some(profiles) = &self.message_profiles {
+            if let Some(display) = display_name {
+                profiles.set_displayname(&user_id, display);
+            }
+            return Ok(user_id);
+        }
 
         let ghost_client = self.appservice.client.clone();
         ghost_client
             .impersonate_user_id(Some(&user_id), None::<&str>)
             .await;
 
-        let _ = ghost_client
+        let registered = ghost_client
             .password_register(&localpart, "", display_name)
-            .await;
+            .await
+            .is_ok();
+
+        if registered
+            && let Some(admin) = &self.directory_admin
+            && let Err(err) = admin.hide_from_directory(&user_id).await
+        {
+            warn!("{}", err);
+        }
 
         if let Some(display) = display_name {
             let _ = ghost_client.set_display_name(display).await;
@@ -270,38 +601,92 @@ impl MatrixAppservice {
         Ok(user_id)
     }
 
+    /// Creates the portal room for a Discord channel with the creation
+    /// options configured for its guild.
     pub async fn create_room(
         &self,
         discord_channel_id: &str,
+        discord_guild_id: &str,
         name: &str,
         topic: Option<&str>,
+        moderators: &[String],
     ) -> Result<String> {
-        let alias_localpart = format!("_discord_{}", discord_channel_id);
-
-        let visibility = match self.config.room.default_visibility.to_lowercase().as_str() {
-            "public" => Some("public".to_string()),
-            _ => Some("private".to_string()),
-        };
+        if self.dry_run("create_room", discord_channel_id) {
+            return Ok(format!(
+                "{}:{}",
+                dry_run::placeholder_id("!"),
+                self.config.bridge.domain
+            ));
+        }
+        let request = portal_room_request(
+            &self.config,
+            &self.bot_user_id(),
+            discord_channel_id,
+            discord_guild_id,
+            name,
+            topic,
+            moderators,
+        );
+        let url = format!(
+            "{}/_matrix/client/v3/createRoom",
+            self.config.bridge.homeserver_url.trim_end_matches('/')
+        );
+        let response = reqwest::Client::new()
+            .post(&url)
+            .header(
+                "Authorization",
+                format!(
+                    "Bearer {}",
+                    self.config.registration.appservice_token.expose_secret()
+                ),
+            )
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| {
+                anyhow::anyhow!("failed to create room for {}: {}", discord_channel_id, e)
+            })?;
 
-        let opt = CreateRoom {
-            visibility,
-            room_alias_name: Some(alias_localpart),
-            name: Some(name.to_owned()),
-            topic: topic.map(ToOwned::to_owned),
-            ..Default::default()
-        };
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!(
+                "failed to create room for {}: {} - {}",
+                discord_channel_id,
+                status,
+                body
+            ));
+        }
 
-        let room_id = self.appservice.client.create_room(&opt).await?;
-        Ok(room_id)
+        let body: Value = response.json().await?;
+        body.get("room_id")
+            .and_then(Value::as_str)
+            .map(ToOwned::to_owned)
+            .ok_or_else(|| anyhow::anyhow!("createRoom response has no room_id"))
     }
 
     pub async fn send_message(&self, room_id: &str, sender: &str, content: &str) -> Result<()> {
-        self.send_message_with_metadata(room_id, sender, content, &[], None, None)
-            .await
-            .map(|_| ())
+        self.send_message_with_metadata(
+            room_id,
+            sender,
+            content,
+            None,
+            false,
+            RelatesTo::default(),
+            None,
+            None,
+        )
+        .await
+        .map(|_| ())
     }
 
     pub async fn send_notice(&self, room_id: &str, content: &str) -> Result<()> {
+        if self.dry_run("send_notice", room_id) {
+            return Ok(());
+        }
+        self.chaos
+            .inject(ChaosTarget::Matrix, "send_notice")
+            .await?;
         match self.appservice.client.send_notice(room_id, content).await {
             Ok(_) => Ok(()),
             Err(err) => {
@@ -328,27 +713,125 @@ impl MatrixAppservice {
         }
     }
 
+    /// Sends `content` as an `m.room.message` from the bridge bot.
+    pub async fn send_bot_message_content(&self, room_id: &str, content: &Value) -> Result<()> {
+        if self.dry_run("send_notice", room_id) {
+            return Ok(());
+        }
+        self.appservice
+            .client
+            .send_event(room_id, "m.room.message", content)
+            .await
+            .with_context(|| format!("failed to send message room_id={}", room_id))?;
+        Ok(())
+    }
+
+    /// Sends as `sender`. `origin_server_ts` (milliseconds) backdates the
+    /// event through appservice timestamp massaging, for messages delivered
+    /// late from the retry queue. `attribution_badge` ends the formatted
+    /// body with a "via Discord" badge. `formatted_body` is the HTML of
+    /// `body`, when the caller rendered one.
+    #[allow(clippy::too_many_arguments)]
     pub async fn send_message_with_metadata(
         &self,
         room_id: &str,
         sender: &str,
         body: &str,
-        _attachments: &[String],
-        reply_to: Option<&str>,
+        formatted_body: Option<&str>,
+        attribution_badge: bool,
+        relates_to: RelatesTo<'_>,
         edit_of: Option<&str>,
+        origin_server_ts: Option<i64>,
     ) -> Result<String> {
-        let ghost_client = self.appservice.client.clone();
-        ghost_client
-            .impersonate_user_id(Some(sender), None::<&str>)
-            .await;
+        if self.dry_run("send_message", room_id) {
+            return Ok(dry_run::placeholder_id("$"));
+        }
+        self.chaos
+            .inject(ChaosTarget::Matrix, "send_message")
+            .await?;
+        let content = build_matrix_message_content(
+            body,
+            formatted_body,
+            relates_to,
+            edit_of,
+            attribution_badge,
+            self.config.bridge.determine_code_language,
+        );
+        self.send_ghost_message(room_id, sender, &content, origin_server_ts)
+            .await
+    }
 
-        let content = build_matrix_message_content(body, reply_to, edit_of);
+    async fn send_ghost_message(
+        &self,
+        room_id: &str,
+        sender: &str,
+        content: &Value,
+        origin_server_ts: Option<i64>,
+    ) -> Result<String> {
+        // Without ghosts, the bot sends and the author goes in the content.
+        let (sender, content) = match &self.message_profiles {
+            Some(profiles) => {
+                let ghost = if sender.starts_with('@') {
+                    sender.to_string()
+                } else {
+                    self.ghost_user_id(sender)
+                };
+                let mut content = content.clone();
+                apply_profile(&mut content, &profiles.get(&ghost));
+                (self.bot_user_id(), Cow::Owned(content))
+            }
+            None => (sender.to_string(), Cow::Borrowed(content)),
+        };
+        let (sender, content) = (sender.as_str(), content.as_ref());
+        let Some(ts) = origin_server_ts else {
+            let ghost_client = self.appservice.client.clone();
+            ghost_client
+                .impersonate_user_id(Some(sender), None::<&str>)
+                .await;
+            let event_id = ghost_client
+                .send_event(room_id, "m.room.message", content)
+                .await?;
+            return Ok(event_id);
+        };
 
-        let event_id = ghost_client
-            .send_event(room_id, "m.room.message", &content)
-            .await?;
+        let url = format!(
+            "{}/_matrix/client/v3/rooms/{}/send/m.room.message/{}?user_id={}&ts={}",
+            self.config.bridge.homeserver_url.trim_end_matches('/'),
+            urlencoding::encode(room_id),
+            uuid::Uuid::new_v4(),
+            urlencoding::encode(sender),
+            ts
+        );
+        let client = reqwest::Client::new();
+        let response = client
+            .put(&url)
+            .header(
+                "Authorization",
+                format!(
+                    "Bearer {}",
+                    self.config.registration.appservice_token.expose_secret()
+                ),
+            )
+            .json(content)
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to send message to {}: {}", room_id, e))?;
 
-        Ok(event_id)
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!(
+                "failed to send message to {}: {} - {}",
+                room_id,
+                status,
+                body
+            ));
+        }
+        let body: Value = response.json().await?;
+        body.get("event_id")
+            .and_then(Value::as_str)
+            .map(ToOwned::to_owned)
+            .ok_or_else(|| anyhow::anyhow!("missing event_id in send response"))
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -360,39 +843,70 @@ impl MatrixAppservice {
         body: &str,
         url: &str,
         info: Option<&serde_json::Value>,
-        reply_to: Option<&str>,
+        relates_to: RelatesTo<'_>,
+        origin_server_ts: Option<i64>,
     ) -> Result<String> {
-        let ghost_client = self.appservice.client.clone();
-        ghost_client
-            .impersonate_user_id(Some(sender), None::<&str>)
-            .await;
+        if self.dry_run("send_media", room_id) {
+            return Ok(dry_run::placeholder_id("$"));
+        }
+        self.chaos
+            .inject(ChaosTarget::Matrix, "send_media_message")
+            .await?;
 
         let mut content = json!({
             "msgtype": msgtype,
             "body": body,
             "url": url,
         });
+        content[BRIDGE_TAG] = bridge_tag();
 
         if let Some(info) = info {
             content["info"] = info.clone();
         }
 
-        if let Some(reply_event_id) = reply_to {
-            content["m.relates_to"] = json!({
-                "m.in_reply_to": {
-                    "event_id": reply_event_id
-                }
-            });
+        if let Some(relates_to) = relates_to.content() {
+            content["m.relates_to"] = relates_to;
         }
 
-        let event_id = ghost_client
-            .send_event(room_id, "m.room.message", &content)
+        self.send_ghost_message(room_id, sender, &content, origin_server_ts)
+            .await
+    }
+
+    /// Sends an `m.location` message for `geo_uri` as `sender`.
+    pub async fn send_location_message(
+        &self,
+        room_id: &str,
+        sender: &str,
+        body: &str,
+        geo_uri: &str,
+        origin_server_ts: Option<i64>,
+    ) -> Result<String> {
+        if self.dry_run("send_location", room_id) {
+            return Ok(dry_run::placeholder_id("$"));
+        }
+        self.chaos
+            .inject(ChaosTarget::Matrix, "send_location_message")
             .await?;
 
-        Ok(event_id)
+        let mut content = json!({
+            "msgtype": "m.location",
+            "body": body,
+            "geo_uri": geo_uri,
+        });
+        content[BRIDGE_TAG] = bridge_tag();
+
+        self.send_ghost_message(room_id, sender, &content, origin_server_ts)
+            .await
     }
 
     pub async fn upload_media(&self, media: &crate::media::MediaInfo) -> Result<String> {
+        if self.dry_run("upload_media", &media.filename) {
+            return Ok(format!(
+                "mxc://{}/{}",
+                self.config.bridge.domain,
+                dry_run::placeholder_id("")
+            ));
+        }
         use reqwest::Client;
 
         let upload_url = format!(
@@ -408,7 +922,10 @@ impl MatrixAppservice {
             .post(&upload_url)
             .header(
                 "Authorization",
-                format!("Bearer {}", self.config.registration.appservice_token),
+                format!(
+                    "Bearer {}",
+                    self.config.registration.appservice_token.expose_secret()
+                ),
             )
             .header("Content-Type", &media.content_type)
             .body(media.data.clone())
@@ -443,12 +960,106 @@ impl MatrixAppservice {
         Ok(content_uri)
     }
 
+    /// Reacts to `event_id` as the bridge bot and returns the reaction's
+    /// event id.
+    pub async fn send_reaction(&self, room_id: &str, event_id: &str, key: &str) -> Result<String> {
+        if self.dry_run("react", event_id) {
+            return Ok(dry_run::placeholder_id("$"));
+        }
+        self.chaos
+            .inject(ChaosTarget::Matrix, "send_reaction")
+            .await?;
+        let content = json!({
+            "m.relates_to": {
+                "rel_type": "m.annotation",
+                "event_id": event_id,
+                "key": key,
+            }
+        });
+        self.appservice
+            .client
+            .send_event(room_id, "m.reaction", &content)
+            .await
+            .with_context(|| format!("failed to send reaction room_id={}", room_id))
+    }
+
+    /// Reacts to `event_id` as the ghost of `discord_user_id`, or as the bot
+    /// when ghosts are replaced by per-message profiles.
+    pub async fn send_ghost_reaction(
+        &self,
+        room_id: &str,
+        discord_user_id: &str,
+        event_id: &str,
+        key: &str,
+    ) -> Result<String> {
+        if self.message_profiles.is_some() {
+            return self.send_reaction(room_id, event_id, key).await;
+        }
+        if self.dry_run("react", event_id) {
+            return Ok(dry_run::placeholder_id("$"));
+        }
+        self.chaos
+            .inject(ChaosTarget::Matrix, "send_reaction")
+            .await?;
+        let content = json!({
+            "m.relates_to": {
+                "rel_type": "m.annotation",
+                "event_id": event_id,
+                "key": key,
+            }
+        });
+        let ghost = self.ghost_user_id(discord_user_id);
+        let url = format!(
+            "{}/_matrix/client/v3/rooms/{}/send/m.reaction/{}?user_id={}",
+            self.config.bridge.homeserver_url.trim_end_matches('/'),
+            urlencoding::encode(room_id),
+            uuid::Uuid::new_v4(),
+            urlencoding::encode(&ghost)
+        );
+        let response = reqwest::Client::new()
+            .put(&url)
+            .header(
+                "Authorization",
+                format!(
+                    "Bearer {}",
+                    self.config.registration.appservice_token.expose_secret()
+                ),
+            )
+            .json(&content)
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to send reaction to {}: {}", room_id, e))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!(
+                "failed to send reaction to {} as {}: {} - {}",
+                room_id,
+                ghost,
+                status,
+                body
+            ));
+        }
+        let body: Value = response.json().await?;
+        body.get("event_id")
+            .and_then(Value::as_str)
+            .map(ToOwned::to_owned)
+            .ok_or_else(|| anyhow::anyhow!("missing event_id in send response"))
+    }
+
     pub async fn redact_message(
         &self,
         room_id: &str,
         event_id: &str,
         reason: Option<&str>,
     ) -> Result<()> {
+        if self.dry_run("redact", event_id) {
+            return Ok(());
+        }
+        self.chaos
+            .inject(ChaosTarget::Matrix, "redact_message")
+            .await?;
         let content = json!({
             "redacts": event_id,
             "reason": reason.unwrap_or(""),
@@ -460,6 +1071,77 @@ impl MatrixAppservice {
         Ok(())
     }
 
+    /// One page of `sender`'s redactable events in `room_id`, newest first,
+    /// and the token for the next page. State events and events that were
+    /// already redacted are left out.
+    pub async fn get_events_by_sender(
+        &self,
+        room_id: &str,
+        sender: &str,
+        from: Option<&str>,
+    ) -> Result<(Vec<String>, Option<String>)> {
+        let filter = json!({ "senders": [sender], "not_types": ["m.room.redaction"] });
+        let mut url = format!(
+            "{}/_matrix/client/v3/rooms/{}/messages?dir=b&limit=100&filter={}",
+            self.config.bridge.homeserver_url.trim_end_matches('/'),
+            urlencoding::encode(room_id),
+            urlencoding::encode(&filter.to_string())
+        );
+        if let Some(from) = from {
+            url.push_str("&from=");
+            url.push_str(&urlencoding::encode(from));
+        }
+
+        let client = reqwest::Client::new();
+        let response = client
+            .get(&url)
+            .header(
+                "Authorization",
+                format!(
+                    "Bearer {}",
+                    self.config.registration.appservice_token.expose_secret()
+                ),
+            )
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to read messages in {}: {}", room_id, e))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!(
+                "failed to read messages in {}: {} - {}",
+                room_id,
+                status,
+                body
+            ));
+        }
+
+        let body: Value = response.json().await?;
+        let events = body
+            .get("chunk")
+            .and_then(Value::as_array)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .filter(|event| {
+                        event.get("sender").and_then(Value::as_str) == Some(sender)
+                            && event.get("state_key").is_none()
+                            && event.pointer("/unsigned/redacted_because").is_none()
+                    })
+                    .filter_map(|event| event.get("event_id").and_then(Value::as_str))
+                    .map(ToOwned::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let end = body
+            .get("end")
+            .and_then(Value::as_str)
+            .filter(|end| Some(*end) != from)
+            .map(ToOwned::to_owned);
+        Ok((events, end))
+    }
+
     pub async fn check_permission(
         &self,
         user_id: &str,
@@ -494,6 +1176,28 @@ impl MatrixAppservice {
         }
     }
 
+    /// Whether `user_id` may notify the whole room, going by the room's
+    /// `notifications.room` power level (50 unless set).
+    pub async fn can_notify_room(&self, room_id: &str, user_id: &str) -> Result<bool> {
+        let power_levels = self
+            .appservice
+            .client
+            .get_room_state_event(room_id, "m.room.power_levels", "")
+            .await?;
+        let user_level = power_levels
+            .get("users")
+            .and_then(|users| users.get(user_id))
+            .or_else(|| power_levels.get("users_default"))
+            .and_then(Value::as_i64)
+            .unwrap_or(0);
+        let required = power_levels
+            .get("notifications")
+            .and_then(|notifications| notifications.get("room"))
+            .and_then(Value::as_i64)
+            .unwrap_or(50);
+        Ok(user_level >= required)
+    }
+
     pub async fn ensure_ghost_user_registered(
         &self,
         discord_user_id: &str,
@@ -504,6 +1208,37 @@ impl MatrixAppservice {
         Ok(())
     }
 
+    pub async fn get_room_state(
+        &self,
+        room_id: &str,
+        event_type: &str,
+        state_key: &str,
+    ) -> Result<Option<Value>> {
+        Ok(self
+            .appservice
+            .client
+            .get_room_state_event(room_id, event_type, state_key)
+            .await
+            .ok())
+    }
+
+    pub async fn set_room_state(
+        &self,
+        room_id: &str,
+        event_type: &str,
+        state_key: &str,
+        content: &Value,
+    ) -> Result<()> {
+        if self.dry_run("set_room_state", room_id) {
+            return Ok(());
+        }
+        self.appservice
+            .client
+            .send_state_event(room_id, event_type, state_key, content)
+            .await?;
+        Ok(())
+    }
+
     pub async fn get_room_name(&self, room_id: &str) -> Result<Option<String>> {
         let state = self
             .appservice
@@ -535,6 +1270,9 @@ impl MatrixAppservice {
     }
 
     pub async fn set_room_name(&self, room_id: &str, name: &str) -> Result<()> {
+        if self.dry_run("set_room_name", room_id) {
+            return Ok(());
+        }
         let event_content = json!({ "name": name });
         self.appservice
             .client
@@ -544,6 +1282,9 @@ impl MatrixAppservice {
     }
 
     pub async fn set_room_topic(&self, room_id: &str, topic: &str) -> Result<()> {
+        if self.dry_run("set_room_topic", room_id) {
+            return Ok(());
+        }
         let event_content = json!({ "topic": topic });
         self.appservice
             .client
@@ -573,6 +1314,9 @@ impl MatrixAppservice {
         presence: &str,
         status_message: &str,
     ) -> Result<()> {
+        if self.dry_run("set_presence", discord_user_id) || self.message_profiles.is_some() {
+            return Ok(());
+        }
         let user_id = ghost_user_id(discord_user_id, &self.config.bridge.domain);
 
         let ghost_client = self.appservice.client.clone();
@@ -599,6 +1343,9 @@ impl MatrixAppservice {
         typing: bool,
         timeout_ms: Option<u64>,
     ) -> Result<()> {
+        if self.dry_run("set_typing", room_id) || self.message_profiles.is_some() {
+            return Ok(());
+        }
         let user_id = ghost_user_id(discord_user_id, &self.config.bridge.domain);
 
         self.appservice
@@ -609,19 +1356,155 @@ impl MatrixAppservice {
     }
 
     pub async fn set_room_alias(&self, room_id: &str, alias: &str) -> Result<()> {
+        if self.dry_run("set_room_alias", alias) {
+            return Ok(());
+        }
         self.appservice
             .client
             .create_room_alias(alias, room_id)
             .await?;
+        self.alias_cache
+            .insert(alias.to_string(), Some(room_id.to_string()))
+            .await;
         Ok(())
     }
 
+    /// Resolves a room alias to its room id; `None` when the alias does not
+    /// exist. Answers, including missing aliases, are cached for
+    /// [`ALIAS_CACHE_TTL_SECS`]; failed lookups are not.
+    pub async fn resolve_room_alias(&self, alias: &str) -> Result<Option<String>> {
+        if let Some(room_id) = self.alias_cache.get(&alias.to_string()).await {
+            return Ok(room_id);
+        }
+        let room_id = self.fetch_room_alias(alias).await?;
+        self.alias_cache
+            .insert(alias.to_string(), room_id.clone())
+            .await;
+        Ok(room_id)
+    }
+
+    async fn fetch_room_alias(&self, alias: &str) -> Result<Option<String>> {
+        let url = format!(
+            "{}/_matrix/client/v3/directory/room/{}",
+            self.config.bridge.homeserver_url.trim_end_matches('/'),
+            urlencoding::encode(alias)
+        );
+
+        let client = reqwest::Client::new();
+        let response = client
+            .get(&url)
+            .header(
+                "Authorization",
+                format!(
+                    "Bearer {}",
+                    self.config.registration.appservice_token.expose_secret()
+                ),
+            )
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to look up room alias {}: {}", alias, e))?;
+
+        let status = response.status();
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!(
+                "failed to look up room alias {}: {} - {}",
+                alias,
+                status,
+                body
+            ));
+        }
+
+        let body: serde_json::Value = response.json().await?;
+        Ok(body
+            .get("room_id")
+            .and_then(serde_json::Value::as_str)
+            .map(ToOwned::to_owned))
+    }
+
     pub async fn leave_room(&self, room_id: &str) -> Result<()> {
+        if self.dry_run("leave_room", room_id) {
+            return Ok(());
+        }
         self.appservice.client.leave_room(room_id, None).await?;
         Ok(())
     }
 
+    /// Hides an existing ghost from the user directory. Returns `false` when
+    /// `ghosts.hide_from_directory` is off or the homeserver cannot do it.
+    pub async fn hide_ghost_from_directory(&self, user_id: &str) -> Result<bool> {
+        if self.dry_run("hide_from_directory", user_id) {
+            return Ok(false);
+        }
+        match &self.directory_admin {
+            Some(admin) => admin.hide_from_directory(user_id).await,
+            None => Ok(false),
+        }
+    }
+
+    /// Leaves `room_id` as one of the bridge's namespaced users.
+    pub async fn leave_room_as(&self, user_id: &str, room_id: &str) -> Result<()> {
+        if self.dry_run("leave_room", room_id) {
+            return Ok(());
+        }
+        self.membership_as(user_id, room_id, "leave").await
+    }
+
+    /// Joins or leaves `room_id` as `user_id`, asserted through the
+    /// appservice's `user_id` query parameter.
+    async fn membership_as(&self, user_id: &str, room_id: &str, action: &str) -> Result<()> {
+        let url = format!(
+            "{}/_matrix/client/v3/rooms/{}/{}?user_id={}",
+            self.config.bridge.homeserver_url.trim_end_matches('/'),
+            urlencoding::encode(room_id),
+            action,
+            urlencoding::encode(user_id)
+        );
+        let response = reqwest::Client::new()
+            .post(&url)
+            .header(
+                "Authorization",
+                format!(
+                    "Bearer {}",
+                    self.config.registration.appservice_token.expose_secret()
+                ),
+            )
+            .json(&json!({}))
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to {} room {}: {}", action, room_id, e))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!(
+                "failed to {} room {} as {}: {} - {}",
+                action,
+                room_id,
+                user_id,
+                status,
+                body
+            ));
+        }
+        Ok(())
+    }
+
+    pub async fn get_joined_rooms_as(&self, user_id: &str) -> Result<Vec<String>> {
+        let ghost_client = self.appservice.client.clone();
+        ghost_client
+            .impersonate_user_id(Some(user_id), None::<&str>)
+            .await;
+        let rooms = ghost_client.get_joined_rooms().await?;
+        Ok(rooms)
+    }
+
     pub async fn send_text(&self, room_id: &str, content: &str) -> Result<()> {
+        if self.dry_run("send_notice", room_id) {
+            return Ok(());
+        }
         self.appservice.client.send_text(room_id, content).await?;
         Ok(())
     }
@@ -640,12 +1523,35 @@ impl MatrixAppservice {
         Ok(members.into_iter().map(|m| m.user_id).collect())
     }
 
+    /// Users currently joined to `room_id`.
+    pub async fn get_joined_members(&self, room_id: &str) -> Result<Vec<String>> {
+        let members = self
+            .appservice
+            .client
+            .get_room_members(room_id, Some(Membership::Join), None)
+            .await?;
+        Ok(members.into_iter().map(|m| m.user_id).collect())
+    }
+
+    /// Users currently banned from `room_id`.
+    pub async fn get_banned_users(&self, room_id: &str) -> Result<Vec<String>> {
+        let members = self
+            .appservice
+            .client
+            .get_room_members(room_id, Some(Membership::Ban), None)
+            .await?;
+        Ok(members.into_iter().map(|m| m.user_id).collect())
+    }
+
     pub async fn send_read_receipt(
         &self,
         room_id: &str,
         event_id: &str,
         user_id: &str,
     ) -> Result<()> {
+        if self.dry_run("send_read_receipt", room_id) {
+            return Ok(());
+        }
         let ghost_client = self.appservice.client.clone();
         ghost_client
             .impersonate_user_id(Some(user_id), None::<&str>)
@@ -668,7 +1574,10 @@ impl MatrixAppservice {
             .post(&url)
             .header(
                 "Authorization",
-                format!("Bearer {}", self.config.registration.appservice_token),
+                format!(
+                    "Bearer {}",
+                    self.config.registration.appservice_token.expose_secret()
+                ),
             )
             .json(&serde_json::json!({}))
             .send()
@@ -685,6 +1594,13 @@ impl MatrixAppservice {
     }
 
     pub async fn create_dm_room(&self, invite_user: &str) -> Result<String> {
+        if self.dry_run("create_room", invite_user) {
+            return Ok(format!(
+                "{}:{}",
+                dry_run::placeholder_id("!"),
+                self.config.bridge.domain
+            ));
+        }
         use matrix_bot_sdk::models::CreateRoom;
         let options = CreateRoom {
             visibility: Some("private".to_string()),
@@ -696,7 +1612,29 @@ impl MatrixAppservice {
         Ok(room_id)
     }
 
+    /// Creates a private room with only the bridge bot in it.
+    pub async fn create_private_room(&self, name: &str) -> Result<String> {
+        if self.dry_run("create_room", name) {
+            return Ok(format!(
+                "{}:{}",
+                dry_run::placeholder_id("!"),
+                self.config.bridge.domain
+            ));
+        }
+        use matrix_bot_sdk::models::CreateRoom;
+        let options = CreateRoom {
+            visibility: Some("private".to_string()),
+            name: Some(name.to_string()),
+            ..Default::default()
+        };
+        let room_id = self.appservice.client.create_room(&options).await?;
+        Ok(room_id)
+    }
+
     pub async fn invite_user_to_room(&self, room_id: &str, user_id: &str) -> Result<()> {
+        if self.dry_run("invite", room_id) {
+            return Ok(());
+        }
         let url = format!(
             "{}/_matrix/client/v3/rooms/{}/invite",
             self.config.bridge.homeserver_url.trim_end_matches('/'),
@@ -710,7 +1648,10 @@ impl MatrixAppservice {
             .post(&url)
             .header(
                 "Authorization",
-                format!("Bearer {}", self.config.registration.appservice_token),
+                format!(
+                    "Bearer {}",
+                    self.config.registration.appservice_token.expose_secret()
+                ),
             )
             .json(&serde_json::json!({
                 "user_id": user_id
@@ -734,6 +1675,9 @@ impl MatrixAppservice {
         user_id: &str,
         reason: Option<&str>,
     ) -> Result<()> {
+        if self.dry_run("kick", room_id) {
+            return Ok(());
+        }
         let url = format!(
             "{}/_matrix/client/v3/rooms/{}/kick",
             self.config.bridge.homeserver_url.trim_end_matches('/'),
@@ -747,7 +1691,10 @@ impl MatrixAppservice {
             .post(&url)
             .header(
                 "Authorization",
-                format!("Bearer {}", self.config.registration.appservice_token),
+                format!(
+                    "Bearer {}",
+                    self.config.registration.appservice_token.expose_secret()
+                ),
             )
             .json(&serde_json::json!({
                 "user_id": user_id,
@@ -772,6 +1719,9 @@ impl MatrixAppservice {
         user_id: &str,
         reason: Option<&str>,
     ) -> Result<()> {
+        if self.dry_run("ban", room_id) {
+            return Ok(());
+        }
         let url = format!(
             "{}/_matrix/client/v3/rooms/{}/ban",
             self.config.bridge.homeserver_url.trim_end_matches('/'),
@@ -785,7 +1735,10 @@ impl MatrixAppservice {
             .post(&url)
             .header(
                 "Authorization",
-                format!("Bearer {}", self.config.registration.appservice_token),
+                format!(
+                    "Bearer {}",
+                    self.config.registration.appservice_token.expose_secret()
+                ),
             )
             .json(&serde_json::json!({
                 "user_id": user_id,
@@ -805,6 +1758,9 @@ impl MatrixAppservice {
     }
 
     pub async fn unban_user_from_room(&self, room_id: &str, user_id: &str) -> Result<()> {
+        if self.dry_run("unban", room_id) {
+            return Ok(());
+        }
         let url = format!(
             "{}/_matrix/client/v3/rooms/{}/unban",
             self.config.bridge.homeserver_url.trim_end_matches('/'),
@@ -818,7 +1774,10 @@ impl MatrixAppservice {
             .post(&url)
             .header(
                 "Authorization",
-                format!("Bearer {}", self.config.registration.appservice_token),
+                format!(
+                    "Bearer {}",
+                    self.config.registration.appservice_token.expose_secret()
+                ),
             )
             .json(&serde_json::json!({
                 "user_id": user_id
@@ -841,19 +1800,34 @@ impl MatrixAppservice {
         discord_user_id: &str,
         displayname: &str,
     ) -> Result<()> {
+        if self.dry_run("set_ghost_profile", discord_user_id) {
+            return Ok(());
+        }
         let user_id = ghost_user_id(discord_user_id, &self.config.bridge.domain);
+        let displayname = self.impersonation.guard(displayname);
+        if let Some(profiles) = &self.message_profiles {
+            profiles.set_displayname(&user_id, &displayname);
+            return Ok(());
+        }
 
         let ghost_client = self.appservice.client.clone();
         ghost_client
             .impersonate_user_id(Some(&user_id), None::<&str>)
             .await;
 
-        ghost_client.set_display_name(displayname).await?;
+        ghost_client.set_display_name(&displayname).await?;
         Ok(())
     }
 
     pub async fn set_ghost_avatar(&self, discord_user_id: &str, avatar_url: &str) -> Result<()> {
+        if self.dry_run("set_ghost_profile", discord_user_id) {
+            return Ok(());
+        }
         let user_id = ghost_user_id(discord_user_id, &self.config.bridge.domain);
+        if let Some(profiles) = &self.message_profiles {
+            profiles.set_avatar_url(&user_id, avatar_url);
+            return Ok(());
+        }
 
         let ghost_client = self.appservice.client.clone();
         ghost_client
@@ -871,6 +1845,13 @@ impl MatrixAppservice {
         content_type: &str,
         filename: &str,
     ) -> Result<String> {
+        if self.dry_run("upload_media", filename) {
+            return Ok(format!(
+                "mxc://{}/{}",
+                self.config.bridge.domain,
+                dry_run::placeholder_id("")
+            ));
+        }
         let media = crate::media::MediaInfo {
             data: data.to_vec(),
             content_type: content_type.to_string(),
@@ -881,11 +1862,29 @@ impl MatrixAppservice {
     }
 
     pub async fn invite_ghost_to_room(&self, discord_user_id: &str, room_id: &str) -> Result<()> {
+        if self.message_profiles.is_some() {
+            return Ok(());
+        }
         let ghost_user_id = ghost_user_id(discord_user_id, &self.config.bridge.domain);
         self.invite_user_to_room(room_id, &ghost_user_id).await
     }
 
+    /// Invites the ghost and joins the room as the ghost, so it appears in the
+    /// member list before it sends anything.
+    pub async fn join_ghost_to_room(&self, discord_user_id: &str, room_id: &str) -> Result<()> {
+        if self.dry_run("join_ghost", room_id) || self.message_profiles.is_some() {
+            return Ok(());
+        }
+        self.invite_ghost_to_room(discord_user_id, room_id).await?;
+
+        let user_id = ghost_user_id(discord_user_id, &self.config.bridge.domain);
+        self.membership_as(&user_id, room_id, "join").await
+    }
+
     pub async fn kick_ghost_from_room(&self, discord_user_id: &str, room_id: &str) -> Result<()> {
+        if self.message_profiles.is_some() {
+            return Ok(());
+        }
         let ghost_user_id = ghost_user_id(discord_user_id, &self.config.bridge.domain);
         self.kick_user_from_room(room_id, &ghost_user_id, None)
             .await
@@ -897,10 +1896,13 @@ impl MatrixAppservice {
         room_id: &str,
         displayname: &str,
     ) -> Result<()> {
+        if self.dry_run("set_ghost_profile", room_id) || self.message_profiles.is_some() {
+            return Ok(());
+        }
         let user_id = ghost_user_id(discord_user_id, &self.config.bridge.domain);
 
         let content = json!({
-            "displayname": displayname,
+            "displayname": self.impersonation.guard(displayname),
             "membership": "join"
         });
 
@@ -918,6 +1920,9 @@ impl MatrixAppservice {
         room_id: &str,
         avatar_mxc: &str,
     ) -> Result<()> {
+        if self.dry_run("set_ghost_profile", room_id) || self.message_profiles.is_some() {
+            return Ok(());
+        }
         let user_id = ghost_user_id(discord_user_id, &self.config.bridge.domain);
 
         let content = json!({
@@ -933,12 +1938,73 @@ impl MatrixAppservice {
         Ok(())
     }
 
+    /// Sets the ghost's whole member event in `room_id`, as the ghost: its
+    /// name and avatar in this room only, and its Discord roles.
+    pub async fn set_ghost_room_profile(
+        &self,
+        discord_user_id: &str,
+        room_id: &str,
+        displayname: &str,
+        avatar_mxc: Option<&str>,
+        roles: &[String],
+    ) -> Result<()> {
+        if self.dry_run("set_ghost_profile", room_id) || self.message_profiles.is_some() {
+            return Ok(());
+        }
+        let user_id = ghost_user_id(discord_user_id, &self.config.bridge.domain);
+        let mut content = json!({
+            "membership": "join",
+            "displayname": self.impersonation.guard(displayname),
+            "discord_roles": roles,
+        });
+        if let Some(avatar_mxc) = avatar_mxc {
+            content["avatar_url"] = avatar_mxc.into();
+        }
+
+        let url = format!(
+            "{}/_matrix/client/v3/rooms/{}/state/m.room.member/{}?user_id={}",
+            self.config.bridge.homeserver_url.trim_end_matches('/'),
+            urlencoding::encode(room_id),
+            urlencoding::encode(&user_id),
+            urlencoding::encode(&user_id)
+        );
+        let response = reqwest::Client::new()
+            .put(&url)
+            .header(
+                "Authorization",
+                format!(
+                    "Bearer {}",
+                    self.config.registration.appservice_token.expose_secret()
+                ),
+            )
+            .json(&content)
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to set member profile in {}: {}", room_id, e))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!(
+                "failed to set member profile of {} in {}: {} - {}",
+                user_id,
+                room_id,
+                status,
+                body
+            ));
+        }
+        Ok(())
+    }
+
     pub async fn set_ghost_room_roles(
         &self,
         discord_user_id: &str,
         room_id: &str,
         roles: &[String],
     ) -> Result<()> {
+        if self.dry_run("set_ghost_roles", room_id) || self.message_profiles.is_some() {
+            return Ok(());
+        }
         let user_id = ghost_user_id(discord_user_id, &self.config.bridge.domain);
 
         let content = json!({
@@ -955,6 +2021,9 @@ impl MatrixAppservice {
     }
 
     pub async fn set_room_avatar(&self, room_id: &str, avatar_mxc: &str) -> Result<()> {
+        if self.dry_run("set_room_avatar", room_id) {
+            return Ok(());
+        }
         let event_content = json!({ "url": avatar_mxc });
         self.appservice
             .client
@@ -964,6 +2033,9 @@ impl MatrixAppservice {
     }
 
     pub async fn set_room_visibility(&self, room_id: &str, visibility: &str) -> Result<()> {
+        if self.dry_run("set_room_visibility", room_id) {
+            return Ok(());
+        }
         let url = format!(
             "{}/_matrix/client/v3/rooms/{}/state/m.room.join_rules",
             self.config.bridge.homeserver_url.trim_end_matches('/'),
@@ -975,7 +2047,10 @@ impl MatrixAppservice {
             .put(&url)
             .header(
                 "Authorization",
-                format!("Bearer {}", self.config.registration.appservice_token),
+                format!(
+                    "Bearer {}",
+                    self.config.registration.appservice_token.expose_secret()
+                ),
             )
             .json(&serde_json::json!({
                 "join_rule": visibility
@@ -1008,8 +2083,8 @@ impl MatrixAppservice {
         json!({
             "id": self.config.registration.bridge_id,
             "url": format!("http://{}:{}", self.config.bridge.bind_address, self.config.bridge.port),
-            "as_token": self.config.registration.appservice_token,
-            "hs_token": self.config.registration.homeserver_token,
+            "as_token": self.config.registration.appservice_token.expose_secret(),
+            "hs_token": self.config.registration.homeserver_token.expose_secret(),
             "sender_localpart": self.config.registration.sender_localpart,
             "rate_limited": false,
             "namespaces": {
@@ -1029,11 +2104,158 @@ impl MatrixAppservice {
 
 #[cfg(test)]
 mod tests {
-    use super::{build_matrix_message_content, ghost_user_id, is_namespaced_user};
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    use anyhow::Result;
+    use matrix_bot_sdk::appservice::AppserviceHandler;
+    use parking_lot::Mutex;
+    use serde_json::json;
+
+    use super::namespaces::NamespaceFilter;
+    use super::{
+        BridgeAppserviceHandler, MatrixEvent, MatrixEventHandler, MatrixEventProcessor, RelatesTo,
+        build_matrix_message_content, ghost_user_id, is_namespaced_user, portal_room_request,
+        redaction_content,
+    };
+    use crate::config::Config;
+
+    /// Records the type and room of every event it is handed; only
+    /// `!bridged:example.org` is bridged.
+    #[derive(Default)]
+    struct RecordingHandler {
+        seen: Mutex<Vec<(String, String)>>,
+    }
+
+    impl RecordingHandler {
+        fn record(&self, event: &MatrixEvent) -> Result<()> {
+            self.seen
+                .lock()
+                .push((event.event_type.clone(), event.room_id.clone()));
+            Ok(())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl MatrixEventHandler for RecordingHandler {
+        async fn handle_room_message(&self, event: &MatrixEvent) -> Result<()> {
+            self.record(event)
+        }
+        async fn handle_room_member(&self, event: &MatrixEvent) -> Result<()> {
+            self.record(event)
+        }
+        async fn handle_presence(&self, event: &MatrixEvent) -> Result<()> {
+            self.record(event)
+        }
+        async fn handle_room_encryption(&self, event: &MatrixEvent) -> Result<()> {
+            self.record(event)
+        }
+        async fn handle_room_name(&self, event: &MatrixEvent) -> Result<()> {
+            self.record(event)
+        }
+        async fn handle_room_topic(&self, event: &MatrixEvent) -> Result<()> {
+            self.record(event)
+        }
+        async fn handle_room_power_levels(&self, event: &MatrixEvent) -> Result<()> {
+            self.record(event)
+        }
+        async fn handle_room_pinned_events(&self, event: &MatrixEvent) -> Result<()> {
+            self.record(event)
+        }
+        async fn handle_reaction(&self, event: &MatrixEvent) -> Result<()> {
+            self.record(event)
+        }
+        async fn handle_redaction(&self, event: &MatrixEvent) -> Result<()> {
+            self.record(event)
+        }
+        async fn handle_receipt(&self, event: &MatrixEvent) -> Result<()> {
+            self.record(event)
+        }
+        async fn is_bridged_room(&self, room_id: &str) -> Result<bool> {
+            Ok(room_id == "!bridged:example.org")
+        }
+    }
+
+    #[tokio::test]
+    async fn transactions_skip_events_in_rooms_the_bridge_does_not_own() {
+        let recorder = Arc::new(RecordingHandler::default());
+        let handler = BridgeAppserviceHandler {
+            processor: Some(Arc::new(MatrixEventProcessor::with_age_limit(
+                recorder.clone(),
+                0,
+            ))),
+            namespaces: NamespaceFilter::new(&[], &[], "discordbot", "example.org"),
+        };
+        let event = |event_type: &str, room_id: &str, event_id: &str| {
+            json!({
+                "type": event_type,
+                "room_id": room_id,
+                "sender": "@alice:example.org",
+                "event_id": event_id,
+                "content": {},
+            })
+        };
+        let body = json!({
+            "events": [
+                event("m.reaction", "!elsewhere:example.org", "$1"),
+                event("m.room.redaction", "!elsewhere:example.org", "$2"),
+                event("m.reaction", "!bridged:example.org", "$3"),
+                event("m.room.member", "!elsewhere:example.org", "$4"),
+                event("m.room.message", "!elsewhere:example.org", "$5"),
+            ]
+        });
+
+        handler
+            .on_transaction("txn", &body)
+            .await
+            .expect("transaction");
+
+        let seen = recorder.seen.lock().clone();
+        assert_eq!(
+            seen,
+            vec![
+                ("m.reaction".to_string(), "!bridged:example.org".to_string()),
+                (
+                    "m.room.member".to_string(),
+                    "!elsewhere:example.org".to_string()
+                ),
+                (
+                    "m.room.message".to_string(),
+                    "!elsewhere:example.org".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn redaction_content_carries_the_redacted_event() {
+        let pre_v11 = json!({
+            "type": "m.room.redaction",
+            "redacts": "$old",
+            "content": { "reason": "spam" },
+        });
+        assert_eq!(
+            redaction_content(&pre_v11).expect("content")["redacts"],
+            "$old"
+        );
+
+        let v11 = json!({ "type": "m.room.redaction", "content": { "redacts": "$new" } });
+        assert_eq!(redaction_content(&v11).expect("content")["redacts"], "$new");
+
+        let message = json!({ "type": "m.room.message", "redacts": "$x", "content": {} });
+        assert_eq!(redaction_content(&message), Some(json!({})));
+    }
 
     #[test]
     fn message_content_adds_reply_relation() {
-        let content = build_matrix_message_content("hello", Some("$event123"), None);
+        let content = build_matrix_message_content(
+            "hello",
+            None,
+            RelatesTo::reply(Some("$event123")),
+            None,
+            false,
+            false,
+        );
         assert_eq!(content["msgtype"], "m.text");
         assert_eq!(content["body"], "hello");
         assert_eq!(
@@ -1043,14 +2265,191 @@ mod tests {
         assert!(content.get("m.new_content").is_none());
     }
 
+    #[test]
+    fn message_content_adds_thread_relation() {
+        let in_thread = RelatesTo {
+            reply_to: None,
+            thread_root: Some("$root"),
+        };
+        let content = build_matrix_message_content("hello", None, in_thread, None, false, false);
+        assert_eq!(
+            content["m.relates_to"],
+            json!({
+                "rel_type": "m.thread",
+                "event_id": "$root",
+                "is_falling_back": true,
+                "m.in_reply_to": { "event_id": "$root" }
+            })
+        );
+
+        let reply_in_thread = RelatesTo {
+            reply_to: Some("$earlier"),
+            ..in_thread
+        };
+        let content =
+            build_matrix_message_content("hello", None, reply_in_thread, None, false, false);
+        assert_eq!(content["m.relates_to"]["rel_type"], "m.thread");
+        assert_eq!(content["m.relates_to"]["is_falling_back"], false);
+        assert_eq!(
+            content["m.relates_to"]["m.in_reply_to"]["event_id"],
+            "$earlier"
+        );
+    }
+
     #[test]
     fn message_content_adds_edit_relation() {
-        let content = build_matrix_message_content("new body", None, Some("$old_event"));
+        let content = build_matrix_message_content(
+            "new body",
+            None,
+            RelatesTo::default(),
+            Some("$old_event"),
+            false,
+            false,
+        );
         assert_eq!(content["msgtype"], "m.text");
         assert_eq!(content["body"], "* new body");
         assert_eq!(content["m.new_content"]["body"], "new body");
         assert_eq!(content["m.relates_to"]["rel_type"], "m.replace");
         assert_eq!(content["m.relates_to"]["event_id"], "$old_event");
+        assert!(content.get("formatted_body").is_none());
+    }
+
+    #[test]
+    fn rendered_formatted_bodies_are_sent_as_they_are() {
+        let pill = "<a href=\"https://matrix.to/#/@_discord_1:example.org\">@_discord_1</a> &lt;3";
+        let content = build_matrix_message_content(
+            "@_discord_1:example.org <3",
+            Some(pill),
+            RelatesTo::default(),
+            None,
+            false,
+            false,
+        );
+        assert_eq!(content["body"], "@_discord_1:example.org <3");
+        assert_eq!(content["formatted_body"], pill);
+
+        let plain = build_matrix_message_content(
+            "a <b>",
+            Some("a &lt;b&gt;"),
+            RelatesTo::default(),
+            None,
+            false,
+            false,
+        );
+        assert!(plain.get("formatted_body").is_none());
+    }
+
+    #[test]
+    fn discord_markdown_is_rendered_in_the_formatted_body() {
+        let content = build_matrix_message_content(
+            "**hi**\n```\n{\"a\": 1}\n```",
+            None,
+            RelatesTo::default(),
+            None,
+            false,
+            true,
+        );
+        assert_eq!(content["body"], "**hi**\n```\n{\"a\": 1}\n```");
+        assert_eq!(content["format"], "org.matrix.custom.html");
+        assert_eq!(
+            content["formatted_body"],
+            "<strong>hi</strong><pre><code class=\"language-json\">{\"a\": 1}</code></pre>"
+        );
+    }
+
+    #[test]
+    fn attribution_badge_goes_in_the_formatted_body_only() {
+        let content =
+            build_matrix_message_content("a <b>\nc", None, RelatesTo::default(), None, true, false);
+        assert_eq!(content["body"], "a <b>\nc");
+        assert_eq!(content["format"], "org.matrix.custom.html");
+        let formatted_body = content["formatted_body"].as_str().unwrap();
+        assert!(formatted_body.starts_with("a &lt;b&gt;<br>c <font"));
+        assert!(formatted_body.ends_with("<sub>• via Discord</sub></font>"));
+
+        let edit = build_matrix_message_content(
+            "new",
+            None,
+            RelatesTo::default(),
+            Some("$old_event"),
+            true,
+            false,
+        );
+        assert!(
+            edit["formatted_body"]
+                .as_str()
+                .unwrap()
+                .starts_with("* new <font")
+        );
+        assert!(
+            edit["m.new_content"]["formatted_body"]
+                .as_str()
+                .unwrap()
+                .starts_with("new <font")
+        );
+    }
+
+    #[test]
+    fn portal_rooms_use_the_guild_options() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+bridge:
+  domain: "example.org"
+auth:
+  bot_token: "token"
+logging: {}
+database:
+  url: "sqlite://./discord.db"
+room:
+  default_visibility: "public"
+  guilds:
+    "1":
+      visibility: "private"
+      join_rule: "invite"
+      history_visibility: "joined"
+      moderators: ["42", "@mod:example.org"]
+channel: {}
+ghosts: {}
+registration:
+  id: "discord"
+  as_token: "as"
+  hs_token: "hs"
+"#,
+        )
+        .unwrap();
+        let bot = "@_discord_bot:example.org";
+
+        let request = portal_room_request(
+            &config,
+            bot,
+            "100",
+            "1",
+            "[Discord] #general",
+            None,
+            &["@linked:example.org".to_string()],
+        );
+        assert_eq!(request["visibility"], "private");
+        assert_eq!(request["room_alias_name"], "_discord_100");
+        let users = &request["power_level_content_override"]["users"];
+        assert_eq!(users[bot], 100);
+        assert_eq!(users["@_discord_42:example.org"], 50);
+        assert_eq!(users["@mod:example.org"], 50);
+        assert_eq!(users["@linked:example.org"], 50);
+        let state_types: Vec<_> = request["initial_state"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|event| event["type"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            state_types,
+            ["m.room.join_rules", "m.room.history_visibility"]
+        );
+
+        let request = portal_room_request(&config, bot, "200", "2", "other", Some("topic"), &[]);
+        assert_eq!(request["visibility"], "public");
+        assert_eq!(request["topic"], "topic");
+        assert_eq!(request["initial_state"], serde_json::json!([]));
     }
 
     #[test]
@@ -1069,8 +2468,14 @@ mod tests {
 
     #[test]
     fn message_content_prefers_edit_relation_over_reply_relation() {
-        let content =
-            build_matrix_message_content("edited", Some("$reply_target"), Some("$edit_target"));
+        let content = build_matrix_message_content(
+            "edited",
+            None,
+            RelatesTo::reply(Some("$reply_target")),
+            Some("$edit_target"),
+            false,
+            false,
+        );
 
         assert_eq!(content["body"], "* edited");
         assert_eq!(content["m.relates_to"]["rel_type"], "m.replace");
@@ -1,6 +1,18 @@
-use crate::parsers::{parse_guild_and_channel, parse_prefixed_command};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+use crate::config::AttachmentPolicy;
+use crate::db::DeliveryDirection;
+use crate::parsers::{MATRIX_COMMANDS, parse_guild_and_channel, parse_prefixed_command};
 
 const DEFAULT_PROVISIONING_POWER_LEVEL: i64 = 50;
+/// How long `!discord unbridge confirm` is accepted after `!discord unbridge`.
+const DEFAULT_UNBRIDGE_CONFIRMATION_WINDOW: Duration = Duration::from_secs(60);
+/// Longest username Discord accepts for a webhook message.
+const MAX_WEBHOOK_NICK_LEN: usize = 80;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct MatrixCommandPermission {
@@ -10,6 +22,15 @@ pub struct MatrixCommandPermission {
     pub self_service: bool,
 }
 
+/// Who sent a command and where, for commands that keep per-room state.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MatrixCommandSender<'a> {
+    pub room_id: &'a str,
+    pub user_id: &'a str,
+    /// The bridge admin (`bridge.admin_mxid`) may skip confirmations.
+    pub is_admin: bool,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MatrixCommandOutcome {
     Ignored,
@@ -19,6 +40,46 @@ pub enum MatrixCommandOutcome {
         channel_id: String,
     },
     UnbridgeRequested,
+    AutoInviteStatus,
+    AutoInviteRequested {
+        enabled: bool,
+    },
+    BadgeStatus,
+    /// Mark messages from Discord with a "via Discord" badge in this room.
+    BadgeRequested {
+        enabled: bool,
+    },
+    ConfirmStatus,
+    /// React to Matrix messages in this room once they reached Discord.
+    ConfirmRequested {
+        enabled: bool,
+    },
+    AttachmentPolicyStatus,
+    /// Set how attachments going `direction` are carried in this room;
+    /// `None` goes back to the configured default.
+    AttachmentPolicyRequested {
+        direction: DeliveryDirection,
+        policy: Option<AttachmentPolicy>,
+    },
+    RoleKeywordsStatus,
+    /// Set the keyword shown next to mentions of a Discord role in this
+    /// room; `None` clears it.
+    RoleKeywordRequested {
+        role_id: String,
+        keyword: Option<String>,
+    },
+    NickStatus,
+    /// Set the sender's webhook name in this room; `None` clears it.
+    NickRequested {
+        nick: Option<String>,
+    },
+    /// Mirror the Discord guild's bans into this room; `dry_run` only
+    /// reports who would be banned.
+    SyncBansRequested {
+        dry_run: bool,
+    },
+    /// Report the room's mapping and bridging health.
+    StatusRequested,
 }
 
 #[derive(Debug, Clone)]
@@ -26,14 +87,19 @@ pub struct MatrixCommandHandler {
     prefix: &'static str,
     self_service_enabled: bool,
     provisioning_power_level: i64,
+    unbridge_confirmation_window: Duration,
+    /// When each (room, user) last asked to unbridge without confirming yet.
+    pending_unbridges: Arc<Mutex<HashMap<(String, String), Instant>>>,
 }
 
 impl Default for MatrixCommandHandler {
     fn default() -> Self {
         Self {
-            prefix: "!discord",
+            prefix: MATRIX_COMMANDS.prefix,
             self_service_enabled: true,
             provisioning_power_level: DEFAULT_PROVISIONING_POWER_LEVEL,
+            unbridge_confirmation_window: DEFAULT_UNBRIDGE_CONFIRMATION_WINDOW,
+            pending_unbridges: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 }
@@ -48,6 +114,11 @@ impl MatrixCommandHandler {
         }
     }
 
+    pub fn with_unbridge_confirmation_window(mut self, window: Duration) -> Self {
+        self.unbridge_confirmation_window = window;
+        self
+    }
+
     pub fn is_command(&self, message: &str) -> bool {
         message.trim_start().starts_with(self.prefix)
     }
@@ -58,6 +129,24 @@ impl MatrixCommandHandler {
         room_is_bridged: bool,
         permission_check: P,
     ) -> MatrixCommandOutcome
+    where
+        P: Fn(MatrixCommandPermission) -> Result<bool, String>,
+    {
+        self.handle_from(
+            MatrixCommandSender::default(),
+            message,
+            room_is_bridged,
+            permission_check,
+        )
+    }
+
+    pub fn handle_from<P>(
+        &self,
+        sender: MatrixCommandSender<'_>,
+        message: &str,
+        room_is_bridged: bool,
+        permission_check: P,
+    ) -> MatrixCommandOutcome
     where
         P: Fn(MatrixCommandPermission) -> Result<bool, String>,
     {
@@ -68,7 +157,7 @@ impl MatrixCommandHandler {
 
         match parsed.command.as_str() {
             "help" => MatrixCommandOutcome::Reply(
-                self.render_help(parsed.args.first().map(String::as_str)),
+                MATRIX_COMMANDS.render_help(parsed.args.first().map(String::as_str)),
             ),
             "bridge" => {
                 if let Err(reply) = self.ensure_permission(&permission_check) {
@@ -97,10 +186,220 @@ impl MatrixCommandHandler {
                 if !room_is_bridged {
                     return MatrixCommandOutcome::Reply("This room is not bridged.".to_string());
                 }
-                MatrixCommandOutcome::UnbridgeRequested
+                self.handle_unbridge(sender, parsed.args.first().map(String::as_str))
+            }
+            "autoinvite" => {
+                if !room_is_bridged {
+                    return MatrixCommandOutcome::Reply("This room is not bridged.".to_string());
+                }
+                let enabled = match parsed.args.first().map(String::as_str) {
+                    None => return MatrixCommandOutcome::AutoInviteStatus,
+                    Some("on") => true,
+                    Some("off") => false,
+                    Some(_) => {
+                        return MatrixCommandOutcome::Reply(
+                            "Invalid syntax. For more information try `!discord help autoinvite`"
+                                .to_string(),
+                        );
+                    }
+                };
+                if let Err(reply) = self.ensure_permission(&permission_check) {
+                    return MatrixCommandOutcome::Reply(reply);
+                }
+                MatrixCommandOutcome::AutoInviteRequested { enabled }
+            }
+            "badge" => {
+                if !room_is_bridged {
+                    return MatrixCommandOutcome::Reply("This room is not bridged.".to_string());
+                }
+                let enabled = match parsed.args.first().map(String::as_str) {
+                    None => return MatrixCommandOutcome::BadgeStatus,
+                    Some("on") => true,
+                    Some("off") => false,
+                    Some(_) => {
+                        return MatrixCommandOutcome::Reply(
+                            "Invalid syntax. For more information try `!discord help badge`"
+                                .to_string(),
+                        );
+                    }
+                };
+                if let Err(reply) = self.ensure_permission(&permission_check) {
+                    return MatrixCommandOutcome::Reply(reply);
+                }
+                MatrixCommandOutcome::BadgeRequested { enabled }
+            }
+            "confirm" => {
+                if !room_is_bridged {
+                    return MatrixCommandOutcome::Reply("This room is not bridged.".to_string());
+                }
+                let enabled = match parsed.args.first().map(String::as_str) {
+                    None => return MatrixCommandOutcome::ConfirmStatus,
+                    Some("on") => true,
+                    Some("off") => false,
+                    Some(_) => {
+                        return MatrixCommandOutcome::Reply(
+                            "Invalid syntax. For more information try `!discord help confirm`"
+                                .to_string(),
+                        );
+                    }
+                };
+                if let Err(reply) = self.ensure_permission(&permission_check) {
+                    return MatrixCommandOutcome::Reply(reply);
+                }
+                MatrixCommandOutcome::ConfirmRequested { enabled }
+            }
+            "attachments" => {
+                if !room_is_bridged {
+                    return MatrixCommandOutcome::Reply("This room is not bridged.".to_string());
+                }
+                let (direction, policy) = match parsed.args.as_slice() {
+                    [] => return MatrixCommandOutcome::AttachmentPolicyStatus,
+                    [direction, policy] => {
+                        let direction = match direction.as_str() {
+                            "matrix_to_discord" => Some(DeliveryDirection::MatrixToDiscord),
+                            "discord_to_matrix" => Some(DeliveryDirection::DiscordToMatrix),
+                            _ => None,
+                        };
+                        let policy = match policy.as_str() {
+                            "default" => Some(None),
+                            policy => AttachmentPolicy::parse(policy).map(Some),
+                        };
+                        match direction.zip(policy) {
+                            Some(parsed) => parsed,
+                            None => {
+                                return MatrixCommandOutcome::Reply(
+                                    "Invalid syntax. For more information try `!discord help attachments`"
+                                        .to_string(),
+                                );
+                            }
+                        }
+                    }
+                    _ => {
+                        return MatrixCommandOutcome::Reply(
+                            "Invalid syntax. For more information try `!discord help attachments`"
+                                .to_string(),
+                        );
+                    }
+                };
+                if let Err(reply) = self.ensure_permission(&permission_check) {
+                    return MatrixCommandOutcome::Reply(reply);
+                }
+                MatrixCommandOutcome::AttachmentPolicyRequested { direction, policy }
+            }
+            "rolekeyword" => {
+                if !room_is_bridged {
+                    return MatrixCommandOutcome::Reply("This room is not bridged.".to_string());
+                }
+                let (role_id, keyword) = match parsed.args.as_slice() {
+                    [] => return MatrixCommandOutcome::RoleKeywordsStatus,
+                    [role_id, keyword]
+                        if crate::utils::snowflake::is_valid(role_id) && !keyword.is_empty() =>
+                    {
+                        (role_id.clone(), keyword.clone())
+                    }
+                    _ => {
+                        return MatrixCommandOutcome::Reply(
+                            "Invalid syntax. For more information try `!discord help rolekeyword`"
+                                .to_string(),
+                        );
+                    }
+                };
+                if let Err(reply) = self.ensure_permission(&permission_check) {
+                    return MatrixCommandOutcome::Reply(reply);
+                }
+                MatrixCommandOutcome::RoleKeywordRequested {
+                    role_id,
+                    keyword: (keyword != "--clear").then_some(keyword),
+                }
             }
-            _ => MatrixCommandOutcome::Reply(
-                "**ERROR:** unknown command. Try `!discord help` to see all commands".to_string(),
+            "nick" => {
+                if !room_is_bridged {
+                    return MatrixCommandOutcome::Reply("This room is not bridged.".to_string());
+                }
+                match parsed.args.join(" ").as_str() {
+                    "" => MatrixCommandOutcome::NickStatus,
+                    "--clear" => MatrixCommandOutcome::NickRequested { nick: None },
+                    nick => match validate_webhook_nick(nick) {
+                        Ok(()) => MatrixCommandOutcome::NickRequested {
+                            nick: Some(nick.to_string()),
+                        },
+                        Err(reply) => MatrixCommandOutcome::Reply(reply),
+                    },
+                }
+            }
+            "status" => {
+                if !room_is_bridged {
+                    return MatrixCommandOutcome::Reply("This room is not bridged.".to_string());
+                }
+                MatrixCommandOutcome::StatusRequested
+            }
+            "sync-bans" => {
+                if !room_is_bridged {
+                    return MatrixCommandOutcome::Reply("This room is not bridged.".to_string());
+                }
+                let dry_run = match parsed.args.as_slice() {
+                    [] => false,
+                    [flag] if flag == "--dry-run" => true,
+                    _ => {
+                        return MatrixCommandOutcome::Reply(
+                            "Invalid syntax. For more information try `!discord help sync-bans`"
+                                .to_string(),
+                        );
+                    }
+                };
+                if let Err(reply) = self.ensure_permission(&permission_check) {
+                    return MatrixCommandOutcome::Reply(reply);
+                }
+                MatrixCommandOutcome::SyncBansRequested { dry_run }
+            }
+            command => MatrixCommandOutcome::Reply(MATRIX_COMMANDS.unknown_command(command)),
+        }
+    }
+
+    /// Unbridging only goes ahead once the same user confirms within the
+    /// window, or straight away for the bridge admin with `--force`.
+    fn handle_unbridge(
+        &self,
+        sender: MatrixCommandSender<'_>,
+        arg: Option<&str>,
+    ) -> MatrixCommandOutcome {
+        let key = (sender.room_id.to_string(), sender.user_id.to_string());
+        let now = Instant::now();
+        let mut pending = self.pending_unbridges.lock();
+        pending.retain(|_, requested| {
+            now.saturating_duration_since(*requested) < self.unbridge_confirmation_window
+        });
+
+        match arg {
+            None => {
+                pending.insert(key, now);
+                MatrixCommandOutcome::Reply(format!(
+                    "This will stop bridging this room to Discord. Reply `!discord unbridge confirm` within {} seconds to continue.",
+                    self.unbridge_confirmation_window.as_secs()
+                ))
+            }
+            Some("confirm") => {
+                if pending.remove(&key).is_some() {
+                    MatrixCommandOutcome::UnbridgeRequested
+                } else {
+                    MatrixCommandOutcome::Reply(
+                        "There is no unbridge waiting for confirmation. Start again with `!discord unbridge`."
+                            .to_string(),
+                    )
+                }
+            }
+            Some("--force") => {
+                if sender.is_admin {
+                    pending.remove(&key);
+                    MatrixCommandOutcome::UnbridgeRequested
+                } else {
+                    MatrixCommandOutcome::Reply(
+                        "**ERROR:** only the bridge admin can use `--force`.".to_string(),
+                    )
+                }
+            }
+            Some(_) => MatrixCommandOutcome::Reply(
+                "Invalid syntax. For more information try `!discord help unbridge`".to_string(),
             ),
         }
     }
@@ -128,25 +427,38 @@ impl MatrixCommandHandler {
             Err("**ERROR:** insufficient permissions to use this command! Try `!discord help` to see all available commands".to_string())
         }
     }
+}
 
-    fn render_help(&self, command: Option<&str>) -> String {
-        match command {
-            Some("bridge") => "`!discord bridge <guildId> <channelId>`: Bridges this room to a Discord channel\nUse `guild/channel` or `guild channel`.".to_string(),
-            Some("unbridge") => {
-                "`!discord unbridge`: Unbridges a Discord channel from this room".to_string()
-            }
-            Some(_) => "**ERROR:** unknown command! Try `!discord help` to see all commands"
+/// Checks `nick` against the names Discord refuses for webhook messages.
+fn validate_webhook_nick(nick: &str) -> Result<(), String> {
+    let lowered = nick.to_lowercase();
+    if nick.chars().count() > MAX_WEBHOOK_NICK_LEN {
+        return Err(format!(
+            "**ERROR:** the name can be at most {MAX_WEBHOOK_NICK_LEN} characters."
+        ));
+    }
+    if lowered.contains("discord") || lowered.contains("clyde") {
+        return Err(
+            "**ERROR:** Discord does not allow names containing \"discord\" or \"clyde\"."
                 .to_string(),
-            None => {
-                "Available Commands:\n - `!discord bridge <guildId> <channelId>`: Bridges this room to a Discord channel\n - `!discord unbridge`: Unbridges a Discord channel from this room".to_string()
-            }
-        }
+        );
+    }
+    if lowered == "everyone" || lowered == "here" {
+        return Err(format!(
+            "**ERROR:** Discord does not allow the name \"{nick}\"."
+        ));
     }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{MatrixCommandHandler, MatrixCommandOutcome, MatrixCommandPermission};
+    use std::time::Duration;
+
+    use super::{
+        AttachmentPolicy, DeliveryDirection, MatrixCommandHandler, MatrixCommandOutcome,
+        MatrixCommandPermission, MatrixCommandSender,
+    };
 
     #[test]
     fn bridge_command_supports_slash_syntax() {
@@ -203,4 +515,283 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn autoinvite_shows_status_or_toggles_with_permission() {
+        let handler = MatrixCommandHandler::default();
+        assert_eq!(
+            handler.handle("!discord autoinvite", true, |_| Ok(false)),
+            MatrixCommandOutcome::AutoInviteStatus
+        );
+        assert_eq!(
+            handler.handle("!discord autoinvite on", true, |_| Ok(true)),
+            MatrixCommandOutcome::AutoInviteRequested { enabled: true }
+        );
+        assert!(matches!(
+            handler.handle("!discord autoinvite off", true, |_| Ok(false)),
+            MatrixCommandOutcome::Reply(_)
+        ));
+        assert_eq!(
+            handler.handle("!discord autoinvite on", false, |_| Ok(true)),
+            MatrixCommandOutcome::Reply("This room is not bridged.".to_string())
+        );
+    }
+
+    #[test]
+    fn badge_shows_status_or_toggles_with_permission() {
+        let handler = MatrixCommandHandler::default();
+        assert_eq!(
+            handler.handle("!discord badge", true, |_| Ok(false)),
+            MatrixCommandOutcome::BadgeStatus
+        );
+        assert_eq!(
+            handler.handle("!discord badge off", true, |_| Ok(true)),
+            MatrixCommandOutcome::BadgeRequested { enabled: false }
+        );
+        assert!(matches!(
+            handler.handle("!discord badge on", true, |_| Ok(false)),
+            MatrixCommandOutcome::Reply(_)
+        ));
+        assert!(matches!(
+            handler.handle("!discord badge maybe", true, |_| Ok(true)),
+            MatrixCommandOutcome::Reply(_)
+        ));
+    }
+
+    #[test]
+    fn attachments_sets_a_policy_per_direction() {
+        let handler = MatrixCommandHandler::default();
+        assert_eq!(
+            handler.handle("!discord attachments", true, |_| Ok(false)),
+            MatrixCommandOutcome::AttachmentPolicyStatus
+        );
+        assert_eq!(
+            handler.handle(
+                "!discord attachments matrix_to_discord link_only",
+                true,
+                |_| Ok(true)
+            ),
+            MatrixCommandOutcome::AttachmentPolicyRequested {
+                direction: DeliveryDirection::MatrixToDiscord,
+                policy: Some(AttachmentPolicy::LinkOnly),
+            }
+        );
+        assert_eq!(
+            handler.handle(
+                "!discord attachments discord_to_matrix default",
+                true,
+                |_| Ok(true)
+            ),
+            MatrixCommandOutcome::AttachmentPolicyRequested {
+                direction: DeliveryDirection::DiscordToMatrix,
+                policy: None,
+            }
+        );
+        for command in [
+            "!discord attachments discord_to_matrix",
+            "!discord attachments sideways link_only",
+            "!discord attachments matrix_to_discord copy",
+        ] {
+            assert!(matches!(
+                handler.handle(command, true, |_| Ok(true)),
+                MatrixCommandOutcome::Reply(_)
+            ));
+        }
+        assert!(matches!(
+            handler.handle(
+                "!discord attachments matrix_to_discord reupload",
+                true,
+                |_| Ok(false)
+            ),
+            MatrixCommandOutcome::Reply(_)
+        ));
+    }
+
+    #[test]
+    fn confirm_shows_status_or_toggles_with_permission() {
+        let handler = MatrixCommandHandler::default();
+        assert_eq!(
+            handler.handle("!discord confirm", true, |_| Ok(false)),
+            MatrixCommandOutcome::ConfirmStatus
+        );
+        assert_eq!(
+            handler.handle("!discord confirm on", true, |_| Ok(true)),
+            MatrixCommandOutcome::ConfirmRequested { enabled: true }
+        );
+        assert!(matches!(
+            handler.handle("!discord confirm off", true, |_| Ok(false)),
+            MatrixCommandOutcome::Reply(_)
+        ));
+        assert_eq!(
+            handler.handle("!discord confirm", false, |_| Ok(true)),
+            MatrixCommandOutcome::Reply("This room is not bridged.".to_string())
+        );
+    }
+
+    #[test]
+    fn sync_bans_needs_permission_and_accepts_dry_run() {
+        let handler = MatrixCommandHandler::default();
+        assert_eq!(
+            handler.handle("!discord sync-bans", true, |_| Ok(true)),
+            MatrixCommandOutcome::SyncBansRequested { dry_run: false }
+        );
+        assert_eq!(
+            handler.handle("!discord sync-bans --dry-run", true, |_| Ok(true)),
+            MatrixCommandOutcome::SyncBansRequested { dry_run: true }
+        );
+        assert!(matches!(
+            handler.handle("!discord sync-bans", true, |_| Ok(false)),
+            MatrixCommandOutcome::Reply(_)
+        ));
+        assert!(matches!(
+            handler.handle("!discord sync-bans --force", true, |_| Ok(true)),
+            MatrixCommandOutcome::Reply(_)
+        ));
+        assert_eq!(
+            handler.handle("!discord sync-bans", false, |_| Ok(true)),
+            MatrixCommandOutcome::Reply("This room is not bridged.".to_string())
+        );
+    }
+
+    #[test]
+    fn status_is_open_to_anyone_in_bridged_rooms() {
+        let handler = MatrixCommandHandler::default();
+        assert_eq!(
+            handler.handle("!discord status", true, |_| Ok(false)),
+            MatrixCommandOutcome::StatusRequested
+        );
+        assert_eq!(
+            handler.handle("!discord status", false, |_| Ok(true)),
+            MatrixCommandOutcome::Reply("This room is not bridged.".to_string())
+        );
+    }
+
+    #[test]
+    fn rolekeyword_lists_or_sets_with_permission() {
+        let handler = MatrixCommandHandler::default();
+        assert_eq!(
+            handler.handle("!discord rolekeyword", true, |_| Ok(false)),
+            MatrixCommandOutcome::RoleKeywordsStatus
+        );
+        assert_eq!(
+            handler.handle("!discord rolekeyword 555 moderators", true, |_| Ok(true)),
+            MatrixCommandOutcome::RoleKeywordRequested {
+                role_id: "555".to_string(),
+                keyword: Some("moderators".to_string()),
+            }
+        );
+        assert_eq!(
+            handler.handle("!discord rolekeyword 555 --clear", true, |_| Ok(true)),
+            MatrixCommandOutcome::RoleKeywordRequested {
+                role_id: "555".to_string(),
+                keyword: None,
+            }
+        );
+        assert!(matches!(
+            handler.handle("!discord rolekeyword 555 moderators", true, |_| Ok(false)),
+            MatrixCommandOutcome::Reply(_)
+        ));
+        assert!(matches!(
+            handler.handle("!discord rolekeyword Mods moderators", true, |_| Ok(true)),
+            MatrixCommandOutcome::Reply(_)
+        ));
+    }
+
+    #[test]
+    fn unbridge_waits_for_confirmation_from_the_same_user() {
+        let handler = MatrixCommandHandler::default();
+        let alice = MatrixCommandSender {
+            room_id: "!room:x",
+            user_id: "@alice:x",
+            is_admin: false,
+        };
+        let bob = MatrixCommandSender {
+            user_id: "@bob:x",
+            ..alice
+        };
+
+        assert!(matches!(
+            handler.handle_from(alice, "!discord unbridge confirm", true, |_| Ok(true)),
+            MatrixCommandOutcome::Reply(_)
+        ));
+        assert!(matches!(
+            handler.handle_from(alice, "!discord unbridge", true, |_| Ok(true)),
+            MatrixCommandOutcome::Reply(_)
+        ));
+        assert!(matches!(
+            handler.handle_from(bob, "!discord unbridge confirm", true, |_| Ok(true)),
+            MatrixCommandOutcome::Reply(_)
+        ));
+        assert_eq!(
+            handler.handle_from(alice, "!discord unbridge confirm", true, |_| Ok(true)),
+            MatrixCommandOutcome::UnbridgeRequested
+        );
+        // A confirmation is only good once.
+        assert!(matches!(
+            handler.handle_from(alice, "!discord unbridge confirm", true, |_| Ok(true)),
+            MatrixCommandOutcome::Reply(_)
+        ));
+    }
+
+    #[test]
+    fn unbridge_confirmation_expires_and_force_needs_admin() {
+        let handler =
+            MatrixCommandHandler::default().with_unbridge_confirmation_window(Duration::ZERO);
+        let user = MatrixCommandSender {
+            room_id: "!room:x",
+            user_id: "@alice:x",
+            is_admin: false,
+        };
+
+        handler.handle_from(user, "!discord unbridge", true, |_| Ok(true));
+        assert!(matches!(
+            handler.handle_from(user, "!discord unbridge confirm", true, |_| Ok(true)),
+            MatrixCommandOutcome::Reply(_)
+        ));
+        assert!(matches!(
+            handler.handle_from(user, "!discord unbridge --force", true, |_| Ok(true)),
+            MatrixCommandOutcome::Reply(_)
+        ));
+        let admin = MatrixCommandSender {
+            is_admin: true,
+            ..user
+        };
+        assert_eq!(
+            handler.handle_from(admin, "!discord unbridge --force", true, |_| Ok(true)),
+            MatrixCommandOutcome::UnbridgeRequested
+        );
+    }
+
+    #[test]
+    fn nick_is_shown_set_or_cleared() {
+        let handler = MatrixCommandHandler::default();
+        assert_eq!(
+            handler.handle("!discord nick", true, |_| Ok(false)),
+            MatrixCommandOutcome::NickStatus
+        );
+        assert_eq!(
+            handler.handle("!discord nick Alice  (away)", true, |_| Ok(false)),
+            MatrixCommandOutcome::NickRequested {
+                nick: Some("Alice (away)".to_string())
+            }
+        );
+        assert_eq!(
+            handler.handle("!discord nick --clear", true, |_| Ok(false)),
+            MatrixCommandOutcome::NickRequested { nick: None }
+        );
+        assert!(matches!(
+            handler.handle("!discord nick my Discord name", true, |_| Ok(false)),
+            MatrixCommandOutcome::Reply(_)
+        ));
+        assert!(matches!(
+            handler.handle(&format!("!discord nick {}", "a".repeat(81)), true, |_| Ok(
+                false
+            )),
+            MatrixCommandOutcome::Reply(_)
+        ));
+        assert_eq!(
+            handler.handle("!discord nick Alice", false, |_| Ok(false)),
+            MatrixCommandOutcome::Reply("This room is not bridged.".to_string())
+        );
+    }
 }
@@ -1,13 +1,19 @@
+use std::collections::{HashSet, VecDeque};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use async_trait::async_trait;
+use parking_lot::Mutex;
 use tracing::{debug, info, warn};
 
 use super::{MatrixAppservice, MatrixEvent};
 use crate::bridge::BridgeCore;
 
 const DEFAULT_AGE_LIMIT_MS: i64 = 900_000;
+/// How many recently processed event ids are remembered, and for how long.
+const RECENT_EVENT_CAPACITY: usize = 4096;
+const RECENT_EVENT_WINDOW: Duration = Duration::from_secs(3600);
 
 #[async_trait]
 pub trait MatrixEventHandler: Send + Sync {
@@ -18,6 +24,11 @@ pub trait MatrixEventHandler: Send + Sync {
     async fn handle_room_name(&self, event: &MatrixEvent) -> Result<()>;
     async fn handle_room_topic(&self, event: &MatrixEvent) -> Result<()>;
     async fn handle_room_power_levels(&self, event: &MatrixEvent) -> Result<()>;
+    async fn handle_room_pinned_events(&self, event: &MatrixEvent) -> Result<()>;
+    async fn handle_reaction(&self, event: &MatrixEvent) -> Result<()>;
+    async fn handle_redaction(&self, event: &MatrixEvent) -> Result<()>;
+    async fn handle_receipt(&self, event: &MatrixEvent) -> Result<()>;
+    async fn is_bridged_room(&self, room_id: &str) -> Result<bool>;
 }
 
 pub struct MatrixEventHandlerImpl {
@@ -97,19 +108,104 @@ impl MatrixEventHandler for MatrixEventHandlerImpl {
         }
         Ok(())
     }
+
+    async fn handle_room_pinned_events(&self, event: &MatrixEvent) -> Result<()> {
+        if let Some(bridge) = &self.bridge {
+            bridge.handle_matrix_pinned_events(event).await?;
+        } else {
+            debug!("matrix pinned events received without bridge binding");
+        }
+        Ok(())
+    }
+
+    async fn handle_reaction(&self, event: &MatrixEvent) -> Result<()> {
+        if let Some(bridge) = &self.bridge {
+            bridge.handle_matrix_reaction(event).await?;
+        } else {
+            debug!("matrix reaction received without bridge binding");
+        }
+        Ok(())
+    }
+
+    async fn handle_redaction(&self, event: &MatrixEvent) -> Result<()> {
+        if let Some(bridge) = &self.bridge {
+            bridge.handle_matrix_redaction(event).await?;
+        } else {
+            debug!("matrix redaction received without bridge binding");
+        }
+        Ok(())
+    }
+
+    async fn handle_receipt(&self, event: &MatrixEvent) -> Result<()> {
+        if let Some(bridge) = &self.bridge {
+            bridge.handle_matrix_receipt(event).await?;
+        } else {
+            debug!("matrix receipt received without bridge binding");
+        }
+        Ok(())
+    }
+
+    async fn is_bridged_room(&self, room_id: &str) -> Result<bool> {
+        match &self.bridge {
+            Some(bridge) => bridge.is_bridged_matrix_room(room_id).await,
+            None => Ok(false),
+        }
+    }
+}
+
+/// Event ids seen in the last [`RECENT_EVENT_WINDOW`], oldest first. Catches
+/// homeservers that deliver the same event in two different transactions,
+/// which transaction id deduplication cannot see.
+struct RecentEventIds {
+    order: VecDeque<(String, Instant)>,
+    ids: HashSet<String>,
+    capacity: usize,
+    window: Duration,
+}
+
+impl RecentEventIds {
+    fn new(capacity: usize, window: Duration) -> Self {
+        Self {
+            order: VecDeque::new(),
+            ids: HashSet::new(),
+            capacity,
+            window,
+        }
+    }
+
+    /// Remembers `event_id` and returns whether it was new.
+    fn insert(&mut self, event_id: &str, now: Instant) -> bool {
+        while let Some((oldest, seen_at)) = self.order.front()
+            && now.saturating_duration_since(*seen_at) >= self.window
+        {
+            self.ids.remove(oldest);
+            self.order.pop_front();
+        }
+        // Repeats are answered before making room, so a duplicate arriving
+        // at capacity does not push out an id that is still remembered.
+        if self.ids.contains(event_id) {
+            return false;
+        }
+        while self.order.len() >= self.capacity
+            && let Some((oldest, _)) = self.order.pop_front()
+        {
+            self.ids.remove(&oldest);
+        }
+        self.ids.insert(event_id.to_string());
+        self.order.push_back((event_id.to_string(), now));
+        true
+    }
 }
 
 pub struct MatrixEventProcessor {
     event_handler: Arc<dyn MatrixEventHandler>,
     age_limit_ms: i64,
+    recent_events: Mutex<RecentEventIds>,
 }
 
 impl MatrixEventProcessor {
     pub fn new(event_handler: Arc<dyn MatrixEventHandler>) -> Self {
-        Self {
-            event_handler,
-            age_limit_ms: DEFAULT_AGE_LIMIT_MS,
-        }
+        Self::with_age_limit(event_handler, DEFAULT_AGE_LIMIT_MS as u64)
     }
 
     pub fn with_age_limit(event_handler: Arc<dyn MatrixEventHandler>, age_limit_ms: u64) -> Self {
@@ -117,9 +213,20 @@ impl MatrixEventProcessor {
         Self {
             event_handler,
             age_limit_ms,
+            recent_events: Mutex::new(RecentEventIds::new(
+                RECENT_EVENT_CAPACITY,
+                RECENT_EVENT_WINDOW,
+            )),
         }
     }
 
+    fn is_duplicate(&self, event: &MatrixEvent) -> bool {
+        let Some(event_id) = &event.event_id else {
+            return false;
+        };
+        !self.recent_events.lock().insert(event_id, Instant::now())
+    }
+
     fn check_event_age(event: &MatrixEvent, age_limit_ms: i64) -> bool {
         if age_limit_ms <= 0 {
             return true;
@@ -148,10 +255,29 @@ impl MatrixEventProcessor {
         true
     }
 
+    /// Whether `room_id` is bridged to a Discord channel. Lookup failures
+    /// count as bridged, so a database hiccup does not drop events.
+    pub async fn is_bridged_room(&self, room_id: &str) -> bool {
+        self.event_handler
+            .is_bridged_room(room_id)
+            .await
+            .unwrap_or_else(|err| {
+                warn!("failed to look up the mapping of {}: {}", room_id, err);
+                true
+            })
+    }
+
     pub async fn process_event(&self, event: MatrixEvent) -> Result<()> {
         if !Self::check_event_age(&event, self.age_limit_ms) {
             return Ok(());
         }
+        if self.is_duplicate(&event) {
+            warn!(
+                "skipping duplicate delivery of event_id={:?} room_id={} type={}",
+                event.event_id, event.room_id, event.event_type
+            );
+            return Ok(());
+        }
 
         match event.event_type.as_str() {
             "m.room.message" => self.event_handler.handle_room_message(&event).await?,
@@ -161,6 +287,10 @@ impl MatrixEventProcessor {
             "m.room.name" => self.event_handler.handle_room_name(&event).await?,
             "m.room.topic" => self.event_handler.handle_room_topic(&event).await?,
             "m.room.power_levels" => self.event_handler.handle_room_power_levels(&event).await?,
+            "m.room.pinned_events" => self.event_handler.handle_room_pinned_events(&event).await?,
+            "m.reaction" => self.event_handler.handle_reaction(&event).await?,
+            "m.room.redaction" => self.event_handler.handle_redaction(&event).await?,
+            "m.receipt" => self.event_handler.handle_receipt(&event).await?,
             other => debug!("unhandled matrix event type: {}", other),
         }
         Ok(())
@@ -179,6 +309,7 @@ mod tests {
             sender: "@user:example.org".to_string(),
             state_key: None,
             content: None,
+            prev_content: None,
             timestamp: ts.map(ToOwned::to_owned),
         }
     }
@@ -221,4 +352,31 @@ mod tests {
             DEFAULT_AGE_LIMIT_MS
         ));
     }
+
+    #[test]
+    fn recent_event_ids_reject_repeats_until_they_age_out() {
+        let mut recent = RecentEventIds::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+
+        assert!(recent.insert("$a", start));
+        assert!(!recent.insert("$a", start + Duration::from_secs(1)));
+        assert!(recent.insert("$b", start + Duration::from_secs(2)));
+        // Capacity reached: "$a" is evicted to make room for "$c".
+        assert!(recent.insert("$c", start + Duration::from_secs(3)));
+        assert!(recent.insert("$a", start + Duration::from_secs(4)));
+        // Outside the window everything is forgotten.
+        assert!(recent.insert("$c", start + Duration::from_secs(120)));
+    }
+
+    #[test]
+    fn recent_event_ids_keep_everything_on_a_repeat_at_capacity() {
+        let mut recent = RecentEventIds::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+
+        assert!(recent.insert("$a", start));
+        assert!(recent.insert("$b", start));
+        assert!(!recent.insert("$b", start + Duration::from_secs(1)));
+        // The repeat did not evict "$a".
+        assert!(!recent.insert("$a", start + Duration::from_secs(2)));
+    }
 }
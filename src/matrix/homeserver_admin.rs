@@ -0,0 +1,199 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{Result, anyhow};
+use reqwest::StatusCode;
+use secrecy::{ExposeSecret, SecretString};
+use serde_json::json;
+use tracing::{info, warn};
+
+use super::urlencoding;
+use crate::config::Config;
+
+/// Client for the Synapse-compatible homeserver admin API (also served by
+/// Palpo), used for account-level actions the appservice API cannot do.
+#[derive(Clone)]
+pub struct HomeserverAdminClient {
+    base_url: String,
+    token: SecretString,
+    http: reqwest::Client,
+    /// Set once the homeserver turns down the user-type update, so hiding
+    /// ghosts from the directory is not attempted again.
+    directory_hiding_unsupported: Arc<AtomicBool>,
+}
+
+impl HomeserverAdminClient {
+    pub fn new(base_url: &str, token: SecretString) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token,
+            http: reqwest::Client::new(),
+            directory_hiding_unsupported: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// The client for the `homeserver_admin` section, or `None` without a
+    /// token.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let admin = &config.homeserver_admin;
+        let token = admin.token.clone()?;
+        let base_url = admin
+            .url
+            .as_deref()
+            .unwrap_or(&config.bridge.homeserver_url);
+        Some(Self::new(base_url, token))
+    }
+
+    /// The admin client configured for ghost deactivation, or `None` when
+    /// `channel.delete_options.deactivate_ghosts` is off.
+    pub fn for_ghost_deactivation(config: &Config) -> Option<Self> {
+        if !config.channel.delete_options.deactivate_ghosts {
+            return None;
+        }
+        Self::from_config(config)
+    }
+
+    fn user_url(&self, user_id: &str) -> String {
+        format!(
+            "{}/_synapse/admin/v2/users/{}",
+            self.base_url,
+            urlencoding::encode(user_id)
+        )
+    }
+
+    /// Marks `user_id` as a support user, which Synapse leaves out of user
+    /// directory searches. Returns `false` without a request once the
+    /// homeserver has shown it does not support this.
+    pub async fn hide_from_directory(&self, user_id: &str) -> Result<bool> {
+        if self.directory_hiding_unsupported.load(Ordering::Relaxed) {
+            return Ok(false);
+        }
+
+        let response = self
+            .http
+            .put(self.user_url(user_id))
+            .bearer_auth(self.token.expose_secret())
+            .json(&json!({ "user_type": "support" }))
+            .send()
+            .await
+            .map_err(|e| anyhow!("failed to hide {} from the user directory: {}", user_id, e))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(true);
+        }
+        let body = response.text().await.unwrap_or_default();
+        if matches!(
+            status,
+            StatusCode::NOT_FOUND | StatusCode::BAD_REQUEST | StatusCode::METHOD_NOT_ALLOWED
+        ) {
+            warn!(
+                "homeserver does not support hiding users from the directory ({} - {}); ghosts will stay listed",
+                status, body
+            );
+            self.directory_hiding_unsupported
+                .store(true, Ordering::Relaxed);
+            return Ok(false);
+        }
+        Err(anyhow!(
+            "failed to hide {} from the user directory: {} - {}",
+            user_id,
+            status,
+            body
+        ))
+    }
+
+    fn media_url(&self, mxc_url: &str) -> Option<String> {
+        let (server_name, media_id) = mxc_url.strip_prefix("mxc://")?.split_once('/')?;
+        Some(format!(
+            "{}/_synapse/admin/v1/media/{}/{}",
+            self.base_url,
+            urlencoding::encode(server_name),
+            urlencoding::encode(media_id)
+        ))
+    }
+
+    /// Deletes a piece of media from the homeserver's store. Media that is
+    /// already gone is not an error.
+    pub async fn delete_media(&self, mxc_url: &str) -> Result<()> {
+        let url = self
+            .media_url(mxc_url)
+            .ok_or_else(|| anyhow!("invalid mxc URL: {}", mxc_url))?;
+        let response = self
+            .http
+            .delete(url)
+            .bearer_auth(self.token.expose_secret())
+            .send()
+            .await
+            .map_err(|e| anyhow!("failed to delete media {}: {}", mxc_url, e))?;
+
+        let status = response.status();
+        if !status.is_success() && status != StatusCode::NOT_FOUND {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow!(
+                "failed to delete media {}: {} - {}",
+                mxc_url,
+                status,
+                body
+            ));
+        }
+        Ok(())
+    }
+
+    fn deactivate_url(&self, user_id: &str) -> String {
+        format!(
+            "{}/_synapse/admin/v1/deactivate/{}",
+            self.base_url,
+            urlencoding::encode(user_id)
+        )
+    }
+
+    /// Deactivates `user_id` without erasing its messages, which also drops
+    /// it from the user directory.
+    pub async fn deactivate_user(&self, user_id: &str) -> Result<()> {
+        let response = self
+            .http
+            .post(self.deactivate_url(user_id))
+            .bearer_auth(self.token.expose_secret())
+            .json(&json!({ "erase": false }))
+            .send()
+            .await
+            .map_err(|e| anyhow!("failed to deactivate {}: {}", user_id, e))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow!(
+                "failed to deactivate {}: {} - {}",
+                user_id,
+                status,
+                body
+            ));
+        }
+        info!("deactivated ghost user {}", user_id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HomeserverAdminClient;
+
+    #[test]
+    fn admin_urls_escape_the_user_id() {
+        let client = HomeserverAdminClient::new("https://hs.example/", "t".to_string().into());
+        assert_eq!(
+            client.deactivate_url("@_discord_1:example.org"),
+            "https://hs.example/_synapse/admin/v1/deactivate/%40_discord_1%3Aexample.org"
+        );
+        assert_eq!(
+            client.user_url("@_discord_1:example.org"),
+            "https://hs.example/_synapse/admin/v2/users/%40_discord_1%3Aexample.org"
+        );
+        assert_eq!(
+            client.media_url("mxc://example.org/abc123").as_deref(),
+            Some("https://hs.example/_synapse/admin/v1/media/example.org/abc123")
+        );
+        assert_eq!(client.media_url("https://example.org/abc123"), None);
+    }
+}
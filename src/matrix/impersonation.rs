@@ -0,0 +1,160 @@
+//! Impersonation protection for ghost display names: a Discord user who
+//! renames themselves after a Matrix admin would otherwise have their ghost
+//! follow. Names are compared by a skeleton that folds case, accents,
+//! lookalike letters from other scripts, fullwidth forms and invisible
+//! characters, so `Аdmіn` (with Cyrillic letters) still matches `admin`.
+
+use std::borrow::Cow;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use tracing::info;
+
+/// The `#1234` (or `#0`) that `ghosts.username_pattern` adds by default.
+static DISCRIMINATOR_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"#\d{1,4}$").unwrap());
+
+/// Appends a suffix to ghost display names that look like a protected name.
+#[derive(Debug, Clone, Default)]
+pub struct ImpersonationGuard {
+    protected: Vec<String>,
+    suffix: String,
+}
+
+impl ImpersonationGuard {
+    /// `protected` holds Matrix ids, whose localpart is protected as well,
+    /// and display names.
+    pub fn new(protected: &[String], suffix: &str) -> Self {
+        let mut skeletons: Vec<String> = Vec::new();
+        for name in protected {
+            let localpart = name
+                .strip_prefix('@')
+                .and_then(|mxid| mxid.split_once(':'))
+                .map(|(localpart, _)| localpart);
+            for name in std::iter::once(name.as_str()).chain(localpart) {
+                let skeleton = skeleton(name);
+                if !skeleton.is_empty() && !skeletons.contains(&skeleton) {
+                    skeletons.push(skeleton);
+                }
+            }
+        }
+        Self {
+            protected: skeletons,
+            suffix: suffix.to_string(),
+        }
+    }
+
+    pub fn is_lookalike(&self, displayname: &str) -> bool {
+        if self.protected.is_empty() {
+            return false;
+        }
+        let name = DISCRIMINATOR_REGEX.replace(displayname.trim(), "");
+        let skeleton = skeleton(&name);
+        self.protected.contains(&skeleton)
+    }
+
+    /// `displayname`, with the suffix when it looks like a protected name.
+    pub fn guard<'a>(&self, displayname: &'a str) -> Cow<'a, str> {
+        if !self.is_lookalike(displayname) {
+            return Cow::Borrowed(displayname);
+        }
+        info!(
+            "ghost display name {:?} looks like a protected name, adding {:?}",
+            displayname, self.suffix
+        );
+        Cow::Owned(format!("{}{}", displayname, self.suffix))
+    }
+}
+
+/// The form names are compared in: lowercase ASCII-like letters and digits
+/// only, with lookalikes folded onto one letter and `rn`/`vv` onto `m`/`w`.
+pub fn skeleton(name: &str) -> String {
+    let mut folded = String::with_capacity(name.len());
+    for c in name.chars() {
+        if is_invisible(c) {
+            continue;
+        }
+        let c = match c as u32 {
+            // Fullwidth forms of ASCII.
+            0xFF01..=0xFF5E => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+            _ => c,
+        };
+        for c in c.to_lowercase() {
+            let c = fold_lookalike(c);
+            if c.is_alphanumeric() {
+                folded.push(c);
+            }
+        }
+    }
+    folded.replace("rn", "m").replace("vv", "w")
+}
+
+fn is_invisible(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x00AD | 0x034F | 0x0300..=0x036F | 0x200B..=0x200F | 0x2060..=0x2064 | 0xFEFF
+    )
+}
+
+fn fold_lookalike(c: char) -> char {
+    match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'а' | 'α' | 'ɑ' | '4' => 'a',
+        'ç' | 'с' => 'c',
+        'ԁ' => 'd',
+        'è' | 'é' | 'ê' | 'ë' | 'е' | 'ё' | 'ε' | '3' => 'e',
+        'ɡ' => 'g',
+        'һ' => 'h',
+        'ì' | 'í' | 'î' | 'ï' | 'і' | 'ї' | 'ι' | 'ı' | 'i' | '1' | 'ℓ' => 'l',
+        'ј' => 'j',
+        'к' | 'κ' => 'k',
+        'м' => 'm',
+        'ñ' => 'n',
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'о' | 'ο' | '0' => 'o',
+        'р' | 'ρ' => 'p',
+        'ԛ' => 'q',
+        'ѕ' | '5' => 's',
+        'τ' => 't',
+        'ù' | 'ú' | 'û' | 'ü' | 'υ' => 'u',
+        'ν' => 'v',
+        'ԝ' => 'w',
+        'х' | 'χ' => 'x',
+        'ý' | 'ÿ' | 'у' => 'y',
+        c => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ImpersonationGuard, skeleton};
+
+    #[test]
+    fn skeleton_folds_homoglyphs_and_invisible_characters() {
+        assert_eq!(skeleton("Admin"), skeleton("Аdmіn"));
+        assert_eq!(skeleton("admin"), skeleton("ＡＤＭＩＮ"));
+        assert_eq!(skeleton("admin"), skeleton("ad\u{200B}min"));
+        assert_eq!(skeleton("Alice"), skeleton("A1ice"));
+        assert_eq!(skeleton("mod team"), skeleton("rnod_team"));
+        assert_ne!(skeleton("alice"), skeleton("alicia"));
+    }
+
+    #[test]
+    fn lookalikes_of_protected_names_get_the_suffix() {
+        let guard = ImpersonationGuard::new(
+            &[
+                "@admin:example.org".to_string(),
+                "Bridge Support".to_string(),
+            ],
+            " (Discord)",
+        );
+        assert_eq!(guard.guard("Аdmin"), "Аdmin (Discord)");
+        assert_eq!(guard.guard("admin#0"), "admin#0 (Discord)");
+        assert_eq!(
+            guard.guard("@admin:example.org"),
+            "@admin:example.org (Discord)"
+        );
+        assert_eq!(guard.guard("bridge-supp0rt"), "bridge-supp0rt (Discord)");
+        assert_eq!(guard.guard("administrator"), "administrator");
+
+        let off = ImpersonationGuard::new(&[], " (Discord)");
+        assert_eq!(off.guard("admin"), "admin");
+    }
+}
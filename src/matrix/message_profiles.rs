@@ -0,0 +1,169 @@
+//! Lightweight puppeting: with `ghosts.per_message_profiles` on, Discord
+//! messages are sent by the bridge bot and carry their author's name and
+//! avatar as an MSC4144 per-message profile, so no ghost users have to be
+//! registered. Clients without MSC4144 support see the name prefixed to the
+//! text instead.
+
+use std::collections::HashMap;
+
+use parking_lot::Mutex;
+use serde_json::{Value, json};
+
+/// The unstable content key of MSC4144.
+pub const PER_MESSAGE_PROFILE_KEY: &str = "com.beeper.per_message_profile";
+
+/// How one Discord user is shown on the messages sent for them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MessageProfile {
+    /// Stable across name changes, so clients can group the messages.
+    pub id: String,
+    pub displayname: Option<String>,
+    pub avatar_url: Option<String>,
+}
+
+/// The profiles of Discord users, filled in where ghosts would otherwise be
+/// registered or have their profile set.
+#[derive(Default)]
+pub struct MessageProfiles {
+    profiles: Mutex<HashMap<String, MessageProfile>>,
+}
+
+impl MessageProfiles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_displayname(&self, id: &str, displayname: &str) {
+        self.profiles
+            .lock()
+            .entry(id.to_string())
+            .or_insert_with(|| profile_for(id))
+            .displayname = Some(displayname.to_string());
+    }
+
+    pub fn set_avatar_url(&self, id: &str, avatar_url: &str) {
+        self.profiles
+            .lock()
+            .entry(id.to_string())
+            .or_insert_with(|| profile_for(id))
+            .avatar_url = Some(avatar_url.to_string());
+    }
+
+    /// The known profile of `id`, or one with only the id.
+    pub fn get(&self, id: &str) -> MessageProfile {
+        self.profiles
+            .lock()
+            .get(id)
+            .cloned()
+            .unwrap_or_else(|| profile_for(id))
+    }
+}
+
+fn profile_for(id: &str) -> MessageProfile {
+    MessageProfile {
+        id: id.to_string(),
+        ..MessageProfile::default()
+    }
+}
+
+/// Adds `profile` to a message's content, and to the new content of an edit.
+/// Text messages also get the name written before the body as a fallback.
+pub fn apply_profile(content: &mut Value, profile: &MessageProfile) {
+    if let Some(new_content) = content.get_mut("m.new_content") {
+        apply_profile(new_content, profile);
+        let new_content = new_content.clone();
+        content["body"] = format!("* {}", new_content["body"].as_str().unwrap_or_default()).into();
+        if let Some(formatted_body) = new_content["formatted_body"].as_str() {
+            content["format"] = "org.matrix.custom.html".into();
+            content["formatted_body"] = format!("* {formatted_body}").into();
+        }
+        content[PER_MESSAGE_PROFILE_KEY] = new_content[PER_MESSAGE_PROFILE_KEY].clone();
+        return;
+    }
+
+    let is_text = matches!(
+        content["msgtype"].as_str(),
+        Some("m.text" | "m.notice" | "m.emote")
+    );
+    let fallback_name = profile.displayname.as_deref().filter(|_| is_text);
+    if let Some(name) = fallback_name {
+        let body = content["body"].as_str().unwrap_or_default().to_string();
+        let formatted_body = match content["formatted_body"].as_str() {
+            Some(formatted_body) => formatted_body.to_string(),
+            None => escape_html(&body).replace('\n', "<br>"),
+        };
+        content["formatted_body"] = format!(
+            "<strong data-mx-profile-fallback>{}: </strong>{formatted_body}",
+            escape_html(name)
+        )
+        .into();
+        content["format"] = "org.matrix.custom.html".into();
+        content["body"] = format!("{name}: {body}").into();
+    }
+
+    let mut meta = json!({
+        "id": profile.id,
+        "has_fallback": fallback_name.is_some(),
+    });
+    if let Some(displayname) = &profile.displayname {
+        meta["displayname"] = displayname.as_str().into();
+    }
+    if let Some(avatar_url) = &profile.avatar_url {
+        meta["avatar_url"] = avatar_url.as_str().into();
+    }
+    content[PER_MESSAGE_PROFILE_KEY] = meta;
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{MessageProfiles, PER_MESSAGE_PROFILE_KEY, apply_profile};
+
+    #[test]
+    fn text_messages_carry_the_profile_and_a_fallback() {
+        let profiles = MessageProfiles::new();
+        profiles.set_displayname("@_discord_42:example.org", "Alice <3");
+        profiles.set_avatar_url("@_discord_42:example.org", "mxc://example.org/a");
+
+        let mut content = json!({ "msgtype": "m.text", "body": "hi" });
+        apply_profile(&mut content, &profiles.get("@_discord_42:example.org"));
+        assert_eq!(content["body"], "Alice <3: hi");
+        assert_eq!(
+            content["formatted_body"],
+            "<strong data-mx-profile-fallback>Alice &lt;3: </strong>hi"
+        );
+        let profile = &content[PER_MESSAGE_PROFILE_KEY];
+        assert_eq!(profile["id"], "@_discord_42:example.org");
+        assert_eq!(profile["displayname"], "Alice <3");
+        assert_eq!(profile["avatar_url"], "mxc://example.org/a");
+        assert_eq!(profile["has_fallback"], true);
+    }
+
+    #[test]
+    fn media_and_edits_keep_their_bodies_usable() {
+        let profiles = MessageProfiles::new();
+        profiles.set_displayname("ghost", "Alice");
+
+        let mut image = json!({ "msgtype": "m.image", "body": "cat.png" });
+        apply_profile(&mut image, &profiles.get("ghost"));
+        assert_eq!(image["body"], "cat.png");
+        assert_eq!(image[PER_MESSAGE_PROFILE_KEY]["has_fallback"], false);
+
+        let mut edit = json!({
+            "msgtype": "m.text",
+            "body": "* new",
+            "m.new_content": { "msgtype": "m.text", "body": "new" },
+        });
+        apply_profile(&mut edit, &profiles.get("ghost"));
+        assert_eq!(edit["m.new_content"]["body"], "Alice: new");
+        assert_eq!(edit["body"], "* Alice: new");
+        assert_eq!(edit[PER_MESSAGE_PROFILE_KEY]["displayname"], "Alice");
+    }
+}
@@ -0,0 +1,210 @@
+//! The appservice's exclusive user and room namespaces, from the
+//! registration. The homeserver sends back everything the bridge's own users
+//! do; those events are dropped before any handler sees them, except
+//! membership changes, which the bridge tracks for its ghosts. Events in rooms
+//! the bridge neither bridges nor holds a namespace for are dropped too, save
+//! invites and commands, which is how such rooms come to be bridged.
+
+use regex::Regex;
+use tracing::warn;
+
+use crate::config::{Config, RegistrationNamespaceEntry};
+
+pub struct NamespaceFilter {
+    users: Vec<Regex>,
+    rooms: Vec<Regex>,
+    bot_user_id: String,
+}
+
+impl NamespaceFilter {
+    /// Compiles the exclusive user and room regexes of the registration,
+    /// anchored at the start like the homeserver matches them. Registrations
+    /// without user namespaces fall back to the ghost prefix on the bridge's
+    /// domain.
+    pub fn from_config(config: &Config) -> Self {
+        let namespaces = &config.registration.namespaces;
+        Self::new(
+            &namespaces.users,
+            &namespaces.rooms,
+            &config.registration.sender_localpart,
+            &config.bridge.domain,
+        )
+    }
+
+    pub fn new(
+        users: &[RegistrationNamespaceEntry],
+        rooms: &[RegistrationNamespaceEntry],
+        sender_localpart: &str,
+        domain: &str,
+    ) -> Self {
+        let mut users = compile_exclusive(users, "user");
+        if users.is_empty() {
+            let fallback = format!("^@_discord_.*:{}$", regex::escape(domain));
+            users.extend(Regex::new(&fallback).ok());
+        }
+        Self {
+            users,
+            rooms: compile_exclusive(rooms, "room"),
+            bot_user_id: format!("@{}:{}", sender_localpart, domain),
+        }
+    }
+
+    /// Whether the appservice owns `user_id`: the bot or a ghost.
+    pub fn owns_user(&self, user_id: &str) -> bool {
+        user_id == self.bot_user_id || self.users.iter().any(|regex| regex.is_match(user_id))
+    }
+
+    /// Whether `room_id` falls in one of the registration's room namespaces.
+    pub fn owns_room(&self, room_id: &str) -> bool {
+        self.rooms.iter().any(|regex| regex.is_match(room_id))
+    }
+
+    /// Whether events of `event_type` are only processed in rooms the bridge
+    /// owns. Invites and commands are what bridge a room in the first place.
+    pub fn needs_owned_room(event_type: &str) -> bool {
+        !matches!(event_type, "m.room.member" | "m.room.message")
+    }
+
+    /// Why an event should not be processed, or `None` to process it.
+    /// `is_bridged` says whether the room is bridged, and is only asked when
+    /// the namespaces alone do not settle it.
+    pub async fn filter_reason<F>(
+        &self,
+        event_type: &str,
+        room_id: &str,
+        sender: &str,
+        is_bridged: F,
+    ) -> Option<&'static str>
+    where
+        F: Future<Output = bool>,
+    {
+        if event_type != "m.room.member" && self.owns_user(sender) {
+            return Some("own_user");
+        }
+        if Self::needs_owned_room(event_type) && !self.owns_room(room_id) && !is_bridged.await {
+            return Some("unowned_room");
+        }
+        None
+    }
+}
+
+fn compile_exclusive(entries: &[RegistrationNamespaceEntry], kind: &str) -> Vec<Regex> {
+    entries
+        .iter()
+        .filter(|entry| entry.exclusive)
+        .filter_map(|entry| match Regex::new(&format!("^(?:{})", entry.regex)) {
+            Ok(regex) => Some(regex),
+            Err(err) => {
+                warn!(
+                    "ignoring invalid {} namespace {:?}: {}",
+                    kind, entry.regex, err
+                );
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NamespaceFilter;
+    use crate::config::RegistrationNamespaceEntry;
+
+    fn entries(regexes: &[(&str, bool)]) -> Vec<RegistrationNamespaceEntry> {
+        regexes
+            .iter()
+            .map(|(regex, exclusive)| RegistrationNamespaceEntry {
+                exclusive: *exclusive,
+                regex: regex.to_string(),
+            })
+            .collect()
+    }
+
+    fn filter(regexes: &[(&str, bool)]) -> NamespaceFilter {
+        NamespaceFilter::new(&entries(regexes), &[], "discordbot", "example.org")
+    }
+
+    async fn reason(
+        filter: &NamespaceFilter,
+        event_type: &str,
+        room_id: &str,
+        sender: &str,
+    ) -> Option<&'static str> {
+        filter
+            .filter_reason(event_type, room_id, sender, async {
+                room_id == "!bridged:example.org"
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn echoes_of_owned_users_are_filtered() {
+        let filter = filter(&[("@_dc_.*:example\\.org", true), ("@x.*", false)]);
+        let room = "!bridged:example.org";
+        assert_eq!(
+            reason(&filter, "m.room.message", room, "@_dc_1:example.org").await,
+            Some("own_user")
+        );
+        assert_eq!(
+            reason(&filter, "m.reaction", room, "@discordbot:example.org").await,
+            Some("own_user")
+        );
+        assert_eq!(
+            reason(&filter, "m.room.member", room, "@_dc_1:example.org").await,
+            None
+        );
+        // Only exclusive namespaces are the bridge's, and matching is anchored.
+        assert_eq!(
+            reason(&filter, "m.room.message", room, "@xavier:example.org").await,
+            None
+        );
+        assert_eq!(
+            reason(&filter, "m.room.message", room, "@a_dc_1:example.org").await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn events_in_rooms_the_bridge_does_not_own_are_filtered() {
+        let filter = NamespaceFilter::new(
+            &[],
+            &entries(&[("!portal_.*:example\\.org", true), ("!shared.*", false)]),
+            "discordbot",
+            "example.org",
+        );
+        let alice = "@alice:example.org";
+        assert_eq!(
+            reason(&filter, "m.reaction", "!elsewhere:example.org", alice).await,
+            Some("unowned_room")
+        );
+        assert_eq!(
+            reason(&filter, "m.room.redaction", "!shared:example.org", alice).await,
+            Some("unowned_room")
+        );
+        assert_eq!(
+            reason(&filter, "m.reaction", "!bridged:example.org", alice).await,
+            None
+        );
+        assert_eq!(
+            reason(&filter, "m.room.topic", "!portal_1:example.org", alice).await,
+            None
+        );
+        // Invites and commands may arrive before the room is bridged.
+        assert_eq!(
+            reason(&filter, "m.room.member", "!elsewhere:example.org", alice).await,
+            None
+        );
+        assert_eq!(
+            reason(&filter, "m.room.message", "!elsewhere:example.org", alice).await,
+            None
+        );
+    }
+
+    #[test]
+    fn registrations_without_user_namespaces_use_the_ghost_prefix() {
+        let filter = filter(&[("[", true)]);
+        assert!(filter.owns_user("@_discord_1:example.org"));
+        assert!(!filter.owns_user("@_discord_1:elsewhere.org"));
+        assert!(!filter.owns_user("@alice:example.org"));
+    }
+}
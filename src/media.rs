@@ -29,6 +29,12 @@ impl MediaHandler {
     }
 
     pub async fn download_from_url(&self, url: &str) -> Result<MediaInfo> {
+        self.download_with_limit(url, None).await
+    }
+
+    /// Downloads `url`, giving up once it turns out to be over `max_size`
+    /// bytes: before reading the body when the server sends its length.
+    async fn download_with_limit(&self, url: &str, max_size: Option<usize>) -> Result<MediaInfo> {
         debug!("downloading media from {}", url);
 
         let response = self
@@ -46,6 +52,18 @@ impl MediaHandler {
             ));
         }
 
+        if let Some(max_size) = max_size
+            && let Some(length) = response.content_length()
+            && length > max_size as u64
+        {
+            return Err(anyhow!(
+                "{} is {} bytes, over the limit of {}",
+                url,
+                length,
+                max_size
+            ));
+        }
+
         let headers = response.headers().clone();
         let raw_content_type = headers
             .get("content-type")
@@ -63,6 +81,16 @@ impl MediaHandler {
             .to_vec();
 
         let size = data.len();
+        if let Some(max_size) = max_size
+            && size > max_size
+        {
+            return Err(anyhow!(
+                "{} is {} bytes, over the limit of {}",
+                url,
+                size,
+                max_size
+            ));
+        }
         let mut filename = content_disposition
             .as_deref()
             .and_then(filename_from_content_disposition)
@@ -82,18 +110,38 @@ impl MediaHandler {
     }
 
     pub async fn download_matrix_media(&self, mxc_url: &str) -> Result<MediaInfo> {
-        if !mxc_url.starts_with("mxc://") {
-            return Err(anyhow!("invalid mxc URL: {}", mxc_url));
-        }
+        self.download_from_url(&self.matrix_download_url(mxc_url)?)
+            .await
+    }
+
+    /// Downloads Matrix media to re-upload to Discord, failing without
+    /// fetching it all when it is over Discord's upload limit.
+    pub async fn download_matrix_media_for_discord(&self, mxc_url: &str) -> Result<MediaInfo> {
+        self.download_with_limit(
+            &self.matrix_download_url(mxc_url)?,
+            Some(MAX_DISCORD_FILE_SIZE),
+        )
+        .await
+    }
 
-        let mxc_path = mxc_url.trim_start_matches("mxc://");
-        let download_url = format!(
+    /// The homeserver URL serving an `mxc://` URI.
+    pub fn matrix_download_url(&self, mxc_url: &str) -> Result<String> {
+        let Some(mxc_path) = mxc_url.strip_prefix("mxc://") else {
+            return Err(anyhow!("invalid mxc URL: {}", mxc_url));
+        };
+        Ok(format!(
             "{}/_matrix/media/v3/download/{}",
             self.homeserver_url.trim_end_matches('/'),
             mxc_path
-        );
+        ))
+    }
+
+    pub fn exceeds_discord_limit(size: usize) -> bool {
+        size > MAX_DISCORD_FILE_SIZE
+    }
 
-        self.download_from_url(&download_url).await
+    pub fn exceeds_matrix_limit(size: usize) -> bool {
+        size > MAX_MATRIX_FILE_SIZE
     }
 
     pub async fn upload_to_matrix(&self, media: &MediaInfo, access_token: &str) -> Result<String> {
@@ -164,6 +212,97 @@ impl MediaHandler {
     }
 }
 
+impl MediaInfo {
+    /// The `info` object of a Matrix media event, with the dimensions of
+    /// images whose header could be read.
+    pub fn matrix_info(&self) -> serde_json::Value {
+        let mut info = serde_json::json!({
+            "mimetype": self.content_type,
+            "size": self.size,
+        });
+        if let Some((width, height)) = image_dimensions(&self.data) {
+            info["w"] = width.into();
+            info["h"] = height.into();
+        }
+        info
+    }
+
+    /// Renames the file to `name`, given the extension of its content type
+    /// when it has none. Discord only plays audio and video inline when the
+    /// file name ends in an extension it knows.
+    pub fn with_filename(mut self, name: &str) -> Self {
+        let Some(name) = sanitize_filename(name) else {
+            return self;
+        };
+        if self.content_type == "application/octet-stream"
+            && let Some(content_type) = guess_mime_from_filename(&name)
+        {
+            self.content_type = content_type.to_string();
+        }
+        self.filename = ensure_filename_extension(&name, &self.content_type);
+        self
+    }
+}
+
+/// A still frame of a video on Discord's CDN, rendered by its media proxy.
+pub fn discord_video_thumbnail_url(url: &str) -> Option<String> {
+    let mut parsed = reqwest::Url::parse(url).ok()?;
+    if parsed.host_str() != Some("cdn.discordapp.com") {
+        return None;
+    }
+    parsed.set_host(Some("media.discordapp.net")).ok()?;
+    parsed.query_pairs_mut().append_pair("format", "jpeg");
+    Some(parsed.into())
+}
+
+/// Width and height read from a PNG, GIF, JPEG or WebP header.
+pub fn image_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let be16 = |at: usize| -> Option<u32> {
+        Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?).into())
+    };
+    let le16 = |at: usize| -> Option<u32> {
+        Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?).into())
+    };
+    let le24 = |at: usize| -> Option<u32> {
+        let bytes = data.get(at..at + 3)?;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]))
+    };
+
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        let width = u32::from_be_bytes(data.get(16..20)?.try_into().ok()?);
+        let height = u32::from_be_bytes(data.get(20..24)?.try_into().ok()?);
+        return Some((width, height));
+    }
+    if data.starts_with(b"GIF8") {
+        return Some((le16(6)?, le16(8)?));
+    }
+    if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        return match data.get(12..16)? {
+            b"VP8 " => Some((le16(26)? & 0x3fff, le16(28)? & 0x3fff)),
+            b"VP8L" => {
+                let bits = u32::from_le_bytes(data.get(21..25)?.try_into().ok()?);
+                Some(((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1))
+            }
+            b"VP8X" => Some((le24(24)? + 1, le24(27)? + 1)),
+            _ => None,
+        };
+    }
+    if data.starts_with(&[0xff, 0xd8]) {
+        // Walk the segments up to the start-of-frame marker.
+        let mut at = 2;
+        while *data.get(at)? == 0xff {
+            let marker = *data.get(at + 1)?;
+            let length = be16(at + 2)? as usize;
+            let is_frame = matches!(marker, 0xc0..=0xcf) && !matches!(marker, 0xc4 | 0xc8 | 0xcc);
+            if is_frame {
+                return Some((be16(at + 7)?, be16(at + 5)?));
+            }
+            at += 2 + length;
+        }
+    }
+    None
+}
+
 fn filename_from_content_disposition(value: &str) -> Option<String> {
     for part in value.split(';').map(str::trim) {
         if let Some(raw) = part.strip_prefix("filename*=") {
@@ -266,7 +405,8 @@ fn normalize_content_type(header_value: Option<&str>, filename: &str, data: &[u8
         .to_string()
 }
 
-fn ensure_filename_extension(filename: &str, content_type: &str) -> String {
+/// `filename` with the extension of `content_type` added when it has none.
+pub fn ensure_filename_extension(filename: &str, content_type: &str) -> String {
     if Path::new(filename).extension().is_some() {
         return filename.to_string();
     }
@@ -296,8 +436,13 @@ fn extension_to_mime(ext: &str) -> Option<&'static str> {
         "svg" => Some("image/svg+xml"),
         "mp4" => Some("video/mp4"),
         "mov" => Some("video/quicktime"),
+        "webm" => Some("video/webm"),
+        "mkv" => Some("video/x-matroska"),
         "mp3" => Some("audio/mpeg"),
-        "ogg" => Some("audio/ogg"),
+        "m4a" => Some("audio/mp4"),
+        "ogg" | "oga" => Some("audio/ogg"),
+        "opus" => Some("audio/opus"),
+        "flac" => Some("audio/flac"),
         "wav" => Some("audio/wav"),
         "pdf" => Some("application/pdf"),
         _ => None,
@@ -314,9 +459,15 @@ fn extension_from_mime(content_type: &str) -> Option<&'static str> {
         "image/svg+xml" => Some("svg"),
         "video/mp4" => Some("mp4"),
         "video/quicktime" => Some("mov"),
-        "audio/mpeg" => Some("mp3"),
+        "video/webm" => Some("webm"),
+        "video/x-matroska" => Some("mkv"),
+        "audio/mpeg" | "audio/mp3" => Some("mp3"),
+        "audio/mp4" | "audio/x-m4a" | "audio/aac" => Some("m4a"),
         "audio/ogg" => Some("ogg"),
-        "audio/wav" => Some("wav"),
+        "audio/opus" => Some("opus"),
+        "audio/webm" => Some("webm"),
+        "audio/flac" | "audio/x-flac" => Some("flac"),
+        "audio/wav" | "audio/x-wav" | "audio/wave" => Some("wav"),
         "application/pdf" => Some("pdf"),
         _ => None,
     }
@@ -335,6 +486,28 @@ fn sniff_mime(data: &[u8]) -> Option<&'static str> {
     if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
         return Some("image/webp");
     }
+    if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WAVE" {
+        return Some("audio/wav");
+    }
+    if data.len() >= 12 && &data[4..8] == b"ftyp" {
+        return Some(if &data[8..12] == b"M4A " {
+            "audio/mp4"
+        } else {
+            "video/mp4"
+        });
+    }
+    if data.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        return Some("video/webm");
+    }
+    if data.starts_with(b"OggS") {
+        return Some("audio/ogg");
+    }
+    if data.starts_with(b"fLaC") {
+        return Some("audio/flac");
+    }
+    if data.starts_with(b"ID3") {
+        return Some("audio/mpeg");
+    }
 
     None
 }
@@ -348,10 +521,61 @@ mod urlencoding {
 #[cfg(test)]
 mod tests {
     use super::{
-        ensure_filename_extension, filename_from_content_disposition, filename_from_url,
+        MediaHandler, MediaInfo, discord_video_thumbnail_url, ensure_filename_extension,
+        filename_from_content_disposition, filename_from_url, image_dimensions,
         normalize_content_type,
     };
 
+    #[test]
+    fn reads_dimensions_from_image_headers() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend_from_slice(&640u32.to_be_bytes());
+        png.extend_from_slice(&480u32.to_be_bytes());
+        assert_eq!(image_dimensions(&png), Some((640, 480)));
+
+        let gif = b"GIF89a\x20\x03\x58\x02";
+        assert_eq!(image_dimensions(gif), Some((800, 600)));
+
+        // SOI, an APP0 segment, then a baseline frame of 300x200.
+        let jpeg = [
+            0xff, 0xd8, 0xff, 0xe0, 0x00, 0x04, 0x00, 0x00, 0xff, 0xc0, 0x00, 0x11, 0x08, 0x00,
+            0xc8, 0x01, 0x2c,
+        ];
+        assert_eq!(image_dimensions(&jpeg), Some((300, 200)));
+
+        assert_eq!(image_dimensions(b"not an image"), None);
+        assert_eq!(image_dimensions(b"\x89PNG\r\n\x1a\n"), None);
+    }
+
+    #[test]
+    fn video_thumbnails_come_from_the_discord_media_proxy() {
+        assert_eq!(
+            discord_video_thumbnail_url("https://cdn.discordapp.com/attachments/1/2/clip.mp4?ex=1")
+                .as_deref(),
+            Some("https://media.discordapp.net/attachments/1/2/clip.mp4?ex=1&format=jpeg")
+        );
+        assert_eq!(
+            discord_video_thumbnail_url("https://example.org/clip.mp4"),
+            None
+        );
+    }
+
+    #[test]
+    fn mxc_urls_map_to_homeserver_downloads() {
+        let media = MediaHandler::new("https://matrix.example.org/");
+        assert_eq!(
+            media
+                .matrix_download_url("mxc://example.org/abc123")
+                .unwrap(),
+            "https://matrix.example.org/_matrix/media/v3/download/example.org/abc123"
+        );
+        assert!(
+            media
+                .matrix_download_url("https://example.org/a.png")
+                .is_err()
+        );
+    }
+
     #[test]
     fn picks_filename_from_content_disposition_filename_star() {
         let header = "attachment; filename*=UTF-8''outfox-board.png";
@@ -376,4 +600,41 @@ mod tests {
         let filename = ensure_filename_extension("attachment", &content_type);
         assert_eq!(filename, "attachment.png");
     }
+
+    #[test]
+    fn sniffs_audio_and_video_containers() {
+        let mp4 = b"\0\0\0\x18ftypisom\0\0\x02\0";
+        assert_eq!(
+            normalize_content_type(Some("application/octet-stream"), "clip", mp4),
+            "video/mp4"
+        );
+        let webm = [0x1A, 0x45, 0xDF, 0xA3, 0x9F];
+        assert_eq!(normalize_content_type(None, "clip", &webm), "video/webm");
+        assert_eq!(
+            normalize_content_type(None, "voice", b"OggS\0\x02"),
+            "audio/ogg"
+        );
+        assert_eq!(ensure_filename_extension("voice", "audio/ogg"), "voice.ogg");
+        assert_eq!(ensure_filename_extension("clip", "video/webm"), "clip.webm");
+    }
+
+    #[test]
+    fn renamed_media_keeps_a_playable_extension() {
+        let media = |content_type: &str| MediaInfo {
+            data: Vec::new(),
+            content_type: content_type.to_string(),
+            filename: "AbCdEfG".to_string(),
+            size: 0,
+        };
+
+        let video = media("video/mp4").with_filename("Holiday clip");
+        assert_eq!(video.filename, "Holiday clip.mp4");
+
+        let audio = media("application/octet-stream").with_filename("voice.ogg");
+        assert_eq!(audio.filename, "voice.ogg");
+        assert_eq!(audio.content_type, "audio/ogg");
+
+        let unnamed = media("video/mp4").with_filename("  ");
+        assert_eq!(unnamed.filename, "AbCdEfG");
+    }
 }
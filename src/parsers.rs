@@ -1,9 +1,15 @@
 pub mod command_parser;
+pub mod command_registry;
 pub mod common;
 pub mod discord_parser;
+pub mod formatter;
+pub mod location;
 pub mod matrix_parser;
 
 pub use command_parser::{ParsedCommand, parse_guild_and_channel, parse_prefixed_command};
-pub use common::{BridgeMessage, MessageUtils, ParsedMessage};
+pub use command_registry::{
+    CommandPermission, CommandRegistry, CommandSpec, DISCORD_COMMANDS, MATRIX_COMMANDS,
+};
+pub use common::{BridgeMessage, MatrixEmoticon, MatrixUserPill, MessageUtils, ParsedMessage};
 pub use discord_parser::{DiscordMessageParser, DiscordToMatrixConverter};
 pub use matrix_parser::{MatrixMessageParser, MatrixToDiscordConverter};
@@ -0,0 +1,326 @@
+/// Who may run a command. The handlers check these, and help shows them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandPermission {
+    Anyone,
+    /// Matrix users at the provisioning power level.
+    Provisioning,
+    /// Anyone may see the current value; changing it takes the provisioning
+    /// power level.
+    ProvisioningToChange,
+    /// Discord members holding all of these permissions.
+    Discord(&'static [&'static str]),
+}
+
+/// One bot command: how it is called, who may call it and what it does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    /// Usage after the command name, e.g. `<guild_id> <channel_id>`.
+    pub args: &'static str,
+    pub permission: CommandPermission,
+    pub description: &'static str,
+    /// Extra lines for `help <command>`.
+    pub details: Option<&'static str>,
+}
+
+/// The commands behind one prefix. Help, unknown-command suggestions and
+/// permission checks are all derived from it.
+#[derive(Debug, Clone, Copy)]
+pub struct CommandRegistry {
+    pub prefix: &'static str,
+    pub commands: &'static [CommandSpec],
+}
+
+/// Commands Discord users send as `!matrix ...`.
+pub const DISCORD_COMMANDS: CommandRegistry = CommandRegistry {
+    prefix: "!matrix",
+    commands: &[
+        CommandSpec {
+            name: "approve",
+            args: "",
+            permission: CommandPermission::Discord(&["MANAGE_GUILD"]),
+            description: "Approve a pending bridge request",
+            details: None,
+        },
+        CommandSpec {
+            name: "deny",
+            args: "",
+            permission: CommandPermission::Discord(&["MANAGE_GUILD"]),
+            description: "Deny a pending bridge request",
+            details: None,
+        },
+        CommandSpec {
+            name: "bridge",
+            args: "<guild_id> <channel_id>",
+            permission: CommandPermission::Discord(&["MANAGE_WEBHOOKS", "MANAGE_CHANNELS"]),
+            description: "Bridge this channel to a Matrix room",
+            details: None,
+        },
+        CommandSpec {
+            name: "kick",
+            args: "<name>",
+            permission: CommandPermission::Discord(&["KICK_MEMBERS"]),
+            description: "Kicks a user on the Matrix side",
+            details: None,
+        },
+        CommandSpec {
+            name: "ban",
+            args: "<name>",
+            permission: CommandPermission::Discord(&["BAN_MEMBERS"]),
+            description: "Bans a user on the Matrix side",
+            details: None,
+        },
+        CommandSpec {
+            name: "unban",
+            args: "<name>",
+            permission: CommandPermission::Discord(&["BAN_MEMBERS"]),
+            description: "Unbans a user on the Matrix side",
+            details: None,
+        },
+        CommandSpec {
+            name: "sync-bans",
+            args: "[--dry-run]",
+            permission: CommandPermission::Discord(&["BAN_MEMBERS"]),
+            description: "Bans the users banned in the bridged Matrix room from this server",
+            details: Some("`--dry-run` only lists who would be banned."),
+        },
+        CommandSpec {
+            name: "unbridge",
+            args: "",
+            permission: CommandPermission::Discord(&["MANAGE_WEBHOOKS", "MANAGE_CHANNELS"]),
+            description: "Unbridge Matrix rooms from this channel",
+            details: None,
+        },
+        CommandSpec {
+            name: "status",
+            args: "",
+            permission: CommandPermission::Anyone,
+            description: "Shows how this channel is bridged and whether that is working",
+            details: None,
+        },
+        CommandSpec {
+            name: "presence",
+            args: "<online|unavailable|offline|clear>",
+            permission: CommandPermission::Anyone,
+            description: "Pins the presence your Matrix ghost shows, whatever Discord reports",
+            details: Some("`clear` goes back to following your Discord status."),
+        },
+    ],
+};
+
+/// Commands Matrix users send as `!discord ...`.
+pub const MATRIX_COMMANDS: CommandRegistry = CommandRegistry {
+    prefix: "!discord",
+    commands: &[
+        CommandSpec {
+            name: "bridge",
+            args: "<guildId> <channelId>",
+            permission: CommandPermission::Provisioning,
+            description: "Bridges this room to a Discord channel",
+            details: Some("Use `guild/channel` or `guild channel`."),
+        },
+        CommandSpec {
+            name: "unbridge",
+            args: "",
+            permission: CommandPermission::Provisioning,
+            description: "Unbridges a Discord channel from this room",
+            details: Some(
+                "Confirm with `!discord unbridge confirm`; the bridge admin can skip that with `--force`.",
+            ),
+        },
+        CommandSpec {
+            name: "autoinvite",
+            args: "[on|off]",
+            permission: CommandPermission::ProvisioningToChange,
+            description: "Shows or sets whether new Discord members are invited to this room",
+            details: None,
+        },
+        CommandSpec {
+            name: "badge",
+            args: "[on|off]",
+            permission: CommandPermission::ProvisioningToChange,
+            description: "Shows or sets whether messages from Discord carry a \"via Discord\" badge",
+            details: Some(
+                "The badge is added to the formatted body only, so plain-text clients are unaffected.",
+            ),
+        },
+        CommandSpec {
+            name: "attachments",
+            args: "[<matrix_to_discord|discord_to_matrix> <reupload|link_only|reupload_with_link|default>]",
+            permission: CommandPermission::ProvisioningToChange,
+            description: "Shows or sets whether attachments are uploaded, linked, or both",
+            details: Some(
+                "`default` goes back to the bridge's `media.attachment_policy` for that direction.",
+            ),
+        },
+        CommandSpec {
+            name: "confirm",
+            args: "[on|off]",
+            permission: CommandPermission::ProvisioningToChange,
+            description: "Shows or sets whether delivered messages get a ✅ reaction",
+            details: Some(
+                "The reaction is removed again when an edit of the message fails to reach Discord.",
+            ),
+        },
+        CommandSpec {
+            name: "rolekeyword",
+            args: "[<roleId> <keyword>|<roleId> --clear]",
+            permission: CommandPermission::ProvisioningToChange,
+            description: "Lists or sets the keywords shown next to Discord role mentions",
+            details: Some("Matrix users can get notified for a role through a keyword rule."),
+        },
+        CommandSpec {
+            name: "sync-bans",
+            args: "[--dry-run]",
+            permission: CommandPermission::Provisioning,
+            description: "Bans the Discord server's banned users from this room",
+            details: Some(
+                "Bans their Discord ghosts and any linked Matrix accounts; `--dry-run` only lists who would be banned.",
+            ),
+        },
+        CommandSpec {
+            name: "status",
+            args: "",
+            permission: CommandPermission::Anyone,
+            description: "Shows how this room is bridged and whether that is working",
+            details: Some(
+                "Lists the ids on both sides, who bridged the room and when, the last bridged messages, and the webhook, pause and encryption state.",
+            ),
+        },
+        CommandSpec {
+            name: "nick",
+            args: "[name|--clear]",
+            permission: CommandPermission::Anyone,
+            description: "Shows or sets the name your messages in this room appear under on Discord",
+            details: None,
+        },
+    ],
+};
+
+impl CommandRegistry {
+    pub fn get(&self, name: &str) -> Option<&'static CommandSpec> {
+        self.commands.iter().find(|spec| spec.name == name)
+    }
+
+    /// The command as it is typed, e.g. `` `!matrix kick <name>` ``.
+    pub fn usage(&self, spec: &CommandSpec) -> String {
+        if spec.args.is_empty() {
+            format!("`{} {}`", self.prefix, spec.name)
+        } else {
+            format!("`{} {} {}`", self.prefix, spec.name, spec.args)
+        }
+    }
+
+    /// The command list, or one command's usage, details and permission.
+    pub fn render_help(&self, command: Option<&str>) -> String {
+        let Some(command) = command else {
+            let mut help = "Available Commands:".to_string();
+            for spec in self.commands {
+                help.push_str(&format!("\n - {}: {}", self.usage(spec), spec.description));
+            }
+            return help;
+        };
+        let Some(spec) = self.get(command) else {
+            return format!(
+                "**ERROR:** unknown command! Try `{} help` to see all commands{}",
+                self.prefix,
+                self.suggestion(command)
+            );
+        };
+
+        let mut help = format!("{}: {}", self.usage(spec), spec.description);
+        if let Some(details) = spec.details {
+            help.push('\n');
+            help.push_str(details);
+        }
+        match spec.permission {
+            CommandPermission::Anyone => {}
+            CommandPermission::Provisioning => {
+                help.push_str("\nRequires permission to change this room's power levels.");
+            }
+            CommandPermission::ProvisioningToChange => {
+                help.push_str(
+                    "\nChanging it requires permission to change this room's power levels.",
+                );
+            }
+            CommandPermission::Discord(permissions) => {
+                help.push_str(&format!("\nRequires {}.", permissions.join(", ")));
+            }
+        }
+        help
+    }
+
+    /// The reply to a command that is not registered.
+    pub fn unknown_command(&self, command: &str) -> String {
+        format!(
+            "**ERROR:** unknown command. Try `{} help` to see all commands{}",
+            self.prefix,
+            self.suggestion(command)
+        )
+    }
+
+    /// ` Did you mean ...?` for the closest registered command, if any is
+    /// close enough to be a typo.
+    fn suggestion(&self, command: &str) -> String {
+        let command = command.to_lowercase();
+        self.commands
+            .iter()
+            .map(|spec| (edit_distance(&command, spec.name), spec))
+            .filter(|(distance, spec)| *distance <= 2 && *distance < spec.name.len())
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, spec)| format!(". Did you mean `{} {}`?", self.prefix, spec.name))
+            .unwrap_or_default()
+    }
+}
+
+/// Levenshtein distance between two short strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DISCORD_COMMANDS, MATRIX_COMMANDS, edit_distance};
+
+    #[test]
+    fn help_lists_every_registered_command() {
+        let help = DISCORD_COMMANDS.render_help(None);
+        for spec in DISCORD_COMMANDS.commands {
+            assert!(help.contains(&DISCORD_COMMANDS.usage(spec)));
+        }
+        assert!(help.contains("\n - `!matrix kick <name>`: Kicks a user on the Matrix side"));
+
+        assert_eq!(
+            DISCORD_COMMANDS.render_help(Some("unbridge")),
+            "`!matrix unbridge`: Unbridge Matrix rooms from this channel\nRequires MANAGE_WEBHOOKS, MANAGE_CHANNELS."
+        );
+        assert_eq!(
+            MATRIX_COMMANDS.render_help(Some("nick")),
+            "`!discord nick [name|--clear]`: Shows or sets the name your messages in this room appear under on Discord"
+        );
+    }
+
+    #[test]
+    fn unknown_commands_suggest_close_matches() {
+        assert_eq!(
+            DISCORD_COMMANDS.unknown_command("unbrige"),
+            "**ERROR:** unknown command. Try `!matrix help` to see all commands. Did you mean `!matrix unbridge`?"
+        );
+        assert_eq!(
+            MATRIX_COMMANDS.unknown_command("frobnicate"),
+            "**ERROR:** unknown command. Try `!discord help` to see all commands"
+        );
+        assert_eq!(edit_distance("kick", "kick"), 0);
+        assert_eq!(edit_distance("bna", "ban"), 2);
+    }
+}
@@ -4,6 +4,8 @@ use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use super::formatter;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ParsedMessage {
     pub text: String,
@@ -22,6 +24,22 @@ pub struct EmojiMention {
     pub animated: bool,
 }
 
+/// A custom emoticon (`<img data-mx-emoticon>`) in a Matrix message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MatrixEmoticon {
+    /// The shortcode without surrounding colons.
+    pub shortcode: String,
+    pub mxc_url: String,
+}
+
+/// A user pill (`<a href="https://matrix.to/#/@user:server">`) in a Matrix
+/// message, with the text it shows.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MatrixUserPill {
+    pub text: String,
+    pub user_id: String,
+}
+
 impl ParsedMessage {
     pub fn new(content: &str) -> Self {
         Self {
@@ -154,133 +172,63 @@ impl MessageUtils {
             .collect()
     }
 
-    pub fn extract_discord_attachments(content: &str) -> Vec<String> {
-        let re = Regex::new(r"https?://[^\s<>\[\](){}\x22\x27]+\.(?:png|jpg|jpeg|gif|webp|mp4|webm|mp3|ogg|wav|pdf|zip|txt)(?:\?[^\s<>\[\](){}\x22\x27]*)?").unwrap();
-        re.find_iter(content)
-            .map(|m| m.as_str().to_string())
-            .collect()
-    }
-
-    pub fn convert_html_to_discord_markdown(html: &str) -> String {
-        let mut result = html.to_string();
-
-        result = Self::convert_html_links(&result);
-        result = Self::convert_html_formatting(&result);
-        result = Self::convert_html_code_blocks(&result);
-        result = Self::convert_html_lists(&result);
-        result = Self::convert_html_blockquotes(&result);
-        result = Self::convert_html_headers(&result);
-        result = strip_html_tags(&result);
-        result = Self::cleanup_whitespace(&result);
-
-        result
-    }
-
-    fn convert_html_links(html: &str) -> String {
-        let re = Regex::new(r#"<a[^>]*href="([^"]*)"[^>]*>([^<]*)</a>"#).unwrap();
-        re.replace_all(html, |caps: &regex::Captures| {
-            let url = &caps[1];
-            let text = &caps[2];
-            if url == text {
-                format!("<{}>", url)
-            } else {
-                format!("[{}]({})", text, url)
+    pub fn extract_matrix_emoticons(html: &str) -> Vec<MatrixEmoticon> {
+        let img_re = Regex::new(r"<img\b[^>]*>").unwrap();
+        let attr_re = Regex::new(r#"\b(src|alt|title)\s*=\s*"([^"]*)""#).unwrap();
+        let mut emoticons: Vec<MatrixEmoticon> = Vec::new();
+        for tag in img_re.find_iter(html).map(|m| m.as_str()) {
+            if !tag.contains("data-mx-emoticon") {
+                continue;
             }
-        })
-        .to_string()
-    }
-
-    fn convert_html_formatting(html: &str) -> String {
-        let mut result = html.to_string();
-
-        let strong_re = Regex::new(r"<strong>([^<]*)</strong>").unwrap();
-        result = strong_re.replace_all(&result, "**$1**").to_string();
-
-        let b_re = Regex::new(r"<b>([^<]*)</b>").unwrap();
-        result = b_re.replace_all(&result, "**$1**").to_string();
-
-        let em_re = Regex::new(r"<em>([^<]*)</em>").unwrap();
-        result = em_re.replace_all(&result, "*$1*").to_string();
-
-        let i_re = Regex::new(r"<i>([^<]*)</i>").unwrap();
-        result = i_re.replace_all(&result, "*$1*").to_string();
-
-        let del_re = Regex::new(r"<del>([^<]*)</del>").unwrap();
-        result = del_re.replace_all(&result, "~~$1~~").to_string();
-
-        let s_re = Regex::new(r"<s>([^<]*)</s>").unwrap();
-        result = s_re.replace_all(&result, "~~$1~~").to_string();
-
-        let u_re = Regex::new(r"<u>([^<]*)</u>").unwrap();
-        result = u_re.replace_all(&result, "__$1__").to_string();
-
-        let code_re = Regex::new(r"<code>([^<]*)</code>").unwrap();
-        result = code_re.replace_all(&result, "`$1`").to_string();
-
-        let span_re = Regex::new(r"<span[^>]*>([^<]*)</span>").unwrap();
-        result = span_re.replace_all(&result, "$1").to_string();
-
-        result
-    }
-
-    fn convert_html_code_blocks(html: &str) -> String {
-        let re =
-            Regex::new(r#"<pre[^>]*><code(?:\s+class="language-([^"]*)")?>([^<]*)</code></pre>"#)
-                .unwrap();
-        re.replace_all(html, |caps: &regex::Captures| {
-            let lang = caps.get(1).map(|m| m.as_str()).unwrap_or("");
-            let code = &caps[2];
-            if lang.is_empty() {
-                format!("```\n{}\n```", code)
-            } else {
-                format!("```{}\n{}\n```", lang, code)
+            let mut src = None;
+            let mut name = None;
+            for cap in attr_re.captures_iter(tag) {
+                match &cap[1] {
+                    "src" => src = Some(cap[2].to_string()),
+                    "alt" => name = Some(cap[2].to_string()),
+                    "title" if name.is_none() => name = Some(cap[2].to_string()),
+                    _ => {}
+                }
             }
-        })
-        .to_string()
-    }
-
-    fn convert_html_lists(html: &str) -> String {
-        let li_re = Regex::new(r"<li>([^<]*)</li>").unwrap();
-        let mut result = li_re.replace_all(html, "- $1\n").to_string();
-
-        let ul_re = Regex::new(r"</?ul>").unwrap();
-        result = ul_re.replace_all(&result, "").to_string();
-
-        let ol_re = Regex::new(r"</?ol>").unwrap();
-        result = ol_re.replace_all(&result, "").to_string();
-
-        result
+            let (Some(mxc_url), Some(name)) = (src, name) else {
+                continue;
+            };
+            let shortcode = name.trim_matches(':').to_string();
+            if !mxc_url.starts_with("mxc://") || shortcode.is_empty() {
+                continue;
+            }
+            let emoticon = MatrixEmoticon { shortcode, mxc_url };
+            if !emoticons.contains(&emoticon) {
+                emoticons.push(emoticon);
+            }
+        }
+        emoticons
     }
 
-    fn convert_html_blockquotes(html: &str) -> String {
-        let re = Regex::new(r"<blockquote>([^<]*)</blockquote>").unwrap();
-        re.replace_all(html, |caps: &regex::Captures| {
-            let text = &caps[1];
-            text.lines()
-                .map(|line| format!("> {}", line))
-                .collect::<Vec<_>>()
-                .join("\n")
-        })
-        .to_string()
+    /// User pills in `html`, in the order they appear.
+    pub fn extract_matrix_user_pills(html: &str) -> Vec<MatrixUserPill> {
+        let link_re = Regex::new(
+            r#"<a\b[^>]*\bhref\s*=\s*"https://matrix\.to/#/(@[^"?/]+)[^"]*"[^>]*>(.*?)</a>"#,
+        )
+        .unwrap();
+        link_re
+            .captures_iter(html)
+            .map(|caps| MatrixUserPill {
+                text: strip_html_tags(&caps[2]),
+                user_id: caps[1].replace("%40", "@").replace("%3A", ":"),
+            })
+            .collect()
     }
 
-    fn convert_html_headers(html: &str) -> String {
-        let mut result = html.to_string();
-
-        for level in (1..=6).rev() {
-            let re = Regex::new(&format!("<h{}[^>]*>([^<]*)</h{}>", level, level)).unwrap();
-            let prefix = "#".repeat(level);
-            result = re
-                .replace_all(&result, format!("{} $1", prefix))
-                .to_string();
-        }
-
-        result
+    pub fn extract_discord_attachments(content: &str) -> Vec<String> {
+        let re = Regex::new(r"https?://[^\s<>\[\](){}\x22\x27]+\.(?:png|jpg|jpeg|gif|webp|mp4|webm|mp3|ogg|wav|pdf|zip|txt)(?:\?[^\s<>\[\](){}\x22\x27]*)?").unwrap();
+        re.find_iter(content)
+            .map(|m| m.as_str().to_string())
+            .collect()
     }
 
-    fn cleanup_whitespace(text: &str) -> String {
-        let re = Regex::new(r"\n{3,}").unwrap();
-        re.replace_all(text, "\n\n").trim().to_string()
+    pub fn convert_html_to_discord_markdown(html: &str) -> String {
+        formatter::html_to_markdown(html)
     }
 
     pub fn convert_matrix_reply_to_discord(
@@ -1,13 +1,17 @@
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 
 use anyhow::Result;
-use chrono::Utc;
+use chrono::{DateTime, TimeZone, Utc};
 use regex::Regex;
 use serde_json::{Value, json};
 
 use super::common::{BridgeMessage, EmojiMention, MessageUtils, ParsedMessage};
+use super::formatter;
+use crate::cache::RoleSnapshot;
+use crate::db::{EmojiUsageKind, RoomStore};
 use crate::discord::DiscordClient;
-use crate::emoji::EmojiHandler;
+use crate::emoji::{EmojiHandler, discord_emoji_url, emoticon_html};
 
 pub struct DiscordMessageParser {
     _client: Arc<DiscordClient>,
@@ -23,25 +27,57 @@ impl DiscordMessageParser {
     }
 }
 
+/// How a `<@&id>` role mention is rendered on the Matrix side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RoleMention {
+    Role(RoleSnapshot),
+    /// The role is configured to notify the whole room.
+    Room,
+}
+
+/// How a `<#id>` channel mention is rendered on the Matrix side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChannelMention {
+    /// The channel is bridged; link to its Matrix room.
+    Bridged { room_id: String, name: String },
+    /// The channel is known but not bridged, so only its name can be shown.
+    Unbridged { name: String },
+}
+
+/// Mentions in a Discord message that were resolved ahead of formatting,
+/// keyed by Discord id.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResolvedMentions {
+    pub roles: HashMap<String, RoleMention>,
+    pub channels: HashMap<String, ChannelMention>,
+    /// The room's `role_keywords`, written out next to those role mentions.
+    pub role_keywords: BTreeMap<String, String>,
+}
+
+const DEFAULT_ROLE_COLOR: &str = "#99AAB5";
+
 pub struct DiscordToMatrixConverter {
     discord_client: Arc<DiscordClient>,
     emoji_handler: Option<Arc<EmojiHandler>>,
+    room_store: Option<Arc<dyn RoomStore>>,
     domain: String,
+    room_mention_roles: Vec<String>,
+    determine_code_language: bool,
     mention_regex: Regex,
+    escaped_mention_regex: Regex,
     channel_regex: Regex,
+    escaped_channel_regex: Regex,
     role_regex: Regex,
+    escaped_role_regex: Regex,
+    timestamp_regex: Regex,
+    escaped_timestamp_regex: Regex,
     emoji_regex: Regex,
     animated_emoji_regex: Regex,
+    escaped_emoji_regex: Regex,
     everyone_regex: Regex,
     here_regex: Regex,
     code_block_regex: Regex,
-    inline_code_regex: Regex,
-    bold_regex: Regex,
-    italic_regex: Regex,
-    underline_regex: Regex,
-    strikethrough_regex: Regex,
     spoiler_regex: Regex,
-    quote_regex: Regex,
 }
 
 impl DiscordToMatrixConverter {
@@ -49,22 +85,25 @@ impl DiscordToMatrixConverter {
         Self {
             discord_client,
             emoji_handler: None,
+            room_store: None,
             domain: String::new(),
+            room_mention_roles: Vec::new(),
+            determine_code_language: false,
             mention_regex: Regex::new(r"<@!?(\d+)>").unwrap(),
+            escaped_mention_regex: Regex::new(r"&lt;@!?(\d+)&gt;").unwrap(),
             channel_regex: Regex::new(r"<#(\d+)>").unwrap(),
+            escaped_channel_regex: Regex::new(r"&lt;#(\d+)&gt;").unwrap(),
             role_regex: Regex::new(r"<@&(\d+)>").unwrap(),
+            escaped_role_regex: Regex::new(r"&lt;@&amp;(\d+)&gt;").unwrap(),
+            timestamp_regex: Regex::new(r"<t:(-?\d+)(?::([tTdDfFR]))?>").unwrap(),
+            escaped_timestamp_regex: Regex::new(r"&lt;t:(-?\d+)(?::([tTdDfFR]))?&gt;").unwrap(),
             emoji_regex: Regex::new(r"<:([a-zA-Z0-9_]+):(\d+)>").unwrap(),
             animated_emoji_regex: Regex::new(r"<a:([a-zA-Z0-9_]+):(\d+)>").unwrap(),
+            escaped_emoji_regex: Regex::new(r"&lt;(a?):([a-zA-Z0-9_]+):(\d+)&gt;").unwrap(),
             everyone_regex: Regex::new(r"@everyone").unwrap(),
             here_regex: Regex::new(r"@here").unwrap(),
             code_block_regex: Regex::new(r"```(?:([a-z]*)\n)?([\s\S]*?)```").unwrap(),
-            inline_code_regex: Regex::new(r"`([^`]+)`").unwrap(),
-            bold_regex: Regex::new(r"\*\*([^*]+)\*\*").unwrap(),
-            italic_regex: Regex::new(r"\*([^*]+)\*").unwrap(),
-            underline_regex: Regex::new(r"__([^_]+)__").unwrap(),
-            strikethrough_regex: Regex::new(r"~~([^~]+)~~").unwrap(),
             spoiler_regex: Regex::new(r"\|\|([^|]+)\|\|").unwrap(),
-            quote_regex: Regex::new(r"^> (.+)$").unwrap(),
         }
     }
 
@@ -73,152 +112,240 @@ impl DiscordToMatrixConverter {
         self
     }
 
+    pub fn with_room_store(mut self, room_store: Arc<dyn RoomStore>) -> Self {
+        self.room_store = Some(room_store);
+        self
+    }
+
     pub fn with_domain(mut self, domain: String) -> Self {
         self.domain = domain;
         self
     }
 
-    pub fn format_for_matrix(&self, message: &str) -> String {
-        let mut result = message.to_string();
-        result = self.convert_code_blocks_to_matrix(&result);
-        result = self.convert_inline_code_to_matrix(&result);
-        result = self.convert_mentions_to_matrix(&result);
-        result = self.convert_channels_to_matrix(&result);
-        result = self.convert_roles_to_matrix(&result);
-        result = self.convert_emojis_to_matrix(&result);
-        result = self.convert_everyone_here(&result);
-        result
+    /// Label code blocks that name no language with a guessed one.
+    pub fn with_code_language_detection(mut self, enabled: bool) -> Self {
+        self.determine_code_language = enabled;
+        self
     }
 
-    pub fn format_as_html(&self, message: &str) -> String {
-        let mut result = message.to_string();
-
-        result = self.escape_html(&result);
-
-        result = self.convert_code_blocks_to_html(&result);
-        result = self.convert_inline_code_to_html(&result);
+    pub fn with_room_mention_roles(mut self, role_ids: Vec<String>) -> Self {
+        self.room_mention_roles = role_ids;
+        self
+    }
 
-        result = self.convert_discord_formatting_to_html(&result);
+    /// Resolves the role and channel mentions in `message`, which was posted
+    /// in `channel_id`. Mentions that cannot be resolved are left out and keep
+    /// their fallback rendering.
+    pub async fn resolve_mentions(
+        &self,
+        message: &str,
+        channel_id: Option<&str>,
+        sender_can_mention_everyone: bool,
+    ) -> ResolvedMentions {
+        let role_keywords = match channel_id {
+            Some(channel_id) if self.role_regex.is_match(message) => {
+                self.role_keywords(channel_id).await
+            }
+            _ => BTreeMap::new(),
+        };
+        ResolvedMentions {
+            roles: self
+                .resolve_role_mentions(message, channel_id, sender_can_mention_everyone)
+                .await,
+            channels: self.resolve_channel_mentions(message).await,
+            role_keywords,
+        }
+    }
 
-        result = self.convert_mentions_to_html(&result);
-        result = self.convert_channels_to_html(&result);
-        result = self.convert_roles_to_html(&result);
-        result = self.convert_emojis_to_html(&result);
+    /// The role keywords set for the room `channel_id` is bridged to.
+    async fn role_keywords(&self, channel_id: &str) -> BTreeMap<String, String> {
+        let Some(room_store) = &self.room_store else {
+            return BTreeMap::new();
+        };
+        let settings = match room_store.get_room_by_discord_channel(channel_id).await {
+            Ok(Some(mapping)) => room_store.get_room_settings(&mapping.matrix_room_id).await,
+            Ok(None) => Ok(None),
+            Err(err) => Err(err),
+        };
+        match settings {
+            Ok(settings) => settings
+                .map(|settings| settings.role_keywords)
+                .unwrap_or_default(),
+            Err(err) => {
+                tracing::warn!(
+                    "Failed to look up role keywords for discord channel {}: {}",
+                    channel_id,
+                    err
+                );
+                BTreeMap::new()
+            }
+        }
+    }
 
-        result = self.convert_everyone_here_to_html(&result);
+    /// Roles listed in `room_mention_roles` become room notifications only
+    /// when the sender may mention everyone.
+    async fn resolve_role_mentions(
+        &self,
+        message: &str,
+        channel_id: Option<&str>,
+        sender_can_mention_everyone: bool,
+    ) -> HashMap<String, RoleMention> {
+        let mut resolved = HashMap::new();
+        if !self.role_regex.is_match(message) {
+            return resolved;
+        }
 
-        result = self.convert_newlines_to_html(&result);
+        let guild_id = match channel_id {
+            Some(channel_id) => match self.discord_client.get_channel(channel_id).await {
+                Ok(channel) => channel.map(|channel| channel.guild_id),
+                Err(err) => {
+                    tracing::warn!(
+                        "Failed to look up discord channel {} for role mentions: {}",
+                        channel_id,
+                        err
+                    );
+                    None
+                }
+            },
+            None => None,
+        };
 
-        result
+        for caps in self.role_regex.captures_iter(message) {
+            let role_id = caps[1].to_string();
+            if resolved.contains_key(&role_id) {
+                continue;
+            }
+            if sender_can_mention_everyone && self.room_mention_roles.contains(&role_id) {
+                resolved.insert(role_id, RoleMention::Room);
+                continue;
+            }
+            let role = match guild_id.as_deref() {
+                Some(guild_id) => match self.discord_client.get_role(guild_id, &role_id).await {
+                    Ok(role) => role,
+                    Err(err) => {
+                        tracing::warn!("Failed to resolve discord role {}: {}", role_id, err);
+                        None
+                    }
+                },
+                None => self.discord_client.metadata().role(&role_id).await,
+            };
+            if let Some(role) = role {
+                resolved.insert(role_id, RoleMention::Role(role));
+            }
+        }
+        resolved
     }
 
-    fn escape_html(&self, text: &str) -> String {
-        text.replace('&', "&amp;")
-            .replace('<', "&lt;")
-            .replace('>', "&gt;")
-    }
+    /// Bridged channels link to their Matrix room; other channels the bot can
+    /// see are shown by name.
+    async fn resolve_channel_mentions(&self, message: &str) -> HashMap<String, ChannelMention> {
+        let mut resolved = HashMap::new();
+        for caps in self.channel_regex.captures_iter(message) {
+            let channel_id = caps[1].to_string();
+            if resolved.contains_key(&channel_id) {
+                continue;
+            }
 
-    fn convert_code_blocks_to_matrix(&self, text: &str) -> String {
-        self.code_block_regex
-            .replace_all(text, |caps: &regex::Captures| {
-                let lang = caps.get(1).map(|m| m.as_str()).unwrap_or("");
-                let code = &caps[2];
-                if lang.is_empty() {
-                    format!("<pre><code>{}</code></pre>", code)
-                } else {
-                    format!(
-                        "<pre><code class=\"language-{}\">{}</code></pre>",
-                        lang, code
-                    )
+            let room_mapping = match &self.room_store {
+                Some(room_store) => match room_store.get_room_by_discord_channel(&channel_id).await
+                {
+                    Ok(mapping) => mapping,
+                    Err(err) => {
+                        tracing::warn!(
+                            "Failed to look up room mapping for discord channel {}: {}",
+                            channel_id,
+                            err
+                        );
+                        None
+                    }
+                },
+                None => None,
+            };
+            let channel_name = match self.discord_client.get_channel(&channel_id).await {
+                Ok(channel) => channel.map(|channel| channel.name),
+                Err(err) => {
+                    tracing::warn!("Failed to resolve discord channel {}: {}", channel_id, err);
+                    None
                 }
-            })
-            .to_string()
-    }
+            };
 
-    fn convert_inline_code_to_matrix(&self, text: &str) -> String {
-        self.inline_code_regex
-            .replace_all(text, |caps: &regex::Captures| {
-                format!("<code>{}</code>", &caps[1])
-            })
-            .to_string()
+            let mention = match (room_mapping, channel_name) {
+                (Some(mapping), name) => ChannelMention::Bridged {
+                    room_id: mapping.matrix_room_id,
+                    name: name.unwrap_or(mapping.discord_channel_name),
+                },
+                (None, Some(name)) => ChannelMention::Unbridged { name },
+                (None, None) => continue,
+            };
+            resolved.insert(channel_id, mention);
+        }
+        resolved
     }
 
-    fn convert_code_blocks_to_html(&self, text: &str) -> String {
-        self.code_block_regex
-            .replace_all(text, |caps: &regex::Captures| {
-                let lang = caps.get(1).map(|m| m.as_str()).unwrap_or("");
-                let code = &caps[2];
-                if lang.is_empty() {
-                    format!("<pre><code>{}</code></pre>", code)
-                } else {
-                    format!(
-                        "<pre><code class=\"language-{}\">{}</code></pre>",
-                        lang, code
-                    )
-                }
-            })
-            .to_string()
+    pub fn format_for_matrix(&self, message: &str) -> String {
+        self.format_for_matrix_with_mentions(message, &ResolvedMentions::default())
     }
 
-    fn convert_inline_code_to_html(&self, text: &str) -> String {
-        self.inline_code_regex
-            .replace_all(text, |caps: &regex::Captures| {
-                format!("<code>{}</code>", &caps[1])
-            })
-            .to_string()
+    pub fn format_for_matrix_with_mentions(
+        &self,
+        message: &str,
+        mentions: &ResolvedMentions,
+    ) -> String {
+        let mut result = message.to_string();
+        result = self.convert_mentions_to_matrix(&result);
+        result = self.convert_channels_to_matrix(&result, &mentions.channels);
+        result = self.convert_roles_to_matrix(&result, mentions);
+        result = self.convert_timestamps_to_matrix(&result);
+        result = self.convert_emojis_to_matrix(&result);
+        result = self.convert_everyone_here(&result);
+        result
     }
 
-    fn convert_discord_formatting_to_html(&self, text: &str) -> String {
-        let mut result = text.to_string();
-
-        result = self
-            .bold_regex
-            .replace_all(&result, "<strong>$1</strong>")
-            .to_string();
-
-        result = self
-            .italic_regex
-            .replace_all(&result, "<em>$1</em>")
-            .to_string();
+    pub fn format_as_html(&self, message: &str) -> String {
+        self.format_as_html_with_mentions(message, &ResolvedMentions::default())
+    }
 
-        result = self
-            .underline_regex
-            .replace_all(&result, "<u>$1</u>")
-            .to_string();
+    pub fn format_as_html_with_mentions(
+        &self,
+        message: &str,
+        mentions: &ResolvedMentions,
+    ) -> String {
+        let mut result = formatter::markdown_to_html(message, self.determine_code_language);
 
-        result = self
-            .strikethrough_regex
-            .replace_all(&result, "<del>$1</del>")
-            .to_string();
+        result = self.convert_mentions_to_html(&result);
+        result = self.convert_channels_to_html(&result, &mentions.channels);
+        result = self.convert_roles_to_html(&result, mentions);
+        result = self.convert_timestamps_to_html(&result);
+        result = self.convert_emojis_to_html(&result);
 
-        result = self
-            .spoiler_regex
-            .replace_all(&result, "<span data-mx-spoiler>$1</span>")
-            .to_string();
+        result = self.convert_everyone_here_to_html(&result);
 
         result
     }
 
+    fn escape_html(&self, text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    }
+
     fn convert_mentions_to_matrix(&self, text: &str) -> String {
         if self.domain.is_empty() {
             return text.to_string();
         }
         self.mention_regex
             .replace_all(text, |caps: &regex::Captures| {
-                let user_id = &caps[1];
-                format!(
-                    "<a href=\"https://matrix.to/#/@_discord_{}:{}\">@_discord_{}</a>",
-                    user_id, self.domain, user_id
-                )
+                format!("@_discord_{}:{}", &caps[1], self.domain)
             })
             .to_string()
     }
 
+    /// Runs on escaped HTML, so it matches the escaped form of `<@id>`.
     fn convert_mentions_to_html(&self, text: &str) -> String {
         if self.domain.is_empty() {
             return text.to_string();
         }
-        self.mention_regex
+        self.escaped_mention_regex
             .replace_all(text, |caps: &regex::Captures| {
                 let user_id = &caps[1];
                 format!(
@@ -229,56 +356,148 @@ impl DiscordToMatrixConverter {
             .to_string()
     }
 
-    fn convert_channels_to_matrix(&self, text: &str) -> String {
-        if self.domain.is_empty() {
+    fn convert_channels_to_matrix(
+        &self,
+        text: &str,
+        channels: &HashMap<String, ChannelMention>,
+    ) -> String {
+        if self.domain.is_empty() && channels.is_empty() {
             return text.to_string();
         }
         self.channel_regex
             .replace_all(text, |caps: &regex::Captures| {
                 let channel_id = &caps[1];
-                format!(
-                    "<a href=\"https://matrix.to/#/#_discord_{}:{}\">#_discord_{}</a>",
-                    channel_id, self.domain, channel_id
-                )
+                match channels.get(channel_id) {
+                    Some(ChannelMention::Bridged { name, .. })
+                    | Some(ChannelMention::Unbridged { name }) => format!("#{}", name),
+                    None if self.domain.is_empty() => caps[0].to_string(),
+                    None => format!("#_discord_{}:{}", channel_id, self.domain),
+                }
             })
             .to_string()
     }
 
-    fn convert_channels_to_html(&self, text: &str) -> String {
-        if self.domain.is_empty() {
+    /// Runs on escaped HTML, so it matches the escaped form of `<#id>`.
+    fn convert_channels_to_html(
+        &self,
+        text: &str,
+        channels: &HashMap<String, ChannelMention>,
+    ) -> String {
+        if self.domain.is_empty() && channels.is_empty() {
             return text.to_string();
         }
-        self.channel_regex
+        self.escaped_channel_regex
             .replace_all(text, |caps: &regex::Captures| {
                 let channel_id = &caps[1];
-                format!(
-                    "<a href=\"https://matrix.to/#/#_discord_{}:{}\">#_discord_{}</a>",
-                    channel_id, self.domain, channel_id
-                )
+                match channels.get(channel_id) {
+                    Some(ChannelMention::Bridged { room_id, name }) => format!(
+                        "<a href=\"https://matrix.to/#/{}\">#{}</a>",
+                        room_id,
+                        self.escape_html(name)
+                    ),
+                    Some(ChannelMention::Unbridged { name }) => {
+                        format!("#{}", self.escape_html(name))
+                    }
+                    None if self.domain.is_empty() => caps[0].to_string(),
+                    None => format!(
+                        "<a href=\"https://matrix.to/#/#_discord_{}:{}\">#_discord_{}</a>",
+                        channel_id, self.domain, channel_id
+                    ),
+                }
             })
             .to_string()
     }
 
-    fn convert_roles_to_matrix(&self, text: &str) -> String {
-        if self.domain.is_empty() {
+    fn convert_roles_to_matrix(&self, text: &str, mentions: &ResolvedMentions) -> String {
+        let roles = &mentions.roles;
+        if self.domain.is_empty() && roles.is_empty() && mentions.role_keywords.is_empty() {
             return text.to_string();
         }
         self.role_regex
             .replace_all(text, |caps: &regex::Captures| {
                 let role_id = &caps[1];
-                format!("@role_{}", role_id)
+                let (label, name) = match roles.get(role_id) {
+                    Some(RoleMention::Role(role)) => {
+                        (format!("@{}", role.name), role.name.as_str())
+                    }
+                    Some(RoleMention::Room) => return "@room".to_string(),
+                    None if self.domain.is_empty() => (caps[0].to_string(), ""),
+                    None => (format!("@role_{}", role_id), ""),
+                };
+                match role_keyword(mentions, role_id, name) {
+                    Some(keyword) => format!("{} ({})", label, keyword),
+                    None => label,
+                }
             })
             .to_string()
     }
 
-    fn convert_roles_to_html(&self, text: &str) -> String {
-        if self.domain.is_empty() {
+    /// Runs on escaped HTML, so it matches the escaped form of `<@&id>`.
+    fn convert_roles_to_html(&self, text: &str, mentions: &ResolvedMentions) -> String {
+        let roles = &mentions.roles;
+        if self.domain.is_empty() && roles.is_empty() && mentions.role_keywords.is_empty() {
             return text.to_string();
         }
-        self.role_regex
+        self.escaped_role_regex
             .replace_all(text, |caps: &regex::Captures| {
                 let role_id = &caps[1];
-                format!("<font color=\"#99AAB5\">@role_{}</font>", role_id)
+                let (label, name) = match roles.get(role_id) {
+                    Some(RoleMention::Role(role)) => {
+                        let color = if role.color == 0 {
+                            DEFAULT_ROLE_COLOR.to_string()
+                        } else {
+                            format!("#{:06X}", role.color)
+                        };
+                        let label = format!(
+                            "<strong><font color=\"{}\">@{}</font></strong>",
+                            color,
+                            self.escape_html(&role.name)
+                        );
+                        (label, role.name.as_str())
+                    }
+                    Some(RoleMention::Room) => return "@room".to_string(),
+                    None if self.domain.is_empty() => (caps[0].to_string(), ""),
+                    None => (
+                        format!(
+                            "<font color=\"{}\">@role_{}</font>",
+                            DEFAULT_ROLE_COLOR, role_id
+                        ),
+                        "",
+                    ),
+                };
+                match role_keyword(mentions, role_id, name) {
+                    Some(keyword) => format!("{} ({})", label, self.escape_html(keyword)),
+                    None => label,
+                }
+            })
+            .to_string()
+    }
+
+    fn convert_timestamps_to_matrix(&self, text: &str) -> String {
+        self.timestamp_regex
+            .replace_all(text, |caps: &regex::Captures| {
+                let style = caps.get(2).map(|m| m.as_str()).unwrap_or("f");
+                match parse_discord_timestamp(&caps[1]) {
+                    Some(time) => format_discord_timestamp(&time, style),
+                    None => caps[0].to_string(),
+                }
+            })
+            .to_string()
+    }
+
+    /// Runs on escaped HTML, so it matches the escaped form of `<t:...>`.
+    fn convert_timestamps_to_html(&self, text: &str) -> String {
+        self.escaped_timestamp_regex
+            .replace_all(text, |caps: &regex::Captures| {
+                let style = caps.get(2).map(|m| m.as_str()).unwrap_or("f");
+                match parse_discord_timestamp(&caps[1]) {
+                    Some(time) => format!(
+                        "<time datetime=\"{}\">{}</time>",
+                        time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                        format_discord_timestamp(&time, style)
+                    ),
+                    None => caps[0].to_string(),
+                }
             })
             .to_string()
     }
@@ -305,113 +524,82 @@ impl DiscordToMatrixConverter {
         result
     }
 
+    /// Custom emoji in HTML-escaped text become images from Discord's CDN.
     fn convert_emojis_to_html(&self, text: &str) -> String {
-        let mut result = text.to_string();
-
-        result = self.animated_emoji_regex
-            .replace_all(&result, |caps: &regex::Captures| {
-                let emoji_name = &caps[1];
-                let emoji_id = &caps[2];
-                format!("<img data-mx-emoticon src=\"https://cdn.discordapp.com/emojis/{}.gif\" alt=\":{}:\" title=\":{}:\" height=\"32\" width=\"32\" />", 
-                    emoji_id, emoji_name, emoji_name)
-            })
-            .to_string();
-
-        result = self.emoji_regex
-            .replace_all(&result, |caps: &regex::Captures| {
-                let emoji_name = &caps[1];
-                let emoji_id = &caps[2];
-                format!("<img data-mx-emoticon src=\"https://cdn.discordapp.com/emojis/{}.png\" alt=\":{}:\" title=\":{}:\" height=\"32\" width=\"32\" />", 
-                    emoji_id, emoji_name, emoji_name)
+        self.escaped_emoji_regex
+            .replace_all(text, |caps: &regex::Captures| {
+                let src = discord_emoji_url(&caps[3], !caps[1].is_empty());
+                emoticon_html(&src, &caps[2])
             })
-            .to_string();
-
-        result
+            .to_string()
     }
 
+    /// Like [`Self::convert_emojis_to_html`], but shows each emoji from its
+    /// upload on the homeserver where possible.
     pub async fn convert_emojis_to_html_with_cache(&self, text: &str) -> String {
-        let Some(handler) = &self.emoji_handler else {
+        if self.emoji_handler.is_none() {
             return self.convert_emojis_to_html(text);
-        };
-
-        let mut result = text.to_string();
+        }
 
-        result = self
-            .animated_emoji_regex
-            .replace_all(&result, |caps: &regex::Captures| {
-                format!("__ANIMATED_EMOJI_{}__", &caps[2])
-            })
-            .to_string();
+        let mut images: HashMap<String, String> = HashMap::new();
+        for caps in self.escaped_emoji_regex.captures_iter(text) {
+            let (name, id, animated) = (&caps[2], &caps[3], !caps[1].is_empty());
+            self.record_emoji_usage(id, name);
+            if !images.contains_key(id) {
+                let src = self.emoji_image_src(id, name, animated).await;
+                images.insert(id.to_string(), src);
+            }
+        }
 
-        result = self
-            .emoji_regex
-            .replace_all(&result, |caps: &regex::Captures| {
-                format!("__STATIC_EMOJI_{}__", &caps[2])
+        self.escaped_emoji_regex
+            .replace_all(text, |caps: &regex::Captures| {
+                emoticon_html(&images[&caps[3]], &caps[2])
             })
-            .to_string();
+            .to_string()
+    }
 
-        let mut emoji_info: Vec<(String, String, bool)> = Vec::new();
-        for caps in self.emoji_regex.captures_iter(text) {
-            emoji_info.push((caps[1].to_string(), caps[2].to_string(), false));
-        }
-        for caps in self.animated_emoji_regex.captures_iter(text) {
-            emoji_info.push((caps[1].to_string(), caps[2].to_string(), true));
+    fn record_emoji_usage(&self, id: &str, name: &str) {
+        if let Some(handler) = &self.emoji_handler {
+            handler.record_usage(EmojiUsageKind::Emoji, id, name);
         }
+    }
 
-        for (emoji_name, emoji_id, animated) in emoji_info {
-            let placeholder = if animated {
-                format!("__ANIMATED_EMOBIJI_{}__", emoji_id)
-            } else {
-                format!("__STATIC_EMOJI_{}__", emoji_id)
-            };
-
-            match handler
-                .get_or_upload_emoji(&emoji_id, &emoji_name, animated)
-                .await
-            {
-                Ok(mxc_url) => {
-                    let html = handler.emoji_to_matrix_html(&mxc_url, &emoji_name);
-                    result = result.replace(&placeholder, &html);
-                }
-                Err(e) => {
-                    tracing::warn!(
-                        "Failed to upload emoji {} ({}): {}",
-                        emoji_name,
-                        emoji_id,
-                        e
-                    );
-                    let ext = if animated { "gif" } else { "png" };
-                    let fallback = format!(
-                        "<img data-mx-emoticon src=\"https://cdn.discordapp.com/emojis/{}.{}\" alt=\":{}:\" title=\":{}:\" height=\"32\" width=\"32\" />",
-                        emoji_id, ext, emoji_name, emoji_name
-                    );
-                    result = result.replace(&placeholder, &fallback);
-                }
+    /// The emoji's upload on the homeserver, made on first use and kept in
+    /// the emoji store. Falls back to Discord's CDN when the upload fails.
+    async fn emoji_image_src(&self, id: &str, name: &str, animated: bool) -> String {
+        let Some(handler) = &self.emoji_handler else {
+            return discord_emoji_url(id, animated);
+        };
+        match handler.get_or_upload_emoji(id, name, animated).await {
+            Ok(mxc_url) => mxc_url,
+            Err(e) => {
+                tracing::warn!("Failed to upload emoji {} ({}): {}", name, id, e);
+                discord_emoji_url(id, animated)
             }
         }
-
-        result
     }
 
     pub async fn format_as_html_async(&self, message: &str) -> String {
-        let mut result = message.to_string();
-
-        result = self.escape_html(&result);
-
-        result = self.convert_code_blocks_to_html(&result);
-        result = self.convert_inline_code_to_html(&result);
+        let mentions = self.resolve_mentions(message, None, false).await;
+        self.format_as_html_async_with_mentions(message, &mentions)
+            .await
+    }
 
-        result = self.convert_discord_formatting_to_html(&result);
+    pub async fn format_as_html_async_with_mentions(
+        &self,
+        message: &str,
+        mentions: &ResolvedMentions,
+    ) -> String {
+        let mut result = formatter::markdown_to_html(message, self.determine_code_language);
 
         result = self.convert_mentions_to_html(&result);
-        result = self.convert_channels_to_html(&result);
-        result = self.convert_roles_to_html(&result);
+        result = self.convert_channels_to_html(&result, &mentions.channels);
+        result = self.convert_roles_to_html(&result, mentions);
+        result = self.convert_timestamps_to_html(&result);
         result = self.convert_emojis_to_html_with_cache(&result).await;
 
         result = self.convert_everyone_here_to_html(&result);
 
-        result = self.convert_newlines_to_html(&result);
-
         result
     }
 
@@ -438,10 +626,6 @@ impl DiscordToMatrixConverter {
         result
     }
 
-    fn convert_newlines_to_html(&self, text: &str) -> String {
-        text.replace("\n", "<br/>")
-    }
-
     pub async fn convert_message(
         &self,
         discord_message: &str,
@@ -570,6 +754,35 @@ impl DiscordToMatrixConverter {
     }
 }
 
+/// The keyword configured for `role_id`, unless the role's name already
+/// says it.
+fn role_keyword<'a>(mentions: &'a ResolvedMentions, role_id: &str, name: &str) -> Option<&'a str> {
+    mentions
+        .role_keywords
+        .get(role_id)
+        .map(String::as_str)
+        .filter(|keyword| !keyword.eq_ignore_ascii_case(name))
+}
+
+fn parse_discord_timestamp(epoch: &str) -> Option<DateTime<Utc>> {
+    Utc.timestamp_opt(epoch.parse().ok()?, 0).single()
+}
+
+/// Renders a Discord timestamp in UTC. Discord shows these in each reader's
+/// local time zone, which Matrix has no equivalent for; relative timestamps
+/// are rendered as absolute ones so they do not go stale in the history.
+fn format_discord_timestamp(time: &DateTime<Utc>, style: &str) -> String {
+    let pattern = match style {
+        "t" => "%H:%M UTC",
+        "T" => "%H:%M:%S UTC",
+        "d" => "%Y-%m-%d",
+        "D" => "%B %-d, %Y",
+        "F" => "%A, %B %-d, %Y %H:%M UTC",
+        _ => "%B %-d, %Y %H:%M UTC",
+    };
+    time.format(pattern).to_string()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -585,6 +798,7 @@ mod tests {
                         homeserver_url: "http://localhost:8008".to_string(),
                         presence_interval: 500,
                         disable_presence: false,
+                        dry_run: false,
                         disable_typing_notifications: false,
                         disable_discord_mentions: false,
                         disable_deletion_forwarding: false,
@@ -601,10 +815,25 @@ mod tests {
                         admin_mxid: None,
                         invalid_token_message: String::new(),
                         user_activity: None,
+                        presence_mapping: Default::default(),
+                        room_mention_roles: Vec::new(),
+                        convert_iso_timestamps: false,
+                        member_change_notices: false,
+                        geo_uri_locations: false,
+                        cross_channel_context: false,
+                        approval_dm_after_secs: 0,
+                        relay_extractors: Vec::new(),
+                        bridge_bot_messages: false,
+                        content_redaction: Default::default(),
+                        maintenance: Default::default(),
+                        inactive_rooms: Default::default(),
+                        listen: None,
+                        socket_permissions: None,
                     },
                     registration: crate::config::RegistrationConfig::default(),
                     auth: crate::config::AuthConfig {
-                        bot_token: "test".to_string(),
+                        bot_token: "test".into(),
+                        bot_token_file: None,
                         client_id: None,
                         client_secret: None,
                         use_privileged_intents: false,
@@ -624,12 +853,16 @@ mod tests {
                         room_store_path: None,
                         max_connections: None,
                         min_connections: None,
+                        max_blocking_threads: None,
                     },
                     room: crate::config::RoomConfig {
                         default_visibility: "private".to_string(),
                         room_alias_prefix: "_discord".to_string(),
                         enable_room_creation: true,
                         kick_for: 0,
+                        auto_invite_members: false,
+                        attribution_badge: false,
+                        guilds: Default::default(),
                     },
                     channel: crate::config::ChannelConfig {
                         enable_channel_creation: false,
@@ -640,6 +873,7 @@ mod tests {
                         enable_webhook: true,
                         webhook_name: "_matrix".to_string(),
                         webhook_avatar: String::new(),
+                        mirror_slowmode: false,
                     },
                     limits: crate::config::LimitsConfig::default(),
                     ghosts: crate::config::GhostsConfig {
@@ -648,8 +882,19 @@ mod tests {
                         username_template: String::new(),
                         displayname_template: String::new(),
                         avatar_url_template: None,
+                        hide_from_directory: false,
+                        per_message_profiles: false,
+                        protected_names: Vec::new(),
+                        impersonation_suffix: String::new(),
                     },
                     metrics: crate::config::MetricsConfig::default(),
+                    chaos: crate::config::ChaosConfig::default(),
+                    admin_api: crate::config::AdminApiConfig::default(),
+                    web: crate::config::WebConfig::default(),
+                    delivery: crate::config::DeliveryConfig::default(),
+                    homeserver_admin: crate::config::HomeserverAdminConfig::default(),
+                    alerts: crate::config::AlertsConfig::default(),
+                    media: crate::config::MediaConfig::default(),
                 }))
                 .await
                 .unwrap(),
@@ -672,6 +917,20 @@ mod tests {
         assert!(result.contains("@_discord_123456789:example.org"));
     }
 
+    #[test]
+    fn user_mentions_are_pills_in_html_only() {
+        let converter = make_converter();
+        assert_eq!(
+            converter.format_for_matrix("Hi <@1> & <@!2>"),
+            "Hi @_discord_1:example.org & @_discord_2:example.org"
+        );
+        assert_eq!(
+            converter.format_as_html("Hi <@1> & <@!2>"),
+            "Hi <a href=\"https://matrix.to/#/@_discord_1:example.org\">@_discord_1</a> &amp; \
+             <a href=\"https://matrix.to/#/@_discord_2:example.org\">@_discord_2</a>"
+        );
+    }
+
     #[test]
     fn converts_channel_mention_to_matrix() {
         let converter = make_converter();
@@ -693,6 +952,17 @@ mod tests {
         assert_eq!(result, "Wow! :dance:");
     }
 
+    #[test]
+    fn converts_custom_emoji_to_html_images() {
+        let converter = make_converter();
+        let result = converter.format_as_html("Nice! <:cool:12345> <a:dance:67890>");
+        assert_eq!(
+            result,
+            "Nice! <img data-mx-emoticon src=\"https://cdn.discordapp.com/emojis/12345.png\" alt=\":cool:\" title=\":cool:\" height=\"32\" width=\"32\" /> \
+             <img data-mx-emoticon src=\"https://cdn.discordapp.com/emojis/67890.gif\" alt=\":dance:\" title=\":dance:\" height=\"32\" width=\"32\" />"
+        );
+    }
+
     #[test]
     fn converts_bold_to_html() {
         let converter = make_converter();
@@ -763,10 +1033,190 @@ mod tests {
         assert!(!converter.is_spoiler("Normal text"));
     }
 
+    #[test]
+    fn spoilers_round_trip() {
+        let converter = make_converter();
+        for (discord, html) in [
+            ("a ||secret|| b", "a <span data-mx-spoiler>secret</span> b"),
+            (
+                "||**loud**||",
+                "<span data-mx-spoiler><strong>loud</strong></span>",
+            ),
+            (
+                "> ||a `b` c||",
+                "<blockquote><span data-mx-spoiler>a <code>b</code> c</span></blockquote>",
+            ),
+        ] {
+            assert_eq!(converter.format_as_html(discord), html);
+            assert_eq!(
+                MessageUtils::convert_html_to_discord_markdown(html),
+                discord
+            );
+        }
+        assert_eq!(
+            MessageUtils::convert_html_to_discord_markdown(
+                "<span data-mx-spoiler=\"plot\">twist</span>"
+            ),
+            "||twist||"
+        );
+    }
+
     #[test]
     fn detects_code_block() {
         let converter = make_converter();
         assert!(converter.has_code_block("Here is code:\n```rust\ncode\n```"));
         assert!(!converter.has_code_block("No code here"));
     }
+
+    fn mods_role() -> RoleSnapshot {
+        RoleSnapshot {
+            id: "555".to_string(),
+            guild_id: "1".to_string(),
+            name: "Mods".to_string(),
+            color: 0x3498db,
+        }
+    }
+
+    #[test]
+    fn renders_resolved_role_mentions() {
+        let converter = make_converter();
+        let mentions = ResolvedMentions {
+            roles: HashMap::from([("555".to_string(), RoleMention::Role(mods_role()))]),
+            ..ResolvedMentions::default()
+        };
+
+        let plain =
+            converter.format_for_matrix_with_mentions("Ping <@&555> and <@&777>", &mentions);
+        assert_eq!(plain, "Ping @Mods and @role_777");
+
+        let html = converter.format_as_html_with_mentions("Ping <@&555>", &mentions);
+        assert_eq!(
+            html,
+            "Ping <strong><font color=\"#3498DB\">@Mods</font></strong>"
+        );
+    }
+
+    #[test]
+    fn role_keywords_follow_role_mentions() {
+        let converter = make_converter();
+        let mentions = ResolvedMentions {
+            roles: HashMap::from([("555".to_string(), RoleMention::Role(mods_role()))]),
+            role_keywords: BTreeMap::from([
+                ("555".to_string(), "moderators".to_string()),
+                ("777".to_string(), "ops".to_string()),
+            ]),
+            ..ResolvedMentions::default()
+        };
+
+        let plain =
+            converter.format_for_matrix_with_mentions("Ping <@&555> and <@&777>", &mentions);
+        assert_eq!(plain, "Ping @Mods (moderators) and @role_777 (ops)");
+
+        let html = converter.format_as_html_with_mentions("Ping <@&555>", &mentions);
+        assert_eq!(
+            html,
+            "Ping <strong><font color=\"#3498DB\">@Mods</font></strong> (moderators)"
+        );
+    }
+
+    #[tokio::test]
+    async fn room_mention_roles_require_mention_everyone() {
+        let converter = tokio::task::spawn_blocking(make_converter)
+            .await
+            .unwrap()
+            .with_room_mention_roles(vec!["555".to_string()]);
+        converter
+            .discord_client
+            .metadata()
+            .upsert_role(mods_role())
+            .await;
+
+        let mentions = converter.resolve_mentions("<@&555>", None, false).await;
+        assert_eq!(
+            mentions.roles.get("555"),
+            Some(&RoleMention::Role(mods_role()))
+        );
+
+        let mentions = converter.resolve_mentions("<@&555>", None, true).await;
+        assert_eq!(mentions.roles.get("555"), Some(&RoleMention::Room));
+        assert_eq!(
+            converter.format_for_matrix_with_mentions("<@&555> meeting", &mentions),
+            "@room meeting"
+        );
+    }
+
+    #[test]
+    fn renders_resolved_channel_mentions() {
+        let converter = make_converter();
+        let mentions = ResolvedMentions {
+            channels: HashMap::from([
+                (
+                    "100".to_string(),
+                    ChannelMention::Bridged {
+                        room_id: "!general:example.org".to_string(),
+                        name: "general".to_string(),
+                    },
+                ),
+                (
+                    "200".to_string(),
+                    ChannelMention::Unbridged {
+                        name: "off-topic".to_string(),
+                    },
+                ),
+            ]),
+            ..ResolvedMentions::default()
+        };
+
+        let plain = converter.format_for_matrix_with_mentions("See <#100> or <#200>", &mentions);
+        assert_eq!(plain, "See #general or #off-topic");
+
+        let html = converter.format_as_html_with_mentions("See <#100> or <#200>", &mentions);
+        assert_eq!(
+            html,
+            "See <a href=\"https://matrix.to/#/!general:example.org\">#general</a> or #off-topic"
+        );
+    }
+
+    #[tokio::test]
+    async fn unbridged_channel_mentions_resolve_from_cache() {
+        let converter = tokio::task::spawn_blocking(make_converter).await.unwrap();
+        converter
+            .discord_client
+            .metadata()
+            .upsert_channel(crate::cache::ChannelSnapshot {
+                id: "200".to_string(),
+                name: "off-topic".to_string(),
+                guild_id: "1".to_string(),
+                topic: None,
+                slowmode_seconds: 0,
+                thread_parent_id: None,
+                everyone_can_view: true,
+            })
+            .await;
+
+        let mentions = converter.resolve_mentions("<#200>", None, false).await;
+        assert_eq!(
+            mentions.channels.get("200"),
+            Some(&ChannelMention::Unbridged {
+                name: "off-topic".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn converts_discord_timestamps() {
+        let converter = make_converter();
+
+        let plain = converter.format_for_matrix("Starts <t:1618953630:F>, ends <t:1618953630:t>");
+        assert_eq!(
+            plain,
+            "Starts Tuesday, April 20, 2021 21:20 UTC, ends 21:20 UTC"
+        );
+
+        let html = converter.format_as_html("Starts <t:1618953630>");
+        assert_eq!(
+            html,
+            "Starts <time datetime=\"2021-04-20T21:20:30Z\">April 20, 2021 21:20 UTC</time>"
+        );
+    }
 }
@@ -0,0 +1,857 @@
+//! Formatting between Discord markdown and Matrix's `org.matrix.custom.html`.
+//! [`markdown_to_html`] renders what Discord renders: bold, italics,
+//! underline, strikethrough, spoilers, inline code, code blocks, block
+//! quotes and masked links. [`html_to_markdown`] turns the HTML that Matrix
+//! clients send back into that markdown, keeping the text of anything
+//! Discord cannot show.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+/// Mentions, custom emoji, timestamps and links. They are copied as they are,
+/// so markdown characters inside them are never taken as formatting.
+static LITERAL_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^(?:<(?:@[!&]?\d+|#\d+|a?:\w+:\d+|t:-?\d+(?::[tTdDfFR])?|https?://[^\s>]+)>|https?://[^\s<>*|~`]+)",
+    )
+    .unwrap()
+});
+
+static CODE_LANGUAGE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[A-Za-z0-9_+#.-]+$").unwrap());
+
+static ATTRIBUTE_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"([A-Za-z_:][-A-Za-z0-9_:.]*)(?:\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'=<>`]+)))?"#)
+        .unwrap()
+});
+
+static BLANK_LINES_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\n{3,}").unwrap());
+
+/// Inline spans, tried in this order so `**` wins over `*`.
+const SPANS: [(&str, &str, &str); 6] = [
+    ("**", "<strong>", "</strong>"),
+    ("__", "<u>", "</u>"),
+    ("~~", "<del>", "</del>"),
+    ("||", "<span data-mx-spoiler>", "</span>"),
+    ("*", "<em>", "</em>"),
+    ("_", "<em>", "</em>"),
+];
+
+/// Markers of a few common languages, for guessing the language of code
+/// blocks that do not name one. Ties go to the language listed first.
+const LANGUAGE_MARKERS: [(&str, &[&str]); 6] = [
+    (
+        "rust",
+        &[
+            "fn ", "let mut ", "impl ", "pub fn ", "println!", "::new(", "-> ", "&mut ",
+        ],
+    ),
+    (
+        "python",
+        &[
+            "def ", "import ", "elif ", "self.", "print(", "__init__", "None",
+        ],
+    ),
+    (
+        "javascript",
+        &[
+            "function ",
+            "const ",
+            "=> ",
+            "console.log",
+            "var ",
+            "require(",
+            "===",
+            "document.",
+        ],
+    ),
+    ("go", &["func ", "package ", ":= ", "fmt."]),
+    (
+        "sql",
+        &[
+            "SELECT ",
+            "INSERT INTO ",
+            "CREATE TABLE ",
+            "UPDATE ",
+            "DELETE FROM ",
+            "WHERE ",
+        ],
+    ),
+    (
+        "bash",
+        &["echo ", "sudo ", "apt ", "$(", "export ", "fi\n", "done\n"],
+    ),
+];
+
+/// The HTML for a Discord message. Code blocks without a language get a
+/// guessed one when `determine_code_language` is on.
+pub fn markdown_to_html(text: &str, determine_code_language: bool) -> String {
+    let mut html = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("```") {
+        let Some(length) = rest[start + 3..].find("```") else {
+            break;
+        };
+        let before = &rest[..start];
+        html.push_str(&render_blocks(before.strip_suffix('\n').unwrap_or(before)));
+        html.push_str(&code_block(
+            &rest[start + 3..start + 3 + length],
+            determine_code_language,
+        ));
+        let after = &rest[start + 3 + length + 3..];
+        rest = after.strip_prefix('\n').unwrap_or(after);
+    }
+    html.push_str(&render_blocks(rest));
+    html
+}
+
+/// The HTML showing `text` as it is, line breaks included.
+pub fn text_to_html(text: &str) -> String {
+    escape_html(text).replace('\n', "<br>")
+}
+
+/// The Discord markdown for a Matrix formatted body. Reply fallbacks are
+/// dropped and user pills keep only their text; room pills stay links, for
+/// the channels of bridged rooms to be found in them.
+pub fn html_to_markdown(html: &str) -> String {
+    let mut stack = vec![Element::new("", Vec::new())];
+    let mut rest = html;
+    while !rest.is_empty() {
+        if let Some(tag) = rest.strip_prefix('<')
+            && let Some(end) = tag.find('>')
+        {
+            open_or_close(&mut stack, &tag[..end]);
+            rest = &tag[end + 1..];
+            continue;
+        }
+        let end = rest
+            .char_indices()
+            .skip(1)
+            .find(|(_, c)| *c == '<')
+            .map_or(rest.len(), |(index, _)| index);
+        push_text(&mut stack, &rest[..end]);
+        rest = &rest[end..];
+    }
+    while stack.len() > 1 {
+        close_innermost(&mut stack);
+    }
+    let markdown = stack.pop().map(|root| root.out).unwrap_or_default();
+    BLANK_LINES_REGEX
+        .replace_all(&markdown, "\n\n")
+        .trim()
+        .to_string()
+}
+
+/// A best guess at the language of a code block, for highlighting on the
+/// Matrix side. `None` when nothing stands out.
+pub fn guess_code_language(code: &str) -> Option<&'static str> {
+    let trimmed = code.trim();
+    if trimmed.starts_with(['{', '[']) && serde_json::from_str::<serde_json::Value>(trimmed).is_ok()
+    {
+        return Some("json");
+    }
+    if let Some(interpreter) = trimmed
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix("#!"))
+    {
+        if interpreter.contains("python") {
+            return Some("python");
+        }
+        if interpreter.contains("node") {
+            return Some("javascript");
+        }
+        if interpreter.contains("bash") || interpreter.ends_with("sh") {
+            return Some("bash");
+        }
+    }
+    if trimmed.starts_with("<?xml") {
+        return Some("xml");
+    }
+    if trimmed.starts_with('<') && trimmed.ends_with('>') {
+        return Some("html");
+    }
+
+    let mut best = None;
+    let mut best_hits = 0;
+    for (language, markers) in LANGUAGE_MARKERS {
+        let hits = markers
+            .iter()
+            .filter(|marker| code.contains(*marker))
+            .count();
+        if hits > best_hits {
+            best = Some(language);
+            best_hits = hits;
+        }
+    }
+    best
+}
+
+fn code_block(block: &str, determine_code_language: bool) -> String {
+    let (language, code) = match block.split_once('\n') {
+        Some((first, code)) if first.is_empty() || CODE_LANGUAGE_REGEX.is_match(first) => {
+            (first, code)
+        }
+        _ => ("", block),
+    };
+    let code = code.strip_suffix('\n').unwrap_or(code);
+    let language = match language {
+        "" if determine_code_language => guess_code_language(code).unwrap_or_default(),
+        language => language,
+    };
+    if language.is_empty() {
+        format!("<pre><code>{}</code></pre>", escape_html(code))
+    } else {
+        format!(
+            "<pre><code class=\"language-{}\">{}</code></pre>",
+            language.to_ascii_lowercase(),
+            escape_html(code)
+        )
+    }
+}
+
+/// Text between code blocks: runs of `> ` lines become block quotes, and a
+/// `>>> ` line quotes everything after it.
+fn render_blocks(text: &str) -> String {
+    let mut html = String::new();
+    let mut run: Vec<&str> = Vec::new();
+    let mut run_quoted = false;
+    let mut lines = text.split('\n');
+    while let Some(line) = lines.next() {
+        if let Some(first) = line.strip_prefix(">>> ").or((line == ">>>").then_some("")) {
+            push_run(&mut html, &run, run_quoted);
+            let quoted: Vec<&str> = std::iter::once(first).chain(lines).collect();
+            push_run(&mut html, &quoted, true);
+            return html;
+        }
+        let (quoted, line) = match line.strip_prefix("> ") {
+            Some(line) => (true, line),
+            None if line == ">" => (true, ""),
+            None => (false, line),
+        };
+        if quoted != run_quoted && !run.is_empty() {
+            push_run(&mut html, &run, run_quoted);
+            run.clear();
+        }
+        run_quoted = quoted;
+        run.push(line);
+    }
+    push_run(&mut html, &run, run_quoted);
+    html
+}
+
+fn push_run(html: &mut String, lines: &[&str], quoted: bool) {
+    if lines.is_empty() {
+        return;
+    }
+    let inner = inline(&lines.join("\n"));
+    if quoted {
+        html.push_str("<blockquote>");
+        html.push_str(&inner);
+        html.push_str("</blockquote>");
+    } else {
+        html.push_str(&inner);
+    }
+}
+
+fn inline(text: &str) -> String {
+    let mut html = String::with_capacity(text.len());
+    let mut prev = None;
+    let mut index = 0;
+    while let Some(c) = text[index..].chars().next() {
+        let rest = &text[index..];
+        if let Some((rendered, length)) = inline_span(rest, prev) {
+            html.push_str(&rendered);
+            prev = rest[..length].chars().next_back();
+            index += length;
+            continue;
+        }
+        match c {
+            '\n' => html.push_str("<br>"),
+            c => push_escaped(&mut html, c),
+        }
+        prev = Some(c);
+        index += c.len_utf8();
+    }
+    html
+}
+
+/// The span `rest` starts with, rendered, and its length in bytes.
+fn inline_span(rest: &str, prev: Option<char>) -> Option<(String, usize)> {
+    if let Some(literal) = LITERAL_REGEX.find(rest) {
+        return Some((escape_html(literal.as_str()), literal.end()));
+    }
+    let mut chars = rest.chars();
+    match chars.next()? {
+        '\\' => {
+            let escaped = chars.next().filter(char::is_ascii_punctuation)?;
+            return Some((escape_html(&escaped.to_string()), 1 + escaped.len_utf8()));
+        }
+        '`' => return code_span(rest),
+        '[' => return masked_link(rest),
+        _ => {}
+    }
+
+    for (delimiter, open, close) in SPANS {
+        if !rest.starts_with(delimiter) {
+            continue;
+        }
+        // `_` only opens and closes at word boundaries, so snake_case stays.
+        if delimiter == "_" && prev.is_some_and(char::is_alphanumeric) {
+            return None;
+        }
+        let body = &rest[delimiter.len()..];
+        if delimiter.len() == 1 && body.starts_with(char::is_whitespace) {
+            continue;
+        }
+        let Some(end) = closing_delimiter(body, delimiter) else {
+            continue;
+        };
+        if delimiter == "_" && body[end + 1..].starts_with(char::is_alphanumeric) {
+            continue;
+        }
+        let rendered = format!("{}{}{}", open, inline(&body[..end]), close);
+        return Some((rendered, end + 2 * delimiter.len()));
+    }
+    None
+}
+
+/// Where `delimiter` closes the span `body` starts, skipping over code spans,
+/// escapes and literals. For doubled delimiters the last pair of a longer
+/// run closes, so `***x***` is bold italics.
+fn closing_delimiter(body: &str, delimiter: &str) -> Option<usize> {
+    let marker = delimiter.chars().next()?;
+    let mut index = 0;
+    while let Some(c) = body[index..].chars().next() {
+        let rest = &body[index..];
+        if c == '\\' {
+            index += 1 + rest[1..].chars().next().map_or(0, char::len_utf8);
+            continue;
+        }
+        if c == '`'
+            && let Some(end) = rest[1..].find('`')
+        {
+            index += end + 2;
+            continue;
+        }
+        if c == marker {
+            let run = rest.len() - rest.trim_start_matches(marker).len();
+            let closes = if delimiter.len() == 1 {
+                run == 1 && !body[..index].ends_with(char::is_whitespace)
+            } else {
+                run >= delimiter.len()
+            };
+            if index > 0 && closes {
+                return Some(index + run - delimiter.len());
+            }
+            index += run;
+            continue;
+        }
+        if let Some(literal) = LITERAL_REGEX.find(rest) {
+            index += literal.end();
+            continue;
+        }
+        index += c.len_utf8();
+    }
+    None
+}
+
+fn code_span(rest: &str) -> Option<(String, usize)> {
+    let ticks = if rest.starts_with("``") { 2 } else { 1 };
+    let body = &rest[ticks..];
+    let end = body.find(&rest[..ticks])?;
+    if end == 0 {
+        return None;
+    }
+    let code = &body[..end];
+    let code = if ticks == 2 { code.trim() } else { code };
+    Some((
+        format!("<code>{}</code>", escape_html(code)),
+        end + 2 * ticks,
+    ))
+}
+
+/// `[text](https://…)`. Parentheses in the URL must be balanced.
+fn masked_link(rest: &str) -> Option<(String, usize)> {
+    let text_end = rest.find(']')?;
+    let text = &rest[1..text_end];
+    if text.is_empty() || text.contains('\n') {
+        return None;
+    }
+    let target = rest[text_end + 1..].strip_prefix('(')?;
+    if !target.starts_with("https://") && !target.starts_with("http://") {
+        return None;
+    }
+    let mut depth = 0;
+    let mut url_end = None;
+    for (index, c) in target.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => {
+                url_end = Some(index);
+                break;
+            }
+            ')' => depth -= 1,
+            c if c.is_whitespace() => return None,
+            _ => {}
+        }
+    }
+    let url_end = url_end?;
+    Some((
+        format!(
+            "<a href=\"{}\">{}</a>",
+            escape_attribute(&target[..url_end]),
+            inline(text)
+        ),
+        text_end + 2 + url_end + 1,
+    ))
+}
+
+fn push_escaped(html: &mut String, c: char) {
+    match c {
+        '&' => html.push_str("&amp;"),
+        '<' => html.push_str("&lt;"),
+        '>' => html.push_str("&gt;"),
+        c => html.push(c),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut html = String::with_capacity(text.len());
+    for c in text.chars() {
+        push_escaped(&mut html, c);
+    }
+    html
+}
+
+fn escape_attribute(text: &str) -> String {
+    escape_html(text).replace('"', "&quot;")
+}
+
+/// An open HTML element and the markdown of what it holds so far.
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    out: String,
+    /// For `<ol>`, the items rendered so far.
+    items: usize,
+    /// For `<pre>`, the language named by its `<code>`.
+    language: Option<String>,
+}
+
+impl Element {
+    fn new(name: &str, attributes: Vec<(String, String)>) -> Self {
+        Self {
+            name: name.to_string(),
+            attributes,
+            out: String::new(),
+            items: 0,
+            language: None,
+        }
+    }
+
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn is_pill(&self) -> bool {
+        self.name == "a"
+            && self
+                .attribute("href")
+                .is_some_and(|href| href.starts_with("https://matrix.to/#/"))
+    }
+
+    fn is_user_pill(&self) -> bool {
+        self.attribute("href").is_some_and(|href| {
+            href.strip_prefix("https://matrix.to/#/")
+                .is_some_and(|target| target.starts_with('@') || target.starts_with("%40"))
+        })
+    }
+}
+
+fn open_or_close(stack: &mut Vec<Element>, tag: &str) {
+    let tag = tag.trim();
+    if tag.starts_with(['!', '?']) {
+        return;
+    }
+    if let Some(name) = tag.strip_prefix('/') {
+        let name = name.trim().to_ascii_lowercase();
+        if stack[1..].iter().any(|element| element.name == name) {
+            loop {
+                let done = stack.last().is_some_and(|element| element.name == name);
+                close_innermost(stack);
+                if done {
+                    break;
+                }
+            }
+        }
+        return;
+    }
+
+    let tag = tag.strip_suffix('/').unwrap_or(tag);
+    let (name, attributes) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+    let name = name.to_ascii_lowercase();
+    let attributes = ATTRIBUTE_REGEX
+        .captures_iter(attributes)
+        .map(|caps| {
+            let value = caps
+                .get(2)
+                .or(caps.get(3))
+                .or(caps.get(4))
+                .map_or("", |value| value.as_str());
+            (caps[1].to_ascii_lowercase(), decode_entities(value))
+        })
+        .collect();
+    let element = Element::new(&name, attributes);
+    let Some(parent) = stack.last_mut() else {
+        return;
+    };
+    match name.as_str() {
+        "br" => parent.out.push('\n'),
+        "hr" => parent.out.push_str("\n\n"),
+        "img" => parent.out.push_str(&image_text(&element)),
+        _ => stack.push(element),
+    }
+}
+
+/// An emoticon as its `:shortcode:`, other images as their description.
+fn image_text(image: &Element) -> String {
+    let name = image
+        .attribute("alt")
+        .or(image.attribute("title"))
+        .unwrap_or_default();
+    if image.attribute("data-mx-emoticon").is_some() {
+        let shortcode = name.trim_matches(':');
+        if !shortcode.is_empty() {
+            return format!(":{}:", shortcode);
+        }
+    }
+    name.to_string()
+}
+
+fn push_text(stack: &mut [Element], text: &str) {
+    if stack.iter().any(|element| element.name == "mx-reply") {
+        return;
+    }
+    let text = decode_entities(text);
+    let in_pre = stack.iter().any(|element| element.name == "pre");
+    let raw = in_pre
+        || stack
+            .iter()
+            .any(|element| element.name == "code" || element.is_pill());
+    let Some(parent) = stack.last_mut() else {
+        return;
+    };
+    if in_pre {
+        parent.out.push_str(&text);
+    } else if text.trim().is_empty() && text.contains('\n') {
+        // Whitespace between block elements.
+    } else if raw {
+        parent.out.push_str(&text.replace('\n', " "));
+    } else {
+        parent
+            .out
+            .push_str(&escape_markdown(&text.replace('\n', " ")));
+    }
+}
+
+fn close_innermost(stack: &mut Vec<Element>) {
+    let Some(element) = stack.pop() else {
+        return;
+    };
+    let Some(parent) = stack.last_mut() else {
+        return;
+    };
+    let markdown = render_element(element, parent);
+    parent.out.push_str(&markdown);
+}
+
+fn render_element(element: Element, parent: &mut Element) -> String {
+    let content = element.out.as_str();
+    match element.name.as_str() {
+        "strong" | "b" => wrap(content, "**"),
+        "em" | "i" => wrap(content, "*"),
+        "u" => wrap(content, "__"),
+        "del" | "s" | "strike" => wrap(content, "~~"),
+        // Discord spoilers have no reason, so `data-mx-spoiler="..."` loses it.
+        "span" if element.attribute("data-mx-spoiler").is_some() => wrap(content, "||"),
+        "code" if parent.name == "pre" => {
+            parent.language = element.attribute("class").and_then(|class| {
+                class
+                    .split_whitespace()
+                    .find_map(|class| class.strip_prefix("language-"))
+                    .map(str::to_string)
+            });
+            content.to_string()
+        }
+        "code" if content.contains('`') => format!("`` {} ``", content),
+        "code" => format!("`{}`", content),
+        "pre" => format!(
+            "\n```{}\n{}\n```\n",
+            element.language.as_deref().unwrap_or_default(),
+            content.strip_suffix('\n').unwrap_or(content)
+        ),
+        "a" => match element.attribute("href") {
+            Some(_) if element.is_user_pill() => content.to_string(),
+            Some(href) if content.is_empty() || content == href => href.to_string(),
+            Some(href) => format!("[{}]({})", content, href),
+            None => content.to_string(),
+        },
+        "blockquote" => {
+            let quoted: Vec<String> = content
+                .trim_matches('\n')
+                .lines()
+                .map(|line| {
+                    if line.is_empty() {
+                        ">".to_string()
+                    } else {
+                        format!("> {}", line)
+                    }
+                })
+                .collect();
+            format!("\n{}\n", quoted.join("\n"))
+        }
+        "li" => {
+            let marker = if parent.name == "ol" {
+                parent.items += 1;
+                let start: usize = parent
+                    .attribute("start")
+                    .and_then(|start| start.parse().ok())
+                    .unwrap_or(1);
+                format!("{}.", start + parent.items - 1)
+            } else {
+                "-".to_string()
+            };
+            format!(
+                "\n{} {}",
+                marker,
+                content.trim_matches('\n').replace('\n', "\n  ")
+            )
+        }
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            let level = element.name[1..].parse().unwrap_or(1usize).min(3);
+            format!("\n{} {}\n", "#".repeat(level), content.trim())
+        }
+        "p" | "div" | "ul" | "ol" | "table" | "tr" => {
+            format!("\n{}\n", content.trim_matches('\n'))
+        }
+        "mx-reply" => String::new(),
+        _ => content.to_string(),
+    }
+}
+
+/// `content` between `marker`s, with surrounding spaces kept outside, since
+/// Discord ignores `** bold**`.
+fn wrap(content: &str, marker: &str) -> String {
+    let trimmed = content.trim();
+    if trimmed.is_empty() {
+        return content.to_string();
+    }
+    let start = content.len() - content.trim_start().len();
+    let end = start + trimmed.len();
+    format!(
+        "{}{}{}{}{}",
+        &content[..start],
+        marker,
+        trimmed,
+        marker,
+        &content[end..]
+    )
+}
+
+/// Escapes characters Discord would read as formatting. Links, user ids and
+/// room aliases are left alone, as is `_` inside words.
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for word in text.split_inclusive(char::is_whitespace) {
+        if word.contains("://") || word.starts_with(['@', '#', '!']) {
+            escaped.push_str(word);
+            continue;
+        }
+        let chars: Vec<char> = word.chars().collect();
+        for (index, &c) in chars.iter().enumerate() {
+            let escape = match c {
+                '\\' | '*' | '~' | '|' | '`' => true,
+                '_' => {
+                    let joined = |c: Option<&char>| c.is_some_and(|c| c.is_alphanumeric());
+                    !joined(index.checked_sub(1).and_then(|prev| chars.get(prev)))
+                        || !joined(chars.get(index + 1))
+                }
+                _ => false,
+            };
+            if escape {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest[1..]
+            .find(';')
+            .filter(|end| *end <= 10)
+            .and_then(|end| Some((decode_entity(&rest[1..end + 1])?, end + 2)));
+        match entity {
+            Some((c, length)) => {
+                decoded.push(c);
+                rest = &rest[length..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn decode_entity(name: &str) -> Option<char> {
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => {
+            let number = name.strip_prefix('#')?;
+            let code = match number.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => number.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{guess_code_language, html_to_markdown, markdown_to_html};
+
+    #[test]
+    fn renders_discord_markdown_as_html() {
+        assert_eq!(
+            markdown_to_html("**bold** *it* _it_ __under__ ~~gone~~ ||hidden||", false),
+            "<strong>bold</strong> <em>it</em> <em>it</em> <u>under</u> <del>gone</del> \
+             <span data-mx-spoiler>hidden</span>"
+        );
+        assert_eq!(
+            markdown_to_html("***both*** and a <b> tag\nnext", false),
+            "<strong><em>both</em></strong> and a &lt;b&gt; tag<br>next"
+        );
+        assert_eq!(
+            markdown_to_html("snake_case_name, 2 * 3 * 4, \\*not\\*", false),
+            "snake_case_name, 2 * 3 * 4, *not*"
+        );
+        assert_eq!(
+            markdown_to_html("see [the docs](https://example.org/a_(b)?x=1&y=2)", false),
+            "see <a href=\"https://example.org/a_(b)?x=1&amp;y=2\">the docs</a>"
+        );
+        assert_eq!(
+            markdown_to_html("`**raw**` https://example.org/__init__ <@&12>", false),
+            "<code>**raw**</code> https://example.org/__init__ &lt;@&amp;12&gt;"
+        );
+    }
+
+    #[test]
+    fn renders_quotes_and_code_blocks() {
+        assert_eq!(
+            markdown_to_html("> one\n> **two**\nafter", false),
+            "<blockquote>one<br><strong>two</strong></blockquote>after"
+        );
+        assert_eq!(
+            markdown_to_html("before\n>>> all\nof this", false),
+            "before<blockquote>all<br>of this</blockquote>"
+        );
+        assert_eq!(
+            markdown_to_html("look:\n```rust\nlet x = **1**;\n```\ndone", false),
+            "look:<pre><code class=\"language-rust\">let x = **1**;</code></pre>done"
+        );
+        assert_eq!(
+            markdown_to_html("```\nfn main() {\n    println!(\"hi\");\n}\n```", false),
+            "<pre><code>fn main() {\n    println!(\"hi\");\n}</code></pre>"
+        );
+        assert_eq!(
+            markdown_to_html("```\nfn main() {\n    println!(\"hi\");\n}\n```", true),
+            "<pre><code class=\"language-rust\">fn main() {\n    println!(\"hi\");\n}</code></pre>"
+        );
+    }
+
+    #[test]
+    fn guesses_common_code_languages() {
+        assert_eq!(guess_code_language("{\"a\": [1, 2]}"), Some("json"));
+        assert_eq!(
+            guess_code_language("def greet(name):\n    print(name)"),
+            Some("python")
+        );
+        assert_eq!(
+            guess_code_language("SELECT * FROM users WHERE id = 1"),
+            Some("sql")
+        );
+        assert_eq!(guess_code_language("#!/bin/sh\nls"), Some("bash"));
+        assert_eq!(guess_code_language("hello there"), None);
+    }
+
+    #[test]
+    fn converts_matrix_html_to_discord_markdown() {
+        assert_eq!(
+            html_to_markdown(
+                "<strong>bold <em>and italic</em></strong>, <del>gone</del>, \
+                 <a href=\"https://example.org\">a link</a> and <code>a `tick`</code>"
+            ),
+            "**bold *and italic***, ~~gone~~, [a link](https://example.org) and `` a `tick` ``"
+        );
+        assert_eq!(
+            html_to_markdown(
+                "<mx-reply><blockquote>quoted <b>reply</b></blockquote></mx-reply>\
+                 <blockquote><p>first</p><p>second</p></blockquote>\n<p>then</p>"
+            ),
+            "> first\n>\n> second\n\nthen"
+        );
+        assert_eq!(
+            html_to_markdown(
+                "<pre><code class=\"language-python\">if a &lt; b:\n    pass\n</code></pre>"
+            ),
+            "```python\nif a < b:\n    pass\n```"
+        );
+        assert_eq!(
+            html_to_markdown(
+                "<ol start=\"3\"><li>three</li><li>four<ul><li>nested</li></ul></li></ol>"
+            ),
+            "3. three\n4. four\n  - nested"
+        );
+    }
+
+    #[test]
+    fn matrix_html_keeps_pills_and_escapes_literal_markdown() {
+        assert_eq!(
+            html_to_markdown(
+                "<a href=\"https://matrix.to/#/@_discord_42:example.org\">_bob_</a>: 2*3 \
+                 <img data-mx-emoticon src=\"mxc://example.org/wave\" alt=\":wave:\">&nbsp;https://example.org/a_b_"
+            ),
+            "_bob_: 2\\*3 :wave: https://example.org/a_b_"
+        );
+        assert_eq!(html_to_markdown("plain &amp; simple"), "plain & simple");
+    }
+
+    #[test]
+    fn room_pills_keep_their_link() {
+        assert_eq!(
+            html_to_markdown(
+                "see <a href=\"https://matrix.to/#/#general:example.org\">#general</a> \
+                 with <a href=\"https://matrix.to/#/%40alice:example.org\">alice</a>"
+            ),
+            "see [#general](https://matrix.to/#/#general:example.org) with alice"
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::Value;
+
+static GEO_URI_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"\bgeo:(-?\d{1,2}(?:\.\d+)?),(-?\d{1,3}(?:\.\d+)?)(?:,-?\d+(?:\.\d+)?)?(?:;[A-Za-z0-9\-]+(?:=[A-Za-z0-9\-]+(?:\.\d+)?)?)*",
+    )
+    .unwrap()
+});
+
+/// A point given by a `geo:` URI (RFC 5870). Altitude and parameters such
+/// as the uncertainty are kept in `uri` only.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoPoint {
+    pub uri: String,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl GeoPoint {
+    pub fn parse(uri: &str) -> Option<Self> {
+        let caps = GEO_URI_REGEX.captures(uri.trim())?;
+        if caps.get(0)?.as_str() != uri.trim() {
+            return None;
+        }
+        Self::from_captures(&caps)
+    }
+
+    fn from_captures(caps: &regex::Captures) -> Option<Self> {
+        let latitude: f64 = caps[1].parse().ok()?;
+        let longitude: f64 = caps[2].parse().ok()?;
+        if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+            return None;
+        }
+        Some(Self {
+            uri: caps[0].to_string(),
+            latitude,
+            longitude,
+        })
+    }
+
+    /// The point on OpenStreetMap, zoomed in to street level.
+    pub fn map_url(&self) -> String {
+        format!(
+            "https://www.openstreetmap.org/?mlat={lat}&mlon={lon}#map=16/{lat}/{lon}",
+            lat = self.latitude,
+            lon = self.longitude
+        )
+    }
+}
+
+/// The `geo:` URIs written in `text`, in order.
+pub fn find_geo_uris(text: &str) -> Vec<GeoPoint> {
+    GEO_URI_REGEX
+        .captures_iter(text)
+        .filter_map(|caps| GeoPoint::from_captures(&caps))
+        .collect()
+}
+
+/// The Discord text for an `m.location` message: its description, the
+/// coordinates and a map link. `None` without a usable `geo_uri`.
+pub fn matrix_location_to_discord(content: &Value) -> Option<String> {
+    let geo_uri = content
+        .get("geo_uri")
+        .or_else(|| {
+            content
+                .get("org.matrix.msc3488.location")
+                .and_then(|location| location.get("uri"))
+        })
+        .and_then(Value::as_str)?;
+    let point = GeoPoint::parse(geo_uri)?;
+    let description = content
+        .get("org.matrix.msc3488.location")
+        .and_then(|location| location.get("description"))
+        .or_else(|| content.get("body"))
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|description| !description.is_empty() && !description.starts_with("geo:"));
+
+    let mut text = String::from("📍 ");
+    if let Some(description) = description {
+        text.push_str(description);
+        text.push_str(" — ");
+    }
+    text.push_str(&format!(
+        "{}, {}\n{}",
+        point.latitude,
+        point.longitude,
+        point.map_url()
+    ));
+    Some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{GeoPoint, find_geo_uris, matrix_location_to_discord};
+
+    #[test]
+    fn parses_geo_uris_with_altitude_and_parameters() {
+        let point = GeoPoint::parse("geo:51.5074,-0.1278,12;u=35").unwrap();
+        assert_eq!(point.latitude, 51.5074);
+        assert_eq!(point.longitude, -0.1278);
+        assert_eq!(point.uri, "geo:51.5074,-0.1278,12;u=35");
+
+        assert!(GeoPoint::parse("geo:91,0").is_none());
+        assert!(GeoPoint::parse("geo:51.5,-0.1 and more").is_none());
+        assert!(GeoPoint::parse("https://example.org").is_none());
+    }
+
+    #[test]
+    fn finds_geo_uris_in_text() {
+        let points = find_geo_uris("meet at geo:48.8584,2.2945 or geo:40.6892,-74.0445;u=10.");
+        let uris: Vec<_> = points.iter().map(|point| point.uri.as_str()).collect();
+        assert_eq!(uris, ["geo:48.8584,2.2945", "geo:40.6892,-74.0445;u=10"]);
+        assert!(find_geo_uris("no places here, geology:1,2").is_empty());
+    }
+
+    #[test]
+    fn location_messages_become_map_links() {
+        let content = json!({
+            "msgtype": "m.location",
+            "body": "Big Ben",
+            "geo_uri": "geo:51.5007,-0.1246;u=20",
+        });
+        assert_eq!(
+            matrix_location_to_discord(&content).as_deref(),
+            Some(
+                "📍 Big Ben — 51.5007, -0.1246\nhttps://www.openstreetmap.org/?mlat=51.5007&mlon=-0.1246#map=16/51.5007/-0.1246"
+            )
+        );
+
+        let bare =
+            json!({ "msgtype": "m.location", "body": "geo:1.5,2.5", "geo_uri": "geo:1.5,2.5" });
+        assert!(
+            matrix_location_to_discord(&bare)
+                .unwrap()
+                .starts_with("📍 1.5, 2.5\n")
+        );
+
+        assert!(matrix_location_to_discord(&json!({ "body": "somewhere" })).is_none());
+    }
+}
@@ -1,11 +1,13 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use anyhow::Result;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use regex::Regex;
 use serde_json::Value;
 
 use super::common::{BridgeMessage, MessageUtils, ParsedMessage};
+use crate::db::RoomStore;
 use crate::matrix::{MatrixAppservice, MatrixEvent};
 
 pub struct MatrixMessageParser {
@@ -24,28 +26,179 @@ impl MatrixMessageParser {
 
 pub struct MatrixToDiscordConverter {
     matrix_client: Arc<MatrixAppservice>,
+    room_store: Option<Arc<dyn RoomStore>>,
     ghost_user_regex: Regex,
     ghost_alias_regex: Regex,
     room_alias_regex: Regex,
+    room_reference_regex: Regex,
+    iso_timestamp_regex: Regex,
+    convert_iso_timestamps: bool,
     mxclink_regex: Regex,
+    room_mention_regex: Regex,
+    /// Let `@room` and `@everyone` notify the whole Discord channel.
+    everyone_mentions: bool,
+    /// Let `@here` notify the online members of the Discord channel.
+    here_mentions: bool,
 }
 
 impl MatrixToDiscordConverter {
     pub fn new(matrix_client: Arc<MatrixAppservice>) -> Self {
         Self {
             matrix_client,
+            room_store: None,
             ghost_user_regex: Regex::new(r"@_discord_(\d+):[A-Za-z0-9.-]+").unwrap(),
             ghost_alias_regex: Regex::new(r"#_discord_(\d+):[A-Za-z0-9.-]+").unwrap(),
             room_alias_regex: Regex::new(r"#([^:]+):([a-zA-Z0-9.-]+)").unwrap(),
+            room_reference_regex: Regex::new(
+                r"\[[^\]]*\]\(https://matrix\.to/#/([#!%][^)\s?/]+)(?:\?[^)\s]*)?\)|https://matrix\.to/#/([#!%][^\s?/)]+)(?:\?[^\s)]*)?|([#!][A-Za-z0-9._=/-]+:[A-Za-z0-9.-]+(?::\d+)?)",
+            )
+            .unwrap(),
+            iso_timestamp_regex: Regex::new(
+                r"\b(\d{4}-\d{2}-\d{2})[T ](\d{2}:\d{2})(:\d{2}(?:\.\d+)?)?(Z|[+-]\d{2}:\d{2})",
+            )
+            .unwrap(),
+            convert_iso_timestamps: false,
             mxclink_regex: Regex::new(r"\[([^\]]+)\]\(mxc://[^)]+\)").unwrap(),
+            room_mention_regex: Regex::new(r"@(room|everyone|here)\b").unwrap(),
+            everyone_mentions: true,
+            here_mentions: true,
         }
     }
 
+    pub fn with_room_store(mut self, room_store: Arc<dyn RoomStore>) -> Self {
+        self.room_store = Some(room_store);
+        self
+    }
+
+    pub fn with_iso_timestamps(mut self, enabled: bool) -> Self {
+        self.convert_iso_timestamps = enabled;
+        self
+    }
+
+    /// Which of `@everyone` (also written `@room`) and `@here` may notify
+    /// Discord channels; the others are defused with a zero-width space.
+    pub fn with_channel_mentions(mut self, everyone: bool, here: bool) -> Self {
+        self.everyone_mentions = everyone;
+        self.here_mentions = here;
+        self
+    }
+
     pub fn format_for_discord(&self, message: &str) -> String {
+        self.format_for_discord_with_channels(message, &HashMap::new(), true)
+    }
+
+    /// Formats `message` for Discord, turning the room references resolved by
+    /// [`Self::resolve_room_references`] into channel mentions. `@room`,
+    /// `@everyone` and `@here` only notify the channel when the sender may
+    /// notify the whole Matrix room.
+    pub fn format_for_discord_with_channels(
+        &self,
+        message: &str,
+        channels: &HashMap<String, String>,
+        sender_can_notify_room: bool,
+    ) -> String {
         let mut result = message.to_string();
         result = self.convert_ghost_users_to_discord(&result);
         result = self.convert_ghost_aliases_to_discord(&result);
+        result = self.convert_room_references_to_discord(&result, channels);
         result = self.convert_mxclinks_to_discord(&result);
+        result = self.convert_channel_mentions_to_discord(&result, sender_can_notify_room);
+        if self.convert_iso_timestamps {
+            result = self.convert_iso_timestamps_to_discord(&result);
+        }
+        result
+    }
+
+    /// Maps the room aliases, room ids and matrix.to room links in `message`
+    /// to the Discord channels they are bridged to. Only channels in
+    /// `guild_id` are returned, since Discord cannot link across guilds.
+    pub async fn resolve_room_references(
+        &self,
+        message: &str,
+        guild_id: &str,
+    ) -> HashMap<String, String> {
+        let mut resolved = HashMap::new();
+        let Some(room_store) = &self.room_store else {
+            return resolved;
+        };
+
+        for (_, target) in self.room_references(message) {
+            if resolved.contains_key(&target) {
+                continue;
+            }
+            let room_id = if target.starts_with('!') {
+                Some(target.clone())
+            } else {
+                match self.matrix_client.resolve_room_alias(&target).await {
+                    Ok(room_id) => room_id,
+                    Err(err) => {
+                        tracing::warn!("Failed to resolve room alias {}: {}", target, err);
+                        None
+                    }
+                }
+            };
+            let Some(room_id) = room_id else {
+                continue;
+            };
+            match room_store.get_room_by_matrix_room(&room_id).await {
+                Ok(Some(mapping)) if mapping.discord_guild_id == guild_id => {
+                    resolved.insert(target, mapping.discord_channel_id);
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    tracing::warn!("Failed to look up room mapping for {}: {}", room_id, err);
+                }
+            }
+        }
+        resolved
+    }
+
+    /// Finds room references together with the byte range they cover. Bare
+    /// aliases and ids only count at the start of a word, and links to events
+    /// inside a room are skipped.
+    fn room_references(&self, text: &str) -> Vec<(std::ops::Range<usize>, String)> {
+        self.room_reference_regex
+            .captures_iter(text)
+            .filter_map(|caps| {
+                let whole = caps.get(0)?;
+                if text[whole.end()..].starts_with('/') {
+                    return None;
+                }
+                if let Some(bare) = caps.get(3) {
+                    let preceded_by_word = text[..bare.start()]
+                        .chars()
+                        .next_back()
+                        .is_some_and(|c| !c.is_whitespace() && c != '(');
+                    if preceded_by_word {
+                        return None;
+                    }
+                    return Some((whole.range(), bare.as_str().to_string()));
+                }
+                let target = caps.get(1).or_else(|| caps.get(2))?.as_str();
+                Some((whole.range(), decode_matrix_to_target(target)))
+            })
+            .collect()
+    }
+
+    fn convert_room_references_to_discord(
+        &self,
+        text: &str,
+        channels: &HashMap<String, String>,
+    ) -> String {
+        if channels.is_empty() {
+            return text.to_string();
+        }
+        let mut result = String::with_capacity(text.len());
+        let mut last = 0;
+        for (range, target) in self.room_references(text) {
+            let Some(channel_id) = channels.get(&target) else {
+                continue;
+            };
+            result.push_str(&text[last..range.start]);
+            result.push_str(&format!("<#{}>", channel_id));
+            last = range.end;
+        }
+        result.push_str(&text[last..]);
         result
     }
 
@@ -64,6 +217,49 @@ impl MatrixToDiscordConverter {
             .to_string()
     }
 
+    /// Whether `message` contains an `@room`, `@everyone` or `@here`, so the
+    /// sender's right to notify the room has to be checked.
+    pub fn mentions_whole_room(&self, message: &str) -> bool {
+        self.room_mention_regex.is_match(message)
+    }
+
+    /// `@room` becomes `@everyone`; mentions that are turned off, or that the
+    /// sender may not make, keep their text but cannot notify anyone. User ids
+    /// such as `@here:example.org` and addresses such as `me@here` are left
+    /// alone.
+    fn convert_channel_mentions_to_discord(
+        &self,
+        text: &str,
+        sender_can_notify_room: bool,
+    ) -> String {
+        self.room_mention_regex
+            .replace_all(text, |caps: &regex::Captures| {
+                let whole = caps.get(0).expect("whole match");
+                let in_word = text[..whole.start()]
+                    .chars()
+                    .next_back()
+                    .is_some_and(|c| c.is_alphanumeric() || c == '_');
+                let user_id = text[whole.end()..]
+                    .strip_prefix(':')
+                    .and_then(|server| server.chars().next())
+                    .is_some_and(|c| !c.is_whitespace());
+                if in_word || user_id {
+                    return whole.as_str().to_string();
+                }
+                let (name, allowed) = match &caps[1] {
+                    "here" => ("here", self.here_mentions),
+                    "room" if !self.everyone_mentions => return caps[0].to_string(),
+                    _ => ("everyone", self.everyone_mentions),
+                };
+                if allowed && sender_can_notify_room {
+                    format!("@{}", name)
+                } else {
+                    format!("@\u{200B}{}", name)
+                }
+            })
+            .to_string()
+    }
+
     fn convert_ghost_aliases_to_discord(&self, text: &str) -> String {
         self.ghost_alias_regex
             .replace_all(text, |caps: &regex::Captures| {
@@ -73,6 +269,26 @@ impl MatrixToDiscordConverter {
             .to_string()
     }
 
+    /// Only timestamps with an explicit offset are converted; without one the
+    /// intended time zone is unknown.
+    fn convert_iso_timestamps_to_discord(&self, text: &str) -> String {
+        self.iso_timestamp_regex
+            .replace_all(text, |caps: &regex::Captures| {
+                let normalized = format!(
+                    "{}T{}{}{}",
+                    &caps[1],
+                    &caps[2],
+                    caps.get(3).map_or(":00", |m| m.as_str()),
+                    &caps[4]
+                );
+                match DateTime::parse_from_rfc3339(&normalized) {
+                    Ok(time) => format!("<t:{}:f>", time.timestamp()),
+                    Err(_) => caps[0].to_string(),
+                }
+            })
+            .to_string()
+    }
+
     fn convert_mxclinks_to_discord(&self, text: &str) -> String {
         self.mxclink_regex
             .replace_all(text, |caps: &regex::Captures| {
@@ -184,6 +400,14 @@ impl MatrixToDiscordConverter {
     }
 }
 
+fn decode_matrix_to_target(target: &str) -> String {
+    target
+        .replace("%23", "#")
+        .replace("%21", "!")
+        .replace("%3A", ":")
+        .replace("%3a", ":")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -197,6 +421,7 @@ mod tests {
                 homeserver_url: "http://localhost:8008".to_string(),
                 presence_interval: 500,
                 disable_presence: false,
+                dry_run: false,
                 disable_typing_notifications: false,
                 disable_discord_mentions: false,
                 disable_deletion_forwarding: false,
@@ -213,10 +438,25 @@ mod tests {
                 admin_mxid: None,
                 invalid_token_message: String::new(),
                 user_activity: None,
+                presence_mapping: Default::default(),
+                room_mention_roles: Vec::new(),
+                convert_iso_timestamps: false,
+                member_change_notices: false,
+                geo_uri_locations: false,
+                cross_channel_context: false,
+                approval_dm_after_secs: 0,
+                relay_extractors: Vec::new(),
+                bridge_bot_messages: false,
+                content_redaction: Default::default(),
+                maintenance: Default::default(),
+                inactive_rooms: Default::default(),
+                listen: None,
+                socket_permissions: None,
             },
             registration: crate::config::RegistrationConfig::default(),
             auth: crate::config::AuthConfig {
-                bot_token: "test".to_string(),
+                bot_token: "test".into(),
+                bot_token_file: None,
                 client_id: None,
                 client_secret: None,
                 use_privileged_intents: false,
@@ -236,12 +476,16 @@ mod tests {
                 room_store_path: None,
                 max_connections: None,
                 min_connections: None,
+                max_blocking_threads: None,
             },
             room: crate::config::RoomConfig {
                 default_visibility: "private".to_string(),
                 room_alias_prefix: "_discord".to_string(),
                 enable_room_creation: true,
                 kick_for: 0,
+                auto_invite_members: false,
+                attribution_badge: false,
+                guilds: Default::default(),
             },
             channel: crate::config::ChannelConfig {
                 enable_channel_creation: false,
@@ -252,6 +496,7 @@ mod tests {
                 enable_webhook: true,
                 webhook_name: "_matrix".to_string(),
                 webhook_avatar: String::new(),
+                mirror_slowmode: false,
             },
             limits: crate::config::LimitsConfig::default(),
             ghosts: crate::config::GhostsConfig {
@@ -260,13 +505,47 @@ mod tests {
                 username_template: String::new(),
                 displayname_template: String::new(),
                 avatar_url_template: None,
+                hide_from_directory: false,
+                per_message_profiles: false,
+                protected_names: Vec::new(),
+                impersonation_suffix: String::new(),
             },
             metrics: crate::config::MetricsConfig::default(),
+            chaos: crate::config::ChaosConfig::default(),
+            admin_api: crate::config::AdminApiConfig::default(),
+            web: crate::config::WebConfig::default(),
+            delivery: crate::config::DeliveryConfig::default(),
+            homeserver_admin: crate::config::HomeserverAdminConfig::default(),
+            alerts: crate::config::AlertsConfig::default(),
+            media: crate::config::MediaConfig::default(),
         });
 
         MatrixToDiscordConverter::new(Arc::new(MatrixAppservice::new(config).await.unwrap()))
     }
 
+    #[tokio::test]
+    async fn room_mentions_follow_the_channel_mention_settings() {
+        let converter = make_converter().await;
+        assert_eq!(
+            converter.format_for_discord("@room: standup, @here too"),
+            "@everyone: standup, @here too"
+        );
+        assert_eq!(
+            converter.format_for_discord("ask @here:example.org or me@everyone.org"),
+            "ask @here:example.org or me@everyone.org"
+        );
+        assert_eq!(
+            converter.format_for_discord_with_channels("@room: standup", &HashMap::new(), false),
+            "@\u{200B}everyone: standup"
+        );
+
+        let converter = converter.with_channel_mentions(false, false);
+        assert_eq!(
+            converter.format_for_discord("@room @everyone @here"),
+            "@room @\u{200B}everyone @\u{200B}here"
+        );
+    }
+
     #[tokio::test]
     async fn converts_ghost_user_to_discord_mention() {
         let converter = make_converter().await;
@@ -350,4 +629,48 @@ mod tests {
         let result = converter.format_emote("Alice", "waves hello");
         assert_eq!(result, "* Alice waves hello");
     }
+
+    #[tokio::test]
+    async fn converts_bridged_room_references_to_channel_mentions() {
+        let converter = make_converter().await;
+        let channels = HashMap::from([
+            ("#general:example.org".to_string(), "100".to_string()),
+            ("!dev:example.org".to_string(), "200".to_string()),
+        ]);
+
+        let result = converter.format_for_discord_with_channels(
+            "See #general:example.org, [dev](https://matrix.to/#/%21dev%3Aexample.org) and #other:example.org",
+            &channels,
+            true,
+        );
+        assert_eq!(result, "See <#100>, <#200> and #other:example.org");
+    }
+
+    #[tokio::test]
+    async fn ignores_event_links_and_url_fragments() {
+        let converter = make_converter().await;
+        let channels = HashMap::from([("!dev:example.org".to_string(), "200".to_string())]);
+
+        let message =
+            "https://matrix.to/#/!dev:example.org/$event and https://example.org/a!dev:example.org";
+        assert_eq!(
+            converter.format_for_discord_with_channels(message, &channels, true),
+            message
+        );
+    }
+
+    #[tokio::test]
+    async fn converts_iso_timestamps_only_when_enabled() {
+        let message =
+            "Meet at 2021-04-20T21:20:30Z or 2021-04-20 23:20+02:00, not 2021-04-20T21:20";
+
+        let converter = make_converter().await;
+        assert_eq!(converter.format_for_discord(message), message);
+
+        let converter = make_converter().await.with_iso_timestamps(true);
+        assert_eq!(
+            converter.format_for_discord(message),
+            "Meet at <t:1618953630:f> or <t:1618953600:f>, not 2021-04-20T21:20"
+        );
+    }
 }
@@ -1,6 +1,17 @@
 pub mod alert;
+pub mod chaos;
+pub mod circuit_breaker;
+pub mod dry_run;
 pub mod error;
+pub mod event_log;
 pub mod formatting;
 pub mod logging;
+pub mod network;
+pub mod snowflake;
+pub mod webhook_alerts;
 
 pub use self::alert::AdminNotifier;
+pub use self::chaos::{ChaosInjector, ChaosTarget};
+pub use self::circuit_breaker::CircuitBreaker;
+pub use self::network::IpNetwork;
+pub use self::webhook_alerts::{AlertCondition, WebhookAlerter};
@@ -0,0 +1,168 @@
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use parking_lot::Mutex;
+use thiserror::Error;
+use tracing::{debug, warn};
+
+use crate::config::ChaosConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChaosTarget {
+    Discord,
+    Matrix,
+    Database,
+}
+
+impl fmt::Display for ChaosTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Discord => f.write_str("discord"),
+            Self::Matrix => f.write_str("matrix"),
+            Self::Database => f.write_str("database"),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("chaos: injected {target} failure in {operation}")]
+pub struct InjectedFailure {
+    pub target: ChaosTarget,
+    pub operation: String,
+}
+
+/// Adds random latency and failures to outgoing calls when `chaos.enabled` is
+/// set, so retry and dedup paths can be exercised against a running bridge.
+pub struct ChaosInjector {
+    config: ChaosConfig,
+    state: Mutex<u64>,
+}
+
+impl ChaosInjector {
+    pub fn new(config: &ChaosConfig) -> Self {
+        let seed = config.seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_nanos() as u64)
+                .unwrap_or_default()
+        });
+        if config.enabled {
+            warn!(
+                "chaos mode enabled: discord={} matrix={} database={} max_latency_ms={} seed={}",
+                config.discord_failure_rate,
+                config.matrix_failure_rate,
+                config.database_failure_rate,
+                config.max_latency_ms,
+                seed
+            );
+        }
+        Self {
+            config: config.clone(),
+            // xorshift never leaves zero, so nudge a zero seed.
+            state: Mutex::new(seed.max(1)),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self::new(&ChaosConfig::default())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Sleeps for a random share of `max_latency_ms`, then fails with the
+    /// configured probability for `target`.
+    pub async fn inject(
+        &self,
+        target: ChaosTarget,
+        operation: &str,
+    ) -> Result<(), InjectedFailure> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+
+        if self.config.max_latency_ms > 0 {
+            let latency = (self.next_unit() * self.config.max_latency_ms as f64) as u64;
+            if latency > 0 {
+                debug!("chaos: delaying {target} {operation} by {latency}ms");
+                tokio::time::sleep(Duration::from_millis(latency)).await;
+            }
+        }
+
+        if self.next_unit() < self.failure_rate(target) {
+            warn!("chaos: failing {target} {operation}");
+            return Err(InjectedFailure {
+                target,
+                operation: operation.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    fn failure_rate(&self, target: ChaosTarget) -> f64 {
+        match target {
+            ChaosTarget::Discord => self.config.discord_failure_rate,
+            ChaosTarget::Matrix => self.config.matrix_failure_rate,
+            ChaosTarget::Database => self.config.database_failure_rate,
+        }
+    }
+
+    /// Uniform value in `[0, 1)` from a xorshift64 sequence.
+    fn next_unit(&self) -> f64 {
+        let mut state = self.state.lock();
+        let mut x = *state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        *state = x;
+        (x >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChaosInjector, ChaosTarget};
+    use crate::config::ChaosConfig;
+
+    fn chaos(discord_failure_rate: f64) -> ChaosInjector {
+        ChaosInjector::new(&ChaosConfig {
+            enabled: true,
+            discord_failure_rate,
+            seed: Some(7),
+            ..ChaosConfig::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn disabled_injector_never_fails() {
+        let injector = ChaosInjector::new(&ChaosConfig {
+            discord_failure_rate: 1.0,
+            ..ChaosConfig::default()
+        });
+        assert!(injector.inject(ChaosTarget::Discord, "send").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn failure_rate_applies_per_target() {
+        let injector = chaos(1.0);
+        let err = injector
+            .inject(ChaosTarget::Discord, "send")
+            .await
+            .expect_err("discord sends always fail");
+        assert_eq!(err.to_string(), "chaos: injected discord failure in send");
+        assert!(injector.inject(ChaosTarget::Matrix, "send").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn seeded_runs_are_reproducible() {
+        let first = chaos(0.5);
+        let second = chaos(0.5);
+        for _ in 0..32 {
+            assert_eq!(
+                first.inject(ChaosTarget::Discord, "send").await.is_ok(),
+                second.inject(ChaosTarget::Discord, "send").await.is_ok()
+            );
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+/// Opens after `failure_threshold` failures in a row and then refuses calls
+/// for `cooldown`. Once the cooldown is over a trial call is let through: a
+/// success closes the breaker again, a failure restarts the cooldown.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    /// Whether a call may go ahead at `now`.
+    pub fn allow(&self, now: Instant) -> bool {
+        match self.state.lock().opened_at {
+            Some(opened_at) => now.saturating_duration_since(opened_at) >= self.cooldown,
+            None => true,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.state.lock().opened_at.is_some()
+    }
+
+    /// Records a successful call. Returns `true` when this closed an open
+    /// breaker.
+    pub fn record_success(&self) -> bool {
+        let mut state = self.state.lock();
+        state.consecutive_failures = 0;
+        state.opened_at.take().is_some()
+    }
+
+    /// Records a failed call. Returns `true` only when this failure opened
+    /// the breaker, so callers can escalate once per outage.
+    pub fn record_failure(&self, now: Instant) -> bool {
+        let mut state = self.state.lock();
+        state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+        if state.opened_at.is_some() {
+            state.opened_at = Some(now);
+            return false;
+        }
+        if state.consecutive_failures >= self.failure_threshold {
+            state.opened_at = Some(now);
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::CircuitBreaker;
+
+    #[test]
+    fn opens_after_consecutive_failures_and_recovers_after_cooldown() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(30));
+        let start = Instant::now();
+
+        assert!(!breaker.record_failure(start));
+        assert!(!breaker.record_failure(start));
+        assert!(!breaker.record_success());
+        assert!(!breaker.record_failure(start));
+        assert!(!breaker.record_failure(start));
+        assert!(breaker.record_failure(start));
+        assert!(breaker.is_open());
+        assert!(!breaker.allow(start + Duration::from_secs(10)));
+
+        // The trial call after the cooldown fails: stay open, no new escalation.
+        let trial = start + Duration::from_secs(30);
+        assert!(breaker.allow(trial));
+        assert!(!breaker.record_failure(trial));
+        assert!(!breaker.allow(trial + Duration::from_secs(10)));
+
+        assert!(breaker.allow(trial + Duration::from_secs(30)));
+        assert!(breaker.record_success());
+        assert!(!breaker.is_open());
+        assert!(breaker.allow(trial + Duration::from_secs(30)));
+    }
+}
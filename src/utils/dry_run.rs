@@ -0,0 +1,81 @@
+//! `bridge.dry_run`: outbound Matrix and Discord calls are logged and
+//! counted instead of made, so a config can be staged against production
+//! traffic without the bridge writing anything to either side.
+
+use tracing::info;
+use uuid::Uuid;
+
+use crate::utils::ChaosTarget;
+use crate::web::Metrics;
+
+/// Returns whether `operation` must be skipped, logging and counting it
+/// when it is.
+pub fn skip(enabled: bool, target: ChaosTarget, operation: &'static str, subject: &str) -> bool {
+    if !enabled {
+        return false;
+    }
+    let side = match target {
+        ChaosTarget::Discord => "discord",
+        ChaosTarget::Matrix => "matrix",
+        ChaosTarget::Database => "database",
+    };
+    info!("dry run: skipped {} {} for {}", side, operation, subject);
+    Metrics::dry_run_skipped(side, operation);
+    true
+}
+
+/// A placeholder id for something a skipped call would have created, e.g.
+/// `$dry-run-<uuid>` for an event. Callers store it like a real id.
+pub fn placeholder_id(prefix: &str) -> String {
+    format!("{}dry-run-{}", prefix, Uuid::new_v4().simple())
+}
+
+/// Whether `id` is a [`placeholder_id`], bare or inside a room id or mxc
+/// url. Placeholders are never stored, since nothing exists behind them.
+pub fn is_placeholder(id: &str) -> bool {
+    let local = id.rsplit('/').next().unwrap_or(id);
+    let local = local.trim_start_matches(['$', '!']);
+    let local = local.split(':').next().unwrap_or(local);
+    local
+        .strip_prefix("dry-run-")
+        .is_some_and(|uuid| uuid.len() == 32 && uuid.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_placeholder, placeholder_id, skip};
+    use crate::utils::ChaosTarget;
+
+    #[test]
+    fn calls_are_skipped_only_when_enabled() {
+        assert!(!skip(
+            false,
+            ChaosTarget::Matrix,
+            "dry_run_test",
+            "!room:example.org"
+        ));
+        assert!(skip(
+            true,
+            ChaosTarget::Matrix,
+            "dry_run_test",
+            "!room:example.org"
+        ));
+        assert!(placeholder_id("$").starts_with("$dry-run-"));
+    }
+
+    #[test]
+    fn placeholders_are_recognised_in_every_id_shape() {
+        assert!(is_placeholder(&placeholder_id("$")));
+        assert!(is_placeholder(&placeholder_id("")));
+        assert!(is_placeholder(&format!(
+            "{}:example.org",
+            placeholder_id("!")
+        )));
+        assert!(is_placeholder(&format!(
+            "mxc://example.org/{}",
+            placeholder_id("")
+        )));
+        assert!(!is_placeholder("$dry-run-event:example.org"));
+        assert!(!is_placeholder("1234567890"));
+    }
+}
@@ -0,0 +1,137 @@
+//! The last raw events received from each side, kept for
+//! `/admin/debug/events` when `admin_api.debug_events` is set. Recording is a
+//! no-op while it is zero.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventSource {
+    Discord,
+    Matrix,
+}
+
+impl EventSource {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "discord" => Some(Self::Discord),
+            "matrix" => Some(Self::Matrix),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordedEvent {
+    pub source: EventSource,
+    pub received_at: DateTime<Utc>,
+    pub event: Value,
+}
+
+static CAPACITY: AtomicUsize = AtomicUsize::new(0);
+static DISCORD_EVENTS: Lazy<Mutex<VecDeque<RecordedEvent>>> =
+    Lazy::new(|| Mutex::new(VecDeque::new()));
+static MATRIX_EVENTS: Lazy<Mutex<VecDeque<RecordedEvent>>> =
+    Lazy::new(|| Mutex::new(VecDeque::new()));
+
+fn events(source: EventSource) -> &'static Mutex<VecDeque<RecordedEvent>> {
+    match source {
+        EventSource::Discord => &DISCORD_EVENTS,
+        EventSource::Matrix => &MATRIX_EVENTS,
+    }
+}
+
+/// Keeps up to `capacity` events per source; zero turns recording off and
+/// drops what was kept.
+pub fn set_capacity(capacity: usize) {
+    CAPACITY.store(capacity, Ordering::Relaxed);
+    for source in [EventSource::Discord, EventSource::Matrix] {
+        let mut events = events(source).lock();
+        while events.len() > capacity {
+            events.pop_front();
+        }
+    }
+}
+
+pub fn is_enabled() -> bool {
+    CAPACITY.load(Ordering::Relaxed) > 0
+}
+
+/// Records an event, building it only when recording is on.
+pub fn record(source: EventSource, event: impl FnOnce() -> Value) {
+    let capacity = CAPACITY.load(Ordering::Relaxed);
+    if capacity == 0 {
+        return;
+    }
+    let recorded = RecordedEvent {
+        source,
+        received_at: Utc::now(),
+        event: event(),
+    };
+    let mut events = events(source).lock();
+    while events.len() >= capacity {
+        events.pop_front();
+    }
+    events.push_back(recorded);
+}
+
+/// Up to `limit` of the newest events, newest first, from one source or
+/// both.
+pub fn recent(source: Option<EventSource>, limit: usize) -> Vec<RecordedEvent> {
+    let sources = match source {
+        Some(source) => vec![source],
+        None => vec![EventSource::Discord, EventSource::Matrix],
+    };
+    // Each buffer is walked newest first so the stable sort keeps that order
+    // for events received at the same instant.
+    let mut recent: Vec<RecordedEvent> = sources
+        .into_iter()
+        .flat_map(|source| {
+            events(source)
+                .lock()
+                .iter()
+                .rev()
+                .cloned()
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    recent.sort_by_key(|event| std::cmp::Reverse(event.received_at));
+    recent.truncate(limit);
+    recent
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{EventSource, recent, record, set_capacity};
+
+    #[test]
+    fn keeps_the_newest_events_per_source() {
+        set_capacity(2);
+        for n in 0..3 {
+            record(EventSource::Discord, || json!({ "n": n }));
+        }
+        record(EventSource::Matrix, || json!({ "type": "m.room.message" }));
+
+        let discord = recent(Some(EventSource::Discord), 10);
+        let numbers: Vec<_> = discord
+            .iter()
+            .map(|event| event.event["n"].clone())
+            .collect();
+        assert_eq!(numbers, [json!(2), json!(1)]);
+        assert_eq!(recent(None, 10).len(), 3);
+        assert_eq!(recent(None, 1).len(), 1);
+
+        set_capacity(0);
+        record(EventSource::Matrix, || unreachable!());
+        assert!(recent(None, 10).is_empty());
+    }
+}
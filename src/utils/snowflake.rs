@@ -0,0 +1,88 @@
+//! Discord ids are snowflakes: a `u64` whose top 42 bits are the
+//! milliseconds since [`DISCORD_EPOCH_MS`], so ids also order and date the
+//! objects they name.
+
+use std::cmp::Ordering;
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+
+/// 2015-01-01T00:00:00Z, the first millisecond a snowflake can encode.
+pub const DISCORD_EPOCH_MS: i64 = 1_420_070_400_000;
+
+const TIMESTAMP_SHIFT: u32 = 22;
+
+/// Parses a snowflake. Ids are decimal digits only and never zero.
+pub fn parse(id: &str) -> Option<u64> {
+    if id.is_empty() || !id.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    id.parse().ok().filter(|&id| id != 0)
+}
+
+pub fn is_valid(id: &str) -> bool {
+    parse(id).is_some()
+}
+
+/// Like [`parse`], failing with `invalid <kind> id: <id>`.
+pub fn parse_id(kind: &str, id: &str) -> Result<u64> {
+    parse(id).ok_or_else(|| anyhow!("invalid {} id: {}", kind, id))
+}
+
+/// Milliseconds since the Unix epoch at which `id` was created.
+pub fn timestamp_ms(id: u64) -> i64 {
+    (id >> TIMESTAMP_SHIFT) as i64 + DISCORD_EPOCH_MS
+}
+
+/// The smallest id created at `ms`, for paging by time; 1 before the epoch.
+pub fn from_timestamp_ms(ms: i64) -> u64 {
+    (((ms - DISCORD_EPOCH_MS).max(0) as u64) << TIMESTAMP_SHIFT).max(1)
+}
+
+pub fn created_at(id: &str) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp_millis(timestamp_ms(parse(id)?))
+}
+
+/// Orders ids by creation, which string comparison gets wrong once ids
+/// differ in length. Invalid ids sort after valid ones.
+pub fn compare(a: &str, b: &str) -> Ordering {
+    match (parse(a), parse(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.cmp(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use super::{compare, created_at, from_timestamp_ms, parse, parse_id, timestamp_ms};
+
+    #[test]
+    fn ids_are_validated_and_dated() {
+        assert_eq!(parse("175928847299117063"), Some(175928847299117063));
+        for invalid in ["", "0", "-1", "+5", "12a", "99999999999999999999"] {
+            assert_eq!(parse(invalid), None, "{invalid:?}");
+        }
+        assert_eq!(
+            parse_id("guild", "abc").unwrap_err().to_string(),
+            "invalid guild id: abc"
+        );
+
+        assert_eq!(
+            created_at("175928847299117063").unwrap().to_rfc3339(),
+            "2016-04-30T11:18:25.796+00:00"
+        );
+        assert_eq!(created_at("nope"), None);
+        assert_eq!(
+            timestamp_ms(from_timestamp_ms(1_462_015_105_796)),
+            1_462_015_105_796
+        );
+        assert_eq!(from_timestamp_ms(0), 1);
+
+        assert_eq!(compare("99", "100"), Ordering::Less);
+        assert_eq!(compare("100", "x"), Ordering::Less);
+    }
+}
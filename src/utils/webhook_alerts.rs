@@ -0,0 +1,239 @@
+//! Posts `alerts` to a webhook when a bridge incident starts and when it
+//! clears.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use serde_json::json;
+use tracing::{info, warn};
+
+use crate::config::{AlertFormat, AlertsConfig, Config};
+
+const ALERT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AlertCondition {
+    GatewayDown,
+    HomeserverCircuitOpen,
+    QueueDepth,
+    MigrationFailed,
+}
+
+impl AlertCondition {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::GatewayDown => "gateway_down",
+            Self::HomeserverCircuitOpen => "homeserver_circuit_open",
+            Self::QueueDepth => "queue_depth",
+            Self::MigrationFailed => "migration_failed",
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            Self::GatewayDown => "Discord gateway down",
+            Self::HomeserverCircuitOpen => "Homeserver circuit open",
+            Self::QueueDepth => "Queue depth above threshold",
+            Self::MigrationFailed => "Database migration failed",
+        }
+    }
+}
+
+/// One HTTP request to the alert webhook.
+#[derive(Debug, Clone, PartialEq)]
+struct AlertRequest {
+    headers: Vec<(&'static str, String)>,
+    body: AlertBody,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum AlertBody {
+    Json(serde_json::Value),
+    Text(String),
+}
+
+pub struct WebhookAlerter {
+    webhook_url: Option<String>,
+    format: AlertFormat,
+    bridge: String,
+    /// Conditions alerted on and not cleared yet.
+    firing: Mutex<HashSet<AlertCondition>>,
+    http: reqwest::Client,
+}
+
+impl WebhookAlerter {
+    pub fn new(config: &Config) -> Self {
+        Self::from_alerts_config(&config.alerts, &config.bridge.domain)
+    }
+
+    pub fn from_alerts_config(alerts: &AlertsConfig, bridge: &str) -> Self {
+        Self {
+            webhook_url: alerts.webhook_url.clone(),
+            format: alerts.format,
+            bridge: bridge.to_string(),
+            firing: Mutex::new(HashSet::new()),
+            http: reqwest::Client::builder()
+                .timeout(ALERT_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.webhook_url.is_some()
+    }
+
+    /// Alerts that `condition` started, unless it is already firing.
+    pub async fn raise(&self, condition: AlertCondition, message: &str) {
+        if !self.is_enabled() || !self.firing.lock().insert(condition) {
+            return;
+        }
+        self.post(condition, true, message).await;
+    }
+
+    /// Alerts that `condition` cleared, if it was firing.
+    pub async fn resolve(&self, condition: AlertCondition, message: &str) {
+        if !self.firing.lock().remove(&condition) {
+            return;
+        }
+        self.post(condition, false, message).await;
+    }
+
+    async fn post(&self, condition: AlertCondition, firing: bool, message: &str) {
+        let Some(url) = &self.webhook_url else {
+            return;
+        };
+        let request = alert_request(self.format, condition, firing, message, &self.bridge);
+        let mut builder = self.http.post(url);
+        for (name, value) in request.headers {
+            builder = builder.header(name, value);
+        }
+        builder = match request.body {
+            AlertBody::Json(body) => builder.json(&body),
+            AlertBody::Text(body) => builder.body(body),
+        };
+        match builder.send().await {
+            Ok(response) if response.status().is_success() => {
+                info!(
+                    "sent {} alert {}",
+                    if firing { "firing" } else { "resolved" },
+                    condition.as_str()
+                );
+            }
+            Ok(response) => warn!(
+                "alert webhook rejected {}: {}",
+                condition.as_str(),
+                response.status()
+            ),
+            Err(err) => warn!("failed to send alert {}: {}", condition.as_str(), err),
+        }
+    }
+}
+
+fn alert_request(
+    format: AlertFormat,
+    condition: AlertCondition,
+    firing: bool,
+    message: &str,
+    bridge: &str,
+) -> AlertRequest {
+    let (status, priority, tags) = if firing {
+        ("firing", "urgent", "warning")
+    } else {
+        ("resolved", "default", "white_check_mark")
+    };
+    let title = if firing {
+        condition.title().to_string()
+    } else {
+        format!("Resolved: {}", condition.title())
+    };
+    let text = format!("[{bridge}] {title}: {message}");
+    match format {
+        AlertFormat::Generic => AlertRequest {
+            headers: Vec::new(),
+            body: AlertBody::Json(json!({
+                "condition": condition.as_str(),
+                "status": status,
+                "message": message,
+                "bridge": bridge,
+            })),
+        },
+        AlertFormat::Slack => AlertRequest {
+            headers: Vec::new(),
+            body: AlertBody::Json(json!({ "text": text })),
+        },
+        AlertFormat::Discord => AlertRequest {
+            headers: Vec::new(),
+            body: AlertBody::Json(json!({ "content": text })),
+        },
+        AlertFormat::Ntfy => AlertRequest {
+            headers: vec![
+                ("Title", format!("[{bridge}] {title}")),
+                ("Priority", priority.to_string()),
+                ("Tags", tags.to_string()),
+            ],
+            body: AlertBody::Text(message.to_string()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{AlertBody, AlertCondition, WebhookAlerter, alert_request};
+    use crate::config::{AlertFormat, AlertsConfig};
+
+    #[test]
+    fn requests_follow_the_configured_format() {
+        let generic = alert_request(
+            AlertFormat::Generic,
+            AlertCondition::QueueDepth,
+            true,
+            "1500 queued",
+            "example.org",
+        );
+        assert_eq!(
+            generic.body,
+            AlertBody::Json(json!({
+                "condition": "queue_depth",
+                "status": "firing",
+                "message": "1500 queued",
+                "bridge": "example.org",
+            }))
+        );
+
+        let discord = alert_request(
+            AlertFormat::Discord,
+            AlertCondition::GatewayDown,
+            false,
+            "reconnected",
+            "example.org",
+        );
+        assert_eq!(
+            discord.body,
+            AlertBody::Json(json!({
+                "content": "[example.org] Resolved: Discord gateway down: reconnected"
+            }))
+        );
+
+        let ntfy = alert_request(
+            AlertFormat::Ntfy,
+            AlertCondition::MigrationFailed,
+            true,
+            "no such table",
+            "example.org",
+        );
+        assert_eq!(ntfy.body, AlertBody::Text("no such table".to_string()));
+        assert!(ntfy.headers.contains(&("Priority", "urgent".to_string())));
+    }
+
+    #[tokio::test]
+    async fn conditions_without_a_webhook_never_fire() {
+        let alerter = WebhookAlerter::from_alerts_config(&AlertsConfig::default(), "example.org");
+        assert!(!alerter.is_enabled());
+        alerter.raise(AlertCondition::GatewayDown, "down").await;
+        assert!(alerter.firing.lock().is_empty());
+    }
+}
@@ -1,31 +1,57 @@
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Instant;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use once_cell::sync::OnceCell;
+use salvo::conn::rustls::{Keycert, RustlsConfig};
 use salvo::prelude::*;
-use tracing::info;
+use tracing::{info, warn};
 
 use crate::bridge::BridgeCore;
-use crate::config::Config;
+use crate::config::{ApiScope, Config, ListenAddress};
 use crate::db::DatabaseManager;
 use crate::matrix::MatrixAppservice;
 
+mod allowlist;
+mod audit;
+mod auth;
+mod debug;
 mod health;
+mod maintenance;
+mod messages;
 mod metrics;
 mod provisioning;
+mod replay;
+mod resolve;
+mod stats;
 mod thirdparty;
+mod tokens;
+mod users;
 
-use health::{get_status, health_check};
+pub use allowlist::IpAllowlist;
+use audit::list_audit_entries;
+pub use auth::{ApiAuth, AuthenticatedToken, RequireScope, hash_token};
+use debug::list_debug_events;
+use health::{get_status, health_check, public_status_json, public_status_page};
+use maintenance::{get_maintenance, set_maintenance};
+use messages::list_messages;
+pub use metrics::Metrics;
 use metrics::metrics_endpoint;
-use provisioning::{create_bridge, delete_bridge, get_bridge_info, list_rooms};
+use provisioning::{create_bridge, delete_bridge, get_bridge_info, get_read_markers, list_rooms};
+use replay::replay_messages;
+use resolve::resolve_ids;
+use stats::emoji_stats;
 use thirdparty::{get_locations, get_networks, get_protocol, get_users};
+use tokens::{create_token, delete_token, list_tokens};
+use users::{list_users, purge_user_data};
 
 #[derive(Clone)]
 pub struct WebState {
     pub db_manager: Arc<DatabaseManager>,
     pub matrix_client: Arc<MatrixAppservice>,
     pub bridge: Arc<BridgeCore>,
+    pub api_auth: Arc<ApiAuth>,
     pub started_at: Instant,
 }
 
@@ -41,6 +67,7 @@ pub fn web_state() -> &'static WebState {
 pub struct WebServer {
     config: Arc<Config>,
     matrix_client: Arc<MatrixAppservice>,
+    api_auth: Arc<ApiAuth>,
 }
 
 impl WebServer {
@@ -50,49 +77,149 @@ impl WebServer {
         db_manager: Arc<DatabaseManager>,
         bridge: Arc<BridgeCore>,
     ) -> Result<Self> {
+        let api_auth = Arc::new(ApiAuth::new(
+            &config.admin_api,
+            db_manager.api_token_store(),
+        ));
         let _ = WEB_STATE.set(WebState {
             db_manager,
             matrix_client: matrix_client.clone(),
             bridge,
+            api_auth: api_auth.clone(),
             started_at: Instant::now(),
         });
 
         Ok(Self {
             config,
             matrix_client,
+            api_auth,
         })
     }
 
     pub async fn start(&self) -> Result<()> {
-        let bind_addr = format!(
-            "{}:{}",
-            self.config.bridge.bind_address, self.config.bridge.port
-        );
-        info!("starting web server on {}", bind_addr);
-
-        let acceptor = TcpListener::new(bind_addr).bind().await;
         let appservice_router = self.matrix_client.appservice.router();
-        let main_router = root_router().push(appservice_router);
-        Server::new(acceptor).serve(main_router).await;
+        if self.api_auth.is_open().await? {
+            warn!("no admin api tokens are configured; the admin api is unauthenticated");
+        }
+        let allowlist = IpAllowlist::new(&self.config.admin_api.allowed_ips);
+        let main_router = root_router(self.api_auth.clone(), allowlist).push(appservice_router);
+
+        let bind_addr = match self.config.bridge.listen_address()? {
+            ListenAddress::Unix(path) => {
+                return serve_unix(&path, self.config.bridge.socket_mode()?, main_router).await;
+            }
+            ListenAddress::Tcp(bind_addr) => bind_addr,
+        };
+        let listener = TcpListener::new(bind_addr.clone());
+        match (
+            &self.config.web.tls_cert_path,
+            &self.config.web.tls_key_path,
+        ) {
+            (Some(cert_path), Some(key_path)) => {
+                // Both rustls backends end up enabled through dependencies, so
+                // rustls cannot pick one on its own.
+                let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+                let keycert = Keycert::new()
+                    .cert_from_path(cert_path)
+                    .with_context(|| format!("failed to read {}", cert_path.display()))?
+                    .key_from_path(key_path)
+                    .with_context(|| format!("failed to read {}", key_path.display()))?;
+                info!("starting web server on {} (tls)", bind_addr);
+                let acceptor = listener.rustls(RustlsConfig::new(keycert)).bind().await;
+                Server::new(acceptor).serve(main_router).await;
+            }
+            _ => {
+                info!("starting web server on {}", bind_addr);
+                let acceptor = listener.bind().await;
+                Server::new(acceptor).serve(main_router).await;
+            }
+        }
 
         Ok(())
     }
 }
 
-pub fn root_router() -> Router {
+#[cfg(unix)]
+async fn serve_unix(path: &Path, mode: Option<u32>, router: Router) -> Result<()> {
+    use std::fs::Permissions;
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    // A socket left behind by an unclean shutdown would make the bind fail.
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            anyhow::bail!("{} exists and is not a socket", path.display());
+        }
+        std::fs::remove_file(path)
+            .with_context(|| format!("failed to remove stale socket {}", path.display()))?;
+    }
+
+    let listener =
+        UnixListener::new(path.to_path_buf()).permissions(mode.map(Permissions::from_mode));
+    info!("starting web server on unix:{}", path.display());
+    let acceptor = listener.try_bind().await?;
+    Server::new(acceptor).serve(router).await;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn serve_unix(path: &Path, _mode: Option<u32>, _router: Router) -> Result<()> {
+    anyhow::bail!(
+        "unix socket listeners are not supported on this platform ({})",
+        path.display()
+    )
+}
+
+/// Provisioning and admin routes, each behind the allowlist and the scope
+/// it needs.
+fn scoped_routes(auth: &Arc<ApiAuth>, allowlist: &IpAllowlist, include_admin: bool) -> Vec<Router> {
+    let guarded = |path: &str, scope: ApiScope| {
+        Router::with_path(path)
+            .hoop(allowlist.clone())
+            .hoop(RequireScope::new(auth.clone(), scope))
+    };
+
+    let rooms_path = if include_admin { "bridges" } else { "rooms" };
+    let mut routes = vec![
+        guarded(rooms_path, ApiScope::ReadOnly).get(list_rooms),
+        guarded("bridges", ApiScope::Provision).post(create_bridge),
+        guarded("bridges/{id}", ApiScope::ReadOnly).get(get_bridge_info),
+        guarded("bridges/{id}", ApiScope::Provision).delete(delete_bridge),
+        guarded("receipts", ApiScope::ReadOnly).get(get_read_markers),
+    ];
+    if include_admin {
+        routes.push(guarded("audit", ApiScope::Admin).get(list_audit_entries));
+        routes.push(
+            guarded("tokens", ApiScope::Admin)
+                .get(list_tokens)
+                .post(create_token),
+        );
+        routes.push(guarded("tokens/{name}", ApiScope::Admin).delete(delete_token));
+        routes.push(guarded("users", ApiScope::ReadOnly).get(list_users));
+        routes.push(guarded("users/purge", ApiScope::Admin).post(purge_user_data));
+        routes.push(guarded("messages", ApiScope::ReadOnly).get(list_messages));
+        routes.push(guarded("debug/events", ApiScope::Admin).get(list_debug_events));
+        routes.push(
+            guarded("maintenance", ApiScope::Admin)
+                .get(get_maintenance)
+                .post(set_maintenance),
+        );
+        routes.push(guarded("replay", ApiScope::Admin).post(replay_messages));
+        routes.push(guarded("stats/emoji", ApiScope::ReadOnly).get(emoji_stats));
+        routes.push(guarded("resolve", ApiScope::ReadOnly).get(resolve_ids));
+    }
+    routes
+}
+
+pub fn root_router(auth: Arc<ApiAuth>, allowlist: IpAllowlist) -> Router {
     Router::new()
         .push(Router::with_path("health").get(health_check))
         .push(Router::with_path("status").get(get_status))
+        .push(Router::with_path("status.json").get(public_status_json))
+        .push(Router::with_path("status.html").get(public_status_page))
         .push(Router::with_path("metrics").get(metrics_endpoint))
         .push(
             Router::with_path("_matrix/app/v1")
-                .push(Router::with_path("rooms").get(list_rooms))
-                .push(Router::with_path("bridges").post(create_bridge))
-                .push(
-                    Router::with_path("bridges/{id}")
-                        .get(get_bridge_info)
-                        .delete(delete_bridge),
-                )
+                .append(&mut scoped_routes(&auth, &allowlist, false))
                 .push(
                     Router::with_path("thirdparty")
                         .push(Router::with_path("protocol").get(get_protocol))
@@ -105,17 +232,5 @@ pub fn root_router() -> Router {
                         .push(Router::with_path("user/discord").get(get_users)),
                 ),
         )
-        .push(
-            Router::with_path("admin")
-                .push(
-                    Router::with_path("bridges")
-                        .get(list_rooms)
-                        .post(create_bridge),
-                )
-                .push(
-                    Router::with_path("bridges/{id}")
-                        .get(get_bridge_info)
-                        .delete(delete_bridge),
-                ),
-        )
+        .push(Router::with_path("admin").append(&mut scoped_routes(&auth, &allowlist, true)))
 }
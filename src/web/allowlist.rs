@@ -0,0 +1,83 @@
+use salvo::prelude::*;
+use tracing::warn;
+
+use crate::utils::IpNetwork;
+use crate::web::provisioning::render_error;
+
+/// Middleware that rejects requests whose peer address is outside the
+/// configured networks. An empty list lets everything through.
+#[derive(Debug, Clone, Default)]
+pub struct IpAllowlist {
+    networks: Vec<IpNetwork>,
+}
+
+impl IpAllowlist {
+    /// Entries are validated with the config, so unparsable ones are skipped.
+    pub fn new(entries: &[String]) -> Self {
+        Self {
+            networks: entries
+                .iter()
+                .filter_map(|entry| entry.parse().ok())
+                .collect(),
+        }
+    }
+
+    pub fn allows(&self, addr: &salvo::conn::SocketAddr) -> bool {
+        if self.networks.is_empty() {
+            return true;
+        }
+        // Unix socket peers are already limited by the socket's permissions.
+        #[cfg(unix)]
+        if matches!(addr, salvo::conn::SocketAddr::Unix(_)) {
+            return true;
+        }
+        addr.ip()
+            .is_some_and(|ip| self.networks.iter().any(|net| net.contains(ip)))
+    }
+}
+
+#[async_trait]
+impl Handler for IpAllowlist {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        if self.allows(req.remote_addr()) {
+            ctrl.call_next(req, depot, res).await;
+            return;
+        }
+        warn!(
+            "rejected {} {} from {} (not in admin_api.allowed_ips)",
+            req.method(),
+            req.uri().path(),
+            req.remote_addr()
+        );
+        render_error(res, StatusCode::FORBIDDEN, "address not allowed");
+        ctrl.skip_rest();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use salvo::conn::SocketAddr;
+
+    use super::IpAllowlist;
+
+    fn addr(value: &str) -> SocketAddr {
+        value.parse::<std::net::SocketAddr>().unwrap().into()
+    }
+
+    #[test]
+    fn allowlist_matches_peer_addresses() {
+        assert!(IpAllowlist::default().allows(&addr("203.0.113.9:443")));
+
+        let allowlist = IpAllowlist::new(&["127.0.0.1".to_string(), "10.0.0.0/8".to_string()]);
+        assert!(allowlist.allows(&addr("127.0.0.1:50000")));
+        assert!(allowlist.allows(&addr("10.20.30.40:1")));
+        assert!(!allowlist.allows(&addr("192.168.1.1:1")));
+        assert!(!allowlist.allows(&SocketAddr::Unknown));
+    }
+}
@@ -0,0 +1,78 @@
+use chrono::{DateTime, Utc};
+use salvo::prelude::*;
+use serde_json::json;
+
+use crate::db::{AuditLogFilter, AuditSource};
+use crate::web::provisioning::render_error;
+use crate::web::web_state;
+
+pub(super) fn parse_timestamp(req: &Request, name: &str) -> Result<Option<DateTime<Utc>>, String> {
+    match req.query::<String>(name) {
+        Some(value) => DateTime::parse_from_rfc3339(&value)
+            .map(|ts| Some(ts.with_timezone(&Utc)))
+            .map_err(|_| format!("{name} must be an RFC 3339 timestamp")),
+        None => Ok(None),
+    }
+}
+
+fn parse_filter(req: &Request) -> Result<AuditLogFilter, String> {
+    let source = match req.query::<String>("source") {
+        Some(value) => Some(AuditSource::parse(&value).ok_or_else(|| {
+            "source must be one of matrix_command, discord_command, api, admin_room, cli, scheduled"
+                .to_string()
+        })?),
+        None => None,
+    };
+    Ok(AuditLogFilter {
+        actor: req.query::<String>("actor"),
+        source,
+        action: req.query::<String>("action"),
+        target: req.query::<String>("target"),
+        since: parse_timestamp(req, "since")?,
+        until: parse_timestamp(req, "until")?,
+    })
+}
+
+#[handler]
+pub async fn list_audit_entries(req: &mut Request, res: &mut Response) {
+    let limit = req.query::<i64>("limit").unwrap_or(100).clamp(1, 1000);
+    let offset = req.query::<i64>("offset").unwrap_or(0).max(0);
+    let filter = match parse_filter(req) {
+        Ok(filter) => filter,
+        Err(message) => {
+            render_error(res, StatusCode::BAD_REQUEST, &message);
+            return;
+        }
+    };
+
+    let audit_store = web_state().db_manager.audit_store();
+    let entries = match audit_store.list_audit_entries(&filter, limit, offset).await {
+        Ok(entries) => entries,
+        Err(err) => {
+            render_error(
+                res,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("database error: {}", err),
+            );
+            return;
+        }
+    };
+    match audit_store.count_audit_entries(&filter).await {
+        Ok(total) => {
+            res.render(Json(json!({
+                "entries": entries,
+                "count": entries.len(),
+                "total": total,
+                "limit": limit,
+                "offset": offset,
+            })));
+        }
+        Err(err) => {
+            render_error(
+                res,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("database error: {}", err),
+            );
+        }
+    }
+}
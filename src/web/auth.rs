@@ -0,0 +1,321 @@
+use std::sync::Arc;
+
+use salvo::http::header::AUTHORIZATION;
+use salvo::prelude::*;
+use secrecy::ExposeSecret;
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::config::{AdminApiConfig, ApiScope};
+use crate::db::{ApiTokenStore, DatabaseError};
+use crate::web::metrics::Metrics;
+use crate::web::provisioning::render_error;
+
+const AUTHENTICATED_TOKEN_KEY: &str = "admin_api_token";
+
+/// Hex SHA-256 of a token; this is what the `api_tokens` table stores.
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthenticatedToken {
+    pub name: String,
+    pub scopes: Vec<ApiScope>,
+}
+
+impl AuthenticatedToken {
+    pub fn allows(&self, required: ApiScope) -> bool {
+        self.scopes.iter().any(|scope| scope.grants(required))
+    }
+}
+
+/// The token the current request authenticated with, if any. Unset while the
+/// API is open because no token exists.
+pub fn authenticated_token(depot: &Depot) -> Option<&AuthenticatedToken> {
+    depot
+        .get::<AuthenticatedToken>(AUTHENTICATED_TOKEN_KEY)
+        .ok()
+}
+
+/// Resolves bearer tokens against the config and the `api_tokens` table.
+pub struct ApiAuth {
+    configured: Vec<(String, AuthenticatedToken)>,
+    store: Arc<dyn ApiTokenStore>,
+}
+
+impl ApiAuth {
+    pub fn new(config: &AdminApiConfig, store: Arc<dyn ApiTokenStore>) -> Self {
+        let configured = config
+            .tokens
+            .iter()
+            .map(|token| {
+                (
+                    hash_token(token.token.expose_secret()),
+                    AuthenticatedToken {
+                        name: token.name.clone(),
+                        scopes: token.scopes.clone(),
+                    },
+                )
+            })
+            .collect();
+        Self { configured, store }
+    }
+
+    pub fn configured_tokens(&self) -> impl Iterator<Item = &AuthenticatedToken> {
+        self.configured.iter().map(|(_, token)| token)
+    }
+
+    pub async fn authenticate(
+        &self,
+        token: &str,
+    ) -> Result<Option<AuthenticatedToken>, DatabaseError> {
+        let hash = hash_token(token);
+        if let Some((_, token)) = self.configured.iter().find(|(h, _)| *h == hash) {
+            return Ok(Some(token.clone()));
+        }
+        Ok(self
+            .store
+            .get_api_token_by_hash(&hash)
+            .await?
+            .map(|token| AuthenticatedToken {
+                name: token.name,
+                scopes: token.scopes,
+            }))
+    }
+
+    /// The API accepts anonymous requests until the first token is defined.
+    pub async fn is_open(&self) -> Result<bool, DatabaseError> {
+        Ok(self.configured.is_empty() && self.store.count_api_tokens().await? == 0)
+    }
+}
+
+fn bearer_token(req: &Request) -> Option<String> {
+    if let Some(value) = req.header::<String>(AUTHORIZATION) {
+        return value
+            .strip_prefix("Bearer ")
+            .map(|token| token.trim().to_string());
+    }
+    req.query::<String>("access_token")
+}
+
+/// Middleware that lets a request through only if its token holds a scope
+/// granting `required`.
+pub struct RequireScope {
+    auth: Arc<ApiAuth>,
+    required: ApiScope,
+}
+
+impl RequireScope {
+    pub fn new(auth: Arc<ApiAuth>, required: ApiScope) -> Self {
+        Self { auth, required }
+    }
+
+    fn deny(res: &mut Response, ctrl: &mut FlowCtrl, status: StatusCode, message: &str) {
+        render_error(res, status, message);
+        ctrl.skip_rest();
+    }
+}
+
+#[async_trait]
+impl Handler for RequireScope {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        let Some(presented) = bearer_token(req) else {
+            match self.auth.is_open().await {
+                Ok(true) => {
+                    Metrics::api_request("anonymous", "allowed");
+                    ctrl.call_next(req, depot, res).await;
+                }
+                Ok(false) => {
+                    Metrics::api_request("anonymous", "unauthorized");
+                    Self::deny(res, ctrl, StatusCode::UNAUTHORIZED, "missing access token");
+                }
+                Err(err) => {
+                    warn!("failed to check admin api tokens: {}", err);
+                    Self::deny(
+                        res,
+                        ctrl,
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        &format!("database error: {}", err),
+                    );
+                }
+            }
+            return;
+        };
+
+        let token = match self.auth.authenticate(&presented).await {
+            Ok(Some(token)) => token,
+            Ok(None) => {
+                Metrics::api_request("unknown", "unauthorized");
+                Self::deny(res, ctrl, StatusCode::UNAUTHORIZED, "unknown access token");
+                return;
+            }
+            Err(err) => {
+                warn!("failed to look up admin api token: {}", err);
+                Self::deny(
+                    res,
+                    ctrl,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    &format!("database error: {}", err),
+                );
+                return;
+            }
+        };
+
+        if !token.allows(self.required) {
+            Metrics::api_request(&token.name, "forbidden");
+            Self::deny(
+                res,
+                ctrl,
+                StatusCode::FORBIDDEN,
+                &format!("token lacks the {} scope", self.required.as_str()),
+            );
+            return;
+        }
+
+        Metrics::api_request(&token.name, "allowed");
+        depot.insert(AUTHENTICATED_TOKEN_KEY, token);
+        ctrl.call_next(req, depot, res).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::Utc;
+    use salvo::prelude::*;
+    use salvo::test::{ResponseExt, TestClient};
+
+    use super::{ApiAuth, RequireScope, authenticated_token, hash_token};
+    use crate::config::{AdminApiConfig, ApiScope, ApiTokenConfig};
+    use crate::db::{ApiToken, DatabaseManager};
+
+    #[handler]
+    async fn whoami(depot: &mut Depot) -> String {
+        authenticated_token(depot)
+            .map(|token| token.name.clone())
+            .unwrap_or_else(|| "anonymous".to_string())
+    }
+
+    // Same path, different scope per method, as in the admin routes.
+    fn router(auth: Arc<ApiAuth>) -> Service {
+        Service::new(
+            Router::new()
+                .push(
+                    Router::with_path("item")
+                        .hoop(RequireScope::new(auth.clone(), ApiScope::ReadOnly))
+                        .get(whoami),
+                )
+                .push(
+                    Router::with_path("item")
+                        .hoop(RequireScope::new(auth, ApiScope::Provision))
+                        .delete(whoami),
+                ),
+        )
+    }
+
+    async fn manager(dir: &tempfile::TempDir) -> DatabaseManager {
+        let path = dir.path().join("auth.db");
+        let manager = DatabaseManager::new(&crate::config::DatabaseConfig {
+            url: Some(format!("sqlite://{}", path.display())),
+            conn_string: None,
+            filename: None,
+            user_store_path: None,
+            room_store_path: None,
+            max_connections: None,
+            min_connections: None,
+            max_blocking_threads: None,
+        })
+        .await
+        .unwrap();
+        manager.migrate().await.unwrap();
+        manager
+    }
+
+    async fn call(service: &Service, method: &str, token: Option<&str>) -> (u16, String) {
+        let url = "http://127.0.0.1/item";
+        let mut request = match method {
+            "DELETE" => TestClient::delete(url),
+            _ => TestClient::get(url),
+        };
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        let mut res = request.send(service).await;
+        let status = res.status_code.map(|s| s.as_u16()).unwrap_or(200);
+        (status, res.take_string().await.unwrap_or_default())
+    }
+
+    #[test]
+    fn admin_scope_grants_everything_and_every_scope_grants_read() {
+        assert!(ApiScope::Admin.grants(ApiScope::Moderate));
+        assert!(ApiScope::Moderate.grants(ApiScope::ReadOnly));
+        assert!(!ApiScope::Moderate.grants(ApiScope::Provision));
+        assert!(!ApiScope::ReadOnly.grants(ApiScope::Provision));
+    }
+
+    #[tokio::test]
+    async fn api_is_open_until_a_token_exists() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = manager(&dir).await;
+        let auth = Arc::new(ApiAuth::new(
+            &AdminApiConfig::default(),
+            db.api_token_store(),
+        ));
+        let service = router(auth);
+
+        assert_eq!(
+            call(&service, "DELETE", None).await,
+            (200, "anonymous".to_string())
+        );
+
+        db.api_token_store()
+            .create_api_token(&ApiToken {
+                id: 0,
+                name: "ops".to_string(),
+                token_hash: hash_token("db-secret"),
+                scopes: vec![ApiScope::Provision],
+                created_at: Utc::now(),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(call(&service, "DELETE", None).await.0, 401);
+        assert_eq!(
+            call(&service, "DELETE", Some("db-secret")).await,
+            (200, "ops".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn configured_tokens_are_limited_to_their_scopes() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = manager(&dir).await;
+        let config = AdminApiConfig {
+            tokens: vec![ApiTokenConfig {
+                name: "dashboard".to_string(),
+                token: "read-secret".into(),
+                scopes: vec![ApiScope::ReadOnly],
+            }],
+            ..AdminApiConfig::default()
+        };
+        let service = router(Arc::new(ApiAuth::new(&config, db.api_token_store())));
+
+        assert_eq!(
+            call(&service, "GET", Some("read-secret")).await,
+            (200, "dashboard".to_string())
+        );
+        assert_eq!(call(&service, "DELETE", Some("read-secret")).await.0, 403);
+        assert_eq!(call(&service, "GET", Some("wrong")).await.0, 401);
+    }
+}
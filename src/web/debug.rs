@@ -0,0 +1,41 @@
+use salvo::prelude::*;
+use serde_json::json;
+
+use crate::utils::event_log::{self, EventSource};
+use crate::web::provisioning::render_error;
+
+/// The newest raw events from `event_log`, optionally from one `source`.
+/// Only served while `admin_api.debug_events` is set.
+#[handler]
+pub async fn list_debug_events(req: &mut Request, res: &mut Response) {
+    if !event_log::is_enabled() {
+        render_error(
+            res,
+            StatusCode::NOT_FOUND,
+            "event recording is off; set admin_api.debug_events to enable it",
+        );
+        return;
+    }
+    let limit = req.query::<usize>("limit").unwrap_or(50).clamp(1, 1000);
+    let source = match req.query::<String>("source") {
+        Some(value) => match EventSource::parse(&value) {
+            Some(source) => Some(source),
+            None => {
+                render_error(
+                    res,
+                    StatusCode::BAD_REQUEST,
+                    "source must be one of discord, matrix",
+                );
+                return;
+            }
+        },
+        None => None,
+    };
+
+    let events = event_log::recent(source, limit);
+    res.render(Json(json!({
+        "events": events,
+        "count": events.len(),
+        "limit": limit,
+    })));
+}
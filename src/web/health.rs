@@ -1,11 +1,38 @@
+use chrono::{DateTime, Utc};
 use salvo::prelude::*;
+use serde::Serialize;
 use serde_json::json;
+use tracing::error;
 
+use crate::db::DeliveryDirection;
+use crate::web::provisioning::render_error;
 use crate::web::web_state;
 
+/// Reports "ok" while every supervised background task is running and
+/// answers 503 with "degraded" while any of them is waiting to restart.
 #[handler]
 pub async fn health_check(res: &mut Response) {
-    res.render("OK");
+    let bridge = &web_state().bridge;
+    let healthy = bridge.tasks_healthy();
+    if !healthy {
+        res.status_code(StatusCode::SERVICE_UNAVAILABLE);
+    }
+    res.render(Json(json!({
+        "status": if healthy { "ok" } else { "degraded" },
+        "tasks": bridge.task_statuses(),
+        "missing_intents": missing_intent_names(),
+    })));
+}
+
+/// Privileged Discord intents the bridge runs without; the features needing
+/// them are turned off rather than failing.
+fn missing_intent_names() -> Vec<&'static str> {
+    web_state()
+        .bridge
+        .missing_intents()
+        .into_iter()
+        .map(|intent| intent.as_str())
+        .collect()
 }
 
 #[handler]
@@ -19,8 +46,137 @@ pub async fn get_status(res: &mut Response) {
         "uptime_seconds": uptime_seconds,
         "bridge": {
             "domain": state.matrix_client.registration_preview().get("url"),
-        }
+        },
+        "tasks": state.bridge.task_statuses(),
+        "maintenance_since": state.bridge.maintenance_since(),
+        "missing_intents": missing_intent_names(),
     });
 
     res.render(Json(status));
 }
+
+/// The non-sensitive subset of the bridge state, safe to show publicly.
+#[derive(Debug, Serialize)]
+struct PublicStatus {
+    version: &'static str,
+    uptime_seconds: u64,
+    maintenance: bool,
+    connected_guilds: usize,
+    bridged_rooms: i64,
+    last_bridged: LastBridged,
+}
+
+#[derive(Debug, Serialize)]
+struct LastBridged {
+    matrix_to_discord: Option<DateTime<Utc>>,
+    discord_to_matrix: Option<DateTime<Utc>>,
+}
+
+/// Failures are logged and reported as a bare "status unavailable", since
+/// anyone can call the public endpoints.
+async fn public_status() -> Result<PublicStatus, &'static str> {
+    let state = web_state();
+    let bridged_rooms = state
+        .db_manager
+        .room_store()
+        .count_rooms()
+        .await
+        .map_err(|err| {
+            error!(
+                "failed to count bridged rooms for the public status: {}",
+                err
+            );
+            "status unavailable"
+        })?;
+    Ok(PublicStatus {
+        version: env!("CARGO_PKG_VERSION"),
+        uptime_seconds: state.started_at.elapsed().as_secs(),
+        maintenance: state.bridge.maintenance_since().is_some(),
+        connected_guilds: state.bridge.connected_guild_count(),
+        bridged_rooms,
+        last_bridged: LastBridged {
+            matrix_to_discord: state
+                .bridge
+                .last_bridged_at(DeliveryDirection::MatrixToDiscord),
+            discord_to_matrix: state
+                .bridge
+                .last_bridged_at(DeliveryDirection::DiscordToMatrix),
+        },
+    })
+}
+
+/// Unauthenticated summary for community status pages; CORS is open so
+/// other sites can fetch it.
+#[handler]
+pub async fn public_status_json(res: &mut Response) {
+    res.add_header("Access-Control-Allow-Origin", "*", true)
+        .ok();
+    match public_status().await {
+        Ok(status) => res.render(Json(status)),
+        Err(message) => render_error(res, StatusCode::INTERNAL_SERVER_ERROR, message),
+    }
+}
+
+#[handler]
+pub async fn public_status_page(res: &mut Response) {
+    match public_status().await {
+        Ok(status) => res.render(Text::Html(render_status_page(&status))),
+        Err(message) => render_error(res, StatusCode::INTERNAL_SERVER_ERROR, message),
+    }
+}
+
+fn render_status_page(status: &PublicStatus) -> String {
+    let last = |at: Option<DateTime<Utc>>| {
+        at.map(|at| at.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+            .unwrap_or_else(|| "never".to_string())
+    };
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Bridge status</title></head>\n\
+         <body><h1>Matrix &harr; Discord bridge</h1><dl>\n\
+         <dt>State</dt><dd>{}</dd>\n\
+         <dt>Version</dt><dd>{}</dd>\n\
+         <dt>Uptime</dt><dd>{}s</dd>\n\
+         <dt>Connected guilds</dt><dd>{}</dd>\n\
+         <dt>Bridged rooms</dt><dd>{}</dd>\n\
+         <dt>Last Matrix &rarr; Discord</dt><dd>{}</dd>\n\
+         <dt>Last Discord &rarr; Matrix</dt><dd>{}</dd>\n\
+         </dl></body></html>\n",
+        if status.maintenance {
+            "maintenance"
+        } else {
+            "running"
+        },
+        status.version,
+        status.uptime_seconds,
+        status.connected_guilds,
+        status.bridged_rooms,
+        last(status.last_bridged.matrix_to_discord),
+        last(status.last_bridged.discord_to_matrix),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::{LastBridged, PublicStatus, render_status_page};
+
+    #[test]
+    fn status_page_shows_counts_and_last_bridged_times() {
+        let page = render_status_page(&PublicStatus {
+            version: "1.0.0",
+            uptime_seconds: 42,
+            maintenance: false,
+            connected_guilds: 3,
+            bridged_rooms: 7,
+            last_bridged: LastBridged {
+                matrix_to_discord: Some(Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap()),
+                discord_to_matrix: None,
+            },
+        });
+        assert!(page.contains("<dd>running</dd>"));
+        assert!(page.contains("<dt>Connected guilds</dt><dd>3</dd>"));
+        assert!(page.contains("<dd>2024-05-01 12:00:00 UTC</dd>"));
+        assert!(page.contains("Matrix</dt><dd>never</dd>"));
+    }
+}
@@ -0,0 +1,47 @@
+use salvo::prelude::*;
+use serde_json::json;
+
+use crate::db::AuditSource;
+use crate::web::provisioning::{api_actor, render_error};
+use crate::web::web_state;
+
+#[handler]
+pub async fn get_maintenance(res: &mut Response) {
+    let since = web_state().bridge.maintenance_since();
+    res.render(Json(json!({
+        "enabled": since.is_some(),
+        "since": since,
+    })));
+}
+
+/// Switches maintenance mode with the `enabled=true|false` query. `changed`
+/// is false when the bridge was already in that mode.
+#[handler]
+pub async fn set_maintenance(req: &mut Request, depot: &mut Depot, res: &mut Response) {
+    let Some(enabled) = req.query::<bool>("enabled") else {
+        render_error(
+            res,
+            StatusCode::BAD_REQUEST,
+            "enabled query parameter must be true or false",
+        );
+        return;
+    };
+
+    let actor = api_actor(req, depot);
+    let bridge = &web_state().bridge;
+    match bridge
+        .set_maintenance(enabled, &actor, AuditSource::Api)
+        .await
+    {
+        Ok(changed) => res.render(Json(json!({
+            "enabled": enabled,
+            "changed": changed,
+            "since": bridge.maintenance_since(),
+        }))),
+        Err(err) => render_error(
+            res,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            &format!("failed to switch maintenance mode: {}", err),
+        ),
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+
+use salvo::prelude::*;
+use serde_json::{Value, json};
+
+use crate::db::{MessageMapping, MessageMappingFilter};
+use crate::web::audit::parse_timestamp;
+use crate::web::provisioning::render_error;
+use crate::web::web_state;
+
+fn parse_filter(req: &Request) -> Result<MessageMappingFilter, String> {
+    Ok(MessageMappingFilter {
+        matrix_room_id: req.query::<String>("room"),
+        since: parse_timestamp(req, "since")?,
+        until: parse_timestamp(req, "until")?,
+    })
+}
+
+/// The room mapping for each distinct room in `messages`, keyed by Matrix
+/// room id; rooms that are no longer bridged map to `null`.
+async fn room_info(messages: &[MessageMapping]) -> Result<BTreeMap<String, Value>, String> {
+    let room_store = web_state().db_manager.room_store();
+    let mut rooms = BTreeMap::new();
+    for message in messages {
+        if rooms.contains_key(&message.matrix_room_id) {
+            continue;
+        }
+        let room = room_store
+            .get_room_by_matrix_room(&message.matrix_room_id)
+            .await
+            .map_err(|err| format!("database error: {}", err))?;
+        rooms.insert(message.matrix_room_id.clone(), json!(room));
+    }
+    Ok(rooms)
+}
+
+#[handler]
+pub async fn list_messages(req: &mut Request, res: &mut Response) {
+    let limit = req.query::<i64>("limit").unwrap_or(100).clamp(1, 1000);
+    let offset = req.query::<i64>("offset").unwrap_or(0).max(0);
+    let include_rooms = req.query::<bool>("include_rooms").unwrap_or(false);
+    let filter = match parse_filter(req) {
+        Ok(filter) => filter,
+        Err(message) => {
+            render_error(res, StatusCode::BAD_REQUEST, &message);
+            return;
+        }
+    };
+
+    let message_store = web_state().db_manager.message_store();
+    let messages = match message_store
+        .list_message_mappings(&filter, limit, offset)
+        .await
+    {
+        Ok(messages) => messages,
+        Err(err) => {
+            render_error(
+                res,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("database error: {}", err),
+            );
+            return;
+        }
+    };
+    let total = match message_store.count_message_mappings(&filter).await {
+        Ok(total) => total,
+        Err(err) => {
+            render_error(
+                res,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("database error: {}", err),
+            );
+            return;
+        }
+    };
+
+    let mut body = json!({
+        "messages": messages,
+        "count": messages.len(),
+        "total": total,
+        "limit": limit,
+        "offset": offset,
+    });
+    if include_rooms {
+        match room_info(&messages).await {
+            Ok(rooms) => body["rooms"] = json!(rooms),
+            Err(message) => {
+                render_error(res, StatusCode::INTERNAL_SERVER_ERROR, &message);
+                return;
+            }
+        }
+    }
+    res.render(Json(body));
+}
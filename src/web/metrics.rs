@@ -1,8 +1,13 @@
+use std::collections::BTreeMap;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Instant;
 
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
 use salvo::prelude::*;
 
+use crate::discord::RouteHeadroom;
+
 static MATRIX_MESSAGES_RECEIVED: AtomicU64 = AtomicU64::new(0);
 static MATRIX_MESSAGES_SUCCESS: AtomicU64 = AtomicU64::new(0);
 static MATRIX_MESSAGES_FAILED: AtomicU64 = AtomicU64::new(0);
@@ -21,6 +26,43 @@ static EDITS_PROCESSED: AtomicU64 = AtomicU64::new(0);
 static DELETES_PROCESSED: AtomicU64 = AtomicU64::new(0);
 static ATTACHMENTS_UPLOADED: AtomicU64 = AtomicU64::new(0);
 static EMOJI_CONVERTED: AtomicU64 = AtomicU64::new(0);
+static DB_POOL_CAPACITY: AtomicU64 = AtomicU64::new(0);
+static DB_POOL_ACTIVE: AtomicU64 = AtomicU64::new(0);
+static DB_POOL_QUEUED: AtomicU64 = AtomicU64::new(0);
+static DB_TASKS: AtomicU64 = AtomicU64::new(0);
+/// Admin API requests keyed by (token name, outcome).
+static API_REQUESTS: Lazy<Mutex<BTreeMap<(String, &'static str), u64>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+/// Message delivery outcomes keyed by (direction, outcome).
+static DELIVERIES: Lazy<Mutex<BTreeMap<(&'static str, &'static str), u64>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+/// Successful Discord sends and edits keyed by mechanism.
+static DISCORD_SENDS: Lazy<Mutex<BTreeMap<&'static str, u64>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+/// Failed Discord requests on the send path keyed by (mechanism, reason).
+static DISCORD_SEND_FAILURES: Lazy<Mutex<BTreeMap<(&'static str, &'static str), u64>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+/// Outbound calls skipped by `bridge.dry_run` keyed by (side, operation).
+static DRY_RUN_SKIPPED: Lazy<Mutex<BTreeMap<(&'static str, &'static str), u64>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+/// Content redaction matches keyed by (rule, origin, mode).
+type RedactionKey = (String, &'static str, &'static str);
+static CONTENT_REDACTIONS: Lazy<Mutex<BTreeMap<RedactionKey, u64>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+/// Appservice events dropped before processing keyed by reason.
+static MATRIX_EVENTS_FILTERED: Lazy<Mutex<BTreeMap<&'static str, u64>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+/// Rate-limit headroom on Discord delivery routes keyed by route:
+/// (limit, remaining, seconds until reset).
+type RateLimitState = (i64, i64, f64);
+static DISCORD_RATE_LIMITS: Lazy<Mutex<BTreeMap<&'static str, RateLimitState>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+/// Requests held back by a Discord rate limit keyed by route.
+static DISCORD_RATE_LIMIT_WAITS: Lazy<Mutex<BTreeMap<String, u64>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+/// Supervised background tasks keyed by name: (up, restarts).
+static TASKS: Lazy<Mutex<BTreeMap<&'static str, (bool, u64)>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
 
 pub struct Metrics {
     started_at: Instant,
@@ -107,6 +149,273 @@ impl Metrics {
     pub fn emoji_converted() {
         EMOJI_CONVERTED.fetch_add(1, Ordering::Relaxed);
     }
+
+    pub fn api_request(token: &str, outcome: &'static str) {
+        *API_REQUESTS
+            .lock()
+            .entry((token.to_string(), outcome))
+            .or_default() += 1;
+    }
+
+    /// Counts a delivery outcome: `dropped` in best-effort mode, `queued`,
+    /// `retried` and `abandoned` on the at-least-once path.
+    pub fn delivery(direction: &'static str, outcome: &'static str) {
+        *DELIVERIES.lock().entry((direction, outcome)).or_default() += 1;
+    }
+
+    pub fn matrix_event_filtered(reason: &'static str) {
+        *MATRIX_EVENTS_FILTERED.lock().entry(reason).or_default() += 1;
+    }
+
+    /// Counts an outbound call `bridge.dry_run` logged instead of making.
+    pub fn dry_run_skipped(side: &'static str, operation: &'static str) {
+        *DRY_RUN_SKIPPED.lock().entry((side, operation)).or_default() += 1;
+    }
+
+    /// Counts a message that matched redaction `rule` on its way out of
+    /// `origin`; `mode` is `redacted`, or `test` when it was only logged.
+    pub fn content_redacted(rule: &str, origin: &'static str, mode: &'static str) {
+        *CONTENT_REDACTIONS
+            .lock()
+            .entry((rule.to_string(), origin, mode))
+            .or_default() += 1;
+    }
+
+    /// Counts a message sent or edited through a `webhook` or `direct`ly as
+    /// the bot.
+    pub fn discord_sent(mechanism: &'static str) {
+        *DISCORD_SENDS.lock().entry(mechanism).or_default() += 1;
+    }
+
+    /// Counts a failed send step: `missing_permission`, `not_found`,
+    /// `rate_limited`, `http_error` or `other`. Webhook failures before a
+    /// send fall back to a direct send.
+    pub fn discord_send_failed(mechanism: &'static str, reason: &'static str) {
+        *DISCORD_SEND_FAILURES
+            .lock()
+            .entry((mechanism, reason))
+            .or_default() += 1;
+    }
+
+    /// Replaces the sampled headroom of every Discord delivery route.
+    pub fn set_discord_rate_limits(headroom: &BTreeMap<&'static str, RouteHeadroom>) {
+        *DISCORD_RATE_LIMITS.lock() = headroom
+            .iter()
+            .map(|(route, state)| {
+                (
+                    *route,
+                    (state.limit, state.remaining, state.reset_in.as_secs_f64()),
+                )
+            })
+            .collect();
+    }
+
+    /// Counts a request serenity held back because a rate limit ran out.
+    pub fn discord_rate_limit_wait(route: String) {
+        *DISCORD_RATE_LIMIT_WAITS.lock().entry(route).or_default() += 1;
+    }
+
+    pub fn set_db_pool_capacity(threads: u64) {
+        DB_POOL_CAPACITY.store(threads, Ordering::Relaxed);
+    }
+
+    /// Counts a database call waiting for a thread on the database executor.
+    pub fn db_task_queued() {
+        DB_POOL_QUEUED.fetch_add(1, Ordering::Relaxed);
+        DB_TASKS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn db_task_started() {
+        DB_POOL_QUEUED.fetch_sub(1, Ordering::Relaxed);
+        DB_POOL_ACTIVE.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn db_task_finished() {
+        DB_POOL_ACTIVE.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn set_task_up(task: &'static str, up: bool) {
+        TASKS.lock().entry(task).or_default().0 = up;
+    }
+
+    pub fn task_restarted(task: &'static str) {
+        TASKS.lock().entry(task).or_default().1 += 1;
+    }
+}
+
+fn format_tasks() -> String {
+    let tasks = TASKS.lock();
+    let mut output = String::from(
+        "# HELP bridge_task_up Whether a supervised background task is running\n# TYPE bridge_task_up gauge\n",
+    );
+    for (task, (up, _)) in tasks.iter() {
+        output.push_str(&format!(
+            "bridge_task_up{{task=\"{}\"}} {}\n",
+            task, *up as u8
+        ));
+    }
+    output.push_str(
+        "# HELP bridge_task_restarts_total Restarts of supervised background tasks\n# TYPE bridge_task_restarts_total counter\n",
+    );
+    for (task, (_, restarts)) in tasks.iter() {
+        output.push_str(&format!(
+            "bridge_task_restarts_total{{task=\"{}\"}} {}\n",
+            task, restarts
+        ));
+    }
+    output
+}
+
+fn format_db_pool() -> String {
+    format!(
+        r#"# HELP bridge_db_pool_capacity Threads the database executor may use
+# TYPE bridge_db_pool_capacity gauge
+bridge_db_pool_capacity {}
+# HELP bridge_db_pool_active Database calls running on the executor
+# TYPE bridge_db_pool_active gauge
+bridge_db_pool_active {}
+# HELP bridge_db_pool_queued Database calls waiting for an executor thread
+# TYPE bridge_db_pool_queued gauge
+bridge_db_pool_queued {}
+# HELP bridge_db_tasks_total Database calls submitted to the executor
+# TYPE bridge_db_tasks_total counter
+bridge_db_tasks_total {}
+"#,
+        DB_POOL_CAPACITY.load(Ordering::Relaxed),
+        DB_POOL_ACTIVE.load(Ordering::Relaxed),
+        DB_POOL_QUEUED.load(Ordering::Relaxed),
+        DB_TASKS.load(Ordering::Relaxed),
+    )
+}
+
+fn format_deliveries() -> String {
+    let mut output = String::from(
+        "# HELP bridge_deliveries_total Message delivery outcomes by direction\n# TYPE bridge_deliveries_total counter\n",
+    );
+    for ((direction, outcome), count) in DELIVERIES.lock().iter() {
+        output.push_str(&format!(
+            "bridge_deliveries_total{{direction=\"{}\",outcome=\"{}\"}} {}\n",
+            direction, outcome, count
+        ));
+    }
+    output
+}
+
+fn format_dry_run() -> String {
+    let mut output = String::from(
+        "# HELP bridge_dry_run_skipped_total Outbound calls skipped in dry-run mode\n# TYPE bridge_dry_run_skipped_total counter\n",
+    );
+    for ((side, operation), count) in DRY_RUN_SKIPPED.lock().iter() {
+        output.push_str(&format!(
+            "bridge_dry_run_skipped_total{{side=\"{}\",operation=\"{}\"}} {}\n",
+            side, operation, count
+        ));
+    }
+    output
+}
+
+fn format_discord_rate_limits() -> String {
+    let limits = DISCORD_RATE_LIMITS.lock();
+    let mut output = String::from(
+        "# HELP bridge_discord_ratelimit_remaining Requests left in the tightest bucket of a Discord route\n# TYPE bridge_discord_ratelimit_remaining gauge\n",
+    );
+    for (route, (_, remaining, _)) in limits.iter() {
+        output.push_str(&format!(
+            "bridge_discord_ratelimit_remaining{{route=\"{}\"}} {}\n",
+            route, remaining
+        ));
+    }
+    output.push_str(
+        "# HELP bridge_discord_ratelimit_limit Size of the tightest bucket of a Discord route\n# TYPE bridge_discord_ratelimit_limit gauge\n",
+    );
+    for (route, (limit, _, _)) in limits.iter() {
+        output.push_str(&format!(
+            "bridge_discord_ratelimit_limit{{route=\"{}\"}} {}\n",
+            route, limit
+        ));
+    }
+    output.push_str(
+        "# HELP bridge_discord_ratelimit_reset_seconds Seconds until the tightest bucket of a Discord route refills\n# TYPE bridge_discord_ratelimit_reset_seconds gauge\n",
+    );
+    for (route, (_, _, reset)) in limits.iter() {
+        output.push_str(&format!(
+            "bridge_discord_ratelimit_reset_seconds{{route=\"{}\"}} {:.3}\n",
+            route, reset
+        ));
+    }
+    output.push_str(
+        "# HELP bridge_discord_ratelimit_waits_total Discord requests held back by a rate limit\n# TYPE bridge_discord_ratelimit_waits_total counter\n",
+    );
+    for (route, count) in DISCORD_RATE_LIMIT_WAITS.lock().iter() {
+        output.push_str(&format!(
+            "bridge_discord_ratelimit_waits_total{{route=\"{}\"}} {}\n",
+            route, count
+        ));
+    }
+    output
+}
+
+fn format_matrix_events_filtered() -> String {
+    let mut output = String::from(
+        "# HELP bridge_matrix_events_filtered_total Appservice events dropped before processing\n# TYPE bridge_matrix_events_filtered_total counter\n",
+    );
+    for (reason, count) in MATRIX_EVENTS_FILTERED.lock().iter() {
+        output.push_str(&format!(
+            "bridge_matrix_events_filtered_total{{reason=\"{}\"}} {}\n",
+            reason, count
+        ));
+    }
+    output
+}
+
+fn format_content_redactions() -> String {
+    let mut output = String::from(
+        "# HELP bridge_content_redactions_total Messages matching a content redaction rule\n# TYPE bridge_content_redactions_total counter\n",
+    );
+    for ((rule, origin, mode), count) in CONTENT_REDACTIONS.lock().iter() {
+        output.push_str(&format!(
+            "bridge_content_redactions_total{{rule=\"{}\",origin=\"{}\",mode=\"{}\"}} {}\n",
+            rule, origin, mode, count
+        ));
+    }
+    output
+}
+
+fn format_discord_sends() -> String {
+    let mut output = String::from(
+        "# HELP bridge_discord_sends_total Messages sent to Discord by mechanism\n# TYPE bridge_discord_sends_total counter\n",
+    );
+    for (mechanism, count) in DISCORD_SENDS.lock().iter() {
+        output.push_str(&format!(
+            "bridge_discord_sends_total{{mechanism=\"{}\"}} {}\n",
+            mechanism, count
+        ));
+    }
+    output.push_str(
+        "# HELP bridge_discord_send_failures_total Failed Discord sends by mechanism and reason\n# TYPE bridge_discord_send_failures_total counter\n",
+    );
+    for ((mechanism, reason), count) in DISCORD_SEND_FAILURES.lock().iter() {
+        output.push_str(&format!(
+            "bridge_discord_send_failures_total{{mechanism=\"{}\",reason=\"{}\"}} {}\n",
+            mechanism, reason, count
+        ));
+    }
+    output
+}
+
+fn format_api_requests() -> String {
+    let mut output = String::from(
+        "# HELP admin_api_requests_total Admin API requests by token and outcome\n# TYPE admin_api_requests_total counter\n",
+    );
+    for ((token, outcome), count) in API_REQUESTS.lock().iter() {
+        output.push_str(&format!(
+            "admin_api_requests_total{{token=\"{}\",outcome=\"{}\"}} {}\n",
+            token.replace('\\', "\\\\").replace('"', "\\\""),
+            outcome,
+            count
+        ));
+    }
+    output
 }
 
 pub fn format_prometheus() -> String {
@@ -143,7 +452,7 @@ pub fn format_prometheus() -> String {
         0.0
     };
 
-    format!(
+    let mut output = format!(
         r#"# HELP bridge_uptime_seconds Number of seconds the bridge has been running
 # TYPE bridge_uptime_seconds gauge
 bridge_uptime_seconds {}
@@ -239,7 +548,22 @@ emoji_converted_total {}
         deletes,
         attachments,
         emoji,
-    )
+    );
+    output.push('\n');
+    output.push_str(&format_api_requests());
+    output.push('\n');
+    output.push_str(&format_deliveries());
+    output.push_str(&format_dry_run());
+    output.push_str(&format_matrix_events_filtered());
+    output.push_str(&format_discord_rate_limits());
+    output.push_str(&format_content_redactions());
+    output.push('\n');
+    output.push_str(&format_discord_sends());
+    output.push('\n');
+    output.push_str(&format_tasks());
+    output.push('\n');
+    output.push_str(&format_db_pool());
+    output
 }
 
 #[handler]
@@ -265,6 +589,8 @@ mod tests {
         Metrics::delete_processed();
         Metrics::attachment_uploaded();
         Metrics::emoji_converted();
+        Metrics::api_request("metrics-test", "allowed");
+        Metrics::api_request("metrics-test", "allowed");
 
         assert_eq!(MATRIX_MESSAGES_RECEIVED.load(Ordering::Relaxed), 1);
         assert_eq!(MATRIX_MESSAGES_SUCCESS.load(Ordering::Relaxed), 1);
@@ -276,6 +602,10 @@ mod tests {
         assert_eq!(DELETES_PROCESSED.load(Ordering::Relaxed), 1);
         assert_eq!(ATTACHMENTS_UPLOADED.load(Ordering::Relaxed), 1);
         assert_eq!(EMOJI_CONVERTED.load(Ordering::Relaxed), 1);
+        assert!(
+            format_prometheus()
+                .contains("admin_api_requests_total{token=\"metrics-test\",outcome=\"allowed\"} 2")
+        );
     }
 
     #[test]
@@ -294,5 +624,50 @@ mod tests {
         assert!(output.contains("deletes_processed_total"));
         assert!(output.contains("attachments_uploaded_total"));
         assert!(output.contains("emoji_converted_total"));
+        assert!(output.contains("bridge_deliveries_total"));
+        assert!(output.contains("bridge_db_pool_queued"));
+        assert!(output.contains("bridge_dry_run_skipped_total"));
+        assert!(output.contains("bridge_content_redactions_total"));
+        assert!(output.contains("bridge_matrix_events_filtered_total"));
+        assert!(output.contains("bridge_discord_ratelimit_remaining"));
+    }
+
+    #[test]
+    fn delivery_outcomes_are_labelled_by_direction() {
+        Metrics::delivery("discord_to_matrix", "dropped");
+        Metrics::delivery("discord_to_matrix", "dropped");
+        Metrics::delivery("matrix_to_discord", "queued");
+
+        let output = format_prometheus();
+        assert!(output.contains(
+            "bridge_deliveries_total{direction=\"discord_to_matrix\",outcome=\"dropped\"} 2"
+        ));
+        assert!(output.contains(
+            "bridge_deliveries_total{direction=\"matrix_to_discord\",outcome=\"queued\"} 1"
+        ));
+    }
+
+    #[test]
+    fn discord_sends_are_labelled_by_mechanism_and_reason() {
+        Metrics::discord_sent("webhook");
+        Metrics::discord_send_failed("webhook", "missing_permission");
+        Metrics::discord_sent("direct");
+
+        let output = format_prometheus();
+        assert!(output.contains("bridge_discord_sends_total{mechanism=\"direct\"}"));
+        assert!(output.contains(
+            "bridge_discord_send_failures_total{mechanism=\"webhook\",reason=\"missing_permission\"}"
+        ));
+    }
+
+    #[test]
+    fn task_status_is_labelled_by_task() {
+        Metrics::set_task_up("metrics_test_task", true);
+        Metrics::task_restarted("metrics_test_task");
+        Metrics::set_task_up("metrics_test_task", false);
+
+        let output = format_prometheus();
+        assert!(output.contains("bridge_task_up{task=\"metrics_test_task\"} 0"));
+        assert!(output.contains("bridge_task_restarts_total{task=\"metrics_test_task\"} 1"));
     }
 }
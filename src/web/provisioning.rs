@@ -2,14 +2,27 @@ use chrono::Utc;
 use salvo::prelude::*;
 use serde_json::json;
 
-use crate::db::RoomMapping;
+use crate::db::{AuditSource, RoomMapping, RoomOrigin};
+use crate::web::auth::authenticated_token;
 use crate::web::web_state;
 
-fn render_error(res: &mut Response, status: StatusCode, message: &str) {
+pub(super) fn render_error(res: &mut Response, status: StatusCode, message: &str) {
     res.status_code(status);
     res.render(Json(json!({ "error": message })));
 }
 
+/// Who to attribute an API action to: the `user_id` query parameter when
+/// the caller sets one, else the name of the token used, else the API itself.
+pub(super) fn api_actor(req: &Request, depot: &Depot) -> String {
+    if let Some(user_id) = req.query::<String>("user_id").filter(|v| !v.is_empty()) {
+        return user_id;
+    }
+    match authenticated_token(depot) {
+        Some(token) => format!("token:{}", token.name),
+        None => "api".to_string(),
+    }
+}
+
 #[handler]
 pub async fn list_rooms(req: &mut Request, res: &mut Response) {
     let limit = req.query::<i64>("limit").unwrap_or(100).clamp(1, 1000);
@@ -40,7 +53,7 @@ pub async fn list_rooms(req: &mut Request, res: &mut Response) {
 }
 
 #[handler]
-pub async fn create_bridge(req: &mut Request, res: &mut Response) {
+pub async fn create_bridge(req: &mut Request, depot: &mut Depot, res: &mut Response) {
     let matrix_room_id = match req.query::<String>("matrix_room_id") {
         Some(v) if !v.is_empty() => v,
         _ => {
@@ -70,10 +83,29 @@ pub async fn create_bridge(req: &mut Request, res: &mut Response) {
     let bridge = web_state().bridge.clone();
 
     match bridge
-        .bridge_matrix_room(&matrix_room_id, &discord_guild_id, &discord_channel_id)
+        .bridge_matrix_room(
+            &matrix_room_id,
+            &discord_guild_id,
+            &discord_channel_id,
+            RoomOrigin::ProvisioningApi,
+            &api_actor(req, depot),
+        )
         .await
     {
         Ok(reply) => {
+            bridge
+                .record_audit(
+                    &api_actor(req, depot),
+                    AuditSource::Api,
+                    "bridge",
+                    Some(&matrix_room_id),
+                    json!({
+                        "guild_id": discord_guild_id,
+                        "channel_id": discord_channel_id,
+                        "result": reply,
+                    }),
+                )
+                .await;
             if reply.contains("problem") || reply.contains("already") {
                 render_error(res, StatusCode::BAD_REQUEST, &reply);
             } else {
@@ -91,7 +123,7 @@ pub async fn create_bridge(req: &mut Request, res: &mut Response) {
 }
 
 #[handler]
-pub async fn delete_bridge(req: &mut Request, res: &mut Response) {
+pub async fn delete_bridge(req: &mut Request, depot: &mut Depot, res: &mut Response) {
     let id = match req.param::<i64>("id") {
         Some(v) if v > 0 => v,
         _ => {
@@ -114,12 +146,18 @@ pub async fn delete_bridge(req: &mut Request, res: &mut Response) {
         }
     };
 
-    match web_state()
-        .bridge
-        .unbridge_matrix_room(&mapping.matrix_room_id)
-        .await
-    {
+    let bridge = web_state().bridge.clone();
+    match bridge.unbridge_matrix_room(&mapping.matrix_room_id).await {
         Ok(reply) => {
+            bridge
+                .record_audit(
+                    &api_actor(req, depot),
+                    AuditSource::Api,
+                    "unbridge",
+                    Some(&mapping.matrix_room_id),
+                    json!({ "bridge_id": id, "result": reply }),
+                )
+                .await;
             res.render(Json(json!({ "ok": true, "message": reply })));
         }
         Err(err) => {
@@ -154,3 +192,72 @@ pub async fn get_bridge_info(req: &mut Request, res: &mut Response) {
         }
     }
 }
+
+/// Where Matrix users read up to in a bridged room, with the Discord
+/// message each marker's event was bridged from or to when there is one.
+#[handler]
+pub async fn get_read_markers(req: &mut Request, res: &mut Response) {
+    let matrix_room_id = match req.query::<String>("matrix_room_id") {
+        Some(v) if !v.is_empty() => v,
+        _ => {
+            render_error(
+                res,
+                StatusCode::BAD_REQUEST,
+                "missing matrix_room_id query parameter",
+            );
+            return;
+        }
+    };
+
+    let state = web_state();
+    match state
+        .db_manager
+        .room_store()
+        .get_room_by_matrix_room(&matrix_room_id)
+        .await
+    {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            render_error(res, StatusCode::NOT_FOUND, "room is not bridged");
+            return;
+        }
+        Err(err) => {
+            render_error(
+                res,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("database error: {}", err),
+            );
+            return;
+        }
+    }
+
+    let mut markers = Vec::new();
+    for (user_id, marker) in state.bridge.read_markers(&matrix_room_id) {
+        let discord_message_id = match state
+            .db_manager
+            .message_store()
+            .get_by_matrix_event_id(&marker.event_id)
+            .await
+        {
+            Ok(mapping) => mapping.map(|mapping| mapping.discord_message_id),
+            Err(err) => {
+                render_error(
+                    res,
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    &format!("database error: {}", err),
+                );
+                return;
+            }
+        };
+        markers.push(json!({
+            "user_id": user_id,
+            "event_id": marker.event_id,
+            "ts": marker.ts,
+            "discord_message_id": discord_message_id,
+        }));
+    }
+    res.render(Json(json!({
+        "matrix_room_id": matrix_room_id,
+        "read_markers": markers,
+    })));
+}
@@ -0,0 +1,72 @@
+use salvo::prelude::*;
+use serde_json::json;
+use tracing::error;
+
+use crate::bridge::replay::parse_since;
+use crate::db::AuditSource;
+use crate::web::provisioning::{api_actor, render_error};
+use crate::web::web_state;
+
+/// Re-bridges the Discord messages of the `room` query sent since `since`
+/// (RFC 3339 or Unix seconds) that never reached Matrix. Reading the history
+/// can take a while, so this returns 202 and the outcome is written to the
+/// audit log.
+#[handler]
+pub async fn replay_messages(req: &mut Request, depot: &mut Depot, res: &mut Response) {
+    let Some(room) = req.query::<String>("room") else {
+        render_error(
+            res,
+            StatusCode::BAD_REQUEST,
+            "room query parameter is required",
+        );
+        return;
+    };
+    let Some(since) = req
+        .query::<String>("since")
+        .as_deref()
+        .and_then(parse_since)
+    else {
+        render_error(
+            res,
+            StatusCode::BAD_REQUEST,
+            "since query parameter must be an RFC 3339 time or Unix seconds",
+        );
+        return;
+    };
+
+    let state = web_state();
+    match state
+        .db_manager
+        .room_store()
+        .get_room_by_matrix_room(&room)
+        .await
+    {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            render_error(res, StatusCode::NOT_FOUND, "room is not bridged");
+            return;
+        }
+        Err(err) => {
+            render_error(
+                res,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("database error: {}", err),
+            );
+            return;
+        }
+    }
+
+    let actor = api_actor(req, depot);
+    tokio::spawn(async move {
+        if let Err(err) = state
+            .bridge
+            .replay_discord_messages(&room, since, &actor, AuditSource::Api)
+            .await
+        {
+            error!("failed to replay discord messages for {}: {}", room, err);
+        }
+    });
+
+    res.status_code(StatusCode::ACCEPTED);
+    res.render(Json(json!({ "status": "replaying", "since": since })));
+}
@@ -0,0 +1,82 @@
+use salvo::prelude::*;
+use serde_json::json;
+use tracing::warn;
+
+use crate::utils::snowflake;
+use crate::web::provisioning::render_error;
+use crate::web::web_state;
+
+/// Maps a Discord id to its Matrix counterpart: `discord_user=<id>` gives
+/// the ghost MXID, and `discord_channel=<id>` the bridged room with its
+/// alias.
+#[handler]
+pub async fn resolve_ids(req: &mut Request, res: &mut Response) {
+    let user = req.query::<String>("discord_user");
+    let channel = req.query::<String>("discord_channel");
+    match (user, channel) {
+        (Some(user), None) => resolve_user(&user, res).await,
+        (None, Some(channel)) => resolve_channel(&channel, res).await,
+        _ => render_error(
+            res,
+            StatusCode::BAD_REQUEST,
+            "pass exactly one of discord_user or discord_channel",
+        ),
+    }
+}
+
+async fn resolve_user(discord_user_id: &str, res: &mut Response) {
+    if !snowflake::is_valid(discord_user_id) {
+        render_error(res, StatusCode::BAD_REQUEST, "invalid discord_user id");
+        return;
+    }
+    res.render(Json(json!({
+        "discord_user": discord_user_id,
+        "ghost_user_id": web_state().matrix_client.ghost_user_id(discord_user_id),
+    })));
+}
+
+async fn resolve_channel(discord_channel_id: &str, res: &mut Response) {
+    if !snowflake::is_valid(discord_channel_id) {
+        render_error(res, StatusCode::BAD_REQUEST, "invalid discord_channel id");
+        return;
+    }
+    let state = web_state();
+    let mapping = match state
+        .db_manager
+        .room_store()
+        .get_room_by_discord_channel(discord_channel_id)
+        .await
+    {
+        Ok(Some(mapping)) => mapping,
+        Ok(None) => {
+            render_error(res, StatusCode::NOT_FOUND, "channel is not bridged");
+            return;
+        }
+        Err(err) => {
+            render_error(
+                res,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("database error: {}", err),
+            );
+            return;
+        }
+    };
+
+    // Rooms bridged into an existing Matrix room have no portal alias, so
+    // it is only reported when it points at the bridged room.
+    let alias = state.matrix_client.portal_room_alias(discord_channel_id);
+    let alias = match state.matrix_client.resolve_room_alias(&alias).await {
+        Ok(Some(room_id)) if room_id == mapping.matrix_room_id => Some(alias),
+        Ok(_) => None,
+        Err(err) => {
+            warn!("{}", err);
+            None
+        }
+    };
+    res.render(Json(json!({
+        "discord_channel": discord_channel_id,
+        "discord_guild": mapping.discord_guild_id,
+        "room_id": mapping.matrix_room_id,
+        "room_alias": alias,
+    })));
+}
@@ -0,0 +1,60 @@
+use chrono::{Days, Utc};
+use salvo::prelude::*;
+use serde_json::json;
+use tracing::warn;
+
+use crate::db::EmojiUsageKind;
+use crate::emoji::summarize_emoji_usage;
+use crate::web::provisioning::render_error;
+use crate::web::web_state;
+
+/// Custom emoji and sticker use over the last `days` UTC days (today
+/// included), most used first. `kind` narrows it to `emoji` or `sticker`.
+#[handler]
+pub async fn emoji_stats(req: &mut Request, res: &mut Response) {
+    let days = req.query::<u64>("days").unwrap_or(30).clamp(1, 366);
+    let limit = req.query::<usize>("limit").unwrap_or(100).clamp(1, 1000);
+    let kind = match req.query::<String>("kind") {
+        Some(kind) => match EmojiUsageKind::parse(&kind) {
+            Some(kind) => Some(kind),
+            None => {
+                render_error(
+                    res,
+                    StatusCode::BAD_REQUEST,
+                    "kind must be emoji or sticker",
+                );
+                return;
+            }
+        },
+        None => None,
+    };
+
+    let state = web_state();
+    if let Err(err) = state.bridge.flush_emoji_usage().await {
+        warn!("failed to save emoji usage before reporting: {}", err);
+    }
+
+    let since = Utc::now().date_naive() - Days::new(days - 1);
+    let usage = match state.db_manager.emoji_store().list_emoji_usage(since).await {
+        Ok(usage) => usage,
+        Err(err) => {
+            render_error(
+                res,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("database error: {}", err),
+            );
+            return;
+        }
+    };
+
+    let mut emoji = summarize_emoji_usage(&usage);
+    if let Some(kind) = kind {
+        emoji.retain(|summary| summary.kind == kind);
+    }
+    emoji.truncate(limit);
+    res.render(Json(json!({
+        "since": since.format("%Y-%m-%d").to_string(),
+        "days": days,
+        "emoji": emoji,
+    })));
+}
@@ -0,0 +1,170 @@
+use chrono::Utc;
+use salvo::prelude::*;
+use serde_json::json;
+
+use crate::cli::generate_token;
+use crate::config::ApiScope;
+use crate::db::{ApiToken, AuditSource};
+use crate::web::auth::hash_token;
+use crate::web::provisioning::{api_actor, render_error};
+use crate::web::web_state;
+
+#[handler]
+pub async fn list_tokens(res: &mut Response) {
+    let configured: Vec<_> = web_state()
+        .api_auth
+        .configured_tokens()
+        .map(|token| json!({ "name": token.name, "scopes": token.scopes, "source": "config" }))
+        .collect();
+
+    match web_state()
+        .db_manager
+        .api_token_store()
+        .list_api_tokens()
+        .await
+    {
+        Ok(stored) => {
+            let stored = stored.into_iter().map(|token| {
+                json!({
+                    "name": token.name,
+                    "scopes": token.scopes,
+                    "source": "database",
+                    "created_at": token.created_at,
+                })
+            });
+            res.render(Json(json!({
+                "tokens": configured.into_iter().chain(stored).collect::<Vec<_>>(),
+            })));
+        }
+        Err(err) => {
+            render_error(
+                res,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("database error: {}", err),
+            );
+        }
+    }
+}
+
+/// Creates a database token from `name` and a comma-separated `scopes`
+/// query. The token is only ever returned in this response.
+#[handler]
+pub async fn create_token(req: &mut Request, depot: &mut Depot, res: &mut Response) {
+    let name = match req.query::<String>("name") {
+        Some(v) if !v.is_empty() => v,
+        _ => {
+            render_error(res, StatusCode::BAD_REQUEST, "missing name query parameter");
+            return;
+        }
+    };
+    let raw_scopes = req.query::<String>("scopes").unwrap_or_default();
+    let mut scopes = Vec::new();
+    for scope in raw_scopes.split(',').filter(|s| !s.is_empty()) {
+        match ApiScope::parse(scope) {
+            Some(scope) => scopes.push(scope),
+            None => {
+                render_error(
+                    res,
+                    StatusCode::BAD_REQUEST,
+                    &format!("unknown scope {scope:?}"),
+                );
+                return;
+            }
+        }
+    }
+    if scopes.is_empty() {
+        render_error(
+            res,
+            StatusCode::BAD_REQUEST,
+            "scopes must list at least one of read_only, provision, moderate, admin",
+        );
+        return;
+    }
+    if web_state()
+        .api_auth
+        .configured_tokens()
+        .any(|token| token.name == name)
+    {
+        render_error(
+            res,
+            StatusCode::CONFLICT,
+            "a token with that name is defined in the config",
+        );
+        return;
+    }
+
+    let secret = generate_token();
+    let token = ApiToken {
+        id: 0,
+        name: name.clone(),
+        token_hash: hash_token(&secret),
+        scopes: scopes.clone(),
+        created_at: Utc::now(),
+    };
+    if let Err(err) = web_state()
+        .db_manager
+        .api_token_store()
+        .create_api_token(&token)
+        .await
+    {
+        render_error(res, StatusCode::CONFLICT, &err.to_string());
+        return;
+    }
+
+    web_state()
+        .bridge
+        .record_audit(
+            &api_actor(req, depot),
+            AuditSource::Api,
+            "create_api_token",
+            Some(&name),
+            json!({ "scopes": scopes }),
+        )
+        .await;
+
+    res.status_code(StatusCode::CREATED);
+    res.render(Json(json!({
+        "name": name,
+        "scopes": scopes,
+        "token": secret,
+    })));
+}
+
+#[handler]
+pub async fn delete_token(req: &mut Request, depot: &mut Depot, res: &mut Response) {
+    let Some(name) = req.param::<String>("name") else {
+        render_error(res, StatusCode::BAD_REQUEST, "invalid token name");
+        return;
+    };
+
+    match web_state()
+        .db_manager
+        .api_token_store()
+        .delete_api_token(&name)
+        .await
+    {
+        Ok(true) => {
+            web_state()
+                .bridge
+                .record_audit(
+                    &api_actor(req, depot),
+                    AuditSource::Api,
+                    "delete_api_token",
+                    Some(&name),
+                    json!({}),
+                )
+                .await;
+            res.render(Json(json!({ "ok": true })));
+        }
+        Ok(false) => {
+            render_error(res, StatusCode::NOT_FOUND, "token not found");
+        }
+        Err(err) => {
+            render_error(
+                res,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("database error: {}", err),
+            );
+        }
+    }
+}
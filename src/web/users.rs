@@ -0,0 +1,105 @@
+use salvo::prelude::*;
+use serde_json::{Value, json};
+use tracing::error;
+
+use crate::bridge::purge::{PurgeTarget, purge_user};
+use crate::db::{AuditSource, UserMapping};
+use crate::web::provisioning::{api_actor, render_error};
+use crate::web::web_state;
+
+fn user_entry(mapping: &UserMapping, ghost_user_id: String) -> Value {
+    json!({
+        "id": mapping.id,
+        "matrix_user_id": mapping.matrix_user_id,
+        "discord_user_id": mapping.discord_user_id,
+        "discord_username": mapping.discord_username,
+        "discord_discriminator": mapping.discord_discriminator,
+        "discord_avatar": mapping.discord_avatar,
+        "presence_override": mapping.presence_override,
+        "ghost_user_id": ghost_user_id,
+        "created_at": mapping.created_at,
+        // The mapping is updated whenever the user's Discord profile or
+        // settings change, which is the activity the bridge records.
+        "last_activity": mapping.updated_at,
+    })
+}
+
+#[handler]
+pub async fn list_users(req: &mut Request, res: &mut Response) {
+    let limit = req.query::<i64>("limit").unwrap_or(100).clamp(1, 1000);
+    let offset = req.query::<i64>("offset").unwrap_or(0).max(0);
+    let query = req
+        .query::<String>("query")
+        .map(|query| query.trim().to_string())
+        .filter(|query| !query.is_empty());
+
+    let state = web_state();
+    match state
+        .db_manager
+        .user_store()
+        .search_users(query.as_deref(), limit, offset)
+        .await
+    {
+        Ok(users) => {
+            let users: Vec<Value> = users
+                .iter()
+                .map(|mapping| {
+                    user_entry(
+                        mapping,
+                        state.matrix_client.ghost_user_id(&mapping.discord_user_id),
+                    )
+                })
+                .collect();
+            res.render(Json(json!({
+                "users": users,
+                "count": users.len(),
+                "query": query,
+                "limit": limit,
+                "offset": offset,
+            })));
+        }
+        Err(err) => {
+            render_error(
+                res,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                &format!("database error: {}", err),
+            );
+        }
+    }
+}
+
+/// Erases the user named by the `discord_id` or `mxid` query. Redacting
+/// their history can take a while, so this returns 202 and the outcome is
+/// written to the audit log.
+#[handler]
+pub async fn purge_user_data(req: &mut Request, depot: &mut Depot, res: &mut Response) {
+    let target = match PurgeTarget::from_ids(
+        req.query::<String>("discord_id"),
+        req.query::<String>("mxid"),
+    ) {
+        Ok(target) => target,
+        Err(err) => {
+            render_error(res, StatusCode::BAD_REQUEST, &err.to_string());
+            return;
+        }
+    };
+
+    let actor = api_actor(req, depot);
+    let state = web_state();
+    tokio::spawn(async move {
+        if let Err(err) = purge_user(
+            &state.matrix_client,
+            &state.db_manager,
+            &target,
+            &actor,
+            AuditSource::Api,
+        )
+        .await
+        {
+            error!("failed to purge {:?}: {}", target, err);
+        }
+    });
+
+    res.status_code(StatusCode::ACCEPTED);
+    res.render(Json(json!({ "status": "purging" })));
+}
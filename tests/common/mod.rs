@@ -0,0 +1,494 @@
+//! Shared harness for the end-to-end tests: a stub HTTP server that stands in
+//! for both the Matrix homeserver and the Discord REST API, and helpers that
+//! wire a `BridgeCore` to them with a throwaway SQLite database.
+//!
+//! The stubs sit below the real `MatrixAppservice` and `DiscordClient`
+//! rather than behind `DiscordApi`/`MatrixApi` trait objects: `BridgeCore`
+//! holds the concrete clients, and stubbing HTTP also covers the request
+//! building and response parsing the traits would skip.
+
+#![allow(dead_code)]
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use matrix_bridge_discord::bridge::BridgeCore;
+use matrix_bridge_discord::config::Config;
+use matrix_bridge_discord::db::{DatabaseManager, RoomMapping};
+use matrix_bridge_discord::discord::DiscordClient;
+use matrix_bridge_discord::matrix::MatrixAppservice;
+use parking_lot::Mutex;
+use serde_json::{Value, json};
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// Percent-decoded path, including the query string.
+    pub path: String,
+    pub body: Value,
+}
+
+impl RecordedRequest {
+    pub fn path_without_query(&self) -> &str {
+        self.path.split('?').next().unwrap_or(&self.path)
+    }
+}
+
+pub type Responder = Arc<dyn Fn(&RecordedRequest) -> (u16, Value) + Send + Sync>;
+
+/// Minimal HTTP/1.1 server that records every request and answers with the
+/// responder's status and JSON body. Connections are closed after each
+/// response, which keeps the parser trivial.
+pub struct StubServer {
+    addr: SocketAddr,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+    task: JoinHandle<()>,
+}
+
+impl StubServer {
+    pub async fn start(responder: Responder) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind stub");
+        let addr = listener.local_addr().expect("stub addr");
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = requests.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else {
+                    return;
+                };
+                let recorded = recorded.clone();
+                let responder = responder.clone();
+                tokio::spawn(async move {
+                    let _ = serve_connection(stream, recorded, responder).await;
+                });
+            }
+        });
+
+        Self {
+            addr,
+            requests,
+            task,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().clone()
+    }
+
+    pub fn requests_matching(&self, method: &str, path_fragment: &str) -> Vec<RecordedRequest> {
+        self.requests()
+            .into_iter()
+            .filter(|req| req.method == method && req.path.contains(path_fragment))
+            .collect()
+    }
+}
+
+impl Drop for StubServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn serve_connection(
+    mut stream: TcpStream,
+    recorded: Arc<Mutex<Vec<RecordedRequest>>>,
+    responder: Responder,
+) -> std::io::Result<()> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        if let Some(pos) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = percent_decode(request_line.next().unwrap_or_default());
+    let content_length = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+
+    while buffer.len() < header_end + content_length {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+    let body_end = (header_end + content_length).min(buffer.len());
+    let body = serde_json::from_slice(&buffer[header_end..body_end]).unwrap_or(Value::Null);
+
+    let request = RecordedRequest { method, path, body };
+    let (status, response) = responder(&request);
+    recorded.lock().push(request);
+
+    let payload = response.to_string();
+    let reply = format!(
+        "HTTP/1.1 {} STUB\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        payload.len(),
+        payload
+    );
+    stream.write_all(reply.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%'
+            && let Some(hex) = value.get(index + 1..index + 3)
+            && let Ok(byte) = u8::from_str_radix(hex, 16)
+        {
+            decoded.push(byte);
+            index += 3;
+            continue;
+        }
+        decoded.push(bytes[index]);
+        index += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Stub homeserver: every send returns a fresh event id, rooms have the ghost
+/// of Discord user 42 joined and `@moderator:localhost` at power level 50,
+/// everything else is an empty object.
+pub fn homeserver_responder() -> Responder {
+    let counter = Arc::new(Mutex::new(0u64));
+    Arc::new(move |req: &RecordedRequest| {
+        if req.method == "PUT" && req.path.contains("/send/") {
+            let mut counter = counter.lock();
+            *counter += 1;
+            return (200, json!({ "event_id": format!("$event{}", *counter) }));
+        }
+        if req.method == "GET" && req.path.contains("/state/m.room.power_levels") {
+            return (200, json!({ "users": { "@moderator:localhost": 50 } }));
+        }
+        if req.path.contains("/profile/") {
+            return (200, json!({ "displayname": "Alice" }));
+        }
+        if req.method == "GET" && req.path_without_query().ends_with("/members") {
+            if req.path.contains("membership=ban") {
+                return (200, json!({ "chunk": [] }));
+            }
+            return (
+                200,
+                json!({ "chunk": [{
+                    "type": "m.room.member",
+                    "state_key": "@_discord_42:localhost",
+                    "content": { "membership": "join" },
+                }] }),
+            );
+        }
+        (200, json!({}))
+    })
+}
+
+// Serenity only accepts webhook urls with snowflake-length ids and
+// real-length tokens.
+pub const WEBHOOK_ID: &str = "900000000000000001";
+pub const WEBHOOK_TOKEN: &str = "stub-webhook-token-0123456789abcdefghijklmnopqrstuvwxyz0123456789";
+
+pub fn discord_user_json(id: &str, name: &str) -> Value {
+    json!({
+        "id": id,
+        "username": name,
+        "discriminator": "0",
+        "global_name": null,
+        "avatar": null,
+        "bot": false,
+    })
+}
+
+pub fn discord_message_json(id: &str, channel_id: &str, content: &str) -> Value {
+    json!({
+        "id": id,
+        "channel_id": channel_id,
+        "author": discord_user_json(WEBHOOK_ID, "_matrix"),
+        "content": content,
+        "timestamp": "2024-01-01T00:00:00+00:00",
+        "edited_timestamp": null,
+        "tts": false,
+        "mention_everyone": false,
+        "mentions": [],
+        "mention_roles": [],
+        "attachments": [],
+        "embeds": [],
+        "pinned": false,
+        "type": 0,
+        "webhook_id": WEBHOOK_ID,
+    })
+}
+
+/// A message posted by a Discord user rather than through our webhook.
+pub fn discord_user_message_json(id: &str, author_id: &str, content: &str) -> Value {
+    let mut message = discord_message_json(id, CHANNEL_ID, content);
+    message["author"] = discord_user_json(author_id, "discord-user");
+    message.as_object_mut().unwrap().remove("webhook_id");
+    message
+}
+
+/// Channel history as the stub returns it, newest first like Discord.
+pub const HISTORY_MESSAGE_IDS: [&str; 2] = ["5002", "5001"];
+
+pub fn channel_json(id: &str) -> Value {
+    json!({
+        "id": id,
+        "type": 0,
+        "guild_id": GUILD_ID,
+        "name": "general",
+        "position": 0,
+        "permission_overwrites": [],
+        "nsfw": false,
+    })
+}
+
+/// `THREAD_ID`, a public thread started from that message in `CHANNEL_ID`.
+pub fn thread_json() -> Value {
+    json!({
+        "id": THREAD_ID,
+        "type": 11,
+        "guild_id": GUILD_ID,
+        "parent_id": CHANNEL_ID,
+        "name": "who is around?",
+        "permission_overwrites": [],
+        "nsfw": false,
+        "thread_metadata": {
+            "archived": false,
+            "auto_archive_duration": 1440,
+            "archive_timestamp": "2024-05-01T10:00:00+00:00",
+            "locked": false,
+        },
+    })
+}
+
+pub fn guild_json(id: &str) -> Value {
+    json!({
+        "id": id,
+        "name": "Guild",
+        "icon": null,
+        "owner_id": "1",
+        "afk_timeout": 300,
+        "verification_level": 0,
+        "default_message_notifications": 0,
+        "explicit_content_filter": 0,
+        "roles": [],
+        "emojis": [],
+        "stickers": [],
+        "features": [],
+        "mfa_level": 0,
+        "system_channel_flags": 0,
+        "premium_tier": 0,
+        "preferred_locale": "en-US",
+        "nsfw_level": 0,
+        "premium_progress_bar_enabled": false,
+    })
+}
+
+pub fn webhook_json() -> Value {
+    json!({
+        "id": WEBHOOK_ID,
+        "type": 1,
+        "channel_id": CHANNEL_ID,
+        "name": "_matrix",
+        "avatar": null,
+        "token": WEBHOOK_TOKEN,
+        "application_id": null,
+    })
+}
+
+/// Stub Discord REST API covering user and channel lookups, channel history
+/// and the webhook send and edit paths.
+pub fn discord_responder() -> Responder {
+    let counter = Arc::new(Mutex::new(1000u64));
+    Arc::new(move |req: &RecordedRequest| {
+        let path = req.path_without_query();
+        if req.method == "GET" && path.starts_with("/api/v10/users/") {
+            let id = path.trim_start_matches("/api/v10/users/");
+            return (200, discord_user_json(id, "discord-user"));
+        }
+        if req.method == "GET"
+            && path.starts_with("/api/v10/channels/")
+            && path.matches('/').count() == 4
+        {
+            let id = path.rsplit('/').next().unwrap_or_default();
+            return if id == THREAD_ID {
+                (200, thread_json())
+            } else {
+                (200, channel_json(id))
+            };
+        }
+        if req.method == "POST"
+            && path.starts_with("/api/v10/channels/")
+            && path.ends_with("/messages")
+        {
+            let mut counter = counter.lock();
+            *counter += 1;
+            let channel_id = path.split('/').nth(4).unwrap_or_default();
+            let content = req.body["content"].as_str().unwrap_or_default();
+            return (
+                200,
+                discord_message_json(&counter.to_string(), channel_id, content),
+            );
+        }
+        if req.method == "GET" && path.ends_with("/messages") {
+            let history = HISTORY_MESSAGE_IDS
+                .iter()
+                .map(|id| discord_user_message_json(id, "42", &format!("history {id}")))
+                .collect();
+            return (200, Value::Array(history));
+        }
+        if req.method == "GET"
+            && path.starts_with("/api/v10/guilds/")
+            && path.matches('/').count() == 4
+        {
+            return (200, guild_json(path.rsplit('/').next().unwrap_or_default()));
+        }
+        if path.ends_with("/webhooks") {
+            return if req.method == "GET" {
+                (200, json!([]))
+            } else {
+                (200, webhook_json())
+            };
+        }
+        if req.method == "GET" && path.starts_with("/api/v10/webhooks/") {
+            return (200, webhook_json());
+        }
+        if req.method == "PATCH" && path.starts_with("/api/v10/webhooks/") {
+            let id = path.rsplit('/').next().unwrap_or_default();
+            let content = req.body["content"].as_str().unwrap_or_default();
+            return (200, discord_message_json(id, CHANNEL_ID, content));
+        }
+        if req.method == "POST" && path.starts_with("/api/v10/webhooks/") {
+            let mut counter = counter.lock();
+            *counter += 1;
+            let content = req.body["content"].as_str().unwrap_or_default();
+            return (
+                200,
+                discord_message_json(&counter.to_string(), CHANNEL_ID, content),
+            );
+        }
+        (200, json!({}))
+    })
+}
+
+pub const CHANNEL_ID: &str = "100";
+pub const THREAD_ID: &str = "555";
+pub const GUILD_ID: &str = "1";
+pub const ROOM_ID: &str = "!room:example.org";
+
+/// A bridge wired to stub servers, with `CHANNEL_ID` bridged to `ROOM_ID`.
+pub struct Harness {
+    pub bridge: Arc<BridgeCore>,
+    pub db: Arc<DatabaseManager>,
+    pub homeserver: StubServer,
+    pub discord_api: StubServer,
+    _data_dir: TempDir,
+}
+
+impl Harness {
+    pub async fn start() -> Self {
+        Self::with_discord_responder(discord_responder()).await
+    }
+
+    /// Like `start`, with `discord` answering the Discord API instead of
+    /// `discord_responder`.
+    pub async fn with_discord_responder(discord: Responder) -> Self {
+        Self::with_config(discord, |yaml| yaml).await
+    }
+
+    /// Like `with_discord_responder`, with `edit_config` applied to the
+    /// sample config first.
+    pub async fn with_config(
+        discord: Responder,
+        edit_config: impl FnOnce(String) -> String,
+    ) -> Self {
+        let harness = Self::before_discord_ready(discord, edit_config).await;
+        harness.bridge.mark_discord_ready().await;
+        harness
+    }
+
+    /// Like `with_config`, but Discord has not finished logging in yet.
+    pub async fn before_discord_ready(
+        discord: Responder,
+        edit_config: impl FnOnce(String) -> String,
+    ) -> Self {
+        let homeserver = StubServer::start(homeserver_responder()).await;
+        let discord_api = StubServer::start(discord).await;
+        let data_dir = tempfile::tempdir().expect("tempdir");
+        let db_path = data_dir.path().join("bridge.db");
+
+        let config_yaml = edit_config(
+            include_str!("../../config/config.sample.yaml")
+                .replace("http://localhost:8008", &homeserver.url())
+                .replace(
+                    "sqlite://./discord.db",
+                    &format!("sqlite://{}", db_path.display()),
+                )
+                .replace("discord_send_delay: 1500", "discord_send_delay: 0"),
+        ) + "\nregistration:\n  id: \"discord\"\n  as_token: \"as_token\"\n  hs_token: \"hs_token\"\n";
+        let config = Arc::new(Config::load_from_bytes(config_yaml.as_bytes()).expect("config"));
+
+        let db = Arc::new(
+            DatabaseManager::new(&config.database)
+                .await
+                .expect("database"),
+        );
+        db.migrate().await.expect("migrate");
+        db.room_store()
+            .create_room_mapping(&RoomMapping {
+                id: 0,
+                matrix_room_id: ROOM_ID.to_string(),
+                discord_channel_id: CHANNEL_ID.to_string(),
+                discord_channel_name: "general".to_string(),
+                discord_guild_id: GUILD_ID.to_string(),
+                created_by: None,
+                origin: None,
+                last_active_at: None,
+                created_at: chrono::Utc::now(),
+                updated_at: chrono::Utc::now(),
+            })
+            .await
+            .expect("room mapping");
+
+        let matrix = Arc::new(MatrixAppservice::new(config.clone()).await.expect("matrix"));
+        let discord = Arc::new(DiscordClient::new(config).await.expect("discord"));
+        let http = serenity::http::HttpBuilder::new("discord_token")
+            .proxy(discord_api.url())
+            .ratelimiter_disabled(true)
+            .build();
+        discord.attach_http(Arc::new(http)).await;
+
+        let bridge = Arc::new(BridgeCore::new(matrix, discord.clone(), db.clone()));
+        discord.set_bridge(bridge.clone()).await;
+
+        Self {
+            bridge,
+            db,
+            homeserver,
+            discord_api,
+            _data_dir: data_dir,
+        }
+    }
+}
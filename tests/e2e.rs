@@ -0,0 +1,1688 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{CHANNEL_ID, GUILD_ID, HISTORY_MESSAGE_IDS, Harness, ROOM_ID, THREAD_ID};
+use matrix_bridge_discord::bridge::replay::ReplayReport;
+use matrix_bridge_discord::bridge::{DiscordMessageContext, DiscordReference};
+use matrix_bridge_discord::config::AttachmentPolicy;
+use matrix_bridge_discord::db::{
+    AuditSource, EmojiMapping, MessageMapping, RoomSettings, UserMapping,
+};
+use matrix_bridge_discord::matrix::MatrixEvent;
+use serde_json::json;
+
+fn discord_message(message_id: &str, content: &str) -> DiscordMessageContext {
+    DiscordMessageContext {
+        channel_id: CHANNEL_ID.to_string(),
+        source_message_id: Some(message_id.to_string()),
+        sender_id: "42".to_string(),
+        content: content.to_string(),
+        attachments: Vec::new(),
+        reply_to: None,
+        edit_of: None,
+        permissions: Default::default(),
+        sent_at: None,
+        relayed_name: None,
+        attributed_to: None,
+        stickers: Vec::new(),
+        reference: None,
+        thread_parent_id: None,
+    }
+}
+
+#[tokio::test]
+async fn discord_message_is_sent_to_matrix_and_mapped() {
+    let harness = Harness::start().await;
+
+    harness
+        .bridge
+        .handle_discord_message_with_context(discord_message("555", "hello from discord"))
+        .await
+        .expect("discord message");
+
+    let sends = harness
+        .homeserver
+        .requests_matching("PUT", "/send/m.room.message/");
+    let send = sends
+        .iter()
+        .find(|req| req.path.contains(ROOM_ID))
+        .expect("message sent to bridged room");
+    assert_eq!(send.body["body"], "hello from discord");
+
+    let mapping = harness
+        .db
+        .message_store()
+        .get_by_discord_message_id("555")
+        .await
+        .expect("lookup")
+        .expect("message mapping stored");
+    assert_eq!(mapping.matrix_room_id, ROOM_ID);
+}
+
+#[tokio::test]
+async fn custom_emoji_are_shown_from_their_cached_upload() {
+    let harness = Harness::start().await;
+    harness
+        .db
+        .emoji_store()
+        .create_emoji(&EmojiMapping::new(
+            "12345".to_string(),
+            "cool".to_string(),
+            false,
+            "mxc://localhost/cool".to_string(),
+        ))
+        .await
+        .expect("emoji mapping");
+
+    harness
+        .bridge
+        .handle_discord_message_with_context(discord_message("555", "nice <:cool:12345>"))
+        .await
+        .expect("discord message");
+
+    let sends = harness
+        .homeserver
+        .requests_matching("PUT", "/send/m.room.message/");
+    let send = sends
+        .iter()
+        .find(|req| req.path.contains(ROOM_ID))
+        .expect("message sent to bridged room");
+    assert_eq!(send.body["body"], "nice :cool:");
+    let formatted_body = send.body["formatted_body"]
+        .as_str()
+        .expect("formatted body");
+    assert!(formatted_body.contains(r#"<img data-mx-emoticon src="mxc://localhost/cool""#));
+}
+
+#[tokio::test]
+async fn discord_mentions_are_plain_in_the_body_and_pills_in_the_formatted_body() {
+    let harness = Harness::start().await;
+    harness
+        .bridge
+        .handle_discord_message_with_context(discord_message("555", "**hey** <@42>"))
+        .await
+        .expect("discord message");
+
+    let sends = harness
+        .homeserver
+        .requests_matching("PUT", "/send/m.room.message/");
+    let send = sends
+        .iter()
+        .find(|req| req.path.contains(ROOM_ID))
+        .expect("message sent to bridged room");
+    assert_eq!(send.body["body"], "**hey** @_discord_42:localhost");
+    assert_eq!(
+        send.body["formatted_body"],
+        "<strong>hey</strong> <a href=\"https://matrix.to/#/@_discord_42:localhost\">@_discord_42</a>"
+    );
+}
+
+#[tokio::test]
+async fn attribution_badge_marks_discord_messages_when_enabled() {
+    let harness = Harness::start().await;
+    harness
+        .db
+        .room_store()
+        .set_room_settings(&RoomSettings {
+            attribution_badge: true,
+            ..RoomSettings::new(ROOM_ID)
+        })
+        .await
+        .expect("room settings");
+
+    harness
+        .bridge
+        .handle_discord_message_with_context(discord_message("555", "hi <there>"))
+        .await
+        .expect("discord message");
+
+    let sends = harness
+        .homeserver
+        .requests_matching("PUT", "/send/m.room.message/");
+    let send = sends
+        .iter()
+        .find(|req| req.path.contains(ROOM_ID))
+        .expect("message sent to bridged room");
+    assert_eq!(send.body["body"], "hi <there>");
+    assert_eq!(send.body["format"], "org.matrix.custom.html");
+    let formatted_body = send.body["formatted_body"]
+        .as_str()
+        .expect("formatted body");
+    assert!(formatted_body.starts_with("hi &lt;there&gt; "));
+    assert!(formatted_body.contains("via Discord"));
+}
+
+#[tokio::test]
+async fn link_only_rooms_send_discord_attachments_as_links() {
+    let harness = Harness::start().await;
+    let mut settings = RoomSettings::new(ROOM_ID);
+    settings.attachment_policy.discord_to_matrix = Some(AttachmentPolicy::LinkOnly);
+    harness
+        .db
+        .room_store()
+        .set_room_settings(&settings)
+        .await
+        .expect("room settings");
+
+    let mut message = discord_message("555", "");
+    message.attachments = vec!["https://cdn.discordapp.com/attachments/1/2/cat.png".to_string()];
+    harness
+        .bridge
+        .handle_discord_message_with_context(message)
+        .await
+        .expect("discord message");
+
+    let sends = harness
+        .homeserver
+        .requests_matching("PUT", "/send/m.room.message/");
+    let bodies: Vec<_> = sends
+        .iter()
+        .filter(|req| req.path.contains(ROOM_ID))
+        .map(|req| req.body["body"].clone())
+        .collect();
+    assert_eq!(
+        bodies,
+        [json!(
+            "Attachment: https://cdn.discordapp.com/attachments/1/2/cat.png"
+        )]
+    );
+    assert!(
+        harness
+            .homeserver
+            .requests_matching("POST", "/upload")
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+async fn bot_command_output_is_attributed_to_the_invoking_user() {
+    let harness = Harness::start().await;
+    let mut message = discord_message("555", "You rolled 4");
+    message.attributed_to = Some("Dice (command: /roll by alice)".to_string());
+    harness
+        .bridge
+        .handle_discord_message_with_context(message)
+        .await
+        .expect("discord message");
+
+    let sends = harness
+        .homeserver
+        .requests_matching("PUT", "/send/m.room.message/");
+    let send = sends
+        .iter()
+        .find(|req| req.path.contains(ROOM_ID))
+        .expect("message sent to bridged room");
+    assert_eq!(
+        send.body["body"],
+        "Dice (command: /roll by alice):\nYou rolled 4"
+    );
+}
+
+#[tokio::test]
+async fn failed_sender_lookup_fails_the_message_instead_of_dropping_the_name() {
+    let fallback = common::discord_responder();
+    let harness = Harness::with_discord_responder(std::sync::Arc::new(move |req| {
+        if req.method == "GET" && req.path_without_query() == "/api/v10/users/42" {
+            return (502, json!({ "code": 0, "message": "Bad Gateway" }));
+        }
+        fallback(req)
+    }))
+    .await;
+
+    // Delivery is best effort in the sample config, so the error reaches the
+    // caller; with at-least-once delivery it would be queued for retry.
+    let err = harness
+        .bridge
+        .handle_discord_message_with_context(discord_message("555", "hello"))
+        .await
+        .expect_err("lookup failure is reported");
+    assert!(
+        err.to_string()
+            .contains("failed to look up discord user 42")
+    );
+    assert!(
+        harness
+            .homeserver
+            .requests_matching("PUT", "/send/m.room.message/")
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+async fn discord_edit_becomes_matrix_replacement() {
+    let harness = Harness::start().await;
+    harness
+        .bridge
+        .handle_discord_message_with_context(discord_message("555", "first"))
+        .await
+        .expect("original");
+
+    let mut edit = discord_message("555", "second");
+    edit.edit_of = Some("555".to_string());
+    harness
+        .bridge
+        .handle_discord_message_with_context(edit)
+        .await
+        .expect("edit");
+
+    let sends = harness
+        .homeserver
+        .requests_matching("PUT", "/send/m.room.message/");
+    let replacement = sends
+        .iter()
+        .find(|req| req.body["m.relates_to"]["rel_type"] == "m.replace")
+        .expect("m.replace event sent");
+    assert_eq!(replacement.body["m.relates_to"]["event_id"], "$event1");
+    assert!(
+        replacement.body["m.new_content"]["body"]
+            .as_str()
+            .is_some_and(|body| body.contains("second"))
+    );
+}
+
+#[tokio::test]
+async fn repeated_discord_edits_target_the_original_event() {
+    let harness = Harness::start().await;
+    harness
+        .bridge
+        .handle_discord_message_with_context(discord_message("555", "first"))
+        .await
+        .expect("original");
+
+    for content in ["second", "third"] {
+        let mut edit = discord_message("555", content);
+        edit.edit_of = Some("555".to_string());
+        harness
+            .bridge
+            .handle_discord_message_with_context(edit)
+            .await
+            .expect("edit");
+    }
+
+    let sends = harness
+        .homeserver
+        .requests_matching("PUT", "/send/m.room.message/");
+    let targets: Vec<_> = sends
+        .iter()
+        .filter(|req| req.body["m.relates_to"]["rel_type"] == "m.replace")
+        .map(|req| req.body["m.relates_to"]["event_id"].clone())
+        .collect();
+    assert_eq!(targets, [json!("$event1"), json!("$event1")]);
+}
+
+#[tokio::test]
+async fn discord_edit_before_original_is_sent_once_as_new_message() {
+    let harness = Harness::start().await;
+
+    let mut edit = discord_message("555", "second");
+    edit.edit_of = Some("555".to_string());
+    harness
+        .bridge
+        .handle_discord_message_with_context(edit)
+        .await
+        .expect("edit");
+    harness
+        .bridge
+        .handle_discord_message_with_context(discord_message("555", "first"))
+        .await
+        .expect("late original");
+
+    let sends = harness
+        .homeserver
+        .requests_matching("PUT", "/send/m.room.message/");
+    let sends: Vec<_> = sends
+        .iter()
+        .filter(|req| req.path.contains(ROOM_ID))
+        .collect();
+    assert_eq!(sends.len(), 1);
+    assert_eq!(sends[0].body["body"], "(edited) second");
+    assert!(sends[0].body.get("m.relates_to").is_none());
+
+    let mapping = harness
+        .db
+        .message_store()
+        .get_by_discord_message_id("555")
+        .await
+        .expect("lookup")
+        .expect("message mapping stored");
+    assert_eq!(mapping.matrix_event_id, "$event1");
+}
+
+#[tokio::test]
+async fn empty_discord_message_sends_no_text_event() {
+    let harness = Harness::start().await;
+
+    harness
+        .bridge
+        .handle_discord_message_with_context(discord_message("555", ""))
+        .await
+        .expect("empty message");
+
+    let sends = harness
+        .homeserver
+        .requests_matching("PUT", "/send/m.room.message/");
+    assert!(sends.iter().all(|req| !req.path.contains(ROOM_ID)));
+}
+
+#[tokio::test]
+async fn discord_delete_redacts_matrix_event() {
+    let harness = Harness::start().await;
+    harness
+        .bridge
+        .handle_discord_message_with_context(discord_message("555", "to be deleted"))
+        .await
+        .expect("original");
+
+    harness
+        .bridge
+        .handle_discord_message_delete(CHANNEL_ID, "555")
+        .await
+        .expect("delete");
+
+    let redactions = harness
+        .homeserver
+        .requests_matching("PUT", "/send/m.room.redaction/");
+    let redaction = redactions.first().expect("redaction sent");
+    assert_eq!(redaction.body["redacts"], "$event1");
+}
+
+#[tokio::test]
+async fn matrix_message_is_sent_through_discord_webhook() {
+    let harness = Harness::start().await;
+
+    harness
+        .bridge
+        .handle_matrix_message(&MatrixEvent {
+            event_id: Some("$matrix1".to_string()),
+            event_type: "m.room.message".to_string(),
+            room_id: ROOM_ID.to_string(),
+            sender: "@alice:localhost".to_string(),
+            state_key: None,
+            content: Some(json!({ "msgtype": "m.text", "body": "hello from matrix" })),
+            prev_content: None,
+            timestamp: Some(chrono::Utc::now().timestamp_millis().to_string()),
+        })
+        .await
+        .expect("matrix message");
+
+    // Webhook execution may be dispatched asynchronously.
+    let mut executed = Vec::new();
+    for _ in 0..50 {
+        executed = harness
+            .discord_api
+            .requests_matching("POST", "/api/v10/webhooks/");
+        if !executed.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let execution = executed.first().expect("webhook executed");
+    assert!(
+        execution.body["content"]
+            .as_str()
+            .is_some_and(|content| content.contains("hello from matrix"))
+    );
+
+    let mapping = harness
+        .db
+        .message_store()
+        .get_by_matrix_event_id("$matrix1")
+        .await
+        .expect("lookup")
+        .expect("message mapping stored");
+    assert_eq!(mapping.discord_message_id, "1001");
+    assert_eq!(mapping.matrix_room_id, ROOM_ID);
+}
+
+#[tokio::test]
+async fn room_mentions_only_notify_discord_from_senders_allowed_to_notify_the_room() {
+    let harness = Harness::start().await;
+
+    for (event_id, sender) in [
+        ("$member", "@alice:localhost"),
+        ("$mod", "@moderator:localhost"),
+    ] {
+        harness
+            .bridge
+            .handle_matrix_message(&MatrixEvent {
+                event_id: Some(event_id.to_string()),
+                event_type: "m.room.message".to_string(),
+                room_id: ROOM_ID.to_string(),
+                sender: sender.to_string(),
+                state_key: None,
+                content: Some(json!({ "msgtype": "m.text", "body": "@room standup" })),
+                prev_content: None,
+                timestamp: Some(chrono::Utc::now().timestamp_millis().to_string()),
+            })
+            .await
+            .expect("matrix message");
+    }
+
+    let mut contents = Vec::new();
+    for _ in 0..50 {
+        contents = harness
+            .discord_api
+            .requests_matching("POST", "/api/v10/webhooks/")
+            .into_iter()
+            .filter_map(|request| request.body["content"].as_str().map(str::to_string))
+            .collect::<Vec<_>>();
+        if contents.len() == 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(contents, ["@\u{200B}everyone standup", "@everyone standup"]);
+}
+
+#[tokio::test]
+async fn room_aliases_in_matrix_messages_are_resolved_once() {
+    let harness = Harness::start().await;
+
+    for event_id in ["$alias1", "$alias2"] {
+        harness
+            .bridge
+            .handle_matrix_message(&MatrixEvent {
+                event_id: Some(event_id.to_string()),
+                event_type: "m.room.message".to_string(),
+                room_id: ROOM_ID.to_string(),
+                sender: "@alice:localhost".to_string(),
+                state_key: None,
+                content: Some(json!({ "msgtype": "m.text", "body": "see #elsewhere:example.org" })),
+                prev_content: None,
+                timestamp: Some(chrono::Utc::now().timestamp_millis().to_string()),
+            })
+            .await
+            .expect("matrix message");
+    }
+
+    let lookups = harness
+        .homeserver
+        .requests_matching("GET", "/directory/room/#elsewhere:example.org");
+    assert_eq!(lookups.len(), 1);
+}
+
+#[tokio::test]
+async fn discord_reaction_is_sent_to_matrix_by_the_ghost() {
+    let harness = Harness::start().await;
+    harness
+        .bridge
+        .handle_discord_message_with_context(discord_message("555", "react to me"))
+        .await
+        .expect("discord message");
+    let mapping = harness
+        .db
+        .message_store()
+        .get_by_discord_message_id("555")
+        .await
+        .expect("lookup")
+        .expect("message mapping stored");
+
+    harness
+        .bridge
+        .handle_discord_reaction(CHANNEL_ID, "555", "77", "👍", None)
+        .await
+        .expect("discord reaction");
+
+    let reactions = harness
+        .homeserver
+        .requests_matching("PUT", "/send/m.reaction/");
+    let [reaction] = reactions.as_slice() else {
+        panic!("expected one reaction, got {reactions:?}");
+    };
+    assert!(reaction.path.contains(ROOM_ID));
+    assert!(reaction.path.contains("user_id=@_discord_77:localhost"));
+    assert_eq!(
+        reaction.body["m.relates_to"],
+        json!({
+            "rel_type": "m.annotation",
+            "event_id": mapping.matrix_event_id,
+            "key": "👍",
+        })
+    );
+
+    // Custom emoji the bridge never saw have no Matrix counterpart.
+    harness
+        .bridge
+        .handle_discord_reaction(CHANNEL_ID, "555", "77", "blobcat", Some("999"))
+        .await
+        .expect("unknown custom emoji");
+    assert_eq!(
+        harness
+            .homeserver
+            .requests_matching("PUT", "/send/m.reaction/")
+            .len(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn matrix_reaction_is_added_to_the_discord_message() {
+    let harness = Harness::start().await;
+    let matrix_event = |event_id: &str, event_type: &str, content| MatrixEvent {
+        event_id: Some(event_id.to_string()),
+        event_type: event_type.to_string(),
+        room_id: ROOM_ID.to_string(),
+        sender: "@alice:localhost".to_string(),
+        state_key: None,
+        content: Some(content),
+        prev_content: None,
+        timestamp: Some(chrono::Utc::now().timestamp_millis().to_string()),
+    };
+    harness
+        .bridge
+        .handle_matrix_message(&matrix_event(
+            "$matrix1",
+            "m.room.message",
+            json!({ "msgtype": "m.text", "body": "react to me" }),
+        ))
+        .await
+        .expect("matrix message");
+    let mut mapping = None;
+    for _ in 0..50 {
+        mapping = harness
+            .db
+            .message_store()
+            .get_by_matrix_event_id("$matrix1")
+            .await
+            .expect("lookup");
+        if mapping.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let mapping = mapping.expect("message mapping stored");
+
+    harness
+        .bridge
+        .handle_matrix_reaction(&matrix_event(
+            "$reaction1",
+            "m.reaction",
+            json!({
+                "m.relates_to": {
+                    "rel_type": "m.annotation",
+                    "event_id": "$matrix1",
+                    "key": "👍",
+                }
+            }),
+        ))
+        .await
+        .expect("matrix reaction");
+
+    let reactions = harness.discord_api.requests_matching(
+        "PUT",
+        &format!(
+            "/api/v10/channels/{CHANNEL_ID}/messages/{}/reactions/",
+            mapping.discord_message_id
+        ),
+    );
+    let [reaction] = reactions.as_slice() else {
+        panic!("expected one reaction, got {reactions:?}");
+    };
+    assert!(reaction.path.ends_with("/reactions/👍/@me"));
+}
+
+#[tokio::test]
+async fn matrix_sticker_of_a_discord_emoji_is_sent_as_the_emoji() {
+    let harness = Harness::start().await;
+    harness
+        .db
+        .emoji_store()
+        .create_emoji(&EmojiMapping::new(
+            "12345".to_string(),
+            "cool".to_string(),
+            false,
+            "mxc://localhost/cool".to_string(),
+        ))
+        .await
+        .expect("emoji mapping");
+
+    harness
+        .bridge
+        .handle_matrix_message(&MatrixEvent {
+            event_id: Some("$sticker1".to_string()),
+            event_type: "m.sticker".to_string(),
+            room_id: ROOM_ID.to_string(),
+            sender: "@alice:localhost".to_string(),
+            state_key: None,
+            content: Some(json!({
+                "body": "cool",
+                "url": "mxc://localhost/cool",
+                "info": { "mimetype": "image/png" },
+            })),
+            prev_content: None,
+            timestamp: Some(chrono::Utc::now().timestamp_millis().to_string()),
+        })
+        .await
+        .expect("matrix sticker");
+
+    let mut executed = Vec::new();
+    for _ in 0..50 {
+        executed = harness
+            .discord_api
+            .requests_matching("POST", "/api/v10/webhooks/");
+        if !executed.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let execution = executed.first().expect("webhook executed");
+    assert_eq!(execution.body["content"], "<:cool:12345>");
+    assert!(
+        harness
+            .homeserver
+            .requests_matching("GET", "/download/")
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+async fn matrix_edit_updates_the_discord_message() {
+    let harness = Harness::start().await;
+    let matrix_event = |event_id: &str, content| MatrixEvent {
+        event_id: Some(event_id.to_string()),
+        event_type: "m.room.message".to_string(),
+        room_id: ROOM_ID.to_string(),
+        sender: "@alice:localhost".to_string(),
+        state_key: None,
+        content: Some(content),
+        prev_content: None,
+        timestamp: Some(chrono::Utc::now().timestamp_millis().to_string()),
+    };
+
+    harness
+        .bridge
+        .handle_matrix_message(&matrix_event(
+            "$matrix1",
+            json!({ "msgtype": "m.text", "body": "first" }),
+        ))
+        .await
+        .expect("original");
+    harness
+        .bridge
+        .handle_matrix_message(&matrix_event(
+            "$matrix2",
+            json!({
+                "msgtype": "m.text",
+                "body": "* second",
+                "m.new_content": { "msgtype": "m.text", "body": "second" },
+                "m.relates_to": { "rel_type": "m.replace", "event_id": "$matrix1" },
+            }),
+        ))
+        .await
+        .expect("edit");
+
+    let edits = harness
+        .discord_api
+        .requests_matching("PATCH", "/api/v10/webhooks/");
+    let edit = edits.first().expect("webhook message edited");
+    assert!(edit.path_without_query().ends_with("/messages/1001"));
+    assert_eq!(edit.body["content"], "second");
+    assert_eq!(
+        harness
+            .discord_api
+            .requests_matching("POST", "/api/v10/webhooks/")
+            .len(),
+        1
+    );
+    assert!(
+        harness
+            .db
+            .message_store()
+            .get_by_matrix_event_id("$matrix2")
+            .await
+            .expect("lookup")
+            .is_none()
+    );
+}
+
+#[tokio::test]
+async fn delivery_confirmation_is_withdrawn_when_an_edit_fails() {
+    let harness = Harness::start().await;
+    harness
+        .db
+        .room_store()
+        .set_room_settings(&RoomSettings {
+            delivery_confirmations: true,
+            ..RoomSettings::new(ROOM_ID)
+        })
+        .await
+        .expect("room settings");
+    let matrix_event = |event_id: &str, content| MatrixEvent {
+        event_id: Some(event_id.to_string()),
+        event_type: "m.room.message".to_string(),
+        room_id: ROOM_ID.to_string(),
+        sender: "@alice:localhost".to_string(),
+        state_key: None,
+        content: Some(content),
+        prev_content: None,
+        timestamp: Some(chrono::Utc::now().timestamp_millis().to_string()),
+    };
+
+    harness
+        .bridge
+        .handle_matrix_message(&matrix_event(
+            "$matrix1",
+            json!({ "msgtype": "m.text", "body": "first" }),
+        ))
+        .await
+        .expect("original");
+    let reactions = harness
+        .homeserver
+        .requests_matching("PUT", "/send/m.reaction/");
+    let [reaction] = reactions.as_slice() else {
+        panic!("expected one confirmation, got {reactions:?}");
+    };
+    assert_eq!(reaction.body["m.relates_to"]["event_id"], "$matrix1");
+    assert_eq!(reaction.body["m.relates_to"]["key"], "✅");
+    assert!(
+        harness
+            .homeserver
+            .requests_matching("PUT", "/send/m.room.redaction/")
+            .is_empty()
+    );
+
+    let long_body = "a".repeat(2500);
+    harness
+        .bridge
+        .handle_matrix_message(&matrix_event(
+            "$matrix2",
+            json!({
+                "msgtype": "m.text",
+                "body": format!("* {long_body}"),
+                "m.new_content": { "msgtype": "m.text", "body": long_body },
+                "m.relates_to": { "rel_type": "m.replace", "event_id": "$matrix1" },
+            }),
+        ))
+        .await
+        .expect("refused edit is handled");
+
+    let redactions = harness
+        .homeserver
+        .requests_matching("PUT", "/send/m.room.redaction/");
+    let redaction = redactions.first().expect("confirmation redacted");
+    assert!(
+        !redaction.body["redacts"]
+            .as_str()
+            .unwrap_or_default()
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+async fn oversized_matrix_message_is_refused_with_a_notice() {
+    let harness = Harness::start().await;
+
+    harness
+        .bridge
+        .handle_matrix_message(&MatrixEvent {
+            event_id: Some("$matrix1".to_string()),
+            event_type: "m.room.message".to_string(),
+            room_id: ROOM_ID.to_string(),
+            sender: "@alice:localhost".to_string(),
+            state_key: None,
+            content: Some(json!({ "msgtype": "m.text", "body": "a".repeat(2500) })),
+            prev_content: None,
+            timestamp: Some(chrono::Utc::now().timestamp_millis().to_string()),
+        })
+        .await
+        .expect("refused message is handled");
+
+    assert!(
+        harness
+            .discord_api
+            .requests_matching("POST", "/api/v10/webhooks/")
+            .is_empty()
+    );
+    let notices = harness
+        .homeserver
+        .requests_matching("PUT", "/send/m.room.message/");
+    let notice = notices
+        .iter()
+        .find(|req| req.path.contains(ROOM_ID) && req.body["msgtype"] == "m.notice")
+        .expect("notice sent to the room");
+    assert!(
+        notice.body["body"]
+            .as_str()
+            .is_some_and(|body| body.contains("2500 characters long"))
+    );
+}
+
+#[tokio::test]
+async fn matrix_redaction_deletes_the_discord_message() {
+    let harness = Harness::start().await;
+    let matrix_event = |event_id: &str, event_type: &str, content| MatrixEvent {
+        event_id: Some(event_id.to_string()),
+        event_type: event_type.to_string(),
+        room_id: ROOM_ID.to_string(),
+        sender: "@alice:localhost".to_string(),
+        state_key: None,
+        content: Some(content),
+        prev_content: None,
+        timestamp: Some(chrono::Utc::now().timestamp_millis().to_string()),
+    };
+
+    harness
+        .bridge
+        .handle_matrix_message(&matrix_event(
+            "$matrix1",
+            "m.room.message",
+            json!({ "msgtype": "m.text", "body": "oops" }),
+        ))
+        .await
+        .expect("original");
+    harness
+        .bridge
+        .handle_matrix_redaction(&matrix_event(
+            "$matrix2",
+            "m.room.redaction",
+            json!({ "redacts": "$matrix1" }),
+        ))
+        .await
+        .expect("redaction");
+
+    let deletes = harness
+        .discord_api
+        .requests_matching("DELETE", "/messages/1001");
+    assert_eq!(deletes.len(), 1);
+    assert!(
+        harness
+            .db
+            .message_store()
+            .get_by_matrix_event_id("$matrix1")
+            .await
+            .expect("lookup")
+            .is_none()
+    );
+}
+
+#[tokio::test]
+async fn ghosts_follow_discord_guild_membership() {
+    let harness = Harness::start().await;
+    harness
+        .db
+        .room_store()
+        .set_room_settings(&RoomSettings {
+            auto_invite_members: true,
+            ..RoomSettings::new(ROOM_ID)
+        })
+        .await
+        .expect("room settings");
+    let ghost_query = "user_id=@_discord_42:localhost";
+
+    harness
+        .bridge
+        .handle_discord_guild_member_add(GUILD_ID, "42", "alice", None, &[])
+        .await
+        .expect("member add");
+    let joins = harness.homeserver.requests_matching("POST", "/join");
+    assert!(
+        joins.iter().any(|req| req.path.contains(ghost_query)),
+        "ghost joined as itself: {joins:?}"
+    );
+
+    harness
+        .bridge
+        .handle_discord_guild_member_remove(GUILD_ID, "42")
+        .await
+        .expect("member remove");
+    let leaves = harness.homeserver.requests_matching("POST", "/leave");
+    let [leave] = leaves.as_slice() else {
+        panic!("expected one leave, got {leaves:?}");
+    };
+    assert!(leave.path.contains(ghost_query));
+    assert!(
+        harness
+            .homeserver
+            .requests_matching("POST", "/kick")
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+async fn presence_command_pins_and_clears_the_ghost_presence() {
+    let harness = Harness::start().await;
+    harness
+        .db
+        .user_store()
+        .create_user_mapping(&UserMapping {
+            id: 0,
+            matrix_user_id: "@_discord_42:localhost".to_string(),
+            discord_user_id: "42".to_string(),
+            discord_username: "alice".to_string(),
+            discord_discriminator: "0".to_string(),
+            discord_avatar: None,
+            presence_override: None,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        })
+        .await
+        .expect("user mapping");
+
+    let presence_override = || async {
+        harness
+            .db
+            .user_store()
+            .get_user_by_discord_id("42")
+            .await
+            .expect("user lookup")
+            .expect("user mapping")
+            .presence_override
+    };
+
+    harness
+        .bridge
+        .handle_discord_message_with_context(discord_message("555", "!matrix presence Offline"))
+        .await
+        .expect("presence command");
+    assert_eq!(presence_override().await.as_deref(), Some("offline"));
+    let replies = harness
+        .discord_api
+        .requests_matching("POST", &format!("/channels/{CHANNEL_ID}/messages"));
+    assert!(
+        replies
+            .iter()
+            .any(|req| req.body["content"] == "Your Matrix presence is now pinned to offline.")
+    );
+
+    harness
+        .bridge
+        .handle_discord_message_with_context(discord_message("556", "!matrix presence clear"))
+        .await
+        .expect("presence command");
+    assert_eq!(presence_override().await, None);
+}
+
+#[tokio::test]
+async fn guild_nick_and_roles_are_set_on_the_ghost_in_guild_rooms() {
+    let harness = Harness::start().await;
+    harness
+        .db
+        .user_store()
+        .create_user_mapping(&UserMapping {
+            id: 0,
+            matrix_user_id: "@_discord_42:localhost".to_string(),
+            discord_user_id: "42".to_string(),
+            discord_username: "alice".to_string(),
+            discord_discriminator: "0".to_string(),
+            discord_avatar: None,
+            presence_override: None,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        })
+        .await
+        .expect("user mapping");
+
+    harness
+        .bridge
+        .handle_discord_guild_member_update(GUILD_ID, "42", "Ally", None, &["7".to_string()])
+        .await
+        .expect("member update");
+
+    let updates = harness
+        .homeserver
+        .requests_matching("PUT", "/state/m.room.member/@_discord_42:localhost");
+    let [update] = updates.as_slice() else {
+        panic!("expected one member update, got {updates:?}");
+    };
+    assert!(update.path.contains(ROOM_ID));
+    assert!(update.path.contains("user_id=@_discord_42:localhost"));
+    assert_eq!(update.body["membership"], "join");
+    assert_eq!(update.body["displayname"], "Ally");
+    assert_eq!(update.body["discord_roles"], json!(["7"]));
+}
+
+#[tokio::test]
+async fn cache_warmup_looks_up_every_bridged_channel() {
+    let harness = Harness::start().await;
+
+    harness.bridge.warm_up_caches().await.expect("warmup");
+
+    let lookups = |path: &str| {
+        harness
+            .discord_api
+            .requests_matching("GET", path)
+            .iter()
+            .filter(|req| req.path_without_query() == path)
+            .count()
+    };
+    assert_eq!(lookups(&format!("/api/v10/channels/{CHANNEL_ID}")), 1);
+    assert_eq!(lookups(&format!("/api/v10/guilds/{GUILD_ID}")), 1);
+    assert_eq!(
+        lookups(&format!("/api/v10/channels/{CHANNEL_ID}/webhooks")),
+        1
+    );
+
+    // Everything is cached now, so a second pass needs no lookups.
+    let requests = harness.discord_api.requests().len();
+    harness
+        .bridge
+        .warm_up_caches()
+        .await
+        .expect("second warmup");
+    assert_eq!(harness.discord_api.requests().len(), requests);
+}
+
+#[tokio::test]
+async fn matrix_read_receipts_are_kept_per_room() {
+    let harness = Harness::start().await;
+    let receipt = MatrixEvent {
+        event_id: None,
+        event_type: "m.receipt".to_string(),
+        room_id: ROOM_ID.to_string(),
+        sender: String::new(),
+        state_key: None,
+        content: Some(json!({
+            "$read": {
+                "m.read": {
+                    "@alice:localhost": { "ts": 1000 },
+                    "@_discord_42:localhost": { "ts": 1000 },
+                }
+            }
+        })),
+        prev_content: None,
+        timestamp: None,
+    };
+    harness
+        .bridge
+        .handle_matrix_receipt(&receipt)
+        .await
+        .expect("receipt");
+
+    let markers = harness.bridge.read_markers(ROOM_ID);
+    assert_eq!(markers.len(), 1, "ghost receipts are skipped");
+    assert_eq!(markers["@alice:localhost"].event_id, "$read");
+}
+
+#[tokio::test]
+async fn status_command_reports_the_room_mapping() {
+    let harness = Harness::start().await;
+    harness
+        .bridge
+        .handle_discord_message_with_context(discord_message("555", "hello"))
+        .await
+        .expect("discord message");
+
+    harness
+        .bridge
+        .handle_matrix_message(&MatrixEvent {
+            event_id: Some("$status".to_string()),
+            event_type: "m.room.message".to_string(),
+            room_id: ROOM_ID.to_string(),
+            sender: "@alice:localhost".to_string(),
+            state_key: None,
+            content: Some(json!({ "msgtype": "m.text", "body": "!discord status" })),
+            prev_content: None,
+            timestamp: Some(chrono::Utc::now().timestamp_millis().to_string()),
+        })
+        .await
+        .expect("status command");
+
+    let sends = harness
+        .homeserver
+        .requests_matching("PUT", "/send/m.room.message/");
+    let notice = sends
+        .iter()
+        .filter_map(|req| req.body["body"].as_str())
+        .find(|body| body.starts_with("Matrix room: "))
+        .expect("status notice");
+    assert!(notice.contains(&format!(
+        "Discord channel: #general ({CHANNEL_ID}) in guild {GUILD_ID}"
+    )));
+    assert!(!notice.contains("Last Discord → Matrix: never"));
+    assert!(notice.contains("Last Matrix → Discord: never"));
+    // The stub channel has no webhook yet, and asking must not create one.
+    assert!(notice.contains("Webhook: missing"));
+    assert!(
+        harness
+            .discord_api
+            .requests_matching("POST", &format!("/api/v10/channels/{CHANNEL_ID}/webhooks"))
+            .is_empty()
+    );
+    assert!(notice.contains("Encrypted: no"));
+}
+
+#[tokio::test]
+async fn replay_bridges_only_history_missing_from_matrix() {
+    let harness = Harness::start().await;
+    harness
+        .bridge
+        .handle_discord_message_with_context(discord_message(
+            HISTORY_MESSAGE_IDS[1],
+            "history 5001",
+        ))
+        .await
+        .expect("discord message");
+
+    let since = chrono::DateTime::from_timestamp(1_420_070_400, 0).unwrap();
+    let report = harness
+        .bridge
+        .replay_discord_messages(ROOM_ID, since, "@admin:localhost", AuditSource::AdminRoom)
+        .await
+        .expect("replay")
+        .expect("room is bridged");
+    assert_eq!(
+        report,
+        ReplayReport {
+            fetched: 2,
+            already_bridged: 1,
+            replayed: 1,
+            failed: 0,
+        }
+    );
+
+    let bodies: Vec<_> = harness
+        .homeserver
+        .requests_matching("PUT", "/send/m.room.message/")
+        .iter()
+        .filter_map(|req| req.body["body"].as_str().map(str::to_string))
+        .collect();
+    assert_eq!(
+        bodies.iter().filter(|body| *body == "history 5001").count(),
+        1
+    );
+    assert_eq!(
+        bodies.iter().filter(|body| *body == "history 5002").count(),
+        1
+    );
+    assert!(
+        harness
+            .db
+            .message_store()
+            .get_by_discord_message_id(HISTORY_MESSAGE_IDS[0])
+            .await
+            .expect("lookup")
+            .is_some()
+    );
+
+    assert!(
+        harness
+            .bridge
+            .replay_discord_messages(
+                "!other:example.org",
+                since,
+                "@admin:localhost",
+                AuditSource::AdminRoom
+            )
+            .await
+            .expect("replay")
+            .is_none()
+    );
+}
+
+#[tokio::test]
+async fn discord_threads_and_matrix_threads_nest_both_ways() {
+    let harness = Harness::start().await;
+    harness
+        .bridge
+        .handle_discord_message_with_context(discord_message(THREAD_ID, "who is around?"))
+        .await
+        .expect("discord message");
+    let root = harness
+        .db
+        .message_store()
+        .get_by_discord_message_id(THREAD_ID)
+        .await
+        .expect("lookup")
+        .expect("root mapped")
+        .matrix_event_id;
+
+    // A thread started from a message shares its id.
+    harness
+        .bridge
+        .handle_discord_message_with_context(DiscordMessageContext {
+            channel_id: THREAD_ID.to_string(),
+            thread_parent_id: Some(CHANNEL_ID.to_string()),
+            ..discord_message("556", "me!")
+        })
+        .await
+        .expect("thread message");
+    let sends = harness
+        .homeserver
+        .requests_matching("PUT", "/send/m.room.message/");
+    let reply = sends
+        .iter()
+        .find(|req| req.body["body"] == "me!")
+        .expect("thread message sent to the parent's room");
+    assert!(reply.path.contains(ROOM_ID));
+    assert_eq!(reply.body["m.relates_to"]["rel_type"], "m.thread");
+    assert_eq!(reply.body["m.relates_to"]["event_id"], root.as_str());
+
+    harness
+        .bridge
+        .handle_matrix_message(&MatrixEvent {
+            event_id: Some("$matrix1".to_string()),
+            event_type: "m.room.message".to_string(),
+            room_id: ROOM_ID.to_string(),
+            sender: "@alice:localhost".to_string(),
+            state_key: None,
+            content: Some(json!({
+                "msgtype": "m.text",
+                "body": "me too",
+                "m.relates_to": {
+                    "rel_type": "m.thread",
+                    "event_id": root,
+                    "is_falling_back": true,
+                    "m.in_reply_to": { "event_id": root }
+                }
+            })),
+            prev_content: None,
+            timestamp: Some(chrono::Utc::now().timestamp_millis().to_string()),
+        })
+        .await
+        .expect("matrix thread reply");
+
+    let mut executed = Vec::new();
+    for _ in 0..50 {
+        executed = harness
+            .discord_api
+            .requests_matching("POST", "/api/v10/webhooks/");
+        if !executed.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let execution = executed.first().expect("webhook executed");
+    assert!(execution.path.contains(&format!("thread_id={THREAD_ID}")));
+    assert_eq!(execution.body["content"], "me too");
+}
+
+#[tokio::test]
+async fn matrix_thread_replies_are_edited_and_deleted_in_their_discord_thread() {
+    let harness = Harness::start().await;
+    harness
+        .bridge
+        .handle_discord_message_with_context(discord_message(THREAD_ID, "who is around?"))
+        .await
+        .expect("discord message");
+    let root = harness
+        .db
+        .message_store()
+        .get_by_discord_message_id(THREAD_ID)
+        .await
+        .expect("lookup")
+        .expect("root mapped")
+        .matrix_event_id;
+    let matrix_event = |event_id: &str, event_type: &str, content| MatrixEvent {
+        event_id: Some(event_id.to_string()),
+        event_type: event_type.to_string(),
+        room_id: ROOM_ID.to_string(),
+        sender: "@alice:localhost".to_string(),
+        state_key: None,
+        content: Some(content),
+        prev_content: None,
+        timestamp: Some(chrono::Utc::now().timestamp_millis().to_string()),
+    };
+
+    harness
+        .bridge
+        .handle_matrix_message(&matrix_event(
+            "$matrix1",
+            "m.room.message",
+            json!({
+                "msgtype": "m.text",
+                "body": "me too",
+                "m.relates_to": { "rel_type": "m.thread", "event_id": root },
+            }),
+        ))
+        .await
+        .expect("matrix thread reply");
+    let mut reply = None;
+    for _ in 0..50 {
+        reply = harness
+            .db
+            .message_store()
+            .get_by_matrix_event_id("$matrix1")
+            .await
+            .expect("lookup");
+        if reply.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let reply = reply.expect("thread reply mapped");
+    assert_eq!(reply.discord_channel_id.as_deref(), Some(THREAD_ID));
+
+    harness
+        .bridge
+        .handle_matrix_message(&matrix_event(
+            "$matrix2",
+            "m.room.message",
+            json!({
+                "msgtype": "m.text",
+                "body": "* me three",
+                "m.new_content": { "msgtype": "m.text", "body": "me three" },
+                "m.relates_to": { "rel_type": "m.replace", "event_id": "$matrix1" },
+            }),
+        ))
+        .await
+        .expect("edit");
+    let edits = harness
+        .discord_api
+        .requests_matching("PATCH", "/api/v10/webhooks/");
+    let edit = edits.first().expect("webhook message edited");
+    assert!(
+        edit.path_without_query()
+            .ends_with(&format!("/messages/{}", reply.discord_message_id))
+    );
+    assert!(edit.path.contains(&format!("thread_id={THREAD_ID}")));
+
+    harness
+        .bridge
+        .handle_matrix_redaction(&matrix_event(
+            "$matrix3",
+            "m.room.redaction",
+            json!({ "redacts": "$matrix1" }),
+        ))
+        .await
+        .expect("redaction");
+    let deletes = harness
+        .discord_api
+        .requests_matching("DELETE", &format!("/messages/{}", reply.discord_message_id));
+    assert_eq!(deletes.len(), 1);
+    assert!(deletes[0].path.starts_with("/api/v10/webhooks/"));
+    assert!(deletes[0].path.contains(&format!("thread_id={THREAD_ID}")));
+}
+
+#[tokio::test]
+async fn forwards_from_public_channels_of_the_guild_are_announced() {
+    let stub = common::discord_responder();
+    let harness = Harness::with_config(
+        std::sync::Arc::new(move |req| {
+            let path = req.path_without_query();
+            if req.method == "GET" && path == "/api/v10/channels/300" {
+                let mut channel = common::channel_json("300");
+                channel["permission_overwrites"] =
+                    json!([{ "id": GUILD_ID, "type": 0, "allow": "0", "deny": "1024" }]);
+                return (200, channel);
+            }
+            if req.method == "GET" && path == "/api/v10/channels/400" {
+                let mut channel = common::channel_json("400");
+                channel["guild_id"] = json!("2");
+                return (200, channel);
+            }
+            stub(req)
+        }),
+        |yaml| {
+            yaml.replace(
+                "cross_channel_context: false",
+                "cross_channel_context: true",
+            )
+        },
+    )
+    .await;
+    let forward = |channel_id: &str, message_id: &str| DiscordMessageContext {
+        channel_id: channel_id.to_string(),
+        reference: Some(DiscordReference {
+            channel_id: CHANNEL_ID.to_string(),
+            message_id: Some("1500".to_string()),
+            forwarded: true,
+            content: "big news for <@42>".to_string(),
+        }),
+        ..discord_message(message_id, "")
+    };
+
+    for (channel_id, message_id) in [("300", "301"), ("400", "401"), ("200", "201")] {
+        harness
+            .bridge
+            .handle_discord_message_with_context(forward(channel_id, message_id))
+            .await
+            .expect("forward");
+    }
+
+    let notices = harness
+        .homeserver
+        .requests_matching("PUT", "/send/m.room.message/");
+    assert_eq!(notices.len(), 1, "only the public channel of the guild");
+    let notice = &notices[0];
+    assert!(notice.path.contains(ROOM_ID));
+    assert_eq!(notice.body["msgtype"], "m.notice");
+    let body = notice.body["body"].as_str().unwrap_or_default();
+    assert!(body.starts_with("discord-user forwarded a message from this room to #general:"));
+    assert!(body.contains("big news for"));
+    assert!(
+        notice.body["formatted_body"]
+            .as_str()
+            .is_some_and(|html| html.contains("https://matrix.to/#/@_discord_42:localhost"))
+    );
+}
+
+#[tokio::test]
+async fn failed_webhook_send_claims_only_its_own_unbridged_message() {
+    let stub = common::discord_responder();
+    let sent_as = std::sync::Arc::new(parking_lot::Mutex::new(None::<String>));
+    let recorded = sent_as.clone();
+    let harness = Harness::with_discord_responder(std::sync::Arc::new(move |req| {
+        let path = req.path_without_query();
+        if req.method == "POST" && path.starts_with("/api/v10/webhooks/") {
+            *recorded.lock() = req.body["username"].as_str().map(str::to_string);
+            return (502, json!({ "code": 0, "message": "Bad Gateway" }));
+        }
+        if req.method == "GET" && path.ends_with("/messages") {
+            let username = recorded.lock().clone().unwrap_or_default();
+            let now = chrono::Utc::now().to_rfc3339();
+            let history = [
+                ("1001", username.as_str()),
+                ("1002", "someone else"),
+                ("1003", username.as_str()),
+            ]
+            .into_iter()
+            .map(|(id, name)| {
+                let mut message = common::discord_message_json(id, CHANNEL_ID, "same words");
+                message["author"]["username"] = json!(name);
+                message["timestamp"] = json!(now);
+                message
+            })
+            .collect();
+            return (200, serde_json::Value::Array(history));
+        }
+        stub(req)
+    }))
+    .await;
+    // 1001 is an earlier message with the same words, bridged already.
+    harness
+        .db
+        .message_store()
+        .upsert_message_mapping(&MessageMapping {
+            id: 0,
+            discord_message_id: "1001".to_string(),
+            matrix_room_id: ROOM_ID.to_string(),
+            matrix_event_id: "$earlier".to_string(),
+            discord_channel_id: Some(CHANNEL_ID.to_string()),
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        })
+        .await
+        .expect("earlier mapping");
+
+    harness
+        .bridge
+        .handle_matrix_message(&MatrixEvent {
+            event_id: Some("$matrix1".to_string()),
+            event_type: "m.room.message".to_string(),
+            room_id: ROOM_ID.to_string(),
+            sender: "@alice:localhost".to_string(),
+            state_key: None,
+            content: Some(json!({ "msgtype": "m.text", "body": "same words" })),
+            prev_content: None,
+            timestamp: Some(chrono::Utc::now().timestamp_millis().to_string()),
+        })
+        .await
+        .expect("send recovered");
+
+    assert!(sent_as.lock().is_some());
+    let mapping = harness
+        .db
+        .message_store()
+        .get_by_matrix_event_id("$matrix1")
+        .await
+        .expect("lookup")
+        .expect("mapped to the message that went through");
+    assert_eq!(mapping.discord_message_id, "1003");
+}
+
+#[tokio::test]
+async fn reactions_and_redactions_wait_for_maintenance_to_end() {
+    let harness = Harness::start().await;
+    harness
+        .bridge
+        .handle_discord_message_with_context(discord_message("555", "react to me"))
+        .await
+        .expect("discord message");
+    let matrix_event = |event_id: &str, event_type: &str, content| MatrixEvent {
+        event_id: Some(event_id.to_string()),
+        event_type: event_type.to_string(),
+        room_id: ROOM_ID.to_string(),
+        sender: "@alice:localhost".to_string(),
+        state_key: None,
+        content: Some(content),
+        prev_content: None,
+        timestamp: Some(chrono::Utc::now().timestamp_millis().to_string()),
+    };
+    harness
+        .bridge
+        .handle_matrix_message(&matrix_event(
+            "$matrix1",
+            "m.room.message",
+            json!({ "msgtype": "m.text", "body": "oops" }),
+        ))
+        .await
+        .expect("original");
+
+    assert!(
+        harness
+            .bridge
+            .set_maintenance(true, "@admin:localhost", AuditSource::AdminRoom)
+            .await
+            .expect("maintenance on")
+    );
+    harness
+        .bridge
+        .handle_discord_reaction(CHANNEL_ID, "555", "77", "👍", None)
+        .await
+        .expect("discord reaction");
+    harness
+        .bridge
+        .handle_matrix_redaction(&matrix_event(
+            "$matrix2",
+            "m.room.redaction",
+            json!({ "redacts": "$matrix1" }),
+        ))
+        .await
+        .expect("redaction");
+    assert!(
+        harness
+            .homeserver
+            .requests_matching("PUT", "/send/m.reaction/")
+            .is_empty()
+    );
+    assert!(
+        harness
+            .discord_api
+            .requests_matching("DELETE", "/messages/1001")
+            .is_empty()
+    );
+
+    assert!(
+        harness
+            .bridge
+            .set_maintenance(false, "@admin:localhost", AuditSource::AdminRoom)
+            .await
+            .expect("maintenance off")
+    );
+    assert_eq!(
+        harness.bridge.retry_due_deliveries().await.expect("replay"),
+        2
+    );
+    assert_eq!(
+        harness
+            .homeserver
+            .requests_matching("PUT", "/send/m.reaction/")
+            .len(),
+        1
+    );
+    assert_eq!(
+        harness
+            .discord_api
+            .requests_matching("DELETE", "/messages/1001")
+            .len(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn matrix_events_before_discord_is_ready_wait_in_the_delivery_queue() {
+    let harness = Harness::before_discord_ready(common::discord_responder(), |yaml| yaml).await;
+    let matrix_event = |event_id: &str, event_type: &str, content| MatrixEvent {
+        event_id: Some(event_id.to_string()),
+        event_type: event_type.to_string(),
+        room_id: ROOM_ID.to_string(),
+        sender: "@alice:localhost".to_string(),
+        state_key: None,
+        content: Some(content),
+        prev_content: None,
+        timestamp: Some(chrono::Utc::now().timestamp_millis().to_string()),
+    };
+    harness
+        .bridge
+        .handle_matrix_message(&matrix_event(
+            "$matrix1",
+            "m.room.message",
+            json!({ "msgtype": "m.text", "body": "react to me" }),
+        ))
+        .await
+        .expect("matrix message");
+    harness
+        .bridge
+        .handle_matrix_reaction(&matrix_event(
+            "$reaction1",
+            "m.reaction",
+            json!({
+                "m.relates_to": {
+                    "rel_type": "m.annotation",
+                    "event_id": "$matrix1",
+                    "key": "👍",
+                }
+            }),
+        ))
+        .await
+        .expect("matrix reaction");
+
+    assert!(
+        harness
+            .discord_api
+            .requests_matching("POST", "/api/v10/webhooks/")
+            .is_empty()
+    );
+    assert_eq!(
+        harness
+            .db
+            .delivery_store()
+            .count_deliveries()
+            .await
+            .expect("count"),
+        2
+    );
+
+    harness.bridge.mark_discord_ready().await;
+    assert_eq!(
+        harness
+            .discord_api
+            .requests_matching("POST", "/api/v10/webhooks/")
+            .len(),
+        1
+    );
+    let reactions = harness
+        .discord_api
+        .requests_matching("PUT", "/messages/1001/reactions/");
+    assert_eq!(reactions.len(), 1);
+    assert_eq!(
+        harness
+            .db
+            .delivery_store()
+            .count_deliveries()
+            .await
+            .expect("count"),
+        0
+    );
+}